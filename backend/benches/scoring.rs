@@ -0,0 +1,97 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use metrics_exporter_prometheus::PrometheusBuilder;
+use rust_fraud_detector::{analyze_event, explain_event, AppState, ScoringConfig, ServiceConfig, UserEvent};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::runtime::Runtime;
+
+/// A clean-looking event: irregular timing, a browser user agent, and some metadata.
+fn event(session_id: &str, index: u64) -> UserEvent {
+    let millis = index * 1_700 + (index * 7_919) % 1_300;
+    serde_json::from_value(serde_json::json!({
+        "sessionId": session_id,
+        "userId": "bench-user",
+        "eventType": if index.is_multiple_of(3) { "PageLoad" } else { "Click" },
+        "timestamp": chrono::DateTime::from_timestamp_millis(1_700_000_000_000 + millis as i64).unwrap(),
+        "ipAddress": "198.51.100.23",
+        "userAgent": "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 Chrome/120.0 Safari/537.36",
+        "metadata": { "path": "/products", "elementId": format!("item-{}", index % 40) },
+    }))
+    .unwrap()
+}
+
+fn build_state(runtime: &Runtime) -> AppState {
+    let service = ServiceConfig::from_env().expect("service config");
+    let metrics = PrometheusBuilder::new().build_recorder().handle();
+    runtime.block_on(AppState::new(service, ScoringConfig::default(), metrics)).expect("app state")
+}
+
+fn analyze_new_session(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let state = build_state(&runtime);
+    let sequence = AtomicU64::new(0);
+
+    c.bench_function("analyze_event/new_session", |b| {
+        b.to_async(&runtime).iter(|| {
+            let session_id = format!("bench-{}", sequence.fetch_add(1, Ordering::Relaxed));
+            let event = event(&session_id, 0);
+            let state = &state;
+            async move { analyze_event(state, event, None).await.unwrap() }
+        })
+    });
+}
+
+/// Keeps adding to one session that already holds 10,000 events, so every iteration
+/// pays for the full stored history.
+fn analyze_long_session(c: &mut Criterion) {
+    const STORED_EVENTS: u64 = 10_000;
+    let runtime = Runtime::new().unwrap();
+    let mut service = ServiceConfig::from_env().expect("service config");
+    service.max_events_per_session = STORED_EVENTS as usize;
+    let metrics = PrometheusBuilder::new().build_recorder().handle();
+    let state = runtime.block_on(AppState::new(service, ScoringConfig::default(), metrics)).expect("app state");
+
+    let session_id = "long-lived";
+    runtime.block_on(async {
+        for index in 0..STORED_EVENTS {
+            analyze_event(&state, event(session_id, index), None).await.unwrap();
+        }
+    });
+    let sequence = AtomicU64::new(STORED_EVENTS);
+
+    c.bench_function("analyze_event/long_session_10k", |b| {
+        b.to_async(&runtime).iter(|| {
+            let event = event(session_id, sequence.fetch_add(1, Ordering::Relaxed));
+            let state = &state;
+            async move { analyze_event(state, event, None).await.unwrap() }
+        })
+    });
+}
+
+/// Scores against stored sessions of increasing length without growing them, since
+/// explain never stores the event.
+fn explain_long_session(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let state = build_state(&runtime);
+    let mut group = c.benchmark_group("explain_event/stored_history");
+
+    for length in [10u64, 100, 1_000, 5_000] {
+        let session_id = format!("long-{}", length);
+        runtime.block_on(async {
+            for index in 0..length {
+                analyze_event(&state, event(&session_id, index), None).await.unwrap();
+            }
+        });
+
+        group.bench_with_input(BenchmarkId::from_parameter(length), &length, |b, &length| {
+            b.to_async(&runtime).iter(|| {
+                let event = event(&session_id, length);
+                let state = &state;
+                async move { explain_event(state, event, None).await.unwrap() }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, analyze_new_session, analyze_long_session, explain_long_session);
+criterion_main!(benches);
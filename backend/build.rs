@@ -0,0 +1,47 @@
+//! Build info for `GET /api/v1/version`: the git commit the binary was built from, and
+//! when. `GIT_COMMIT` overrides the commit for builds outside a checkout (container
+//! builds, say), and `SOURCE_DATE_EPOCH` the time for reproducible ones.
+use std::path::PathBuf;
+use std::process::Command;
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    let value = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (output.status.success() && !value.is_empty()).then_some(value)
+}
+
+fn main() {
+    let commit = std::env::var("GIT_COMMIT")
+        .ok()
+        .filter(|commit| !commit.trim().is_empty())
+        .or_else(|| git(&["rev-parse", "HEAD"]));
+    if let Some(commit) = commit {
+        println!("cargo:rustc-env=BUILD_GIT_COMMIT={}", commit.trim());
+    }
+
+    let built_at = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.trim().parse().ok())
+        .and_then(|epoch| chrono::DateTime::from_timestamp(epoch, 0))
+        .unwrap_or_else(chrono::Utc::now);
+    println!(
+        "cargo:rustc-env=BUILD_TIMESTAMP={}",
+        built_at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+    );
+
+    // Naming any file stops Cargo rerunning this on every change, so name the sources
+    // too, and the files a new commit or checkout changes.
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=Cargo.toml");
+    println!("cargo:rerun-if-changed=src");
+    if let Some(git_dir) = git(&["rev-parse", "--absolute-git-dir"]).map(PathBuf::from) {
+        // A path that doesn't exist would count as changed on every build.
+        let reference = git(&["symbolic-ref", "-q", "HEAD"]).map(|reference| git_dir.join(reference));
+        let watched = [Some(git_dir.join("HEAD")), reference, Some(git_dir.join("packed-refs"))];
+        for path in watched.into_iter().flatten().filter(|path| path.exists()) {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+    }
+}
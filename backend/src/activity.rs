@@ -0,0 +1,97 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Timelike, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::store::StoreError;
+
+/// Profiles held in memory above which stale ones are swept on insert.
+const SWEEP_THRESHOLD: usize = 100_000;
+
+// --- USER ACTIVITY PROFILES ---
+/// When a user is usually active: how many of their events fell in each UTC hour of the
+/// day, and when they were last seen. Updating it is one counter and one timestamp.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ActivityProfile {
+    pub hours: [u64; 24],
+    pub last_seen: Option<DateTime<Utc>>,
+}
+
+impl ActivityProfile {
+    pub fn events(&self) -> u64 {
+        self.hours.iter().sum()
+    }
+
+    /// The share of the user's events that fell in `at`'s hour.
+    pub fn hour_share(&self, at: DateTime<Utc>) -> f64 {
+        let events = self.events();
+        if events == 0 {
+            return 0.0;
+        }
+        self.hours[at.hour() as usize] as f64 / events as f64
+    }
+
+    pub fn record(&mut self, at: DateTime<Utc>) {
+        self.hours[at.hour() as usize] += 1;
+        self.last_seen = Some(self.last_seen.map_or(at, |last_seen| last_seen.max(at)));
+    }
+}
+
+/// Where activity profiles live: in process memory, or alongside the events in Redis.
+#[async_trait]
+pub trait ActivityStore: Send + Sync {
+    /// The user's profile before the event being scored, if they have one.
+    async fn profile(&self, user_id: &str) -> Result<Option<ActivityProfile>, StoreError>;
+
+    async fn record(&self, user_id: &str, at: DateTime<Utc>) -> Result<(), StoreError>;
+
+    /// Drops the user's profile, returning whether they had one.
+    async fn forget(&self, user_id: &str) -> Result<bool, StoreError>;
+
+    /// Drops profiles not seen for the retention period. Backends that expire entries
+    /// themselves leave this alone.
+    fn sweep(&self, _now: DateTime<Utc>) {}
+}
+
+/// Profiles in process memory, dropped once a user has been away for `ttl`.
+pub struct MemoryActivityStore {
+    profiles: Mutex<HashMap<String, ActivityProfile>>,
+    ttl: Duration,
+}
+
+impl MemoryActivityStore {
+    pub fn new(ttl: std::time::Duration) -> Self {
+        Self {
+            profiles: Mutex::new(HashMap::new()),
+            ttl: Duration::from_std(ttl).unwrap_or(Duration::MAX),
+        }
+    }
+}
+
+fn prune(profiles: &mut HashMap<String, ActivityProfile>, cutoff: DateTime<Utc>) {
+    profiles.retain(|_, profile| profile.last_seen.is_some_and(|last_seen| last_seen > cutoff));
+}
+
+#[async_trait]
+impl ActivityStore for MemoryActivityStore {
+    async fn profile(&self, user_id: &str) -> Result<Option<ActivityProfile>, StoreError> {
+        Ok(self.profiles.lock().unwrap().get(user_id).cloned())
+    }
+
+    async fn record(&self, user_id: &str, at: DateTime<Utc>) -> Result<(), StoreError> {
+        let mut profiles = self.profiles.lock().unwrap();
+        if profiles.len() >= SWEEP_THRESHOLD {
+            prune(&mut profiles, at - self.ttl);
+        }
+        profiles.entry(user_id.to_string()).or_default().record(at);
+        Ok(())
+    }
+
+    async fn forget(&self, user_id: &str) -> Result<bool, StoreError> {
+        Ok(self.profiles.lock().unwrap().remove(user_id).is_some())
+    }
+
+    fn sweep(&self, now: DateTime<Utc>) {
+        prune(&mut self.profiles.lock().unwrap(), now - self.ttl);
+    }
+}
@@ -0,0 +1,156 @@
+use axum::{
+    extract::State,
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use metrics::{counter, gauge};
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::{AppError, AppState};
+
+// --- ADMISSION CONTROL ---
+/// What an `Admission` counts, which names its metrics and its rejection.
+#[derive(Debug, Clone, Copy)]
+pub enum Budget {
+    /// Analyses across HTTP and gRPC (`MAX_IN_FLIGHT_ANALYSES`).
+    Analyses,
+    /// HTTP requests on any API route but the probes (`MAX_IN_FLIGHT_REQUESTS`).
+    Requests,
+}
+
+impl Budget {
+    fn in_flight_gauge(self) -> &'static str {
+        match self {
+            Budget::Analyses => "analyses_in_flight",
+            Budget::Requests => "requests_in_flight",
+        }
+    }
+
+    fn limit_gauge(self) -> &'static str {
+        match self {
+            Budget::Analyses => "analyses_in_flight_limit",
+            Budget::Requests => "requests_in_flight_limit",
+        }
+    }
+
+    fn rejected_counter(self) -> &'static str {
+        match self {
+            Budget::Analyses => "analyses_rejected_total",
+            Budget::Requests => "requests_shed_total",
+        }
+    }
+
+    fn rejection(self) -> AppError {
+        match self {
+            Budget::Analyses => AppError::Overloaded("Too many analyses in flight".to_string()),
+            Budget::Requests => AppError::Shed("Too many requests in flight".to_string()),
+        }
+    }
+}
+
+/// Caps the work in flight of one `Budget`. Work beyond the cap is turned away at once
+/// rather than queued, so a spike costs rejected requests instead of memory and a pile-up
+/// on the store, reputation service, or model.
+pub struct Admission {
+    permits: Arc<Semaphore>,
+    limit: usize,
+    budget: Budget,
+}
+
+/// Holds one slot until dropped.
+pub struct Admitted {
+    _permit: OwnedSemaphorePermit,
+    budget: Budget,
+}
+
+impl Drop for Admitted {
+    fn drop(&mut self) {
+        gauge!(self.budget.in_flight_gauge()).decrement(1.0);
+    }
+}
+
+impl Admission {
+    pub fn new(limit: usize, budget: Budget) -> Self {
+        gauge!(budget.limit_gauge()).set(limit as f64);
+        Self {
+            permits: Arc::new(Semaphore::new(limit)),
+            limit,
+            budget,
+        }
+    }
+
+    /// A slot for one unit of work, or the budget's rejection when every slot is taken.
+    pub fn admit(&self) -> Result<Admitted, AppError> {
+        match self.permits.clone().try_acquire_owned() {
+            Ok(permit) => {
+                gauge!(self.budget.in_flight_gauge()).increment(1.0);
+                Ok(Admitted {
+                    _permit: permit,
+                    budget: self.budget,
+                })
+            }
+            Err(_) => {
+                counter!(self.budget.rejected_counter()).increment(1);
+                Err(self.budget.rejection())
+            }
+        }
+    }
+
+    pub fn utilization(&self) -> Utilization {
+        Utilization {
+            current: self.limit - self.permits.available_permits(),
+            limit: Some(self.limit),
+        }
+    }
+}
+
+/// How much of a budget is in use. `limit` is omitted when there is none.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Utilization {
+    pub current: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+/// Layer for the scoring routes: holds a slot for the whole request, body included.
+pub async fn limit_in_flight<B>(State(state): State<Arc<AppState>>, request: Request<B>, next: Next<B>) -> Response {
+    match state.admission.admit() {
+        Ok(_admitted) => next.run(request).await,
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Layer for every API route but the probes, when `MAX_IN_FLIGHT_REQUESTS` is set. The
+/// slot is held until the handler returns its response, so a streamed export body isn't
+/// counted while it drains.
+pub async fn shed_excess<B>(State(state): State<Arc<AppState>>, request: Request<B>, next: Next<B>) -> Response {
+    let Some(requests) = &state.request_admission else {
+        return next.run(request).await;
+    };
+    match requests.admit() {
+        Ok(_admitted) => next.run(request).await,
+        Err(e) => e.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn admits_analyses_up_to_the_limit_and_frees_slots_on_drop() {
+        let admission = Admission::new(2, Budget::Analyses);
+        let first = admission.admit().unwrap();
+        let _second = admission.admit().unwrap();
+        assert!(matches!(admission.admit(), Err(AppError::Overloaded(_))));
+        assert_eq!(admission.utilization().current, 2);
+
+        drop(first);
+        assert_eq!(admission.utilization().current, 1);
+        assert!(admission.admit().is_ok());
+    }
+}
@@ -0,0 +1,258 @@
+use chrono::SecondsFormat;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use metrics::counter;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+use tracing::warn;
+
+use crate::config::{AlertsConfig, EmailAlertConfig, SlackAlertConfig, SmtpTls};
+use crate::FraudCheckResult;
+
+/// Alerts waiting to be sent before new ones are dropped.
+const QUEUE_SIZE: usize = 1_000;
+/// Latency budget for one Slack post or SMTP conversation.
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+/// Reasons listed per alert, highest points first.
+const TOP_REASONS: usize = 3;
+/// Sessions in cooldown per sink above which expired ones are swept on insert.
+const SWEEP_THRESHOLD: usize = 10_000;
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+// --- ALERTING ---
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Sink {
+    Slack,
+    Email,
+}
+
+impl Sink {
+    fn name(self) -> &'static str {
+        match self {
+            Sink::Slack => "slack",
+            Sink::Email => "email",
+        }
+    }
+}
+
+/// One alert, with the sink settings it was raised under so a reload mid-queue
+/// doesn't send it somewhere else.
+enum Alert {
+    Slack { config: SlackAlertConfig, text: String },
+    Email { config: EmailAlertConfig, subject: String, text: String },
+}
+
+/// Per sink: sessions still in their cooldown, and the current minute's count.
+struct Limiter {
+    sessions: HashMap<String, Instant>,
+    window_start: Instant,
+    sent: u32,
+}
+
+impl Limiter {
+    fn new() -> Self {
+        Self {
+            sessions: HashMap::new(),
+            window_start: Instant::now(),
+            sent: 0,
+        }
+    }
+
+    /// Takes a slot for the session, or says why there is none.
+    fn admit(&mut self, session_id: &str, config: &AlertsConfig) -> Result<(), &'static str> {
+        let now = Instant::now();
+        let cooldown = config.session_cooldown();
+        if self.sessions.get(session_id).is_some_and(|at| now.duration_since(*at) < cooldown) {
+            return Err("session");
+        }
+        if now.duration_since(self.window_start) >= RATE_WINDOW {
+            self.window_start = now;
+            self.sent = 0;
+        }
+        if self.sent >= config.max_per_minute {
+            return Err("rate");
+        }
+
+        self.sent += 1;
+        if self.sessions.len() >= SWEEP_THRESHOLD {
+            self.sessions.retain(|_, at| now.duration_since(*at) < cooldown);
+        }
+        self.sessions.insert(session_id.to_string(), now);
+        Ok(())
+    }
+}
+
+/// Sends Slack and email alerts for results matching the scoring config's `alerts`
+/// section. Matching and rate limiting happen inline; sending is left to a background
+/// task, and failures there are only logged and counted, so alerting never slows or
+/// fails an analysis.
+pub struct Alerts {
+    sender: Sender<Alert>,
+    limiters: Mutex<HashMap<Sink, Limiter>>,
+}
+
+impl Alerts {
+    /// Starts the sending task; must be called within the runtime.
+    pub fn start() -> Self {
+        let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+        tokio::spawn(deliver(receiver, reqwest::Client::new()));
+        Self {
+            sender,
+            limiters: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Queues an alert on each configured sink when the result calls for one and the
+    /// sink's limits allow it.
+    pub fn consider(&self, config: &AlertsConfig, result: &FraudCheckResult) {
+        if !config.enabled() || !config.matches(result.fraud_score, result.breakdown.iter().map(|hit| &hit.code)) {
+            return;
+        }
+
+        if let Some(slack) = &config.slack {
+            self.raise(Sink::Slack, config, result, || Alert::Slack {
+                config: slack.clone(),
+                text: slack_text(config, result),
+            });
+        }
+        if let Some(email) = &config.email {
+            self.raise(Sink::Email, config, result, || Alert::Email {
+                config: email.clone(),
+                subject: format!("Fraud alert: session {} scored {}", result.session_id, result.fraud_score),
+                text: plain_text(config, result),
+            });
+        }
+    }
+
+    fn raise(&self, sink: Sink, config: &AlertsConfig, result: &FraudCheckResult, alert: impl FnOnce() -> Alert) {
+        let admitted = self
+            .limiters
+            .lock()
+            .unwrap()
+            .entry(sink)
+            .or_insert_with(Limiter::new)
+            .admit(&result.session_id, config);
+        if let Err(reason) = admitted {
+            counter!("alerts_suppressed_total", "sink" => sink.name(), "reason" => reason).increment(1);
+            return;
+        }
+        if let Err(TrySendError::Full(_) | TrySendError::Closed(_)) = self.sender.try_send(alert()) {
+            counter!("alerts_suppressed_total", "sink" => sink.name(), "reason" => "queue_full").increment(1);
+        }
+    }
+}
+
+async fn deliver(mut receiver: Receiver<Alert>, http: reqwest::Client) {
+    while let Some(alert) = receiver.recv().await {
+        let (sink, outcome) = match alert {
+            Alert::Slack { config, text } => (Sink::Slack, send_slack(&http, &config, &text).await),
+            Alert::Email { config, subject, text } => (Sink::Email, send_email(&config, &subject, &text).await),
+        };
+        match outcome {
+            Ok(()) => counter!("alerts_sent_total", "sink" => sink.name()).increment(1),
+            Err(e) => {
+                warn!("Failed to send {} alert: {}", sink.name(), e);
+                counter!("alerts_failed_total", "sink" => sink.name()).increment(1);
+            }
+        }
+    }
+}
+
+async fn send_slack(http: &reqwest::Client, config: &SlackAlertConfig, text: &str) -> Result<(), String> {
+    let response = http
+        .post(&config.webhook_url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(serde_json::json!({ "text": text }).to_string())
+        .timeout(SEND_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("Slack answered {}", response.status()));
+    }
+    Ok(())
+}
+
+async fn send_email(config: &EmailAlertConfig, subject: &str, text: &str) -> Result<(), String> {
+    let mut message = Message::builder()
+        .from(config.from.parse().map_err(|e| format!("invalid from address: {}", e))?)
+        .subject(subject);
+    for to in &config.to {
+        message = message.to(to.parse().map_err(|e| format!("invalid recipient `{}`: {}", to, e))?);
+    }
+    let message = message.body(text.to_string()).map_err(|e| e.to_string())?;
+
+    let transport = match config.tls {
+        SmtpTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_host).map_err(|e| e.to_string())?,
+        SmtpTls::Implicit => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.smtp_host).map_err(|e| e.to_string())?,
+        SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.smtp_host),
+    }
+    .port(config.smtp_port)
+    .timeout(Some(SEND_TIMEOUT));
+    let transport = match &config.username {
+        Some(username) => {
+            let password = config
+                .password_env
+                .as_ref()
+                .and_then(|name| std::env::var(name).ok())
+                .unwrap_or_default();
+            transport.credentials(Credentials::new(username.clone(), password))
+        }
+        None => transport,
+    };
+
+    transport.build().send(message).await.map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// The result's highest-scoring hits, as `(code, points, reason)`.
+fn top_reasons(result: &FraudCheckResult) -> Vec<(&str, i32, &str)> {
+    let mut hits: Vec<_> = result
+        .breakdown
+        .iter()
+        .map(|hit| (hit.code.as_ref(), hit.points, hit.reason.as_str()))
+        .collect();
+    hits.sort_by_key(|(_, points, _)| std::cmp::Reverse(*points));
+    hits.truncate(TOP_REASONS);
+    hits
+}
+
+fn link(config: &AlertsConfig, result: &FraudCheckResult) -> Option<String> {
+    let template = config.link_template.as_ref()?;
+    Some(template.replace("{session_id}", &result.session_id))
+}
+
+fn slack_text(config: &AlertsConfig, result: &FraudCheckResult) -> String {
+    let mut text = format!(
+        ":rotating_light: *Fraud alert*: session `{}` scored *{}* ({})",
+        result.session_id,
+        result.fraud_score,
+        result.decision.as_str()
+    );
+    for (code, points, reason) in top_reasons(result) {
+        text.push_str(&format!("\n• `{}` ({:+}) {}", code, points, reason));
+    }
+    if let Some(link) = link(config, result) {
+        text.push_str(&format!("\n<{}|Open session>", link));
+    }
+    text
+}
+
+fn plain_text(config: &AlertsConfig, result: &FraudCheckResult) -> String {
+    let mut text = format!(
+        "Session {} scored {} ({}) at {}.\n\nTop reasons:\n",
+        result.session_id,
+        result.fraud_score,
+        result.decision.as_str(),
+        result.check_timestamp.to_rfc3339_opts(SecondsFormat::Secs, true)
+    );
+    for (code, points, reason) in top_reasons(result) {
+        text.push_str(&format!("- {} ({:+}): {}\n", code, points, reason));
+    }
+    if let Some(link) = link(config, result) {
+        text.push_str(&format!("\n{}\n", link));
+    }
+    text
+}
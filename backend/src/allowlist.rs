@@ -0,0 +1,209 @@
+use arc_swap::ArcSwap;
+use axum::{extract::State, Json};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use crate::config::AllowlistConfig;
+use crate::error::AppJson;
+use crate::ip;
+use crate::{AppError, AppState};
+
+// --- ALLOWLIST ---
+/// Trusted IP ranges and users whose events skip the rule pipeline entirely. Changes
+/// are made to a copy and swapped in whole, so a lookup never waits on one.
+pub struct Allowlist {
+    entries: ArcSwap<Entries>,
+    /// Held by whatever changes `entries`, so two changes can't both start from the same
+    /// copy and lose one of them.
+    writer: Mutex<()>,
+}
+
+#[derive(Clone)]
+struct Entries {
+    networks: HashSet<IpNet>,
+    user_ids: HashSet<String>,
+}
+
+/// Why an event was allowlisted; also the `match` label on the suppression metric.
+#[derive(Debug, Clone, Copy)]
+pub enum AllowlistMatch {
+    Ip,
+    User,
+}
+
+impl AllowlistMatch {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Ip => "ip",
+            Self::User => "user",
+        }
+    }
+}
+
+/// Accepts a CIDR range or a bare address, which is treated as a single-host range.
+pub fn parse_network(value: &str) -> Option<IpNet> {
+    let value = value.trim();
+    value
+        .parse::<IpNet>()
+        .map(|network| network.trunc())
+        .ok()
+        .or_else(|| ip::parse(value).map(IpNet::from))
+}
+
+impl Allowlist {
+    pub fn new(config: &AllowlistConfig) -> Self {
+        Self {
+            entries: ArcSwap::from_pointee(Entries {
+                networks: config.ips.iter().map(IpNet::trunc).collect(),
+                user_ids: config.user_ids.iter().cloned().collect(),
+            }),
+            writer: Mutex::new(()),
+        }
+    }
+
+    /// Users are checked first so a trusted account stays trusted from any network.
+    pub fn matches(&self, ip: &str, user_id: Option<&str>) -> Option<AllowlistMatch> {
+        let entries = self.entries.load();
+        if let Some(user_id) = user_id {
+            if entries.user_ids.contains(user_id) {
+                return Some(AllowlistMatch::User);
+            }
+        }
+
+        let ip = ip::parse(ip)?;
+        entries
+            .networks
+            .iter()
+            .any(|network| network.contains(&ip))
+            .then_some(AllowlistMatch::Ip)
+    }
+
+    /// Applies `change` to a copy of the entries and swaps the copy in, so a lookup sees
+    /// all of a change or none of it.
+    fn change<T>(&self, change: impl FnOnce(&mut Entries) -> T) -> T {
+        let _writer = self.writer.lock().unwrap();
+        let mut entries = Entries::clone(&self.entries.load());
+        let changed = change(&mut entries);
+        self.entries.store(Arc::new(entries));
+        changed
+    }
+
+    fn snapshot(&self) -> AllowlistEntries {
+        let entries = self.entries.load();
+        let mut ips: Vec<String> = entries.networks.iter().map(IpNet::to_string).collect();
+        let mut user_ids: Vec<String> = entries.user_ids.iter().cloned().collect();
+        ips.sort();
+        user_ids.sort();
+        AllowlistEntries { ips, user_ids }
+    }
+}
+
+// --- ADMIN API HANDLERS ---
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AllowlistEntries {
+    ips: Vec<String>,
+    user_ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AllowlistChange {
+    ips: usize,
+    user_ids: usize,
+}
+
+/// Parses every IP entry up front so a bad entry rejects the whole change.
+fn parse_networks(ips: &[String]) -> Result<Vec<IpNet>, AppError> {
+    let invalid: Vec<&str> = ips
+        .iter()
+        .filter(|ip| parse_network(ip).is_none())
+        .map(String::as_str)
+        .collect();
+    if !invalid.is_empty() {
+        return Err(AppError::Validation(format!("Invalid IP or CIDR entries: {}", invalid.join(", "))));
+    }
+    Ok(ips.iter().filter_map(|ip| parse_network(ip)).collect())
+}
+
+pub async fn list_handler(State(state): State<Arc<AppState>>) -> Json<AllowlistEntries> {
+    Json(state.allowlist.snapshot())
+}
+
+/// Adds entries, returning how many of each kind were new.
+pub async fn add_handler(
+    State(state): State<Arc<AppState>>,
+    AppJson(entries): AppJson<AllowlistEntries>,
+) -> Result<Json<AllowlistChange>, AppError> {
+    let networks = parse_networks(&entries.ips)?;
+    let change = state.allowlist.change(|current| AllowlistChange {
+        ips: networks.into_iter().filter(|network| current.networks.insert(*network)).count(),
+        user_ids: entries.user_ids.into_iter().filter(|user_id| current.user_ids.insert(user_id.clone())).count(),
+    });
+    Ok(Json(change))
+}
+
+/// Removes entries, returning how many of each kind were present.
+pub async fn remove_handler(
+    State(state): State<Arc<AppState>>,
+    AppJson(entries): AppJson<AllowlistEntries>,
+) -> Result<Json<AllowlistChange>, AppError> {
+    let networks = parse_networks(&entries.ips)?;
+    let change = state.allowlist.change(|current| AllowlistChange {
+        ips: networks.iter().filter(|network| current.networks.remove(*network)).count(),
+        user_ids: entries.user_ids.iter().filter(|user_id| current.user_ids.remove(*user_id)).count(),
+    });
+    Ok(Json(change))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowlist(ips: &[&str], user_ids: &[&str]) -> Allowlist {
+        Allowlist::new(&AllowlistConfig {
+            ips: ips.iter().map(|ip| parse_network(ip).unwrap()).collect(),
+            user_ids: user_ids.iter().map(|user_id| user_id.to_string()).collect(),
+        })
+    }
+
+    #[test]
+    fn parses_ranges_and_bare_addresses() {
+        assert_eq!(parse_network("10.0.0.0/8"), Some("10.0.0.0/8".parse().unwrap()));
+        assert_eq!(parse_network(" 10.1.2.3/8 "), Some("10.0.0.0/8".parse().unwrap()));
+        assert_eq!(parse_network("203.0.113.9"), Some("203.0.113.9/32".parse().unwrap()));
+        assert_eq!(parse_network("::ffff:203.0.113.9"), Some("203.0.113.9/32".parse().unwrap()));
+        assert_eq!(parse_network("2001:db8::1"), Some("2001:db8::1/128".parse().unwrap()));
+        assert_eq!(parse_network("10.0.0.0/33"), None);
+        assert_eq!(parse_network("qa-runner"), None);
+    }
+
+    #[test]
+    fn matches_addresses_inside_a_range() {
+        let allowlist = allowlist(&["10.20.0.0/16", "2001:db8::/32"], &[]);
+        assert!(matches!(allowlist.matches("10.20.3.4", None), Some(AllowlistMatch::Ip)));
+        assert!(matches!(allowlist.matches("2001:db8:1::9", None), Some(AllowlistMatch::Ip)));
+        assert!(allowlist.matches("10.21.0.1", None).is_none());
+        assert!(allowlist.matches("not-an-ip", None).is_none());
+    }
+
+    #[test]
+    fn a_trusted_user_matches_from_any_network() {
+        let allowlist = allowlist(&["10.20.0.0/16"], &["qa-bot"]);
+        assert!(matches!(allowlist.matches("198.51.100.23", Some("qa-bot")), Some(AllowlistMatch::User)));
+        assert!(matches!(allowlist.matches("10.20.3.4", Some("qa-bot")), Some(AllowlistMatch::User)));
+        assert!(allowlist.matches("198.51.100.23", Some("someone")).is_none());
+    }
+
+    #[test]
+    fn changes_apply_to_later_lookups() {
+        let allowlist = allowlist(&[], &[]);
+        allowlist.change(|entries| entries.user_ids.insert("qa-bot".to_string()));
+        assert!(allowlist.matches("198.51.100.23", Some("qa-bot")).is_some());
+        assert_eq!(allowlist.snapshot().user_ids, ["qa-bot"]);
+        allowlist.change(|entries| entries.user_ids.remove("qa-bot"));
+        assert!(allowlist.matches("198.51.100.23", Some("qa-bot")).is_none());
+    }
+}
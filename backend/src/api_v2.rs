@@ -0,0 +1,110 @@
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header, HeaderMap, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::config::Decision;
+use crate::msgpack;
+use crate::protobuf::EventBody;
+use crate::rules::RuleHit;
+use crate::{derive_client_ip, score_event, usage_key, AppError, AppState, FraudCheckResult};
+
+// --- V2 EVENTS API ---
+/// One reason the event scored, structured rather than split across `reasons` and
+/// `breakdown` as in v1.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reason {
+    code: Cow<'static, str>,
+    points: i32,
+    message: String,
+    /// For a composite rule, the reasons of the rules it combines.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    reasons: Vec<Reason>,
+}
+
+impl From<RuleHit> for Reason {
+    fn from(hit: RuleHit) -> Self {
+        Reason {
+            code: hit.code,
+            points: hit.points,
+            message: hit.reason,
+            reasons: hit.children.into_iter().map(Reason::from).collect(),
+        }
+    }
+}
+
+/// The v2 result of `POST /api/v2/events`. The same analysis as v1's `FraudCheckResult`,
+/// reshaped; v1 keeps its shape for existing integrations.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventResult {
+    session_id: String,
+    /// What to do with the event, for integrators that shouldn't have to know the
+    /// thresholds.
+    decision: Decision,
+    score: i32,
+    raw_score: i32,
+    confidence: u8,
+    reasons: Vec<Reason>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    session_reputation: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    asn: Option<u32>,
+    checked_at: DateTime<Utc>,
+    /// Set when a dependency failed and the event was scored without it.
+    degraded: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    skipped_rules: Vec<String>,
+    /// Set when storage sampling left some of the session's events unstored.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    history_sampled: bool,
+}
+
+impl From<FraudCheckResult> for EventResult {
+    fn from(result: FraudCheckResult) -> Self {
+        EventResult {
+            session_id: result.session_id,
+            decision: result.decision,
+            score: result.fraud_score,
+            raw_score: result.raw_score,
+            confidence: result.confidence,
+            reasons: result.breakdown.into_iter().map(Reason::from).collect(),
+            session_reputation: result.session_reputation,
+            asn: result.asn,
+            checked_at: result.check_timestamp,
+            degraded: result.degraded,
+            skipped_rules: result.skipped_rules,
+            history_sampled: result.history_sampled,
+        }
+    }
+}
+
+/// Same body, checks, and scoring as `POST /api/v1/events`; only the result differs.
+/// Answers in JSON or MessagePack.
+pub async fn analyze_event_handler(
+    State(state): State<Arc<AppState>>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    EventBody(event): EventBody,
+) -> Result<Response, AppError> {
+    let derived_ip = derive_client_ip(&state, peer, &headers);
+    let result = score_event(&state, event, derived_ip, usage_key(&state, &headers)).await?;
+    msgpack::respond(&headers, EventResult::from(result))
+}
+
+/// Layer for the v1 events route: marks its responses deprecated in favour of v2.
+pub async fn deprecate_v1<B>(request: Request<B>, next: Next<B>) -> Response {
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert("deprecation", HeaderValue::from_static("true"));
+    headers.insert(header::LINK, HeaderValue::from_static("</api/v2/events>; rel=\"successor-version\""));
+    response
+}
@@ -0,0 +1,164 @@
+use chrono::{DateTime, Utc};
+use metrics::counter;
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread;
+use tracing::warn;
+
+use crate::config::AuditConfig;
+use crate::rules::RuleHit;
+use crate::{FraudCheckResult, UserEvent};
+
+// --- AUDIT LOG ---
+/// One line of the audit log. The IP is hashed so the log can be retained longer than
+/// raw event data.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuditRecord<'a> {
+    timestamp: DateTime<Utc>,
+    session_id: &'a str,
+    user_id: Option<&'a str>,
+    ip_hash: String,
+    fraud_score: i32,
+    flagged: bool,
+    reasons: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    breakdown: Option<&'a [RuleHit]>,
+    config_version: &'a str,
+}
+
+/// Handle to the dedicated writer thread. Records are queued without blocking; when the
+/// queue is full they are dropped and counted, so a stalled disk never slows scoring.
+pub struct AuditLog {
+    sender: SyncSender<String>,
+    include_breakdown: bool,
+}
+
+impl AuditLog {
+    /// Opens (or creates) the log up front so a bad path fails startup, not the first write.
+    pub fn start(config: &AuditConfig) -> io::Result<Self> {
+        let writer = AuditWriter::open(config)?;
+        let (sender, receiver) = mpsc::sync_channel(config.queue_size);
+        thread::Builder::new()
+            .name("audit-writer".to_string())
+            .spawn(move || writer.run(receiver))?;
+
+        Ok(Self {
+            sender,
+            include_breakdown: config.include_breakdown,
+        })
+    }
+
+    pub fn record(&self, event: &UserEvent, result: &FraudCheckResult, config_version: &str) {
+        let record = AuditRecord {
+            timestamp: result.check_timestamp,
+            session_id: &result.session_id,
+            user_id: event.user_id.as_deref(),
+            ip_hash: crate::short_hash(event.ip_address.as_bytes()),
+            fraud_score: result.fraud_score,
+            flagged: result.flagged,
+            reasons: &result.reasons,
+            breakdown: self.include_breakdown.then_some(result.breakdown.as_slice()),
+            config_version,
+        };
+        let line = match serde_json::to_string(&record) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Failed to serialize audit record: {}", e);
+                return;
+            }
+        };
+
+        match self.sender.try_send(line) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                counter!("audit_records_dropped_total").increment(1);
+            }
+        }
+    }
+}
+
+struct AuditWriter {
+    path: PathBuf,
+    file: BufWriter<File>,
+    size: u64,
+    max_bytes: u64,
+    max_files: usize,
+}
+
+impl AuditWriter {
+    fn open(config: &AuditConfig) -> io::Result<Self> {
+        let (file, size) = open_append(&config.path)?;
+        Ok(Self {
+            path: config.path.clone(),
+            file,
+            size,
+            max_bytes: config.max_bytes,
+            max_files: config.max_files,
+        })
+    }
+
+    /// Writes records as they arrive, flushing whenever the queue drains.
+    fn run(mut self, receiver: Receiver<String>) {
+        while let Ok(line) = receiver.recv() {
+            self.write_line(&line);
+            while let Ok(line) = receiver.try_recv() {
+                self.write_line(&line);
+            }
+            if let Err(e) = self.file.flush() {
+                warn!("Failed to flush audit log {}: {}", self.path.display(), e);
+            }
+        }
+    }
+
+    fn write_line(&mut self, line: &str) {
+        let len = line.len() as u64 + 1;
+        if self.size > 0 && self.size + len > self.max_bytes {
+            if let Err(e) = self.rotate() {
+                warn!("Failed to rotate audit log {}: {}", self.path.display(), e);
+            }
+        }
+
+        match writeln!(self.file, "{}", line) {
+            Ok(()) => self.size += len,
+            Err(e) => warn!("Failed to write audit log {}: {}", self.path.display(), e),
+        }
+    }
+
+    /// Shifts `<path>.N-1` to `<path>.N` down to `<path>` to `<path>.1`, discarding the
+    /// oldest, then starts a fresh file.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for index in (1..self.max_files).rev() {
+                let from = rotated_path(&self.path, index);
+                if from.exists() {
+                    fs::rename(&from, rotated_path(&self.path, index + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+
+        let (file, size) = open_append(&self.path)?;
+        self.file = file;
+        self.size = size;
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<(BufWriter<File>, u64)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok((BufWriter::new(file), size))
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}", index));
+    PathBuf::from(rotated)
+}
@@ -0,0 +1,774 @@
+use arc_swap::ArcSwap;
+use axum::{
+    body::StreamBody,
+    extract::State,
+    http::header,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use futures_util::stream;
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::clock::{Clock, SystemClock};
+use crate::config::BlacklistSyncConfig;
+use crate::error::{AppJson, AppQuery};
+use crate::ip;
+use crate::ip_set::{self, IpSet, IpSetMemory};
+use crate::{AppError, AppState};
+
+/// Number of entries written per chunk when streaming an export.
+const EXPORT_CHUNK_SIZE: usize = 1024;
+
+/// Upper bound on a single feed download, so a hung server can't stall the sync loop.
+const SYNC_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+// --- BLACKLIST STORE ---
+/// A set of IPs, plus temporary bans that stop matching once they expire. Imports and
+/// feed syncs only ever touch the permanent entries, so a replacing sync keeps the bans.
+///
+/// Entries are kept as `ip_set` keys behind a bloom filter, so a feed of millions costs
+/// a fraction of a set of strings, and a lookup that misses rarely touches the set.
+/// Entries and bans are both copied on write and swapped in whole, so lookups never
+/// take a lock and never see half an import.
+pub struct Blacklist {
+    entries: ArcSwap<IpSet>,
+    /// Held by whatever changes `entries` or `bans`, so a copy being changed can't miss
+    /// a change made to the original meanwhile.
+    writer: Mutex<()>,
+    /// Temporary bans and when they expire.
+    bans: ArcSwap<HashMap<u128, DateTime<Utc>>>,
+    /// The earliest ban expiry (millis), or `i64::MAX` without bans.
+    next_expiry: AtomicI64,
+    /// Where bans are kept across restarts (`BLACKLIST_BANS_PATH`), when set.
+    bans_path: Option<PathBuf>,
+    clock: RwLock<Arc<dyn Clock>>,
+    sync_status: RwLock<Option<SyncStatus>>,
+    /// Bumped on every change, so cached membership answers can tell they're stale.
+    version: AtomicU64,
+}
+
+impl Blacklist {
+    /// `false_positive_rate` sizes the bloom filter: about that share of lookups for IPs
+    /// not on the list fall through to the exact set (`IP_LIST_FALSE_POSITIVE_RATE`).
+    pub fn new(entries: impl IntoIterator<Item = u128>, false_positive_rate: f64) -> Self {
+        Self {
+            entries: ArcSwap::from_pointee(IpSet::build(entries.into_iter().collect(), false_positive_rate)),
+            writer: Mutex::new(()),
+            bans: ArcSwap::from_pointee(HashMap::new()),
+            next_expiry: AtomicI64::new(i64::MAX),
+            bans_path: None,
+            clock: RwLock::new(Arc::new(SystemClock)),
+            sync_status: RwLock::new(None),
+            version: AtomicU64::new(0),
+        }
+    }
+
+    /// Keeps temporary bans in `path`, one `ip,expires_at` line each, loading the ones
+    /// there that haven't expired. A missing file is an empty one.
+    pub fn with_bans_file(mut self, path: &Path) -> std::io::Result<Self> {
+        let body = match fs::read_to_string(path) {
+            Ok(body) => body,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let now = self.now();
+        let mut bans = HashMap::new();
+        for (index, line) in body.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let parsed = line.split_once(',').and_then(|(value, expires_at)| {
+                let expires_at = DateTime::parse_from_rfc3339(expires_at.trim()).ok()?.with_timezone(&Utc);
+                Some((ip_set::key(ip::parse(value)?), expires_at))
+            });
+            match parsed {
+                Some((ip, expires_at)) if expires_at > now => {
+                    bans.insert(ip, expires_at);
+                }
+                Some(_) => {}
+                None => warn!("Skipping invalid ban `{}` at {}:{}", line, path.display(), index + 1),
+            }
+        }
+        info!("Loaded {} temporary bans from {}", bans.len(), path.display());
+        self.next_expiry = AtomicI64::new(earliest_expiry(&bans));
+        self.bans = ArcSwap::from_pointee(bans);
+        self.bans_path = Some(path.to_path_buf());
+        Ok(self)
+    }
+
+    /// Reads ban expiries against `clock` instead of the system clock.
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) {
+        *self.clock.write().unwrap() = clock;
+    }
+
+    fn now(&self) -> DateTime<Utc> {
+        self.clock.read().unwrap().now()
+    }
+
+    /// Loads a blacklist file using the same format as the import endpoint. Invalid
+    /// lines are logged and skipped rather than failing startup.
+    pub fn load_file(path: &Path, false_positive_rate: f64) -> std::io::Result<Self> {
+        let parsed = parse_blacklist(&fs::read_to_string(path)?);
+        for entry in &parsed.invalid {
+            warn!("Skipping invalid IP list entry `{}` at {}:{}", entry.value, path.display(), entry.line);
+        }
+        let list = Self::new(parsed.entries, false_positive_rate);
+        let memory = list.memory();
+        info!(
+            "Loaded {} IP list entries from {}: {} in memory ({} of it the bloom filter), against {} as strings",
+            memory.entries,
+            path.display(),
+            mebibytes(memory.bytes),
+            mebibytes(memory.bloom_bytes),
+            mebibytes(memory.string_set_bytes)
+        );
+        Ok(list)
+    }
+
+    /// Permanent entries plus bans, expired or not until they're pruned.
+    pub fn len(&self) -> usize {
+        self.entries.load().len() + self.bans.load().len()
+    }
+
+    /// What the permanent entries take in memory. Bans are few and left out.
+    pub fn memory(&self) -> IpSetMemory {
+        self.entries.load().memory()
+    }
+
+    pub fn sync_status(&self) -> Option<SyncStatus> {
+        self.sync_status.read().unwrap().clone()
+    }
+
+    pub fn contains(&self, ip: &str) -> bool {
+        let Some(key) = ip::parse(ip).map(ip_set::key) else {
+            return false;
+        };
+        if self.entries.load().contains(key) {
+            return true;
+        }
+        match self.bans.load().get(&key) {
+            Some(expires_at) => self.now() < *expires_at,
+            None => false,
+        }
+    }
+
+    /// Also prunes bans that have expired since the last call, so their expiry counts
+    /// as a change to anything that cached an answer.
+    pub fn version(&self) -> u64 {
+        if self.next_expiry.load(Ordering::Acquire) <= self.now().timestamp_millis() {
+            self.prune_expired();
+        }
+        self.version.load(Ordering::Acquire)
+    }
+
+    /// Bans `ips` until `expires_at`, replacing any expiry they had. Returns how many
+    /// weren't banned already.
+    fn ban(&self, ips: Vec<u128>, expires_at: DateTime<Utc>) -> usize {
+        let _writer = self.writer.lock().unwrap();
+        let mut bans = HashMap::clone(&self.bans.load());
+        let added = ips.into_iter().filter(|ip| bans.insert(*ip, expires_at).is_none()).count();
+        self.next_expiry.store(earliest_expiry(&bans), Ordering::Release);
+        self.bans.store(Arc::new(bans));
+        self.version.fetch_add(1, Ordering::Release);
+        self.save_bans();
+        added
+    }
+
+    /// Drops expired bans, returning how many there were.
+    pub fn prune_expired(&self) -> usize {
+        let now = self.now();
+        let _writer = self.writer.lock().unwrap();
+        let current = self.bans.load_full();
+        let bans: HashMap<u128, DateTime<Utc>> = current
+            .iter()
+            .filter(|(_, expires_at)| **expires_at > now)
+            .map(|(ip, expires_at)| (*ip, *expires_at))
+            .collect();
+        let pruned = current.len() - bans.len();
+        self.next_expiry.store(earliest_expiry(&bans), Ordering::Release);
+        if pruned > 0 {
+            self.bans.store(Arc::new(bans));
+            self.version.fetch_add(1, Ordering::Release);
+            self.save_bans();
+        }
+        pruned
+    }
+
+    /// Rewrites the bans file, through a temporary file so a crash never leaves it
+    /// half written. A failed write is logged; the bans still hold until restart.
+    fn save_bans(&self) {
+        let Some(path) = &self.bans_path else {
+            return;
+        };
+        let mut bans: Vec<(String, DateTime<Utc>)> = self
+            .bans
+            .load()
+            .iter()
+            .map(|(ip, expires_at)| (ip_set::addr(*ip).to_string(), *expires_at))
+            .collect();
+        bans.sort();
+        let body: String = bans
+            .iter()
+            .map(|(ip, expires_at)| format!("{},{}\n", ip, expires_at.to_rfc3339()))
+            .collect();
+        let temporary = path.with_extension("tmp");
+        if let Err(e) = fs::write(&temporary, body).and_then(|()| fs::rename(&temporary, path)) {
+            warn!("Failed to save temporary bans to {}: {}", path.display(), e);
+        }
+    }
+
+    /// Merges `entries` into a copy of the current set and swaps it in, returning how
+    /// many were new. While the filter has room they're added to a copy of it; past that,
+    /// a bigger set is built.
+    fn extend(&self, entries: Vec<u128>) -> usize {
+        let _writer = self.writer.lock().unwrap();
+        let current = self.entries.load_full();
+        let (added, set) = if current.has_room(entries.len()) {
+            let mut set = IpSet::clone(&current);
+            (entries.into_iter().filter(|ip| set.insert(*ip)).count(), set)
+        } else {
+            let mut keys = current.keys().clone();
+            let added = entries.into_iter().filter(|ip| keys.insert(*ip)).count();
+            (added, IpSet::build(keys, current.false_positive_rate()))
+        };
+        drop(current);
+        if added > 0 {
+            self.swap(set);
+            self.version.fetch_add(1, Ordering::Release);
+        }
+        added
+    }
+
+    /// Swaps in a set built from `entries` in one step so readers never see a partial
+    /// list, returning how many entries were added and removed relative to the previous
+    /// set.
+    fn replace(&self, entries: HashSet<u128>) -> (usize, usize) {
+        let _writer = self.writer.lock().unwrap();
+        let (added, removed, false_positive_rate) = {
+            let current = self.entries.load();
+            let current_keys = current.keys();
+            (
+                entries.difference(current_keys).count(),
+                current_keys.difference(&entries).count(),
+                current.false_positive_rate(),
+            )
+        };
+        self.swap(IpSet::build(entries, false_positive_rate));
+        self.version.fetch_add(1, Ordering::Release);
+        (added, removed)
+    }
+
+    /// Puts `set` in place of the current one. Lookups already on the old set finish on
+    /// it; whichever lets go of it last frees it.
+    fn swap(&self, set: IpSet) {
+        self.entries.store(Arc::new(set));
+    }
+
+    /// Permanent entries and bans still in force.
+    fn snapshot(&self) -> Vec<String> {
+        let now = self.now();
+        let mut keys: HashSet<u128> = self.entries.load().keys().clone();
+        let bans = self.bans.load();
+        keys.extend(bans.iter().filter(|(_, expires_at)| **expires_at > now).map(|(ip, _)| *ip));
+        let mut entries: Vec<String> = keys.into_iter().map(|key| ip_set::addr(key).to_string()).collect();
+        entries.sort();
+        entries
+    }
+}
+
+fn mebibytes(bytes: usize) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+fn earliest_expiry(bans: &HashMap<u128, DateTime<Utc>>) -> i64 {
+    bans.values().map(DateTime::timestamp_millis).min().unwrap_or(i64::MAX)
+}
+
+// --- PARSING ---
+#[derive(Debug, Serialize)]
+pub struct InvalidEntry {
+    line: usize,
+    value: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ParsedBlacklist {
+    entries: HashSet<u128>,
+    duplicates: usize,
+    invalid: Vec<InvalidEntry>,
+}
+
+/// Parses a newline-delimited or CSV blacklist body. Only the first CSV column is
+/// read, blank lines and `#` comments are skipped, and an `ip` header row is allowed.
+pub fn parse_blacklist(body: &str) -> ParsedBlacklist {
+    let mut parsed = ParsedBlacklist::default();
+
+    for (index, line) in body.lines().enumerate() {
+        let value = line.split(',').next().unwrap_or("").trim().trim_matches('"');
+
+        if value.is_empty() || value.starts_with('#') {
+            continue;
+        }
+        if index == 0 && value.eq_ignore_ascii_case("ip") {
+            continue;
+        }
+
+        match ip::parse(value) {
+            Some(ip) => {
+                if !parsed.entries.insert(ip_set::key(ip)) {
+                    parsed.duplicates += 1;
+                }
+            }
+            None => parsed.invalid.push(InvalidEntry {
+                line: index + 1,
+                value: value.to_string(),
+            }),
+        }
+    }
+
+    parsed
+}
+
+// --- REMOTE SYNC ---
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncStatus {
+    last_attempt: DateTime<Utc>,
+    last_success: Option<DateTime<Utc>>,
+    status: SyncOutcome,
+    error: Option<String>,
+    added: usize,
+    removed: usize,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+enum SyncOutcome {
+    Updated,
+    NotModified,
+    Failed,
+}
+
+enum FetchResult {
+    NotModified,
+    Updated { body: String, etag: Option<String> },
+}
+
+async fn fetch_feed(
+    client: &reqwest::Client,
+    url: &str,
+    etag: Option<&str>,
+) -> Result<FetchResult, reqwest::Error> {
+    let mut request = client.get(url);
+    if let Some(etag) = etag {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+
+    let response = request.send().await?;
+    if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(FetchResult::NotModified);
+    }
+
+    let response = response.error_for_status()?;
+    let etag = response
+        .headers()
+        .get(ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    Ok(FetchResult::Updated {
+        body: response.text().await?,
+        etag,
+    })
+}
+
+/// Polls the configured threat feed forever. A failed fetch leaves the current list
+/// untouched; the outcome of every attempt is recorded for the readiness endpoint.
+/// `label` names the list in logs, e.g. `Blacklist`.
+pub async fn run_sync(blacklist: Arc<Blacklist>, label: &'static str, config: BlacklistSyncConfig) {
+    let client = match reqwest::Client::builder().timeout(SYNC_REQUEST_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("{} sync disabled, failed to build HTTP client: {}", label, e);
+            return;
+        }
+    };
+
+    let mut etag: Option<String> = None;
+    let mut last_success = None;
+    let mut ticker = tokio::time::interval(config.interval);
+
+    loop {
+        ticker.tick().await;
+        let now = Utc::now();
+
+        let status = match fetch_feed(&client, &config.url, etag.as_deref()).await {
+            Ok(FetchResult::NotModified) => {
+                last_success = Some(now);
+                info!("{} feed unchanged since last sync", label);
+                SyncStatus {
+                    last_attempt: now,
+                    last_success,
+                    status: SyncOutcome::NotModified,
+                    error: None,
+                    added: 0,
+                    removed: 0,
+                }
+            }
+            Ok(FetchResult::Updated { body, etag: new_etag }) => {
+                let parsed = parse_blacklist(&body);
+                let (added, removed) = if config.replace {
+                    blacklist.replace(parsed.entries)
+                } else {
+                    (blacklist.extend(parsed.entries.into_iter().collect()), 0)
+                };
+                etag = new_etag;
+                last_success = Some(now);
+                info!(
+                    "{} sync complete: added = {}, removed = {}, invalid = {}, total = {}",
+                    label,
+                    added,
+                    removed,
+                    parsed.invalid.len(),
+                    blacklist.len()
+                );
+                SyncStatus {
+                    last_attempt: now,
+                    last_success,
+                    status: SyncOutcome::Updated,
+                    error: None,
+                    added,
+                    removed,
+                }
+            }
+            Err(e) => {
+                warn!("{} sync from {} failed, keeping current list: {}", label, config.url, e);
+                SyncStatus {
+                    last_attempt: now,
+                    last_success,
+                    status: SyncOutcome::Failed,
+                    error: Some(e.to_string()),
+                    added: 0,
+                    removed: 0,
+                }
+            }
+        };
+
+        *blacklist.sync_status.write().unwrap() = Some(status);
+    }
+}
+
+// --- API ENDPOINT HANDLERS ---
+#[derive(Debug, Deserialize)]
+pub struct ImportParams {
+    #[serde(default)]
+    replace: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportSummary {
+    added: usize,
+    duplicates: usize,
+    invalid: Vec<InvalidEntry>,
+    replaced: bool,
+}
+
+/// Imports keep the limit they had before `MAX_BODY_BYTES`, since a feed easily
+/// outgrows an event-sized body.
+pub const MAX_IMPORT_BYTES: usize = 2 * 1024 * 1024;
+
+pub async fn import_handler(
+    State(state): State<Arc<AppState>>,
+    AppQuery(params): AppQuery<ImportParams>,
+    body: String,
+) -> Json<ImportSummary> {
+    Json(import(&state.ip_blacklist, "Blacklist", &body, &params))
+}
+
+/// Merges or swaps in an uploaded list; shared with the suspicious-source lists.
+pub fn import(list: &Blacklist, label: &str, body: &str, params: &ImportParams) -> ImportSummary {
+    // Parse before touching the lock so large imports don't stall the analysis path.
+    let parsed = parse_blacklist(body);
+    let mut duplicates = parsed.duplicates;
+
+    let added = if params.replace {
+        let added = parsed.entries.len();
+        list.replace(parsed.entries);
+        added
+    } else {
+        let candidates = parsed.entries.len();
+        let added = list.extend(parsed.entries.into_iter().collect());
+        duplicates += candidates - added;
+        added
+    };
+
+    info!(
+        "{} import complete: added = {}, duplicates = {}, invalid = {}, replaced = {}",
+        label,
+        added,
+        duplicates,
+        parsed.invalid.len(),
+        params.replace
+    );
+
+    ImportSummary {
+        added,
+        duplicates,
+        invalid: parsed.invalid,
+        replaced: params.replace,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlacklistAddition {
+    ips: Vec<String>,
+    /// How long the entries stay banned. Without it they're added for good.
+    ttl_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlacklistAdded {
+    added: usize,
+    /// When the bans expire; absent for permanent entries.
+    expires_at: Option<DateTime<Utc>>,
+}
+
+/// Adds entries, for good or as bans expiring after `ttlSecs`. Any invalid IP rejects
+/// the whole request.
+pub async fn add_handler(
+    State(state): State<Arc<AppState>>,
+    AppJson(addition): AppJson<BlacklistAddition>,
+) -> Result<Json<BlacklistAdded>, AppError> {
+    let invalid: Vec<&str> = addition
+        .ips
+        .iter()
+        .filter(|value| ip::parse(value).is_none())
+        .map(String::as_str)
+        .collect();
+    if !invalid.is_empty() {
+        return Err(AppError::Validation(format!("Invalid IP entries: {}", invalid.join(", "))));
+    }
+    let ips: Vec<u128> = addition.ips.iter().filter_map(|value| ip::parse(value)).map(ip_set::key).collect();
+
+    let (added, expires_at) = match addition.ttl_secs {
+        Some(0) => return Err(AppError::Validation("ttlSecs must be at least 1".to_string())),
+        Some(ttl_secs) => {
+            let ttl = chrono::Duration::seconds(i64::try_from(ttl_secs).unwrap_or(i64::MAX));
+            let expires_at = state.clock.now().checked_add_signed(ttl).unwrap_or(DateTime::<Utc>::MAX_UTC);
+            (state.ip_blacklist.ban(ips, expires_at), Some(expires_at))
+        }
+        None => (state.ip_blacklist.extend(ips), None),
+    };
+    info!("Blacklist entries added: added = {}, expires_at = {:?}", added, expires_at);
+    Ok(Json(BlacklistAdded { added, expires_at }))
+}
+
+pub async fn export_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    export(&state.ip_blacklist)
+}
+
+/// Streams the list as one sorted IP per line, in the format imports accept.
+pub fn export(list: &Blacklist) -> impl IntoResponse {
+    let entries = list.snapshot();
+    let chunks: Vec<Result<String, Infallible>> = entries
+        .chunks(EXPORT_CHUNK_SIZE)
+        .map(|chunk| {
+            let mut lines = chunk.join("\n");
+            lines.push('\n');
+            Ok(lines)
+        })
+        .collect();
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+        StreamBody::new(stream::iter(chunks)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    fn start() -> DateTime<Utc> {
+        "2024-05-01T12:00:00Z".parse().unwrap()
+    }
+
+    fn keys(ips: &[&str]) -> Vec<u128> {
+        ips.iter().map(|value| ip_set::key(ip::parse(value).unwrap())).collect()
+    }
+
+    /// An empty list reading expiries from a manual clock at `start`.
+    fn list() -> (Blacklist, Arc<ManualClock>) {
+        let clock = Arc::new(ManualClock::new(start()));
+        let list = Blacklist::new(Vec::new(), 0.01);
+        list.set_clock(clock.clone());
+        (list, clock)
+    }
+
+    fn scratch(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("blacklist-{}-{}", std::process::id(), name))
+    }
+
+    // --- BANS ---
+    #[test]
+    fn a_ban_matches_until_it_expires() {
+        let (list, clock) = list();
+        assert_eq!(list.ban(keys(&["203.0.113.5"]), start() + chrono::Duration::seconds(30)), 1);
+        assert!(list.contains("203.0.113.5"));
+        assert!(!list.contains("203.0.113.6"));
+
+        clock.advance(chrono::Duration::seconds(29));
+        assert!(list.contains("203.0.113.5"));
+        clock.advance(chrono::Duration::seconds(1));
+        assert!(!list.contains("203.0.113.5"));
+        assert_eq!(list.len(), 1, "expired but not yet pruned");
+        assert_eq!(list.snapshot(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn version_prunes_expired_bans_and_counts_it_as_a_change() {
+        let (list, clock) = list();
+        list.ban(keys(&["203.0.113.5"]), start() + chrono::Duration::seconds(30));
+        list.ban(keys(&["203.0.113.6"]), start() + chrono::Duration::seconds(90));
+        let before = list.version();
+        assert_eq!(list.version(), before);
+
+        clock.advance(chrono::Duration::seconds(60));
+        assert_eq!(list.version(), before + 1);
+        assert_eq!(list.len(), 1);
+        assert_eq!(list.prune_expired(), 0);
+        assert!(list.contains("203.0.113.6"));
+    }
+
+    #[test]
+    fn rebanning_replaces_the_expiry() {
+        let (list, clock) = list();
+        list.ban(keys(&["203.0.113.5"]), start() + chrono::Duration::seconds(30));
+        assert_eq!(list.ban(keys(&["203.0.113.5"]), start() + chrono::Duration::seconds(120)), 0);
+        clock.advance(chrono::Duration::seconds(60));
+        assert!(list.contains("203.0.113.5"));
+    }
+
+    #[test]
+    fn replacing_the_permanent_entries_keeps_the_bans() {
+        let (list, _clock) = list();
+        list.ban(keys(&["203.0.113.5"]), start() + chrono::Duration::seconds(30));
+        list.replace(keys(&["198.51.100.1"]).into_iter().collect());
+        assert!(list.contains("203.0.113.5"));
+        assert!(list.contains("198.51.100.1"));
+        assert_eq!(list.snapshot(), ["198.51.100.1", "203.0.113.5"]);
+    }
+
+    #[test]
+    fn bans_are_saved_and_reloaded_without_the_expired_or_invalid_ones() {
+        let path = scratch("bans.txt");
+        let lines = [
+            "203.0.113.7,2999-01-01T00:00:00+00:00",
+            "203.0.113.8,2000-01-01T00:00:00+00:00",
+            "not-an-ip,2999-01-01T00:00:00+00:00",
+            "203.0.113.9",
+        ];
+        fs::write(&path, lines.join("\n")).unwrap();
+        let list = Blacklist::new(Vec::new(), 0.01).with_bans_file(&path).unwrap();
+        assert_eq!(list.len(), 1);
+        assert!(list.contains("203.0.113.7"));
+
+        let expires_at: DateTime<Utc> = "2999-06-01T00:00:00Z".parse().unwrap();
+        list.ban(keys(&["2001:db8::1"]), expires_at);
+        let saved = fs::read_to_string(&path).unwrap();
+        assert_eq!(saved, "2001:db8::1,2999-06-01T00:00:00+00:00\n203.0.113.7,2999-01-01T00:00:00+00:00\n");
+        let reloaded = Blacklist::new(Vec::new(), 0.01).with_bans_file(&path).unwrap();
+        assert!(reloaded.contains("2001:db8::1") && reloaded.contains("203.0.113.7"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_missing_bans_file_is_an_empty_one() {
+        let list = Blacklist::new(Vec::new(), 0.01).with_bans_file(&scratch("missing.txt")).unwrap();
+        assert_eq!(list.len(), 0);
+    }
+
+    // --- CONCURRENT CHANGES ---
+    #[test]
+    fn concurrent_changes_are_never_lost() {
+        let list = Arc::new(Blacklist::new(Vec::new(), 0.01));
+        let writers: Vec<_> = (0..4u128)
+            .map(|writer| {
+                let list = list.clone();
+                std::thread::spawn(move || {
+                    for n in 0..100u128 {
+                        let key = ip_set::key(format!("10.{}.0.{}", writer, n).parse().unwrap());
+                        if n % 2 == 0 {
+                            list.extend(vec![key]);
+                        } else {
+                            list.ban(vec![key], DateTime::<Utc>::MAX_UTC);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert_eq!(list.len(), 400);
+        assert!(list.contains("10.3.0.99") && list.contains("10.0.0.0"));
+    }
+
+    // --- SYNC ---
+    /// A feed answering whatever `body` holds, or a `503` while it's `None`.
+    async fn feed(body: Arc<RwLock<Option<String>>>) -> String {
+        use axum::{http::StatusCode, routing::get, Router};
+        let app = Router::new().route(
+            "/exits",
+            get(move || {
+                let body = body.read().unwrap().clone();
+                async move { body.ok_or(StatusCode::SERVICE_UNAVAILABLE) }
+            }),
+        );
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/exits", listener.local_addr().unwrap());
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+        url
+    }
+
+    async fn eventually(done: impl Fn() -> bool) {
+        for _ in 0..200 {
+            if done() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("timed out");
+    }
+
+    #[tokio::test]
+    async fn a_synced_list_refreshes_and_keeps_its_entries_while_the_feed_is_down() {
+        let body = Arc::new(RwLock::new(Some("185.220.101.1\n".to_string())));
+        let config = BlacklistSyncConfig {
+            url: feed(body.clone()).await,
+            interval: Duration::from_millis(20),
+            replace: true,
+        };
+        let list = Arc::new(Blacklist::new(Vec::new(), 0.01));
+        let sync = tokio::spawn(run_sync(list.clone(), "Tor exit list", config));
+        eventually(|| list.contains("185.220.101.1")).await;
+
+        *body.write().unwrap() = Some("185.220.101.2\n".to_string());
+        eventually(|| list.contains("185.220.101.2") && !list.contains("185.220.101.1")).await;
+
+        *body.write().unwrap() = None;
+        eventually(|| matches!(list.sync_status(), Some(SyncStatus { status: SyncOutcome::Failed, .. }))).await;
+        assert!(list.contains("185.220.101.2"));
+        sync.abort();
+    }
+}
@@ -0,0 +1,192 @@
+use metrics::gauge;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+// --- CIRCUIT BREAKER ---
+/// Closed/open/half-open breaker for an external dependency. After `failure_threshold`
+/// consecutive failures calls are short-circuited for `cooldown`, then a single probe
+/// call is let through to decide whether to close again. A probe that never reports
+/// back (its caller gave up) is replaced by another after a further `cooldown`. The state is exported as the
+/// `circuit_breaker_state{dependency}` gauge: 0 closed, 1 half-open, 2 open.
+pub struct CircuitBreaker {
+    name: &'static str,
+    failure_threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BreakerState {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    HalfOpen { probe_started: Instant },
+}
+
+impl BreakerState {
+    fn gauge_value(self) -> f64 {
+        match self {
+            BreakerState::Closed { .. } => 0.0,
+            BreakerState::HalfOpen { .. } => 1.0,
+            BreakerState::Open { .. } => 2.0,
+        }
+    }
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &'static str, failure_threshold: u32, cooldown: Duration) -> Self {
+        gauge!("circuit_breaker_state", "dependency" => name).set(0.0);
+        Self {
+            name,
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            state: Mutex::new(BreakerState::Closed { failures: 0 }),
+        }
+    }
+
+    /// Returns whether a call may go ahead. Every allowed call must be followed by
+    /// `record_success` or `record_failure`.
+    pub fn allow(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            BreakerState::Closed { .. } => true,
+            BreakerState::Open { until } if Instant::now() >= until => {
+                let probe = BreakerState::HalfOpen {
+                    probe_started: Instant::now(),
+                };
+                self.transition(&mut state, probe);
+                true
+            }
+            BreakerState::Open { .. } => false,
+            BreakerState::HalfOpen { probe_started } if probe_started.elapsed() >= self.cooldown => {
+                *state = BreakerState::HalfOpen {
+                    probe_started: Instant::now(),
+                };
+                true
+            }
+            BreakerState::HalfOpen { .. } => false,
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if matches!(*state, BreakerState::HalfOpen { .. }) {
+            info!("Circuit breaker [{}] closed", self.name);
+        }
+        self.transition(&mut state, BreakerState::Closed { failures: 0 });
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        let failures = match *state {
+            BreakerState::Closed { failures } => failures + 1,
+            _ => self.failure_threshold,
+        };
+
+        let next = if failures >= self.failure_threshold {
+            warn!("Circuit breaker [{}] opened for {:?}", self.name, self.cooldown);
+            BreakerState::Open {
+                until: Instant::now() + self.cooldown,
+            }
+        } else {
+            BreakerState::Closed { failures }
+        };
+        self.transition(&mut state, next);
+    }
+
+    fn transition(&self, state: &mut BreakerState, next: BreakerState) {
+        if state.gauge_value() != next.gauge_value() {
+            gauge!("circuit_breaker_state", "dependency" => self.name).set(next.gauge_value());
+        }
+        *state = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOLDOWN: Duration = Duration::from_millis(30);
+
+    fn state(breaker: &CircuitBreaker) -> BreakerState {
+        *breaker.state.lock().unwrap()
+    }
+
+    /// A breaker its threshold of 3 failures has just opened.
+    fn opened() -> CircuitBreaker {
+        let breaker = CircuitBreaker::new("test", 3, COOLDOWN);
+        for _ in 0..3 {
+            assert!(breaker.allow());
+            breaker.record_failure();
+        }
+        breaker
+    }
+
+    #[test]
+    fn opens_after_the_threshold_of_consecutive_failures() {
+        let breaker = CircuitBreaker::new("test", 3, COOLDOWN);
+        breaker.record_failure();
+        breaker.record_failure();
+        assert_eq!(state(&breaker), BreakerState::Closed { failures: 2 });
+        assert!(breaker.allow());
+
+        // A success resets the count.
+        breaker.record_success();
+        breaker.record_failure();
+        breaker.record_failure();
+        assert!(breaker.allow());
+        breaker.record_failure();
+        assert!(matches!(state(&breaker), BreakerState::Open { .. }));
+        assert!(!breaker.allow());
+    }
+
+    #[test]
+    fn one_probe_is_let_through_after_the_cooldown() {
+        let breaker = opened();
+        assert!(!breaker.allow());
+        std::thread::sleep(COOLDOWN);
+        assert!(breaker.allow());
+        assert!(matches!(state(&breaker), BreakerState::HalfOpen { .. }));
+        // Others wait on the probe.
+        assert!(!breaker.allow());
+    }
+
+    #[test]
+    fn a_successful_probe_closes_the_breaker() {
+        let breaker = opened();
+        std::thread::sleep(COOLDOWN);
+        assert!(breaker.allow());
+        breaker.record_success();
+        assert_eq!(state(&breaker), BreakerState::Closed { failures: 0 });
+        assert!(breaker.allow());
+    }
+
+    #[test]
+    fn a_failed_probe_reopens_it_at_once() {
+        let breaker = opened();
+        std::thread::sleep(COOLDOWN);
+        assert!(breaker.allow());
+        breaker.record_failure();
+        assert!(matches!(state(&breaker), BreakerState::Open { .. }));
+        assert!(!breaker.allow());
+    }
+
+    #[test]
+    fn an_abandoned_probe_is_replaced_after_another_cooldown() {
+        let breaker = opened();
+        std::thread::sleep(COOLDOWN);
+        assert!(breaker.allow());
+        // Its caller gave up without reporting back.
+        assert!(!breaker.allow());
+        std::thread::sleep(COOLDOWN);
+        assert!(breaker.allow());
+        assert!(!breaker.allow());
+    }
+
+    #[test]
+    fn a_zero_threshold_opens_on_the_first_failure() {
+        let breaker = CircuitBreaker::new("test", 0, COOLDOWN);
+        breaker.record_failure();
+        assert!(!breaker.allow());
+    }
+}
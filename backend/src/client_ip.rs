@@ -0,0 +1,214 @@
+use axum::http::HeaderMap;
+use std::net::{IpAddr, SocketAddr};
+
+use crate::config::{ClientIpConfig, ClientIpSource};
+use crate::ip;
+
+// --- CLIENT IP DERIVATION ---
+/// The client IP of a request per `CLIENT_IP_SOURCE`, from its peer address and the
+/// values of its `Forwarded` and `X-Forwarded-For` headers. `None` when the request
+/// doesn't say who the client is in a way the service can trust.
+///
+/// Forwarding headers are only read when the peer is a trusted proxy, since anyone
+/// else could have written them. Entries are then walked from the nearest hop back,
+/// skipping trusted proxies; the first untrusted address is the client. `Forwarded`
+/// wins over `X-Forwarded-For` when a request has both. A trusted proxy that sent
+/// neither header, or an entry that isn't an address (`unknown`, an obfuscated
+/// identifier, garbage) before the client was reached, gives `None`.
+pub fn derive<'a>(
+    config: &ClientIpConfig,
+    peer: Option<IpAddr>,
+    forwarded: impl Iterator<Item = &'a str>,
+    x_forwarded_for: impl Iterator<Item = &'a str>,
+) -> Option<IpAddr> {
+    let peer = peer?.to_canonical();
+    if config.source == ClientIpSource::Connection || !is_trusted(config, peer) {
+        return Some(peer);
+    }
+
+    let mut hops: Vec<Option<IpAddr>> = forwarded.flat_map(forwarded_hops).collect();
+    if hops.is_empty() {
+        hops = x_forwarded_for
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(parse_node)
+            .collect();
+    }
+
+    // When every hop is a trusted proxy, the first one is the client itself (an
+    // internal caller going through the load balancer, say).
+    let first = *hops.first()?;
+    for hop in hops.into_iter().rev() {
+        let ip = hop?;
+        if !is_trusted(config, ip) {
+            return Some(ip);
+        }
+    }
+    first
+}
+
+/// `derive` for an HTTP request.
+pub fn from_headers(config: &ClientIpConfig, peer: Option<SocketAddr>, headers: &HeaderMap) -> Option<IpAddr> {
+    let values = |name: &'static str| headers.get_all(name).into_iter().filter_map(|value| value.to_str().ok());
+    derive(config, peer.map(|peer| peer.ip()), values("forwarded"), values("x-forwarded-for"))
+}
+
+fn is_trusted(config: &ClientIpConfig, ip: IpAddr) -> bool {
+    config.trusted_proxies.iter().any(|network| network.contains(&ip))
+}
+
+/// The `for=` node of each element of one `Forwarded` header value (RFC 7239). An
+/// element without one still counts as a hop, one that names nobody.
+fn forwarded_hops(value: &str) -> impl Iterator<Item = Option<IpAddr>> + '_ {
+    value.split(',').filter(|element| !element.trim().is_empty()).map(|element| {
+        element.split(';').find_map(|pair| {
+            let (key, node) = pair.split_once('=')?;
+            key.trim().eq_ignore_ascii_case("for").then(|| parse_node(node))
+        })
+        .flatten()
+    })
+}
+
+/// An address as proxies write them: bare, quoted, with a port (`192.0.2.1:4711`), or
+/// bracketed IPv6 with or without one (`[2001:db8::1]:4711`).
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Some(bracketed) = node.strip_prefix('[') {
+        return ip::parse(bracketed.split_once(']')?.0);
+    }
+    if let Some(ip) = ip::parse(node) {
+        return Some(ip);
+    }
+    // Only IPv4 can carry a port without brackets; bare IPv6 parsed above.
+    let (host, port) = node.rsplit_once(':')?;
+    port.parse::<u16>().ok()?;
+    Some(IpAddr::V4(host.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::allowlist;
+    use crate::config::ClientIpPolicy;
+
+    fn config(source: ClientIpSource, trusted: &[&str]) -> ClientIpConfig {
+        ClientIpConfig {
+            source,
+            trusted_proxies: trusted.iter().map(|proxy| allowlist::parse_network(proxy).unwrap()).collect(),
+            policy: ClientIpPolicy::Override,
+        }
+    }
+
+    fn behind_proxies() -> ClientIpConfig {
+        config(ClientIpSource::Forwarded, &["10.0.0.0/8", "2001:db8:ffff::/48"])
+    }
+
+    fn ip(raw: &str) -> Option<IpAddr> {
+        Some(raw.parse().unwrap())
+    }
+
+    /// `derive` for a request from `peer` with the given header values.
+    fn client(config: &ClientIpConfig, peer: &str, forwarded: &[&str], xff: &[&str]) -> Option<IpAddr> {
+        derive(config, peer.parse().ok(), forwarded.iter().copied(), xff.iter().copied())
+    }
+
+    // --- NODES ---
+    #[test]
+    fn nodes_parse_with_quotes_ports_and_brackets() {
+        assert_eq!(parse_node("192.0.2.1"), ip("192.0.2.1"));
+        assert_eq!(parse_node(" \"192.0.2.1:4711\" "), ip("192.0.2.1"));
+        assert_eq!(parse_node("2001:db8::1"), ip("2001:db8::1"));
+        assert_eq!(parse_node("[2001:db8::1]"), ip("2001:db8::1"));
+        assert_eq!(parse_node("\"[2001:db8::1]:4711\""), ip("2001:db8::1"));
+        assert_eq!(parse_node("::ffff:192.0.2.1"), ip("192.0.2.1"));
+        for garbage in ["unknown", "_hidden", "", "192.0.2.1:http", "[2001:db8::1", "2001:db8::1:99999"] {
+            assert_eq!(parse_node(garbage), None, "{:?}", garbage);
+        }
+    }
+
+    // --- SOURCES ---
+    #[test]
+    fn the_connection_source_ignores_forwarding_headers() {
+        let config = config(ClientIpSource::Connection, &["10.0.0.0/8"]);
+        assert_eq!(client(&config, "10.0.0.5", &["for=192.0.2.1"], &["192.0.2.2"]), ip("10.0.0.5"));
+        assert_eq!(client(&config, "::ffff:198.51.100.7", &[], &[]), ip("198.51.100.7"));
+        assert_eq!(client(&config, "not a peer", &[], &[]), None);
+    }
+
+    #[test]
+    fn a_trusted_proxy_without_headers_names_nobody() {
+        assert_eq!(client(&behind_proxies(), "10.0.0.5", &[], &[]), None);
+        assert_eq!(client(&behind_proxies(), "10.0.0.5", &[], &[" , "]), None);
+    }
+
+    // --- FORWARDING CHAINS ---
+    #[test]
+    fn multiple_x_forwarded_for_entries_are_walked_back_past_trusted_proxies() {
+        let config = behind_proxies();
+        assert_eq!(client(&config, "10.0.0.5", &[], &["203.0.113.9, 192.0.2.1, 10.1.2.3"]), ip("192.0.2.1"));
+        // Repeated headers are one list, in order.
+        assert_eq!(client(&config, "10.0.0.5", &[], &["203.0.113.9", "192.0.2.1", "10.1.2.3"]), ip("192.0.2.1"));
+        // Only trusted hops: the first is an internal client.
+        assert_eq!(client(&config, "10.0.0.5", &[], &["10.9.9.9, 10.1.2.3"]), ip("10.9.9.9"));
+    }
+
+    #[test]
+    fn an_unusable_entry_before_the_client_gives_none() {
+        let config = behind_proxies();
+        assert_eq!(client(&config, "10.0.0.5", &[], &["192.0.2.1, unknown, 10.1.2.3"]), None);
+        // Past the client it doesn't matter.
+        assert_eq!(client(&config, "10.0.0.5", &[], &["garbage, 192.0.2.1, 10.1.2.3"]), ip("192.0.2.1"));
+    }
+
+    #[test]
+    fn ipv6_clients_and_proxies() {
+        let config = behind_proxies();
+        assert_eq!(client(&config, "2001:db8:ffff::1", &[], &["2001:db8:1::7"]), ip("2001:db8:1::7"));
+        assert_eq!(
+            client(&config, "10.0.0.5", &["for=\"[2001:db8:1::7]:4711\", for=\"[2001:db8:ffff::2]\""], &[]),
+            ip("2001:db8:1::7")
+        );
+        assert_eq!(client(&config, "::ffff:10.0.0.5", &[], &["::ffff:192.0.2.1"]), ip("192.0.2.1"));
+    }
+
+    #[test]
+    fn forwarded_wins_over_x_forwarded_for() {
+        let config = behind_proxies();
+        let forwarded = ["for=192.0.2.1;proto=https;by=10.0.0.5, For=10.1.2.3"];
+        assert_eq!(client(&config, "10.0.0.5", &forwarded, &["203.0.113.9"]), ip("192.0.2.1"));
+        // An element without `for=` is a hop that names nobody.
+        assert_eq!(client(&config, "10.0.0.5", &["for=192.0.2.1, proto=https"], &[]), None);
+    }
+
+    // --- PROXY TRUST ---
+    #[test]
+    fn a_trusted_proxy_gives_the_rightmost_untrusted_hop() {
+        let config = config(ClientIpSource::Forwarded, &["10.0.0.0/8"]);
+        assert_eq!(client(&config, "10.0.0.5", &[], &["192.0.2.1"]), ip("192.0.2.1"));
+        assert_eq!(client(&config, "10.0.0.5", &[], &["192.0.2.1, 10.0.0.7"]), ip("192.0.2.1"));
+    }
+
+    #[test]
+    fn headers_from_an_untrusted_peer_are_ignored() {
+        let network = config(ClientIpSource::Forwarded, &["10.0.0.0/8"]);
+        assert_eq!(client(&network, "198.51.100.7", &[], &["192.0.2.1"]), ip("198.51.100.7"));
+        assert_eq!(client(&network, "198.51.100.7", &["for=192.0.2.1"], &[]), ip("198.51.100.7"));
+        // A single trusted address is trusted exactly.
+        let exact = config(ClientIpSource::Forwarded, &["10.0.0.5"]);
+        assert_eq!(client(&exact, "10.0.0.6", &[], &["192.0.2.1"]), ip("10.0.0.6"));
+    }
+
+    #[test]
+    fn entries_a_client_spoofs_ahead_of_the_proxy_are_ignored() {
+        let config = config(ClientIpSource::Forwarded, &["10.0.0.0/8"]);
+        // The client sent `X-Forwarded-For: 1.1.1.1, 10.0.0.9`, and the proxy appended
+        // the address it saw.
+        assert_eq!(client(&config, "10.0.0.5", &[], &["1.1.1.1, 10.0.0.9, 198.51.100.7"]), ip("198.51.100.7"));
+        // The same through two proxies, the client's copy of the header sent twice.
+        assert_eq!(
+            client(&config, "10.0.0.5", &[], &["1.1.1.1", "1.1.1.1, 198.51.100.7", "10.0.0.6"]),
+            ip("198.51.100.7")
+        );
+    }
+}
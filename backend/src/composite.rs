@@ -0,0 +1,159 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use crate::config::CompositeRuleConfig;
+use crate::rules::{RuleHit, RuleWeight};
+
+// --- COMPOSITE RULES ---
+/// A combination of other rules' results from the scoring config. It never looks at the
+/// event itself: it is evaluated after the rules it names, from the hits they left.
+pub struct CompositeRule {
+    name: String,
+    code: String,
+    points: i32,
+    description: String,
+    all_of: Vec<String>,
+    any_of: Vec<String>,
+    none_of: Vec<String>,
+}
+
+/// Checks every composite rule against `rule_names` (the native and scripted rules) and
+/// the other composites, and orders them so each comes after the composites it names.
+/// Fails on the first unknown reference or cycle, naming the rules involved.
+pub fn compile(configs: &[CompositeRuleConfig], rule_names: &HashSet<String>) -> Result<Vec<CompositeRule>, String> {
+    let mut codes = HashSet::new();
+    let mut composites = HashMap::new();
+    for config in configs {
+        let valid_name =
+            !config.name.is_empty() && config.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            return Err(format!(
+                "composite rule name `{}` must be letters, digits, and underscores",
+                config.name
+            ));
+        }
+        if rule_names.contains(&config.name) || !codes.insert(config.name.to_ascii_uppercase()) {
+            return Err(format!("composite rule `{}` reuses another rule's name", config.name));
+        }
+        if config.all_of.is_empty() && config.any_of.is_empty() {
+            return Err(format!("composite rule `{}` needs `all_of` or `any_of`", config.name));
+        }
+        composites.insert(config.name.as_str(), config);
+    }
+
+    for config in configs {
+        let references = config.all_of.iter().chain(&config.any_of).chain(&config.none_of);
+        if let Some(unknown) = references
+            .into_iter()
+            .find(|name| !rule_names.contains(*name) && !composites.contains_key(name.as_str()))
+        {
+            return Err(format!("composite rule `{}` refers to unknown rule `{}`", config.name, unknown));
+        }
+    }
+
+    // Depth-first, so a composite is emitted once everything it names has been.
+    let mut ordered = Vec::with_capacity(configs.len());
+    let mut done = HashSet::new();
+    for config in configs {
+        visit(config, &composites, &mut Vec::new(), &mut done, &mut ordered)?;
+    }
+    Ok(ordered.into_iter().map(CompositeRule::new).collect())
+}
+
+fn visit<'a>(
+    config: &'a CompositeRuleConfig,
+    composites: &HashMap<&str, &'a CompositeRuleConfig>,
+    path: &mut Vec<&'a str>,
+    done: &mut HashSet<&'a str>,
+    ordered: &mut Vec<&'a CompositeRuleConfig>,
+) -> Result<(), String> {
+    if done.contains(config.name.as_str()) {
+        return Ok(());
+    }
+    if let Some(start) = path.iter().position(|name| *name == config.name) {
+        let mut cycle = path[start..].to_vec();
+        cycle.push(&config.name);
+        return Err(format!("composite rules form a cycle: {}", cycle.join(" -> ")));
+    }
+
+    path.push(&config.name);
+    for name in config.all_of.iter().chain(&config.any_of).chain(&config.none_of) {
+        if let Some(child) = composites.get(name.as_str()) {
+            visit(child, composites, path, done, ordered)?;
+        }
+    }
+    path.pop();
+    done.insert(&config.name);
+    ordered.push(config);
+    Ok(())
+}
+
+impl CompositeRule {
+    fn new(config: &CompositeRuleConfig) -> Self {
+        let description = config.description.clone().unwrap_or_else(|| {
+            let mut parts = Vec::new();
+            for (label, names) in [("all of", &config.all_of), ("any of", &config.any_of), ("none of", &config.none_of)] {
+                if !names.is_empty() {
+                    parts.push(format!("{} {}", label, names.join(", ")));
+                }
+            }
+            format!("Combines {}", parts.join("; "))
+        });
+        Self {
+            name: config.name.clone(),
+            code: config.name.to_ascii_uppercase(),
+            points: config.points,
+            description,
+            all_of: config.all_of.clone(),
+            any_of: config.any_of.clone(),
+            none_of: config.none_of.clone(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    pub fn weight(&self) -> RuleWeight {
+        RuleWeight {
+            code: Cow::Owned(self.code.clone()),
+            points: self.points,
+        }
+    }
+
+    /// Every rule the composite names, whatever the combinator.
+    pub fn references(&self) -> impl Iterator<Item = &str> {
+        self.all_of.iter().chain(&self.any_of).chain(&self.none_of).map(String::as_str)
+    }
+
+    /// The composite's hit when the combination holds, given each rule's hits for the
+    /// event (`None` for rules that didn't hit). The hits of the `all_of` and `any_of`
+    /// rules that matched are nested under it.
+    pub fn evaluate<'h>(&self, hits_of: impl Fn(&str) -> Option<&'h [RuleHit]>) -> Option<RuleHit> {
+        if !self.all_of.iter().all(|name| hits_of(name).is_some())
+            || (!self.any_of.is_empty() && !self.any_of.iter().any(|name| hits_of(name).is_some()))
+            || self.none_of.iter().any(|name| hits_of(name).is_some())
+        {
+            return None;
+        }
+
+        let matched: Vec<&str> = self
+            .all_of
+            .iter()
+            .chain(&self.any_of)
+            .map(String::as_str)
+            .filter(|name| hits_of(name).is_some())
+            .collect();
+        let children = matched.iter().filter_map(|name| hits_of(name)).flatten().cloned().collect();
+        Some(RuleHit {
+            code: Cow::Owned(self.code.clone()),
+            points: self.points,
+            reason: format!("Rules {} matched together", matched.join(", ")),
+            children,
+        })
+    }
+}
@@ -0,0 +1,2282 @@
+use axum::http::{HeaderName, HeaderValue};
+use ipnet::IpNet;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::allowlist;
+use crate::privacy::{IpPrivacy, LogIpMode};
+use crate::sources::SourceCategory;
+use crate::EventType;
+
+// --- SERVICE CONFIGURATION ---
+/// Deployment settings read from environment variables at startup.
+#[derive(Debug, Clone)]
+pub struct ServiceConfig {
+    /// File of blacklisted IPs loaded at startup (`BLACKLIST_PATH`).
+    pub blacklist_path: Option<PathBuf>,
+    /// File temporary bans are kept in across restarts (`BLACKLIST_BANS_PATH`).
+    pub blacklist_bans_path: Option<PathBuf>,
+    pub blacklist_sync: Option<BlacklistSyncConfig>,
+    /// Tor exit and open proxy lists, loaded and synced like the blacklist.
+    pub source_lists: Vec<(SourceCategory, SourceListConfig)>,
+    /// Share of lookups for IPs not on a list that the bloom filter in front of each IP
+    /// list lets through to the exact set (`IP_LIST_FALSE_POSITIVE_RATE`, default 0.01).
+    pub ip_list_false_positive_rate: f64,
+    pub ip_reputation: Option<ReputationConfig>,
+    pub model: Option<ModelConfig>,
+    /// Shared Redis event store (`REDIS_URL`); events stay in process memory when unset.
+    pub redis_url: Option<String>,
+    /// How long a session outlives its last event in Redis and in the search index
+    /// (`REDIS_SESSION_TTL_SECS`, default 86400).
+    pub redis_session_ttl: Duration,
+    /// How long a user's activity profile outlives their last event, in memory or Redis
+    /// (`ACTIVITY_PROFILE_TTL_SECS`, default 31536000).
+    pub activity_profile_ttl: Duration,
+    pub write_behind: Option<WriteBehindConfig>,
+    pub grpc: Option<GrpcConfig>,
+    /// How far an event's timestamp may be from the server clock, either way, before the
+    /// event is rejected (`EVENT_FRESHNESS_WINDOW_SECS`, default 600; 0 disables).
+    pub freshness_window: Option<chrono::Duration>,
+    /// Newest events kept per session; older ones are dropped on push
+    /// (`MAX_EVENTS_PER_SESSION`, default 500).
+    pub max_events_per_session: usize,
+    /// Analyses in flight across HTTP and gRPC before new ones get `429`
+    /// (`MAX_IN_FLIGHT_ANALYSES`, default 1024).
+    pub max_in_flight_analyses: usize,
+    /// HTTP requests in flight on the API routes before new ones get `503`
+    /// (`MAX_IN_FLIGHT_REQUESTS`, default 0: no cap).
+    pub max_in_flight_requests: Option<usize>,
+    /// Events one `/api/v1/stream` connection scores at once before it stops reading
+    /// more (`STREAM_MAX_IN_FLIGHT`, default 8).
+    pub stream_max_in_flight: usize,
+    pub store_capacity: Option<StoreCapacity>,
+    /// Largest request body the API reads (`MAX_BODY_BYTES`, default 65536). Larger
+    /// bodies get `413`.
+    pub max_body_bytes: usize,
+    /// How long a request may run before it gets `504` (`REQUEST_TIMEOUT_MS`, default
+    /// 2000; 0 disables).
+    pub request_timeout: Option<Duration>,
+    /// How long per-session facts (blacklist membership, GeoIP, user agent matches) are
+    /// reused (`SESSION_CACHE_TTL_SECS`, default 300; 0 disables the cache).
+    pub session_cache_ttl: Option<Duration>,
+    pub response_cache: Option<ResponseCacheConfig>,
+    pub metadata_limits: MetadataLimits,
+    /// Metadata keys kept in the stored copy of each event (`RETAINED_METADATA_KEYS`).
+    pub metadata_retention: MetadataRetention,
+    /// Storing only some low-risk events (`STORAGE_SAMPLE_RATES`), unset to store all.
+    pub storage_sampling: Option<StorageSamplingConfig>,
+    /// Browser origins allowed to call the API (`ALLOWED_ORIGINS`, comma-separated).
+    /// Empty means no CORS headers are sent and browsers block cross-origin calls.
+    pub allowed_origins: Vec<HeaderValue>,
+    /// Default response shape for the events endpoint (`RESPONSE_VERBOSITY=full|minimal`).
+    pub response_verbosity: Verbosity,
+    /// MaxMind City database used for country lookups (`GEOIP_CITY_DB_PATH`).
+    pub geoip_city_db_path: Option<PathBuf>,
+    /// MaxMind ASN, ISP, or Enterprise database used for network lookups
+    /// (`GEOIP_ASN_DB_PATH`).
+    pub geoip_asn_db_path: Option<PathBuf>,
+    /// Disposable email domains replacing the bundled list (`DISPOSABLE_DOMAINS_PATH`).
+    pub disposable_domains_path: Option<PathBuf>,
+    /// JSON Schema that JSON event bodies must match (`EVENT_SCHEMA_PATH`); unset skips
+    /// the check.
+    pub event_schema_path: Option<PathBuf>,
+    pub audit: Option<AuditConfig>,
+    pub webhook: Option<WebhookConfig>,
+    pub usage: UsageConfig,
+    pub degradation: DegradationPolicy,
+    /// What is kept of event IPs after scoring (`IP_PRIVACY_MODE=off|hash|truncate`,
+    /// with `IP_PRIVACY_SALT` required for `hash`).
+    pub ip_privacy: IpPrivacy,
+    /// How IPs appear in logs (`LOG_IP_MODE=full|mask|omit`, default `mask`).
+    pub log_ip: LogIpMode,
+    /// Clean analyses get their log line one in this many times (`LOG_SAMPLE_RATE`,
+    /// default 1: every one). Flagged analyses are always logged.
+    pub log_sample_rate: u64,
+    /// Seed for generated request ids (`RANDOM_SEED`), so replays and tests get the same
+    /// ids run after run. They are random when unset.
+    pub random_seed: Option<u64>,
+    /// Where the scored client IP comes from when not the payload's `ipAddress`
+    /// (`CLIENT_IP_SOURCE`).
+    pub client_ip: Option<ClientIpConfig>,
+}
+
+/// Deriving the client IP server-side instead of trusting the payload.
+#[derive(Debug, Clone)]
+pub struct ClientIpConfig {
+    /// `CLIENT_IP_SOURCE=connection|forwarded`.
+    pub source: ClientIpSource,
+    /// Proxies whose forwarding headers are believed (`TRUSTED_PROXIES`, comma-separated
+    /// IPs or CIDRs). Required for `forwarded`.
+    pub trusted_proxies: Vec<IpNet>,
+    /// `CLIENT_IP_POLICY=override|cross_check`, default `override`.
+    pub policy: ClientIpPolicy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientIpSource {
+    /// The TCP peer address.
+    Connection,
+    /// The `Forwarded` or `X-Forwarded-For` entry added by the outermost trusted proxy.
+    Forwarded,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientIpPolicy {
+    /// The derived IP replaces the payload's for scoring and storage.
+    Override,
+    /// The payload IP is kept, and the `ip_mismatch` rule scores disagreement.
+    CrossCheck,
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditConfig {
+    /// Append-only decision log (`AUDIT_LOG_PATH`).
+    pub path: PathBuf,
+    /// Size at which the file is rotated (`AUDIT_LOG_MAX_BYTES`, default 100 MiB).
+    pub max_bytes: u64,
+    /// Rotated files kept as `<path>.1` (newest) to `<path>.N` (`AUDIT_LOG_MAX_FILES`, default 10).
+    pub max_files: usize,
+    /// Records buffered for the writer before new ones are dropped (`AUDIT_LOG_QUEUE_SIZE`, default 10000).
+    pub queue_size: usize,
+    /// Adds each rule's code and points to the record (`AUDIT_LOG_INCLUDE_BREAKDOWN`, default false).
+    pub include_breakdown: bool,
+}
+
+/// Notifying a receiver of every flagged event.
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// Receives a JSON `POST` per flagged event (`WEBHOOK_URL`).
+    pub url: String,
+    /// Latency budget for one attempt (`WEBHOOK_TIMEOUT_MS`, default 2000).
+    pub timeout: Duration,
+    /// Attempts before a delivery is marked failed (`WEBHOOK_MAX_ATTEMPTS`, default 3).
+    pub max_attempts: u32,
+    /// Deliveries kept in the log, oldest dropped first (`WEBHOOK_LOG_SIZE`, default 10000).
+    pub log_size: usize,
+    /// How long deliveries stay in the log (`WEBHOOK_LOG_MAX_AGE_SECS`, default 86400).
+    pub log_max_age: Duration,
+}
+
+/// Attributing ingested traffic to the API key it came in under.
+#[derive(Debug, Clone)]
+pub struct UsageConfig {
+    /// Header carrying the key (`USAGE_KEY_HEADER`, default `x-api-key`).
+    pub key_header: HeaderName,
+    /// How long hourly counts are kept (`USAGE_RETENTION_DAYS`, default 35).
+    pub retention_days: u32,
+    /// Keys tracked per instance; further keys are counted as `other` (`USAGE_MAX_KEYS`,
+    /// default 1000).
+    pub max_keys: usize,
+}
+
+/// Reusing explain and simulate responses for repeated identical requests.
+#[derive(Debug, Clone)]
+pub struct ResponseCacheConfig {
+    /// Responses kept, least recently used dropped first (`RESPONSE_CACHE_SIZE`, default
+    /// 256; 0 disables the cache).
+    pub size: usize,
+    /// How long a response is reused (`RESPONSE_CACHE_TTL_SECS`, default 60).
+    pub ttl: Duration,
+}
+
+/// Storing a share of the events of some types while their session looks clean.
+#[derive(Debug, Clone)]
+pub struct StorageSamplingConfig {
+    /// Share of each event type's events stored, from 0 to 1 (`STORAGE_SAMPLE_RATES`, as
+    /// `Click=0.1,PageLoad=0.5`). Types left out are always stored.
+    pub rates: HashMap<EventType, f64>,
+    /// Sessions whose latest score is this or more store every event
+    /// (`STORAGE_SAMPLING_MAX_SCORE`, default 10).
+    pub max_score: i32,
+}
+
+/// Parses `STORAGE_SAMPLE_RATES`: comma-separated `EventType=rate` pairs.
+fn parse_sample_rates(value: &str) -> Result<HashMap<EventType, f64>, ConfigError> {
+    use serde::de::IntoDeserializer;
+
+    let invalid = |pair: &str| ConfigError(format!("STORAGE_SAMPLE_RATES has an invalid entry `{}`, expected EventType=rate with a rate from 0 to 1", pair));
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (event_type, rate) = pair.split_once('=').ok_or_else(|| invalid(pair))?;
+            let event_type: Result<EventType, serde::de::value::Error> = EventType::deserialize(event_type.trim().into_deserializer());
+            let rate: f64 = rate.trim().parse().map_err(|_| invalid(pair))?;
+            match (event_type, (0.0..=1.0).contains(&rate)) {
+                (Ok(event_type), true) => Ok((event_type, rate)),
+                _ => Err(invalid(pair)),
+            }
+        })
+        .collect()
+}
+
+/// Writing events to Redis in the background (`EVENT_WRITE_BEHIND`, default false).
+#[derive(Debug, Clone)]
+pub struct WriteBehindConfig {
+    /// Events waiting to be written before new ones are scored without being persisted
+    /// (`EVENT_WRITE_QUEUE_SIZE`, default 10000).
+    pub queue_size: usize,
+    /// Events written per batch (`EVENT_WRITE_BATCH_SIZE`, default 100).
+    pub batch_size: usize,
+    /// Longest a queued event waits for its batch to fill (`EVENT_WRITE_FLUSH_MS`, default 50).
+    pub flush_interval: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct GrpcConfig {
+    /// Port for the gRPC API (`GRPC_PORT`); it isn't served when unset.
+    pub port: u16,
+    /// Calls in flight before new ones are refused (`GRPC_MAX_CONCURRENT_REQUESTS`, default 256).
+    pub max_concurrent_requests: usize,
+}
+
+/// Bounds on one event's metadata, checked before scoring.
+#[derive(Debug, Clone)]
+pub struct MetadataLimits {
+    /// `MAX_METADATA_KEYS`, default 64.
+    pub max_keys: usize,
+    /// `MAX_METADATA_VALUE_BYTES`, default 2048.
+    pub max_value_bytes: usize,
+    /// `METADATA_LIMIT_MODE=reject|truncate`, default `reject`.
+    pub mode: MetadataLimitMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataLimitMode {
+    /// Oversized metadata is a `422`.
+    Reject,
+    /// Excess keys are dropped and long values cut short, and the result says so.
+    Truncate,
+}
+
+/// Metadata the rules read back from stored history. Other keys only matter for the
+/// event being scored, so they aren't stored unless `RETAINED_METADATA_KEYS` names them.
+pub const DEFAULT_RETAINED_METADATA_KEYS: &[&str] = &["amount", "currency", "userAgent"];
+
+#[derive(Debug, Clone)]
+pub enum MetadataRetention {
+    /// Every key is stored (`RETAINED_METADATA_KEYS=*`).
+    All,
+    Keys(HashSet<String>),
+}
+
+/// A cap on the events the in-memory store holds across all sessions (`MAX_STORED_EVENTS`).
+#[derive(Debug, Clone)]
+pub struct StoreCapacity {
+    pub max_events: usize,
+    /// What a full store does with an event for a session it doesn't hold
+    /// (`STORE_FULL_POLICY=shed_oldest|reject_new`, default `shed_oldest`).
+    pub policy: StoreFullPolicy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StoreFullPolicy {
+    /// Drop the least recently active sessions to make room.
+    ShedOldest,
+    /// Turn the event away with `503`; sessions already stored carry on.
+    RejectNew,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verbosity {
+    /// Every result carries its reasons and breakdown.
+    Full,
+    /// Clean results carry only the session id, score, and flag.
+    Minimal,
+}
+
+#[derive(Debug, Clone)]
+pub struct BlacklistSyncConfig {
+    /// Threat feed to poll (`BLACKLIST_SYNC_URL`).
+    pub url: String,
+    /// Poll interval (`BLACKLIST_SYNC_INTERVAL_SECS`, default 900).
+    pub interval: Duration,
+    /// Whether a fetched list replaces the current set or is merged into it
+    /// (`BLACKLIST_SYNC_MODE=replace|merge`, default merge).
+    pub replace: bool,
+}
+
+/// Where a suspicious-source list comes from: a file loaded at startup
+/// (`TOR_EXIT_LIST_PATH`) and a feed (`TOR_EXIT_SYNC_URL`, with the same interval and
+/// mode settings as the blacklist's), or neither, leaving it to the admin endpoints.
+#[derive(Debug, Clone, Default)]
+pub struct SourceListConfig {
+    pub path: Option<PathBuf>,
+    pub sync: Option<BlacklistSyncConfig>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReputationConfig {
+    /// Lookup URL with an `{ip}` placeholder (`IP_REPUTATION_URL`).
+    pub url: String,
+    /// Sent as the `Key` header when set (`IP_REPUTATION_API_KEY`).
+    pub api_key: Option<String>,
+    /// JSON pointer to the 0-100 confidence in the response
+    /// (`IP_REPUTATION_SCORE_POINTER`, default `/data/abuseConfidenceScore`).
+    pub score_pointer: String,
+    /// How long a lookup result is reused (`IP_REPUTATION_CACHE_TTL_SECS`, default 3600).
+    pub cache_ttl: Duration,
+    /// Latency budget for a lookup, including waiting for a permit
+    /// (`IP_REPUTATION_TIMEOUT_MS`, default 100).
+    pub timeout: Duration,
+    /// Maximum concurrent outbound lookups (`IP_REPUTATION_MAX_CONCURRENCY`, default 8).
+    pub max_concurrency: usize,
+    /// Consecutive failures that open the breaker (`IP_REPUTATION_BREAKER_FAILURES`, default 5).
+    pub breaker_failures: u32,
+    /// How long the breaker stays open (`IP_REPUTATION_BREAKER_COOLDOWN_SECS`, default 30).
+    pub breaker_cooldown: Duration,
+    /// `IP_REPUTATION_ON_MISS=await|background`, default await.
+    pub on_miss: ReputationMissMode,
+}
+
+#[derive(Debug, Clone)]
+pub struct ModelConfig {
+    pub source: ModelSource,
+    /// Latency budget for one inference (`MODEL_TIMEOUT_MS`, default 20).
+    pub timeout: Duration,
+    /// Consecutive failed or late inferences that open the breaker (`MODEL_BREAKER_FAILURES`, default 5).
+    pub breaker_failures: u32,
+    /// How long the breaker stays open (`MODEL_BREAKER_COOLDOWN_SECS`, default 30).
+    pub breaker_cooldown: Duration,
+}
+
+/// The model scored alongside the rules; at most one may be configured.
+#[derive(Debug, Clone)]
+pub enum ModelSource {
+    /// ONNX file run in-process (`MODEL_PATH`).
+    Onnx(PathBuf),
+    /// External model service (`MODEL_GRPC_ADDR`, e.g. `http://model:50051`).
+    Grpc(String),
+}
+
+impl fmt::Display for ModelSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelSource::Onnx(path) => write!(f, "{}", path.display()),
+            ModelSource::Grpc(addr) => f.write_str(addr),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ReputationMissMode {
+    /// Wait for the lookup (up to the latency budget) before scoring.
+    Await,
+    /// Score without the lookup and warm the cache for the next event.
+    Background,
+}
+
+/// What becomes of an event scored while a dependency is failing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureMode {
+    /// Score without the rules that need it, and mark the result `degraded`.
+    SkipOnFailure,
+    /// Turn the event away with a `503`.
+    FailRequest,
+}
+
+/// The failure mode of each dependency that can fail while an event is scored.
+#[derive(Debug, Clone)]
+pub struct DegradationPolicy {
+    /// `IP_REPUTATION_ON_FAILURE`, default `skip_on_failure`.
+    pub ip_reputation: FailureMode,
+    /// `MODEL_ON_FAILURE`, default `skip_on_failure`.
+    pub model: FailureMode,
+    /// `ACTIVITY_STORE_ON_FAILURE`, default `fail_request`.
+    pub activity_store: FailureMode,
+    /// `ENTITY_REPUTATION_ON_FAILURE`, default `skip_on_failure`.
+    pub entity_reputation: FailureMode,
+}
+
+fn failure_mode(name: &str, default: FailureMode) -> Result<FailureMode, ConfigError> {
+    match env_var(name).as_deref() {
+        None => Ok(default),
+        Some("skip_on_failure") => Ok(FailureMode::SkipOnFailure),
+        Some("fail_request") => Ok(FailureMode::FailRequest),
+        Some(other) => Err(ConfigError(format!(
+            "{} must be `skip_on_failure` or `fail_request`, got `{}`",
+            name, other
+        ))),
+    }
+}
+
+impl ServiceConfig {
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let blacklist_sync = list_sync_config("BLACKLIST")?;
+        let source_lists = SourceCategory::ALL
+            .into_iter()
+            .map(|category| {
+                let prefix = category.env_prefix();
+                let config = SourceListConfig {
+                    path: env_var(&format!("{}_LIST_PATH", prefix)).map(PathBuf::from),
+                    sync: list_sync_config(prefix)?,
+                };
+                Ok((category, config))
+            })
+            .collect::<Result<Vec<_>, ConfigError>>()?;
+        let ip_list_false_positive_rate = env_parse("IP_LIST_FALSE_POSITIVE_RATE", 0.01)?;
+        if !(ip_list_false_positive_rate > 0.0 && ip_list_false_positive_rate < 1.0) {
+            return Err(ConfigError("IP_LIST_FALSE_POSITIVE_RATE must be between 0 and 1".to_string()));
+        }
+
+        let ip_reputation = match env_var("IP_REPUTATION_URL") {
+            Some(url) => Some(ReputationConfig {
+                url,
+                api_key: env_var("IP_REPUTATION_API_KEY"),
+                score_pointer: env_var("IP_REPUTATION_SCORE_POINTER")
+                    .unwrap_or_else(|| "/data/abuseConfidenceScore".to_string()),
+                cache_ttl: Duration::from_secs(env_parse("IP_REPUTATION_CACHE_TTL_SECS", 3600)?),
+                timeout: Duration::from_millis(env_parse("IP_REPUTATION_TIMEOUT_MS", 100)?),
+                max_concurrency: env_parse("IP_REPUTATION_MAX_CONCURRENCY", 8)?,
+                breaker_failures: env_parse("IP_REPUTATION_BREAKER_FAILURES", 5)?,
+                breaker_cooldown: Duration::from_secs(env_parse("IP_REPUTATION_BREAKER_COOLDOWN_SECS", 30)?),
+                on_miss: match env_var("IP_REPUTATION_ON_MISS").as_deref() {
+                    None | Some("await") => ReputationMissMode::Await,
+                    Some("background") => ReputationMissMode::Background,
+                    Some(other) => {
+                        return Err(ConfigError(format!(
+                            "IP_REPUTATION_ON_MISS must be `await` or `background`, got `{}`",
+                            other
+                        )))
+                    }
+                },
+            }),
+            None => None,
+        };
+
+        let allowed_origins = env_var("ALLOWED_ORIGINS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|origin| !origin.is_empty())
+            .map(|origin| {
+                HeaderValue::from_str(origin)
+                    .map_err(|_| ConfigError(format!("ALLOWED_ORIGINS has an invalid origin `{}`", origin)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let response_verbosity = match env_var("RESPONSE_VERBOSITY").as_deref() {
+            None | Some("full") => Verbosity::Full,
+            Some("minimal") => Verbosity::Minimal,
+            Some(other) => {
+                return Err(ConfigError(format!(
+                    "RESPONSE_VERBOSITY must be `full` or `minimal`, got `{}`",
+                    other
+                )))
+            }
+        };
+
+        let audit = match env_var("AUDIT_LOG_PATH") {
+            Some(path) => Some(AuditConfig {
+                path: PathBuf::from(path),
+                max_bytes: env_parse("AUDIT_LOG_MAX_BYTES", 100 * 1024 * 1024)?,
+                max_files: env_parse("AUDIT_LOG_MAX_FILES", 10)?,
+                queue_size: env_parse("AUDIT_LOG_QUEUE_SIZE", 10_000)?,
+                include_breakdown: env_parse("AUDIT_LOG_INCLUDE_BREAKDOWN", false)?,
+            }),
+            None => None,
+        };
+
+        let webhook = match env_var("WEBHOOK_URL") {
+            Some(url) => {
+                let max_attempts = env_parse("WEBHOOK_MAX_ATTEMPTS", 3)?;
+                if max_attempts == 0 {
+                    return Err(ConfigError("WEBHOOK_MAX_ATTEMPTS must be at least 1".to_string()));
+                }
+                Some(WebhookConfig {
+                    url,
+                    timeout: Duration::from_millis(env_parse("WEBHOOK_TIMEOUT_MS", 2000)?),
+                    max_attempts,
+                    log_size: env_parse("WEBHOOK_LOG_SIZE", 10_000)?,
+                    log_max_age: Duration::from_secs(env_parse("WEBHOOK_LOG_MAX_AGE_SECS", 86_400)?),
+                })
+            }
+            None => None,
+        };
+
+        let degradation = DegradationPolicy {
+            ip_reputation: failure_mode("IP_REPUTATION_ON_FAILURE", FailureMode::SkipOnFailure)?,
+            model: failure_mode("MODEL_ON_FAILURE", FailureMode::SkipOnFailure)?,
+            activity_store: failure_mode("ACTIVITY_STORE_ON_FAILURE", FailureMode::FailRequest)?,
+            entity_reputation: failure_mode("ENTITY_REPUTATION_ON_FAILURE", FailureMode::SkipOnFailure)?,
+        };
+
+        let key_header = env_var("USAGE_KEY_HEADER").unwrap_or_else(|| "x-api-key".to_string());
+        let usage = UsageConfig {
+            key_header: HeaderName::try_from(key_header.trim())
+                .map_err(|_| ConfigError(format!("USAGE_KEY_HEADER is not a valid header name: `{}`", key_header)))?,
+            retention_days: env_parse("USAGE_RETENTION_DAYS", 35)?,
+            max_keys: env_parse("USAGE_MAX_KEYS", 1_000)?,
+        };
+        if usage.retention_days == 0 {
+            return Err(ConfigError("USAGE_RETENTION_DAYS must be at least 1".to_string()));
+        }
+
+        let write_behind = match env_parse("EVENT_WRITE_BEHIND", false)? {
+            true => {
+                let config = WriteBehindConfig {
+                    queue_size: env_parse("EVENT_WRITE_QUEUE_SIZE", 10_000)?,
+                    batch_size: env_parse("EVENT_WRITE_BATCH_SIZE", 100)?,
+                    flush_interval: Duration::from_millis(env_parse("EVENT_WRITE_FLUSH_MS", 50)?),
+                };
+                if config.queue_size == 0 || config.batch_size == 0 || config.flush_interval.is_zero() {
+                    return Err(ConfigError(
+                        "EVENT_WRITE_QUEUE_SIZE, EVENT_WRITE_BATCH_SIZE, and EVENT_WRITE_FLUSH_MS must be at least 1".to_string(),
+                    ));
+                }
+                Some(config)
+            }
+            false => None,
+        };
+
+        let grpc = match env_var("GRPC_PORT") {
+            Some(port) => Some(GrpcConfig {
+                port: port
+                    .trim()
+                    .parse()
+                    .map_err(|_| ConfigError(format!("GRPC_PORT has an invalid value `{}`", port)))?,
+                max_concurrent_requests: env_parse("GRPC_MAX_CONCURRENT_REQUESTS", 256)?,
+            }),
+            None => None,
+        };
+
+        let ip_privacy = match env_var("IP_PRIVACY_MODE").as_deref() {
+            None | Some("off") => IpPrivacy::Off,
+            Some("hash") => IpPrivacy::Hash {
+                salt: env_var("IP_PRIVACY_SALT")
+                    .ok_or_else(|| ConfigError("IP_PRIVACY_MODE=hash requires IP_PRIVACY_SALT".to_string()))?,
+            },
+            Some("truncate") => IpPrivacy::Truncate,
+            Some(other) => {
+                return Err(ConfigError(format!(
+                    "IP_PRIVACY_MODE must be `off`, `hash`, or `truncate`, got `{}`",
+                    other
+                )))
+            }
+        };
+
+        let log_ip = match env_var("LOG_IP_MODE").as_deref() {
+            Some("full") => LogIpMode::Full,
+            None | Some("mask") => LogIpMode::Mask,
+            Some("omit") => LogIpMode::Omit,
+            Some(other) => {
+                return Err(ConfigError(format!(
+                    "LOG_IP_MODE must be `full`, `mask`, or `omit`, got `{}`",
+                    other
+                )))
+            }
+        };
+
+        let log_sample_rate = env_parse("LOG_SAMPLE_RATE", 1)?;
+        if log_sample_rate == 0 {
+            return Err(ConfigError("LOG_SAMPLE_RATE must be at least 1".to_string()));
+        }
+
+        let model_source = match (env_var("MODEL_PATH"), env_var("MODEL_GRPC_ADDR")) {
+            (Some(_), Some(_)) => {
+                return Err(ConfigError("MODEL_PATH and MODEL_GRPC_ADDR are mutually exclusive".to_string()))
+            }
+            (Some(path), None) => Some(ModelSource::Onnx(PathBuf::from(path))),
+            (None, Some(addr)) => Some(ModelSource::Grpc(addr)),
+            (None, None) => None,
+        };
+        let model = match model_source {
+            Some(source) => Some(ModelConfig {
+                source,
+                timeout: Duration::from_millis(env_parse("MODEL_TIMEOUT_MS", 20)?),
+                breaker_failures: env_parse("MODEL_BREAKER_FAILURES", 5)?,
+                breaker_cooldown: Duration::from_secs(env_parse("MODEL_BREAKER_COOLDOWN_SECS", 30)?),
+            }),
+            None => None,
+        };
+
+        let freshness_window = match env_parse::<u32>("EVENT_FRESHNESS_WINDOW_SECS", 600)? {
+            0 => None,
+            secs => Some(chrono::Duration::seconds(i64::from(secs))),
+        };
+
+        let max_events_per_session = env_parse("MAX_EVENTS_PER_SESSION", 500)?;
+        if max_events_per_session == 0 {
+            return Err(ConfigError("MAX_EVENTS_PER_SESSION must be at least 1".to_string()));
+        }
+
+        let max_in_flight_analyses = env_parse("MAX_IN_FLIGHT_ANALYSES", 1024)?;
+        if max_in_flight_analyses == 0 {
+            return Err(ConfigError("MAX_IN_FLIGHT_ANALYSES must be at least 1".to_string()));
+        }
+        let max_in_flight_requests = match env_parse::<usize>("MAX_IN_FLIGHT_REQUESTS", 0)? {
+            0 => None,
+            limit => Some(limit),
+        };
+        let stream_max_in_flight = env_parse("STREAM_MAX_IN_FLIGHT", 8)?;
+        if stream_max_in_flight == 0 {
+            return Err(ConfigError("STREAM_MAX_IN_FLIGHT must be at least 1".to_string()));
+        }
+
+        let store_capacity = match env_parse::<usize>("MAX_STORED_EVENTS", 0)? {
+            0 => None,
+            max_events => Some(StoreCapacity {
+                max_events,
+                policy: match env_var("STORE_FULL_POLICY").as_deref() {
+                    None | Some("shed_oldest") => StoreFullPolicy::ShedOldest,
+                    Some("reject_new") => StoreFullPolicy::RejectNew,
+                    Some(other) => {
+                        return Err(ConfigError(format!(
+                            "STORE_FULL_POLICY must be `shed_oldest` or `reject_new`, got `{}`",
+                            other
+                        )))
+                    }
+                },
+            }),
+        };
+
+        let max_body_bytes = env_parse("MAX_BODY_BYTES", 64 * 1024)?;
+        if max_body_bytes == 0 {
+            return Err(ConfigError("MAX_BODY_BYTES must be at least 1".to_string()));
+        }
+
+        let request_timeout = match env_parse::<u64>("REQUEST_TIMEOUT_MS", 2_000)? {
+            0 => None,
+            millis => Some(Duration::from_millis(millis)),
+        };
+        let session_cache_ttl = match env_parse::<u64>("SESSION_CACHE_TTL_SECS", 300)? {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        };
+        let response_cache = match env_parse::<usize>("RESPONSE_CACHE_SIZE", 256)? {
+            0 => None,
+            size => Some(ResponseCacheConfig {
+                size,
+                ttl: Duration::from_secs(env_parse("RESPONSE_CACHE_TTL_SECS", 60)?),
+            }),
+        };
+
+        let metadata_limits = MetadataLimits {
+            max_keys: env_parse("MAX_METADATA_KEYS", 64)?,
+            max_value_bytes: env_parse("MAX_METADATA_VALUE_BYTES", 2048)?,
+            mode: match env_var("METADATA_LIMIT_MODE").as_deref() {
+                None | Some("reject") => MetadataLimitMode::Reject,
+                Some("truncate") => MetadataLimitMode::Truncate,
+                Some(other) => {
+                    return Err(ConfigError(format!(
+                        "METADATA_LIMIT_MODE must be `reject` or `truncate`, got `{}`",
+                        other
+                    )))
+                }
+            },
+        };
+
+        let metadata_retention = match env_var("RETAINED_METADATA_KEYS") {
+            Some(keys) if keys.trim() == "*" => MetadataRetention::All,
+            Some(keys) => MetadataRetention::Keys(
+                keys.split(',')
+                    .map(str::trim)
+                    .filter(|key| !key.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            ),
+            None => MetadataRetention::Keys(DEFAULT_RETAINED_METADATA_KEYS.iter().map(|key| key.to_string()).collect()),
+        };
+
+        let storage_sampling = match env_var("STORAGE_SAMPLE_RATES") {
+            Some(rates) => Some(StorageSamplingConfig {
+                rates: parse_sample_rates(&rates)?,
+                max_score: env_parse("STORAGE_SAMPLING_MAX_SCORE", 10)?,
+            }),
+            None => None,
+        };
+
+        let random_seed = match env_var("RANDOM_SEED") {
+            Some(seed) => Some(
+                seed.trim()
+                    .parse()
+                    .map_err(|_| ConfigError(format!("RANDOM_SEED has an invalid value `{}`", seed)))?,
+            ),
+            None => None,
+        };
+
+        let client_ip_source = match env_var("CLIENT_IP_SOURCE").as_deref() {
+            None | Some("payload") => None,
+            Some("connection") => Some(ClientIpSource::Connection),
+            Some("forwarded") => Some(ClientIpSource::Forwarded),
+            Some(other) => {
+                return Err(ConfigError(format!(
+                    "CLIENT_IP_SOURCE must be `payload`, `connection`, or `forwarded`, got `{}`",
+                    other
+                )))
+            }
+        };
+        let client_ip = match client_ip_source {
+            None => None,
+            Some(source) => {
+                let trusted_proxies = env_var("TRUSTED_PROXIES")
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|proxy| !proxy.is_empty())
+                    .map(|proxy| {
+                        allowlist::parse_network(proxy)
+                            .ok_or_else(|| ConfigError(format!("TRUSTED_PROXIES has an invalid entry `{}`", proxy)))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                if source == ClientIpSource::Forwarded && trusted_proxies.is_empty() {
+                    return Err(ConfigError(
+                        "CLIENT_IP_SOURCE=forwarded requires TRUSTED_PROXIES".to_string(),
+                    ));
+                }
+                let policy = match env_var("CLIENT_IP_POLICY").as_deref() {
+                    None | Some("override") => ClientIpPolicy::Override,
+                    Some("cross_check") => ClientIpPolicy::CrossCheck,
+                    Some(other) => {
+                        return Err(ConfigError(format!(
+                            "CLIENT_IP_POLICY must be `override` or `cross_check`, got `{}`",
+                            other
+                        )))
+                    }
+                };
+                Some(ClientIpConfig {
+                    source,
+                    trusted_proxies,
+                    policy,
+                })
+            }
+        };
+
+        Ok(Self {
+            blacklist_path: env_var("BLACKLIST_PATH").map(PathBuf::from),
+            blacklist_bans_path: env_var("BLACKLIST_BANS_PATH").map(PathBuf::from),
+            blacklist_sync,
+            source_lists,
+            ip_list_false_positive_rate,
+            ip_reputation,
+            model,
+            redis_url: env_var("REDIS_URL"),
+            redis_session_ttl: Duration::from_secs(env_parse("REDIS_SESSION_TTL_SECS", 86_400)?),
+            activity_profile_ttl: Duration::from_secs(env_parse("ACTIVITY_PROFILE_TTL_SECS", 365 * 86_400)?),
+            write_behind,
+            grpc,
+            freshness_window,
+            max_events_per_session,
+            max_in_flight_analyses,
+            max_in_flight_requests,
+            stream_max_in_flight,
+            store_capacity,
+            max_body_bytes,
+            request_timeout,
+            session_cache_ttl,
+            response_cache,
+            metadata_limits,
+            metadata_retention,
+            storage_sampling,
+            allowed_origins,
+            response_verbosity,
+            geoip_city_db_path: env_var("GEOIP_CITY_DB_PATH").map(PathBuf::from),
+            geoip_asn_db_path: env_var("GEOIP_ASN_DB_PATH").map(PathBuf::from),
+            disposable_domains_path: env_var("DISPOSABLE_DOMAINS_PATH").map(PathBuf::from),
+            event_schema_path: env_var("EVENT_SCHEMA_PATH").map(PathBuf::from),
+            audit,
+            webhook,
+            usage,
+            degradation,
+            ip_privacy,
+            log_ip,
+            log_sample_rate,
+            random_seed,
+            client_ip,
+        })
+    }
+}
+
+/// `<PREFIX>_SYNC_URL`, `<PREFIX>_SYNC_INTERVAL_SECS`, and `<PREFIX>_SYNC_MODE`, shared
+/// by the blacklist and the suspicious-source lists.
+fn list_sync_config(prefix: &str) -> Result<Option<BlacklistSyncConfig>, ConfigError> {
+    let Some(url) = env_var(&format!("{}_SYNC_URL", prefix)) else {
+        return Ok(None);
+    };
+    let mode_key = format!("{}_SYNC_MODE", prefix);
+    Ok(Some(BlacklistSyncConfig {
+        url,
+        interval: Duration::from_secs(env_parse(&format!("{}_SYNC_INTERVAL_SECS", prefix), 900)?),
+        replace: match env_var(&mode_key).as_deref() {
+            None | Some("merge") => false,
+            Some("replace") => true,
+            Some(other) => {
+                return Err(ConfigError(format!(
+                    "{} must be `merge` or `replace`, got `{}`",
+                    mode_key, other
+                )))
+            }
+        },
+    }))
+}
+
+fn env_var(key: &str) -> Option<String> {
+    std::env::var(key).ok().filter(|value| !value.trim().is_empty())
+}
+
+fn env_parse<T: std::str::FromStr>(key: &str, default: T) -> Result<T, ConfigError> {
+    match env_var(key) {
+        Some(value) => value
+            .trim()
+            .parse()
+            .map_err(|_| ConfigError(format!("{} has an invalid value `{}`", key, value))),
+        None => Ok(default),
+    }
+}
+
+// --- SCORING CONFIGURATION ---
+/// Tunable scoring settings, loaded from the JSON file named by `SCORING_CONFIG_PATH`.
+/// Every field has a default so a config file only needs the values it overrides.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ScoringConfig {
+    /// Short hash of the loaded file, or `default`, recorded with each audited decision.
+    #[serde(skip)]
+    pub version: String,
+    /// Events whose normalized 0-100 score reaches this are flagged.
+    pub flag_threshold: i32,
+    /// Events a session needs, the current one included, before rules that judge its
+    /// history (frequency, shared sessions, cadence, click timing, type toggling) are consulted.
+    pub min_events_for_stateful: usize,
+    /// Prefix length IPv6 addresses are grouped by in per-IP counts (distinct IPs in a
+    /// session, top IPs in the stats); 0 counts each address on its own.
+    pub ipv6_counter_prefix: u8,
+    pub confidence: ConfidenceConfig,
+    /// Per-event-type overrides of the flag thresholds and rule weights. Event types
+    /// without an entry use the global values.
+    pub event_profiles: HashMap<EventType, EventProfile>,
+    pub decision_policy: DecisionPolicyConfig,
+    pub alerts: AlertsConfig,
+    pub blacklist: BlacklistRuleConfig,
+    pub suspicious_sources: SuspiciousSourcesConfig,
+    pub fast_interaction: FastInteractionConfig,
+    pub high_frequency: HighFrequencyConfig,
+    pub metadata_keys: MetadataKeysConfig,
+    pub ip_reputation: IpReputationRuleConfig,
+    pub asn_reputation: AsnReputationConfig,
+    pub ip_mismatch: IpMismatchConfig,
+    pub model: ModelScoreConfig,
+    pub user_agent: UserAgentConfig,
+    pub shared_session: SharedSessionConfig,
+    pub repeat_offender: RepeatOffenderConfig,
+    pub concurrent_sessions: ConcurrentSessionsConfig,
+    pub device_fingerprint: DeviceFingerprintConfig,
+    pub session_reputation: SessionReputationConfig,
+    pub entity_reputation: EntityReputationConfig,
+    pub cadence: CadenceConfig,
+    pub click_timing: ClickTimingConfig,
+    pub type_toggling: TypeTogglingConfig,
+    pub payments: PaymentsConfig,
+    pub disposable_email: DisposableEmailConfig,
+    pub allowlist: AllowlistConfig,
+    pub referrer_origin: ReferrerOriginConfig,
+    pub locale_mismatch: LocaleMismatchConfig,
+    pub field_timing: FieldTimingConfig,
+    pub session_duration: SessionDurationConfig,
+    pub activity_profile: ActivityProfileConfig,
+    pub scripted_rules: Vec<ScriptedRuleConfig>,
+    pub composite_rules: Vec<CompositeRuleConfig>,
+    /// Rules enforced for only part of the traffic, by rule name. Sessions outside a
+    /// rule's rollout evaluate it in shadow: counted in the rule report, never scored.
+    pub rollouts: HashMap<String, RolloutConfig>,
+    pub script_limits: ScriptLimitsConfig,
+}
+
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        Self {
+            version: "default".to_string(),
+            flag_threshold: 50,
+            min_events_for_stateful: 3,
+            ipv6_counter_prefix: 0,
+            confidence: ConfidenceConfig::default(),
+            event_profiles: HashMap::new(),
+            decision_policy: DecisionPolicyConfig::default(),
+            alerts: AlertsConfig::default(),
+            blacklist: BlacklistRuleConfig::default(),
+            suspicious_sources: SuspiciousSourcesConfig::default(),
+            fast_interaction: FastInteractionConfig::default(),
+            high_frequency: HighFrequencyConfig::default(),
+            metadata_keys: MetadataKeysConfig::default(),
+            ip_reputation: IpReputationRuleConfig::default(),
+            asn_reputation: AsnReputationConfig::default(),
+            ip_mismatch: IpMismatchConfig::default(),
+            model: ModelScoreConfig::default(),
+            user_agent: UserAgentConfig::default(),
+            shared_session: SharedSessionConfig::default(),
+            repeat_offender: RepeatOffenderConfig::default(),
+            concurrent_sessions: ConcurrentSessionsConfig::default(),
+            device_fingerprint: DeviceFingerprintConfig::default(),
+            session_reputation: SessionReputationConfig::default(),
+            entity_reputation: EntityReputationConfig::default(),
+            cadence: CadenceConfig::default(),
+            click_timing: ClickTimingConfig::default(),
+            type_toggling: TypeTogglingConfig::default(),
+            payments: PaymentsConfig::default(),
+            disposable_email: DisposableEmailConfig::default(),
+            allowlist: AllowlistConfig::default(),
+            referrer_origin: ReferrerOriginConfig::default(),
+            locale_mismatch: LocaleMismatchConfig::default(),
+            field_timing: FieldTimingConfig::default(),
+            session_duration: SessionDurationConfig::default(),
+            activity_profile: ActivityProfileConfig::default(),
+            scripted_rules: Vec::new(),
+            composite_rules: Vec::new(),
+            rollouts: HashMap::new(),
+            script_limits: ScriptLimitsConfig::default(),
+        }
+    }
+}
+
+/// Scoring overrides for one event type, so e.g. form submissions can be held to a
+/// stricter threshold than page loads. Fields left unset fall back to the global config.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct EventProfile {
+    /// Replaces `flag_threshold`.
+    pub flag_threshold: Option<i32>,
+    /// Points per reason code, replacing what the rules would add for it; `0` drops the
+    /// hit. Codes without an entry keep their points.
+    pub weights: HashMap<String, i32>,
+}
+
+/// How the raw additive score is mapped onto the 0-100 `fraudScore` that flagging,
+/// decisions, and everything downstream of a result compare.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ConfidenceConfig {
+    pub mode: ConfidenceMode,
+    /// Raw score that maps to a confidence of 50 (`logistic` mode).
+    pub midpoint: f64,
+    /// How sharply confidence rises around the midpoint (`logistic` mode).
+    pub steepness: f64,
+}
+
+impl Default for ConfidenceConfig {
+    fn default() -> Self {
+        Self {
+            mode: ConfidenceMode::Raw,
+            midpoint: 50.0,
+            steepness: 0.08,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfidenceMode {
+    /// The raw score clamped to 0-100.
+    Raw,
+    /// `100 / (1 + e^(-steepness * (score - midpoint)))`.
+    Logistic,
+}
+
+impl ConfidenceConfig {
+    pub fn confidence(&self, score: i32) -> u8 {
+        let confidence = match self.mode {
+            ConfidenceMode::Raw => f64::from(score),
+            ConfidenceMode::Logistic => 100.0 / (1.0 + (-self.steepness * (f64::from(score) - self.midpoint)).exp()),
+        };
+        confidence.round().clamp(0.0, 100.0) as u8
+    }
+}
+
+/// What a caller should do with an event, from least to most severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Decision {
+    Allow,
+    /// Show a CAPTCHA or step-up check before going on.
+    Challenge,
+    /// Let it through, but queue it for an analyst.
+    Review,
+    Deny,
+}
+
+impl Decision {
+    pub const ALL: [Decision; 4] = [Decision::Allow, Decision::Challenge, Decision::Review, Decision::Deny];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Decision::Allow => "ALLOW",
+            Decision::Challenge => "CHALLENGE",
+            Decision::Review => "REVIEW",
+            Decision::Deny => "DENY",
+        }
+    }
+}
+
+/// Maps each result to a `Decision`, so callers don't each re-implement thresholds.
+/// Without bands, flagged results are `REVIEW` and the rest `ALLOW`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DecisionPolicyConfig {
+    /// In ascending `min_score` order. A score takes the last band it reaches; scores
+    /// below the first band are `ALLOW`.
+    pub bands: Vec<DecisionBand>,
+    /// Floors applied after the bands, whatever the score.
+    pub overrides: Vec<DecisionOverride>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DecisionBand {
+    pub min_score: i32,
+    pub decision: Decision,
+}
+
+/// Raises the decision to at least `at_least` when a hit with `code` is present.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DecisionOverride {
+    pub code: String,
+    pub at_least: Decision,
+}
+
+impl DecisionPolicyConfig {
+    pub fn decide(&self, score: i32, flagged: bool, codes: impl IntoIterator<Item = impl AsRef<str>>) -> Decision {
+        let banded = if self.bands.is_empty() {
+            if flagged {
+                Decision::Review
+            } else {
+                Decision::Allow
+            }
+        } else {
+            self.bands
+                .iter()
+                .take_while(|band| score >= band.min_score)
+                .last()
+                .map_or(Decision::Allow, |band| band.decision)
+        };
+        codes.into_iter().fold(banded, |decision, code| {
+            self.overrides
+                .iter()
+                .filter(|floor| floor.code == code.as_ref())
+                .fold(decision, |decision, floor| decision.max(floor.at_least))
+        })
+    }
+}
+
+/// Pages humans about the worst results directly. Off until a sink is configured.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AlertsConfig {
+    /// Results scoring at least this alert.
+    pub min_score: i32,
+    /// Results with a hit for any of these reason codes alert whatever their score.
+    pub codes: Vec<String>,
+    /// Alerts each sink sends per minute at most; the rest are dropped and counted.
+    pub max_per_minute: u32,
+    /// How long a session that alerted on a sink stays quiet on it.
+    pub session_cooldown_secs: u64,
+    /// Link added to each alert, with `{session_id}` replaced, e.g. to an internal
+    /// case tool.
+    pub link_template: Option<String>,
+    pub slack: Option<SlackAlertConfig>,
+    pub email: Option<EmailAlertConfig>,
+}
+
+impl AlertsConfig {
+    pub fn session_cooldown(&self) -> Duration {
+        Duration::from_secs(self.session_cooldown_secs)
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.slack.is_some() || self.email.is_some()
+    }
+
+    /// Whether a result with this score and these hits should alert.
+    pub fn matches(&self, score: i32, codes: impl IntoIterator<Item = impl AsRef<str>>) -> bool {
+        score >= self.min_score || codes.into_iter().any(|code| self.codes.iter().any(|alerting| alerting == code.as_ref()))
+    }
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        Self {
+            min_score: 90,
+            codes: Vec::new(),
+            max_per_minute: 10,
+            session_cooldown_secs: 3600,
+            link_template: None,
+            slack: None,
+            email: None,
+        }
+    }
+}
+
+/// Posts alerts to a Slack incoming webhook.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SlackAlertConfig {
+    pub webhook_url: String,
+}
+
+/// Mails alerts through an SMTP relay, over STARTTLS unless `tls` is `none`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmailAlertConfig {
+    pub smtp_host: String,
+    #[serde(default = "default_smtp_port")]
+    pub smtp_port: u16,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    /// Environment variable holding the SMTP password, so it stays out of the file.
+    pub password_env: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpTls {
+    #[default]
+    Starttls,
+    /// TLS from the first byte, usually on port 465.
+    Implicit,
+    /// Plain text, for a relay on localhost.
+    None,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BlacklistRuleConfig {
+    pub points: i32,
+}
+
+impl Default for BlacklistRuleConfig {
+    fn default() -> Self {
+        Self { points: 100 }
+    }
+}
+
+/// Points per suspicious-source list, kept well below the blacklist's: these sources
+/// deserve scrutiny, not an outright block.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SuspiciousSourcesConfig {
+    pub tor_exit: SourceListRuleConfig,
+    pub open_proxy: SourceListRuleConfig,
+}
+
+impl SuspiciousSourcesConfig {
+    pub fn points(&self, category: SourceCategory) -> i32 {
+        match category {
+            SourceCategory::TorExit => self.tor_exit.points,
+            SourceCategory::OpenProxy => self.open_proxy.points,
+        }
+    }
+}
+
+impl Default for SuspiciousSourcesConfig {
+    fn default() -> Self {
+        Self {
+            tor_exit: SourceListRuleConfig { points: 30 },
+            open_proxy: SourceListRuleConfig { points: 25 },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SourceListRuleConfig {
+    pub points: i32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FastInteractionConfig {
+    pub points: i32,
+    /// Gaps between consecutive session events shorter than this are scored.
+    pub min_interval_ms: i64,
+}
+
+impl Default for FastInteractionConfig {
+    fn default() -> Self {
+        Self {
+            points: 75,
+            min_interval_ms: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HighFrequencyConfig {
+    pub points: i32,
+    /// Sessions with more counted events than this are scored.
+    pub max_events: usize,
+    /// Only events less than this many seconds older than the scored one are counted; an
+    /// event exactly `window_secs` older is outside. 0 counts the whole stored session.
+    pub window_secs: u64,
+    /// Whether the event being scored counts toward the limits.
+    pub count_current_event: bool,
+    /// Event types counted on their own against these limits instead of toward
+    /// `max_events`, so a type that comes in bursts (prefetched page loads, say) can be
+    /// given more room.
+    pub max_events_by_type: HashMap<EventType, usize>,
+    /// Limits on events per IP and per user across all their sessions, within
+    /// `window_secs`. Need a window.
+    pub max_events_per_ip: Option<usize>,
+    pub max_events_per_user: Option<usize>,
+}
+
+impl Default for HighFrequencyConfig {
+    fn default() -> Self {
+        Self {
+            points: 50,
+            max_events: 10,
+            window_secs: 0,
+            count_current_event: true,
+            max_events_by_type: HashMap::new(),
+            max_events_per_ip: None,
+            max_events_per_user: None,
+        }
+    }
+}
+
+impl HighFrequencyConfig {
+    /// Stored events a session needs before the largest limit can be exceeded.
+    fn history_needed(&self) -> usize {
+        let largest = self.max_events_by_type.values().copied().fold(self.max_events, usize::max);
+        largest + 1 + usize::from(!self.count_current_event)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct IpReputationRuleConfig {
+    /// Points for a provider confidence of 100; lower confidences scale linearly.
+    pub max_points: i32,
+    /// Confidences below this are ignored.
+    pub min_confidence: u8,
+}
+
+impl Default for IpReputationRuleConfig {
+    fn default() -> Self {
+        Self {
+            max_points: 60,
+            min_confidence: 25,
+        }
+    }
+}
+
+/// Large cloud and VPS providers, the default `datacenter_asns`. Real users rarely browse
+/// from them; scripted traffic often does.
+const BUNDLED_DATACENTER_ASNS: &[u32] = &[
+    16509,  // Amazon (AWS)
+    14618,  // Amazon (AWS)
+    8075,   // Microsoft (Azure)
+    396982, // Google Cloud
+    31898,  // Oracle Cloud
+    45102,  // Alibaba Cloud
+    132203, // Tencent Cloud
+    14061,  // DigitalOcean
+    16276,  // OVH
+    24940,  // Hetzner
+    63949,  // Linode (Akamai)
+    20473,  // Vultr (Choopa)
+    51167,  // Contabo
+    12876,  // Scaleway
+    197540, // netcup
+    8560,   // IONOS
+    36352,  // ColoCrossing
+    9009,   // M247
+    53667,  // FranTech (BuyVM)
+];
+
+/// Networks known for abuse, such as datacenters and bulletproof hosts, by ASN.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AsnReputationConfig {
+    /// Points when the event IP's ASN is one of `bad_asns`.
+    pub points: i32,
+    pub bad_asns: HashSet<u32>,
+    /// Points when the database reports a hosting network. Plain ASN databases don't
+    /// say, so this needs an ISP or Enterprise database.
+    pub hosting_points: i32,
+    /// Points when the ASN is one of `datacenter_asns`. Works with plain ASN databases,
+    /// unlike `hosting_points`.
+    pub datacenter_points: i32,
+    /// The bundled cloud and VPS providers unless set; setting it replaces them.
+    pub datacenter_asns: HashSet<u32>,
+}
+
+impl Default for AsnReputationConfig {
+    fn default() -> Self {
+        Self {
+            points: 60,
+            bad_asns: HashSet::new(),
+            hosting_points: 0,
+            datacenter_points: 0,
+            datacenter_asns: BUNDLED_DATACENTER_ASNS.iter().copied().collect(),
+        }
+    }
+}
+
+/// Payload IPs that disagree with the one derived from the connection, under
+/// `CLIENT_IP_POLICY=cross_check`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct IpMismatchConfig {
+    pub points: i32,
+}
+
+impl Default for IpMismatchConfig {
+    fn default() -> Self {
+        Self { points: 40 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ModelScoreConfig {
+    /// Points for a model probability of 1; lower probabilities scale linearly.
+    pub weight: i32,
+}
+
+impl Default for ModelScoreConfig {
+    fn default() -> Self {
+        Self { weight: 40 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct UserAgentConfig {
+    /// Points when the user agent matches a known automation signature or is blank.
+    pub signature_points: i32,
+    /// Case-insensitive substrings identifying headless browsers and HTTP libraries.
+    pub signatures: Vec<String>,
+    /// Points when the user agent differs from the one previously seen in the session.
+    pub change_points: i32,
+}
+
+impl Default for UserAgentConfig {
+    fn default() -> Self {
+        Self {
+            signature_points: 40,
+            signatures: ["HeadlessChrome", "PhantomJS", "python-requests", "curl"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            change_points: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RepeatOffenderConfig {
+    /// Bonus for a user with one prior flag.
+    pub points: i32,
+    /// Each further prior flag multiplies the bonus by this factor.
+    pub growth_factor: f64,
+    /// Prior flags beyond this no longer increase the bonus.
+    pub max_flags: u32,
+    /// A user's flag count resets this long after their most recent flag.
+    pub ttl_secs: u64,
+}
+
+impl Default for RepeatOffenderConfig {
+    fn default() -> Self {
+        Self {
+            points: 10,
+            growth_factor: 2.0,
+            max_flags: 4,
+            ttl_secs: 86_400,
+        }
+    }
+}
+
+/// One user active in many sessions at once, as when activity is spread over several
+/// tabs to stay under per-session thresholds.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ConcurrentSessionsConfig {
+    pub points: i32,
+    /// Sessions a user may have active at once, counting the event's own.
+    pub max_sessions: usize,
+    /// A session is active while its last event is at most this old.
+    pub window_secs: u64,
+}
+
+impl ConcurrentSessionsConfig {
+    pub fn window(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.window_secs as i64)
+    }
+}
+
+impl Default for ConcurrentSessionsConfig {
+    fn default() -> Self {
+        Self {
+            points: 40,
+            max_sessions: 3,
+            window_secs: 30 * 60,
+        }
+    }
+}
+
+/// Many sessions from one device, by the client-computed `metadata["fingerprint"]`, as
+/// from a fraud farm cycling sessions on the same hardware.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DeviceFingerprintConfig {
+    pub points: i32,
+    /// Sessions one fingerprint may have active at once, counting the event's own.
+    pub max_sessions: usize,
+    /// A session is active while its last event is at most this old.
+    pub window_secs: u64,
+}
+
+impl DeviceFingerprintConfig {
+    pub fn window(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.window_secs as i64)
+    }
+}
+
+impl Default for DeviceFingerprintConfig {
+    fn default() -> Self {
+        Self {
+            points: 50,
+            max_sessions: 3,
+            window_secs: 60 * 60,
+        }
+    }
+}
+
+/// Accumulates each session's event scores so many small signals can flag a session
+/// that no single event would.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SessionReputationConfig {
+    /// Time for a session's reputation to halve. `0` disables session reputation.
+    pub half_life_secs: u64,
+    /// Events are flagged once their session's reputation, including them, reaches this.
+    pub flag_threshold: i32,
+}
+
+impl SessionReputationConfig {
+    pub fn half_life(&self) -> Option<Duration> {
+        (self.half_life_secs > 0).then(|| Duration::from_secs(self.half_life_secs))
+    }
+}
+
+impl Default for SessionReputationConfig {
+    fn default() -> Self {
+        Self {
+            half_life_secs: 900,
+            flag_threshold: 150,
+        }
+    }
+}
+
+/// Reputations kept per user and per IP across sessions, growing with each flagged
+/// event and decaying between, so repeat offenders start their next session behind.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct EntityReputationConfig {
+    /// Time for a reputation to halve. `0` disables entity reputation.
+    pub half_life_secs: u64,
+    /// Added to the user's and the IP's reputation for each flagged event.
+    pub flag_points: f64,
+    /// Points per unit of reputation (the user's plus the IP's) added to new events.
+    pub weight: f64,
+    /// The most points reputation adds to one event.
+    pub max_points: i32,
+}
+
+impl EntityReputationConfig {
+    pub fn half_life(&self) -> Option<Duration> {
+        (self.half_life_secs > 0).then(|| Duration::from_secs(self.half_life_secs))
+    }
+}
+
+impl Default for EntityReputationConfig {
+    fn default() -> Self {
+        Self {
+            half_life_secs: 604_800,
+            flag_points: 10.0,
+            weight: 1.0,
+            max_points: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CadenceConfig {
+    pub points: i32,
+    /// Sessions with fewer events are not evaluated.
+    pub min_events: usize,
+    /// Only the most recent `window` events are considered.
+    pub window: usize,
+    /// Intervals whose coefficient of variation is below this look machine-generated.
+    pub max_coefficient_of_variation: f64,
+    /// Sessions whose intervals are all shorter than this are scored regardless of variation.
+    pub burst_interval_ms: i64,
+}
+
+impl Default for CadenceConfig {
+    fn default() -> Self {
+        Self {
+            points: 40,
+            min_events: 8,
+            window: 20,
+            max_coefficient_of_variation: 0.1,
+            burst_interval_ms: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ClickTimingConfig {
+    pub points: i32,
+    /// Sessions with fewer clicks are not evaluated.
+    pub min_clicks: usize,
+    /// Only the most recent `window` clicks are considered.
+    pub window: usize,
+    /// Inter-click intervals whose standard deviation is below this look scripted.
+    pub max_std_dev_ms: f64,
+}
+
+impl Default for ClickTimingConfig {
+    fn default() -> Self {
+        Self {
+            points: 35,
+            min_clicks: 6,
+            window: 15,
+            max_std_dev_ms: 25.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TypeTogglingConfig {
+    pub points: i32,
+    /// Consecutive event type changes, ending with the current event, needed to score.
+    pub min_switches: usize,
+    /// Only changes arriving within this long of the previous event count.
+    pub max_interval_ms: i64,
+}
+
+impl Default for TypeTogglingConfig {
+    fn default() -> Self {
+        Self {
+            points: 30,
+            min_switches: 4,
+            max_interval_ms: 150,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PaymentsConfig {
+    /// Points added when a user's payments in one currency exceed `max_window_amount`.
+    pub velocity_points: i32,
+    /// Cumulative amount per user and currency allowed within `window_secs`.
+    pub max_window_amount: f64,
+    /// Rolling window for the per-user amount total.
+    pub window_secs: u64,
+    /// Points added when a session repeats the same amount (card testing).
+    pub repeat_points: i32,
+    /// Payments of an identical amount, including the current one, that trigger the rule.
+    pub repeat_count: usize,
+    /// Only payments this close to the current one count as repeats.
+    pub repeat_window_secs: i64,
+    /// Points added when the currency isn't the one expected for the GeoIP country.
+    pub currency_mismatch_points: i32,
+    /// Expected currency per ISO country code, overriding or extending the built-in table.
+    pub expected_currencies: HashMap<String, String>,
+}
+
+impl Default for PaymentsConfig {
+    fn default() -> Self {
+        Self {
+            velocity_points: 50,
+            max_window_amount: 5_000.0,
+            window_secs: 86_400,
+            repeat_points: 40,
+            repeat_count: 3,
+            repeat_window_secs: 600,
+            currency_mismatch_points: 30,
+            expected_currencies: HashMap::new(),
+        }
+    }
+}
+
+/// Trusted traffic loaded at startup; the admin endpoint can change it at runtime.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AllowlistConfig {
+    /// IP addresses or CIDR ranges.
+    #[serde(deserialize_with = "deserialize_networks")]
+    pub ips: Vec<IpNet>,
+    pub user_ids: Vec<String>,
+}
+
+fn deserialize_networks<'de, D>(deserializer: D) -> Result<Vec<IpNet>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|entry| {
+            allowlist::parse_network(entry)
+                .ok_or_else(|| serde::de::Error::custom(format!("invalid allowlist IP or CIDR `{}`", entry)))
+        })
+        .collect()
+}
+
+/// One session id used by several users suggests a hijacked or shared session token.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SharedSessionConfig {
+    /// Points added when a session's history holds more than `max_users` distinct user ids.
+    pub points: i32,
+    /// Anonymous events don't count towards this.
+    pub max_users: usize,
+}
+
+impl Default for SharedSessionConfig {
+    fn default() -> Self {
+        Self {
+            points: 50,
+            max_users: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ReferrerOriginConfig {
+    /// Points added when a form's `referrer` and `origin` metadata name different hosts.
+    pub points: i32,
+}
+
+impl Default for ReferrerOriginConfig {
+    fn default() -> Self {
+        Self { points: 35 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LocaleMismatchConfig {
+    /// Points added when the country of `metadata.locale` isn't the GeoIP country.
+    pub points: i32,
+    /// IP countries not scored per locale country, for mismatches that are common and
+    /// harmless (travelers, VPN exits, neighbouring markets). ISO country codes.
+    pub allowed_mismatches: HashMap<String, HashSet<String>>,
+}
+
+impl Default for LocaleMismatchConfig {
+    fn default() -> Self {
+        Self {
+            points: 20,
+            allowed_mismatches: HashMap::new(),
+        }
+    }
+}
+
+impl LocaleMismatchConfig {
+    pub fn allows(&self, locale_country: &str, ip_country: &str) -> bool {
+        self.allowed_mismatches.iter().any(|(locale, countries)| {
+            locale.eq_ignore_ascii_case(locale_country) && countries.iter().any(|country| country.eq_ignore_ascii_case(ip_country))
+        })
+    }
+}
+
+/// Checks `metadata.fieldTimings` on form submissions: a JSON object mapping each field
+/// to when it was first filled, in milliseconds since the form was shown.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FieldTimingConfig {
+    /// Points added when every field was filled within `min_fill_ms` of the first.
+    pub fast_fill_points: i32,
+    pub min_fill_ms: f64,
+    /// Forms with fewer timed fields are not evaluated.
+    pub min_fields: usize,
+    /// Points added when the fields in `field_order` were filled in a different order.
+    pub out_of_order_points: i32,
+    /// The form's fields in visual order. The order check is skipped when empty.
+    pub field_order: Vec<String>,
+}
+
+impl Default for FieldTimingConfig {
+    fn default() -> Self {
+        Self {
+            fast_fill_points: 40,
+            min_fill_ms: 1_000.0,
+            min_fields: 3,
+            out_of_order_points: 25,
+            field_order: Vec::new(),
+        }
+    }
+}
+
+/// How long a session ran before a form was submitted, from its first stored event.
+/// Bots tend to submit within a second of landing, or after sitting idle on a page left
+/// open for hours.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SessionDurationConfig {
+    /// Points when the submission came less than `min_duration_ms` after the first event.
+    pub short_points: i32,
+    pub min_duration_ms: u64,
+    /// Points when it came more than `max_duration_secs` after.
+    pub long_points: i32,
+    pub max_duration_secs: u64,
+}
+
+impl Default for SessionDurationConfig {
+    fn default() -> Self {
+        Self {
+            short_points: 35,
+            min_duration_ms: 1_000,
+            long_points: 15,
+            max_duration_secs: 4 * 60 * 60,
+        }
+    }
+}
+
+/// Account takeover signals from a user's activity profile: activity in an hour of the
+/// day the user is almost never active in, and a return after a long absence.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ActivityProfileConfig {
+    /// Events a user's profile needs before either signal is scored, so new users are
+    /// never penalized.
+    pub min_events: u64,
+    /// Points when at most `max_hour_share` of the user's events fell in the event's UTC
+    /// hour.
+    pub unusual_hour_points: i32,
+    pub max_hour_share: f64,
+    /// Points for the first event after the user was away for `dormancy_days`.
+    pub dormant_points: i32,
+    pub dormancy_days: u64,
+}
+
+impl ActivityProfileConfig {
+    pub fn dormancy(&self) -> chrono::Duration {
+        chrono::Duration::days(self.dormancy_days as i64)
+    }
+
+    /// Whether either signal can score, so profiles need looking up.
+    pub fn enabled(&self) -> bool {
+        self.unusual_hour_points != 0 || self.dormant_points != 0
+    }
+}
+
+impl Default for ActivityProfileConfig {
+    fn default() -> Self {
+        Self {
+            min_events: 20,
+            unusual_hour_points: 25,
+            max_hour_share: 0.01,
+            dormant_points: 30,
+            dormancy_days: 90,
+        }
+    }
+}
+
+/// A customer-specific rule written in Rhai. The script sees `event`, `session`, and
+/// `geo`, and returns a boolean (adds `weight` when true) or a number (adds that
+/// multiple of `weight`). Its hits use the uppercased `name` as their code.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScriptedRuleConfig {
+    pub name: String,
+    pub weight: i32,
+    pub script: String,
+}
+
+/// Signals that are weak alone but telling together: adds `points` when every rule in
+/// `all_of`, at least one in `any_of` (when set), and none in `none_of` hit the event.
+/// Rules are named as in the rules listing, and may be other composite rules. Its hits
+/// use the uppercased `name` as their code.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompositeRuleConfig {
+    pub name: String,
+    pub points: i32,
+    /// For the rules listing; describes the combination when unset.
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub all_of: Vec<String>,
+    #[serde(default)]
+    pub any_of: Vec<String>,
+    #[serde(default)]
+    pub none_of: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RolloutConfig {
+    /// Share of traffic the rule is enforced for, 0 to 100 in steps of 0.01.
+    pub percent: f64,
+    #[serde(default)]
+    pub basis: RolloutBasis,
+}
+
+/// What decides whether an event falls in a rollout, hashed with the rule's name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RolloutBasis {
+    #[default]
+    SessionId,
+    /// Keeps all of a user's sessions on the same side; anonymous events fall back to
+    /// the session id.
+    UserId,
+}
+
+/// Per-run budget for each scripted rule; a script over budget contributes nothing.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ScriptLimitsConfig {
+    pub max_operations: u64,
+    pub max_duration_ms: u64,
+}
+
+impl Default for ScriptLimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_operations: 50_000,
+            max_duration_ms: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DisposableEmailConfig {
+    /// Points added when a form's `email` uses a disposable domain.
+    pub points: i32,
+    /// Points added when the `email` value isn't a plausible address.
+    pub invalid_format_points: i32,
+}
+
+impl Default for DisposableEmailConfig {
+    fn default() -> Self {
+        Self {
+            points: 40,
+            invalid_format_points: 15,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MetadataKeysConfig {
+    /// Points added when any required key is absent.
+    pub missing_key_points: i32,
+    /// Points added when keys outside the required/optional sets appear.
+    pub unexpected_key_points: i32,
+    /// Points added when values fail their configured type or pattern (`score` mode only).
+    pub malformed_points: i32,
+    /// Whether schema violations are scored or reject the request with 422.
+    pub mode: MetadataSchemaMode,
+    /// Expected metadata keys per event type. Event types without an entry are not checked.
+    pub schemas: HashMap<EventType, MetadataKeySchema>,
+}
+
+impl Default for MetadataKeysConfig {
+    fn default() -> Self {
+        Self {
+            missing_key_points: 30,
+            unexpected_key_points: 20,
+            malformed_points: 30,
+            mode: MetadataSchemaMode::Score,
+            schemas: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataSchemaMode {
+    /// Missing keys and malformed values add to the score.
+    Score,
+    /// Missing required keys and malformed values are rejected with 422.
+    Reject,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MetadataKeySchema {
+    pub required: HashSet<String>,
+    pub optional: HashSet<String>,
+    /// Value constraints for required or optional keys.
+    pub values: HashMap<String, MetadataValueRule>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MetadataValueRule {
+    #[serde(rename = "type")]
+    pub kind: MetadataValueKind,
+    /// Regex the whole value must match.
+    #[serde(deserialize_with = "deserialize_pattern")]
+    pub pattern: Option<Regex>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataValueKind {
+    #[default]
+    String,
+    Integer,
+    Number,
+    Boolean,
+    /// RFC 3339 timestamp.
+    Timestamp,
+}
+
+impl MetadataValueKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::String => "string",
+            Self::Integer => "integer",
+            Self::Number => "number",
+            Self::Boolean => "boolean",
+            Self::Timestamp => "timestamp",
+        }
+    }
+}
+
+/// Compiles patterns at load time, anchored so they must match the whole value.
+fn deserialize_pattern<'de, D>(deserializer: D) -> Result<Option<Regex>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(pattern) => Regex::new(&format!("^(?:{})$", pattern))
+            .map(Some)
+            .map_err(|e| serde::de::Error::custom(format!("invalid pattern `{}`: {}", pattern, e))),
+        None => Ok(None),
+    }
+}
+
+#[derive(Debug)]
+pub struct ConfigError(String);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl ScoringConfig {
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let raw = fs::read_to_string(path)
+            .map_err(|e| ConfigError(format!("failed to read {}: {}", path.display(), e)))?;
+        let mut config: Self = serde_json::from_str(&raw)
+            .map_err(|e| ConfigError(format!("failed to parse {}: {}", path.display(), e)))?;
+        config.version = crate::short_hash(raw.as_bytes());
+        config
+            .validate()
+            .map_err(|e| ConfigError(format!("{}: {}", path.display(), e)))?;
+        Ok(config)
+    }
+
+    /// Checks what deserializing can't, for configs from a file or a request.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.flag_threshold < 0 {
+            return Err(format!("flag_threshold must not be negative, got {}", self.flag_threshold));
+        }
+        if self.flag_threshold > 100 {
+            return Err(format!("flag_threshold must be at most 100, got {}", self.flag_threshold));
+        }
+        if self.session_reputation.flag_threshold < 0 {
+            return Err(format!(
+                "session_reputation.flag_threshold must not be negative, got {}",
+                self.session_reputation.flag_threshold
+            ));
+        }
+        let entity_reputation = &self.entity_reputation;
+        if entity_reputation.flag_points < 0.0 || entity_reputation.weight < 0.0 || entity_reputation.max_points < 0 {
+            return Err("entity_reputation.flag_points, weight, and max_points must not be negative".to_string());
+        }
+        for (event_type, profile) in &self.event_profiles {
+            if let Some(threshold) = profile.flag_threshold.filter(|threshold| *threshold < 0) {
+                return Err(format!(
+                    "event_profiles.{}.flag_threshold must not be negative, got {}",
+                    event_type.as_str(),
+                    threshold
+                ));
+            }
+            if let Some(threshold) = profile.flag_threshold.filter(|threshold| *threshold > 100) {
+                return Err(format!(
+                    "event_profiles.{}.flag_threshold must be at most 100, got {}",
+                    event_type.as_str(),
+                    threshold
+                ));
+            }
+        }
+        if let Some(pair) = self.decision_policy.bands.windows(2).find(|pair| pair[0].min_score >= pair[1].min_score) {
+            return Err(format!(
+                "decision_policy.bands must be in ascending min_score order, but {} follows {}",
+                pair[1].min_score, pair[0].min_score
+            ));
+        }
+        if let Some(email) = &self.alerts.email {
+            if email.to.is_empty() {
+                return Err("alerts.email.to must list at least one recipient".to_string());
+            }
+            for address in email.to.iter().chain([&email.from]) {
+                address
+                    .parse::<lettre::message::Mailbox>()
+                    .map_err(|e| format!("alerts.email has an invalid address `{}`: {}", address, e))?;
+            }
+        }
+        if let Some((rule, rollout)) = self
+            .rollouts
+            .iter()
+            .find(|(_, rollout)| !(0.0..=100.0).contains(&rollout.percent))
+        {
+            return Err(format!("rollouts.{}.percent must be between 0 and 100, got {}", rule, rollout.percent));
+        }
+        let high_frequency = &self.high_frequency;
+        if high_frequency.window_secs == 0 && (high_frequency.max_events_per_ip.is_some() || high_frequency.max_events_per_user.is_some()) {
+            return Err("high_frequency.max_events_per_ip and max_events_per_user need a window_secs above 0".to_string());
+        }
+        if self.ipv6_counter_prefix > 128 {
+            return Err(format!("ipv6_counter_prefix must be at most 128, got {}", self.ipv6_counter_prefix));
+        }
+        Ok(())
+    }
+
+    /// The flag threshold for events of `event_type`, after its profile's override.
+    pub fn flag_threshold(&self, event_type: &EventType) -> i32 {
+        self.event_profiles
+            .get(event_type)
+            .and_then(|profile| profile.flag_threshold)
+            .unwrap_or(self.flag_threshold)
+    }
+
+    /// The most session events any history-based rule looks at. A session cap below this
+    /// would keep those rules from ever firing.
+    pub fn history_needed(&self) -> usize {
+        [
+            self.min_events_for_stateful,
+            self.high_frequency.history_needed(),
+            self.cadence.min_events,
+            self.cadence.window,
+            self.click_timing.min_clicks,
+            self.click_timing.window,
+            self.type_toggling.min_switches + 1,
+            self.payments.repeat_count,
+        ]
+        .into_iter()
+        .max()
+        .unwrap_or_default()
+    }
+
+    /// The file named by `SCORING_CONFIG_PATH`, if any.
+    pub fn path_from_env() -> Option<PathBuf> {
+        env_var("SCORING_CONFIG_PATH").map(PathBuf::from)
+    }
+
+    /// Loads the file named by `SCORING_CONFIG_PATH`, or the defaults when it is unset.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        match Self::path_from_env() {
+            Some(path) => Self::load(&path),
+            None => Ok(Self::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn logistic(midpoint: f64, steepness: f64) -> ConfidenceConfig {
+        ConfidenceConfig {
+            mode: ConfidenceMode::Logistic,
+            midpoint,
+            steepness,
+        }
+    }
+
+    // --- CONFIDENCE ---
+    #[test]
+    fn confidence_is_monotonic_and_within_0_to_100() {
+        for config in [ConfidenceConfig::default(), logistic(50.0, 0.08), logistic(120.0, 0.5), logistic(-20.0, 0.01)] {
+            let mut previous = 0;
+            for score in -1_000..=1_000 {
+                let confidence = config.confidence(score);
+                assert!(confidence <= 100, "{:?} at {}: {}", config.mode, score, confidence);
+                assert!(confidence >= previous, "{:?} fell from {} to {} at {}", config.mode, previous, confidence, score);
+                previous = confidence;
+            }
+        }
+    }
+
+    #[test]
+    fn raw_confidence_is_the_clamped_score() {
+        let config = ConfidenceConfig::default();
+        assert_eq!(config.confidence(-30), 0);
+        assert_eq!(config.confidence(42), 42);
+        assert_eq!(config.confidence(175), 100);
+    }
+
+    #[test]
+    fn logistic_confidence_is_50_at_the_midpoint_and_saturates() {
+        let config = logistic(60.0, 0.1);
+        assert_eq!(config.confidence(60), 50);
+        assert!(config.confidence(40) < 50 && config.confidence(80) > 50);
+        assert_eq!(config.confidence(i32::MIN), 0);
+        assert_eq!(config.confidence(i32::MAX), 100);
+    }
+
+    #[test]
+    fn a_flag_threshold_above_100_is_rejected() {
+        let mut config = ScoringConfig {
+            flag_threshold: 100,
+            ..ScoringConfig::default()
+        };
+        assert!(config.validate().is_ok());
+        config.flag_threshold = 101;
+        assert_eq!(config.validate().unwrap_err(), "flag_threshold must be at most 100, got 101");
+    }
+
+    // --- EVENT PROFILES ---
+    #[test]
+    fn flag_thresholds_fall_back_to_the_global_one() {
+        let config: ScoringConfig = serde_json::from_value(serde_json::json!({
+            "flag_threshold": 60,
+            "event_profiles": {
+                "FormSubmission": { "flag_threshold": 30 },
+                "Click": { "weights": { "IP_BLACKLISTED": 10 } },
+            },
+        }))
+        .unwrap();
+        assert_eq!(config.flag_threshold(&EventType::FormSubmission), 30);
+        assert_eq!(config.flag_threshold(&EventType::Click), 60);
+        assert_eq!(config.flag_threshold(&EventType::PageLoad), 60);
+    }
+
+    #[test]
+    fn a_profile_threshold_above_100_is_rejected() {
+        let config: ScoringConfig = serde_json::from_value(serde_json::json!({
+            "event_profiles": { "Payment": { "flag_threshold": 101 } },
+        }))
+        .unwrap();
+        assert_eq!(
+            config.validate().unwrap_err(),
+            "event_profiles.Payment.flag_threshold must be at most 100, got 101"
+        );
+    }
+
+    // --- DECISION POLICY ---
+    fn policy(config: serde_json::Value) -> DecisionPolicyConfig {
+        serde_json::from_value(config).unwrap()
+    }
+
+    #[test]
+    fn without_bands_flagging_decides() {
+        let policy = DecisionPolicyConfig::default();
+        assert_eq!(policy.decide(500, false, ["IP_BLACKLISTED"]), Decision::Allow);
+        assert_eq!(policy.decide(0, true, [] as [&str; 0]), Decision::Review);
+    }
+
+    #[test]
+    fn a_score_takes_the_last_band_it_reaches() {
+        let policy = policy(serde_json::json!({ "bands": [
+            { "min_score": 20, "decision": "CHALLENGE" },
+            { "min_score": 50, "decision": "REVIEW" },
+            { "min_score": 90, "decision": "DENY" },
+        ] }));
+        for (score, expected) in [
+            (-5, Decision::Allow),
+            (19, Decision::Allow),
+            (20, Decision::Challenge),
+            (49, Decision::Challenge),
+            (50, Decision::Review),
+            (89, Decision::Review),
+            (90, Decision::Deny),
+            (i32::MAX, Decision::Deny),
+        ] {
+            // Bands decide, whether or not the threshold flagged the result.
+            assert_eq!(policy.decide(score, false, [] as [&str; 0]), expected, "{}", score);
+            assert_eq!(policy.decide(score, true, [] as [&str; 0]), expected, "{} flagged", score);
+        }
+    }
+
+    #[test]
+    fn overrides_raise_the_decision_but_never_lower_it() {
+        let policy = policy(serde_json::json!({
+            "bands": [{ "min_score": 50, "decision": "REVIEW" }, { "min_score": 90, "decision": "DENY" }],
+            "overrides": [
+                { "code": "IP_BLACKLISTED", "at_least": "CHALLENGE" },
+                { "code": "TOR_EXIT", "at_least": "REVIEW" },
+            ],
+        }));
+        assert_eq!(policy.decide(0, false, ["IP_BLACKLISTED"]), Decision::Challenge);
+        assert_eq!(policy.decide(60, false, ["IP_BLACKLISTED"]), Decision::Review);
+        assert_eq!(policy.decide(95, false, ["IP_BLACKLISTED", "TOR_EXIT"]), Decision::Deny);
+        // Several present: the most severe floor wins, in any order.
+        assert_eq!(policy.decide(0, false, ["TOR_EXIT", "IP_BLACKLISTED"]), Decision::Review);
+        assert_eq!(policy.decide(0, false, ["OPEN_PROXY"]), Decision::Allow);
+    }
+
+    #[test]
+    fn bands_out_of_order_are_rejected() {
+        let config = ScoringConfig {
+            decision_policy: policy(serde_json::json!({ "bands": [
+                { "min_score": 50, "decision": "REVIEW" },
+                { "min_score": 50, "decision": "DENY" },
+            ] })),
+            ..ScoringConfig::default()
+        };
+        assert_eq!(
+            config.validate().unwrap_err(),
+            "decision_policy.bands must be in ascending min_score order, but 50 follows 50"
+        );
+    }
+
+    // --- THRESHOLDS ---
+    #[test]
+    fn negative_flag_thresholds_are_rejected() {
+        for (config, message) in [
+            (serde_json::json!({ "flag_threshold": -1 }), "flag_threshold must not be negative, got -1"),
+            (
+                serde_json::json!({ "session_reputation": { "flag_threshold": -20 } }),
+                "session_reputation.flag_threshold must not be negative, got -20",
+            ),
+            (
+                serde_json::json!({ "event_profiles": { "FormSubmission": { "flag_threshold": -5 } } }),
+                "event_profiles.FormSubmission.flag_threshold must not be negative, got -5",
+            ),
+        ] {
+            let config: ScoringConfig = serde_json::from_value(config).unwrap();
+            assert_eq!(config.validate().unwrap_err(), message);
+        }
+    }
+
+    #[test]
+    fn a_zero_flag_threshold_is_allowed() {
+        let config: ScoringConfig = serde_json::from_value(serde_json::json!({ "flag_threshold": 0 })).unwrap();
+        assert!(config.validate().is_ok());
+        assert!(ScoringConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn sample_rates_parse_as_type_rate_pairs() {
+        let rates = parse_sample_rates(" Click=0.1, PageLoad = 1 ,").unwrap();
+        assert_eq!(rates, HashMap::from([(EventType::Click, 0.1), (EventType::PageLoad, 1.0)]));
+        for value in ["Click", "Click=1.5", "Click=-0.1", "Scroll=0.5", "Click=half"] {
+            let error = parse_sample_rates(value).unwrap_err();
+            assert!(error.0.starts_with("STORAGE_SAMPLE_RATES has an invalid entry"), "{}: {}", value, error.0);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use chrono::{DateTime, Utc};
+use metrics::counter;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use crate::config::{DegradationPolicy, FailureMode};
+use crate::rules::Dependency;
+use crate::AppError;
+
+// --- DEGRADATION ---
+/// A dependency call that failed, timed out, or was skipped by an open breaker.
+#[derive(Debug, Clone, Copy)]
+pub struct Unavailable;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DegradedDependency {
+    dependency: &'static str,
+    /// When its calls started failing.
+    since: DateTime<Utc>,
+    /// Whether events are still scored (`skip_on_failure`) or turned away.
+    fails_requests: bool,
+}
+
+impl DegradationPolicy {
+    pub fn mode(&self, dependency: Dependency) -> FailureMode {
+        match dependency {
+            Dependency::IpReputation => self.ip_reputation,
+            Dependency::Model => self.model,
+            Dependency::ActivityStore => self.activity_store,
+            Dependency::EntityReputation => self.entity_reputation,
+            // Set up at startup or not at all, so there is nothing to fail mid-request.
+            Dependency::AsnData | Dependency::ObservedIp => FailureMode::FailRequest,
+        }
+    }
+}
+
+/// Applies the degradation policy to dependency calls, and keeps which dependencies
+/// are failing right now: from a failed call until the next one succeeds.
+pub struct DependencyHealth {
+    policy: DegradationPolicy,
+    failing: RwLock<HashMap<Dependency, DateTime<Utc>>>,
+}
+
+impl DependencyHealth {
+    pub fn new(policy: DegradationPolicy) -> Self {
+        Self {
+            policy,
+            failing: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// One call's outcome under the dependency's failure mode: its value when it
+    /// answered; otherwise the default, with the dependency added to `unavailable` so
+    /// the rules needing it are skipped, or a `503` under `fail_request`.
+    pub fn settle<T: Default>(
+        &self,
+        dependency: Dependency,
+        outcome: Result<T, Unavailable>,
+        unavailable: &mut Vec<Dependency>,
+        now: DateTime<Utc>,
+    ) -> Result<T, AppError> {
+        match outcome {
+            Ok(value) => {
+                if self.failing.read().unwrap().contains_key(&dependency) {
+                    self.failing.write().unwrap().remove(&dependency);
+                }
+                Ok(value)
+            }
+            Err(Unavailable) => {
+                self.failing.write().unwrap().entry(dependency).or_insert(now);
+                match self.policy.mode(dependency) {
+                    FailureMode::SkipOnFailure => {
+                        counter!("dependency_unavailable_total", "dependency" => dependency.name(), "action" => "skipped")
+                            .increment(1);
+                        unavailable.push(dependency);
+                        Ok(T::default())
+                    }
+                    FailureMode::FailRequest => {
+                        counter!("dependency_unavailable_total", "dependency" => dependency.name(), "action" => "failed")
+                            .increment(1);
+                        Err(AppError::Unavailable(format!("{} is unavailable", dependency.name())))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Dependencies whose last call failed, for the readiness probe.
+    pub fn degraded(&self) -> Vec<DegradedDependency> {
+        let mut degraded: Vec<_> = self
+            .failing
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(dependency, since)| DegradedDependency {
+                dependency: dependency.name(),
+                since: *since,
+                fails_requests: self.policy.mode(*dependency) == FailureMode::FailRequest,
+            })
+            .collect();
+        degraded.sort_by_key(|degraded| degraded.dependency);
+        degraded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn health() -> DependencyHealth {
+        DependencyHealth::new(DegradationPolicy {
+            ip_reputation: FailureMode::SkipOnFailure,
+            model: FailureMode::SkipOnFailure,
+            activity_store: FailureMode::FailRequest,
+            entity_reputation: FailureMode::SkipOnFailure,
+        })
+    }
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_714_564_800 + secs, 0).unwrap()
+    }
+
+    #[test]
+    fn a_skipped_dependency_yields_the_default_and_is_listed() {
+        let health = health();
+        let mut unavailable = Vec::new();
+        let value: u8 = health.settle(Dependency::IpReputation, Err(Unavailable), &mut unavailable, at(0)).unwrap();
+        assert_eq!(value, 0);
+        assert_eq!(unavailable, [Dependency::IpReputation]);
+    }
+
+    #[test]
+    fn a_fail_request_dependency_is_a_503() {
+        let health = health();
+        let mut unavailable = Vec::new();
+        let error = health.settle::<u8>(Dependency::ActivityStore, Err(Unavailable), &mut unavailable, at(0)).unwrap_err();
+        assert!(matches!(error, AppError::Unavailable(ref message) if message == "activity_store is unavailable"));
+        assert!(unavailable.is_empty());
+    }
+
+    #[test]
+    fn degraded_lists_failures_from_the_first_until_a_success() {
+        let health = health();
+        let mut unavailable = Vec::new();
+        let _ = health.settle::<u8>(Dependency::Model, Err(Unavailable), &mut unavailable, at(0));
+        let _ = health.settle::<u8>(Dependency::Model, Err(Unavailable), &mut unavailable, at(5));
+        let _ = health.settle::<u8>(Dependency::ActivityStore, Err(Unavailable), &mut unavailable, at(10));
+        let degraded = health.degraded();
+        let listed: Vec<_> = degraded.iter().map(|d| (d.dependency, d.since, d.fails_requests)).collect();
+        assert_eq!(listed, [("activity_store", at(10), true), ("model", at(0), false)]);
+
+        assert_eq!(health.settle(Dependency::Model, Ok(7u8), &mut unavailable, at(20)).unwrap(), 7);
+        assert_eq!(health.degraded().len(), 1);
+        assert_eq!(health.degraded()[0].dependency, "activity_store");
+    }
+
+    #[test]
+    fn startup_dependencies_always_fail_the_request() {
+        let policy = health().policy;
+        assert_eq!(policy.mode(Dependency::AsnData), FailureMode::FailRequest);
+        assert_eq!(policy.mode(Dependency::ObservedIp), FailureMode::FailRequest);
+        assert_eq!(policy.mode(Dependency::EntityReputation), FailureMode::SkipOnFailure);
+    }
+}
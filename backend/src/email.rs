@@ -0,0 +1,141 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use tracing::info;
+
+/// Common throwaway-mail providers, used unless `DISPOSABLE_DOMAINS_PATH` names a list.
+const BUNDLED_DISPOSABLE_DOMAINS: &[&str] = &[
+    "10minutemail.com",
+    "guerrillamail.com",
+    "guerrillamail.net",
+    "sharklasers.com",
+    "mailinator.com",
+    "maildrop.cc",
+    "yopmail.com",
+    "trashmail.com",
+    "temp-mail.org",
+    "tempmail.com",
+    "throwawaymail.com",
+    "getnada.com",
+    "dispostable.com",
+    "fakeinbox.com",
+    "mailnesia.com",
+    "mintemail.com",
+    "mohmal.com",
+    "emailondeck.com",
+    "spamgourmet.com",
+    "burnermail.io",
+];
+
+// --- DISPOSABLE EMAIL DOMAINS ---
+pub struct DisposableDomains {
+    domains: HashSet<String>,
+}
+
+impl DisposableDomains {
+    pub fn bundled() -> Self {
+        Self {
+            domains: BUNDLED_DISPOSABLE_DOMAINS.iter().map(|domain| domain.to_string()).collect(),
+        }
+    }
+
+    /// Loads one domain per line, skipping blank lines and `#` comments. Replaces the
+    /// bundled list rather than extending it.
+    pub fn load_file(path: &Path) -> std::io::Result<Self> {
+        let domains: HashSet<String> = fs::read_to_string(path)?
+            .lines()
+            .map(|line| line.trim().trim_start_matches("*.").to_ascii_lowercase())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect();
+        info!("Loaded {} disposable email domains from {}", domains.len(), path.display());
+        Ok(Self { domains })
+    }
+
+    /// Matches the domain itself or any parent, so `eu.mailinator.com` counts too.
+    pub fn matches(&self, domain: &str) -> Option<&str> {
+        let mut candidate = domain;
+        loop {
+            if let Some(found) = self.domains.get(candidate) {
+                return Some(found);
+            }
+            candidate = candidate.split_once('.')?.1;
+        }
+    }
+}
+
+// --- ADDRESS PARSING ---
+/// An email address normalized for comparison: trimmed, lowercased, and with any
+/// `+tag` removed from the local part.
+pub struct EmailAddress {
+    pub local: String,
+    pub domain: String,
+}
+
+impl EmailAddress {
+    /// A deliberately loose check: one `@`, a non-empty local part, and a dotted domain
+    /// without whitespace or empty labels.
+    pub fn parse(raw: &str) -> Option<Self> {
+        let raw = raw.trim().to_ascii_lowercase();
+        let (local, domain) = raw.split_once('@')?;
+        let domain = domain.trim_end_matches('.');
+        let local = local.split_once('+').map_or(local, |(base, _)| base);
+
+        let valid_domain = domain.contains('.')
+            && domain.split('.').all(|label| {
+                !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            });
+        let valid_local = !local.is_empty() && !local.contains(|c: char| c.is_whitespace() || c == '@');
+        if !valid_domain || !valid_local {
+            return None;
+        }
+
+        Some(Self {
+            local: local.to_string(),
+            domain: domain.to_string(),
+        })
+    }
+
+    /// Short, stable fingerprint for logs, so the same address can be correlated without
+    /// the address itself ever being written out.
+    pub fn log_hash(&self) -> String {
+        crate::short_hash(format!("{}@{}", self.local, self.domain).as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_are_normalized() {
+        let email = EmailAddress::parse("  Jane.Doe+Promo@Example.COM. ").unwrap();
+        assert_eq!((email.local.as_str(), email.domain.as_str()), ("jane.doe", "example.com"));
+    }
+
+    #[test]
+    fn malformed_addresses_do_not_parse() {
+        for raw in ["", "jane", "@example.com", "jane@", "jane@localhost", "jane@exa mple.com", "jane@example..com", "a@b@example.com"] {
+            assert!(EmailAddress::parse(raw).is_none(), "{}", raw);
+        }
+    }
+
+    #[test]
+    fn domains_match_themselves_and_their_subdomains() {
+        let domains = DisposableDomains::bundled();
+        assert_eq!(domains.matches("mailinator.com"), Some("mailinator.com"));
+        assert_eq!(domains.matches("eu.mailinator.com"), Some("mailinator.com"));
+        assert_eq!(domains.matches("example.com"), None);
+        assert_eq!(domains.matches("notmailinator.com"), None);
+    }
+
+    #[test]
+    fn a_domain_file_replaces_the_bundled_list() {
+        let path = std::env::temp_dir().join(format!("disposable-domains-{}.txt", std::process::id()));
+        std::fs::write(&path, "# throwaway providers\n\nExample.NET\n*.burner.test\n").unwrap();
+        let domains = DisposableDomains::load_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(domains.matches("example.net"), Some("example.net"));
+        assert_eq!(domains.matches("x.burner.test"), Some("burner.test"));
+        assert_eq!(domains.matches("mailinator.com"), None);
+    }
+}
@@ -0,0 +1,212 @@
+use async_trait::async_trait;
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::store::StoreError;
+use crate::{ip, AppError, AppState};
+
+/// Reputations held in memory above which faded ones are swept on insert.
+const SWEEP_THRESHOLD: usize = 100_000;
+/// Reputations decayed below this are treated as gone.
+pub const FADED: f64 = 1.0;
+
+// --- ENTITY REPUTATION ---
+/// What a reputation is kept against. Unlike session reputation, these outlive the
+/// session, so a user or IP flagged before starts their next session with a deficit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Entity {
+    User,
+    /// Keyed by the IP as stored (after `IP_PRIVACY_MODE`), grouped like the per-IP
+    /// counters under `ipv6_counter_prefix`.
+    Ip,
+}
+
+impl Entity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Entity::User => "user",
+            Entity::Ip => "ip",
+        }
+    }
+}
+
+/// A reputation as last updated. It is decayed when read, so idle entities cost nothing.
+#[derive(Debug, Clone, Copy)]
+pub struct StoredReputation {
+    pub score: f64,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl StoredReputation {
+    /// The reputation as of `now`, halving every `half_life` since it was updated.
+    pub fn at(&self, now: DateTime<Utc>, half_life: Duration) -> f64 {
+        let elapsed = (now - self.updated_at).to_std().unwrap_or_default();
+        self.score * 0.5f64.powf(elapsed.as_secs_f64() / half_life.as_secs_f64())
+    }
+}
+
+/// The reputations of an event's user and IP before it, for the `entity_reputation` rule.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EntityScores {
+    pub user: f64,
+    pub ip: f64,
+}
+
+/// Where entity reputations live: in process memory, or in Redis when `REDIS_URL` is
+/// set, so they survive restarts and are shared across replicas.
+#[async_trait]
+pub trait EntityReputationStore: Send + Sync {
+    async fn get(&self, entity: Entity, id: &str) -> Result<Option<StoredReputation>, StoreError>;
+
+    /// Adds `points` to the reputation, decayed to `now` first.
+    async fn add(&self, entity: Entity, id: &str, points: f64, now: DateTime<Utc>, half_life: Duration)
+        -> Result<(), StoreError>;
+
+    /// Drops the reputation, returning whether there was one.
+    async fn reset(&self, entity: Entity, id: &str) -> Result<bool, StoreError>;
+
+    /// Drops reputations that have faded. Backends that expire entries themselves leave
+    /// this alone.
+    fn sweep(&self, _now: DateTime<Utc>, _half_life: Duration) {}
+}
+
+#[derive(Default)]
+pub struct MemoryEntityReputationStore {
+    reputations: Mutex<HashMap<(Entity, String), StoredReputation>>,
+}
+
+fn prune(reputations: &mut HashMap<(Entity, String), StoredReputation>, now: DateTime<Utc>, half_life: Duration) {
+    reputations.retain(|_, reputation| reputation.at(now, half_life) >= FADED);
+}
+
+#[async_trait]
+impl EntityReputationStore for MemoryEntityReputationStore {
+    async fn get(&self, entity: Entity, id: &str) -> Result<Option<StoredReputation>, StoreError> {
+        Ok(self.reputations.lock().unwrap().get(&(entity, id.to_string())).copied())
+    }
+
+    async fn add(
+        &self,
+        entity: Entity,
+        id: &str,
+        points: f64,
+        now: DateTime<Utc>,
+        half_life: Duration,
+    ) -> Result<(), StoreError> {
+        let mut reputations = self.reputations.lock().unwrap();
+        if reputations.len() >= SWEEP_THRESHOLD {
+            prune(&mut reputations, now, half_life);
+        }
+
+        let entry = reputations.entry((entity, id.to_string())).or_insert(StoredReputation {
+            score: 0.0,
+            updated_at: now,
+        });
+        *entry = StoredReputation {
+            score: entry.at(now, half_life) + points,
+            updated_at: now,
+        };
+        Ok(())
+    }
+
+    async fn reset(&self, entity: Entity, id: &str) -> Result<bool, StoreError> {
+        Ok(self.reputations.lock().unwrap().remove(&(entity, id.to_string())).is_some())
+    }
+
+    fn sweep(&self, now: DateTime<Utc>, half_life: Duration) {
+        prune(&mut self.reputations.lock().unwrap(), now, half_life);
+    }
+}
+
+// --- HANDLERS ---
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReputationReport {
+    entity: Entity,
+    id: String,
+    /// Decayed to now; `0` for entities never flagged or long since faded.
+    score: f64,
+    /// When it was last added to.
+    updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReputationReset {
+    entity: Entity,
+    id: String,
+    /// Whether there was a reputation to reset.
+    reset: bool,
+}
+
+/// The key an IP's reputation is kept under: the path IP as the event store would
+/// hold it, so reputations can be looked up without ever storing the raw address.
+fn ip_key(state: &AppState, ip: &str) -> Result<String, AppError> {
+    let ip: IpAddr = ip
+        .parse()
+        .map_err(|_| AppError::BadRequest(format!("`{}` is not an IP address", ip)))?;
+    let stored = state.service.ip_privacy.apply(&ip.to_string());
+    Ok(ip::counter_key(&stored, state.scoring().ipv6_counter_prefix).into_owned())
+}
+
+async fn report(state: &AppState, entity: Entity, id: String, key: &str) -> Result<Json<ReputationReport>, AppError> {
+    let stored = state.entity_reputations.get(entity, key).await?;
+    let half_life = state.scoring().entity_reputation.half_life();
+    let score = match (stored, half_life) {
+        (Some(stored), Some(half_life)) => stored.at(state.clock.now(), half_life),
+        (Some(stored), None) => stored.score,
+        (None, _) => 0.0,
+    };
+    Ok(Json(ReputationReport {
+        entity,
+        id,
+        score,
+        updated_at: stored.map(|stored| stored.updated_at),
+    }))
+}
+
+pub async fn ip_reputation_handler(
+    State(state): State<Arc<AppState>>,
+    Path(ip): Path<String>,
+) -> Result<Json<ReputationReport>, AppError> {
+    let key = ip_key(&state, &ip)?;
+    report(&state, Entity::Ip, ip, &key).await
+}
+
+pub async fn user_reputation_handler(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<ReputationReport>, AppError> {
+    report(&state, Entity::User, user_id.clone(), &user_id).await
+}
+
+/// Clears an IP's reputation, after a confirmed false positive.
+pub async fn reset_ip_reputation_handler(
+    State(state): State<Arc<AppState>>,
+    Path(ip): Path<String>,
+) -> Result<Json<ReputationReset>, AppError> {
+    let key = ip_key(&state, &ip)?;
+    let reset = state.entity_reputations.reset(Entity::Ip, &key).await?;
+    Ok(Json(ReputationReset { entity: Entity::Ip, id: ip, reset }))
+}
+
+/// Clears a user's reputation, after a confirmed false positive.
+pub async fn reset_user_reputation_handler(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<ReputationReset>, AppError> {
+    let reset = state.entity_reputations.reset(Entity::User, &user_id).await?;
+    Ok(Json(ReputationReset {
+        entity: Entity::User,
+        id: user_id,
+        reset,
+    }))
+}
@@ -0,0 +1,226 @@
+use axum::{
+    extract::{
+        rejection::{BytesRejection, JsonRejection, QueryRejection},
+        FromRequest, FromRequestParts,
+    },
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use tracing::{error, warn};
+
+use crate::store::StoreError;
+
+// --- API ERRORS ---
+/// Sent as `Retry-After` with overload responses.
+pub const RETRY_AFTER_SECS: u64 = 1;
+
+/// Every error a handler can return. Responses are `{ "error": <kind>, "message": ... }`
+/// with the status below; internal details are logged, never sent to clients.
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    /// Malformed request syntax: bad JSON, bad query strings, unusable cursors. `400`.
+    #[error("{0}")]
+    BadRequest(String),
+    /// Well-formed but unacceptable input. `422`.
+    #[error("{0}")]
+    Validation(String),
+    #[error("{0}")]
+    NotFound(String),
+    /// Missing or wrong credentials. `401`.
+    #[error("{0}")]
+    Unauthorized(String),
+    /// The body is over `MAX_BODY_BYTES`. `413`.
+    #[error("{0}")]
+    PayloadTooLarge(String),
+    /// The service is at capacity; clients should back off and retry. `429`, with
+    /// `Retry-After`.
+    #[error("{0}")]
+    Overloaded(String),
+    /// Too many requests in flight across the API (`MAX_IN_FLIGHT_REQUESTS`), or a full
+    /// store turning away a new session. `503`, with `Retry-After`.
+    #[error("{0}")]
+    Shed(String),
+    /// The request outran `REQUEST_TIMEOUT_MS`. `504`.
+    #[error("{0}")]
+    Timeout(String),
+    /// A dependency under `fail_request` failed while the event was scored; clients may
+    /// retry. `503`.
+    #[error("{0}")]
+    Unavailable(String),
+    /// The event store failed; clients may retry. `503`.
+    #[error("Event store unavailable")]
+    Store(StoreError),
+    /// A bug or an unexpected failure. `500`.
+    #[error("Internal error")]
+    Internal(String),
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    error: &'static str,
+    message: String,
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::Overloaded(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Shed(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            AppError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Store(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The `error` field of the body, stable for clients to match on.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AppError::BadRequest(_) => "bad_request",
+            AppError::Validation(_) => "validation",
+            AppError::NotFound(_) => "not_found",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::PayloadTooLarge(_) => "payload_too_large",
+            AppError::Overloaded(_) => "overloaded",
+            AppError::Shed(_) => "unavailable",
+            AppError::Timeout(_) => "timeout",
+            AppError::Unavailable(_) => "dependency_unavailable",
+            AppError::Store(_) => "unavailable",
+            AppError::Internal(_) => "internal",
+        }
+    }
+
+    /// The body sent to clients, logging the internal details that are left out of it.
+    pub fn report(&self) -> ErrorBody {
+        match self {
+            AppError::Store(e) => warn!("Event store error: {}", e),
+            AppError::Internal(detail) => error!("Internal error: {}", detail),
+            _ => {}
+        }
+        ErrorBody {
+            error: self.kind(),
+            message: self.to_string(),
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let mut response = (self.status(), Json(self.report())).into_response();
+        if let AppError::Overloaded(_) | AppError::Shed(_) = self {
+            response.headers_mut().insert(header::RETRY_AFTER, RETRY_AFTER_SECS.into());
+        }
+        response
+    }
+}
+
+/// A full store is shedding, not an outage: the sessions it holds are still answered.
+impl From<StoreError> for AppError {
+    fn from(e: StoreError) -> Self {
+        match e {
+            StoreError::Full => AppError::Shed("Event store is full".to_string()),
+            e => AppError::Store(e),
+        }
+    }
+}
+
+/// Unparseable bodies are `400`; JSON that doesn't fit the expected shape (a missing
+/// field, an invalid IP) is a validation error.
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        match rejection {
+            JsonRejection::JsonDataError(e) => AppError::Validation(e.body_text()),
+            other if other.status() == StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge(other.body_text()),
+            other => AppError::BadRequest(other.body_text()),
+        }
+    }
+}
+
+/// For handlers reading the raw body, like the protobuf and MessagePack ones.
+impl From<BytesRejection> for AppError {
+    fn from(rejection: BytesRejection) -> Self {
+        match rejection.status() {
+            StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge(rejection.body_text()),
+            _ => AppError::BadRequest(rejection.body_text()),
+        }
+    }
+}
+
+impl From<QueryRejection> for AppError {
+    fn from(rejection: QueryRejection) -> Self {
+        AppError::BadRequest(rejection.body_text())
+    }
+}
+
+/// `Json`, with rejections reported as `AppError`s.
+#[derive(Debug, FromRequest)]
+#[from_request(via(Json), rejection(AppError))]
+pub struct AppJson<T>(pub T);
+
+/// `Query`, with rejections reported as `AppError`s.
+#[derive(Debug, FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(AppError))]
+pub struct AppQuery<T>(pub T);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    async fn sent(error: AppError) -> (StatusCode, Option<String>, Value) {
+        let response = error.into_response();
+        let retry_after = response
+            .headers()
+            .get(header::RETRY_AFTER)
+            .map(|value| value.to_str().unwrap().to_string());
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, retry_after, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn each_variant_has_its_status_and_kind() {
+        let cases = [
+            (AppError::BadRequest("bad".into()), StatusCode::BAD_REQUEST, "bad_request", None),
+            (AppError::Validation("bad".into()), StatusCode::UNPROCESSABLE_ENTITY, "validation", None),
+            (AppError::NotFound("bad".into()), StatusCode::NOT_FOUND, "not_found", None),
+            (AppError::Unauthorized("bad".into()), StatusCode::UNAUTHORIZED, "unauthorized", None),
+            (AppError::PayloadTooLarge("bad".into()), StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large", None),
+            (AppError::Overloaded("bad".into()), StatusCode::TOO_MANY_REQUESTS, "overloaded", Some("1")),
+            (AppError::Shed("bad".into()), StatusCode::SERVICE_UNAVAILABLE, "unavailable", Some("1")),
+            (AppError::Timeout("bad".into()), StatusCode::GATEWAY_TIMEOUT, "timeout", None),
+            (AppError::Unavailable("bad".into()), StatusCode::SERVICE_UNAVAILABLE, "dependency_unavailable", None),
+        ];
+        for (error, status, kind, retry_after) in cases {
+            let name = format!("{:?}", error);
+            let sent = sent(error).await;
+            assert_eq!(sent, (status, retry_after.map(String::from), json!({ "error": kind, "message": "bad" })), "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn internal_details_are_not_sent() {
+        let (status, _, body) = sent(AppError::Internal("lock poisoned at store.rs:10".into())).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body, json!({ "error": "internal", "message": "Internal error" }));
+
+        let (status, _, body) = sent(AppError::Store(StoreError::Backend("connection refused".into()))).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body, json!({ "error": "unavailable", "message": "Event store unavailable" }));
+    }
+
+    #[tokio::test]
+    async fn a_full_store_is_shed_rather_than_an_outage() {
+        let (status, retry_after, body) = sent(StoreError::Full.into()).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(retry_after.as_deref(), Some("1"));
+        assert_eq!(body, json!({ "error": "unavailable", "message": "Event store is full" }));
+    }
+}
@@ -0,0 +1,51 @@
+use jsonschema::Validator;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+use crate::AppError;
+
+// --- EVENT SCHEMA ---
+/// A JSON Schema that raw JSON event bodies must match before they are decoded, so
+/// fields the `UserEvent` struct would silently ignore (or a misspelled optional one)
+/// are caught at the door.
+pub struct EventSchema {
+    validator: Validator,
+}
+
+impl EventSchema {
+    pub fn load_file(path: &Path) -> Result<Self, String> {
+        let raw = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let schema: Value = serde_json::from_str(&raw).map_err(|e| format!("invalid JSON: {}", e))?;
+        let validator = jsonschema::validator_for(&schema).map_err(|e| format!("invalid schema: {}", e))?;
+        Ok(Self { validator })
+    }
+
+    /// `422` naming the first violation by its paths in the body and in the schema. The
+    /// offending value itself is left out, like everywhere else client input is reported.
+    pub fn check(&self, body: &Value) -> Result<(), AppError> {
+        let mut errors = self.validator.iter_errors(body);
+        let Some(first) = errors.next() else {
+            return Ok(());
+        };
+        let others = errors.count();
+
+        let mut message = format!(
+            "Event does not match the event schema at `{}` (schema path `{}`)",
+            path_or_root(first.instance_path.as_str()),
+            path_or_root(first.schema_path.as_str())
+        );
+        if others > 0 {
+            message.push_str(&format!(", and {} more violations", others));
+        }
+        Err(AppError::Validation(message))
+    }
+}
+
+fn path_or_root(path: &str) -> &str {
+    if path.is_empty() {
+        "/"
+    } else {
+        path
+    }
+}
@@ -0,0 +1,174 @@
+use axum::{
+    body::StreamBody,
+    extract::State,
+    http::{header, HeaderMap, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures_util::stream;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::store::StoreError;
+use crate::error::AppQuery;
+use crate::flags::{decode_cursor, encode_cursor};
+use crate::{AppError, AppState, UserEvent};
+
+// --- EVENT EXPORT ---
+#[derive(Debug, Deserialize)]
+pub struct ExportParams {
+    /// Only events with a later timestamp are exported.
+    since: DateTime<Utc>,
+    /// Restricts the export to one session.
+    session: Option<String>,
+    /// Events per page. Giving this or `cursor` switches to paged export.
+    limit: Option<usize>,
+    /// Continuation token from the previous page's `Link` header.
+    cursor: Option<String>,
+}
+
+/// Events per page unless the request asks for fewer.
+const MAX_EXPORT_PAGE: usize = 10_000;
+
+/// Page order: event timestamp, then session id.
+type ExportKey = (DateTime<Utc>, String);
+
+/// Streams stored events as newline-delimited JSON, one session at a time, so memory use
+/// is bounded by the largest session rather than the whole store. A store error after
+/// the response has started aborts the body, so clients see a truncated transfer instead
+/// of a silently short export. With `limit` or `cursor`, a single page is exported instead.
+pub async fn export_handler(
+    State(state): State<Arc<AppState>>,
+    AppQuery(params): AppQuery<ExportParams>,
+) -> Result<Response, AppError> {
+    if params.limit.is_some() || params.cursor.is_some() {
+        return export_page(&state, params).await;
+    }
+    let mut session_ids = match params.session {
+        Some(session_id) => vec![session_id],
+        None => state.event_store.session_ids().await?,
+    };
+    session_ids.sort_unstable();
+
+    let since = params.since;
+    let body = stream::unfold(session_ids.into_iter(), move |mut session_ids| {
+        let state = state.clone();
+        async move {
+            let session_id = session_ids.next()?;
+            let chunk = session_chunk(&state, &session_id, since).await.map_err(|e| {
+                warn!("Export aborted at session {}: {}", session_id, e);
+                e
+            });
+            Some((chunk, session_ids))
+        }
+    });
+
+    Ok(([(header::CONTENT_TYPE, "application/x-ndjson")], StreamBody::new(body)).into_response())
+}
+
+async fn session_chunk(state: &AppState, session_id: &str, since: DateTime<Utc>) -> Result<String, StoreError> {
+    let mut chunk = String::new();
+    for event in state.event_store.session_events(session_id).await? {
+        if event.timestamp > since {
+            chunk.push_str(&serde_json::to_string(&*event).map_err(|e| StoreError::Backend(e.to_string()))?);
+            chunk.push('\n');
+        }
+    }
+    Ok(chunk)
+}
+
+/// Drops the groups past the one that follows the first `limit` events, keeping just
+/// enough to cut the page and to tell whether anything comes after it.
+fn trim_page(page: &mut BTreeMap<ExportKey, Vec<Arc<UserEvent>>>, limit: usize) {
+    let mut count = 0;
+    let drop_from = page
+        .iter()
+        .skip_while(|(_, events)| {
+            count += events.len();
+            count < limit
+        })
+        .nth(2)
+        .map(|(key, _)| key.clone());
+    if let Some(key) = drop_from {
+        page.split_off(&key);
+    }
+}
+
+/// One page of events after the cursor, ordered by timestamp then session id, so paging
+/// is deterministic while new events arrive. Events sharing a timestamp and session are
+/// never split across pages, so a page can run slightly over `limit`. Every page reads
+/// the whole store but only holds about two pages of events.
+async fn export_page(state: &AppState, params: ExportParams) -> Result<Response, AppError> {
+    let after = match &params.cursor {
+        Some(cursor) => Some(decode_cursor(cursor).ok_or(AppError::BadRequest("Invalid cursor".to_string()))?),
+        None => None,
+    };
+    let limit = params.limit.unwrap_or(MAX_EXPORT_PAGE).clamp(1, MAX_EXPORT_PAGE);
+    let session_ids = match &params.session {
+        Some(session_id) => vec![session_id.clone()],
+        None => state.event_store.session_ids().await?,
+    };
+
+    let mut page: BTreeMap<ExportKey, Vec<Arc<UserEvent>>> = BTreeMap::new();
+    let mut held = 0;
+    for session_id in session_ids {
+        for event in state.event_store.session_events(&session_id).await? {
+            let key = (event.timestamp, session_id.clone());
+            if event.timestamp > params.since && after.as_ref().is_none_or(|after| key > *after) {
+                page.entry(key).or_default().push(event);
+                held += 1;
+            }
+        }
+        if held > 2 * limit {
+            trim_page(&mut page, limit);
+            held = page.values().map(Vec::len).sum();
+        }
+    }
+    trim_page(&mut page, limit);
+
+    let mut count = 0;
+    let mut body = String::new();
+    let mut last = None;
+    while count < limit {
+        let Some((key, events)) = page.pop_first() else {
+            break;
+        };
+        for event in &events {
+            body.push_str(&serde_json::to_string(&**event).map_err(|e| AppError::Internal(e.to_string()))?);
+            body.push('\n');
+        }
+        count += events.len();
+        last = Some(key);
+    }
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/x-ndjson"));
+    if let Some(last) = last.as_ref().filter(|_| !page.is_empty()) {
+        let mut next = format!(
+            "/api/v1/export?since={}&limit={}&cursor={}",
+            params.since.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            limit,
+            encode_cursor(last)
+        );
+        if let Some(session_id) = &params.session {
+            next.push_str(&format!("&session={}", percent_encode(session_id)));
+        }
+        let link = HeaderValue::from_str(&format!("<{}>; rel=\"next\"", next)).expect("link header is ASCII");
+        headers.insert(header::LINK, link);
+    }
+    Ok((headers, StreamBody::new(stream::once(async { Ok::<_, Infallible>(body) }))).into_response())
+}
+
+/// Escapes everything but unreserved characters, for echoing a session id in a URL.
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
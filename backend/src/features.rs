@@ -0,0 +1,185 @@
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    response::Response,
+    Json,
+};
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::blacklist::Blacklist;
+use crate::geoip::GeoInfo;
+use crate::ip;
+use crate::msgpack::{self, AppBody};
+use crate::{AppError, AppState, EventType, UserEvent};
+
+// --- MODEL FEATURES ---
+/// Bumped whenever `FEATURE_NAMES` or how a feature is computed changes, so a model
+/// trained on one layout is never silently served another.
+pub const FEATURE_VERSION: u32 = 1;
+
+/// Column order of the feature vector the model is trained on.
+pub const FEATURE_NAMES: [&str; 15] = [
+    "event_count",
+    "page_load_count",
+    "click_count",
+    "form_submission_count",
+    "payment_count",
+    "secs_since_session_start",
+    "interval_mean_ms",
+    "interval_std_dev_ms",
+    "interval_min_ms",
+    "last_interval_ms",
+    "events_last_60s",
+    "distinct_ips",
+    "ip_blacklisted",
+    "geo_known",
+    "user_agent_present",
+];
+
+pub type FeatureVector = [f32; FEATURE_NAMES.len()];
+
+/// Features for the last event of `history`, computed only from the session and the
+/// IP-derived facts the rules see, so the service and offline training agree.
+pub fn extract(
+    history: &[Arc<UserEvent>],
+    ip: &str,
+    blacklist: &Blacklist,
+    geo: Option<&GeoInfo>,
+    ipv6_prefix: u8,
+) -> FeatureVector {
+    let Some(event) = history.last() else {
+        return [0.0; FEATURE_NAMES.len()];
+    };
+    let count_of = |event_type: EventType| history.iter().filter(|event| event.event_type == event_type).count() as f32;
+
+    let timestamps: Vec<i64> = history.iter().map(|event| event.timestamp.timestamp_millis()).collect();
+    let intervals: Vec<i64> = timestamps.windows(2).map(|pair| (pair[1] - pair[0]).max(0)).collect();
+    let (mean, std_dev) = if intervals.is_empty() {
+        (0.0, 0.0)
+    } else {
+        crate::rules::mean_and_std_dev(&intervals)
+    };
+    let now = event.timestamp.timestamp_millis();
+    let first = timestamps.iter().min().copied().unwrap_or_default();
+    let recent = timestamps.iter().filter(|&&timestamp| timestamp > now - 60_000 && timestamp <= now).count();
+    let distinct_ips = history
+        .iter()
+        .map(|event| ip::counter_key(&event.ip_address, ipv6_prefix))
+        .collect::<HashSet<_>>()
+        .len();
+
+    [
+        history.len() as f32,
+        count_of(EventType::PageLoad),
+        count_of(EventType::Click),
+        count_of(EventType::FormSubmission),
+        count_of(EventType::Payment),
+        (now - first) as f32 / 1000.0,
+        mean as f32,
+        std_dev as f32,
+        intervals.iter().min().copied().unwrap_or_default() as f32,
+        intervals.last().copied().unwrap_or_default() as f32,
+        recent as f32,
+        distinct_ips as f32,
+        f32::from(u8::from(blacklist.contains(ip))),
+        f32::from(u8::from(geo.is_some_and(|geo| geo.country.is_some()))),
+        f32::from(u8::from(event.user_agent().is_some())),
+    ]
+}
+
+// --- FEATURES API HANDLERS ---
+/// Most sessions one batch request may ask for.
+const MAX_BATCH_SESSIONS: usize = 1_000;
+
+/// Serializes as a `name: value` object in `FEATURE_NAMES` order.
+#[derive(Debug)]
+pub struct NamedFeatures(FeatureVector);
+
+impl Serialize for NamedFeatures {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(FEATURE_NAMES.iter().zip(self.0))
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionFeatures {
+    session_id: String,
+    feature_version: u32,
+    features: NamedFeatures,
+    /// Categorical geo fields, which aren't part of the model's numeric vector.
+    country: Option<String>,
+}
+
+/// Features for the session's latest event, as the scorer computed them. Read-only: it
+/// touches no metrics, counters, or per-user state.
+async fn session_features(state: &AppState, session_id: String) -> Result<Option<SessionFeatures>, AppError> {
+    let history = state.event_store.session_events(&session_id).await?;
+    let Some(event) = history.last() else {
+        return Ok(None);
+    };
+    let geo = state.geo(&event.ip_address);
+    let features = extract(
+        &history,
+        &event.ip_address,
+        &state.ip_blacklist,
+        geo.as_ref(),
+        state.scoring().ipv6_counter_prefix,
+    );
+
+    Ok(Some(SessionFeatures {
+        session_id,
+        feature_version: FEATURE_VERSION,
+        features: NamedFeatures(features),
+        country: geo.and_then(|geo| geo.country),
+    }))
+}
+
+pub async fn session_features_handler(
+    State(state): State<Arc<AppState>>,
+    Path(session_id): Path<String>,
+) -> Result<Json<SessionFeatures>, AppError> {
+    let not_found = AppError::NotFound(format!("No events stored for session {}", session_id));
+    session_features(&state, session_id).await?.map(Json).ok_or(not_found)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchRequest {
+    session_ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchResponse {
+    feature_version: u32,
+    sessions: Vec<SessionFeatures>,
+    /// Requested sessions with no stored events.
+    missing: Vec<String>,
+}
+
+/// Takes and answers in JSON or MessagePack, like the analyze endpoint.
+pub async fn batch_features_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    AppBody(request): AppBody<BatchRequest>,
+) -> Result<Response, AppError> {
+    if request.session_ids.len() > MAX_BATCH_SESSIONS {
+        return Err(AppError::Validation(format!("At most {} session ids per batch", MAX_BATCH_SESSIONS)));
+    }
+
+    let mut response = BatchResponse {
+        feature_version: FEATURE_VERSION,
+        sessions: Vec::new(),
+        missing: Vec::new(),
+    };
+    for session_id in request.session_ids {
+        match session_features(&state, session_id.clone()).await? {
+            Some(features) => response.sessions.push(features),
+            None => response.missing.push(session_id),
+        }
+    }
+    msgpack::respond(&headers, response)
+}
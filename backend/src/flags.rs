@@ -0,0 +1,234 @@
+use axum::{
+    body::StreamBody,
+    extract::State,
+    http::{header, HeaderMap, HeaderValue},
+    response::IntoResponse,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures_util::{stream, StreamExt};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::ops::Bound;
+use std::sync::{Arc, Mutex};
+
+use crate::error::AppQuery;
+use crate::{AppError, AppState};
+
+// --- FLAGGED SESSIONS ---
+/// Flagged sessions kept for export; the oldest flags are dropped beyond this.
+const MAX_FLAGGED_SESSIONS: usize = 100_000;
+
+/// Ledger order: flag time, then session id to break ties.
+type FlagKey = (DateTime<Utc>, String);
+
+/// The latest flagged result of a session, as exported.
+#[derive(Debug, Clone)]
+pub struct FlagRecord {
+    pub user_id: Option<String>,
+    pub score: i32,
+    /// Reason codes joined with `|`.
+    pub codes: String,
+    pub first_event: DateTime<Utc>,
+    pub last_event: DateTime<Utc>,
+    /// As stored, so it is pseudonymized when IP privacy is on.
+    pub ip: String,
+}
+
+#[derive(Default)]
+struct Ledger {
+    by_time: BTreeMap<FlagKey, FlagRecord>,
+    by_session: HashMap<String, DateTime<Utc>>,
+}
+
+/// In-memory record of each session's latest flag, ordered by flag time so exports can
+/// page through it with a cursor.
+#[derive(Default)]
+pub struct FlagLedger {
+    ledger: Mutex<Ledger>,
+}
+
+impl FlagLedger {
+    /// Replaces any earlier flag for the session.
+    pub fn record(&self, session_id: &str, flagged_at: DateTime<Utc>, record: FlagRecord) {
+        let mut ledger = self.ledger.lock().unwrap();
+        if let Some(previous) = ledger.by_session.insert(session_id.to_string(), flagged_at) {
+            ledger.by_time.remove(&(previous, session_id.to_string()));
+        }
+        ledger.by_time.insert((flagged_at, session_id.to_string()), record);
+
+        while ledger.by_time.len() > MAX_FLAGGED_SESSIONS {
+            if let Some(((_, session_id), _)) = ledger.by_time.pop_first() {
+                ledger.by_session.remove(&session_id);
+            }
+        }
+    }
+
+    /// Drops every flag attributed to the user, returning how many there were.
+    pub fn forget_user(&self, user_id: &str) -> usize {
+        let mut ledger = self.ledger.lock().unwrap();
+        let Ledger { by_time, by_session } = &mut *ledger;
+        let before = by_time.len();
+        by_time.retain(|(_, session_id), record| {
+            let keep = record.user_id.as_deref() != Some(user_id);
+            if !keep {
+                by_session.remove(session_id);
+            }
+            keep
+        });
+        before - by_time.len()
+    }
+
+    /// Up to `max_rows` matching flags after `from`, stopping at `until`.
+    fn page(&self, from: Bound<&FlagKey>, until: Bound<&FlagKey>, min_score: i32, max_rows: usize) -> Vec<(FlagKey, FlagRecord)> {
+        let ledger = self.ledger.lock().unwrap();
+        ledger
+            .by_time
+            .range::<FlagKey, _>((from, until))
+            .filter(|(_, record)| record.score >= min_score)
+            .take(max_rows)
+            .map(|(key, record)| (key.clone(), record.clone()))
+            .collect()
+    }
+
+    /// The key of the `limit`-th matching flag after `from`, when more follow it.
+    fn page_end(&self, from: Bound<&FlagKey>, until: Bound<&FlagKey>, min_score: i32, limit: usize) -> Option<FlagKey> {
+        let ledger = self.ledger.lock().unwrap();
+        let mut keys = ledger
+            .by_time
+            .range::<FlagKey, _>((from, until))
+            .filter(|(_, record)| record.score >= min_score)
+            .map(|(key, _)| key);
+        let last = keys.nth(limit - 1)?.clone();
+        keys.next().map(|_| last)
+    }
+}
+
+// --- CSV EXPORT HANDLER ---
+/// Rows rendered per body chunk, and per ledger lock.
+const EXPORT_CHUNK_ROWS: usize = 500;
+/// Rows per page unless the request asks for fewer.
+const MAX_EXPORT_ROWS: usize = 10_000;
+
+const CSV_HEADER: &str = "session_id,user_id,score,reason_codes,first_event,last_event,ip,flagged_at\n";
+
+#[derive(Debug, Deserialize)]
+pub struct FlagExportParams {
+    /// Flags at or after this time.
+    since: Option<DateTime<Utc>>,
+    /// Flags before this time.
+    until: Option<DateTime<Utc>>,
+    min_score: Option<i32>,
+    limit: Option<usize>,
+    /// Continuation token from the previous page's `Link` header.
+    cursor: Option<String>,
+}
+
+/// Hex of `<secs>.<nanos>:<session id>`, so it is URL-safe whatever the session id. The
+/// full precision matters: a truncated time would sort before the row it came from.
+pub fn encode_cursor((flagged_at, session_id): &FlagKey) -> String {
+    format!("{}.{}:{}", flagged_at.timestamp(), flagged_at.timestamp_subsec_nanos(), session_id)
+        .bytes()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub fn decode_cursor(cursor: &str) -> Option<FlagKey> {
+    let bytes = (0..cursor.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(cursor.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    let decoded = String::from_utf8(bytes).ok()?;
+    let (time, session_id) = decoded.split_once(':')?;
+    let (secs, nanos) = time.split_once('.')?;
+    Some((DateTime::from_timestamp(secs.parse().ok()?, nanos.parse().ok()?)?, session_id.to_string()))
+}
+
+/// Quotes fields containing separators, quotes, or line breaks, and defuses values a
+/// spreadsheet would run as a formula (session and user ids come from clients).
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn csv_row((flagged_at, session_id): &FlagKey, record: &FlagRecord) -> String {
+    let timestamp = |at: &DateTime<Utc>| at.to_rfc3339_opts(SecondsFormat::Millis, true);
+    format!(
+        "{},{},{},{},{},{},{},{}\n",
+        csv_field(session_id),
+        csv_field(record.user_id.as_deref().unwrap_or_default()),
+        record.score,
+        csv_field(&record.codes),
+        timestamp(&record.first_event),
+        timestamp(&record.last_event),
+        csv_field(&record.ip),
+        timestamp(flagged_at),
+    )
+}
+
+/// Streams flagged sessions as CSV, oldest flag first, `limit` rows per page. When more
+/// rows match, the response carries a `Link: <...>; rel="next"` header for the next page.
+/// The page's end is found up front so the header can be sent before the body; the rows
+/// themselves are read and rendered a chunk at a time.
+pub async fn export_handler(
+    State(state): State<Arc<AppState>>,
+    AppQuery(params): AppQuery<FlagExportParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let from = match &params.cursor {
+        Some(cursor) => Bound::Excluded(decode_cursor(cursor).ok_or(AppError::BadRequest("Invalid cursor".to_string()))?),
+        None => params.since.map_or(Bound::Unbounded, |since| Bound::Included((since, String::new()))),
+    };
+    let until = params.until.map(|until| (until, String::new()));
+    let until_bound = until.as_ref().map_or(Bound::Unbounded, Bound::Excluded);
+    let min_score = params.min_score.unwrap_or(i32::MIN);
+    let limit = params.limit.unwrap_or(MAX_EXPORT_ROWS).clamp(1, MAX_EXPORT_ROWS);
+
+    let page_end = state.flags.page_end(from.as_ref(), until_bound, min_score, limit);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/csv; charset=utf-8"));
+    if let Some(last) = &page_end {
+        let mut next = format!("/api/v1/flags/export?limit={}&cursor={}", limit, encode_cursor(last));
+        if let Some(until) = params.until {
+            next.push_str(&format!("&until={}", until.to_rfc3339_opts(SecondsFormat::Millis, true)));
+        }
+        if let Some(min_score) = params.min_score {
+            next.push_str(&format!("&min_score={}", min_score));
+        }
+        let link = HeaderValue::from_str(&format!("<{}>; rel=\"next\"", next)).expect("link header is ASCII");
+        headers.insert(header::LINK, link);
+    }
+
+    let end = match page_end {
+        Some(last) => Some((last, true)),
+        None => until.map(|until| (until, false)),
+    };
+    let header_chunk = stream::once(async { Ok::<_, Infallible>(CSV_HEADER.to_string()) });
+    let rows = stream::unfold(Some(from), move |from| {
+        let state = state.clone();
+        let end = end.clone();
+        async move {
+            let from = from?;
+            let until = match &end {
+                Some((key, true)) => Bound::Included(key),
+                Some((key, false)) => Bound::Excluded(key),
+                None => Bound::Unbounded,
+            };
+            let chunk = state.flags.page(from.as_ref(), until, min_score, EXPORT_CHUNK_ROWS);
+            let (last, _) = chunk.last()?;
+            let next = (chunk.len() == EXPORT_CHUNK_ROWS).then(|| Bound::Excluded(last.clone()));
+            let body: String = chunk.iter().map(|(key, record)| csv_row(key, record)).collect();
+            Some((Ok::<_, Infallible>(body), next))
+        }
+    });
+
+    Ok((headers, StreamBody::new(header_chunk.chain(rows))))
+}
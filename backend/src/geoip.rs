@@ -0,0 +1,86 @@
+use maxminddb::{geoip2, MaxMindDbError, Reader};
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::path::Path;
+
+// --- GEOIP LOOKUPS ---
+/// Location and network facts resolved for an event's IP.
+#[derive(Debug, Clone, Default)]
+pub struct GeoInfo {
+    /// ISO 3166-1 alpha-2 country code.
+    pub country: Option<String>,
+    /// Autonomous system number of the network the IP belongs to.
+    pub asn: Option<u32>,
+    pub asn_organization: Option<String>,
+    /// Whether the network is a hosting provider (datacenter) rather than an access
+    /// network. Only ISP and Enterprise databases say; `None` when it isn't known.
+    pub hosting: Option<bool>,
+}
+
+/// The ASN fields of a GeoLite2/GeoIP2 ASN or ISP record, which keep them at the top
+/// level, or an Enterprise record, which keeps them under `traits`. Decoding
+/// one shape covers all three databases.
+#[derive(Debug, Default, Deserialize)]
+struct AsnRecord<'a> {
+    autonomous_system_number: Option<u32>,
+    autonomous_system_organization: Option<&'a str>,
+    #[serde(borrow, default)]
+    traits: AsnTraits<'a>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct AsnTraits<'a> {
+    autonomous_system_number: Option<u32>,
+    autonomous_system_organization: Option<&'a str>,
+    is_hosting_provider: Option<bool>,
+    /// MaxMind's connection category, such as `hosting`, `residential`, or `cellular`.
+    user_type: Option<&'a str>,
+}
+
+/// MaxMind databases, loaded fully into memory at startup: a City database for the
+/// country, an ASN (or ISP/Enterprise) database for the network, or both.
+pub struct GeoIp {
+    city: Option<Reader<Vec<u8>>>,
+    asn: Option<Reader<Vec<u8>>>,
+}
+
+impl GeoIp {
+    pub fn open(city_path: Option<&Path>, asn_path: Option<&Path>) -> Result<Self, MaxMindDbError> {
+        Ok(Self {
+            city: city_path.map(Reader::open_readfile).transpose()?,
+            asn: asn_path.map(Reader::open_readfile).transpose()?,
+        })
+    }
+
+    /// Returns `None` for unparseable IPs, addresses missing from every database, and
+    /// lookup errors alike; rules treat all of them as "no geo data".
+    pub fn lookup(&self, ip: &str) -> Option<GeoInfo> {
+        let ip: IpAddr = ip.parse().ok()?;
+        let city = self.city.as_ref().and_then(|reader| {
+            let city: geoip2::City = reader.lookup(ip).ok()?.decode().ok()??;
+            Some(city.country.iso_code.map(str::to_string))
+        });
+        let asn = self.asn.as_ref().and_then(|reader| {
+            let record: AsnRecord = reader.lookup(ip).ok()?.decode().ok()??;
+            Some(record)
+        });
+        if city.is_none() && asn.is_none() {
+            return None;
+        }
+
+        let asn = asn.unwrap_or_default();
+        let hosting = match (asn.traits.user_type, asn.traits.is_hosting_provider) {
+            (None, None) => None,
+            (user_type, hosting) => Some(user_type == Some("hosting") || hosting == Some(true)),
+        };
+        Some(GeoInfo {
+            country: city.flatten(),
+            asn: asn.autonomous_system_number.or(asn.traits.autonomous_system_number),
+            asn_organization: asn
+                .autonomous_system_organization
+                .or(asn.traits.autonomous_system_organization)
+                .map(str::to_string),
+            hosting,
+        })
+    }
+}
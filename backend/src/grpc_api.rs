@@ -0,0 +1,319 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::sync::Semaphore;
+use tonic::body::BoxBody;
+use tonic::codec::ProstCodec;
+use tonic::codegen::{http, BoxFuture, Service};
+use tonic::server::{ClientStreamingService, Grpc, NamedService, UnaryService};
+use tonic::{Request, Response, Status, Streaming};
+use tracing::{error, info, warn};
+
+use crate::client_ip;
+use crate::config::GrpcConfig;
+use crate::protobuf::{ProtoFraudCheckResult, ProtoUserEvent};
+use crate::usage::UsageCounts;
+use crate::{AnalyzeResponse, AppError, AppState, UserEvent};
+
+// --- GRPC API ---
+/// Messages from `proto/fraud_events.proto` beyond the ones the HTTP endpoint shares,
+/// written out by hand so the build doesn't need `protoc`. Keep the tags in sync.
+#[derive(Clone, PartialEq, prost::Message)]
+struct BatchResult {
+    #[prost(message, optional, tag = "1")]
+    result: Option<ProtoFraudCheckResult>,
+    #[prost(string, tag = "2")]
+    error: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct AnalyzeBatchResponse {
+    #[prost(message, repeated, tag = "1")]
+    results: Vec<BatchResult>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct GetSessionScoreRequest {
+    #[prost(string, tag = "1")]
+    session_id: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct SessionScore {
+    #[prost(string, tag = "1")]
+    session_id: String,
+    #[prost(string, repeated, tag = "2")]
+    user_ids: Vec<String>,
+    #[prost(int32, tag = "3")]
+    latest_score: i32,
+    #[prost(bool, tag = "4")]
+    flagged: bool,
+    #[prost(string, repeated, tag = "5")]
+    reason_codes: Vec<String>,
+    #[prost(string, tag = "6")]
+    first_event: String,
+    #[prost(string, tag = "7")]
+    last_event: String,
+    #[prost(string, tag = "8")]
+    last_scored_at: String,
+    #[prost(int32, optional, tag = "9")]
+    session_reputation: Option<i32>,
+}
+
+const SERVICE_NAME: &str = "fraud.events.v1.FraudDetection";
+const ANALYZE_EVENT_PATH: &str = "/fraud.events.v1.FraudDetection/AnalyzeEvent";
+const ANALYZE_BATCH_PATH: &str = "/fraud.events.v1.FraudDetection/AnalyzeBatch";
+const GET_SESSION_SCORE_PATH: &str = "/fraud.events.v1.FraudDetection/GetSessionScore";
+
+/// Events one `AnalyzeBatch` call may stream.
+const MAX_BATCH_EVENTS: usize = 1_000;
+
+/// Validation failures are the caller's to fix; store outages are worth retrying.
+impl From<AppError> for Status {
+    fn from(e: AppError) -> Self {
+        match &e {
+            AppError::BadRequest(message) | AppError::Validation(message) => Status::invalid_argument(message),
+            AppError::PayloadTooLarge(message) => Status::resource_exhausted(message),
+            AppError::NotFound(message) => Status::not_found(message),
+            AppError::Unauthorized(message) => Status::unauthenticated(message),
+            AppError::Overloaded(message) => Status::resource_exhausted(message),
+            AppError::Shed(message) | AppError::Unavailable(message) => Status::unavailable(message),
+            AppError::Timeout(message) => Status::deadline_exceeded(message),
+            AppError::Store(store) => {
+                warn!("Event store error: {}", store);
+                Status::unavailable(e.to_string())
+            }
+            AppError::Internal(detail) => {
+                error!("Internal error: {}", detail);
+                Status::internal(e.to_string())
+            }
+        }
+    }
+}
+
+fn overloaded() -> Status {
+    Status::resource_exhausted("Too many requests in flight")
+}
+
+fn timestamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+/// `fraud.events.v1.FraudDetection`, scoring through the same pipeline and state as
+/// the HTTP endpoints. Calls beyond `max_concurrent_requests` in flight are turned
+/// away with `RESOURCE_EXHAUSTED` rather than queued.
+#[derive(Clone)]
+pub struct FraudDetectionService {
+    state: Arc<AppState>,
+    permits: Arc<Semaphore>,
+}
+
+impl FraudDetectionService {
+    fn new(state: Arc<AppState>, config: &GrpcConfig) -> Self {
+        Self {
+            state,
+            permits: Arc::new(Semaphore::new(config.max_concurrent_requests)),
+        }
+    }
+
+    /// A slot for one call, or `None` when the limit is reached.
+    fn admit(&self) -> Option<tokio::sync::OwnedSemaphorePermit> {
+        let permit = self.permits.clone().try_acquire_owned().ok();
+        if permit.is_none() {
+            metrics::counter!("grpc_requests_rejected_total").increment(1);
+        }
+        permit
+    }
+
+    async fn score(
+        &self,
+        event: ProtoUserEvent,
+        derived_ip: Option<IpAddr>,
+        usage_key: &str,
+    ) -> Result<ProtoFraudCheckResult, Status> {
+        let received = UsageCounts {
+            bytes: prost::Message::encoded_len(&event) as u64,
+            ..UsageCounts::default()
+        };
+        self.state.usage.record(usage_key, received, self.state.clock.now());
+        let _admitted = self.state.admission.admit()?;
+        let event = UserEvent::try_from(event)?;
+        let result = crate::score_event(&self.state, event, derived_ip, usage_key).await?;
+        Ok(AnalyzeResponse::new(result, self.state.service.response_verbosity).into())
+    }
+
+    /// The caller's IP under `CLIENT_IP_SOURCE`, from the peer address and the
+    /// forwarding headers a proxy passed on as metadata.
+    fn client_ip<T>(&self, request: &Request<T>) -> Option<IpAddr> {
+        let config = self.state.service.client_ip.as_ref()?;
+        let values = |name: &'static str| {
+            request
+                .metadata()
+                .get_all(name)
+                .into_iter()
+                .filter_map(|value| value.to_str().ok())
+        };
+        let peer = request.remote_addr().map(|peer| peer.ip());
+        client_ip::derive(config, peer, values("forwarded"), values("x-forwarded-for"))
+    }
+
+    /// The key the call is billed to, from the `USAGE_KEY_HEADER` metadata.
+    fn usage_key<T>(&self, request: &Request<T>) -> String {
+        let value = request
+            .metadata()
+            .get(self.state.usage.key_header().as_str())
+            .and_then(|value| value.to_str().ok());
+        self.state.usage.key(value).to_string()
+    }
+
+    async fn analyze_event(&self, request: Request<ProtoUserEvent>) -> Result<Response<ProtoFraudCheckResult>, Status> {
+        let _permit = self.admit().ok_or_else(overloaded)?;
+        let derived_ip = self.client_ip(&request);
+        let usage_key = self.usage_key(&request);
+        self.score(request.into_inner(), derived_ip, &usage_key).await.map(Response::new)
+    }
+
+    /// Scores events as they arrive. A rejected event gets an `error` entry and the rest
+    /// are still scored; a store outage ends the call, since later events would fail too.
+    async fn analyze_batch(
+        &self,
+        request: Request<Streaming<ProtoUserEvent>>,
+    ) -> Result<Response<AnalyzeBatchResponse>, Status> {
+        let _permit = self.admit().ok_or_else(overloaded)?;
+        let derived_ip = self.client_ip(&request);
+        let usage_key = self.usage_key(&request);
+        let mut events = request.into_inner();
+        let mut results = Vec::new();
+        while let Some(event) = events.message().await? {
+            if results.len() == MAX_BATCH_EVENTS {
+                return Err(Status::resource_exhausted(format!(
+                    "AnalyzeBatch takes at most {} events per call; the first {} were scored",
+                    MAX_BATCH_EVENTS, MAX_BATCH_EVENTS
+                )));
+            }
+            let result = match self.score(event, derived_ip, &usage_key).await {
+                Ok(result) => BatchResult {
+                    result: Some(result),
+                    error: String::new(),
+                },
+                Err(status) if status.code() == tonic::Code::InvalidArgument => BatchResult {
+                    result: None,
+                    error: status.message().to_string(),
+                },
+                Err(status) => return Err(status),
+            };
+            results.push(result);
+        }
+        Ok(Response::new(AnalyzeBatchResponse { results }))
+    }
+
+    /// From this instance's search index, so sessions it didn't score, or that outlived
+    /// `REDIS_SESSION_TTL_SECS`, are `NOT_FOUND`.
+    async fn get_session_score(&self, request: Request<GetSessionScoreRequest>) -> Result<Response<SessionScore>, Status> {
+        let session_id = request.into_inner().session_id;
+        let session = self
+            .state
+            .search
+            .session(&session_id)
+            .ok_or_else(|| Status::not_found(format!("No scored session `{}`", session_id)))?;
+        let reputation = self.state.prior_reputation(&session_id);
+
+        Ok(Response::new(SessionScore {
+            session_id: session.session_id,
+            user_ids: session.user_ids,
+            latest_score: session.latest_score,
+            flagged: session.flagged,
+            reason_codes: session.reason_codes,
+            first_event: timestamp(session.first_event),
+            last_event: timestamp(session.last_event),
+            last_scored_at: timestamp(session.last_scored_at),
+            session_reputation: reputation.map(|reputation| reputation.round() as i32),
+        }))
+    }
+}
+
+// Adapters from each RPC to tonic's per-method service traits, as generated code would have.
+struct AnalyzeEvent(FraudDetectionService);
+
+impl UnaryService<ProtoUserEvent> for AnalyzeEvent {
+    type Response = ProtoFraudCheckResult;
+    type Future = BoxFuture<Response<Self::Response>, Status>;
+
+    fn call(&mut self, request: Request<ProtoUserEvent>) -> Self::Future {
+        let service = self.0.clone();
+        Box::pin(async move { service.analyze_event(request).await })
+    }
+}
+
+struct AnalyzeBatch(FraudDetectionService);
+
+impl ClientStreamingService<ProtoUserEvent> for AnalyzeBatch {
+    type Response = AnalyzeBatchResponse;
+    type Future = BoxFuture<Response<Self::Response>, Status>;
+
+    fn call(&mut self, request: Request<Streaming<ProtoUserEvent>>) -> Self::Future {
+        let service = self.0.clone();
+        Box::pin(async move { service.analyze_batch(request).await })
+    }
+}
+
+struct GetSessionScore(FraudDetectionService);
+
+impl UnaryService<GetSessionScoreRequest> for GetSessionScore {
+    type Response = SessionScore;
+    type Future = BoxFuture<Response<Self::Response>, Status>;
+
+    fn call(&mut self, request: Request<GetSessionScoreRequest>) -> Self::Future {
+        let service = self.0.clone();
+        Box::pin(async move { service.get_session_score(request).await })
+    }
+}
+
+impl Service<http::Request<BoxBody>> for FraudDetectionService {
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<BoxBody>) -> Self::Future {
+        let service = self.clone();
+        Box::pin(async move {
+            let response = match request.uri().path() {
+                ANALYZE_EVENT_PATH => Grpc::new(ProstCodec::default()).unary(AnalyzeEvent(service), request).await,
+                ANALYZE_BATCH_PATH => {
+                    Grpc::new(ProstCodec::default())
+                        .client_streaming(AnalyzeBatch(service), request)
+                        .await
+                }
+                GET_SESSION_SCORE_PATH => {
+                    Grpc::new(ProstCodec::default())
+                        .unary(GetSessionScore(service), request)
+                        .await
+                }
+                _ => Status::unimplemented("Unknown method").into_http(),
+            };
+            Ok(response)
+        })
+    }
+}
+
+impl NamedService for FraudDetectionService {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+/// Serves the gRPC API until `shutdown` resolves, then lets in-flight calls finish.
+pub async fn serve(state: Arc<AppState>, config: GrpcConfig, shutdown: impl Future<Output = ()>) {
+    let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
+    info!("Starting gRPC server, listening on {}", addr);
+    tonic::transport::Server::builder()
+        .add_service(FraudDetectionService::new(state, &config))
+        .serve_with_shutdown(addr, shutdown)
+        .await
+        .unwrap_or_else(|e| panic!("gRPC server failed: {}", e));
+}
@@ -0,0 +1,83 @@
+use ipnet::Ipv6Net;
+use std::borrow::Cow;
+use std::net::IpAddr;
+
+// --- IP ADDRESSES ---
+/// Parses an address the way events, the blacklist, and the allowlist all store them, so
+/// string comparisons between them hold: IPv4-mapped IPv6 (`::ffff:1.1.1.1`) becomes
+/// IPv4, and an IPv6 zone id (`fe80::1%eth0`) is dropped, since it names an interface on
+/// the sender's host rather than anything about the address. `IpAddr` itself takes care
+/// of hex case and `::` compression when the result is formatted.
+pub fn parse(raw: &str) -> Option<IpAddr> {
+    let raw = raw.trim();
+    let raw = match raw.split_once('%') {
+        Some((addr, _zone)) if addr.contains(':') => addr,
+        _ => raw,
+    };
+    raw.parse::<IpAddr>().ok().map(|ip| ip.to_canonical())
+}
+
+/// What per-IP counters count `ip` as: the address itself, or, with `ipv6_prefix` set,
+/// its IPv6 network of that length, since an attacker holding one /64 can rotate through
+/// its addresses for free. IPv4 and hashed or truncated IPs are counted as they are.
+pub fn counter_key(ip: &str, ipv6_prefix: u8) -> Cow<'_, str> {
+    if ipv6_prefix == 0 {
+        return Cow::Borrowed(ip);
+    }
+    match ip.parse::<IpAddr>() {
+        Ok(IpAddr::V6(addr)) => match Ipv6Net::new(addr, ipv6_prefix) {
+            Ok(network) => Cow::Owned(network.trunc().to_string()),
+            Err(_) => Cow::Borrowed(ip),
+        },
+        _ => Cow::Borrowed(ip),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canonical(raw: &str) -> Option<String> {
+        parse(raw).map(|ip| ip.to_string())
+    }
+
+    #[test]
+    fn mapped_ipv6_becomes_ipv4() {
+        assert_eq!(canonical("::ffff:1.1.1.1").as_deref(), Some("1.1.1.1"));
+        assert_eq!(canonical("::FFFF:101:101").as_deref(), Some("1.1.1.1"));
+        assert_eq!(canonical("0:0:0:0:0:ffff:0101:0101").as_deref(), Some("1.1.1.1"));
+        // IPv4-compatible (deprecated) addresses are not mapped ones.
+        assert_eq!(canonical("::1.1.1.1").as_deref(), Some("::101:101"));
+    }
+
+    #[test]
+    fn every_spelling_of_an_ipv6_address_is_one_string() {
+        for spelling in ["2001:db8::1", "2001:DB8::1", "2001:0db8:0000:0000:0000:0000:0000:0001", "2001:db8:0:0::0:1", " 2001:db8::1 "] {
+            assert_eq!(canonical(spelling).as_deref(), Some("2001:db8::1"), "{:?}", spelling);
+        }
+    }
+
+    #[test]
+    fn zone_ids_are_dropped_from_ipv6_only() {
+        assert_eq!(canonical("fe80::1%eth0").as_deref(), Some("fe80::1"));
+        assert_eq!(canonical("192.0.2.1%eth0"), None);
+    }
+
+    #[test]
+    fn non_addresses_are_rejected() {
+        for raw in ["", "unknown", "1.1.1", "1.1.1.256", "2001:db8::1::2", "[2001:db8::1]", "192.0.2.1:80"] {
+            assert_eq!(parse(raw), None, "{:?}", raw);
+        }
+    }
+
+    #[test]
+    fn ipv6_counters_group_by_network_when_a_prefix_is_set() {
+        assert_eq!(counter_key("2001:db8:1:2:aaaa::1", 0), "2001:db8:1:2:aaaa::1");
+        assert_eq!(counter_key("2001:db8:1:2:aaaa::1", 64), "2001:db8:1:2::/64");
+        assert_eq!(counter_key("2001:db8:1:2:bbbb::9", 64), "2001:db8:1:2::/64");
+        assert_eq!(counter_key("2001:db8:1:2:aaaa::1", 48), "2001:db8:1::/48");
+        // IPv4 and values that are no longer addresses are counted as they are.
+        assert_eq!(counter_key("192.0.2.1", 64), "192.0.2.1");
+        assert_eq!(counter_key("a1b2c3", 64), "a1b2c3");
+    }
+}
@@ -0,0 +1,176 @@
+use serde::Serialize;
+use std::collections::HashSet;
+use std::f64::consts::LN_2;
+use std::net::{IpAddr, Ipv6Addr};
+
+/// Room a set is sized for beyond its entries, so small merges don't rebuild it.
+const HEADROOM_DIVISOR: usize = 4;
+/// Smallest capacity a set is sized for, so an empty list can take a few bans' worth of
+/// additions without a rebuild.
+const MIN_CAPACITY: usize = 1024;
+
+// --- IP SETS ---
+/// An IP as a set key: IPv6 addresses as they are, IPv4 ones IPv4-mapped, so one integer
+/// type holds both without collisions.
+pub fn key(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped().into(),
+        IpAddr::V6(ip) => ip.into(),
+    }
+}
+
+/// The IP a key was made from, IPv4 again for IPv4-mapped keys.
+pub fn addr(key: u128) -> IpAddr {
+    Ipv6Addr::from(key).to_canonical()
+}
+
+fn is_ipv4(key: u128) -> bool {
+    key >> 32 == 0xffff
+}
+
+/// Bits set by `hashes` hash functions per key. Answers "maybe" for every key inserted
+/// and "no" for most others, at a fraction of an exact set's memory.
+#[derive(Clone)]
+struct Bloom {
+    bits: Vec<u64>,
+    hashes: u32,
+}
+
+/// The splitmix64 finalizer: cheap, and mixes every input bit into every output bit.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+impl Bloom {
+    /// Sized so that `capacity` keys give false positives at about `false_positive_rate`.
+    fn sized(capacity: usize, false_positive_rate: f64) -> Self {
+        let n = capacity.max(1) as f64;
+        let bits = (-(n * false_positive_rate.ln()) / (LN_2 * LN_2)).ceil().max(64.0);
+        let hashes = (bits / n * LN_2).round().clamp(1.0, 16.0) as u32;
+        Self {
+            bits: vec![0; (bits as usize).div_ceil(64)],
+            hashes,
+        }
+    }
+
+    /// Bit positions for `key`, by double hashing two halves of one mixed hash.
+    fn positions(&self, key: u128) -> impl Iterator<Item = usize> {
+        let h1 = mix(key as u64 ^ mix((key >> 64) as u64));
+        let h2 = mix(h1 ^ 0x9e37_79b9_7f4a_7c15) | 1;
+        let width = self.bits.len() as u64 * 64;
+        (0..u64::from(self.hashes)).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % width) as usize)
+    }
+
+    fn insert(&mut self, key: u128) {
+        let positions: Vec<usize> = self.positions(key).collect();
+        for position in positions {
+            self.bits[position / 64] |= 1 << (position % 64);
+        }
+    }
+
+    fn may_contain(&self, key: u128) -> bool {
+        self.positions(key)
+            .all(|position| self.bits[position / 64] & (1 << (position % 64)) != 0)
+    }
+}
+
+/// What an `IpSet` takes in memory, estimated from its capacities.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IpSetMemory {
+    pub entries: usize,
+    /// The exact set and the filter together.
+    pub bytes: usize,
+    pub bloom_bytes: usize,
+    /// What the same entries took as a set of strings, for comparison.
+    pub string_set_bytes: usize,
+}
+
+/// A set of IPs as integer keys, fronted by a bloom filter so most misses, which are
+/// nearly every lookup, never touch the exact set. Sized when built; inserting past
+/// its capacity is the owner's cue to build a bigger one.
+#[derive(Clone)]
+pub struct IpSet {
+    exact: HashSet<u128>,
+    bloom: Bloom,
+    capacity: usize,
+    false_positive_rate: f64,
+    ipv6_entries: usize,
+}
+
+/// Buckets a `HashSet` allocates for `capacity` entries: a power of two, at most 7/8 full.
+fn buckets(capacity: usize) -> usize {
+    if capacity == 0 {
+        return 0;
+    }
+    (capacity * 8 / 7).next_power_of_two()
+}
+
+impl IpSet {
+    /// Builds the set and its filter. Costs a pass over every entry, so callers build
+    /// off the lookup path and swap the result in.
+    pub fn build(exact: HashSet<u128>, false_positive_rate: f64) -> Self {
+        let capacity = (exact.len() + exact.len() / HEADROOM_DIVISOR).max(MIN_CAPACITY);
+        let mut bloom = Bloom::sized(capacity, false_positive_rate);
+        for key in &exact {
+            bloom.insert(*key);
+        }
+        let ipv6_entries = exact.iter().filter(|key| !is_ipv4(**key)).count();
+        Self {
+            exact,
+            bloom,
+            capacity,
+            false_positive_rate,
+            ipv6_entries,
+        }
+    }
+
+    pub fn contains(&self, key: u128) -> bool {
+        self.bloom.may_contain(key) && self.exact.contains(&key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.exact.len()
+    }
+
+    /// Whether `additional` more entries stay within what the filter was sized for.
+    pub fn has_room(&self, additional: usize) -> bool {
+        self.exact.len() + additional <= self.capacity
+    }
+
+    /// Adds `key` in place, returning whether it was new. Past the capacity the filter's
+    /// false-positive rate climbs, so check `has_room` first.
+    pub fn insert(&mut self, key: u128) -> bool {
+        let added = self.exact.insert(key);
+        if added {
+            self.bloom.insert(key);
+            self.ipv6_entries += usize::from(!is_ipv4(key));
+        }
+        added
+    }
+
+    pub fn false_positive_rate(&self) -> f64 {
+        self.false_positive_rate
+    }
+
+    pub fn keys(&self) -> &HashSet<u128> {
+        &self.exact
+    }
+
+    pub fn memory(&self) -> IpSetMemory {
+        let buckets = buckets(self.exact.capacity());
+        let bloom_bytes = self.bloom.bits.len() * std::mem::size_of::<u64>();
+        // A `String` is a pointer, length, and capacity inline plus its bytes on the
+        // heap, where the allocator hands out 16 bytes for most IPv4 addresses and 40
+        // for most IPv6 ones.
+        let heap = (self.exact.len() - self.ipv6_entries) * 16 + self.ipv6_entries * 40;
+        IpSetMemory {
+            entries: self.exact.len(),
+            bytes: buckets * (std::mem::size_of::<u128>() + 1) + bloom_bytes,
+            bloom_bytes,
+            string_set_bytes: buckets * (std::mem::size_of::<String>() + 1) + heap,
+        }
+    }
+}
@@ -0,0 +1,1848 @@
+use arc_swap::ArcSwap;
+use axum::{
+    extract::{ConnectInfo, DefaultBodyLimit, State},
+    http::{header, HeaderMap, Method},
+    middleware,
+    response::Response,
+    routing::{delete, get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tower_http::services::{ServeDir, ServeFile};
+use tracing::{info, warn};
+
+mod activity;
+mod admission;
+mod alerts;
+mod api_v2;
+mod allowlist;
+mod audit;
+mod blacklist;
+mod breaker;
+mod client_ip;
+mod clock;
+mod composite;
+mod config;
+mod degradation;
+mod email;
+mod entity_reputation;
+mod error;
+mod event_schema;
+mod export;
+mod features;
+mod flags;
+mod geoip;
+mod grpc_api;
+mod ip;
+mod ip_set;
+mod linked_sessions;
+mod log_sampling;
+mod metadata_limits;
+mod model;
+mod model_grpc;
+mod msgpack;
+mod offenders;
+mod payments;
+mod privacy;
+mod protobuf;
+mod rates;
+#[cfg(feature = "redis")]
+mod redis_store;
+mod reputation;
+mod request_id;
+mod response_cache;
+mod rollout;
+mod rules;
+mod sampling;
+mod scripting;
+mod search;
+mod session_cache;
+mod session_reputation;
+mod simulate;
+mod sources;
+mod stats;
+mod store;
+mod stream;
+mod timeout;
+mod usage;
+mod users;
+mod webhooks;
+#[cfg(feature = "redis")]
+mod write_behind;
+
+use activity::{ActivityProfile, ActivityStore, MemoryActivityStore};
+use admission::{Admission, Budget};
+use alerts::Alerts;
+use allowlist::{Allowlist, AllowlistMatch};
+use audit::AuditLog;
+use blacklist::Blacklist;
+pub use clock::{Clock, ManualClock, SystemClock};
+use config::{ClientIpPolicy, Decision, MetadataSchemaMode, Verbosity};
+use degradation::{DependencyHealth, Unavailable};
+pub use config::{ScoringConfig, ServiceConfig};
+use error::AppQuery;
+pub use error::AppError;
+use metrics::counter;
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use email::{DisposableDomains, EmailAddress};
+use entity_reputation::{Entity, EntityReputationStore, EntityScores, MemoryEntityReputationStore};
+use event_schema::EventSchema;
+use flags::{FlagLedger, FlagRecord};
+use geoip::{GeoInfo, GeoIp};
+use linked_sessions::LinkedSessionTracker;
+use log_sampling::LogSampler;
+use model::ModelScorer;
+use msgpack::AppBody;
+use offenders::OffenderTracker;
+use payments::PaymentTracker;
+use protobuf::EventBody;
+use rates::{RateTracker, Rates};
+use session_cache::{SessionCache, SessionContext};
+use sources::SuspiciousSources;
+use reputation::ReputationClient;
+use request_id::RequestIds;
+use response_cache::ResponseCache;
+use rollout::RolloutTracker;
+use sampling::StorageSampler;
+use rules::{Analysis, Dependency, RolloutOutcome, RuleContext, RuleEngine, RuleHit, RuleInfo, RuleOutcome};
+use search::SearchIndex;
+use session_reputation::SessionReputationTracker;
+use stats::ScoringStats;
+use store::{EventStore, MemoryEventStore, SessionHistory, StoreError};
+use usage::{Usage, UsageCounts, UsageStore};
+use webhooks::Webhooks;
+
+/// How often expired per-user state is swept.
+const USER_STATE_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+/// The metadata key naming an event, for amendments to refer to. Always stored, whatever
+/// `RETAINED_METADATA_KEYS` says, or nothing could be amended.
+const EVENT_ID_KEY: &str = "eventId";
+
+// --- SHARED APPLICATION STATE ---
+/// The scoring config and the rule engine built from it, swapped together on reload.
+struct Scoring {
+    config: Arc<ScoringConfig>,
+    engine: Arc<RuleEngine>,
+}
+
+#[derive(Clone)]
+pub struct AppState {
+    service: Arc<ServiceConfig>,
+    event_store: Arc<dyn EventStore>,
+    activity: Arc<dyn ActivityStore>,
+    entity_reputations: Arc<dyn EntityReputationStore>,
+    ip_blacklist: Arc<Blacklist>,
+    suspicious_sources: Arc<SuspiciousSources>,
+    allowlist: Arc<Allowlist>,
+    disposable_domains: Arc<DisposableDomains>,
+    event_schema: Option<Arc<EventSchema>>,
+    /// Replaced as a whole by `reload_scoring`. Reads never wait on a reload, and a
+    /// reload never waits on reads.
+    scoring: Arc<ArcSwap<Scoring>>,
+    reputation: Option<Arc<ReputationClient>>,
+    offenders: Arc<OffenderTracker>,
+    linked_sessions: Arc<LinkedSessionTracker>,
+    /// Sessions per device fingerprint, for the `device_fingerprint` rule.
+    fingerprints: Arc<LinkedSessionTracker>,
+    payments: Arc<PaymentTracker>,
+    /// Events per session, IP, and user over the `high_frequency` window.
+    rates: Arc<RateTracker>,
+    session_reputation: Arc<SessionReputationTracker>,
+    /// Which side of each rule rollout sessions fell on.
+    rollouts: Arc<RolloutTracker>,
+    flags: Arc<FlagLedger>,
+    stats: Arc<ScoringStats>,
+    search: Arc<SearchIndex>,
+    geoip: Option<Arc<GeoIp>>,
+    session_cache: Option<Arc<SessionCache>>,
+    response_cache: Option<Arc<ResponseCache>>,
+    model: Option<Arc<ModelScorer>>,
+    audit: Option<Arc<AuditLog>>,
+    webhooks: Option<Arc<Webhooks>>,
+    alerts: Arc<Alerts>,
+    usage: Arc<Usage>,
+    health: Arc<DependencyHealth>,
+    admission: Arc<Admission>,
+    request_admission: Option<Arc<Admission>>,
+    clock: Arc<dyn Clock>,
+    request_ids: Arc<RequestIds>,
+    analysis_logs: Arc<LogSampler>,
+    sampler: Option<Arc<StorageSampler>>,
+    metrics: PrometheusHandle,
+}
+
+impl AppState {
+    /// Loads every data file and connects every backend the service config names,
+    /// failing on the first that can't be, since the service can't start without them.
+    pub async fn new(service: ServiceConfig, scoring: ScoringConfig, metrics: PrometheusHandle) -> Result<Self, String> {
+        let false_positive_rate = service.ip_list_false_positive_rate;
+        let ip_blacklist = match &service.blacklist_path {
+            Some(path) => Blacklist::load_file(path, false_positive_rate)
+                .map_err(|e| format!("Failed to load blacklist {}: {}", path.display(), e))?,
+            None => {
+                let defaults = [[1, 1, 1, 1], [2, 2, 2, 2]].map(|octets| ip_set::key(IpAddr::from(octets)));
+                Blacklist::new(defaults, false_positive_rate)
+            }
+        };
+        let ip_blacklist = match &service.blacklist_bans_path {
+            Some(path) => ip_blacklist
+                .with_bans_file(path)
+                .map_err(|e| format!("Failed to load temporary bans {}: {}", path.display(), e))?,
+            None => ip_blacklist,
+        };
+
+        let suspicious_sources = SuspiciousSources::load(&service.source_lists, false_positive_rate)
+            .map_err(|e| format!("Failed to load suspicious-source list {}", e))?;
+
+        let reputation = match service.ip_reputation.clone() {
+            Some(config) => {
+                info!("IP reputation lookups enabled against {}", config.url);
+                let client = ReputationClient::new(config)
+                    .map_err(|e| format!("Failed to build IP reputation client: {}", e))?;
+                Some(Arc::new(client))
+            }
+            None => None,
+        };
+
+        let disposable_domains = match &service.disposable_domains_path {
+            Some(path) => DisposableDomains::load_file(path)
+                .map_err(|e| format!("Failed to load disposable domains {}: {}", path.display(), e))?,
+            None => DisposableDomains::bundled(),
+        };
+
+        let event_schema = match &service.event_schema_path {
+            Some(path) => {
+                let schema = EventSchema::load_file(path)
+                    .map_err(|e| format!("Failed to load event schema {}: {}", path.display(), e))?;
+                info!("Validating JSON events against {}", path.display());
+                Some(Arc::new(schema))
+            }
+            None => None,
+        };
+
+        let city_db = service.geoip_city_db_path.as_deref();
+        let asn_db = service.geoip_asn_db_path.as_deref();
+        let geoip = if city_db.is_some() || asn_db.is_some() {
+            let geoip = GeoIp::open(city_db, asn_db).map_err(|e| format!("Failed to open GeoIP database: {}", e))?;
+            for path in city_db.into_iter().chain(asn_db) {
+                info!("GeoIP lookups enabled from {}", path.display());
+            }
+            Some(Arc::new(geoip))
+        } else {
+            None
+        };
+
+        // Unlike the other data files, a bad model only costs the model's contribution.
+        let model = service.model.as_ref().and_then(|config| match ModelScorer::load(config) {
+            Ok(model) => {
+                info!("Model scoring enabled from {}", config.source);
+                Some(Arc::new(model))
+            }
+            Err(e) => {
+                warn!("Model scoring disabled, failed to load {}: {}", config.source, e);
+                None
+            }
+        });
+
+        let audit = match &service.audit {
+            Some(config) => {
+                let audit = AuditLog::start(config)
+                    .map_err(|e| format!("Failed to open audit log {}: {}", config.path.display(), e))?;
+                info!("Auditing decisions to {}", config.path.display());
+                Some(Arc::new(audit))
+            }
+            None => None,
+        };
+
+        let webhooks = match service.webhook.clone() {
+            Some(config) => {
+                info!("Notifying {} of flagged events", config.url);
+                let webhooks = Webhooks::new(config).map_err(|e| format!("Failed to build webhook client: {}", e))?;
+                Some(Arc::new(webhooks))
+            }
+            None => None,
+        };
+
+        if service.max_events_per_session < scoring.history_needed() {
+            return Err(format!(
+                "MAX_EVENTS_PER_SESSION is {} but the scoring config's windows need {} events per session",
+                service.max_events_per_session,
+                scoring.history_needed()
+            ));
+        }
+
+        let dormancy = scoring.activity_profile.dormancy().to_std().unwrap_or_default();
+        if scoring.activity_profile.dormant_points != 0 && service.activity_profile_ttl <= dormancy {
+            warn!("ACTIVITY_PROFILE_TTL_SECS is shorter than the dormancy period, so dormant accounts are never scored");
+        }
+
+        let engine = RuleEngine::new(&scoring).map_err(|e| format!("Invalid scoring config: {}", e))?;
+        if !scoring.scripted_rules.is_empty() {
+            info!("Loaded {} scripted rules", scoring.scripted_rules.len());
+        }
+
+        Ok(Self {
+            event_store: connect_event_store(&service).await?,
+            activity: connect_activity_store(&service).await?,
+            entity_reputations: connect_entity_reputation_store(&service).await?,
+            usage: Arc::new(Usage::new(service.usage.clone(), connect_usage_store(&service).await?)),
+            health: Arc::new(DependencyHealth::new(service.degradation.clone())),
+            admission: Arc::new(Admission::new(service.max_in_flight_analyses, Budget::Analyses)),
+            request_admission: service
+                .max_in_flight_requests
+                .map(|limit| Arc::new(Admission::new(limit, Budget::Requests))),
+            request_ids: Arc::new(RequestIds::new(service.random_seed)),
+            analysis_logs: Arc::new(LogSampler::new(service.log_sample_rate)),
+            sampler: service.storage_sampling.clone().map(|config| Arc::new(StorageSampler::new(config))),
+            session_cache: service.session_cache_ttl.map(|ttl| Arc::new(SessionCache::new(ttl))),
+            response_cache: service.response_cache.as_ref().map(|config| Arc::new(ResponseCache::new(config))),
+            service: Arc::new(service),
+            ip_blacklist: Arc::new(ip_blacklist),
+            suspicious_sources: Arc::new(suspicious_sources),
+            allowlist: Arc::new(Allowlist::new(&scoring.allowlist)),
+            disposable_domains: Arc::new(disposable_domains),
+            event_schema,
+            scoring: Arc::new(ArcSwap::from_pointee(Scoring {
+                config: Arc::new(scoring),
+                engine: Arc::new(engine),
+            })),
+            reputation,
+            offenders: Arc::new(OffenderTracker::default()),
+            linked_sessions: Arc::new(LinkedSessionTracker::default()),
+            fingerprints: Arc::new(LinkedSessionTracker::default()),
+            payments: Arc::new(PaymentTracker::default()),
+            rates: Arc::new(RateTracker::default()),
+            session_reputation: Arc::new(SessionReputationTracker::default()),
+            rollouts: Arc::new(RolloutTracker::default()),
+            flags: Arc::new(FlagLedger::default()),
+            stats: Arc::new(ScoringStats::default()),
+            search: Arc::new(SearchIndex::default()),
+            geoip,
+            model,
+            audit,
+            webhooks,
+            alerts: Arc::new(Alerts::start()),
+            clock: Arc::new(SystemClock),
+            metrics,
+        })
+    }
+
+    /// Reads the time from `clock` instead of the system clock, for tests and replays
+    /// that need to control it.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.ip_blacklist.set_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
+
+    /// The scoring config in force. A reload can replace it between two calls on a
+    /// shared state, so request paths work on a `pinned` one.
+    fn scoring(&self) -> Arc<ScoringConfig> {
+        self.scoring.load().config.clone()
+    }
+
+    fn scoring_snapshot(&self) -> Arc<Scoring> {
+        self.scoring.load_full()
+    }
+
+    /// A state fixed to the scoring config in force now: a reload after this leaves it
+    /// on the config it started with, so everything one event goes through (validation,
+    /// lookups, rules, the audit record) sees the same version.
+    pub fn pinned(&self) -> AppState {
+        AppState {
+            scoring: Arc::new(ArcSwap::new(self.scoring.load_full())),
+            ..self.clone()
+        }
+    }
+
+    /// Re-reads `SCORING_CONFIG_PATH` and swaps in the new config and rule engine. On any
+    /// error the running config stays in force.
+    pub fn reload_scoring(&self) -> Result<ConfigReload, String> {
+        let reload = self.swap_scoring();
+        let outcome = if reload.is_ok() { "ok" } else { "error" };
+        counter!("config_reloads_total", "result" => outcome).increment(1);
+        reload
+    }
+
+    fn swap_scoring(&self) -> Result<ConfigReload, String> {
+        let path = ScoringConfig::path_from_env().ok_or("SCORING_CONFIG_PATH is not set")?;
+        let config = ScoringConfig::load(&path).map_err(|e| e.to_string())?;
+        if self.service.max_events_per_session < config.history_needed() {
+            return Err(format!(
+                "MAX_EVENTS_PER_SESSION is {} but the scoring config's windows need {} events per session",
+                self.service.max_events_per_session,
+                config.history_needed()
+            ));
+        }
+        let engine = RuleEngine::new(&config).map_err(|e| format!("Invalid scoring config: {}", e))?;
+
+        let reload = ConfigReload {
+            previous_version: self.scoring().version.clone(),
+            config_version: config.version.clone(),
+        };
+        self.scoring.store(Arc::new(Scoring {
+            config: Arc::new(config),
+            engine: Arc::new(engine),
+        }));
+        if let Some(cache) = &self.response_cache {
+            cache.clear();
+        }
+        info!("Reloaded scoring config {} (was {})", reload.config_version, reload.previous_version);
+        Ok(reload)
+    }
+
+    /// The IP's reputation, when a provider is configured, under its failure mode.
+    async fn ip_reputation(&self, ip: &str, unavailable: &mut Vec<Dependency>) -> Result<Option<u8>, AppError> {
+        let Some(client) = &self.reputation else {
+            return Ok(None);
+        };
+        let outcome = client.lookup(ip).await;
+        self.health
+            .settle(Dependency::IpReputation, outcome, unavailable, self.clock.now())
+    }
+
+    fn prior_flags(&self, event: &UserEvent) -> u32 {
+        event.user_id.as_deref().map_or(0, |user_id| self.offenders.flag_count(user_id))
+    }
+
+    /// The event's user's other sessions active within the concurrent-sessions window.
+    fn linked_sessions(&self, event: &UserEvent) -> Vec<String> {
+        let Some(user_id) = &event.user_id else {
+            return Vec::new();
+        };
+        let window = self.scoring().concurrent_sessions.window();
+        self.linked_sessions
+            .active_sessions(user_id, &event.session_id, event.timestamp, window)
+    }
+
+    /// Other sessions seen with the event's device fingerprint within the window.
+    fn fingerprint_sessions(&self, event: &UserEvent) -> Vec<String> {
+        let Some(fingerprint) = event.fingerprint() else {
+            return Vec::new();
+        };
+        let window = self.scoring().device_fingerprint.window();
+        self.fingerprints
+            .active_sessions(fingerprint, &event.session_id, event.timestamp, window)
+    }
+
+    /// The session's current reputation, when session reputation is on.
+    fn prior_reputation(&self, session_id: &str) -> Option<f64> {
+        let half_life = self.scoring().session_reputation.half_life()?;
+        Some(self.session_reputation.current(session_id, half_life))
+    }
+
+    /// The rules the event runs in shadow, outside their rollouts. Only scoring takes a
+    /// side for a session new to a rollout; `remember` is false for explain requests.
+    fn shadow_rules(&self, event: &UserEvent, config: &ScoringConfig, remember: bool) -> Vec<String> {
+        self.rollouts
+            .shadowed(&config.rollouts, event, remember, self.service.redis_session_ttl)
+    }
+
+    /// The `high_frequency` rates for the last event of `history`. Only scoring counts
+    /// the event; `record` is false for explain requests and amendments. Without a
+    /// window the rule reads the history instead, so nothing is counted.
+    fn rates(&self, history: &[Arc<UserEvent>], config: &ScoringConfig, record: bool) -> Rates {
+        let window_secs = config.high_frequency.window_secs;
+        let Some((event, prior)) = history.split_last().filter(|_| window_secs > 0) else {
+            return Rates::default();
+        };
+        let ip = ip::counter_key(&event.ip_address, config.ipv6_counter_prefix);
+        match record {
+            true => self.rates.observe(event, prior, &ip, window_secs),
+            false => self.rates.peek(event, prior, &ip, window_secs),
+        }
+    }
+
+    fn record_reputation(&self, session_id: &str, score: i32) {
+        if let Some(half_life) = self.scoring().session_reputation.half_life() {
+            self.session_reputation.add(session_id, f64::from(score.max(0)), half_life);
+        }
+    }
+
+    fn audit(&self, event: &UserEvent, result: &FraudCheckResult) {
+        if let Some(audit) = &self.audit {
+            audit.record(event, result, &self.scoring().version);
+        }
+    }
+
+    fn geo(&self, ip: &str) -> Option<GeoInfo> {
+        self.geoip.as_ref()?.lookup(ip)
+    }
+
+    /// The session's facts, from the session cache when it is on.
+    fn session_context<'a>(&'a self, session_id: &'a str) -> SessionContext<'a> {
+        SessionContext::new(self.session_cache.as_deref(), session_id, &self.ip_blacklist, self.geoip.as_deref())
+    }
+
+    /// The session's facts worked out afresh, for runs that keep no per-session state.
+    fn uncached_session_context<'a>(&'a self, session_id: &'a str) -> SessionContext<'a> {
+        SessionContext::new(None, session_id, &self.ip_blacklist, self.geoip.as_deref())
+    }
+
+    /// The model's fraud probability, when a model is loaded, under its failure mode.
+    async fn model_probability(
+        &self,
+        history: &[Arc<UserEvent>],
+        client_ip: &str,
+        geo: Option<&GeoInfo>,
+        unavailable: &mut Vec<Dependency>,
+    ) -> Result<Option<f32>, AppError> {
+        let Some(model) = &self.model else {
+            return Ok(None);
+        };
+        let features = features::extract(history, client_ip, &self.ip_blacklist, geo, self.scoring().ipv6_counter_prefix);
+        let outcome = model.predict(features).await.map(Some);
+        self.health.settle(Dependency::Model, outcome, unavailable, self.clock.now())
+    }
+
+    /// `span` is the session's first and last event times, as the search index has them.
+    fn record_flagged(&self, event: &UserEvent, result: &FraudCheckResult, span: (DateTime<Utc>, DateTime<Utc>)) {
+        let (first_event, last_event) = span;
+        let record = FlagRecord {
+            user_id: event.user_id.clone(),
+            score: result.fraud_score,
+            codes: result.breakdown.iter().map(|hit| hit.code.as_ref()).collect::<Vec<_>>().join("|"),
+            first_event,
+            last_event,
+            ip: event.ip_address.clone(),
+        };
+        self.flags.record(&result.session_id, result.check_timestamp, record);
+    }
+
+    /// Runs the lookups for a not yet stored event. The model scores the stored history
+    /// plus the event, as storing it would leave the session.
+    async fn lookups(&self, event: &UserEvent, client_ip: &str) -> Result<Lookups, AppError> {
+        let mut unavailable = Vec::new();
+        let ip_reputation = self.ip_reputation(client_ip, &mut unavailable).await?;
+        let geo = self.session_context(&event.session_id).geo(client_ip);
+        let model_probability = match &self.model {
+            Some(_) => {
+                let mut history = self.event_store.session_events(&event.session_id).await?;
+                let events = history.to_mut();
+                events.push(Arc::new(event.clone()));
+                let excess = events.len().saturating_sub(self.service.max_events_per_session);
+                events.drain(..excess);
+                self.model_probability(&history, client_ip, geo.as_ref(), &mut unavailable)
+                    .await?
+            }
+            None => None,
+        };
+        Ok(Lookups {
+            ip_reputation,
+            geo,
+            model_probability,
+            activity: self.activity_profile(event, &mut unavailable).await?,
+            entity_reputation: self.entity_reputation(event, &mut unavailable).await?,
+            unavailable,
+        })
+    }
+
+    /// The user's activity profile before this event, unless the event is anonymous or
+    /// the `activity_profile` rule can't score, under the activity store's failure mode.
+    async fn activity_profile(
+        &self,
+        event: &UserEvent,
+        unavailable: &mut Vec<Dependency>,
+    ) -> Result<Option<ActivityProfile>, AppError> {
+        let Some(user_id) = event.user_id.as_ref().filter(|_| self.scoring().activity_profile.enabled()) else {
+            return Ok(None);
+        };
+        let outcome = self.activity.profile(user_id).await.map_err(|e| {
+            counter!("activity_profile_errors_total").increment(1);
+            warn!("Failed to read the activity profile for session [{}]: {}", &event.session_id, e);
+            Unavailable
+        });
+        self.health
+            .settle(Dependency::ActivityStore, outcome, unavailable, self.clock.now())
+    }
+
+    /// The reputations of the event's user and IP before it, when entity reputation is
+    /// on, under the reputation store's failure mode.
+    async fn entity_reputation(&self, event: &UserEvent, unavailable: &mut Vec<Dependency>) -> Result<EntityScores, AppError> {
+        let Some(half_life) = self.scoring().entity_reputation.half_life() else {
+            return Ok(EntityScores::default());
+        };
+        let now = self.clock.now();
+        let mut scores = Ok(EntityScores::default());
+        for (entity, id) in self.reputation_entities(event) {
+            let stored = match self.entity_reputations.get(entity, &id).await {
+                Ok(stored) => stored,
+                Err(e) => {
+                    counter!("entity_reputation_errors_total").increment(1);
+                    warn!("Failed to read the {} reputation for session [{}]: {}", entity.as_str(), &event.session_id, e);
+                    scores = Err(Unavailable);
+                    break;
+                }
+            };
+            if let (Ok(scores), Some(stored)) = (&mut scores, stored) {
+                match entity {
+                    Entity::User => scores.user = stored.at(now, half_life),
+                    Entity::Ip => scores.ip = stored.at(now, half_life),
+                }
+            }
+        }
+        self.health.settle(Dependency::EntityReputation, scores, unavailable, now)
+    }
+
+    /// What the event's reputation is kept against: its IP, grouped like the per-IP
+    /// counters, and its user when it has one.
+    fn reputation_entities(&self, event: &UserEvent) -> Vec<(Entity, String)> {
+        let ip = ip::counter_key(&event.ip_address, self.scoring().ipv6_counter_prefix).into_owned();
+        let mut entities = vec![(Entity::Ip, ip)];
+        if let Some(user_id) = &event.user_id {
+            entities.push((Entity::User, user_id.clone()));
+        }
+        entities
+    }
+
+    /// Adds a flagged event's points to its user's and IP's reputations. Like activity
+    /// updates, a failure is logged rather than failing an event that is already stored.
+    async fn record_entity_flag(&self, event: &UserEvent) {
+        let scoring = self.scoring();
+        let Some(half_life) = scoring.entity_reputation.half_life() else {
+            return;
+        };
+        for (entity, id) in self.reputation_entities(event) {
+            let added = self
+                .entity_reputations
+                .add(entity, &id, scoring.entity_reputation.flag_points, self.clock.now(), half_life)
+                .await;
+            if let Err(e) = added {
+                counter!("entity_reputation_errors_total").increment(1);
+                warn!("Failed to update the {} reputation for session [{}]: {}", entity.as_str(), &event.session_id, e);
+            }
+        }
+    }
+
+    /// Adds the event to its user's activity profile. A failed update costs the profile
+    /// one event, so it is logged rather than failing an event that is already stored.
+    async fn record_activity(&self, event: &UserEvent) {
+        let Some(user_id) = &event.user_id else {
+            return;
+        };
+        if let Err(e) = self.activity.record(user_id, event.timestamp).await {
+            counter!("activity_profile_errors_total").increment(1);
+            warn!("Failed to update the activity profile for session [{}]: {}", &event.session_id, e);
+        }
+    }
+
+    /// Stores the event's retained copy, returning the session's history ending with the
+    /// event as received, so the rules still see all of its metadata.
+    async fn store_event(&self, event: UserEvent) -> Result<SessionHistory, StoreError> {
+        let Some(stored) = self.service.metadata_retention.stored_copy(&event) else {
+            return self.event_store.push(event).await;
+        };
+        let mut history = self.event_store.push(stored).await?;
+        *history.to_mut().last_mut().unwrap() = Arc::new(event);
+        Ok(history)
+    }
+
+    fn payment_window_total(&self, event: &UserEvent) -> f64 {
+        let (Some(user_id), Some(payment)) = (&event.user_id, payments::parse_payment(event)) else {
+            return 0.0;
+        };
+        let window = chrono::Duration::seconds(self.scoring().payments.window_secs as i64);
+        self.payments
+            .window_total(user_id, payment.currency.unwrap_or_default(), event.timestamp, window)
+    }
+
+    /// Remembers a scored payment for the user's velocity total. Malformed amounts are
+    /// scored but never accumulated.
+    fn record_payment(&self, event: &UserEvent) {
+        let (Some(user_id), Some(payment)) = (&event.user_id, payments::parse_payment(event)) else {
+            return;
+        };
+        if let Ok(amount) = payment.amount {
+            let window = chrono::Duration::seconds(self.scoring().payments.window_secs as i64);
+            self.payments
+                .record(user_id, payment.currency.unwrap_or_default(), event.timestamp, amount, window);
+        }
+    }
+}
+
+// --- DATA STRUCTURES ---
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UserEvent {
+    session_id: String,
+    user_id: Option<String>,
+    event_type: EventType,
+    #[serde(deserialize_with = "msgpack::deserialize_timestamp")]
+    timestamp: DateTime<Utc>,
+    #[serde(deserialize_with = "deserialize_ip_address")]
+    ip_address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_agent: Option<String>,
+    metadata: Option<HashMap<String, String>>,
+    /// Autonomous system of the event IP, resolved from `GEOIP_ASN_DB_PATH` when the
+    /// event is scored. Never taken from the client.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    asn: Option<u32>,
+}
+
+impl UserEvent {
+    /// Sets `asn` from the GeoIP data for `ip`, the event's real IP.
+    fn resolve_asn(&mut self, state: &AppState, ip: &str) {
+        self.asn = state.geo(ip).and_then(|geo| geo.asn);
+    }
+
+    /// The client's user agent, from the top-level field or `metadata["userAgent"]`.
+    fn user_agent(&self) -> Option<&str> {
+        self.user_agent
+            .as_deref()
+            .or_else(|| self.metadata.as_ref()?.get("userAgent").map(String::as_str))
+    }
+
+    /// The client-computed device hash in `metadata["fingerprint"]`, when not blank.
+    fn fingerprint(&self) -> Option<&str> {
+        let fingerprint = self.metadata.as_ref()?.get("fingerprint")?.trim();
+        (!fingerprint.is_empty()).then_some(fingerprint)
+    }
+
+    /// The client's id for the event in `metadata["eventId"]`, when not blank. A later
+    /// event in the same session carrying it amends this one instead of being scored anew.
+    fn event_id(&self) -> Option<&str> {
+        let event_id = self.metadata.as_ref()?.get(EVENT_ID_KEY)?.trim();
+        (!event_id.is_empty()).then_some(event_id)
+    }
+
+    /// A copy with `amendment` merged over its metadata, the amendment's keys winning.
+    fn amended(&self, amendment: &HashMap<String, String>) -> UserEvent {
+        let mut amended = self.clone();
+        let metadata = amended.metadata.get_or_insert_with(HashMap::new);
+        metadata.extend(amendment.iter().map(|(key, value)| (key.clone(), value.clone())));
+        amended
+    }
+}
+
+/// First 8 bytes of the value's SHA-256, hex-encoded. Used wherever a value needs to
+/// be correlated across logs or records without being written out.
+fn short_hash(value: &[u8]) -> String {
+    Sha256::digest(value)[..8].iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Rejects anything that isn't a literal IPv4/IPv6 address (hostnames included) and
+/// stores the canonical form (see `ip::parse`) so it compares equal to imported
+/// blacklist entries.
+fn deserialize_ip_address<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw = String::deserialize(deserializer)?;
+    ip::parse(&raw)
+        .map(|ip| ip.to_string())
+        .ok_or_else(|| serde::de::Error::custom(format!("invalid IP address `{}`", raw)))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Hash)]
+pub enum EventType {
+    PageLoad,
+    Click,
+    FormSubmission,
+    /// Carries `amount` and `currency` metadata.
+    Payment,
+}
+
+impl EventType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            EventType::PageLoad => "PageLoad",
+            EventType::Click => "Click",
+            EventType::FormSubmission => "FormSubmission",
+            EventType::Payment => "Payment",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FraudCheckResult {
+    session_id: String,
+    /// The raw score normalized onto 0-100; what `flagged` and the decision compare.
+    fraud_score: i32,
+    /// The hits' points, summed.
+    raw_score: i32,
+    /// The same as `fraud_score`, from before it was normalized.
+    confidence: u8,
+    flagged: bool,
+    /// What the decision policy says to do with the event.
+    decision: Decision,
+    reasons: Vec<String>,
+    breakdown: Vec<RuleHit>,
+    /// Accumulated, decaying score of the session including this event.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    session_reputation: Option<i32>,
+    /// The event IP's autonomous system, when the ASN database has it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    asn: Option<u32>,
+    check_timestamp: DateTime<Utc>,
+    /// Set when a dependency failed and the event was scored without it.
+    degraded: bool,
+    /// Rules left out of the score because a dependency they require failed.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    skipped_rules: Vec<String>,
+    /// Set once `STORAGE_SAMPLE_RATES` left some of the session's events unstored, so
+    /// its history, and anything scored from it, may be partial.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    history_sampled: bool,
+    /// Per-rule contributions, for the rule report.
+    #[serde(skip)]
+    outcomes: Vec<RuleOutcome>,
+    /// How rules under a rollout fared, for the rule report.
+    #[serde(skip)]
+    rollouts: Vec<RolloutOutcome>,
+}
+
+/// The slim shape returned for clean events in minimal verbosity.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MinimalResult {
+    session_id: String,
+    fraud_score: i32,
+    raw_score: i32,
+    confidence: u8,
+    flagged: bool,
+    decision: Decision,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum AnalyzeResponse {
+    Full(FraudCheckResult),
+    Minimal(MinimalResult),
+}
+
+impl AnalyzeResponse {
+    /// Flagged, allowlisted, and degraded results, and any decided other than `ALLOW`,
+    /// always keep their reasons, whatever the verbosity.
+    fn new(result: FraudCheckResult, verbosity: Verbosity) -> Self {
+        let allowlisted = result.breakdown.iter().any(|hit| hit.code == "ALLOWLISTED");
+        let clean = !result.flagged && !allowlisted && !result.degraded && result.decision == Decision::Allow;
+        if verbosity == Verbosity::Minimal && clean {
+            AnalyzeResponse::Minimal(MinimalResult {
+                session_id: result.session_id,
+                fraud_score: result.fraud_score,
+                raw_score: result.raw_score,
+                confidence: result.confidence,
+                flagged: result.flagged,
+                decision: result.decision,
+            })
+        } else {
+            AnalyzeResponse::Full(result)
+        }
+    }
+}
+
+/// The versions a scoring config reload swapped.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigReload {
+    previous_version: String,
+    config_version: String,
+}
+
+/// The rule registry as the current scoring config sets it up.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RulesResponse {
+    config_version: String,
+    flag_threshold: i32,
+    rules: Vec<RuleInfo>,
+}
+
+impl FraudCheckResult {
+    fn new(event: &UserEvent, analysis: Analysis, check_timestamp: DateTime<Utc>) -> Self {
+        Self {
+            session_id: event.session_id.clone(),
+            fraud_score: analysis.score,
+            raw_score: analysis.raw_score,
+            confidence: analysis.score as u8,
+            flagged: analysis.flagged,
+            decision: analysis.decision,
+            reasons: analysis.hits.iter().map(|hit| hit.reason.clone()).collect(),
+            breakdown: analysis.hits,
+            session_reputation: analysis.session_reputation.map(|reputation| reputation.round() as i32),
+            asn: event.asn,
+            check_timestamp,
+            degraded: analysis.degraded,
+            skipped_rules: analysis.skipped_rules,
+            history_sampled: false,
+            outcomes: analysis.outcomes,
+            rollouts: analysis.rollouts,
+        }
+    }
+
+    /// Adds a zero-point hit from outside the rules, such as a metadata truncation.
+    fn note(&mut self, hit: Option<RuleHit>) {
+        if let Some(hit) = hit {
+            self.reasons.push(hit.reason.clone());
+            self.breakdown.push(hit);
+        }
+    }
+}
+
+// --- API ENDPOINT HANDLERS ---
+/// Counts metadata keys outside the event type's schema. They are allowed, but a rising
+/// count points at clients drifting from the expected contract.
+fn record_unknown_metadata_keys(event: &UserEvent, config: &ScoringConfig) {
+    let (Some(schema), Some(metadata)) = (config.metadata_keys.schemas.get(&event.event_type), &event.metadata) else {
+        return;
+    };
+
+    let unexpected = rules::unexpected_metadata_keys(metadata, schema);
+    if unexpected > 0 {
+        counter!("metadata_unknown_keys_total", "event_type" => event.event_type.as_str()).increment(unexpected as u64);
+    }
+}
+
+/// In `reject` mode, fails events whose metadata is missing required keys or has
+/// malformed values. In `score` mode the metadata rule scores them instead.
+fn validate_metadata(event: &UserEvent, config: &ScoringConfig) -> Result<(), AppError> {
+    let metadata_config = &config.metadata_keys;
+    if metadata_config.mode != MetadataSchemaMode::Reject {
+        return Ok(());
+    }
+    let Some(schema) = metadata_config.schemas.get(&event.event_type) else {
+        return Ok(());
+    };
+
+    let empty = HashMap::new();
+    let metadata = event.metadata.as_ref().unwrap_or(&empty);
+
+    let mut violations: Vec<String> = schema
+        .required
+        .iter()
+        .filter(|key| !metadata.contains_key(*key))
+        .map(|key| format!("{} is required", key))
+        .collect();
+    violations.sort_unstable();
+    violations.extend(rules::malformed_metadata_values(metadata, schema));
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::Validation(format!(
+            "Malformed metadata for {}: {}",
+            event.event_type.as_str(),
+            violations.join("; ")
+        )))
+    }
+}
+
+/// Holds the event's metadata to `MAX_METADATA_KEYS` and `MAX_METADATA_VALUE_BYTES`, counting truncations.
+fn limit_metadata(event: &mut UserEvent, service: &ServiceConfig) -> Result<Option<RuleHit>, AppError> {
+    let truncated = metadata_limits::enforce(event, &service.metadata_limits, &service.metadata_retention)?;
+    if truncated.is_some() {
+        counter!("metadata_truncated_total").increment(1);
+    }
+    Ok(truncated)
+}
+
+/// Rejects events stamped too far from the server clock, so a replayed or badly
+/// buffered batch can't write stale (or future) history that every later event in the
+/// session would be scored against.
+fn validate_freshness(event: &UserEvent, service: &ServiceConfig, now: DateTime<Utc>) -> Result<(), AppError> {
+    let Some(window) = service.freshness_window else {
+        return Ok(());
+    };
+    let offset = event.timestamp - now;
+    if offset < -window {
+        Err(AppError::Validation(format!(
+            "Event timestamp is {}s in the past, outside the {}s window",
+            -offset.num_seconds(),
+            window.num_seconds()
+        )))
+    } else if offset > window {
+        Err(AppError::Validation(format!(
+            "Event timestamp is {}s in the future, outside the {}s window",
+            offset.num_seconds(),
+            window.num_seconds()
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AnalyzeParams {
+    verbosity: Option<Verbosity>,
+}
+
+/// What scoring needs from services that may be slow or down.
+#[derive(Default)]
+struct Lookups {
+    ip_reputation: Option<u8>,
+    geo: Option<GeoInfo>,
+    model_probability: Option<f32>,
+    activity: Option<ActivityProfile>,
+    entity_reputation: EntityScores,
+    /// Dependencies that failed under `skip_on_failure`.
+    unavailable: Vec<Dependency>,
+}
+
+/// Scores one event and commits its side effects: stores it, updates per-user state,
+/// and writes the audit record. Metadata validation is the caller's job.
+///
+/// Every lookup that can stall runs before anything is written, so a caller dropping
+/// the future then (on a request timeout, say) leaves no trace. From the first write
+/// on, the work runs to completion in its own task, so it is never left half done.
+///
+/// `observed_ip` is the client IP derived from the connection, for the `ip_mismatch`
+/// rule to compare the payload's against. Reads the scoring config from `state` as it
+/// goes, so pass a `pinned` state to score under a single version across a reload.
+pub async fn analyze_event(
+    state: &AppState,
+    mut event: UserEvent,
+    observed_ip: Option<IpAddr>,
+) -> Result<FraudCheckResult, AppError> {
+    // The real IP is only used up to scoring; the stored copy gets the privacy transform.
+    let client_ip = event.ip_address.clone();
+    event.ip_address = state.service.ip_privacy.apply(&client_ip);
+
+    // Allowlisted events are stored like any other but skip scoring (and the lookups).
+    let allowlisted = state.allowlist.matches(&client_ip, event.user_id.as_deref());
+    let lookups = match allowlisted {
+        Some(_) => {
+            event.resolve_asn(state, &client_ip);
+            Lookups::default()
+        }
+        None => {
+            let lookups = state.lookups(&event, &client_ip).await?;
+            event.asn = lookups.geo.as_ref().and_then(|geo| geo.asn);
+            lookups
+        }
+    };
+
+    let state = state.clone();
+    tokio::spawn(async move {
+        match allowlisted {
+            Some(matched) => commit_allowlisted(&state, event, matched).await,
+            None => commit_scored(&state, event, &client_ip, observed_ip, lookups).await,
+        }
+    })
+    .await
+    .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+    .map_err(AppError::from)
+}
+
+async fn commit_allowlisted(state: &AppState, event: UserEvent, matched: AllowlistMatch) -> Result<FraudCheckResult, StoreError> {
+    counter!("events_allowlisted_total", "match" => matched.as_str()).increment(1);
+    let session_events = state.store_event(event).await?;
+    // Counted like any stored event, so later events' rates match their history.
+    state.rates(&session_events, &state.scoring(), true);
+    let event = session_events.last().unwrap();
+    let analysis = Analysis::allowlisted(matched, state.prior_reputation(&event.session_id));
+    state.record_activity(event).await;
+    let mut result = FraudCheckResult::new(event, analysis, state.clock.now());
+    result.history_sampled = state.sampler.as_ref().is_some_and(|sampler| sampler.is_partial(&event.session_id));
+    info!("Allowlisted event for session [{}] ({})", &result.session_id, matched.as_str());
+    state.stats.record(&ip::counter_key(&event.ip_address, state.scoring().ipv6_counter_prefix), &result);
+    state.search.record(&session_events, &result);
+    state.audit(event, &result);
+    Ok(result)
+}
+
+async fn commit_scored(
+    state: &AppState,
+    event: UserEvent,
+    client_ip: &str,
+    observed_ip: Option<IpAddr>,
+    lookups: Lookups,
+) -> Result<FraudCheckResult, StoreError> {
+    // An event drawn out by storage sampling is scored against the stored history as if
+    // it had been stored, and stored after all if it scores as risky.
+    let drawn_out = state.sampler.as_ref().is_some_and(|sampler| sampler.draws_out(&event));
+    let session_events = match drawn_out {
+        true => {
+            let mut history = state.event_store.session_events(&event.session_id).await?;
+            history.to_mut().push(Arc::new(event));
+            history
+        }
+        false => state.store_event(event).await?,
+    };
+
+    let event = session_events.last().unwrap();
+    let linked_sessions = state.linked_sessions(event);
+    let fingerprint_sessions = state.fingerprint_sessions(event);
+    let scoring = state.scoring_snapshot();
+    let shadow_rules = state.shadow_rules(event, &scoring.config, true);
+    let rates = state.rates(&session_events, &scoring.config, true);
+    let analysis = scoring.engine.evaluate(&RuleContext {
+        event,
+        history: &session_events,
+        client_ip,
+        observed_ip,
+        session: state.session_context(&event.session_id),
+        suspicious_sources: &state.suspicious_sources,
+        disposable_domains: &state.disposable_domains,
+        config: &scoring.config,
+        ip_reputation: lookups.ip_reputation,
+        prior_flags: state.prior_flags(event),
+        linked_sessions: &linked_sessions,
+        fingerprint_sessions: &fingerprint_sessions,
+        activity: lookups.activity.as_ref(),
+        geo: lookups.geo,
+        payment_window_total: state.payment_window_total(event),
+        rates: &rates,
+        model_probability: lookups.model_probability,
+        session_reputation: state.prior_reputation(&event.session_id).unwrap_or_default(),
+        entity_reputation: lookups.entity_reputation,
+        unavailable: &lookups.unavailable,
+        shadow_rules: &shadow_rules,
+    });
+    let unstored = drawn_out
+        && !state
+            .sampler
+            .as_ref()
+            .is_some_and(|sampler| sampler.risky(analysis.score, !analysis.hits.is_empty()));
+    if drawn_out && !unstored {
+        state.store_event(UserEvent::clone(event)).await?;
+    }
+    if unstored && scoring.config.high_frequency.window_secs > 0 {
+        state.rates.unstored(event, scoring.config.high_frequency.window_secs);
+    }
+
+    for hit in &analysis.hits {
+        counter!("rule_hits_total", "code" => hit.code.to_string()).increment(1);
+    }
+    state.record_payment(event);
+    state.record_reputation(&event.session_id, analysis.raw_score);
+    if let Some(user_id) = &event.user_id {
+        let window = state.scoring().concurrent_sessions.window();
+        state.linked_sessions.record(user_id, &event.session_id, event.timestamp, window);
+    }
+    if let Some(fingerprint) = event.fingerprint() {
+        let window = state.scoring().device_fingerprint.window();
+        state.fingerprints.record(fingerprint, &event.session_id, event.timestamp, window);
+    }
+    state.record_activity(event).await;
+    if let (true, Some(user_id)) = (analysis.flagged, &event.user_id) {
+        let ttl = Duration::from_secs(state.scoring().repeat_offender.ttl_secs);
+        state.offenders.record_flag(user_id, ttl);
+    }
+    if analysis.flagged {
+        state.record_entity_flag(event).await;
+    }
+
+    if analysis.hits.iter().any(|hit| hit.code == "IP_BLACKLISTED") {
+        let ip = state.service.log_ip.render(&event.ip_address);
+        warn!("BLACKLISTED IP DETECTED: IP {} in session {}", ip, &event.session_id);
+    }
+    if analysis.hits.iter().any(|hit| hit.code == "DISPOSABLE_EMAIL") {
+        if let Some(email) = event.metadata.as_ref().and_then(|m| EmailAddress::parse(m.get("email")?)) {
+            info!("Disposable email domain {} in session {} (email hash {})", email.domain, &event.session_id, email.log_hash());
+        }
+    }
+
+    let history_sampled = state.sampler.as_ref().is_some_and(|sampler| {
+        sampler.record(event, analysis.score, !analysis.hits.is_empty(), unstored, state.clock.now())
+    });
+    let mut result = FraudCheckResult::new(event, analysis, state.clock.now());
+    result.history_sampled = history_sampled;
+    state.stats.record(&ip::counter_key(&event.ip_address, state.scoring().ipv6_counter_prefix), &result);
+    let span = state.search.record(&session_events, &result);
+    if result.flagged {
+        state.record_flagged(event, &result, span);
+        if let Some(webhooks) = &state.webhooks {
+            webhooks.notify(event, &result);
+        }
+    }
+    state.alerts.consider(&state.scoring().alerts, &result);
+
+    if state.analysis_logs.should_log(result.flagged) {
+        info!("Analysis complete for session [{}]: Score = {}, Flagged = {}", &result.session_id, result.fraud_score, result.flagged);
+    }
+    state.audit(event, &result);
+    Ok(result)
+}
+
+/// Validates a decoded event and scores it. Shared by the HTTP and gRPC endpoints so
+/// both accept the same events and score them the same way.
+///
+/// `derived_ip` is the client IP worked out from the connection under
+/// `CLIENT_IP_SOURCE`; it replaces or is checked against the payload's, per
+/// `CLIENT_IP_POLICY`. Without one the payload IP is scored as is.
+async fn score_event(
+    state: &AppState,
+    mut event: UserEvent,
+    derived_ip: Option<IpAddr>,
+    usage_key: &str,
+) -> Result<FraudCheckResult, AppError> {
+    let state = &state.pinned();
+    let mut observed_ip = None;
+    if let Some(config) = &state.service.client_ip {
+        match (derived_ip, config.policy) {
+            (None, _) => counter!("client_ip_unresolved_total").increment(1),
+            (Some(ip), ClientIpPolicy::Override) => event.ip_address = ip.to_string(),
+            (Some(ip), ClientIpPolicy::CrossCheck) => observed_ip = Some(ip),
+        }
+    }
+
+    validate_freshness(&event, &state.service, state.clock.now())?;
+    let truncated = limit_metadata(&mut event, &state.service)?;
+    let mut result = match amend_event(state, &event).await? {
+        Some(result) => result,
+        None => {
+            validate_metadata(&event, &state.scoring())?;
+            record_unknown_metadata_keys(&event, &state.scoring());
+            analyze_event(state, event, observed_ip).await?
+        }
+    };
+    result.note(truncated);
+    let scored = UsageCounts {
+        events: 1,
+        flagged: u64::from(result.flagged),
+        bytes: 0,
+    };
+    state.usage.record(usage_key, scored, state.clock.now());
+    Ok(result)
+}
+
+/// Merges an event naming a stored event of its session by `eventId` into that event,
+/// then rescores the amended event against the history before it. Nothing is kept but
+/// the merge, as with explain, so rescoring doesn't count the event twice. `None` when
+/// the event names no stored event and should be scored as a new one.
+async fn amend_event(state: &AppState, amendment: &UserEvent) -> Result<Option<FraudCheckResult>, AppError> {
+    let (Some(event_id), Some(metadata)) = (amendment.event_id(), &amendment.metadata) else {
+        return Ok(None);
+    };
+    let history = state.event_store.session_events(&amendment.session_id).await?;
+    let Some(original) = history.iter().rev().find(|event| event.event_id() == Some(event_id)) else {
+        return Ok(None);
+    };
+    let amended = original.amended(metadata);
+    validate_metadata(&amended, &state.scoring())?;
+    record_unknown_metadata_keys(&amended, &state.scoring());
+
+    let retained = state.service.metadata_retention.stored_copy(amendment);
+    let retained = retained.as_ref().unwrap_or(amendment).metadata.clone().unwrap_or_default();
+    // The event may have been trimmed or erased since it was read.
+    let Some(mut history) = state.event_store.amend(&amendment.session_id, event_id, &retained).await? else {
+        return Ok(None);
+    };
+    counter!("events_amended_total").increment(1);
+
+    // Scored with the amendment's full metadata, not just what was stored.
+    let index = history.iter().rposition(|event| event.event_id() == Some(event_id)).unwrap_or(history.len());
+    history.to_mut().truncate(index);
+    history.to_mut().push(Arc::new(amended));
+    score_without_storing(state, history, &amendment.ip_address).await.map(Some)
+}
+
+/// The key an HTTP request is billed to, from its `USAGE_KEY_HEADER`.
+fn usage_key<'a>(state: &AppState, headers: &'a HeaderMap) -> &'a str {
+    state
+        .usage
+        .key(headers.get(state.usage.key_header()).and_then(|value| value.to_str().ok()))
+}
+
+/// The client IP of an HTTP request per `CLIENT_IP_SOURCE`, when that is set.
+fn derive_client_ip(state: &AppState, peer: Option<ConnectInfo<SocketAddr>>, headers: &HeaderMap) -> Option<IpAddr> {
+    let config = state.service.client_ip.as_ref()?;
+    client_ip::from_headers(config, peer.map(|ConnectInfo(peer)| peer), headers)
+}
+
+async fn analyze_event_handler(
+    State(state): State<Arc<AppState>>,
+    AppQuery(params): AppQuery<AnalyzeParams>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    EventBody(event): EventBody,
+) -> Result<Response, AppError> {
+    let derived_ip = derive_client_ip(&state, peer, &headers);
+    let result = score_event(&state, event, derived_ip, usage_key(&state, &headers)).await?;
+    let verbosity = params.verbosity.unwrap_or(state.service.response_verbosity);
+    let response = AnalyzeResponse::new(result, verbosity);
+    if protobuf::accepts_protobuf(&headers) {
+        return Ok(protobuf::protobuf_response(response));
+    }
+    msgpack::respond(&headers, response)
+}
+
+#[derive(Debug, Deserialize)]
+struct ExplainRequest {
+    event: UserEvent,
+    /// Session history to score against instead of the stored one.
+    history: Option<Vec<UserEvent>>,
+}
+
+/// Runs the full pipeline without storing the event or touching any per-user state, so
+/// analysts can test hypotheses without affecting the session's real history. Scores
+/// against `history` when given, otherwise against the stored session.
+pub async fn explain_event(
+    state: &AppState,
+    mut event: UserEvent,
+    history: Option<Vec<UserEvent>>,
+) -> Result<FraudCheckResult, AppError> {
+    let privacy = &state.service.ip_privacy;
+    let client_ip = event.ip_address.clone();
+    event.resolve_asn(state, &client_ip);
+    event.ip_address = privacy.apply(&client_ip);
+    let stored = history.is_none();
+    let mut history = match history {
+        // Supplied history arrives raw; transform it to match what the store would hold.
+        Some(history) => history
+            .into_iter()
+            .map(|mut event| {
+                let ip = std::mem::take(&mut event.ip_address);
+                event.resolve_asn(state, &ip);
+                event.ip_address = privacy.apply(&ip);
+                let stored = state.service.metadata_retention.stored_copy(&event);
+                Arc::new(stored.unwrap_or(event))
+            })
+            .collect(),
+        None => state.event_store.session_events(&event.session_id).await?,
+    };
+    let session_id = event.session_id.clone();
+    history.to_mut().push(Arc::new(event));
+    let mut result = score_without_storing(state, history, &client_ip).await?;
+    // Only the stored history can be partial; supplied history is what the caller sent.
+    result.history_sampled = stored && state.sampler.as_ref().is_some_and(|sampler| sampler.is_partial(&session_id));
+    Ok(result)
+}
+
+/// Scores the last event of `history` against the events before it, seen from
+/// `client_ip`, through the full pipeline but without storing or recording anything.
+async fn score_without_storing(
+    state: &AppState,
+    history: SessionHistory,
+    client_ip: &str,
+) -> Result<FraudCheckResult, AppError> {
+    let event = history.last().unwrap();
+    if let Some(matched) = state.allowlist.matches(client_ip, event.user_id.as_deref()) {
+        let analysis = Analysis::allowlisted(matched, state.prior_reputation(&event.session_id));
+        return Ok(FraudCheckResult::new(event, analysis, state.clock.now()));
+    }
+
+    let mut unavailable = Vec::new();
+    let ip_reputation = state.ip_reputation(client_ip, &mut unavailable).await?;
+    let geo = state.geo(client_ip);
+    let model_probability = state
+        .model_probability(&history, client_ip, geo.as_ref(), &mut unavailable)
+        .await?;
+    let linked_sessions = state.linked_sessions(event);
+    let fingerprint_sessions = state.fingerprint_sessions(event);
+    let activity = state.activity_profile(event, &mut unavailable).await?;
+    let entity_reputation = state.entity_reputation(event, &mut unavailable).await?;
+    let scoring = state.scoring_snapshot();
+    let shadow_rules = state.shadow_rules(event, &scoring.config, false);
+    let rates = state.rates(&history, &scoring.config, false);
+    let analysis = scoring.engine.evaluate(&RuleContext {
+        event,
+        history: &history,
+        client_ip,
+        observed_ip: None,
+        session: state.uncached_session_context(&event.session_id),
+        suspicious_sources: &state.suspicious_sources,
+        disposable_domains: &state.disposable_domains,
+        config: &scoring.config,
+        ip_reputation,
+        prior_flags: state.prior_flags(event),
+        linked_sessions: &linked_sessions,
+        fingerprint_sessions: &fingerprint_sessions,
+        activity: activity.as_ref(),
+        geo,
+        payment_window_total: state.payment_window_total(event),
+        rates: &rates,
+        model_probability,
+        session_reputation: state.prior_reputation(&event.session_id).unwrap_or_default(),
+        entity_reputation,
+        unavailable: &unavailable,
+        shadow_rules: &shadow_rules,
+    });
+
+    Ok(FraudCheckResult::new(event, analysis, state.clock.now()))
+}
+
+async fn explain_event_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    AppBody(mut request): AppBody<ExplainRequest>,
+) -> Result<Response, AppError> {
+    let state = state.pinned();
+    let truncated = limit_metadata(&mut request.event, &state.service)?;
+    validate_metadata(&request.event, &state.scoring())?;
+    let mut result = explain_event(&state, request.event, request.history).await?;
+    result.note(truncated);
+    msgpack::respond(&headers, result)
+}
+
+async fn rules_handler(State(state): State<Arc<AppState>>) -> Json<RulesResponse> {
+    let scoring = state.scoring_snapshot();
+    let rules = scoring.engine.describe(&scoring.config, |dependency| match dependency {
+        Dependency::IpReputation => state.reputation.is_some(),
+        Dependency::Model => state.model.is_some(),
+        Dependency::AsnData => state.service.geoip_asn_db_path.is_some(),
+        Dependency::ObservedIp => state
+            .service
+            .client_ip
+            .as_ref()
+            .is_some_and(|config| config.policy == ClientIpPolicy::CrossCheck),
+        Dependency::ActivityStore | Dependency::EntityReputation => true,
+    });
+    Json(RulesResponse {
+        config_version: scoring.config.version.clone(),
+        flag_threshold: scoring.config.flag_threshold,
+        rules,
+    })
+}
+
+/// Reloads the scoring config from `SCORING_CONFIG_PATH`, like `SIGHUP`.
+async fn reload_config_handler(State(state): State<Arc<AppState>>) -> Result<Json<ConfigReload>, AppError> {
+    let reload = state.reload_scoring().map_err(AppError::Validation)?;
+    Ok(Json(reload))
+}
+
+/// Ready even while dependencies are degraded: events are still answered, per the
+/// degradation policy.
+async fn readiness_handler(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    let degraded = state.health.degraded();
+    Json(serde_json::json!({
+        "status": if degraded.is_empty() { "ready" } else { "degraded" },
+        "degraded": degraded,
+        "blacklist": {
+            "entries": state.ip_blacklist.len(),
+            "sync": state.ip_blacklist.sync_status(),
+        },
+        "sources": sources::summaries(&state.suspicious_sources),
+    }))
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BuildInfo {
+    version: &'static str,
+    /// Absent when built outside a git checkout without `GIT_COMMIT`.
+    git_commit: Option<&'static str>,
+    built_at: &'static str,
+}
+
+/// Which build is running, for deploy verification. Fixed at compile time by `build.rs`.
+async fn version_handler() -> Json<BuildInfo> {
+    Json(BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_commit: option_env!("BUILD_GIT_COMMIT"),
+        built_at: env!("BUILD_TIMESTAMP"),
+    })
+}
+
+async fn metrics_handler(State(state): State<Arc<AppState>>) -> String {
+    state.metrics.render()
+}
+
+#[cfg(feature = "redis")]
+async fn connect_event_store(service: &ServiceConfig) -> Result<Arc<dyn EventStore>, String> {
+    match &service.redis_url {
+        Some(url) => {
+            let store = redis_store::RedisEventStore::connect(url, service.redis_session_ttl, service.max_events_per_session)
+                .await
+                .map_err(|e| format!("Failed to connect to Redis: {}", e))?;
+            info!("Using Redis event store");
+            if service.store_capacity.is_some() {
+                warn!("MAX_STORED_EVENTS only applies to the in-memory store; bound Redis with its own maxmemory");
+            }
+            match &service.write_behind {
+                Some(config) => {
+                    info!("Writing events to Redis in the background, {} per batch", config.batch_size);
+                    let store = write_behind::WriteBehindStore::start(
+                        Arc::new(store),
+                        config,
+                        service.max_events_per_session,
+                        service.redis_session_ttl,
+                    );
+                    Ok(Arc::new(store))
+                }
+                None => Ok(Arc::new(store)),
+            }
+        }
+        None => {
+            if service.write_behind.is_some() {
+                warn!("EVENT_WRITE_BEHIND has no effect without REDIS_URL");
+            }
+            Ok(Arc::new(MemoryEventStore::new(service.max_events_per_session, service.store_capacity.clone())))
+        }
+    }
+}
+
+#[cfg(not(feature = "redis"))]
+async fn connect_event_store(service: &ServiceConfig) -> Result<Arc<dyn EventStore>, String> {
+    if service.redis_url.is_some() {
+        return Err("REDIS_URL is set but this build was compiled without the `redis` feature".to_string());
+    }
+    if service.write_behind.is_some() {
+        warn!("EVENT_WRITE_BEHIND has no effect without REDIS_URL");
+    }
+    Ok(Arc::new(MemoryEventStore::new(service.max_events_per_session, service.store_capacity.clone())))
+}
+
+#[cfg(feature = "redis")]
+async fn connect_activity_store(service: &ServiceConfig) -> Result<Arc<dyn ActivityStore>, String> {
+    match &service.redis_url {
+        Some(url) => {
+            let store = redis_store::RedisActivityStore::connect(url, service.activity_profile_ttl)
+                .await
+                .map_err(|e| format!("Failed to connect to Redis: {}", e))?;
+            Ok(Arc::new(store))
+        }
+        None => Ok(Arc::new(MemoryActivityStore::new(service.activity_profile_ttl))),
+    }
+}
+
+#[cfg(not(feature = "redis"))]
+async fn connect_activity_store(service: &ServiceConfig) -> Result<Arc<dyn ActivityStore>, String> {
+    Ok(Arc::new(MemoryActivityStore::new(service.activity_profile_ttl)))
+}
+
+/// Entity reputations persist in Redis when there is one, so they outlive restarts.
+#[cfg(feature = "redis")]
+async fn connect_entity_reputation_store(service: &ServiceConfig) -> Result<Arc<dyn EntityReputationStore>, String> {
+    match &service.redis_url {
+        Some(url) => {
+            let store = redis_store::RedisEntityReputationStore::connect(url)
+                .await
+                .map_err(|e| format!("Failed to connect to Redis: {}", e))?;
+            Ok(Arc::new(store))
+        }
+        None => Ok(Arc::new(MemoryEntityReputationStore::default())),
+    }
+}
+
+#[cfg(not(feature = "redis"))]
+async fn connect_entity_reputation_store(_service: &ServiceConfig) -> Result<Arc<dyn EntityReputationStore>, String> {
+    Ok(Arc::new(MemoryEntityReputationStore::default()))
+}
+
+/// Usage counts persist in Redis when there is one, and live in memory alone otherwise.
+#[cfg(feature = "redis")]
+async fn connect_usage_store(service: &ServiceConfig) -> Result<Option<Arc<dyn UsageStore>>, String> {
+    match &service.redis_url {
+        Some(url) => {
+            let ttl = Duration::from_secs(u64::from(service.usage.retention_days) * 86_400);
+            let store = redis_store::RedisUsageStore::connect(url, ttl)
+                .await
+                .map_err(|e| format!("Failed to connect to Redis: {}", e))?;
+            Ok(Some(Arc::new(store)))
+        }
+        None => Ok(None),
+    }
+}
+
+#[cfg(not(feature = "redis"))]
+async fn connect_usage_store(_service: &ServiceConfig) -> Result<Option<Arc<dyn UsageStore>>, String> {
+    Ok(None)
+}
+
+/// Browser access for admin tooling hosted on other origins. Strict (no layer at all)
+/// unless origins are configured.
+fn cors_layer(service: &ServiceConfig) -> Option<CorsLayer> {
+    if service.allowed_origins.is_empty() {
+        return None;
+    }
+
+    Some(
+        CorsLayer::new()
+            .allow_origin(AllowOrigin::list(service.allowed_origins.clone()))
+            .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+            .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION, request_id::REQUEST_ID_HEADER.clone()])
+            .expose_headers([request_id::REQUEST_ID_HEADER.clone()]),
+    )
+}
+
+/// Periodically drops expired per-user state (flag counts, payment totals, linked
+/// sessions, and activity profiles), idle device fingerprints, faded session and
+/// entity reputations, expired search index entries, and expired temporary bans, so
+/// users who never come back don't stay in memory.
+async fn sweep_user_state(state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(USER_STATE_SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        state.offenders.sweep();
+        let window = chrono::Duration::seconds(state.scoring().payments.window_secs as i64);
+        state.payments.sweep(state.clock.now(), window);
+        let window = state.scoring().concurrent_sessions.window();
+        state.linked_sessions.sweep(state.clock.now(), window);
+        let window = state.scoring().device_fingerprint.window();
+        state.fingerprints.sweep(state.clock.now(), window);
+        state.rates.sweep(state.clock.now());
+        state.activity.sweep(state.clock.now());
+        state.ip_blacklist.prune_expired();
+        if let Some(half_life) = state.scoring().entity_reputation.half_life() {
+            state.entity_reputations.sweep(state.clock.now(), half_life);
+        }
+        state.usage.sweep(state.clock.now());
+        if let Some(half_life) = state.scoring().session_reputation.half_life() {
+            state.session_reputation.sweep(half_life);
+        }
+        state.rollouts.sweep(state.service.redis_session_ttl);
+        let session_ttl = chrono::Duration::from_std(state.service.redis_session_ttl).unwrap_or(chrono::Duration::MAX);
+        state.search.sweep(state.clock.now() - session_ttl);
+        if let Some(sampler) = &state.sampler {
+            sampler.sweep(state.clock.now() - session_ttl);
+        }
+        if let Some(cache) = &state.session_cache {
+            cache.sweep();
+        }
+    }
+}
+
+/// All API routes plus the static frontend, with request ids and (when configured) CORS.
+pub fn router(state: Arc<AppState>) -> Router {
+    let cors = cors_layer(&state.service);
+    let admitted = middleware::from_fn_with_state(state.clone(), admission::limit_in_flight);
+    let cached = middleware::from_fn_with_state(state.clone(), response_cache::reuse_responses);
+    let metered = middleware::from_fn_with_state(state.clone(), usage::meter_bytes);
+    let app = Router::new()
+        .route(
+            "/api/v1/events",
+            post(analyze_event_handler)
+                .route_layer(metered.clone())
+                .route_layer(admitted.clone())
+                .route_layer(middleware::from_fn(api_v2::deprecate_v1)),
+        )
+        .route(
+            "/api/v2/events",
+            post(api_v2::analyze_event_handler)
+                .route_layer(metered)
+                .route_layer(admitted.clone()),
+        )
+        .route(
+            "/api/v1/events/explain",
+            post(explain_event_handler).route_layer(admitted).route_layer(cached.clone()),
+        )
+        .route(
+            "/api/v1/simulate",
+            post(simulate::simulate_handler)
+                .route_layer(cached.clone())
+                .layer(DefaultBodyLimit::max(simulate::MAX_SIMULATION_BYTES)),
+        )
+        .route(
+            "/api/v1/simulate/diff",
+            post(simulate::diff_handler)
+                .route_layer(cached)
+                .layer(DefaultBodyLimit::max(simulate::MAX_SIMULATION_BYTES)),
+        )
+        .route(
+            "/api/v1/blacklist/import",
+            post(blacklist::import_handler).layer(DefaultBodyLimit::max(blacklist::MAX_IMPORT_BYTES)),
+        )
+        .route("/api/v1/blacklist", post(blacklist::add_handler))
+        .route("/api/v1/blacklist/export", get(blacklist::export_handler))
+        .route("/api/v1/sources", get(sources::list_handler))
+        .route(
+            "/api/v1/sources/:category/import",
+            post(sources::import_handler).layer(DefaultBodyLimit::max(blacklist::MAX_IMPORT_BYTES)),
+        )
+        .route("/api/v1/sources/:category/export", get(sources::export_handler))
+        .route(
+            "/api/v1/allowlist",
+            get(allowlist::list_handler)
+                .post(allowlist::add_handler)
+                .delete(allowlist::remove_handler),
+        )
+        .route("/api/v1/export", get(export::export_handler))
+        .route("/api/v1/flags/export", get(flags::export_handler))
+        .route("/api/v1/users/:user_id/data", delete(users::delete_user_data_handler))
+        .route("/api/v1/users/:user_id/sessions", get(users::user_sessions_handler))
+        .route(
+            "/api/v1/reputation/ip/:ip",
+            get(entity_reputation::ip_reputation_handler).delete(entity_reputation::reset_ip_reputation_handler),
+        )
+        .route(
+            "/api/v1/reputation/user/:user_id",
+            get(entity_reputation::user_reputation_handler).delete(entity_reputation::reset_user_reputation_handler),
+        )
+        .route("/api/v1/webhooks/deliveries", get(webhooks::list_deliveries_handler))
+        .route("/api/v1/webhooks/deliveries/:id/retry", post(webhooks::retry_delivery_handler))
+        .route("/api/v1/sessions/:session_id/features", get(features::session_features_handler))
+        .route("/api/v1/features/batch", post(features::batch_features_handler))
+        .route("/api/v1/stats", get(stats::stats_handler))
+        .route("/api/v1/stats/score-histogram", get(stats::score_histogram_handler))
+        .route("/api/v1/rules", get(rules_handler))
+        .route("/api/v1/config/reload", post(reload_config_handler))
+        .route("/api/v1/rules/report", get(stats::rule_report_handler))
+        .route("/api/v1/search", get(search::search_handler))
+        .route("/api/v1/usage", get(usage::usage_handler))
+        .route("/api/v1/stream", get(stream::stream_handler))
+        // Probes stay outside the request cap, so a busy instance isn't taken for a dead one.
+        .route_layer(middleware::from_fn_with_state(state.clone(), admission::shed_excess))
+        .route("/api/v1/ready", get(readiness_handler))
+        .route("/api/v1/version", get(version_handler))
+        .route("/metrics", get(metrics_handler))
+        .fallback_service(
+            ServeDir::new("../frontend")
+                .fallback(ServeFile::new("../frontend/index.html"))
+        )
+        .layer(DefaultBodyLimit::max(state.service.max_body_bytes))
+        .layer(middleware::from_fn_with_state(state.clone(), timeout::enforce_deadline))
+        .layer(middleware::from_fn_with_state(state.clone(), request_id::propagate_request_id))
+        .with_state(state);
+
+    match cors {
+        Some(cors) => app.layer(cors),
+        None => app,
+    }
+}
+
+// --- SERVER ---
+/// Loads the service and scoring configs from the environment, builds the state from
+/// them, and checks the event store answers. Everything a misconfigured deployment
+/// would otherwise only find out on its first request.
+async fn self_test(metrics: PrometheusHandle) -> Result<AppState, String> {
+    let service = ServiceConfig::from_env().map_err(|e| format!("Invalid service config: {}", e))?;
+    let scoring = ScoringConfig::from_env().map_err(|e| format!("Invalid scoring config: {}", e))?;
+    let state = AppState::new(service, scoring, metrics).await?;
+    state
+        .event_store
+        .size()
+        .await
+        .map_err(|e| format!("Event store is unreachable: {}", e))?;
+    Ok(state)
+}
+
+/// Runs the startup self-test and stops, for `--check-config`.
+pub async fn check_config() -> Result<(), String> {
+    self_test(PrometheusBuilder::new().build_recorder().handle()).await?;
+    info!("Configuration OK");
+    Ok(())
+}
+
+/// Loads configuration from the environment, starts the background tasks, and serves
+/// the API on port 8080 (and gRPC on `GRPC_PORT`) until Ctrl-C or SIGTERM, letting
+/// in-flight requests on both finish. Fails, before binding any port, if the startup
+/// self-test does.
+pub async fn run() -> Result<(), String> {
+    let metrics = PrometheusBuilder::new()
+        .install_recorder()
+        .map_err(|e| format!("Failed to install metrics recorder: {}", e))?;
+
+    let state = Arc::new(self_test(metrics).await?);
+
+    if let Some(sync) = state.service.blacklist_sync.clone() {
+        info!("Syncing blacklist from {} every {:?}", sync.url, sync.interval);
+        tokio::spawn(blacklist::run_sync(state.ip_blacklist.clone(), "Blacklist", sync));
+    }
+    for (category, config) in &state.service.source_lists {
+        if let Some(sync) = config.sync.clone() {
+            info!("Syncing {} from {} every {:?}", category.label(), sync.url, sync.interval);
+            let list = state.suspicious_sources.list(*category).clone();
+            tokio::spawn(blacklist::run_sync(list, category.label(), sync));
+        }
+    }
+    tokio::spawn(sweep_user_state(state.clone()));
+    tokio::spawn(usage::run_flush(state.usage.clone()));
+    #[cfg(unix)]
+    tokio::spawn(reload_on_hangup(state.clone()));
+
+    // Either server stopping, or a shutdown signal, stops both.
+    let shutdown = Arc::new(watch::channel(false).0);
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            shutdown_signal().await;
+            info!("Shutdown signal received, finishing in-flight requests");
+            shutdown.send_replace(true);
+        }
+    });
+
+    let http = async {
+        let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
+        info!("Starting server, listening on {}", addr);
+        axum::Server::bind(&addr)
+            .serve(router(state.clone()).into_make_service_with_connect_info::<SocketAddr>())
+            .with_graceful_shutdown(stopped(shutdown.subscribe()))
+            .await
+            .unwrap();
+        shutdown.send_replace(true);
+    };
+    let grpc = async {
+        if let Some(config) = state.service.grpc.clone() {
+            grpc_api::serve(state.clone(), config, stopped(shutdown.subscribe())).await;
+            shutdown.send_replace(true);
+        }
+    };
+    tokio::join!(http, grpc);
+    // Counted since the last flush; would otherwise go unbilled.
+    state.usage.flush().await;
+    Ok(())
+}
+
+/// Resolves on Ctrl-C, or on SIGTERM where there is one.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("Failed to listen for Ctrl-C");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Reloads the scoring config on every `SIGHUP`.
+#[cfg(unix)]
+async fn reload_on_hangup(state: Arc<AppState>) {
+    let mut hangups = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+        .expect("Failed to listen for SIGHUP");
+    while hangups.recv().await.is_some() {
+        if let Err(e) = state.reload_scoring() {
+            warn!("Scoring config reload failed, keeping the running config: {}", e);
+        }
+    }
+}
+
+async fn stopped(mut shutdown: watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|stopping| *stopping).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn event_with_ip(ip: &str) -> Result<UserEvent, serde_json::Error> {
+        serde_json::from_value(json!({
+            "sessionId": "s1",
+            "eventType": "PageLoad",
+            "timestamp": "2024-05-01T12:00:00Z",
+            "ipAddress": ip,
+        }))
+    }
+
+    #[test]
+    fn accepts_ipv4_addresses() {
+        assert_eq!(event_with_ip("203.0.113.9").unwrap().ip_address, "203.0.113.9");
+    }
+
+    #[test]
+    fn accepts_ipv6_addresses_in_canonical_form() {
+        assert_eq!(event_with_ip("2001:DB8:0:0::1").unwrap().ip_address, "2001:db8::1");
+    }
+
+    #[test]
+    fn rejects_strings_that_are_not_ips() {
+        let error = event_with_ip("not-an-ip").unwrap_err().to_string();
+        assert!(error.contains("invalid IP address `not-an-ip`"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_hostnames() {
+        assert!(event_with_ip("example.com").is_err());
+    }
+}
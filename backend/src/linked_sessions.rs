@@ -0,0 +1,155 @@
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Tracker size above which idle keys are swept on insert.
+const SWEEP_THRESHOLD: usize = 100_000;
+/// Sessions remembered per key; the least recently active go first beyond this.
+const MAX_SESSIONS_PER_KEY: usize = 1_000;
+
+// --- LINKED SESSION TRACKING ---
+/// Remembers, per key, when each session under it last had a scored event, so one actor
+/// spreading activity over many parallel sessions can be seen as such. Keyed by user id
+/// for concurrent sessions, and by device fingerprint for fingerprint collisions.
+#[derive(Default)]
+pub struct LinkedSessionTracker {
+    keys: Mutex<HashMap<String, HashMap<String, DateTime<Utc>>>>,
+}
+
+impl LinkedSessionTracker {
+    /// The key's other sessions with an event within `window` of `now`, most recently
+    /// active first.
+    pub fn active_sessions(&self, key: &str, session_id: &str, now: DateTime<Utc>, window: Duration) -> Vec<String> {
+        let keys = self.keys.lock().unwrap();
+        let Some(sessions) = keys.get(key) else {
+            return Vec::new();
+        };
+        let mut active: Vec<(&String, &DateTime<Utc>)> = sessions
+            .iter()
+            .filter(|(id, last_seen)| id.as_str() != session_id && **last_seen > now - window)
+            .collect();
+        active.sort_unstable_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        active.into_iter().map(|(id, _)| id.clone()).collect()
+    }
+
+    pub fn record(&self, key: &str, session_id: &str, at: DateTime<Utc>, window: Duration) {
+        let mut keys = self.keys.lock().unwrap();
+        if keys.len() >= SWEEP_THRESHOLD {
+            prune(&mut keys, at - window);
+        }
+
+        let sessions = keys.entry(key.to_string()).or_default();
+        let last_seen = sessions.entry(session_id.to_string()).or_insert(at);
+        *last_seen = (*last_seen).max(at);
+        if sessions.len() > MAX_SESSIONS_PER_KEY {
+            if let Some(oldest) = sessions.iter().min_by_key(|(_, last_seen)| **last_seen).map(|(id, _)| id.clone()) {
+                sessions.remove(&oldest);
+            }
+        }
+    }
+
+    /// Drops the key's sessions, returning whether it had any.
+    pub fn forget(&self, key: &str) -> bool {
+        self.keys.lock().unwrap().remove(key).is_some()
+    }
+
+    /// Drops the session from every key, and keys left with none, returning how many
+    /// keys it was under.
+    pub fn unlink_session(&self, session_id: &str) -> usize {
+        let mut unlinked = 0;
+        self.keys.lock().unwrap().retain(|_, sessions| {
+            unlinked += usize::from(sessions.remove(session_id).is_some());
+            !sessions.is_empty()
+        });
+        unlinked
+    }
+
+    /// Forgets sessions idle for longer than `window`, and keys left with none.
+    pub fn sweep(&self, now: DateTime<Utc>, window: Duration) {
+        prune(&mut self.keys.lock().unwrap(), now - window);
+    }
+}
+
+fn prune(keys: &mut HashMap<String, HashMap<String, DateTime<Utc>>>, cutoff: DateTime<Utc>) {
+    keys.retain(|_, sessions| {
+        sessions.retain(|_, last_seen| *last_seen > cutoff);
+        !sessions.is_empty()
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_714_564_800 + secs, 0).unwrap()
+    }
+
+    const WINDOW: Duration = Duration::minutes(10);
+
+    #[test]
+    fn other_active_sessions_come_most_recent_first() {
+        let tracker = LinkedSessionTracker::default();
+        tracker.record("device", "a", at(0), WINDOW);
+        tracker.record("device", "b", at(60), WINDOW);
+        tracker.record("device", "c", at(30), WINDOW);
+        tracker.record("elsewhere", "d", at(60), WINDOW);
+        assert_eq!(tracker.active_sessions("device", "c", at(90), WINDOW), ["b", "a"]);
+        assert!(tracker.active_sessions("unknown", "c", at(90), WINDOW).is_empty());
+    }
+
+    #[test]
+    fn sessions_idle_past_the_window_are_not_active() {
+        let tracker = LinkedSessionTracker::default();
+        tracker.record("device", "a", at(0), WINDOW);
+        tracker.record("device", "b", at(300), WINDOW);
+        assert_eq!(tracker.active_sessions("device", "x", at(600), WINDOW), ["b"]);
+        // An out-of-order event doesn't move a session's last activity back.
+        tracker.record("device", "b", at(100), WINDOW);
+        assert_eq!(tracker.active_sessions("device", "x", at(899), WINDOW), ["b"]);
+    }
+
+    #[test]
+    fn sweeping_forgets_idle_sessions_and_empty_keys() {
+        let tracker = LinkedSessionTracker::default();
+        tracker.record("old", "a", at(0), WINDOW);
+        tracker.record("mixed", "b", at(0), WINDOW);
+        tracker.record("mixed", "c", at(500), WINDOW);
+        tracker.sweep(at(700), WINDOW);
+        let keys = tracker.keys.lock().unwrap();
+        assert!(!keys.contains_key("old"));
+        assert_eq!(keys["mixed"].keys().collect::<Vec<_>>(), ["c"]);
+    }
+
+    #[test]
+    fn forgetting_a_key_drops_its_sessions() {
+        let tracker = LinkedSessionTracker::default();
+        tracker.record("user", "a", at(0), WINDOW);
+        assert!(tracker.forget("user"));
+        assert!(!tracker.forget("user"));
+        assert!(tracker.active_sessions("user", "x", at(1), WINDOW).is_empty());
+    }
+
+    #[test]
+    fn a_key_keeps_its_most_recently_active_sessions() {
+        let tracker = LinkedSessionTracker::default();
+        for n in 0..=MAX_SESSIONS_PER_KEY as i64 {
+            tracker.record("device", &format!("s{}", n), at(n), Duration::days(1));
+        }
+        let active = tracker.active_sessions("device", "x", at(2_000), Duration::days(1));
+        assert_eq!(active.len(), MAX_SESSIONS_PER_KEY);
+        assert!(!active.contains(&"s0".to_string()));
+    }
+
+    #[test]
+    fn unlinking_a_session_drops_it_under_every_key() {
+        let tracker = LinkedSessionTracker::default();
+        tracker.record("device", "a", at(0), WINDOW);
+        tracker.record("device", "b", at(0), WINDOW);
+        tracker.record("other-device", "a", at(0), WINDOW);
+        assert_eq!(tracker.unlink_session("a"), 2);
+        assert_eq!(tracker.unlink_session("a"), 0);
+        assert_eq!(tracker.active_sessions("device", "x", at(1), WINDOW), ["b"]);
+        assert!(!tracker.keys.lock().unwrap().contains_key("other-device"));
+    }
+}
@@ -0,0 +1,58 @@
+use metrics::counter;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// --- LOG SAMPLING ---
+/// Thins out the log line written per analysis: flagged analyses are always logged, and
+/// clean ones one in `rate`, counting across HTTP and gRPC.
+pub struct LogSampler {
+    rate: u64,
+    clean: AtomicU64,
+}
+
+impl LogSampler {
+    pub fn new(rate: u64) -> Self {
+        Self {
+            rate: rate.max(1),
+            clean: AtomicU64::new(0),
+        }
+    }
+
+    pub fn should_log(&self, flagged: bool) -> bool {
+        if flagged || self.rate == 1 {
+            return true;
+        }
+        let logged = self.clean.fetch_add(1, Ordering::Relaxed).is_multiple_of(self.rate);
+        if !logged {
+            counter!("analysis_logs_sampled_out_total").increment(1);
+        }
+        logged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(sampler: &LogSampler, flagged: &[bool]) -> Vec<bool> {
+        flagged.iter().map(|&flagged| sampler.should_log(flagged)).collect()
+    }
+
+    #[test]
+    fn logs_the_first_of_every_n_clean_analyses() {
+        let sampler = LogSampler::new(3);
+        assert_eq!(pattern(&sampler, &[false; 7]), [true, false, false, true, false, false, true]);
+    }
+
+    #[test]
+    fn flagged_analyses_are_logged_without_advancing_the_count() {
+        let sampler = LogSampler::new(2);
+        assert_eq!(pattern(&sampler, &[false, true, true, false, false]), [true, true, true, false, true]);
+    }
+
+    #[test]
+    fn a_rate_of_one_or_less_logs_everything() {
+        for rate in [0, 1] {
+            assert!(pattern(&LogSampler::new(rate), &[false; 4]).into_iter().all(|logged| logged));
+        }
+    }
+}
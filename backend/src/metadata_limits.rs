@@ -0,0 +1,100 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use crate::config::{MetadataLimitMode, MetadataLimits, MetadataRetention};
+use crate::rules::RuleHit;
+use crate::{AppError, UserEvent, EVENT_ID_KEY};
+
+// --- METADATA LIMITS ---
+/// Holds the event's metadata to `limits`. In `truncate` mode it is cut down in place,
+/// keeping retained keys over others, and the returned zero-point hit says what went.
+pub fn enforce(
+    event: &mut UserEvent,
+    limits: &MetadataLimits,
+    retention: &MetadataRetention,
+) -> Result<Option<RuleHit>, AppError> {
+    let Some(metadata) = &mut event.metadata else {
+        return Ok(None);
+    };
+    let excess_keys = metadata.len().saturating_sub(limits.max_keys);
+    let long_values = metadata.values().filter(|value| value.len() > limits.max_value_bytes).count();
+    if excess_keys == 0 && long_values == 0 {
+        return Ok(None);
+    }
+
+    // Keys are attacker-controlled, so messages and reasons only give counts.
+    if limits.mode == MetadataLimitMode::Reject {
+        let message = match excess_keys {
+            0 => format!(
+                "{} metadata values are longer than {} bytes",
+                long_values, limits.max_value_bytes
+            ),
+            _ => format!("Metadata has {} keys; at most {} are allowed", metadata.len(), limits.max_keys),
+        };
+        return Err(AppError::Validation(message));
+    }
+
+    if excess_keys > 0 {
+        let mut keys: Vec<String> = metadata.keys().cloned().collect();
+        keys.sort_unstable_by(|a, b| (Reverse(retention.keeps(a)), a).cmp(&(Reverse(retention.keeps(b)), b)));
+        for key in &keys[limits.max_keys..] {
+            metadata.remove(key);
+        }
+    }
+    let mut shortened = 0;
+    for value in metadata.values_mut() {
+        if value.len() > limits.max_value_bytes {
+            let mut end = limits.max_value_bytes;
+            while !value.is_char_boundary(end) {
+                end -= 1;
+            }
+            value.truncate(end);
+            shortened += 1;
+        }
+    }
+
+    Ok(Some(RuleHit {
+        code: "METADATA_TRUNCATED".into(),
+        points: 0,
+        reason: format!(
+            "Metadata truncated: {} keys dropped, {} values shortened to {} bytes",
+            excess_keys, shortened, limits.max_value_bytes
+        ),
+        children: Vec::new(),
+    }))
+}
+
+// --- METADATA RETENTION ---
+impl MetadataRetention {
+    fn keeps(&self, key: &str) -> bool {
+        key == EVENT_ID_KEY
+            || match self {
+                MetadataRetention::All => true,
+                MetadataRetention::Keys(keys) => keys.contains(key),
+            }
+    }
+
+    /// The copy of `event` to store, or `None` when it would keep all its metadata.
+    pub fn stored_copy(&self, event: &UserEvent) -> Option<UserEvent> {
+        let metadata = event.metadata.as_ref()?;
+        if metadata.keys().all(|key| self.keeps(key)) {
+            return None;
+        }
+        let retained: HashMap<String, String> = metadata
+            .iter()
+            .filter(|(key, _)| self.keeps(key))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+
+        Some(UserEvent {
+            session_id: event.session_id.clone(),
+            user_id: event.user_id.clone(),
+            event_type: event.event_type.clone(),
+            timestamp: event.timestamp,
+            ip_address: event.ip_address.clone(),
+            user_agent: event.user_agent.clone(),
+            metadata: (!retained.is_empty()).then_some(retained),
+            asn: event.asn,
+        })
+    }
+}
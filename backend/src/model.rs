@@ -0,0 +1,137 @@
+use async_trait::async_trait;
+use metrics::counter;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tract_onnx::prelude::*;
+use tracing::warn;
+
+use crate::breaker::CircuitBreaker;
+use crate::config::{ModelConfig, ModelSource};
+use crate::degradation::Unavailable;
+use crate::features::{FeatureVector, FEATURE_NAMES};
+use crate::model_grpc::GrpcModel;
+
+// --- MODEL SCORING ---
+/// Where fraud probabilities come from. Backends only report errors; the latency budget
+/// and the fallback to rules-only scoring are handled once, in `ModelScorer`.
+#[async_trait]
+pub trait ModelBackend: Send + Sync {
+    /// Short name used as the `backend` metric label.
+    fn name(&self) -> &'static str;
+
+    /// The fraud probability (0-1) for one feature vector.
+    async fn infer(&self, features: FeatureVector) -> Result<f32, String>;
+}
+
+pub struct ModelScorer {
+    backend: Box<dyn ModelBackend>,
+    timeout: Duration,
+    /// Trips on errors and missed budgets alike, so a struggling model stops costing
+    /// every event its full budget.
+    breaker: CircuitBreaker,
+}
+
+impl ModelScorer {
+    pub fn load(config: &ModelConfig) -> Result<Self, String> {
+        let backend: Box<dyn ModelBackend> = match &config.source {
+            ModelSource::Onnx(path) => Box::new(OnnxModel::load(path).map_err(|e| e.to_string())?),
+            ModelSource::Grpc(addr) => Box::new(GrpcModel::connect(addr)?),
+        };
+        Ok(Self {
+            backend,
+            timeout: config.timeout,
+            breaker: CircuitBreaker::new("model", config.breaker_failures, config.breaker_cooldown),
+        })
+    }
+
+    /// The model's fraud probability, or `Unavailable` when inference fails, misses the
+    /// latency budget, or is skipped by the open breaker.
+    pub async fn predict(&self, features: FeatureVector) -> Result<f32, Unavailable> {
+        let backend = self.backend.name();
+        if !self.breaker.allow() {
+            counter!("model_inference_skipped_total", "backend" => backend, "reason" => "circuit_open").increment(1);
+            return Err(Unavailable);
+        }
+        let outcome = match tokio::time::timeout(self.timeout, self.backend.infer(features)).await {
+            Ok(Ok(probability)) if (0.0..=1.0).contains(&probability) => Ok(probability),
+            Ok(Ok(probability)) => Err(format!("model output {} is not a probability", probability)),
+            Ok(Err(e)) => Err(e),
+            Err(_) => {
+                self.breaker.record_failure();
+                counter!("model_inference_total", "backend" => backend, "outcome" => "timeout").increment(1);
+                return Err(Unavailable);
+            }
+        };
+
+        match outcome {
+            Ok(probability) => {
+                self.breaker.record_success();
+                counter!("model_inference_total", "backend" => backend, "outcome" => "ok").increment(1);
+                Ok(probability)
+            }
+            Err(e) => {
+                self.breaker.record_failure();
+                counter!("model_inference_total", "backend" => backend, "outcome" => "error").increment(1);
+                warn!("Model inference failed ({}): {}", backend, e);
+                Err(Unavailable)
+            }
+        }
+    }
+}
+
+// --- ONNX BACKEND ---
+type Plan = TypedRunnableModel<TypedModel>;
+
+/// An ONNX classifier run in-process. The model takes one `[1, N]` float input in
+/// `FEATURE_NAMES` order; its first float output is either the fraud probability
+/// (`[1]`/`[1, 1]`) or both class probabilities (`[1, 2]`).
+struct OnnxModel {
+    plan: Arc<Plan>,
+}
+
+impl OnnxModel {
+    /// Loads and optimizes the model, then runs it once so a model with the wrong input
+    /// or output shape fails here rather than on every event.
+    fn load(path: &Path) -> TractResult<Self> {
+        let plan = tract_onnx::onnx()
+            .model_for_path(path)?
+            .with_input_fact(0, f32::fact([1, FEATURE_NAMES.len()]).into())?
+            .into_optimized()?
+            .into_runnable()?;
+        infer_onnx(&plan, [0.0; FEATURE_NAMES.len()])?;
+
+        Ok(Self { plan: Arc::new(plan) })
+    }
+}
+
+#[async_trait]
+impl ModelBackend for OnnxModel {
+    fn name(&self) -> &'static str {
+        "onnx"
+    }
+
+    /// Runs on a blocking thread; an inference that misses the budget finishes there and
+    /// is discarded.
+    async fn infer(&self, features: FeatureVector) -> Result<f32, String> {
+        let plan = self.plan.clone();
+        tokio::task::spawn_blocking(move || infer_onnx(&plan, features))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())
+    }
+}
+
+fn infer_onnx(plan: &Plan, features: FeatureVector) -> TractResult<f32> {
+    let input = Tensor::from_shape(&[1, FEATURE_NAMES.len()], &features)?;
+    let outputs = plan.run(tvec!(input.into()))?;
+
+    let output = outputs
+        .iter()
+        .find(|output| output.datum_type() == f32::datum_type())
+        .ok_or_else(|| TractError::msg("model has no float output"))?;
+    match output.as_slice::<f32>()? {
+        [probability] | [_, probability] => Ok(*probability),
+        values => Err(TractError::msg(format!("expected 1 or 2 output probabilities, got {}", values.len()))),
+    }
+}
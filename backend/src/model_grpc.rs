@@ -0,0 +1,71 @@
+use async_trait::async_trait;
+use tonic::codec::ProstCodec;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::transport::{Channel, Endpoint};
+
+use crate::features::{FeatureVector, FEATURE_NAMES, FEATURE_VERSION};
+use crate::model::ModelBackend;
+
+// --- GRPC MODEL BACKEND ---
+/// Messages from `proto/fraud_model.proto`, written out by hand so the build doesn't
+/// need `protoc`. Keep the tags in sync with the proto file.
+#[derive(Clone, PartialEq, prost::Message)]
+struct ScoreRequest {
+    #[prost(string, repeated, tag = "1")]
+    feature_names: Vec<String>,
+    #[prost(float, repeated, tag = "2")]
+    features: Vec<f32>,
+    #[prost(uint32, tag = "3")]
+    feature_version: u32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ScoreResponse {
+    #[prost(float, tag = "1")]
+    probability: f32,
+}
+
+const SCORE_PATH: &str = "/fraud.model.v1.FraudModel/Score";
+
+/// A model served by an external `fraud.model.v1.FraudModel` service.
+pub struct GrpcModel {
+    channel: Channel,
+}
+
+impl GrpcModel {
+    /// Connects lazily, so the service starts (and scores rules-only) while the model
+    /// server is down, and reconnects on its own once it's back.
+    pub fn connect(addr: &str) -> Result<Self, String> {
+        let endpoint = Endpoint::from_shared(addr.to_string()).map_err(|e| format!("invalid address `{}`: {}", addr, e))?;
+        Ok(Self {
+            channel: endpoint.connect_lazy(),
+        })
+    }
+}
+
+#[async_trait]
+impl ModelBackend for GrpcModel {
+    fn name(&self) -> &'static str {
+        "grpc"
+    }
+
+    async fn infer(&self, features: FeatureVector) -> Result<f32, String> {
+        let mut client = tonic::client::Grpc::new(self.channel.clone());
+        client.ready().await.map_err(|e| format!("model server unavailable: {}", e))?;
+
+        let request = ScoreRequest {
+            feature_names: FEATURE_NAMES.iter().map(|name| name.to_string()).collect(),
+            features: features.to_vec(),
+            feature_version: FEATURE_VERSION,
+        };
+        let response = client
+            .unary(
+                tonic::Request::new(request),
+                PathAndQuery::from_static(SCORE_PATH),
+                ProstCodec::<ScoreRequest, ScoreResponse>::default(),
+            )
+            .await
+            .map_err(|status| format!("{}: {}", status.code(), status.message()))?;
+        Ok(response.into_inner().probability)
+    }
+}
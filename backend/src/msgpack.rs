@@ -0,0 +1,241 @@
+use axum::{
+    async_trait,
+    body::{Bytes, HttpBody},
+    extract::FromRequest,
+    http::{header, HeaderMap, Request},
+    response::{IntoResponse, Response},
+    BoxError, Json,
+};
+use chrono::{DateTime, Utc};
+use serde::de::{self, DeserializeOwned, Deserializer, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::error::AppJson;
+use crate::protobuf::has_media_type;
+use crate::AppError;
+
+// --- MESSAGEPACK BODIES ---
+pub const MSGPACK_CONTENT_TYPE: &str = "application/msgpack";
+/// Accepted too, since many clients still send the pre-registration name.
+const MSGPACK_CONTENT_TYPES: &[&str] = &[MSGPACK_CONTENT_TYPE, "application/x-msgpack"];
+
+/// MessagePack's reserved extension type for timestamps.
+const TIMESTAMP_EXT: i8 = -1;
+
+/// Malformed bytes are `400`, like unparseable JSON; a body of the wrong shape (a
+/// missing field, an invalid IP) is a validation error.
+fn rejection(e: rmp_serde::decode::Error) -> AppError {
+    use rmp_serde::decode::Error;
+    let message = format!("Failed to deserialize the MessagePack body: {}", e);
+    match e {
+        Error::TypeMismatch(_) | Error::OutOfRange | Error::LengthMismatch(_) | Error::Uncategorized(_) | Error::Syntax(_) => {
+            AppError::Validation(message)
+        }
+        Error::InvalidMarkerRead(_) | Error::InvalidDataRead(_) | Error::Utf8Error(_) | Error::DepthLimitExceeded => {
+            AppError::BadRequest(message)
+        }
+    }
+}
+
+/// A request body in MessagePack when the `Content-Type` says so, JSON otherwise. Both
+/// decode into the same type through its serde impls.
+pub struct AppBody<T>(pub T);
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for AppBody<T>
+where
+    T: DeserializeOwned,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, AppError> {
+        if !sends_msgpack(req.headers()) {
+            let AppJson(value) = AppJson::from_request(req, state).await?;
+            return Ok(AppBody(value));
+        }
+        let body = Bytes::from_request(req, state).await?;
+        rmp_serde::from_slice(&body).map(AppBody).map_err(rejection)
+    }
+}
+
+/// Whether the request body is MessagePack.
+pub fn sends_msgpack(headers: &HeaderMap) -> bool {
+    has_media_type(headers.get(header::CONTENT_TYPE), MSGPACK_CONTENT_TYPES)
+}
+
+/// Whether the client asked for a MessagePack response.
+pub fn accepts_msgpack(headers: &HeaderMap) -> bool {
+    has_media_type(headers.get(header::ACCEPT), MSGPACK_CONTENT_TYPES)
+}
+
+/// MessagePack when the client asked for it, JSON otherwise. Maps keep their field
+/// names, so both encodings have the same shape. Errors stay JSON.
+pub fn respond<T: Serialize>(headers: &HeaderMap, value: T) -> Result<Response, AppError> {
+    if !accepts_msgpack(headers) {
+        return Ok(Json(value).into_response());
+    }
+    let body = rmp_serde::to_vec_named(&value).map_err(|e| AppError::Internal(format!("MessagePack encoding failed: {}", e)))?;
+    Ok(([(header::CONTENT_TYPE, MSGPACK_CONTENT_TYPE)], body).into_response())
+}
+
+// --- TIMESTAMPS ---
+/// Event timestamps in JSON are RFC 3339 strings, as before. Binary formats also take
+/// epoch milliseconds and the MessagePack timestamp extension.
+pub fn deserialize_timestamp<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    if deserializer.is_human_readable() {
+        return DateTime::<Utc>::deserialize(deserializer);
+    }
+    deserializer.deserialize_any(TimestampVisitor)
+}
+
+struct TimestampVisitor;
+
+impl<'de> Visitor<'de> for TimestampVisitor {
+    type Value = DateTime<Utc>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an RFC 3339 string, epoch milliseconds, or a MessagePack timestamp")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        value.parse().map_err(|_| E::custom(format!("invalid timestamp `{}`", value)))
+    }
+
+    fn visit_i64<E: de::Error>(self, millis: i64) -> Result<Self::Value, E> {
+        DateTime::from_timestamp_millis(millis).ok_or_else(|| E::custom(format!("timestamp {} is out of range", millis)))
+    }
+
+    fn visit_u64<E: de::Error>(self, millis: u64) -> Result<Self::Value, E> {
+        let millis = i64::try_from(millis).map_err(|_| E::custom(format!("timestamp {} is out of range", millis)))?;
+        self.visit_i64(millis)
+    }
+
+    /// How rmp-serde hands over extension types: a sequence of the type and its bytes.
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(ExtVisitor)
+    }
+}
+
+struct ExtVisitor;
+
+impl<'de> Visitor<'de> for ExtVisitor {
+    type Value = DateTime<Utc>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a MessagePack timestamp extension")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let invalid = || de::Error::custom("invalid MessagePack timestamp");
+        let kind: i8 = seq.next_element()?.ok_or_else(invalid)?;
+        let ExtData(data) = seq.next_element()?.ok_or_else(invalid)?;
+        if kind != TIMESTAMP_EXT {
+            return Err(de::Error::custom(format!("unsupported MessagePack extension type {}", kind)));
+        }
+        let (secs, nanos) = match data.len() {
+            4 => (i64::from(u32::from_be_bytes(data[..4].try_into().unwrap())), 0),
+            8 => {
+                let packed = u64::from_be_bytes(data[..8].try_into().unwrap());
+                ((packed & 0x3_ffff_ffff) as i64, (packed >> 34) as u32)
+            }
+            12 => (
+                i64::from_be_bytes(data[4..].try_into().unwrap()),
+                u32::from_be_bytes(data[..4].try_into().unwrap()),
+            ),
+            _ => return Err(invalid()),
+        };
+        DateTime::from_timestamp(secs, nanos).ok_or_else(invalid)
+    }
+}
+
+/// The raw bytes of an extension value.
+struct ExtData(Vec<u8>);
+
+impl<'de> Deserialize<'de> for ExtData {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BytesVisitor;
+
+        impl<'de> Visitor<'de> for BytesVisitor {
+            type Value = ExtData;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("extension bytes")
+            }
+
+            fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+                Ok(ExtData(bytes.to_vec()))
+            }
+        }
+
+        deserializer.deserialize_bytes(BytesVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Stamped {
+        #[serde(deserialize_with = "deserialize_timestamp")]
+        t: DateTime<Utc>,
+    }
+
+    /// `{"t": value}` with `value` already encoded.
+    fn decode(value: &[u8]) -> Result<DateTime<Utc>, rmp_serde::decode::Error> {
+        let body = [&[0x81, 0xa1, b't'], value].concat();
+        rmp_serde::from_slice::<Stamped>(&body).map(|stamped| stamped.t)
+    }
+
+    fn at(raw: &str) -> DateTime<Utc> {
+        raw.parse().unwrap()
+    }
+
+    #[test]
+    fn timestamps_decode_from_strings_and_epoch_millis() {
+        assert_eq!(decode(&rmp_serde::to_vec("2024-05-01T12:00:00Z").unwrap()).unwrap(), at("2024-05-01T12:00:00Z"));
+        assert_eq!(decode(&rmp_serde::to_vec(&1_714_564_800_123i64).unwrap()).unwrap(), at("2024-05-01T12:00:00.123Z"));
+        assert!(decode(&rmp_serde::to_vec("yesterday").unwrap()).is_err());
+    }
+
+    #[test]
+    fn timestamps_decode_from_every_extension_width() {
+        let secs = 1_714_564_800u32;
+        let timestamp32 = [&[0xd6, 0xff][..], &secs.to_be_bytes()].concat();
+        assert_eq!(decode(&timestamp32).unwrap(), at("2024-05-01T12:00:00Z"));
+
+        let packed = (250_000_000u64 << 34) | u64::from(secs);
+        let timestamp64 = [&[0xd7, 0xff][..], &packed.to_be_bytes()].concat();
+        assert_eq!(decode(&timestamp64).unwrap(), at("2024-05-01T12:00:00.25Z"));
+
+        let timestamp96 = [&[0xc7, 12, 0xff][..], &5_000_000u32.to_be_bytes(), &i64::from(secs).to_be_bytes()].concat();
+        assert_eq!(decode(&timestamp96).unwrap(), at("2024-05-01T12:00:00.005Z"));
+    }
+
+    #[test]
+    fn other_extensions_are_rejected() {
+        let other = [&[0xd6, 0x05][..], &1_714_564_800u32.to_be_bytes()].concat();
+        assert!(decode(&other).is_err());
+        let odd_width = [&[0xd5, 0xff][..], &[0, 1]].concat();
+        assert!(decode(&odd_width).is_err());
+    }
+
+    #[test]
+    fn results_negotiate_on_either_media_type_name() {
+        for accept in ["application/msgpack", "application/x-msgpack", "text/html, application/msgpack;q=0.9"] {
+            let headers = HeaderMap::from_iter([(header::ACCEPT, accept.parse().unwrap())]);
+            assert!(accepts_msgpack(&headers), "{}", accept);
+        }
+        assert!(!accepts_msgpack(&HeaderMap::new()));
+        let json = HeaderMap::from_iter([(header::ACCEPT, "application/json".parse().unwrap())]);
+        assert!(!accepts_msgpack(&json));
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Tracker size above which expired entries are swept on insert.
+const SWEEP_THRESHOLD: usize = 100_000;
+
+// --- REPEAT OFFENDER TRACKING ---
+/// Counts how often each user has been flagged. A user's count expires `ttl` after
+/// their most recent flag, so the escalation decays for users who stop misbehaving.
+#[derive(Default)]
+pub struct OffenderTracker {
+    flags: Mutex<HashMap<String, (u32, Instant)>>,
+}
+
+impl OffenderTracker {
+    pub fn flag_count(&self, user_id: &str) -> u32 {
+        match self.flags.lock().unwrap().get(user_id) {
+            Some((count, expires_at)) if Instant::now() < *expires_at => *count,
+            _ => 0,
+        }
+    }
+
+    pub fn record_flag(&self, user_id: &str, ttl: Duration) {
+        let now = Instant::now();
+        let mut flags = self.flags.lock().unwrap();
+        if flags.len() >= SWEEP_THRESHOLD {
+            flags.retain(|_, (_, expires_at)| now < *expires_at);
+        }
+
+        let entry = flags.entry(user_id.to_string()).or_insert((0, now));
+        if now >= entry.1 {
+            entry.0 = 0;
+        }
+        entry.0 += 1;
+        entry.1 = now + ttl;
+    }
+
+    /// Drops the user's flag count, returning whether they had one.
+    pub fn forget(&self, user_id: &str) -> bool {
+        self.flags.lock().unwrap().remove(user_id).is_some()
+    }
+
+    /// Forgets users whose flag count has expired.
+    pub fn sweep(&self) {
+        let now = Instant::now();
+        self.flags.lock().unwrap().retain(|_, (_, expires_at)| now < *expires_at);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(86_400);
+
+    #[test]
+    fn counts_each_flag() {
+        let tracker = OffenderTracker::default();
+        assert_eq!(tracker.flag_count("u1"), 0);
+        tracker.record_flag("u1", DAY);
+        tracker.record_flag("u1", DAY);
+        assert_eq!(tracker.flag_count("u1"), 2);
+        assert_eq!(tracker.flag_count("u2"), 0);
+    }
+
+    #[test]
+    fn an_expired_count_starts_over() {
+        let tracker = OffenderTracker::default();
+        tracker.record_flag("u1", Duration::ZERO);
+        tracker.record_flag("u1", Duration::ZERO);
+        assert_eq!(tracker.flag_count("u1"), 0);
+        tracker.record_flag("u1", DAY);
+        assert_eq!(tracker.flag_count("u1"), 1);
+    }
+
+    #[test]
+    fn sweep_and_forget_drop_counts() {
+        let tracker = OffenderTracker::default();
+        tracker.record_flag("expired", Duration::ZERO);
+        tracker.record_flag("active", DAY);
+        tracker.sweep();
+        assert!(!tracker.forget("expired"));
+        assert!(tracker.forget("active"));
+        assert_eq!(tracker.flag_count("active"), 0);
+    }
+}
@@ -0,0 +1,218 @@
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use crate::{EventType, UserEvent};
+
+/// Payment history per (user id, currency): timestamp and amount, oldest first.
+type PaymentLog = HashMap<(String, String), VecDeque<(DateTime<Utc>, f64)>>;
+
+// --- PAYMENT TRACKING ---
+/// Recent payment amounts per user and currency, used for rolling-window velocity.
+#[derive(Default)]
+pub struct PaymentTracker {
+    payments: Mutex<PaymentLog>,
+}
+
+impl PaymentTracker {
+    /// Sum of the user's payments in `currency` within `window` before `at`.
+    pub fn window_total(&self, user_id: &str, currency: &str, at: DateTime<Utc>, window: Duration) -> f64 {
+        let payments = self.payments.lock().unwrap();
+        payments
+            .get(&(user_id.to_string(), currency.to_string()))
+            .map(|entries| {
+                entries
+                    .iter()
+                    .filter(|(timestamp, _)| *timestamp > at - window && *timestamp <= at)
+                    .map(|(_, amount)| amount)
+                    .sum()
+            })
+            .unwrap_or(0.0)
+    }
+
+    pub fn record(&self, user_id: &str, currency: &str, at: DateTime<Utc>, amount: f64, window: Duration) {
+        let mut payments = self.payments.lock().unwrap();
+        let entries = payments.entry((user_id.to_string(), currency.to_string())).or_default();
+        entries.push_back((at, amount));
+        while entries.front().is_some_and(|(timestamp, _)| *timestamp <= at - window) {
+            entries.pop_front();
+        }
+    }
+
+    /// Drops all of the user's payments, in every currency, returning how many there were.
+    pub fn forget(&self, user_id: &str) -> usize {
+        let mut payments = self.payments.lock().unwrap();
+        let mut removed = 0;
+        payments.retain(|(id, _), entries| {
+            if id == user_id {
+                removed += entries.len();
+                false
+            } else {
+                true
+            }
+        });
+        removed
+    }
+
+    /// Drops payments older than `window` and forgets users with none left.
+    pub fn sweep(&self, now: DateTime<Utc>, window: Duration) {
+        let mut payments = self.payments.lock().unwrap();
+        payments.retain(|_, entries| {
+            entries.retain(|(timestamp, _)| *timestamp > now - window);
+            !entries.is_empty()
+        });
+    }
+}
+
+/// A parsed `Payment` event: `Err` carries what was wrong with the metadata.
+pub struct Payment<'a> {
+    pub amount: Result<f64, &'static str>,
+    pub currency: Option<&'a str>,
+}
+
+/// Reads `amount` and `currency` from a `Payment` event's metadata. Returns `None` for
+/// other event types.
+pub fn parse_payment(event: &UserEvent) -> Option<Payment<'_>> {
+    if event.event_type != EventType::Payment {
+        return None;
+    }
+
+    let metadata = event.metadata.as_ref();
+    let amount = match metadata.and_then(|m| m.get("amount")) {
+        None => Err("amount is required"),
+        Some(raw) => match raw.trim().parse::<f64>() {
+            Ok(amount) if amount.is_finite() && amount >= 0.0 => Ok(amount),
+            _ => Err("amount is not a valid number"),
+        },
+    };
+    let currency = metadata
+        .and_then(|m| m.get("currency"))
+        .map(|currency| currency.trim())
+        .filter(|currency| !currency.is_empty());
+
+    Some(Payment { amount, currency })
+}
+
+/// Usual ISO 4217 currency for a country, for the most common payment origins.
+/// Deployments can extend or override this through the payments config.
+pub fn default_currency(country: &str) -> Option<&'static str> {
+    const EURO: &[&str] = &[
+        "AT", "BE", "CY", "DE", "EE", "ES", "FI", "FR", "GR", "HR", "IE", "IT", "LT", "LU", "LV", "MT", "NL",
+        "PT", "SI", "SK",
+    ];
+    if EURO.contains(&country) {
+        return Some("EUR");
+    }
+
+    let currency = match country {
+        "US" => "USD",
+        "GB" => "GBP",
+        "CA" => "CAD",
+        "AU" => "AUD",
+        "NZ" => "NZD",
+        "JP" => "JPY",
+        "CN" => "CNY",
+        "HK" => "HKD",
+        "SG" => "SGD",
+        "IN" => "INR",
+        "KR" => "KRW",
+        "BR" => "BRL",
+        "MX" => "MXN",
+        "CH" => "CHF",
+        "SE" => "SEK",
+        "NO" => "NOK",
+        "DK" => "DKK",
+        "PL" => "PLN",
+        "CZ" => "CZK",
+        "HU" => "HUF",
+        "RO" => "RON",
+        "TR" => "TRY",
+        "RU" => "RUB",
+        "UA" => "UAH",
+        "ZA" => "ZAR",
+        "NG" => "NGN",
+        "IL" => "ILS",
+        "AE" => "AED",
+        "SA" => "SAR",
+        "TH" => "THB",
+        "ID" => "IDR",
+        "MY" => "MYR",
+        "PH" => "PHP",
+        "VN" => "VND",
+        "AR" => "ARS",
+        "CL" => "CLP",
+        "CO" => "COP",
+        _ => return None,
+    };
+    Some(currency)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        "2024-05-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap() + Duration::seconds(secs)
+    }
+
+    fn payment(metadata: &[(&str, &str)]) -> UserEvent {
+        serde_json::from_value(serde_json::json!({
+            "sessionId": "s1",
+            "eventType": "Payment",
+            "timestamp": at(0),
+            "ipAddress": "198.51.100.23",
+            "metadata": metadata.iter().copied().collect::<HashMap<_, _>>(),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn window_total_sums_one_currency_within_the_window() {
+        let tracker = PaymentTracker::default();
+        let day = Duration::days(1);
+        tracker.record("u1", "USD", at(0), 100.0, day);
+        tracker.record("u1", "USD", at(3_600), 250.0, day);
+        tracker.record("u1", "EUR", at(3_600), 999.0, day);
+        tracker.record("u2", "USD", at(3_600), 999.0, day);
+        assert_eq!(tracker.window_total("u1", "USD", at(7_200), day), 350.0);
+        // A payment exactly a window old has left it.
+        assert_eq!(tracker.window_total("u1", "USD", at(86_400), day), 250.0);
+        assert_eq!(tracker.window_total("u3", "USD", at(7_200), day), 0.0);
+    }
+
+    #[test]
+    fn sweep_expires_payments_with_the_rest_of_user_state() {
+        let tracker = PaymentTracker::default();
+        let day = Duration::days(1);
+        tracker.record("u1", "USD", at(0), 100.0, day);
+        tracker.record("u2", "USD", at(0), 100.0, day);
+        tracker.record("u2", "USD", at(50_000), 100.0, day);
+        tracker.sweep(at(90_000), day);
+        assert_eq!(tracker.forget("u1"), 0);
+        assert_eq!(tracker.forget("u2"), 1);
+    }
+
+    #[test]
+    fn parses_amount_and_currency() {
+        let event = payment(&[("amount", " 19.99 "), ("currency", "usd")]);
+        let parsed = parse_payment(&event).unwrap();
+        assert_eq!(parsed.amount, Ok(19.99));
+        assert_eq!(parsed.currency, Some("usd"));
+    }
+
+    #[test]
+    fn reports_missing_and_invalid_amounts() {
+        assert_eq!(parse_payment(&payment(&[("currency", "USD")])).unwrap().amount, Err("amount is required"));
+        for raw in ["ten", "-5", "NaN", "inf"] {
+            let event = payment(&[("amount", raw)]);
+            assert_eq!(parse_payment(&event).unwrap().amount, Err("amount is not a valid number"), "{}", raw);
+        }
+    }
+
+    #[test]
+    fn other_event_types_are_not_payments() {
+        let mut event = payment(&[("amount", "10")]);
+        event.event_type = EventType::Click;
+        assert!(parse_payment(&event).is_none());
+    }
+}
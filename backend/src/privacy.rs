@@ -0,0 +1,136 @@
+use ipnet::IpNet;
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+
+// --- IP PRIVACY ---
+/// How event IPs are kept once an event has been scored. Lookups and the blacklist see
+/// the real address at the scoring moment; everything stored, exported, audited, or
+/// logged afterwards sees only the transformed value. The transformation is
+/// deterministic, so IP comparisons across a session's history still line up.
+#[derive(Debug, Clone)]
+pub enum IpPrivacy {
+    /// Raw addresses are stored.
+    Off,
+    /// Salted SHA-256, hex-encoded.
+    Hash { salt: String },
+    /// The /24 (IPv4) or /48 (IPv6) network, e.g. `203.0.113.0/24`.
+    Truncate,
+}
+
+impl IpPrivacy {
+    pub fn apply(&self, ip: &str) -> String {
+        match self {
+            IpPrivacy::Off => ip.to_string(),
+            IpPrivacy::Hash { salt } => {
+                let digest = Sha256::new().chain_update(salt).chain_update(ip).finalize();
+                digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+            }
+            IpPrivacy::Truncate => match ip.parse::<IpAddr>() {
+                Ok(addr) => {
+                    let prefix = if addr.is_ipv4() { 24 } else { 48 };
+                    IpNet::new(addr, prefix).map_or_else(|_| ip.to_string(), |net| net.trunc().to_string())
+                }
+                Err(_) => ip.to_string(),
+            },
+        }
+    }
+}
+
+// --- IP LOGGING ---
+/// How IPs appear in log lines. Applied to the stored form, so it stacks on `IpPrivacy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogIpMode {
+    Full,
+    /// See `mask_ip`.
+    Mask,
+    Omit,
+}
+
+impl LogIpMode {
+    /// The IP as it may be written to a log line.
+    pub fn render(self, ip: &str) -> String {
+        match self {
+            LogIpMode::Full => ip.to_string(),
+            LogIpMode::Mask => mask_ip(ip),
+            LogIpMode::Omit => "[omitted]".to_string(),
+        }
+    }
+}
+
+/// Hides the host part of an address: the last octet of IPv4 (`203.0.113.*`) or the last
+/// segment of IPv6 (`2001:db8:0:0:0:0:0:*`). Anything that isn't an address, such as a
+/// hashed or truncated IP, is redacted entirely rather than guessed at.
+pub fn mask_ip(ip: &str) -> String {
+    match ip.trim().parse::<IpAddr>() {
+        Ok(IpAddr::V4(addr)) => {
+            let [a, b, c, _] = addr.octets();
+            format!("{}.{}.{}.*", a, b, c)
+        }
+        Ok(IpAddr::V6(addr)) => {
+            let segments = addr.segments();
+            let network: Vec<String> = segments[..7].iter().map(|segment| format!("{:x}", segment)).collect();
+            format!("{}:*", network.join(":"))
+        }
+        Err(_) => "[redacted]".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hashed(salt: &str) -> IpPrivacy {
+        IpPrivacy::Hash { salt: salt.to_string() }
+    }
+
+    #[test]
+    fn hashing_is_deterministic_per_salt() {
+        let ip = "203.0.113.9";
+        assert_eq!(hashed("pepper").apply(ip), hashed("pepper").apply(ip));
+        assert_ne!(hashed("pepper").apply(ip), hashed("salt").apply(ip));
+        assert_ne!(hashed("pepper").apply(ip), hashed("pepper").apply("203.0.113.10"));
+        let hash = hashed("pepper").apply(ip);
+        assert_eq!(hash.len(), 64);
+        assert!(!hash.contains(ip));
+    }
+
+    #[test]
+    fn truncation_keeps_the_network() {
+        assert_eq!(IpPrivacy::Truncate.apply("203.0.113.9"), "203.0.113.0/24");
+        assert_eq!(IpPrivacy::Truncate.apply("2001:db8:1:2:3::9"), "2001:db8:1::/48");
+        assert_eq!(IpPrivacy::Truncate.apply("not-an-ip"), "not-an-ip");
+    }
+
+    #[test]
+    fn off_keeps_the_address() {
+        assert_eq!(IpPrivacy::Off.apply("203.0.113.9"), "203.0.113.9");
+    }
+
+    // --- IP LOGGING ---
+    #[test]
+    fn masking_hides_the_last_ipv4_octet() {
+        assert_eq!(mask_ip("203.0.113.9"), "203.0.113.*");
+        assert_eq!(mask_ip(" 10.0.0.255 "), "10.0.0.*");
+    }
+
+    #[test]
+    fn masking_hides_the_last_ipv6_segment() {
+        assert_eq!(mask_ip("2001:db8::1"), "2001:db8:0:0:0:0:0:*");
+        assert_eq!(mask_ip("2001:DB8:1:2:3:4:5:6"), "2001:db8:1:2:3:4:5:*");
+    }
+
+    #[test]
+    fn masking_redacts_anything_that_is_not_an_address() {
+        assert_eq!(mask_ip("not-an-ip"), "[redacted]");
+        assert_eq!(mask_ip(""), "[redacted]");
+        assert_eq!(mask_ip(&IpPrivacy::Truncate.apply("203.0.113.9")), "[redacted]");
+        assert_eq!(mask_ip(&hashed("pepper").apply("203.0.113.9")), "[redacted]");
+    }
+
+    #[test]
+    fn each_log_mode_renders_the_ip_its_way() {
+        assert_eq!(LogIpMode::Full.render("203.0.113.9"), "203.0.113.9");
+        assert_eq!(LogIpMode::Mask.render("203.0.113.9"), "203.0.113.*");
+        assert_eq!(LogIpMode::Omit.render("203.0.113.9"), "[omitted]");
+    }
+}
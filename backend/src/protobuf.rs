@@ -0,0 +1,229 @@
+use axum::{
+    async_trait,
+    body::{Bytes, HttpBody},
+    extract::FromRequest,
+    http::{header, HeaderMap, HeaderValue, Request},
+    response::{IntoResponse, Response},
+    BoxError,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use prost::Message;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::error::AppJson;
+use crate::ip;
+use crate::msgpack::{self, AppBody};
+use crate::rules::RuleHit;
+use crate::{AnalyzeResponse, AppError, AppState, EventType, UserEvent};
+
+// --- PROTOBUF EVENTS ---
+pub const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
+
+/// Messages from `proto/fraud_events.proto`, written out by hand so the build doesn't
+/// need `protoc`. Keep the tags in sync with the proto file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration)]
+#[repr(i32)]
+enum ProtoEventType {
+    Unspecified = 0,
+    PageLoad = 1,
+    Click = 2,
+    FormSubmission = 3,
+    Payment = 4,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ProtoUserEvent {
+    #[prost(string, tag = "1")]
+    session_id: String,
+    #[prost(string, optional, tag = "2")]
+    user_id: Option<String>,
+    #[prost(enumeration = "ProtoEventType", tag = "3")]
+    event_type: i32,
+    #[prost(string, tag = "4")]
+    timestamp: String,
+    #[prost(string, tag = "5")]
+    ip_address: String,
+    #[prost(string, optional, tag = "6")]
+    user_agent: Option<String>,
+    #[prost(map = "string, string", tag = "7")]
+    metadata: HashMap<String, String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ProtoRuleHit {
+    #[prost(string, tag = "1")]
+    code: String,
+    #[prost(int32, tag = "2")]
+    points: i32,
+    #[prost(string, tag = "3")]
+    reason: String,
+    #[prost(message, repeated, tag = "4")]
+    children: Vec<ProtoRuleHit>,
+}
+
+impl From<RuleHit> for ProtoRuleHit {
+    fn from(hit: RuleHit) -> Self {
+        ProtoRuleHit {
+            code: hit.code.into_owned(),
+            points: hit.points,
+            reason: hit.reason,
+            children: hit.children.into_iter().map(ProtoRuleHit::from).collect(),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ProtoFraudCheckResult {
+    #[prost(string, tag = "1")]
+    session_id: String,
+    #[prost(int32, tag = "2")]
+    fraud_score: i32,
+    #[prost(uint32, tag = "3")]
+    confidence: u32,
+    #[prost(bool, tag = "4")]
+    flagged: bool,
+    #[prost(string, repeated, tag = "5")]
+    reasons: Vec<String>,
+    #[prost(message, repeated, tag = "6")]
+    breakdown: Vec<ProtoRuleHit>,
+    #[prost(int32, optional, tag = "7")]
+    session_reputation: Option<i32>,
+    #[prost(string, tag = "8")]
+    check_timestamp: String,
+    #[prost(uint32, optional, tag = "9")]
+    asn: Option<u32>,
+    #[prost(string, tag = "10")]
+    decision: String,
+    #[prost(bool, tag = "11")]
+    degraded: bool,
+    #[prost(string, repeated, tag = "12")]
+    skipped_rules: Vec<String>,
+    #[prost(int32, tag = "13")]
+    raw_score: i32,
+}
+
+/// Applies the same checks the JSON body goes through, so both encodings accept and
+/// reject the same events.
+impl TryFrom<ProtoUserEvent> for UserEvent {
+    type Error = AppError;
+
+    fn try_from(event: ProtoUserEvent) -> Result<Self, AppError> {
+        let event_type = match ProtoEventType::try_from(event.event_type) {
+            Ok(ProtoEventType::PageLoad) => EventType::PageLoad,
+            Ok(ProtoEventType::Click) => EventType::Click,
+            Ok(ProtoEventType::FormSubmission) => EventType::FormSubmission,
+            Ok(ProtoEventType::Payment) => EventType::Payment,
+            Ok(ProtoEventType::Unspecified) | Err(_) => {
+                return Err(AppError::Validation(format!("invalid event type `{}`", event.event_type)));
+            }
+        };
+        let timestamp: DateTime<Utc> = event
+            .timestamp
+            .parse()
+            .map_err(|_| AppError::Validation(format!("invalid timestamp `{}`", event.timestamp)))?;
+        let ip_address = ip::parse(&event.ip_address)
+            .ok_or_else(|| AppError::Validation(format!("invalid IP address `{}`", event.ip_address)))?
+            .to_string();
+
+        Ok(UserEvent {
+            session_id: event.session_id,
+            user_id: event.user_id,
+            event_type,
+            timestamp,
+            ip_address,
+            user_agent: event.user_agent,
+            metadata: (!event.metadata.is_empty()).then_some(event.metadata),
+            asn: None,
+        })
+    }
+}
+
+impl From<AnalyzeResponse> for ProtoFraudCheckResult {
+    fn from(response: AnalyzeResponse) -> Self {
+        match response {
+            AnalyzeResponse::Full(result) => ProtoFraudCheckResult {
+                session_id: result.session_id,
+                fraud_score: result.fraud_score,
+                raw_score: result.raw_score,
+                confidence: result.confidence.into(),
+                flagged: result.flagged,
+                decision: result.decision.as_str().to_string(),
+                reasons: result.reasons,
+                breakdown: result.breakdown.into_iter().map(ProtoRuleHit::from).collect(),
+                session_reputation: result.session_reputation,
+                asn: result.asn,
+                check_timestamp: result.check_timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+                degraded: result.degraded,
+                skipped_rules: result.skipped_rules,
+            },
+            AnalyzeResponse::Minimal(result) => ProtoFraudCheckResult {
+                session_id: result.session_id,
+                fraud_score: result.fraud_score,
+                raw_score: result.raw_score,
+                confidence: result.confidence.into(),
+                flagged: result.flagged,
+                decision: result.decision.as_str().to_string(),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Whether a `Content-Type` or `Accept` value names one of `media_types`, ignoring
+/// parameters and case.
+pub fn has_media_type(value: Option<&HeaderValue>, media_types: &[&str]) -> bool {
+    let Some(value) = value.and_then(|value| value.to_str().ok()) else {
+        return false;
+    };
+    value.split(',').any(|media_type| {
+        let media_type = media_type.split(';').next().unwrap_or_default().trim();
+        media_types.iter().any(|expected| media_type.eq_ignore_ascii_case(expected))
+    })
+}
+
+/// Whether the client asked for a protobuf response.
+pub fn accepts_protobuf(headers: &HeaderMap) -> bool {
+    has_media_type(headers.get(header::ACCEPT), &[PROTOBUF_CONTENT_TYPE])
+}
+
+/// An analyze request body: protobuf or MessagePack when the `Content-Type` says so,
+/// JSON otherwise. JSON bodies are checked against `EVENT_SCHEMA_PATH`, when set, before
+/// they are decoded.
+pub struct EventBody(pub UserEvent);
+
+#[async_trait]
+impl<B> FromRequest<Arc<AppState>, B> for EventBody
+where
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request<B>, state: &Arc<AppState>) -> Result<Self, AppError> {
+        if !has_media_type(req.headers().get(header::CONTENT_TYPE), &[PROTOBUF_CONTENT_TYPE]) {
+            let Some(schema) = state.event_schema.as_ref().filter(|_| !msgpack::sends_msgpack(req.headers())) else {
+                let AppBody(event) = AppBody::from_request(req, state).await?;
+                return Ok(EventBody(event));
+            };
+            let AppJson(body) = AppJson::<Value>::from_request(req, state).await?;
+            schema.check(&body)?;
+            let event = serde_json::from_value(body).map_err(|e| {
+                AppError::Validation(format!("Failed to deserialize the JSON body into the target type: {}", e))
+            })?;
+            return Ok(EventBody(event));
+        }
+        let body = Bytes::from_request(req, state).await?;
+        let event = ProtoUserEvent::decode(body)
+            .map_err(|e| AppError::BadRequest(format!("Failed to decode protobuf body: {}", e)))?;
+        Ok(EventBody(event.try_into()?))
+    }
+}
+
+/// Encodes the response as protobuf. Errors stay JSON whatever the `Accept` header.
+pub fn protobuf_response(response: AnalyzeResponse) -> Response {
+    let body = ProtoFraudCheckResult::from(response).encode_to_vec();
+    ([(header::CONTENT_TYPE, PROTOBUF_CONTENT_TYPE)], body).into_response()
+}
@@ -0,0 +1,471 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::{EventType, UserEvent};
+
+/// Most buckets a counter keeps. Longer windows get wider buckets rather than more.
+const MAX_BUCKETS: u64 = 60;
+
+// --- SLIDING WINDOW COUNTERS ---
+/// Events per fixed-width time bucket over a window, in a ring that the newest bucket
+/// overwrites the oldest of. Recording is O(1) (amortized over skipped buckets) and
+/// counting O(buckets), however many events there were. Buckets are a second wide for
+/// windows up to a minute, and `window_secs / 60` seconds (rounded up) beyond, so counts
+/// are exact to one bucket.
+#[derive(Debug, Clone)]
+pub struct SlidingWindowCounter {
+    counts: Vec<u32>,
+    bucket_secs: i64,
+    window_secs: u64,
+    /// Slot (seconds since the epoch over `bucket_secs`) of the newest bucket. The ring
+    /// holds this slot and the ones just before it; `None` until the first event.
+    newest: Option<i64>,
+}
+
+impl SlidingWindowCounter {
+    pub fn new(window_secs: u64) -> Self {
+        let bucket_secs = window_secs.div_ceil(MAX_BUCKETS).max(1);
+        Self {
+            counts: vec![0; window_secs.div_ceil(bucket_secs).max(1) as usize],
+            bucket_secs: bucket_secs as i64,
+            window_secs,
+            newest: None,
+        }
+    }
+
+    pub fn window_secs(&self) -> u64 {
+        self.window_secs
+    }
+
+    fn slot(&self, at: DateTime<Utc>) -> i64 {
+        at.timestamp().div_euclid(self.bucket_secs)
+    }
+
+    fn bucket(&self, slot: i64) -> usize {
+        slot.rem_euclid(self.counts.len() as i64) as usize
+    }
+
+    /// The oldest slot the ring still holds.
+    fn oldest(&self, newest: i64) -> i64 {
+        newest - (self.counts.len() as i64 - 1)
+    }
+
+    /// Counts an event at `at`. Buckets for time skipped since the newest one are
+    /// cleared first, so an idle gap reads as zero. Events older than the ring are
+    /// dropped: no window they could count toward is left.
+    pub fn record(&mut self, at: DateTime<Utc>) {
+        let slot = self.slot(at);
+        match self.newest {
+            Some(newest) if slot <= newest => {
+                if slot < self.oldest(newest) {
+                    return;
+                }
+            }
+            Some(newest) if slot - newest < self.counts.len() as i64 => {
+                for skipped in newest + 1..=slot {
+                    let bucket = self.bucket(skipped);
+                    self.counts[bucket] = 0;
+                }
+                self.newest = Some(slot);
+            }
+            _ => {
+                self.counts.fill(0);
+                self.newest = Some(slot);
+            }
+        }
+        let bucket = self.bucket(slot);
+        self.counts[bucket] = self.counts[bucket].saturating_add(1);
+    }
+
+    /// Events in the window ending with `at`'s bucket. Buckets after it, holding events
+    /// recorded out of order, aren't counted.
+    pub fn count(&self, at: DateTime<Utc>) -> usize {
+        let Some(newest) = self.newest else {
+            return 0;
+        };
+        let slot = self.slot(at);
+        let from = self.oldest(slot).max(self.oldest(newest));
+        let to = slot.min(newest);
+        (from..=to).map(|slot| self.counts[self.bucket(slot)] as usize).sum()
+    }
+
+    /// Whether every event counted has left the window as of `now`.
+    pub fn is_idle(&self, now: DateTime<Utc>) -> bool {
+        self.newest.is_none_or(|newest| self.oldest(self.slot(now)) > newest)
+    }
+}
+
+// --- RATE TRACKING ---
+/// Events within the `high_frequency` window before the one being scored, which isn't
+/// counted.
+#[derive(Debug, Default)]
+pub struct Rates {
+    pub session_by_type: HashMap<EventType, usize>,
+    pub ip: usize,
+    /// `None` for anonymous events.
+    pub user: Option<usize>,
+}
+
+/// The session's events in `prior` within the window ending with `at`'s bucket, per
+/// type. They are counted into the same buckets as the counters, so a sub-second
+/// timestamp is in or out of the window the same way wherever it is counted.
+pub fn scan(prior: &[Arc<UserEvent>], at: DateTime<Utc>, window_secs: u64) -> HashMap<EventType, usize> {
+    let mut counters = HashMap::new();
+    for earlier in prior {
+        counter_for_type(&mut counters, &earlier.event_type, window_secs).record(earlier.timestamp);
+    }
+    counts(&counters, at)
+}
+
+/// Each counter's events in the window ending with `at`'s bucket, leaving out the empty.
+fn counts(counters: &HashMap<EventType, SlidingWindowCounter>, at: DateTime<Utc>) -> HashMap<EventType, usize> {
+    counters
+        .iter()
+        .map(|(event_type, counter)| (event_type.clone(), counter.count(at)))
+        .filter(|(_, count)| *count > 0)
+        .collect()
+}
+
+/// Counters over the `high_frequency` window per IP and per user, so the rule reads
+/// those rates without walking any history. A session's rates are worked out from its
+/// stored history on every event instead, so with a shared store they include what
+/// other replicas stored; only its events left unstored by storage sampling, which no
+/// history holds, are counted here. A counter built for another window, after a reload
+/// changed it, starts over.
+#[derive(Default)]
+pub struct RateTracker {
+    /// Per session, its unstored events by type.
+    sessions: Mutex<HashMap<String, HashMap<EventType, SlidingWindowCounter>>>,
+    ips: Mutex<HashMap<String, SlidingWindowCounter>>,
+    users: Mutex<HashMap<String, SlidingWindowCounter>>,
+}
+
+/// The key's counter, fresh when it has none or one for another window.
+fn counter<'a>(counters: &'a mut HashMap<String, SlidingWindowCounter>, key: &str, window_secs: u64) -> &'a mut SlidingWindowCounter {
+    let counter = counters
+        .entry(key.to_string())
+        .or_insert_with(|| SlidingWindowCounter::new(window_secs));
+    if counter.window_secs() != window_secs {
+        *counter = SlidingWindowCounter::new(window_secs);
+    }
+    counter
+}
+
+impl RateTracker {
+    /// The rates `event` is scored against, then counts it toward its IP and user.
+    /// `prior` is the session's stored history before it; the event itself counts toward
+    /// the session once stored, or through `unstored` if it isn't. `ip` is the event's
+    /// counter key.
+    pub fn observe(&self, event: &UserEvent, prior: &[Arc<UserEvent>], ip: &str, window_secs: u64) -> Rates {
+        let at = event.timestamp;
+        let session_by_type = self.session_rates(event, prior, window_secs);
+        let ip = {
+            let mut ips = self.ips.lock().unwrap();
+            let counter = counter(&mut ips, ip, window_secs);
+            let count = counter.count(at);
+            counter.record(at);
+            count
+        };
+        let user = event.user_id.as_ref().map(|user_id| {
+            let mut users = self.users.lock().unwrap();
+            let counter = counter(&mut users, user_id, window_secs);
+            let count = counter.count(at);
+            counter.record(at);
+            count
+        });
+        Rates { session_by_type, ip, user }
+    }
+
+    /// The rates `event` would be scored against, without counting it. `prior` is the
+    /// history it's scored against.
+    pub fn peek(&self, event: &UserEvent, prior: &[Arc<UserEvent>], ip: &str, window_secs: u64) -> Rates {
+        let count = |counters: &HashMap<String, SlidingWindowCounter>, key: &str| {
+            counters
+                .get(key)
+                .filter(|counter| counter.window_secs() == window_secs)
+                .map_or(0, |counter| counter.count(event.timestamp))
+        };
+        Rates {
+            session_by_type: self.session_rates(event, prior, window_secs),
+            ip: count(&self.ips.lock().unwrap(), ip),
+            user: event.user_id.as_ref().map(|user_id| count(&self.users.lock().unwrap(), user_id)),
+        }
+    }
+
+    /// Counts an observed event that storage sampling left unstored toward its session,
+    /// since no later history will hold it.
+    pub fn unstored(&self, event: &UserEvent, window_secs: u64) {
+        let mut sessions = self.sessions.lock().unwrap();
+        let counters = sessions.entry(event.session_id.clone()).or_default();
+        if counters.values().any(|counter| counter.window_secs() != window_secs) {
+            counters.clear();
+        }
+        counter_for_type(counters, &event.event_type, window_secs).record(event.timestamp);
+    }
+
+    /// The session's events before `event` in the window: those in `prior`, and those
+    /// counted as unstored.
+    fn session_rates(&self, event: &UserEvent, prior: &[Arc<UserEvent>], window_secs: u64) -> HashMap<EventType, usize> {
+        let mut by_type = scan(prior, event.timestamp, window_secs);
+        let sessions = self.sessions.lock().unwrap();
+        let unstored = sessions
+            .get(&event.session_id)
+            .filter(|counters| counters.values().all(|counter| counter.window_secs() == window_secs));
+        if let Some(counters) = unstored {
+            for (event_type, count) in counts(counters, event.timestamp) {
+                *by_type.entry(event_type).or_default() += count;
+            }
+        }
+        by_type
+    }
+
+    pub fn forget_user(&self, user_id: &str) {
+        self.users.lock().unwrap().remove(user_id);
+    }
+
+    /// Drops counters that have counted nothing within their window as of `now`.
+    pub fn sweep(&self, now: DateTime<Utc>) {
+        self.sessions.lock().unwrap().retain(|_, counters| {
+            counters.retain(|_, counter| !counter.is_idle(now));
+            !counters.is_empty()
+        });
+        self.ips.lock().unwrap().retain(|_, counter| !counter.is_idle(now));
+        self.users.lock().unwrap().retain(|_, counter| !counter.is_idle(now));
+    }
+}
+
+fn counter_for_type<'a>(
+    counters: &'a mut HashMap<EventType, SlidingWindowCounter>,
+    event_type: &EventType,
+    window_secs: u64,
+) -> &'a mut SlidingWindowCounter {
+    counters
+        .entry(event_type.clone())
+        .or_insert_with(|| SlidingWindowCounter::new(window_secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use proptest::prelude::*;
+
+    fn start() -> DateTime<Utc> {
+        "2024-05-01T12:00:00Z".parse().unwrap()
+    }
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        start() + Duration::seconds(secs)
+    }
+
+    fn event(event_type: EventType, secs: i64) -> Arc<UserEvent> {
+        event_ms(event_type, secs * 1000)
+    }
+
+    fn event_ms(event_type: EventType, offset_ms: i64) -> Arc<UserEvent> {
+        Arc::new(UserEvent {
+            session_id: "rates".to_string(),
+            user_id: None,
+            event_type,
+            timestamp: start() + Duration::milliseconds(offset_ms),
+            ip_address: "198.51.100.23".to_string(),
+            user_agent: None,
+            metadata: None,
+            asn: None,
+        })
+    }
+
+    // --- SLIDING WINDOW COUNTERS ---
+    #[test]
+    fn windows_up_to_a_minute_have_one_second_buckets() {
+        let counter = SlidingWindowCounter::new(10);
+        assert_eq!((counter.bucket_secs, counter.counts.len()), (1, 10));
+        let counter = SlidingWindowCounter::new(60);
+        assert_eq!((counter.bucket_secs, counter.counts.len()), (1, 60));
+    }
+
+    #[test]
+    fn longer_windows_round_their_buckets_up() {
+        let counter = SlidingWindowCounter::new(61);
+        assert_eq!((counter.bucket_secs, counter.counts.len()), (2, 31));
+        let counter = SlidingWindowCounter::new(90);
+        assert_eq!((counter.bucket_secs, counter.counts.len()), (2, 45));
+        let counter = SlidingWindowCounter::new(3600);
+        assert_eq!((counter.bucket_secs, counter.counts.len()), (60, 60));
+    }
+
+    #[test]
+    fn a_wide_bucket_counts_its_events_together() {
+        let mut counter = SlidingWindowCounter::new(120);
+        counter.record(at(0));
+        counter.record(at(1));
+        assert_eq!(counter.count(at(119)), 2);
+        assert_eq!(counter.count(at(120)), 0);
+    }
+
+    #[test]
+    fn the_ring_wraps_around_keeping_only_the_window() {
+        let mut counter = SlidingWindowCounter::new(10);
+        for secs in 0..25 {
+            counter.record(at(secs));
+        }
+        assert_eq!(counter.count(at(24)), 10);
+        assert_eq!(counter.count(at(20)), 6);
+    }
+
+    #[test]
+    fn skipped_buckets_are_cleared_when_the_next_event_lands() {
+        let mut counter = SlidingWindowCounter::new(10);
+        for secs in 0..10 {
+            counter.record(at(secs));
+        }
+        // Slots 10-13 reuse the buckets of 0-3, which must not be read as theirs.
+        counter.record(at(13));
+        assert_eq!(counter.count(at(13)), 7);
+    }
+
+    #[test]
+    fn an_idle_gap_reads_as_zero() {
+        let mut counter = SlidingWindowCounter::new(10);
+        for _ in 0..5 {
+            counter.record(at(0));
+        }
+        assert_eq!(counter.count(at(10)), 0);
+        assert!(counter.is_idle(at(10)));
+        assert!(!counter.is_idle(at(9)));
+
+        counter.record(at(40));
+        assert_eq!(counter.count(at(40)), 1);
+    }
+
+    #[test]
+    fn out_of_order_events_count_where_they_belong() {
+        let mut counter = SlidingWindowCounter::new(10);
+        counter.record(at(5));
+        counter.record(at(2));
+        assert_eq!(counter.count(at(5)), 2);
+        // The later event isn't in a window ending before it.
+        assert_eq!(counter.count(at(3)), 1);
+        // Older than the ring: dropped.
+        counter.record(at(-20));
+        assert_eq!(counter.count(at(5)), 2);
+    }
+
+    #[test]
+    fn counts_outside_the_ring_are_zero() {
+        let mut counter = SlidingWindowCounter::new(10);
+        assert_eq!(counter.count(at(0)), 0);
+        counter.record(at(5));
+        // A window ending before the ring's oldest slot, and one starting after its newest.
+        assert_eq!(counter.count(at(-30)), 0);
+        assert_eq!(counter.count(at(100)), 0);
+    }
+
+    proptest! {
+        /// In order and at any millisecond, a counter read before each event matches a
+        /// full scan of the events before it, and so do the rates the tracker scores and
+        /// explains it against, whichever of the events were left unstored.
+        #[test]
+        fn counters_match_a_full_scan(
+            window_secs in 1u64..=60,
+            gaps in proptest::collection::vec((0i64..8000, 0usize..3, any::<bool>()), 1..80),
+        ) {
+            let types = [EventType::PageLoad, EventType::Click, EventType::Payment];
+            let tracker = RateTracker::default();
+            let mut counters: HashMap<EventType, SlidingWindowCounter> = HashMap::new();
+            let (mut all, mut stored) = (Vec::new(), Vec::new());
+            let mut offset_ms = 0;
+            for (gap, kind, keep) in gaps {
+                offset_ms += gap;
+                let next = event_ms(types[kind].clone(), offset_ms);
+                let scanned = scan(&all, next.timestamp, window_secs);
+                let peeked = tracker.peek(&next, &stored, "198.51.100.23", window_secs).session_by_type;
+                let observed = tracker.observe(&next, &stored, "198.51.100.23", window_secs).session_by_type;
+                for event_type in &types {
+                    let counted = counters.get(event_type).map_or(0, |counter| counter.count(next.timestamp));
+                    let read = |rates: &HashMap<EventType, usize>| rates.get(event_type).copied().unwrap_or(0);
+                    prop_assert_eq!(counted, read(&scanned), "{:?} at {}ms", event_type, offset_ms);
+                    prop_assert_eq!(counted, read(&observed), "{:?} at {}ms", event_type, offset_ms);
+                    prop_assert_eq!(counted, read(&peeked), "{:?} at {}ms", event_type, offset_ms);
+                }
+                counter_for_type(&mut counters, &next.event_type, window_secs).record(next.timestamp);
+                match keep {
+                    true => stored.push(next.clone()),
+                    false => tracker.unstored(&next, window_secs),
+                }
+                all.push(next);
+            }
+        }
+    }
+
+    #[test]
+    fn a_sub_second_event_is_counted_by_its_bucket_in_a_scan() {
+        // 9.5s before the next event, but in the bucket 10s before its own: out of the window.
+        let prior = vec![event_ms(EventType::Click, 500)];
+        assert!(scan(&prior, at(10), 10).is_empty());
+        // 9.4s before it, in the bucket 9s before: in.
+        assert_eq!(scan(&prior, start() + Duration::milliseconds(9_900), 10)[&EventType::Click], 1);
+    }
+
+    // --- RATE TRACKING ---
+    #[test]
+    fn a_sessions_rates_come_from_its_stored_history_every_time() {
+        let tracker = RateTracker::default();
+        let mut prior = vec![event(EventType::Click, 0), event(EventType::Click, 1), event(EventType::PageLoad, 2)];
+        let next = event(EventType::Click, 3);
+        let rates = tracker.observe(&next, &prior, "198.51.100.23", 60);
+        assert_eq!(rates.session_by_type, scan(&prior, next.timestamp, 60));
+        // The IP counter has no history to seed from.
+        assert_eq!(rates.ip, 0);
+        assert_eq!(rates.user, None);
+
+        // Another instance stored a click in between: it's counted without being seen here.
+        prior.extend([next, event(EventType::Click, 4)]);
+        let after = event(EventType::Click, 5);
+        let rates = tracker.observe(&after, &prior, "198.51.100.23", 60);
+        assert_eq!(rates.session_by_type[&EventType::Click], 4);
+        assert_eq!(rates.ip, 1);
+    }
+
+    #[test]
+    fn unstored_events_count_on_top_of_the_history() {
+        let tracker = RateTracker::default();
+        let prior = vec![event(EventType::Click, 0)];
+        tracker.unstored(&event(EventType::Click, 1), 60);
+        tracker.unstored(&event(EventType::PageLoad, 2), 60);
+        let next = event(EventType::Click, 3);
+        let rates = tracker.observe(&next, &prior, "198.51.100.23", 60);
+        assert_eq!(rates.session_by_type[&EventType::Click], 2);
+        assert_eq!(rates.session_by_type[&EventType::PageLoad], 1);
+        assert_eq!(tracker.peek(&next, &prior, "198.51.100.23", 60).session_by_type, rates.session_by_type);
+        // Counted for another window, they're left out.
+        assert_eq!(tracker.peek(&next, &prior, "198.51.100.23", 30).session_by_type[&EventType::Click], 1);
+    }
+
+    #[test]
+    fn a_changed_window_starts_the_counters_over() {
+        let tracker = RateTracker::default();
+        let first = event(EventType::Click, 0);
+        tracker.observe(&first, &[], "198.51.100.23", 60);
+        tracker.unstored(&first, 60);
+        tracker.unstored(&event(EventType::Click, 1), 30);
+        let rates = tracker.observe(&event(EventType::Click, 2), &[], "198.51.100.23", 30);
+        assert_eq!(rates.session_by_type[&EventType::Click], 1);
+        assert_eq!(rates.ip, 0);
+    }
+
+    #[test]
+    fn sweeping_drops_idle_counters() {
+        let tracker = RateTracker::default();
+        let first = event(EventType::Click, 0);
+        tracker.observe(&first, &[], "198.51.100.23", 10);
+        tracker.unstored(&first, 10);
+        tracker.sweep(at(5));
+        assert_eq!(tracker.sessions.lock().unwrap().len(), 1);
+        assert_eq!(tracker.ips.lock().unwrap().len(), 1);
+        tracker.sweep(at(10));
+        assert!(tracker.ips.lock().unwrap().is_empty());
+        assert!(tracker.sessions.lock().unwrap().is_empty());
+    }
+}
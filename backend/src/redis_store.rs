@@ -0,0 +1,542 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Timelike, Utc};
+use redis::aio::ConnectionManager;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::activity::{ActivityProfile, ActivityStore};
+use crate::entity_reputation::{Entity, EntityReputationStore, StoredReputation, FADED};
+use crate::store::{EventStore, SessionErasure, SessionHistory, StoreError, StoreSize, StoredEvent};
+use crate::usage::{UsageCounts, UsageStore};
+use crate::UserEvent;
+
+// --- REDIS EVENT STORE ---
+/// Shared store for multi-instance deployments. Each session is a sorted set of
+/// serialized events scored by timestamp (millis), expiring `ttl` after its last event
+/// and trimmed to its newest `max_events` events. Each user id has a set of the
+/// sessions it has events in, expiring `ttl` after the user's last event.
+pub struct RedisEventStore {
+    connection: ConnectionManager,
+    ttl: Duration,
+    max_events: usize,
+    /// Prefix that keeps members from different replicas unique even for identical events.
+    instance_id: String,
+    sequence: AtomicU64,
+}
+
+impl RedisEventStore {
+    pub async fn connect(url: &str, ttl: Duration, max_events: usize) -> Result<Self, StoreError> {
+        let client = redis::Client::open(url).map_err(redis_error)?;
+        let connection = ConnectionManager::new(client).await.map_err(redis_error)?;
+        let started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        Ok(Self {
+            connection,
+            ttl,
+            max_events,
+            instance_id: format!("{}-{}", std::process::id(), started.as_nanos()),
+            sequence: AtomicU64::new(0),
+        })
+    }
+
+    /// Adds the event's session to its user's sessions, when it has a user.
+    fn index_user(&self, pipe: &mut redis::Pipeline, event: &UserEvent) {
+        if let Some(user_id) = &event.user_id {
+            let key = user_sessions_key(user_id);
+            pipe.sadd(&key, &event.session_id)
+                .ignore()
+                .expire(&key, self.ttl.as_secs() as i64)
+                .ignore();
+        }
+    }
+
+    fn member(&self, event: &UserEvent) -> Result<String, StoreError> {
+        let json = serde_json::to_string(event).map_err(|e| StoreError::Backend(e.to_string()))?;
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        Ok(format!("{}:{}|{}", self.instance_id, sequence, json))
+    }
+}
+
+const SESSION_KEY_PREFIX: &str = "fraud:session:";
+const SESSION_KEY_SUFFIX: &str = ":events";
+
+/// Replaces member `ARGV[1]` with `ARGV[3]` at score `ARGV[2]` when it is still in the set.
+const AMEND_SCRIPT: &str = "if redis.call('ZREM', KEYS[1], ARGV[1]) == 1 then \
+    redis.call('ZADD', KEYS[1], ARGV[2], ARGV[3]) return 1 end return 0";
+
+fn session_key(session_id: &str) -> String {
+    format!("{}{}{}", SESSION_KEY_PREFIX, session_id, SESSION_KEY_SUFFIX)
+}
+
+fn user_sessions_key(user_id: &str) -> String {
+    format!("fraud:user:{}:sessions", user_id)
+}
+
+fn redis_error(e: redis::RedisError) -> StoreError {
+    StoreError::Backend(format!("redis: {}", e))
+}
+
+fn decode_members(members: Vec<String>) -> Result<SessionHistory, StoreError> {
+    members
+        .iter()
+        .map(|member| {
+            let json = member.split_once('|').map_or(member.as_str(), |(_, json)| json);
+            serde_json::from_str::<StoredEvent>(json)
+                .map(|event| Arc::new(UserEvent::from(event)))
+                .map_err(|e| StoreError::Backend(format!("corrupt stored event: {}", e)))
+        })
+        .collect()
+}
+
+#[async_trait]
+impl EventStore for RedisEventStore {
+    async fn push(&self, event: UserEvent) -> Result<SessionHistory, StoreError> {
+        let key = session_key(&event.session_id);
+        let member = self.member(&event)?;
+
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .zadd(&key, &member, event.timestamp.timestamp_millis())
+            .ignore()
+            .zremrangebyrank(&key, 0, -(self.max_events as isize) - 1)
+            .ignore()
+            .expire(&key, self.ttl.as_secs() as i64)
+            .ignore();
+        self.index_user(&mut pipe, &event);
+        let (mut members,): (Vec<String>,) = pipe
+            .zrange(&key, 0, -1)
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(redis_error)?;
+
+        // The set is ordered by timestamp, but callers expect the pushed event last even
+        // when it arrived out of order (or so late that trimming already dropped it).
+        match members.iter().position(|m| *m == member) {
+            Some(index) => {
+                let pushed = members.remove(index);
+                members.push(pushed);
+            }
+            None => members.push(member),
+        }
+
+        decode_members(members)
+    }
+
+    /// One round trip for the whole batch.
+    async fn push_batch(&self, events: Vec<UserEvent>) -> Result<(), StoreError> {
+        let mut pipe = redis::pipe();
+        for event in &events {
+            let key = session_key(&event.session_id);
+            pipe.zadd(&key, self.member(event)?, event.timestamp.timestamp_millis())
+                .ignore()
+                .zremrangebyrank(&key, 0, -(self.max_events as isize) - 1)
+                .ignore()
+                .expire(&key, self.ttl.as_secs() as i64)
+                .ignore();
+            self.index_user(&mut pipe, event);
+        }
+        pipe.query_async::<()>(&mut self.connection.clone()).await.map_err(redis_error)
+    }
+
+    async fn session_events(&self, session_id: &str) -> Result<SessionHistory, StoreError> {
+        let members: Vec<String> = redis::cmd("ZRANGE")
+            .arg(session_key(session_id))
+            .arg(0)
+            .arg(-1)
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(redis_error)?;
+
+        decode_members(members)
+    }
+
+    /// Swaps the event's member for the amended one under the same score, only if the
+    /// old member is still there, so an event trimmed meanwhile isn't written back.
+    async fn amend(
+        &self,
+        session_id: &str,
+        event_id: &str,
+        metadata: &HashMap<String, String>,
+    ) -> Result<Option<SessionHistory>, StoreError> {
+        let key = session_key(session_id);
+        let mut connection = self.connection.clone();
+        let members: Vec<String> = redis::cmd("ZRANGE")
+            .arg(&key)
+            .arg(0)
+            .arg(-1)
+            .query_async(&mut connection)
+            .await
+            .map_err(redis_error)?;
+        let mut events = decode_members(members.clone())?;
+        let Some(index) = events.iter().rposition(|event| event.event_id() == Some(event_id)) else {
+            return Ok(None);
+        };
+
+        let amended = events[index].amended(metadata);
+        let member = self.member(&amended)?;
+        let swapped: i64 = redis::cmd("EVAL")
+            .arg(AMEND_SCRIPT)
+            .arg(1)
+            .arg(&key)
+            .arg(&members[index])
+            .arg(amended.timestamp.timestamp_millis())
+            .arg(&member)
+            .query_async(&mut connection)
+            .await
+            .map_err(redis_error)?;
+        if swapped == 0 {
+            return Ok(None);
+        }
+
+        events.to_mut()[index] = Arc::new(amended);
+        Ok(Some(events))
+    }
+
+    /// Walks the keyspace with `SCAN` so a large store never blocks Redis.
+    async fn session_ids(&self) -> Result<Vec<String>, StoreError> {
+        let pattern = session_key("*");
+        let mut connection = self.connection.clone();
+        let mut cursor = 0u64;
+        let mut ids = Vec::new();
+
+        loop {
+            let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(1000)
+                .query_async(&mut connection)
+                .await
+                .map_err(redis_error)?;
+
+            ids.extend(keys.iter().filter_map(|key| {
+                key.strip_prefix(SESSION_KEY_PREFIX)?
+                    .strip_suffix(SESSION_KEY_SUFFIX)
+                    .map(str::to_string)
+            }));
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+
+        // SCAN may return a key more than once.
+        ids.sort_unstable();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    /// Sessions that have expired since are dropped from the user's set as they're found.
+    /// One whose events of the user were all trimmed away stays listed until the set
+    /// expires.
+    async fn user_sessions(&self, user_id: &str) -> Result<Vec<String>, StoreError> {
+        let key = user_sessions_key(user_id);
+        let mut connection = self.connection.clone();
+        let session_ids: Vec<String> = redis::cmd("SMEMBERS")
+            .arg(&key)
+            .query_async(&mut connection)
+            .await
+            .map_err(redis_error)?;
+        let mut pipe = redis::pipe();
+        for session_id in &session_ids {
+            pipe.exists(session_key(session_id));
+        }
+        let exists: Vec<bool> = pipe.query_async(&mut connection).await.map_err(redis_error)?;
+
+        let (live, gone): (Vec<_>, Vec<_>) = session_ids.into_iter().zip(exists).partition(|(_, exists)| *exists);
+        if !gone.is_empty() {
+            let gone: Vec<String> = gone.into_iter().map(|(session_id, _)| session_id).collect();
+            redis::cmd("SREM")
+                .arg(&key)
+                .arg(&gone)
+                .query_async::<()>(&mut connection)
+                .await
+                .map_err(redis_error)?;
+        }
+        Ok(live.into_iter().map(|(session_id, _)| session_id).collect())
+    }
+
+    async fn erase_user_events(&self, session_id: &str, user_id: &str) -> Result<SessionErasure, StoreError> {
+        let key = session_key(session_id);
+        let mut connection = self.connection.clone();
+        let members: Vec<String> = redis::cmd("ZRANGE")
+            .arg(&key)
+            .arg(0)
+            .arg(-1)
+            .query_async(&mut connection)
+            .await
+            .map_err(redis_error)?;
+        let events = decode_members(members.clone())?;
+
+        let mut erasure = SessionErasure::default();
+        let mut doomed = Vec::new();
+        for (member, event) in members.into_iter().zip(events) {
+            match event.user_id.as_deref() {
+                Some(id) if id == user_id => {
+                    erasure.remove(&event);
+                    doomed.push(member);
+                }
+                None => erasure.anonymous_retained += 1,
+                Some(_) => {}
+            }
+        }
+        if doomed.is_empty() {
+            redis::cmd("SREM")
+                .arg(user_sessions_key(user_id))
+                .arg(session_id)
+                .query_async::<()>(&mut connection)
+                .await
+                .map_err(redis_error)?;
+            return Ok(erasure);
+        }
+
+        let (deleted,): (usize,) = redis::pipe()
+            .atomic()
+            .zrem(&key, &doomed)
+            .srem(user_sessions_key(user_id), session_id)
+            .ignore()
+            .query_async(&mut connection)
+            .await
+            .map_err(redis_error)?;
+        erasure.deleted = deleted;
+        Ok(erasure)
+    }
+
+    /// Walks the session keys and counts each one's events in a single pipeline. Keys
+    /// that expire mid-walk count as 0 events and aren't counted as sessions.
+    async fn size(&self) -> Result<StoreSize, StoreError> {
+        let session_ids = self.session_ids().await?;
+        let mut pipe = redis::pipe();
+        for session_id in &session_ids {
+            pipe.zcard(session_key(session_id));
+        }
+        let counts: Vec<usize> = pipe
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(redis_error)?;
+
+        Ok(StoreSize {
+            sessions: counts.iter().filter(|&&count| count > 0).count(),
+            events: counts.iter().sum(),
+        })
+    }
+}
+
+// --- REDIS ENTITY REPUTATION STORE ---
+/// Entity reputations shared across replicas and kept over restarts. Each is a hash of
+/// `score` and `updated_at` (millis), expiring once it would have faded.
+pub struct RedisEntityReputationStore {
+    connection: ConnectionManager,
+}
+
+impl RedisEntityReputationStore {
+    pub async fn connect(url: &str) -> Result<Self, StoreError> {
+        let client = redis::Client::open(url).map_err(redis_error)?;
+        let connection = ConnectionManager::new(client).await.map_err(redis_error)?;
+        Ok(Self { connection })
+    }
+}
+
+fn reputation_key(entity: Entity, id: &str) -> String {
+    format!("fraud:reputation:{}:{}", entity.as_str(), id)
+}
+
+/// Decays the stored score to `ARGV[1]` (millis) by half-life `ARGV[2]` (millis), adds
+/// `ARGV[3]`, and expires the hash once the new score would fade below `ARGV[4]`. One
+/// script, so concurrent flags on one replica or several don't lose updates.
+const ADD_REPUTATION_SCRIPT: &str = "\
+    local stored = redis.call('HMGET', KEYS[1], 'score', 'updated_at') \
+    local now, half_life = tonumber(ARGV[1]), tonumber(ARGV[2]) \
+    local score = tonumber(stored[1]) or 0 \
+    local elapsed = math.max(now - (tonumber(stored[2]) or now), 0) \
+    score = score * math.pow(0.5, elapsed / half_life) + tonumber(ARGV[3]) \
+    redis.call('HSET', KEYS[1], 'score', tostring(score), 'updated_at', ARGV[1]) \
+    local fades = half_life * math.log(math.max(score / tonumber(ARGV[4]), 2)) / math.log(2) \
+    redis.call('PEXPIRE', KEYS[1], math.ceil(fades)) \
+    return 1";
+
+#[async_trait]
+impl EntityReputationStore for RedisEntityReputationStore {
+    async fn get(&self, entity: Entity, id: &str) -> Result<Option<StoredReputation>, StoreError> {
+        let (score, updated_at): (Option<f64>, Option<i64>) = redis::cmd("HMGET")
+            .arg(reputation_key(entity, id))
+            .arg("score")
+            .arg("updated_at")
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(redis_error)?;
+        let (Some(score), Some(updated_at)) = (score, updated_at.and_then(DateTime::from_timestamp_millis)) else {
+            return Ok(None);
+        };
+        Ok(Some(StoredReputation { score, updated_at }))
+    }
+
+    async fn add(
+        &self,
+        entity: Entity,
+        id: &str,
+        points: f64,
+        now: DateTime<Utc>,
+        half_life: Duration,
+    ) -> Result<(), StoreError> {
+        redis::cmd("EVAL")
+            .arg(ADD_REPUTATION_SCRIPT)
+            .arg(1)
+            .arg(reputation_key(entity, id))
+            .arg(now.timestamp_millis())
+            .arg(half_life.as_millis() as u64)
+            .arg(points)
+            .arg(FADED)
+            .query_async::<i64>(&mut self.connection.clone())
+            .await
+            .map_err(redis_error)?;
+        Ok(())
+    }
+
+    async fn reset(&self, entity: Entity, id: &str) -> Result<bool, StoreError> {
+        let deleted: u32 = redis::cmd("DEL")
+            .arg(reputation_key(entity, id))
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(redis_error)?;
+        Ok(deleted > 0)
+    }
+}
+
+// --- REDIS ACTIVITY STORE ---
+/// Activity profiles shared across replicas. Each user's is a hash of per-hour counts
+/// (fields `0` to `23`) and `last_seen` (millis), expiring `ttl` after their last event.
+pub struct RedisActivityStore {
+    connection: ConnectionManager,
+    ttl: Duration,
+}
+
+impl RedisActivityStore {
+    pub async fn connect(url: &str, ttl: Duration) -> Result<Self, StoreError> {
+        let client = redis::Client::open(url).map_err(redis_error)?;
+        let connection = ConnectionManager::new(client).await.map_err(redis_error)?;
+        Ok(Self { connection, ttl })
+    }
+}
+
+fn activity_key(user_id: &str) -> String {
+    format!("fraud:user:{}:activity", user_id)
+}
+
+#[async_trait]
+impl ActivityStore for RedisActivityStore {
+    async fn profile(&self, user_id: &str) -> Result<Option<ActivityProfile>, StoreError> {
+        let fields: HashMap<String, i64> = redis::cmd("HGETALL")
+            .arg(activity_key(user_id))
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(redis_error)?;
+        if fields.is_empty() {
+            return Ok(None);
+        }
+
+        let mut profile = ActivityProfile::default();
+        for (field, value) in fields {
+            if field == "last_seen" {
+                profile.last_seen = DateTime::from_timestamp_millis(value);
+            } else if let Some(count) = field.parse::<usize>().ok().and_then(|hour| profile.hours.get_mut(hour)) {
+                *count = value.max(0) as u64;
+            }
+        }
+        Ok(Some(profile))
+    }
+
+    /// Sets `last_seen` to the event's time even when it arrived out of order; a late
+    /// event is off by minutes, which a dormancy period measured in days doesn't notice.
+    async fn record(&self, user_id: &str, at: DateTime<Utc>) -> Result<(), StoreError> {
+        let key = activity_key(user_id);
+        redis::pipe()
+            .atomic()
+            .hincr(&key, at.hour().to_string(), 1)
+            .ignore()
+            .hset(&key, "last_seen", at.timestamp_millis())
+            .ignore()
+            .expire(&key, self.ttl.as_secs() as i64)
+            .ignore()
+            .query_async::<()>(&mut self.connection.clone())
+            .await
+            .map_err(redis_error)
+    }
+
+    async fn forget(&self, user_id: &str) -> Result<bool, StoreError> {
+        let deleted: usize = redis::cmd("DEL")
+            .arg(activity_key(user_id))
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(redis_error)?;
+        Ok(deleted > 0)
+    }
+}
+
+// --- REDIS USAGE STORE ---
+/// Usage counts shared across replicas and restarts. Each key's hour is a hash of
+/// `events`, `flagged`, and `bytes`, expiring `ttl` after the hour's last write.
+pub struct RedisUsageStore {
+    connection: ConnectionManager,
+    ttl: Duration,
+}
+
+impl RedisUsageStore {
+    pub async fn connect(url: &str, ttl: Duration) -> Result<Self, StoreError> {
+        let client = redis::Client::open(url).map_err(redis_error)?;
+        let connection = ConnectionManager::new(client).await.map_err(redis_error)?;
+        Ok(Self { connection, ttl })
+    }
+}
+
+fn usage_key(key: &str, hour: i64) -> String {
+    format!("fraud:usage:{}:{}", key, hour)
+}
+
+#[async_trait]
+impl UsageStore for RedisUsageStore {
+    async fn add(&self, key: &str, hour: i64, counts: UsageCounts) -> Result<(), StoreError> {
+        let key = usage_key(key, hour);
+        redis::pipe()
+            .atomic()
+            .hincr(&key, "events", counts.events)
+            .ignore()
+            .hincr(&key, "flagged", counts.flagged)
+            .ignore()
+            .hincr(&key, "bytes", counts.bytes)
+            .ignore()
+            .expire(&key, self.ttl.as_secs() as i64)
+            .ignore()
+            .query_async::<()>(&mut self.connection.clone())
+            .await
+            .map_err(redis_error)
+    }
+
+    async fn series(&self, key: &str, from: i64, to: i64) -> Result<Vec<(i64, UsageCounts)>, StoreError> {
+        let mut pipe = redis::pipe();
+        for hour in from..to {
+            pipe.cmd("HGETALL").arg(usage_key(key, hour));
+        }
+        let hours: Vec<HashMap<String, u64>> = pipe
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(redis_error)?;
+
+        Ok((from..to)
+            .zip(hours)
+            .filter(|(_, fields)| !fields.is_empty())
+            .map(|(hour, fields)| {
+                let field = |name: &str| fields.get(name).copied().unwrap_or_default();
+                let counts = UsageCounts {
+                    events: field("events"),
+                    flagged: field("flagged"),
+                    bytes: field("bytes"),
+                };
+                (hour, counts)
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,142 @@
+use metrics::counter;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+use tokio::sync::Semaphore;
+use tracing::warn;
+
+use crate::breaker::CircuitBreaker;
+use crate::config::{ReputationConfig, ReputationMissMode};
+use crate::degradation::Unavailable;
+
+/// Cache size above which expired entries are swept on insert.
+const CACHE_SWEEP_THRESHOLD: usize = 100_000;
+
+// --- IP REPUTATION CLIENT ---
+/// Looks up abuse confidence scores (0-100) from an AbuseIPDB-style HTTP API. Results
+/// are cached per IP, outbound calls are capped by a semaphore, and a circuit breaker
+/// stops lookups entirely while the provider is failing.
+pub struct ReputationClient {
+    config: ReputationConfig,
+    http: reqwest::Client,
+    cache: RwLock<HashMap<String, (u8, Instant)>>,
+    permits: Arc<Semaphore>,
+    breaker: CircuitBreaker,
+}
+
+impl ReputationClient {
+    pub fn new(config: ReputationConfig) -> Result<Self, reqwest::Error> {
+        Ok(Self {
+            http: reqwest::Client::builder().build()?,
+            cache: RwLock::new(HashMap::new()),
+            permits: Arc::new(Semaphore::new(config.max_concurrency)),
+            breaker: CircuitBreaker::new("ip_reputation", config.breaker_failures, config.breaker_cooldown),
+            config,
+        })
+    }
+
+    /// Returns the IP's confidence score. Cache hits only take a read lock; misses either
+    /// wait up to the latency budget or warm the cache in the background, depending on
+    /// the configured miss mode. `Ok(None)` is a background miss, answered later;
+    /// `Unavailable` is a lookup that failed, timed out, or the open breaker skipped.
+    pub async fn lookup(self: &Arc<Self>, ip: &str) -> Result<Option<u8>, Unavailable> {
+        if let Some(score) = self.cached(ip) {
+            counter!("ip_reputation_cache_hits_total").increment(1);
+            return Ok(Some(score));
+        }
+
+        match self.config.on_miss {
+            ReputationMissMode::Await => {
+                if !self.breaker.allow() {
+                    counter!("ip_reputation_skipped_total", "reason" => "circuit_open").increment(1);
+                    return Err(Unavailable);
+                }
+                self.fetch_and_cache(ip).await.map(Some).ok_or(Unavailable)
+            }
+            ReputationMissMode::Background => {
+                let Ok(permit) = self.permits.clone().try_acquire_owned() else {
+                    counter!("ip_reputation_skipped_total", "reason" => "concurrency").increment(1);
+                    return Ok(None);
+                };
+                if !self.breaker.allow() {
+                    counter!("ip_reputation_skipped_total", "reason" => "circuit_open").increment(1);
+                    return Err(Unavailable);
+                }
+
+                let client = self.clone();
+                let ip = ip.to_string();
+                tokio::spawn(async move {
+                    client.fetch_and_cache(&ip).await;
+                    drop(permit);
+                });
+                Ok(None)
+            }
+        }
+    }
+
+    fn cached(&self, ip: &str) -> Option<u8> {
+        let cache = self.cache.read().unwrap();
+        let (score, fetched_at) = cache.get(ip)?;
+        (fetched_at.elapsed() < self.config.cache_ttl).then_some(*score)
+    }
+
+    /// Fetches within the latency budget (waiting for a permit counts against it) and
+    /// reports the outcome to the breaker.
+    async fn fetch_and_cache(&self, ip: &str) -> Option<u8> {
+        let outcome = tokio::time::timeout(self.config.timeout, async {
+            let _permit = self.permits.acquire().await.ok()?;
+            self.fetch(ip).await
+        })
+        .await;
+
+        match outcome {
+            Ok(Some(score)) => {
+                self.breaker.record_success();
+                counter!("ip_reputation_lookups_total", "outcome" => "ok").increment(1);
+                self.store(ip, score);
+                Some(score)
+            }
+            Ok(None) => {
+                self.breaker.record_failure();
+                counter!("ip_reputation_lookups_total", "outcome" => "error").increment(1);
+                None
+            }
+            Err(_) => {
+                self.breaker.record_failure();
+                counter!("ip_reputation_lookups_total", "outcome" => "timeout").increment(1);
+                None
+            }
+        }
+    }
+
+    async fn fetch(&self, ip: &str) -> Option<u8> {
+        let mut request = self.http.get(self.config.url.replace("{ip}", ip));
+        if let Some(key) = &self.config.api_key {
+            request = request.header("Key", key);
+        }
+
+        let body = match request.send().await.and_then(|r| r.error_for_status()) {
+            Ok(response) => response.text().await.ok()?,
+            Err(e) => {
+                // The URL carries the IP, which must not reach the logs.
+                warn!("IP reputation lookup failed: {}", e.without_url());
+                return None;
+            }
+        };
+
+        let score = serde_json::from_str::<serde_json::Value>(&body)
+            .ok()?
+            .pointer(&self.config.score_pointer)?
+            .as_u64()?;
+        Some(score.min(100) as u8)
+    }
+
+    fn store(&self, ip: &str, score: u8) {
+        let mut cache = self.cache.write().unwrap();
+        if cache.len() >= CACHE_SWEEP_THRESHOLD {
+            let ttl = self.config.cache_ttl;
+            cache.retain(|_, (_, fetched_at)| fetched_at.elapsed() < ttl);
+        }
+        cache.insert(ip.to_string(), (score, Instant::now()));
+    }
+}
@@ -0,0 +1,111 @@
+use axum::{
+    extract::State,
+    http::{HeaderName, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tracing::{info_span, Instrument};
+use uuid::Uuid;
+
+use crate::AppState;
+
+pub static REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest client-supplied id that is reused; anything longer gets a fresh one.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// SplitMix64's increment, which walks the whole `u64` range before repeating.
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+// --- REQUEST ID GENERATION ---
+/// Where generated request ids come from. With `RANDOM_SEED` set they are drawn from a
+/// SplitMix64 sequence, so a replay sending the same requests in the same order gets
+/// the same ids.
+pub enum RequestIds {
+    Random,
+    Seeded(AtomicU64),
+}
+
+impl RequestIds {
+    pub fn new(seed: Option<u64>) -> Self {
+        match seed {
+            Some(seed) => RequestIds::Seeded(AtomicU64::new(seed)),
+            None => RequestIds::Random,
+        }
+    }
+
+    fn next(&self) -> Uuid {
+        match self {
+            RequestIds::Random => Uuid::new_v4(),
+            RequestIds::Seeded(state) => {
+                // Two draws per id, for its 128 bits.
+                let state = state.fetch_add(GOLDEN_GAMMA.wrapping_mul(2), Ordering::Relaxed);
+                let high = mix(state.wrapping_add(GOLDEN_GAMMA));
+                let low = mix(state.wrapping_add(GOLDEN_GAMMA.wrapping_mul(2)));
+                let bytes = ((u128::from(high) << 64) | u128::from(low)).to_be_bytes();
+                uuid::Builder::from_random_bytes(bytes).into_uuid()
+            }
+        }
+    }
+}
+
+/// SplitMix64's output function.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// --- REQUEST ID MIDDLEWARE ---
+/// Reuses the client's `X-Request-Id` (or generates a UUID), runs the request inside a
+/// span carrying it so every log line can be correlated, and echoes it in the response.
+pub async fn propagate_request_id<B>(State(state): State<Arc<AppState>>, mut request: Request<B>, next: Next<B>) -> Response {
+    let request_id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .filter(|value| is_usable(value))
+        .cloned()
+        .unwrap_or_else(|| {
+            HeaderValue::from_str(&state.request_ids.next().to_string()).expect("UUIDs are valid header values")
+        });
+    request.headers_mut().insert(REQUEST_ID_HEADER.clone(), request_id.clone());
+
+    let span = info_span!("request", request_id = %request_id.to_str().unwrap_or_default());
+    let mut response = next.run(request).instrument(span).await;
+    response.headers_mut().insert(REQUEST_ID_HEADER.clone(), request_id);
+    response
+}
+
+/// Only short, printable ids are trusted, so a client can't inject junk into the logs.
+fn is_usable(value: &HeaderValue) -> bool {
+    value
+        .to_str()
+        .is_ok_and(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.chars().all(|c| c.is_ascii_graphic()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_ids_repeat_per_seed_and_differ_within_a_sequence() {
+        let (a, b) = (RequestIds::new(Some(7)), RequestIds::new(Some(7)));
+        let first: Vec<Uuid> = (0..3).map(|_| a.next()).collect();
+        let second: Vec<Uuid> = (0..3).map(|_| b.next()).collect();
+        assert_eq!(first, second);
+        assert!(first[0] != first[1] && first[1] != first[2]);
+        assert_ne!(RequestIds::new(Some(8)).next(), first[0]);
+        assert_eq!(first[0].get_version_num(), 4);
+    }
+
+    #[test]
+    fn only_short_printable_ids_are_usable() {
+        assert!(is_usable(&HeaderValue::from_static("ticket-4521")));
+        assert!(!is_usable(&HeaderValue::from_static("")));
+        assert!(!is_usable(&HeaderValue::from_static("two words")));
+        assert!(!is_usable(&HeaderValue::from_str(&"x".repeat(MAX_REQUEST_ID_LEN + 1)).unwrap()));
+        assert!(is_usable(&HeaderValue::from_str(&"x".repeat(MAX_REQUEST_ID_LEN)).unwrap()));
+    }
+}
@@ -0,0 +1,232 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{FromRequest, State},
+    http::{header, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    RequestExt,
+};
+use metrics::counter;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::ResponseCacheConfig;
+use crate::msgpack;
+use crate::{AppError, AppState};
+
+/// Larger responses are served but not kept, so a few big simulation reports can't
+/// crowd out everything else.
+const MAX_CACHED_BODY_BYTES: usize = 1024 * 1024;
+
+// --- RESPONSE CACHE ---
+struct CachedResponse {
+    content_type: Option<HeaderValue>,
+    body: Bytes,
+    stored_at: Instant,
+    /// Tick of the last hit, for least-recently-used eviction.
+    used: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    responses: HashMap<[u8; 32], CachedResponse>,
+    tick: u64,
+}
+
+/// Responses of the explain and simulate endpoints (the diff included), keyed by a hash
+/// of the request and the scoring config and blacklist versions, so interactive tuning
+/// that sends the same request again gets the answer without rescoring. Cleared on
+/// config reload.
+pub struct ResponseCache {
+    entries: Mutex<Entries>,
+    size: usize,
+    ttl: Duration,
+}
+
+impl ResponseCache {
+    pub fn new(config: &ResponseCacheConfig) -> Self {
+        Self {
+            entries: Mutex::new(Entries::default()),
+            size: config.size,
+            ttl: config.ttl,
+        }
+    }
+
+    fn get(&self, key: &[u8; 32]) -> Option<(Option<HeaderValue>, Bytes)> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.tick += 1;
+        let tick = entries.tick;
+        let cached = entries.responses.get_mut(key)?;
+        if cached.stored_at.elapsed() >= self.ttl {
+            entries.responses.remove(key);
+            return None;
+        }
+        cached.used = tick;
+        Some((cached.content_type.clone(), cached.body.clone()))
+    }
+
+    fn insert(&self, key: [u8; 32], content_type: Option<HeaderValue>, body: Bytes) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.tick += 1;
+        let used = entries.tick;
+        if entries.responses.len() >= self.size && !entries.responses.contains_key(&key) {
+            let oldest = entries.responses.iter().min_by_key(|(_, cached)| cached.used).map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                entries.responses.remove(&oldest);
+            }
+        }
+        let cached = CachedResponse {
+            content_type,
+            body,
+            stored_at: Instant::now(),
+            used,
+        };
+        entries.responses.insert(key, cached);
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().responses.clear();
+    }
+}
+
+/// Layer for the explain and simulate routes: answers from the cache when it can, and
+/// keeps successful answers. Every response says which in `X-Cache: hit|miss`.
+pub async fn reuse_responses(State(state): State<Arc<AppState>>, request: Request<Body>, next: Next<Body>) -> Response {
+    let Some(cache) = &state.response_cache else {
+        return next.run(request).await;
+    };
+    let endpoint = match request.uri().path() {
+        path if path.ends_with("/simulate") => "simulate",
+        path if path.ends_with("/simulate/diff") => "simulate_diff",
+        _ => "explain",
+    };
+
+    // Buffered under the route's body limit, as the handler itself would.
+    let (parts, body) = match request.with_limited_body() {
+        Ok(request) => {
+            let (parts, body) = request.into_parts();
+            (parts, Bytes::from_request(Request::new(body), &()).await)
+        }
+        Err(request) => {
+            let (parts, body) = request.into_parts();
+            (parts, Bytes::from_request(Request::new(body), &()).await)
+        }
+    };
+    let body = match body {
+        Ok(body) => body,
+        Err(rejection) => return AppError::from(rejection).into_response(),
+    };
+
+    let mut hasher = Sha256::new();
+    for part in [
+        parts.uri.path().as_bytes(),
+        parts.headers.get(header::CONTENT_TYPE).map_or(b"", |value| value.as_bytes()),
+        if msgpack::accepts_msgpack(&parts.headers) { b"msgpack" } else { b"json" },
+        state.scoring().version.as_bytes(),
+        &state.ip_blacklist.version().to_be_bytes(),
+    ] {
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+    hasher.update(&body);
+    let key: [u8; 32] = hasher.finalize().into();
+
+    if let Some((content_type, body)) = cache.get(&key) {
+        counter!("response_cache_hits_total", "endpoint" => endpoint).increment(1);
+        let mut response = body.into_response();
+        if let Some(content_type) = content_type {
+            response.headers_mut().insert(header::CONTENT_TYPE, content_type);
+        }
+        response.headers_mut().insert("x-cache", HeaderValue::from_static("hit"));
+        return response;
+    }
+    counter!("response_cache_misses_total", "endpoint" => endpoint).increment(1);
+
+    let mut response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    if response.status() == StatusCode::OK {
+        let (mut parts, body) = response.into_parts();
+        let body = match Bytes::from_request(Request::new(body), &()).await {
+            Ok(body) => body,
+            Err(rejection) => return AppError::from(rejection).into_response(),
+        };
+        if body.len() <= MAX_CACHED_BODY_BYTES {
+            cache.insert(key, parts.headers.get(header::CONTENT_TYPE).cloned(), body.clone());
+        }
+        parts.headers.insert("x-cache", HeaderValue::from_static("miss"));
+        return Response::from_parts(parts, axum::body::boxed(axum::body::Full::from(body)));
+    }
+    response.headers_mut().insert("x-cache", HeaderValue::from_static("miss"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(size: usize, ttl: Duration) -> ResponseCache {
+        ResponseCache::new(&ResponseCacheConfig { size, ttl })
+    }
+
+    fn body(cache: &ResponseCache, key: u8) -> Option<Bytes> {
+        cache.get(&[key; 32]).map(|(_, body)| body)
+    }
+
+    fn put(cache: &ResponseCache, key: u8) {
+        cache.insert([key; 32], Some(HeaderValue::from_static("application/json")), Bytes::from(vec![key]));
+    }
+
+    #[test]
+    fn keeps_the_content_type_and_body() {
+        let cache = cache(4, Duration::from_secs(60));
+        put(&cache, 1);
+        let (content_type, body) = cache.get(&[1; 32]).unwrap();
+        assert_eq!(content_type.unwrap(), "application/json");
+        assert_eq!(body, Bytes::from(vec![1]));
+        assert!(cache.get(&[2; 32]).is_none());
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_entry() {
+        let cache = cache(2, Duration::from_secs(60));
+        put(&cache, 1);
+        put(&cache, 2);
+        // Reading 1 makes 2 the oldest.
+        assert!(body(&cache, 1).is_some());
+        put(&cache, 3);
+        assert!(body(&cache, 2).is_none());
+        assert!(body(&cache, 1).is_some());
+        assert!(body(&cache, 3).is_some());
+    }
+
+    #[test]
+    fn replacing_an_entry_evicts_nothing() {
+        let cache = cache(2, Duration::from_secs(60));
+        put(&cache, 1);
+        put(&cache, 2);
+        put(&cache, 2);
+        assert!(body(&cache, 1).is_some());
+        assert!(body(&cache, 2).is_some());
+    }
+
+    #[test]
+    fn entries_expire_after_the_ttl() {
+        let cache = cache(4, Duration::from_millis(20));
+        put(&cache, 1);
+        assert!(body(&cache, 1).is_some());
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(body(&cache, 1).is_none());
+        assert!(cache.entries.lock().unwrap().responses.is_empty());
+    }
+
+    #[test]
+    fn clear_drops_everything() {
+        let cache = cache(4, Duration::from_secs(60));
+        put(&cache, 1);
+        put(&cache, 2);
+        cache.clear();
+        assert!(body(&cache, 1).is_none());
+        assert!(body(&cache, 2).is_none());
+    }
+}
@@ -0,0 +1,106 @@
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::{RolloutBasis, RolloutConfig};
+use crate::UserEvent;
+
+// --- RULE ROLLOUTS ---
+/// Rollout percentages resolve to 0.01%.
+const ROLLOUT_BUCKETS: u64 = 10_000;
+/// Sessions held above which idle ones are swept on insert.
+const SWEEP_THRESHOLD: usize = 100_000;
+
+/// Whether the event's hash basis falls in the first `percent` of the rule's buckets.
+/// The rule's name is hashed in too, so each rollout takes its own slice of traffic.
+pub fn in_rollout(rule: &str, rollout: &RolloutConfig, event: &UserEvent) -> bool {
+    let key = match rollout.basis {
+        RolloutBasis::SessionId => &event.session_id,
+        RolloutBasis::UserId => event.user_id.as_ref().unwrap_or(&event.session_id),
+    };
+    let digest = Sha256::new().chain_update(rule).chain_update([0]).chain_update(key).finalize();
+    let bucket = u64::from_be_bytes(digest[..8].try_into().unwrap()) % ROLLOUT_BUCKETS;
+    (bucket as f64) < rollout.percent * (ROLLOUT_BUCKETS / 100) as f64
+}
+
+/// The rules to run in shadow for the event under the current percentages, without
+/// any memory of earlier assignments (for simulations).
+pub fn shadowed(rollouts: &HashMap<String, RolloutConfig>, event: &UserEvent) -> Vec<String> {
+    rollouts
+        .iter()
+        .filter(|(rule, rollout)| !in_rollout(rule, rollout, event))
+        .map(|(rule, _)| rule.clone())
+        .collect()
+}
+
+/// Which side of each rollout a session fell on. A session keeps the side it got on its
+/// first event under the rollout, so a reload changing the percentage only moves new
+/// sessions, and none flips mid-session.
+#[derive(Default)]
+pub struct RolloutTracker {
+    sessions: Mutex<HashMap<String, Assignments>>,
+}
+
+#[derive(Clone)]
+struct Assignments {
+    /// Whether each rule is enforced for the session.
+    enforced: HashMap<String, bool>,
+    touched: Instant,
+}
+
+impl RolloutTracker {
+    /// The rules to run in shadow for the event. `remember` keeps new assignments, which
+    /// only the scoring path does; explain requests see them without taking a side.
+    pub fn shadowed(
+        &self,
+        rollouts: &HashMap<String, RolloutConfig>,
+        event: &UserEvent,
+        remember: bool,
+        ttl: Duration,
+    ) -> Vec<String> {
+        if rollouts.is_empty() {
+            return Vec::new();
+        }
+        let now = Instant::now();
+        let mut sessions = self.sessions.lock().unwrap();
+        if remember && sessions.len() >= SWEEP_THRESHOLD && !sessions.contains_key(&event.session_id) {
+            sessions.retain(|_, assignments| now.duration_since(assignments.touched) < ttl);
+        }
+
+        let mut scratch = Assignments {
+            enforced: HashMap::new(),
+            touched: now,
+        };
+        let assignments = if remember {
+            let assignments = sessions.entry(event.session_id.clone()).or_insert(scratch);
+            assignments.touched = now;
+            assignments
+        } else {
+            if let Some(assignments) = sessions.get(&event.session_id) {
+                scratch.clone_from(assignments);
+            }
+            &mut scratch
+        };
+        rollouts
+            .iter()
+            .filter(|(rule, rollout)| {
+                let enforced = assignments
+                    .enforced
+                    .entry(rule.to_string())
+                    .or_insert_with(|| in_rollout(rule, rollout, event));
+                !*enforced
+            })
+            .map(|(rule, _)| rule.clone())
+            .collect()
+    }
+
+    /// Forgets sessions with no event for `ttl`.
+    pub fn sweep(&self, ttl: Duration) {
+        let now = Instant::now();
+        self.sessions
+            .lock()
+            .unwrap()
+            .retain(|_, assignments| now.duration_since(assignments.touched) < ttl);
+    }
+}
@@ -0,0 +1,2703 @@
+use chrono::Timelike;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::activity::ActivityProfile;
+use crate::allowlist::AllowlistMatch;
+use crate::composite::{self, CompositeRule};
+use crate::config::{Decision, MetadataKeySchema, MetadataSchemaMode, MetadataValueKind, ScoringConfig};
+use crate::email::{DisposableDomains, EmailAddress};
+use crate::entity_reputation::EntityScores;
+use crate::geoip::GeoInfo;
+use crate::payments;
+use crate::rates::Rates;
+use crate::scripting;
+use crate::session_cache::SessionContext;
+use crate::sources::{SourceCategory, SuspiciousSources};
+use crate::{EventType, UserEvent};
+
+// --- RULE PIPELINE ---
+/// A single rule's contribution to an event's score.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleHit {
+    pub code: Cow<'static, str>,
+    pub points: i32,
+    pub reason: String,
+    /// For a composite rule, the hits of the rules it combines.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<RuleHit>,
+}
+
+/// Everything a rule may look at. Rules only read from the context; storing the event
+/// is the caller's job, which is what lets the explain endpoint run the same pipeline.
+pub struct RuleContext<'a> {
+    pub event: &'a UserEvent,
+    /// The session's events in arrival order, ending with `event`.
+    pub history: &'a [Arc<UserEvent>],
+    /// The event's IP as received. `event.ip_address` (like the rest of the history) may
+    /// already be pseudonymized, so anything matching real addresses uses this.
+    pub client_ip: &'a str,
+    /// The client IP derived from the connection, when `CLIENT_IP_POLICY=cross_check`
+    /// and the request carried one the service could trust.
+    pub observed_ip: Option<IpAddr>,
+    /// Cached per-session facts: blacklist membership and user agent classification.
+    pub session: SessionContext<'a>,
+    pub suspicious_sources: &'a SuspiciousSources,
+    pub disposable_domains: &'a DisposableDomains,
+    pub config: &'a ScoringConfig,
+    /// Provider confidence (0-100) for the event's IP, when a lookup succeeded in time.
+    pub ip_reputation: Option<u8>,
+    /// How many times the event's user was flagged before this event (0 when anonymous).
+    pub prior_flags: u32,
+    /// The user's other sessions active within the `concurrent_sessions` window, most
+    /// recently active first (empty when anonymous).
+    pub linked_sessions: &'a [String],
+    /// Other sessions seen with the event's device fingerprint within the
+    /// `device_fingerprint` window, most recently active first (empty without one).
+    pub fingerprint_sessions: &'a [String],
+    /// The user's activity profile before this event (`None` when anonymous, new, or
+    /// the `activity_profile` rule is off).
+    pub activity: Option<&'a ActivityProfile>,
+    /// GeoIP facts for the event's IP, when a database is configured and knows the IP.
+    pub geo: Option<GeoInfo>,
+    /// The user's payments in the event's currency within the velocity window, excluding
+    /// this event (0 for anonymous users and non-payment events).
+    pub payment_window_total: f64,
+    /// Events before this one within the `high_frequency` window, per session event
+    /// type, IP, and user (all 0 without a window).
+    pub rates: &'a Rates,
+    /// The model's fraud probability, when a model is loaded and answered in time.
+    pub model_probability: Option<f32>,
+    /// The session's decayed reputation before this event (0 when disabled).
+    pub session_reputation: f64,
+    /// The decayed reputations of the event's user and IP before it (0 when disabled,
+    /// anonymous, or never flagged).
+    pub entity_reputation: EntityScores,
+    /// Dependencies that failed for this event; rules requiring them are skipped.
+    pub unavailable: &'a [Dependency],
+    /// Rules with a rollout the event is outside of; they run, but don't score.
+    pub shadow_rules: &'a [String],
+}
+
+/// A reason code a rule can add and its points under the current config. For rules
+/// whose points scale (by confidence, probability, or prior flags), the most one hit adds.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleWeight {
+    pub code: Cow<'static, str>,
+    pub points: i32,
+}
+
+impl RuleWeight {
+    fn new(code: impl Into<Cow<'static, str>>, points: i32) -> Self {
+        Self {
+            code: code.into(),
+            points,
+        }
+    }
+}
+
+/// Optional services a rule can't score without.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dependency {
+    IpReputation,
+    Model,
+    /// `GEOIP_ASN_DB_PATH`.
+    AsnData,
+    /// `CLIENT_IP_POLICY=cross_check`.
+    ObservedIp,
+    /// Activity profiles, in Redis when `REDIS_URL` is set.
+    ActivityStore,
+    /// Entity reputations, in Redis when `REDIS_URL` is set.
+    EntityReputation,
+}
+
+impl Dependency {
+    pub fn name(self) -> &'static str {
+        match self {
+            Dependency::IpReputation => "ip_reputation",
+            Dependency::Model => "model",
+            Dependency::AsnData => "asn_data",
+            Dependency::ObservedIp => "observed_ip",
+            Dependency::ActivityStore => "activity_store",
+            Dependency::EntityReputation => "entity_reputation",
+        }
+    }
+}
+
+pub trait Rule: Send + Sync {
+    /// Identifies the rule in reports: its scoring config section, or a scripted rule's name.
+    fn name(&self) -> &str;
+
+    /// One line on what the rule scores, for the rules listing.
+    fn description(&self) -> &str;
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight>;
+
+    /// Whether `config` gives the rule anything to check, beyond its points.
+    fn configured(&self, _config: &ScoringConfig) -> bool {
+        true
+    }
+
+    fn requires(&self) -> Option<Dependency> {
+        None
+    }
+
+    /// Whether the rule judges the session's history rather than the event alone, and
+    /// so sits out until the session reaches `min_events_for_stateful`.
+    fn stateful(&self) -> bool {
+        false
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>);
+}
+
+/// The points one rule contributed to a result, across all its hits.
+#[derive(Debug, Clone)]
+pub struct RuleOutcome {
+    pub rule: String,
+    pub points: i32,
+}
+
+/// How a rule under a rollout fared on one event, on whichever side of it the event fell.
+#[derive(Debug, Clone)]
+pub struct RolloutOutcome {
+    pub rule: String,
+    /// Enforced rather than run in shadow.
+    pub in_rollout: bool,
+    pub hit: bool,
+    /// The rule hit and the event was flagged, or in shadow, would have been with its points.
+    pub flagged: bool,
+}
+
+#[derive(Debug)]
+pub struct Analysis {
+    /// `raw_score` mapped onto 0-100 per the confidence config.
+    pub score: i32,
+    /// The points of the hits, summed.
+    pub raw_score: i32,
+    pub flagged: bool,
+    /// What the decision policy says to do with the event.
+    pub decision: Decision,
+    pub hits: Vec<RuleHit>,
+    /// The session's reputation including this event, when session reputation is on.
+    pub session_reputation: Option<f64>,
+    /// Rules that hit, in engine order. Empty when the rules didn't run.
+    pub outcomes: Vec<RuleOutcome>,
+    /// Set when a dependency failed and the event was scored without it.
+    pub degraded: bool,
+    /// Rules left out because a dependency they require failed.
+    pub skipped_rules: Vec<String>,
+    /// Rules under a rollout that ran, in engine order.
+    pub rollouts: Vec<RolloutOutcome>,
+}
+
+impl Analysis {
+    /// The result for trusted traffic: never scored, but the reason keeps it auditable.
+    pub fn allowlisted(matched: AllowlistMatch, session_reputation: Option<f64>) -> Self {
+        let reason = match matched {
+            AllowlistMatch::Ip => "Allowlisted IP address",
+            AllowlistMatch::User => "Allowlisted user",
+        };
+        Self {
+            score: 0,
+            raw_score: 0,
+            flagged: false,
+            decision: Decision::Allow,
+            hits: vec![RuleHit {
+                code: "ALLOWLISTED".into(),
+                points: 0,
+                reason: reason.to_string(),
+                children: Vec::new(),
+            }],
+            session_reputation,
+            outcomes: Vec::new(),
+            degraded: false,
+            skipped_rules: Vec::new(),
+            rollouts: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleInfo {
+    pub name: String,
+    pub enabled: bool,
+    /// Waits for `min_events_for_stateful` events in the session.
+    pub stateful: bool,
+    pub description: String,
+    pub weights: Vec<RuleWeight>,
+}
+
+pub struct RuleEngine {
+    rules: Vec<Box<dyn Rule>>,
+    /// Evaluated after `rules`, from their hits, each after the composites it names.
+    composites: Vec<CompositeRule>,
+}
+
+impl RuleEngine {
+    /// The native rules followed by the config's scripted rules, in config order, and
+    /// then its composite rules.
+    pub fn new(config: &ScoringConfig) -> Result<Self, String> {
+        let mut rules: Vec<Box<dyn Rule>> = vec![
+            Box::new(BlacklistRule),
+            Box::new(SuspiciousSourceRule),
+            Box::new(FastInteractionRule),
+            Box::new(HighFrequencyRule),
+            Box::new(MetadataKeysRule),
+            Box::new(IpReputationRule),
+            Box::new(AsnReputationRule),
+            Box::new(IpMismatchRule),
+            Box::new(UserAgentRule),
+            Box::new(SharedSessionRule),
+            Box::new(RepeatOffenderRule),
+            Box::new(EntityReputationRule),
+            Box::new(ConcurrentSessionsRule),
+            Box::new(DeviceFingerprintRule),
+            Box::new(CadenceRule),
+            Box::new(ClickTimingRule),
+            Box::new(TypeTogglingRule),
+            Box::new(PaymentRule),
+            Box::new(DisposableEmailRule),
+            Box::new(ReferrerOriginRule),
+            Box::new(LocaleMismatchRule),
+            Box::new(FieldTimingRule),
+            Box::new(SessionDurationRule),
+            Box::new(ActivityProfileRule),
+            Box::new(ModelScoreRule),
+        ];
+        for rule in scripting::compile(&config.scripted_rules, &config.script_limits).map_err(|e| e.to_string())? {
+            rules.push(Box::new(rule));
+        }
+        let rule_names = rules.iter().map(|rule| rule.name().to_string()).collect();
+        let composites = composite::compile(&config.composite_rules, &rule_names)?;
+        if let Some(unknown) = config.rollouts.keys().find(|name| {
+            !rule_names.contains(*name) && !composites.iter().any(|composite| composite.name() == name.as_str())
+        }) {
+            return Err(format!("rollouts refers to unknown rule `{}`", unknown));
+        }
+        Ok(Self { rules, composites })
+    }
+
+    /// Names of every registered rule, in evaluation order.
+    pub fn rule_names(&self) -> impl Iterator<Item = &str> {
+        self.rules
+            .iter()
+            .map(|rule| rule.name())
+            .chain(self.composites.iter().map(|composite| composite.name()))
+    }
+
+    /// Every registered rule as configured, in evaluation order. A rule is enabled when
+    /// it is configured, any of its points is nonzero, and `available` has what it requires.
+    pub fn describe(&self, config: &ScoringConfig, available: impl Fn(Dependency) -> bool) -> Vec<RuleInfo> {
+        self.rules
+            .iter()
+            .map(|rule| {
+                let weights = rule.weights(config);
+                let enabled = rule.configured(config)
+                    && rule.requires().is_none_or(&available)
+                    && weights.iter().any(|weight| weight.points != 0);
+                RuleInfo {
+                    name: rule.name().to_string(),
+                    enabled,
+                    stateful: rule.stateful(),
+                    description: rule.description().to_string(),
+                    weights,
+                }
+            })
+            .chain(self.composites.iter().map(|composite| {
+                let weight = composite.weight();
+                RuleInfo {
+                    name: composite.name().to_string(),
+                    enabled: weight.points != 0,
+                    stateful: false,
+                    description: composite.description().to_string(),
+                    weights: vec![weight],
+                }
+            }))
+            .collect()
+    }
+
+    pub fn evaluate(&self, ctx: &RuleContext<'_>) -> Analysis {
+        let mut tally = Tally::default();
+        let profile = ctx.config.event_profiles.get(&ctx.event.event_type);
+        let mut skipped_rules = Vec::new();
+        let established = ctx.history.len() >= ctx.config.min_events_for_stateful;
+        for rule in &self.rules {
+            if rule.requires().is_some_and(|dependency| ctx.unavailable.contains(&dependency)) {
+                skipped_rules.push(rule.name().to_string());
+                continue;
+            }
+            if rule.stateful() && !established {
+                continue;
+            }
+            let before = tally.hits.len();
+            rule.evaluate(ctx, &mut tally.hits);
+            if let Some(profile) = profile.filter(|profile| !profile.weights.is_empty()) {
+                reweigh(&mut tally.hits, before, &profile.weights);
+            }
+            tally.file(rule.name(), before, ctx);
+        }
+
+        for composite in &self.composites {
+            // An unscored rule can neither be counted on nor ruled out.
+            if composite.references().any(|name| skipped_rules.iter().any(|skipped| skipped == name)) {
+                skipped_rules.push(composite.name().to_string());
+                continue;
+            }
+            let hit = composite.evaluate(|name| tally.hit_ranges.get(name).map(|range| &tally.hits[range.clone()]));
+            let before = tally.hits.len();
+            tally.hits.extend(hit);
+            if let Some(profile) = profile.filter(|profile| !profile.weights.is_empty()) {
+                reweigh(&mut tally.hits, before, &profile.weights);
+            }
+            tally.file(composite.name(), before, ctx);
+        }
+        let Tally {
+            mut hits,
+            outcomes,
+            rolled_out,
+            ..
+        } = tally;
+
+        let raw_score: i32 = hits.iter().map(|hit| hit.points).sum();
+        let normalize = |raw_score: i32| i32::from(ctx.config.confidence.confidence(raw_score));
+        let score = normalize(raw_score);
+        let flag_threshold = ctx.config.flag_threshold(&ctx.event.event_type);
+        let flags = |raw_score: i32| normalize(raw_score) >= flag_threshold;
+        let mut flagged = flags(raw_score);
+        let rollouts = rolled_out
+            .into_iter()
+            .map(|(rule, in_rollout, points)| RolloutOutcome {
+                rule: rule.to_string(),
+                in_rollout,
+                hit: points.is_some(),
+                flagged: match (in_rollout, points) {
+                    (_, None) => false,
+                    (true, Some(_)) => flagged,
+                    (false, Some(points)) => flags(raw_score + points),
+                },
+            })
+            .collect();
+
+        // Only scores add to the reputation; negative points can't launder a session.
+        let reputation_config = &ctx.config.session_reputation;
+        let session_reputation = reputation_config
+            .half_life()
+            .map(|_| ctx.session_reputation + f64::from(raw_score.max(0)));
+        if let Some(reputation) = session_reputation {
+            if !flagged && reputation >= f64::from(reputation_config.flag_threshold) {
+                flagged = true;
+                hits.push(RuleHit {
+                    code: "SESSION_REPUTATION".into(),
+                    points: 0,
+                    reason: format!(
+                        "Session reputation {:.0} reached the threshold of {}",
+                        reputation, reputation_config.flag_threshold
+                    ),
+                    children: Vec::new(),
+                });
+            }
+        }
+
+        let decision = ctx
+            .config
+            .decision_policy
+            .decide(score, flagged, hits.iter().map(|hit| &hit.code));
+        Analysis {
+            score,
+            raw_score,
+            flagged,
+            decision,
+            hits,
+            session_reputation,
+            outcomes,
+            degraded: !ctx.unavailable.is_empty(),
+            skipped_rules,
+            rollouts,
+        }
+    }
+}
+
+/// The hits of one evaluation so far, filed by rule.
+#[derive(Default)]
+struct Tally<'r> {
+    hits: Vec<RuleHit>,
+    /// Where each rule that hit left its hits, for the composites.
+    hit_ranges: HashMap<&'r str, Range<usize>>,
+    outcomes: Vec<RuleOutcome>,
+    /// Each rule under a rollout that ran: whether it was enforced, and its points if it hit.
+    rolled_out: Vec<(&'r str, bool, Option<i32>)>,
+}
+
+impl<'r> Tally<'r> {
+    /// Files the hits from `before` on under `rule`, or takes them back out when the
+    /// event is outside the rule's rollout.
+    fn file(&mut self, rule: &'r str, before: usize, ctx: &RuleContext<'_>) {
+        let hit = self.hits.len() > before;
+        let points = hit.then(|| self.hits[before..].iter().map(|hit| hit.points).sum());
+        if ctx.config.rollouts.contains_key(rule) {
+            let enforced = !ctx.shadow_rules.iter().any(|shadow| shadow == rule);
+            self.rolled_out.push((rule, enforced, points));
+            if !enforced {
+                self.hits.truncate(before);
+                return;
+            }
+        }
+        if let Some(points) = points {
+            self.hit_ranges.insert(rule, before..self.hits.len());
+            self.outcomes.push(RuleOutcome {
+                rule: rule.to_string(),
+                points,
+            });
+        }
+    }
+}
+
+/// Applies an event profile's weights to the hits from `start` on, dropping those it
+/// weighs at 0.
+fn reweigh(hits: &mut Vec<RuleHit>, start: usize, weights: &HashMap<String, i32>) {
+    let reweighed: Vec<RuleHit> = hits
+        .drain(start..)
+        .filter_map(|mut hit| {
+            if let Some(points) = weights.get(hit.code.as_ref()) {
+                hit.points = *points;
+            }
+            (hit.points != 0).then_some(hit)
+        })
+        .collect();
+    hits.extend(reweighed);
+}
+
+/// Gaps in milliseconds between consecutive timestamps, after sorting them.
+fn intervals_ms(mut timestamps: Vec<i64>) -> Vec<i64> {
+    timestamps.sort_unstable();
+    timestamps.windows(2).map(|pair| pair[1] - pair[0]).collect()
+}
+
+/// Mean and population standard deviation.
+pub fn mean_and_std_dev(values: &[i64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<i64>() as f64 / n;
+    let variance = values.iter().map(|v| (*v as f64 - mean).powi(2)).sum::<f64>() / n;
+    (mean, variance.sqrt())
+}
+
+/// Host part of a URL-ish value, lowercased, ignoring scheme, credentials, port, path,
+/// query, and fragment. Scheme-less values like `example.com/path` are accepted.
+fn url_host(value: &str) -> Option<String> {
+    let value = value.trim();
+    let rest = value.split_once("://").map_or(value, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host_port = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+
+    let host = if let Some(bracketed) = host_port.strip_prefix('[') {
+        bracketed.split_once(']')?.0
+    } else {
+        host_port.split_once(':').map_or(host_port, |(host, _)| host)
+    };
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    (!host.is_empty()).then_some(host)
+}
+
+/// The region of a BCP 47 locale like `en-US` or `zh-Hant-TW`, uppercased: its first
+/// two-letter subtag after the language. POSIX forms like `en_US.UTF-8` are accepted.
+/// `None` for bare languages and numeric regions like `es-419`, which name no country.
+fn locale_country(locale: &str) -> Option<String> {
+    let locale = locale.trim().split(['.', '@']).next()?;
+    for subtag in locale.split(['-', '_']).skip(1) {
+        match subtag.len() {
+            2 if subtag.bytes().all(|byte| byte.is_ascii_alphabetic()) => return Some(subtag.to_ascii_uppercase()),
+            3 if subtag.bytes().all(|byte| byte.is_ascii_digit()) => return None,
+            // Extensions and private use (`-u-...`, `-x-...`) come after any region.
+            1 => return None,
+            // Scripts, extended languages, and variants.
+            _ => {}
+        }
+    }
+    None
+}
+
+// --- RULES ---
+struct BlacklistRule;
+
+impl Rule for BlacklistRule {
+    fn name(&self) -> &str {
+        "blacklist"
+    }
+
+    fn description(&self) -> &str {
+        "Event IP is on the blacklist"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        vec![RuleWeight::new("IP_BLACKLISTED", config.blacklist.points)]
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        if ctx.session.blacklisted(ctx.client_ip) {
+            hits.push(RuleHit {
+                code: "IP_BLACKLISTED".into(),
+                points: ctx.config.blacklist.points,
+                reason: "Blacklisted IP address".to_string(),
+                children: Vec::new(),
+            });
+        }
+    }
+}
+
+/// Scores IPs on the Tor exit and open proxy lists, each with its own weight. An IP on
+/// several lists is scored for each.
+struct SuspiciousSourceRule;
+
+impl Rule for SuspiciousSourceRule {
+    fn name(&self) -> &str {
+        "suspicious_sources"
+    }
+
+    fn description(&self) -> &str {
+        "Event IP is a known Tor exit node or open proxy"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        SourceCategory::ALL
+            .into_iter()
+            .map(|category| RuleWeight::new(category.code(), config.suspicious_sources.points(category)))
+            .collect()
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        for category in ctx.suspicious_sources.matches(ctx.client_ip) {
+            let points = ctx.config.suspicious_sources.points(category);
+            if points != 0 {
+                hits.push(RuleHit {
+                    code: category.code().into(),
+                    points,
+                    reason: category.reason().to_string(),
+                    children: Vec::new(),
+                });
+            }
+        }
+    }
+}
+
+struct FastInteractionRule;
+
+impl Rule for FastInteractionRule {
+    fn name(&self) -> &str {
+        "fast_interaction"
+    }
+
+    fn description(&self) -> &str {
+        "Events in a session arrive faster than a person could act"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        vec![RuleWeight::new("FAST_INTERACTION", config.fast_interaction.points)]
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        if ctx.history.len() < 2 {
+            return;
+        }
+
+        let previous_event = &ctx.history[ctx.history.len() - 2];
+        let time_diff = ctx.event.timestamp.timestamp_millis() - previous_event.timestamp.timestamp_millis();
+
+        if time_diff < ctx.config.fast_interaction.min_interval_ms {
+            hits.push(RuleHit {
+                code: "FAST_INTERACTION".into(),
+                points: ctx.config.fast_interaction.points,
+                reason: "Impossibly fast user interaction".to_string(),
+                children: Vec::new(),
+            });
+        }
+    }
+}
+
+struct HighFrequencyRule;
+
+impl Rule for HighFrequencyRule {
+    fn name(&self) -> &str {
+        "high_frequency"
+    }
+
+    fn description(&self) -> &str {
+        "A session sends more events than the frequency limit allows"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        vec![RuleWeight::new("HIGH_FREQUENCY", config.high_frequency.points)]
+    }
+
+    fn stateful(&self) -> bool {
+        true
+    }
+
+    /// One hit at most, worth `points` however far over its limit the session is, so a
+    /// long burst can't drown out the other rules.
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        let config = &ctx.config.high_frequency;
+        let current = usize::from(config.count_current_event);
+
+        // Within a window the counters have the counts; without one, the whole stored
+        // session counts.
+        let mut by_type: HashMap<&EventType, usize> = HashMap::new();
+        match config.window_secs {
+            0 => {
+                for event in &ctx.history[..ctx.history.len().saturating_sub(1)] {
+                    *by_type.entry(&event.event_type).or_default() += 1;
+                }
+            }
+            _ => by_type.extend(ctx.rates.session_by_type.iter().map(|(event_type, count)| (event_type, *count))),
+        }
+        *by_type.entry(&ctx.event.event_type).or_default() += current;
+        let untyped: usize = by_type
+            .iter()
+            .filter(|(event_type, _)| !config.max_events_by_type.contains_key(**event_type))
+            .map(|(_, count)| count)
+            .sum();
+        by_type.retain(|event_type, _| config.max_events_by_type.contains_key(*event_type));
+
+        let reason = if untyped > config.max_events {
+            Some("High frequency of events".to_string())
+        } else {
+            let mut over: Vec<&EventType> = by_type
+                .into_iter()
+                .filter(|(event_type, count)| *count > config.max_events_by_type[*event_type])
+                .map(|(event_type, _)| event_type)
+                .collect();
+            over.sort_unstable_by_key(|event_type| event_type.as_str());
+            over.first().map(|event_type| format!("High frequency of {} events", event_type.as_str()))
+        };
+        let reason = reason
+            .or_else(|| {
+                let max = config.max_events_per_ip?;
+                (ctx.rates.ip + current > max).then(|| "High frequency of events from the IP".to_string())
+            })
+            .or_else(|| {
+                let (count, max) = ctx.rates.user.zip(config.max_events_per_user)?;
+                (count + current > max).then(|| "High frequency of events from the user".to_string())
+            });
+        if let Some(reason) = reason {
+            hits.push(RuleHit {
+                code: "HIGH_FREQUENCY".into(),
+                points: config.points,
+                reason,
+                children: Vec::new(),
+            });
+        }
+    }
+}
+
+/// Number of metadata keys outside the schema's required and optional sets.
+pub fn unexpected_metadata_keys(metadata: &HashMap<String, String>, schema: &MetadataKeySchema) -> usize {
+    metadata
+        .keys()
+        .filter(|key| !schema.required.contains(*key) && !schema.optional.contains(*key))
+        .count()
+}
+
+/// Values that fail their configured type or pattern, described by key only so the
+/// (attacker-controlled) values never end up in reasons or error bodies.
+pub fn malformed_metadata_values(metadata: &HashMap<String, String>, schema: &MetadataKeySchema) -> Vec<String> {
+    let mut violations: Vec<String> = schema
+        .values
+        .iter()
+        .filter_map(|(key, rule)| {
+            let value = metadata.get(key)?.trim();
+            let type_ok = match rule.kind {
+                MetadataValueKind::String => true,
+                MetadataValueKind::Integer => value.parse::<i64>().is_ok(),
+                MetadataValueKind::Number => value.parse::<f64>().is_ok_and(f64::is_finite),
+                MetadataValueKind::Boolean => value.parse::<bool>().is_ok(),
+                MetadataValueKind::Timestamp => chrono::DateTime::parse_from_rfc3339(value).is_ok(),
+            };
+
+            if !type_ok {
+                Some(format!("{} is not a valid {}", key, rule.kind.name()))
+            } else if rule.pattern.as_ref().is_some_and(|pattern| !pattern.is_match(value)) {
+                Some(format!("{} does not match the expected pattern", key))
+            } else {
+                None
+            }
+        })
+        .collect();
+    violations.sort_unstable();
+    violations
+}
+
+/// Compares the event's metadata with the configured schema for its type, scoring
+/// missing required keys, unexpected extra keys, and malformed values separately.
+/// In `reject` mode malformed events never get here (the handler returns 422).
+struct MetadataKeysRule;
+
+impl Rule for MetadataKeysRule {
+    fn name(&self) -> &str {
+        "metadata_keys"
+    }
+
+    fn description(&self) -> &str {
+        "Event metadata is missing required keys, has unexpected keys, or has malformed values"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        let config = &config.metadata_keys;
+        vec![
+            RuleWeight::new("MISSING_METADATA_KEYS", config.missing_key_points),
+            RuleWeight::new("UNEXPECTED_METADATA_KEYS", config.unexpected_key_points),
+            RuleWeight::new("MALFORMED_METADATA", config.malformed_points),
+        ]
+    }
+
+    /// Nothing to check against until a schema is configured.
+    fn configured(&self, config: &ScoringConfig) -> bool {
+        !config.metadata_keys.schemas.is_empty()
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        let config = &ctx.config.metadata_keys;
+        let Some(schema) = config.schemas.get(&ctx.event.event_type) else {
+            return;
+        };
+
+        let empty = HashMap::new();
+        let metadata = ctx.event.metadata.as_ref().unwrap_or(&empty);
+
+        let mut missing: Vec<&str> = schema
+            .required
+            .iter()
+            .filter(|key| !metadata.contains_key(*key))
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            missing.sort_unstable();
+            hits.push(RuleHit {
+                code: "MISSING_METADATA_KEYS".into(),
+                points: config.missing_key_points,
+                reason: format!("Missing expected metadata keys: {}", missing.join(", ")),
+                children: Vec::new(),
+            });
+        }
+
+        // Extra keys are attacker-controlled, so only their count goes into the reason.
+        let unexpected = unexpected_metadata_keys(metadata, schema);
+        if unexpected > 0 {
+            hits.push(RuleHit {
+                code: "UNEXPECTED_METADATA_KEYS".into(),
+                points: config.unexpected_key_points,
+                reason: format!("Unexpected metadata keys ({})", unexpected),
+                children: Vec::new(),
+            });
+        }
+
+        if config.mode == MetadataSchemaMode::Score {
+            let malformed = malformed_metadata_values(metadata, schema);
+            if !malformed.is_empty() {
+                hits.push(RuleHit {
+                    code: "MALFORMED_METADATA".into(),
+                    points: config.malformed_points,
+                    reason: format!("Malformed metadata: {}", malformed.join("; ")),
+                    children: Vec::new(),
+                });
+            }
+        }
+    }
+}
+
+/// Scores the external reputation provider's confidence for the event's IP. Skipped
+/// whenever no score is available (provider disabled, breaker open, or lookup too slow).
+struct IpReputationRule;
+
+impl Rule for IpReputationRule {
+    fn name(&self) -> &str {
+        "ip_reputation"
+    }
+
+    fn description(&self) -> &str {
+        "The reputation provider reports the event IP as abusive, scaled by its confidence"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        vec![RuleWeight::new("IP_REPUTATION", config.ip_reputation.max_points)]
+    }
+
+    fn requires(&self) -> Option<Dependency> {
+        Some(Dependency::IpReputation)
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        let config = &ctx.config.ip_reputation;
+        let Some(confidence) = ctx.ip_reputation else {
+            return;
+        };
+
+        if confidence >= config.min_confidence {
+            hits.push(RuleHit {
+                code: "IP_REPUTATION".into(),
+                points: config.max_points * i32::from(confidence) / 100,
+                reason: format!("Poor IP reputation (confidence {}%)", confidence),
+                children: Vec::new(),
+            });
+        }
+    }
+}
+
+/// Scores IPs from networks on the bad-ASN list and, where the database says, hosting
+/// networks. Skipped when the IP has no ASN data.
+struct AsnReputationRule;
+
+impl Rule for AsnReputationRule {
+    fn name(&self) -> &str {
+        "asn_reputation"
+    }
+
+    fn description(&self) -> &str {
+        "The event IP belongs to a flagged network (ASN), a hosting provider, or a datacenter"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        let config = &config.asn_reputation;
+        vec![
+            RuleWeight::new("BAD_ASN", config.points),
+            RuleWeight::new("HOSTING_NETWORK", config.hosting_points),
+            RuleWeight::new("DATACENTER_ASN", config.datacenter_points),
+        ]
+    }
+
+    fn configured(&self, config: &ScoringConfig) -> bool {
+        let config = &config.asn_reputation;
+        !config.bad_asns.is_empty()
+            || config.hosting_points != 0
+            || (config.datacenter_points != 0 && !config.datacenter_asns.is_empty())
+    }
+
+    fn requires(&self) -> Option<Dependency> {
+        Some(Dependency::AsnData)
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        let config = &ctx.config.asn_reputation;
+        let Some(geo) = &ctx.geo else {
+            return;
+        };
+        let network = match (geo.asn, &geo.asn_organization) {
+            (Some(asn), Some(organization)) => format!("AS{} ({})", asn, organization),
+            (Some(asn), None) => format!("AS{}", asn),
+            (None, _) => "unknown ASN".to_string(),
+        };
+
+        if geo.asn.is_some_and(|asn| config.bad_asns.contains(&asn)) {
+            hits.push(RuleHit {
+                code: "BAD_ASN".into(),
+                points: config.points,
+                reason: format!("IP from flagged network {}", network),
+                children: Vec::new(),
+            });
+        }
+        if geo.hosting == Some(true) && config.hosting_points != 0 {
+            hits.push(RuleHit {
+                code: "HOSTING_NETWORK".into(),
+                points: config.hosting_points,
+                reason: format!("IP from hosting network {}", network),
+                children: Vec::new(),
+            });
+        }
+        if geo.asn.is_some_and(|asn| config.datacenter_asns.contains(&asn)) && config.datacenter_points != 0 {
+            hits.push(RuleHit {
+                code: "DATACENTER_ASN".into(),
+                points: config.datacenter_points,
+                reason: format!("IP from datacenter network {}", network),
+                children: Vec::new(),
+            });
+        }
+    }
+}
+
+/// Scores payloads whose `ipAddress` isn't the IP the request came from, as derived
+/// server-side. Neither address goes in the reason, which is stored and returned.
+struct IpMismatchRule;
+
+impl Rule for IpMismatchRule {
+    fn name(&self) -> &str {
+        "ip_mismatch"
+    }
+
+    fn description(&self) -> &str {
+        "The payload IP differs from the client IP derived from the connection"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        vec![RuleWeight::new("IP_MISMATCH", config.ip_mismatch.points)]
+    }
+
+    fn requires(&self) -> Option<Dependency> {
+        Some(Dependency::ObservedIp)
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        let Some(observed) = ctx.observed_ip else {
+            return;
+        };
+        // Both sides are canonical, so `::ffff:192.0.2.1` already reads `192.0.2.1`.
+        if ctx.client_ip.parse::<IpAddr>().ok() != Some(observed) {
+            hits.push(RuleHit {
+                code: "IP_MISMATCH".into(),
+                points: ctx.config.ip_mismatch.points,
+                reason: "Payload IP differs from the connection's client IP".to_string(),
+                children: Vec::new(),
+            });
+        }
+    }
+}
+
+/// Flags automation signatures in the user agent and user agents that change within a
+/// session. Reasons name the matched signature rather than echoing the (possibly huge) UA.
+struct UserAgentRule;
+
+impl Rule for UserAgentRule {
+    fn name(&self) -> &str {
+        "user_agent"
+    }
+
+    fn description(&self) -> &str {
+        "The user agent matches an automation signature or changes within a session"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        vec![
+            RuleWeight::new("AUTOMATION_USER_AGENT", config.user_agent.signature_points),
+            RuleWeight::new("USER_AGENT_CHANGED", config.user_agent.change_points),
+        ]
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        let config = &ctx.config.user_agent;
+        let Some(user_agent) = ctx.event.user_agent() else {
+            return;
+        };
+
+        if user_agent.trim().is_empty() {
+            hits.push(RuleHit {
+                code: "AUTOMATION_USER_AGENT".into(),
+                points: config.signature_points,
+                reason: "Empty user agent".to_string(),
+                children: Vec::new(),
+            });
+        } else if let Some(signature) =
+            ctx.session.automation_signature(user_agent, &config.signatures, &ctx.config.version)
+        {
+            hits.push(RuleHit {
+                code: "AUTOMATION_USER_AGENT".into(),
+                points: config.signature_points,
+                reason: format!("Automation user agent signature: {}", signature),
+                children: Vec::new(),
+            });
+        }
+
+        let previous = ctx.history[..ctx.history.len().saturating_sub(1)]
+            .iter()
+            .rev()
+            .find_map(|event| event.user_agent());
+        if previous.is_some_and(|previous| previous != user_agent) {
+            hits.push(RuleHit {
+                code: "USER_AGENT_CHANGED".into(),
+                points: config.change_points,
+                reason: "User agent changed mid-session".to_string(),
+                children: Vec::new(),
+            });
+        }
+    }
+}
+
+/// Counts the distinct user ids in the session's history, which ends with the event
+/// itself. Only events carrying a user id are scored, so a session's anonymous traffic
+/// isn't flagged for users seen earlier. Reasons give the count, never the ids.
+struct SharedSessionRule;
+
+impl Rule for SharedSessionRule {
+    fn name(&self) -> &str {
+        "shared_session"
+    }
+
+    fn description(&self) -> &str {
+        "A session id is used by more distinct users than allowed"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        vec![RuleWeight::new("SHARED_SESSION", config.shared_session.points)]
+    }
+
+    fn stateful(&self) -> bool {
+        true
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        let config = &ctx.config.shared_session;
+        if ctx.event.user_id.is_none() {
+            return;
+        }
+
+        // Events mostly come in runs from one user, so a run is hashed only once.
+        let mut users: HashSet<&str> = HashSet::new();
+        let mut previous = None;
+        for user_id in ctx.history.iter().filter_map(|event| event.user_id.as_deref()) {
+            if previous != Some(user_id) {
+                users.insert(user_id);
+                previous = Some(user_id);
+            }
+        }
+        if users.len() > config.max_users {
+            hits.push(RuleHit {
+                code: "SHARED_SESSION".into(),
+                points: config.points,
+                reason: format!("Session used by {} distinct users", users.len()),
+                children: Vec::new(),
+            });
+        }
+    }
+}
+
+/// Session ids listed in a `CONCURRENT_SESSIONS` or `SHARED_FINGERPRINT` reason; the
+/// rest are counted.
+const MAX_LISTED_SESSIONS: usize = 10;
+
+/// Scores a user active in more sessions at once than allowed. The reason names the
+/// other sessions, so an analyst can pivot to them.
+struct ConcurrentSessionsRule;
+
+impl Rule for ConcurrentSessionsRule {
+    fn name(&self) -> &str {
+        "concurrent_sessions"
+    }
+
+    fn description(&self) -> &str {
+        "A user is active in more sessions at once than allowed"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        vec![RuleWeight::new("CONCURRENT_SESSIONS", config.concurrent_sessions.points)]
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        let config = &ctx.config.concurrent_sessions;
+        let active = ctx.linked_sessions.len() + 1;
+        if ctx.event.user_id.is_none() || active <= config.max_sessions || config.points == 0 {
+            return;
+        }
+
+        hits.push(RuleHit {
+            code: "CONCURRENT_SESSIONS".into(),
+            points: config.points,
+            reason: format!(
+                "User active in {} sessions within {} minutes; linked sessions: {}",
+                active,
+                config.window_secs / 60,
+                list_sessions(ctx.linked_sessions)
+            ),
+            children: Vec::new(),
+        });
+    }
+}
+
+/// The first `MAX_LISTED_SESSIONS` ids, then how many more there are.
+fn list_sessions(session_ids: &[String]) -> String {
+    let mut listed = session_ids.iter().take(MAX_LISTED_SESSIONS).cloned().collect::<Vec<_>>().join(", ");
+    if session_ids.len() > MAX_LISTED_SESSIONS {
+        listed.push_str(&format!(" and {} more", session_ids.len() - MAX_LISTED_SESSIONS));
+    }
+    listed
+}
+
+/// Scores a device fingerprint seen in more sessions at once than allowed. Events
+/// without a fingerprint aren't scored; the fingerprint itself stays out of the reason.
+struct DeviceFingerprintRule;
+
+impl Rule for DeviceFingerprintRule {
+    fn name(&self) -> &str {
+        "device_fingerprint"
+    }
+
+    fn description(&self) -> &str {
+        "One device fingerprint is seen in more sessions at once than allowed"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        vec![RuleWeight::new("SHARED_FINGERPRINT", config.device_fingerprint.points)]
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        let config = &ctx.config.device_fingerprint;
+        let active = ctx.fingerprint_sessions.len() + 1;
+        if ctx.event.fingerprint().is_none() || active <= config.max_sessions || config.points == 0 {
+            return;
+        }
+
+        hits.push(RuleHit {
+            code: "SHARED_FINGERPRINT".into(),
+            points: config.points,
+            reason: format!(
+                "Device fingerprint seen in {} sessions within {} minutes; other sessions: {}",
+                active,
+                config.window_secs / 60,
+                list_sessions(ctx.fingerprint_sessions)
+            ),
+            children: Vec::new(),
+        });
+    }
+}
+
+/// Escalates the score for users flagged before: `points * growth_factor^(n - 1)` where
+/// `n` is the prior flag count capped at `max_flags`.
+struct RepeatOffenderRule;
+
+impl Rule for RepeatOffenderRule {
+    fn name(&self) -> &str {
+        "repeat_offender"
+    }
+
+    fn description(&self) -> &str {
+        "The user was flagged before, growing with each prior flag"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        let config = &config.repeat_offender;
+        let most = f64::from(config.points) * config.growth_factor.powi(config.max_flags.max(1) as i32 - 1);
+        vec![RuleWeight::new("REPEAT_OFFENDER", most.round() as i32)]
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        let config = &ctx.config.repeat_offender;
+        let flags = ctx.prior_flags.min(config.max_flags);
+        if flags == 0 {
+            return;
+        }
+
+        let points = f64::from(config.points) * config.growth_factor.powi(flags as i32 - 1);
+        hits.push(RuleHit {
+            code: "REPEAT_OFFENDER".into(),
+            points: points.round() as i32,
+            reason: format!("User previously flagged {} time(s)", ctx.prior_flags),
+            children: Vec::new(),
+        });
+    }
+}
+
+/// Carries reputation over from earlier sessions: `weight` points per unit of the
+/// user's and the IP's combined reputation, up to `max_points`.
+struct EntityReputationRule;
+
+impl Rule for EntityReputationRule {
+    fn name(&self) -> &str {
+        "entity_reputation"
+    }
+
+    fn description(&self) -> &str {
+        "The user or IP was flagged in earlier sessions, fading over time"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        vec![RuleWeight::new("ENTITY_REPUTATION", config.entity_reputation.max_points)]
+    }
+
+    fn configured(&self, config: &ScoringConfig) -> bool {
+        config.entity_reputation.half_life().is_some()
+    }
+
+    fn requires(&self) -> Option<Dependency> {
+        Some(Dependency::EntityReputation)
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        let config = &ctx.config.entity_reputation;
+        let reputation = ctx.entity_reputation;
+        let points = (config.weight * (reputation.user + reputation.ip)).round() as i32;
+        let points = points.min(config.max_points);
+        if points <= 0 {
+            return;
+        }
+
+        let parts: Vec<String> = [("user", reputation.user), ("IP", reputation.ip)]
+            .into_iter()
+            .filter(|(_, value)| *value > 0.0)
+            .map(|(label, value)| format!("{} {:.1}", label, value))
+            .collect();
+        hits.push(RuleHit {
+            code: "ENTITY_REPUTATION".into(),
+            points,
+            reason: format!("Reputation from earlier flags: {}", parts.join(", ")),
+            children: Vec::new(),
+        });
+    }
+}
+
+/// Humans interact irregularly; scripts fire at near-constant intervals. Scores sessions
+/// whose recent inter-event intervals have a tiny coefficient of variation (std dev /
+/// mean) or are all sub-`burst_interval_ms`.
+struct CadenceRule;
+
+impl Rule for CadenceRule {
+    fn name(&self) -> &str {
+        "cadence"
+    }
+
+    fn description(&self) -> &str {
+        "A session's events arrive at near-constant intervals"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        vec![RuleWeight::new("REGULAR_CADENCE", config.cadence.points)]
+    }
+
+    fn stateful(&self) -> bool {
+        true
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        let config = &ctx.config.cadence;
+        if ctx.history.len() < config.min_events.max(3) {
+            return;
+        }
+
+        let recent = &ctx.history[ctx.history.len().saturating_sub(config.window)..];
+        let intervals = intervals_ms(recent.iter().map(|e| e.timestamp.timestamp_millis()).collect());
+
+        if intervals.iter().all(|interval| *interval < config.burst_interval_ms) {
+            hits.push(RuleHit {
+                code: "REGULAR_CADENCE".into(),
+                points: config.points,
+                reason: format!("All {} recent intervals under {}ms", intervals.len(), config.burst_interval_ms),
+                children: Vec::new(),
+            });
+            return;
+        }
+
+        let (mean, std_dev) = mean_and_std_dev(&intervals);
+        let coefficient_of_variation = std_dev / mean;
+        if coefficient_of_variation < config.max_coefficient_of_variation {
+            hits.push(RuleHit {
+                code: "REGULAR_CADENCE".into(),
+                points: config.points,
+                reason: format!(
+                    "Machine-like event cadence (CV {:.3} over {} intervals, mean {:.0}ms)",
+                    coefficient_of_variation,
+                    intervals.len(),
+                    mean
+                ),
+                children: Vec::new(),
+            });
+        }
+    }
+}
+
+/// Like `CadenceRule`, but only for clicks: people click with jitter of tens to hundreds
+/// of milliseconds, while click bots fire at near-identical intervals.
+struct ClickTimingRule;
+
+impl Rule for ClickTimingRule {
+    fn name(&self) -> &str {
+        "click_timing"
+    }
+
+    fn description(&self) -> &str {
+        "A session's clicks are spaced too evenly to be human"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        vec![RuleWeight::new("REGULAR_CLICK_TIMING", config.click_timing.points)]
+    }
+
+    fn stateful(&self) -> bool {
+        true
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        let config = &ctx.config.click_timing;
+        if ctx.event.event_type != EventType::Click {
+            return;
+        }
+
+        let clicks: Vec<i64> = ctx
+            .history
+            .iter()
+            .rev()
+            .filter(|event| event.event_type == EventType::Click)
+            .take(config.window)
+            .map(|event| event.timestamp.timestamp_millis())
+            .collect();
+        if clicks.len() < config.min_clicks.max(3) {
+            return;
+        }
+
+        let intervals = intervals_ms(clicks);
+        let (mean, std_dev) = mean_and_std_dev(&intervals);
+        if std_dev < config.max_std_dev_ms {
+            hits.push(RuleHit {
+                code: "REGULAR_CLICK_TIMING".into(),
+                points: config.points,
+                reason: format!(
+                    "Bot-like click timing (std dev {:.1}ms over {} intervals, mean {:.0}ms)",
+                    std_dev,
+                    intervals.len(),
+                    mean
+                ),
+                children: Vec::new(),
+            });
+        }
+    }
+}
+
+/// Scripts replaying a flow tend to flip between event types (click, submit, click,
+/// submit) far faster than anyone moves between a page's controls.
+struct TypeTogglingRule;
+
+impl Rule for TypeTogglingRule {
+    fn name(&self) -> &str {
+        "type_toggling"
+    }
+
+    fn description(&self) -> &str {
+        "A session switches between event types faster than a person could"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        vec![RuleWeight::new("RAPID_TYPE_TOGGLING", config.type_toggling.points)]
+    }
+
+    fn stateful(&self) -> bool {
+        true
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        let config = &ctx.config.type_toggling;
+        let switches = config.min_switches.max(2);
+        if ctx.history.len() <= switches {
+            return;
+        }
+
+        let recent = &ctx.history[ctx.history.len() - switches - 1..];
+        let toggling = recent.windows(2).all(|pair| {
+            pair[0].event_type != pair[1].event_type
+                && (pair[1].timestamp - pair[0].timestamp).num_milliseconds() < config.max_interval_ms
+        });
+        if toggling {
+            let span_ms = (recent[switches].timestamp - recent[0].timestamp).num_milliseconds();
+            let sequence: Vec<_> = recent.iter().map(|event| event.event_type.as_str()).collect();
+            hits.push(RuleHit {
+                code: "RAPID_TYPE_TOGGLING".into(),
+                points: config.points,
+                reason: format!(
+                    "Event type changed {} times in {}ms ({})",
+                    switches,
+                    span_ms,
+                    sequence.join(" > ")
+                ),
+                children: Vec::new(),
+            });
+        }
+    }
+}
+
+/// Payment checks: cumulative amount per user within the rolling window, repeated
+/// identical amounts within a session (card testing), and a currency that doesn't match
+/// the IP's country. An unparseable amount is scored as malformed metadata instead.
+struct PaymentRule;
+
+impl Rule for PaymentRule {
+    fn name(&self) -> &str {
+        "payments"
+    }
+
+    fn description(&self) -> &str {
+        "Payment velocity per user, repeated identical amounts, and currency that doesn't match the IP country"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        let config = &config.payments;
+        vec![
+            RuleWeight::new("PAYMENT_VELOCITY", config.velocity_points),
+            RuleWeight::new("CARD_TESTING", config.repeat_points),
+            RuleWeight::new("CURRENCY_MISMATCH", config.currency_mismatch_points),
+        ]
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        let config = &ctx.config.payments;
+        let Some(payment) = payments::parse_payment(ctx.event) else {
+            return;
+        };
+
+        if let Some(currency) = payment.currency {
+            let country = ctx.geo.as_ref().and_then(|geo| geo.country.as_deref());
+            let expected = country.and_then(|country| {
+                config
+                    .expected_currencies
+                    .get(country)
+                    .map(String::as_str)
+                    .or_else(|| payments::default_currency(country))
+            });
+            if let (Some(country), Some(expected)) = (country, expected) {
+                if !currency.eq_ignore_ascii_case(expected) {
+                    hits.push(RuleHit {
+                        code: "CURRENCY_MISMATCH".into(),
+                        points: config.currency_mismatch_points,
+                        reason: format!("Payment currency doesn't match IP country {} (expected {})", country, expected),
+                        children: Vec::new(),
+                    });
+                }
+            }
+        }
+
+        let amount = match payment.amount {
+            Ok(amount) => amount,
+            Err(problem) => {
+                // Skip when the metadata schema already checks the amount, to avoid scoring it twice.
+                let present = ctx.event.metadata.as_ref().is_some_and(|m| m.contains_key("amount"));
+                let schema = ctx.config.metadata_keys.schemas.get(&ctx.event.event_type);
+                let covered = schema.is_some_and(|schema| {
+                    if present {
+                        schema.values.contains_key("amount")
+                    } else {
+                        schema.required.contains("amount")
+                    }
+                });
+                if covered {
+                    return;
+                }
+                hits.push(RuleHit {
+                    code: "MALFORMED_METADATA".into(),
+                    points: ctx.config.metadata_keys.malformed_points,
+                    reason: format!("Malformed metadata: {}", problem),
+                    children: Vec::new(),
+                });
+                return;
+            }
+        };
+
+        if ctx.payment_window_total + amount > config.max_window_amount {
+            hits.push(RuleHit {
+                code: "PAYMENT_VELOCITY".into(),
+                points: config.velocity_points,
+                reason: format!(
+                    "Payments total {:.2} within {}s, over the {:.2} limit",
+                    ctx.payment_window_total + amount,
+                    config.window_secs,
+                    config.max_window_amount
+                ),
+                children: Vec::new(),
+            });
+        }
+
+        // Amounts are compared in cents so float formatting ("10" vs "10.00") doesn't matter.
+        let cents = (amount * 100.0).round() as i64;
+        let since = ctx.event.timestamp - chrono::Duration::seconds(config.repeat_window_secs);
+        let repeats = ctx
+            .history
+            .iter()
+            .filter(|event| event.timestamp >= since)
+            .filter_map(|event| payments::parse_payment(event))
+            .filter(|other| other.currency == payment.currency)
+            .filter(|other| other.amount.is_ok_and(|other| (other * 100.0).round() as i64 == cents))
+            .count();
+        if repeats >= config.repeat_count {
+            hits.push(RuleHit {
+                code: "CARD_TESTING".into(),
+                points: config.repeat_points,
+                reason: format!(
+                    "{} payments of the same amount within {}s",
+                    repeats, config.repeat_window_secs
+                ),
+                children: Vec::new(),
+            });
+        }
+    }
+}
+
+/// Scores form submissions whose `email` metadata uses a throwaway domain. Reasons name
+/// only the matched domain; the address itself never appears in reasons or logs.
+struct DisposableEmailRule;
+
+impl Rule for DisposableEmailRule {
+    fn name(&self) -> &str {
+        "disposable_email"
+    }
+
+    fn description(&self) -> &str {
+        "A form submission's email is malformed or uses a disposable domain"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        vec![
+            RuleWeight::new("DISPOSABLE_EMAIL", config.disposable_email.points),
+            RuleWeight::new("INVALID_EMAIL_FORMAT", config.disposable_email.invalid_format_points),
+        ]
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        let config = &ctx.config.disposable_email;
+        if ctx.event.event_type != EventType::FormSubmission {
+            return;
+        }
+        let Some(raw) = ctx.event.metadata.as_ref().and_then(|m| m.get("email")) else {
+            return;
+        };
+
+        let Some(email) = EmailAddress::parse(raw) else {
+            hits.push(RuleHit {
+                code: "INVALID_EMAIL_FORMAT".into(),
+                points: config.invalid_format_points,
+                reason: "Invalid email format".to_string(),
+                children: Vec::new(),
+            });
+            return;
+        };
+
+        if let Some(domain) = ctx.disposable_domains.matches(&email.domain) {
+            hits.push(RuleHit {
+                code: "DISPOSABLE_EMAIL".into(),
+                points: config.points,
+                reason: format!("Disposable email domain: {}", domain),
+                children: Vec::new(),
+            });
+        }
+    }
+}
+
+struct FieldTimingRule;
+
+impl Rule for FieldTimingRule {
+    fn name(&self) -> &str {
+        "field_timing"
+    }
+
+    fn description(&self) -> &str {
+        "A form's fields were filled implausibly fast or out of visual order"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        vec![
+            RuleWeight::new("FAST_FORM_FILL", config.field_timing.fast_fill_points),
+            RuleWeight::new("FIELD_ORDER_ANOMALY", config.field_timing.out_of_order_points),
+        ]
+    }
+
+    /// Missing, malformed, or negative timings skip the rule rather than score.
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        let config = &ctx.config.field_timing;
+        if ctx.event.event_type != EventType::FormSubmission {
+            return;
+        }
+        let Some(raw) = ctx.event.metadata.as_ref().and_then(|m| m.get("fieldTimings")) else {
+            return;
+        };
+        let Ok(timings) = serde_json::from_str::<HashMap<String, f64>>(raw) else {
+            return;
+        };
+        if timings.len() < config.min_fields.max(2) || timings.values().any(|ms| !ms.is_finite() || *ms < 0.0) {
+            return;
+        }
+
+        let first = timings.values().copied().fold(f64::INFINITY, f64::min);
+        let last = timings.values().copied().fold(f64::NEG_INFINITY, f64::max);
+        if last - first < config.min_fill_ms {
+            hits.push(RuleHit {
+                code: "FAST_FORM_FILL".into(),
+                points: config.fast_fill_points,
+                reason: format!("{} form fields filled within {:.0}ms", timings.len(), last - first),
+                children: Vec::new(),
+            });
+        }
+
+        let in_visual_order: Vec<f64> = config.field_order.iter().filter_map(|field| timings.get(field).copied()).collect();
+        if in_visual_order.len() >= 2 && in_visual_order.windows(2).any(|pair| pair[1] < pair[0]) {
+            hits.push(RuleHit {
+                code: "FIELD_ORDER_ANOMALY".into(),
+                points: config.out_of_order_points,
+                reason: "Form fields filled out of visual order".to_string(),
+                children: Vec::new(),
+            });
+        }
+    }
+}
+
+/// Scores form submissions by how long after the session's first stored event they
+/// came. A submission that opens its session has nothing to measure and isn't scored.
+struct SessionDurationRule;
+
+impl Rule for SessionDurationRule {
+    fn name(&self) -> &str {
+        "session_duration"
+    }
+
+    fn description(&self) -> &str {
+        "A form was submitted implausibly soon after the session began, or after hours"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        vec![
+            RuleWeight::new("SHORT_SESSION", config.session_duration.short_points),
+            RuleWeight::new("LONG_SESSION", config.session_duration.long_points),
+        ]
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        let config = &ctx.config.session_duration;
+        if ctx.event.event_type != EventType::FormSubmission || ctx.history.len() < 2 {
+            return;
+        }
+        let Some(first) = ctx.history.iter().map(|event| event.timestamp).min() else {
+            return;
+        };
+        let duration = ctx.event.timestamp - first;
+
+        if duration < chrono::Duration::milliseconds(config.min_duration_ms as i64) && config.short_points != 0 {
+            hits.push(RuleHit {
+                code: "SHORT_SESSION".into(),
+                points: config.short_points,
+                reason: format!("Form submitted {}ms into the session", duration.num_milliseconds().max(0)),
+                children: Vec::new(),
+            });
+        } else if duration > chrono::Duration::seconds(config.max_duration_secs as i64) && config.long_points != 0 {
+            hits.push(RuleHit {
+                code: "LONG_SESSION".into(),
+                points: config.long_points,
+                reason: format!("Form submitted {} minutes into the session", duration.num_minutes()),
+                children: Vec::new(),
+            });
+        }
+    }
+}
+
+/// Scores activity that doesn't fit the user's history, a common sign of a taken-over
+/// account: an hour of the day they are almost never active in, or a return after a long
+/// absence. Users with fewer than `min_events` profiled events aren't scored.
+struct ActivityProfileRule;
+
+impl Rule for ActivityProfileRule {
+    fn name(&self) -> &str {
+        "activity_profile"
+    }
+
+    fn description(&self) -> &str {
+        "A user is active at an hour they rarely are, or after a long dormancy"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        vec![
+            RuleWeight::new("UNUSUAL_HOUR", config.activity_profile.unusual_hour_points),
+            RuleWeight::new("DORMANT_ACCOUNT", config.activity_profile.dormant_points),
+        ]
+    }
+
+    fn requires(&self) -> Option<Dependency> {
+        Some(Dependency::ActivityStore)
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        let config = &ctx.config.activity_profile;
+        let Some(profile) = ctx.activity.filter(|profile| profile.events() >= config.min_events) else {
+            return;
+        };
+
+        let share = profile.hour_share(ctx.event.timestamp);
+        if share <= config.max_hour_share && config.unusual_hour_points != 0 {
+            hits.push(RuleHit {
+                code: "UNUSUAL_HOUR".into(),
+                points: config.unusual_hour_points,
+                reason: format!(
+                    "User active at {:02}:00 UTC, an hour with {:.1}% of their {} past events",
+                    ctx.event.timestamp.hour(),
+                    share * 100.0,
+                    profile.events()
+                ),
+                children: Vec::new(),
+            });
+        }
+        if let Some(last_seen) = profile.last_seen {
+            let away = ctx.event.timestamp - last_seen;
+            if away >= config.dormancy() && config.dormant_points != 0 {
+                hits.push(RuleHit {
+                    code: "DORMANT_ACCOUNT".into(),
+                    points: config.dormant_points,
+                    reason: format!("First activity in {} days", away.num_days()),
+                    children: Vec::new(),
+                });
+            }
+        }
+    }
+}
+
+/// A form submitted with a `referrer` on a different host from its declared `origin`
+/// suggests the submission was scripted from another site.
+struct ReferrerOriginRule;
+
+impl Rule for ReferrerOriginRule {
+    fn name(&self) -> &str {
+        "referrer_origin"
+    }
+
+    fn description(&self) -> &str {
+        "A form submission's referrer host differs from its declared origin"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        vec![RuleWeight::new("REFERRER_ORIGIN_MISMATCH", config.referrer_origin.points)]
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        if ctx.event.event_type != EventType::FormSubmission {
+            return;
+        }
+        let Some(metadata) = &ctx.event.metadata else {
+            return;
+        };
+        let (Some(referrer), Some(origin)) = (metadata.get("referrer"), metadata.get("origin")) else {
+            return;
+        };
+        let (Some(referrer), Some(origin)) = (url_host(referrer), url_host(origin)) else {
+            return;
+        };
+
+        if referrer != origin {
+            hits.push(RuleHit {
+                code: "REFERRER_ORIGIN_MISMATCH".into(),
+                points: ctx.config.referrer_origin.points,
+                reason: "Referrer host doesn't match the declared origin".to_string(),
+                children: Vec::new(),
+            });
+        }
+    }
+}
+
+struct LocaleMismatchRule;
+
+impl Rule for LocaleMismatchRule {
+    fn name(&self) -> &str {
+        "locale_mismatch"
+    }
+
+    fn description(&self) -> &str {
+        "The country of the event's declared locale isn't the IP's country"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        vec![RuleWeight::new("LOCALE_MISMATCH", config.locale_mismatch.points)]
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        let config = &ctx.config.locale_mismatch;
+        let Some(locale) = ctx.event.metadata.as_ref().and_then(|metadata| locale_country(metadata.get("locale")?)) else {
+            return;
+        };
+        let Some(country) = ctx.geo.as_ref().and_then(|geo| geo.country.as_deref()) else {
+            return;
+        };
+
+        if !locale.eq_ignore_ascii_case(country) && !config.allows(&locale, country) {
+            hits.push(RuleHit {
+                code: "LOCALE_MISMATCH".into(),
+                points: config.points,
+                reason: format!("Locale country {} doesn't match IP country {}", locale, country),
+                children: Vec::new(),
+            });
+        }
+    }
+}
+
+/// Blends the ML model into the additive score. The reason carries the probability
+/// itself so analysts can compare it across events.
+struct ModelScoreRule;
+
+impl Rule for ModelScoreRule {
+    fn name(&self) -> &str {
+        "model"
+    }
+
+    fn description(&self) -> &str {
+        "The ML model's fraud probability, scaled by its weight"
+    }
+
+    fn weights(&self, config: &ScoringConfig) -> Vec<RuleWeight> {
+        vec![RuleWeight::new("MODEL_SCORE", config.model.weight)]
+    }
+
+    fn requires(&self) -> Option<Dependency> {
+        Some(Dependency::Model)
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        if let Some(probability) = ctx.model_probability {
+            hits.push(RuleHit {
+                code: "MODEL_SCORE".into(),
+                points: (f64::from(probability) * f64::from(ctx.config.model.weight)).round() as i32,
+                reason: format!("MODEL_SCORE: {:.2}", probability),
+                children: Vec::new(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blacklist::Blacklist;
+    use chrono::{DateTime, Duration, Utc};
+    use serde_json::{json, Value};
+
+    fn start() -> DateTime<Utc> {
+        "2024-05-01T12:00:00Z".parse().unwrap()
+    }
+
+    fn config(config: Value) -> ScoringConfig {
+        let config: ScoringConfig = serde_json::from_value(config).unwrap();
+        config.validate().unwrap();
+        config
+    }
+
+    /// An event `offset_ms` after `start` from a clean IP, with `metadata` when any.
+    fn event(session_id: &str, event_type: EventType, offset_ms: i64, metadata: &[(&str, &str)]) -> Arc<UserEvent> {
+        Arc::new(UserEvent {
+            session_id: session_id.to_string(),
+            user_id: None,
+            event_type,
+            timestamp: start() + Duration::milliseconds(offset_ms),
+            ip_address: "198.51.100.23".to_string(),
+            user_agent: None,
+            metadata: (!metadata.is_empty())
+                .then(|| metadata.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()),
+            asn: None,
+        })
+    }
+
+    /// What a `RuleContext` borrows, with nothing blacklisted or listed and no
+    /// dependencies configured.
+    struct Fixture {
+        config: ScoringConfig,
+        blacklist: Blacklist,
+        sources: SuspiciousSources,
+        domains: DisposableDomains,
+        rates: Rates,
+    }
+
+    impl Fixture {
+        fn new(config: ScoringConfig) -> Self {
+            Self {
+                config,
+                blacklist: Blacklist::new(Vec::new(), 0.01),
+                sources: SuspiciousSources::load(&[], 0.01).unwrap(),
+                domains: DisposableDomains::bundled(),
+                rates: Rates::default(),
+            }
+        }
+
+        /// `history`'s last event in context, after `adjust` fills in what the test
+        /// is about.
+        fn with<T>(&self, history: &[Arc<UserEvent>], adjust: impl FnOnce(&mut RuleContext<'_>), f: impl FnOnce(&RuleContext<'_>) -> T) -> T {
+            let event = history.last().expect("history ends with the scored event");
+            let mut ctx = RuleContext {
+                event,
+                history,
+                client_ip: &event.ip_address,
+                observed_ip: None,
+                session: SessionContext::new(None, &event.session_id, &self.blacklist, None),
+                suspicious_sources: &self.sources,
+                disposable_domains: &self.domains,
+                config: &self.config,
+                ip_reputation: None,
+                prior_flags: 0,
+                linked_sessions: &[],
+                fingerprint_sessions: &[],
+                activity: None,
+                geo: None,
+                payment_window_total: 0.0,
+                rates: &self.rates,
+                model_probability: None,
+                session_reputation: 0.0,
+                entity_reputation: EntityScores::default(),
+                unavailable: &[],
+                shadow_rules: &[],
+            };
+            adjust(&mut ctx);
+            f(&ctx)
+        }
+
+        /// What `rule` alone adds for `history`'s last event.
+        fn hits(&self, rule: &dyn Rule, history: &[Arc<UserEvent>]) -> Vec<RuleHit> {
+            self.hits_with(rule, history, |_| {})
+        }
+
+        fn hits_with(&self, rule: &dyn Rule, history: &[Arc<UserEvent>], adjust: impl FnOnce(&mut RuleContext<'_>)) -> Vec<RuleHit> {
+            self.with(history, adjust, |ctx| {
+                let mut hits = Vec::new();
+                rule.evaluate(ctx, &mut hits);
+                hits
+            })
+        }
+    }
+
+    fn codes(hits: &[RuleHit]) -> Vec<&str> {
+        hits.iter().map(|hit| hit.code.as_ref()).collect()
+    }
+
+    // --- METADATA KEYS ---
+    fn form_schema() -> Fixture {
+        Fixture::new(config(json!({
+            "metadata_keys": {
+                "schemas": {
+                    "FormSubmission": { "required": ["email", "formId"], "optional": ["referrer"] }
+                }
+            }
+        })))
+    }
+
+    #[test]
+    fn metadata_matching_the_schema_adds_nothing() {
+        let fixture = form_schema();
+        let history = [event("s1", EventType::FormSubmission, 0, &[("email", "a@example.com"), ("formId", "signup"), ("referrer", "x")])];
+        assert!(fixture.hits(&MetadataKeysRule, &history).is_empty());
+    }
+
+    #[test]
+    fn a_missing_required_key_is_scored() {
+        let fixture = form_schema();
+        let history = [event("s1", EventType::FormSubmission, 0, &[("formId", "signup")])];
+        let hits = fixture.hits(&MetadataKeysRule, &history);
+        assert_eq!(codes(&hits), ["MISSING_METADATA_KEYS"]);
+        assert_eq!(hits[0].points, 30);
+        assert_eq!(hits[0].reason, "Missing expected metadata keys: email");
+    }
+
+    #[test]
+    fn unexpected_keys_are_counted_not_named() {
+        let fixture = form_schema();
+        let history = [event("s1", EventType::FormSubmission, 0, &[("email", "a@example.com"), ("formId", "signup"), ("<script>", "x")])];
+        let hits = fixture.hits(&MetadataKeysRule, &history);
+        assert_eq!(codes(&hits), ["UNEXPECTED_METADATA_KEYS"]);
+        assert_eq!(hits[0].reason, "Unexpected metadata keys (1)");
+    }
+
+    #[test]
+    fn event_types_without_a_schema_are_not_checked() {
+        let fixture = form_schema();
+        let history = [event("s1", EventType::Click, 0, &[("anything", "x")])];
+        assert!(fixture.hits(&MetadataKeysRule, &history).is_empty());
+    }
+
+    // --- REPEAT OFFENDERS ---
+    fn repeat_offender_points(prior_flags: u32) -> Option<i32> {
+        let fixture = Fixture::new(ScoringConfig::default());
+        let history = [event("s1", EventType::Click, 0, &[])];
+        let hits = fixture.hits_with(&RepeatOffenderRule, &history, |ctx| ctx.prior_flags = prior_flags);
+        hits.first().map(|hit| hit.points)
+    }
+
+    #[test]
+    fn the_repeat_offender_bonus_doubles_with_each_prior_flag_up_to_the_cap() {
+        assert_eq!(repeat_offender_points(0), None);
+        assert_eq!(repeat_offender_points(1), Some(10));
+        assert_eq!(repeat_offender_points(2), Some(20));
+        assert_eq!(repeat_offender_points(4), Some(80));
+        assert_eq!(repeat_offender_points(9), Some(80));
+    }
+
+    // --- PAYMENTS ---
+    fn payment(offset_ms: i64, amount: &str, currency: &str) -> Arc<UserEvent> {
+        event("pay", EventType::Payment, offset_ms, &[("amount", amount), ("currency", currency)])
+    }
+
+    fn in_country(country: &str) -> impl FnOnce(&mut RuleContext<'_>) + '_ {
+        move |ctx| {
+            ctx.geo = Some(GeoInfo {
+                country: Some(country.to_string()),
+                ..GeoInfo::default()
+            })
+        }
+    }
+
+    #[test]
+    fn payments_over_the_window_limit_are_scored() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        let history = [payment(0, "200", "USD")];
+        let under = fixture.hits_with(&PaymentRule, &history, |ctx| ctx.payment_window_total = 4_800.0);
+        assert!(under.is_empty());
+        let over = fixture.hits_with(&PaymentRule, &history, |ctx| ctx.payment_window_total = 4_900.0);
+        assert_eq!(codes(&over), ["PAYMENT_VELOCITY"]);
+        assert_eq!(over[0].reason, "Payments total 5100.00 within 86400s, over the 5000.00 limit");
+    }
+
+    #[test]
+    fn repeated_identical_amounts_are_card_testing() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        let history = [payment(0, "1", "USD"), payment(60_000, "1.00", "USD"), payment(120_000, "1", "USD")];
+        assert_eq!(codes(&fixture.hits(&PaymentRule, &history)), ["CARD_TESTING"]);
+        // Two repeats, or repeats in another currency or outside the window, don't count.
+        assert!(fixture.hits(&PaymentRule, &history[1..]).is_empty());
+        let mixed = [payment(0, "1", "EUR"), payment(60_000, "1", "USD"), payment(120_000, "1", "USD")];
+        assert!(fixture.hits(&PaymentRule, &mixed).is_empty());
+        let spread = [payment(0, "1", "USD"), payment(601_000, "1", "USD"), payment(602_000, "1", "USD")];
+        assert!(fixture.hits(&PaymentRule, &spread).is_empty());
+    }
+
+    #[test]
+    fn a_currency_foreign_to_the_ip_country_is_scored() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        let history = [payment(0, "20", "USD")];
+        let hits = fixture.hits_with(&PaymentRule, &history, in_country("DE"));
+        assert_eq!(codes(&hits), ["CURRENCY_MISMATCH"]);
+        assert_eq!(hits[0].reason, "Payment currency doesn't match IP country DE (expected EUR)");
+        assert!(fixture.hits_with(&PaymentRule, &[payment(0, "20", "eur")], in_country("DE")).is_empty());
+        // Without a country, or for one the table doesn't know, nothing is expected.
+        assert!(fixture.hits(&PaymentRule, &history).is_empty());
+        assert!(fixture.hits_with(&PaymentRule, &history, in_country("AQ")).is_empty());
+    }
+
+    #[test]
+    fn configured_currencies_override_the_built_in_table() {
+        let fixture = Fixture::new(config(json!({ "payments": { "expected_currencies": { "DE": "USD", "AQ": "NZD" } } })));
+        assert!(fixture.hits_with(&PaymentRule, &[payment(0, "20", "USD")], in_country("DE")).is_empty());
+        let hits = fixture.hits_with(&PaymentRule, &[payment(0, "20", "USD")], in_country("AQ"));
+        assert_eq!(codes(&hits), ["CURRENCY_MISMATCH"]);
+    }
+
+    #[test]
+    fn an_unparseable_amount_is_malformed_metadata() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        let hits = fixture.hits(&PaymentRule, &[payment(0, "twenty", "USD")]);
+        assert_eq!(codes(&hits), ["MALFORMED_METADATA"]);
+        assert_eq!(hits[0].reason, "Malformed metadata: amount is not a valid number");
+        let missing = fixture.hits(&PaymentRule, &[event("pay", EventType::Payment, 0, &[("currency", "USD")])]);
+        assert_eq!(missing[0].reason, "Malformed metadata: amount is required");
+    }
+
+    #[test]
+    fn an_amount_the_metadata_schema_checks_is_not_scored_twice() {
+        let fixture = Fixture::new(config(json!({
+            "metadata_keys": { "schemas": { "Payment": { "required": ["amount"], "values": { "amount": { "type": "number" } } } } }
+        })));
+        assert!(fixture.hits(&PaymentRule, &[payment(0, "twenty", "USD")]).is_empty());
+    }
+
+    // --- CLICK TIMING ---
+    fn clicks(offsets_ms: &[i64]) -> Vec<Arc<UserEvent>> {
+        offsets_ms.iter().map(|offset| event("clicks", EventType::Click, *offset, &[])).collect()
+    }
+
+    #[test]
+    fn evenly_spaced_clicks_are_flagged() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        let history = clicks(&[0, 1_500, 3_010, 4_500, 5_990, 7_500]);
+        let hits = fixture.hits(&ClickTimingRule, &history);
+        assert_eq!(codes(&hits), ["REGULAR_CLICK_TIMING"]);
+        assert_eq!(hits[0].points, 35);
+    }
+
+    #[test]
+    fn irregular_clicks_are_not() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        let history = clicks(&[0, 900, 3_100, 3_700, 6_400, 7_000]);
+        assert!(fixture.hits(&ClickTimingRule, &history).is_empty());
+    }
+
+    #[test]
+    fn click_timing_waits_for_min_clicks() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        let history = clicks(&[0, 1_500, 3_000, 4_500, 6_000]);
+        assert!(fixture.hits(&ClickTimingRule, &history).is_empty());
+    }
+
+    #[test]
+    fn only_the_most_recent_clicks_are_timed() {
+        let fixture = Fixture::new(config(json!({ "click_timing": { "window": 6 } })));
+        let mut offsets = vec![0, 4_000, 4_300, 9_000];
+        offsets.extend((0..6).map(|n| 10_000 + n * 1_500));
+        let mut history = clicks(&offsets);
+        assert_eq!(codes(&fixture.hits(&ClickTimingRule, &history)), ["REGULAR_CLICK_TIMING"]);
+
+        // Other event types in between don't break the rhythm, and aren't timed themselves.
+        history.insert(6, event("clicks", EventType::PageLoad, 12_200, &[]));
+        assert_eq!(codes(&fixture.hits(&ClickTimingRule, &history)), ["REGULAR_CLICK_TIMING"]);
+        history.push(event("clicks", EventType::PageLoad, 19_000, &[]));
+        assert!(fixture.hits(&ClickTimingRule, &history).is_empty());
+    }
+
+    // --- REFERRER AND ORIGIN ---
+    fn submission(metadata: &[(&str, &str)]) -> [Arc<UserEvent>; 1] {
+        [event("form", EventType::FormSubmission, 0, metadata)]
+    }
+
+    #[test]
+    fn url_hosts_ignore_scheme_port_path_and_case() {
+        assert_eq!(url_host("https://Shop.Example.com:8443/checkout?step=2#pay").as_deref(), Some("shop.example.com"));
+        assert_eq!(url_host("shop.example.com/checkout").as_deref(), Some("shop.example.com"));
+        assert_eq!(url_host("https://user:pw@shop.example.com./").as_deref(), Some("shop.example.com"));
+        assert_eq!(url_host("http://[2001:db8::1]:8080/").as_deref(), Some("2001:db8::1"));
+        assert_eq!(url_host("https:///path"), None);
+        assert_eq!(url_host("  "), None);
+    }
+
+    #[test]
+    fn matching_referrer_and_origin_hosts_add_nothing() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        let history = submission(&[("referrer", "https://shop.example.com/cart"), ("origin", "http://SHOP.example.com:443")]);
+        assert!(fixture.hits(&ReferrerOriginRule, &history).is_empty());
+    }
+
+    #[test]
+    fn mismatched_referrer_and_origin_hosts_are_scored() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        let history = submission(&[("referrer", "https://evil.example.net/"), ("origin", "https://shop.example.com")]);
+        let hits = fixture.hits(&ReferrerOriginRule, &history);
+        assert_eq!(codes(&hits), ["REFERRER_ORIGIN_MISMATCH"]);
+        assert_eq!(hits[0].points, 35);
+    }
+
+    #[test]
+    fn a_missing_referrer_or_origin_is_skipped() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        assert!(fixture.hits(&ReferrerOriginRule, &submission(&[("origin", "https://shop.example.com")])).is_empty());
+        assert!(fixture.hits(&ReferrerOriginRule, &submission(&[("referrer", "https://evil.example.net/")])).is_empty());
+        assert!(fixture.hits(&ReferrerOriginRule, &submission(&[])).is_empty());
+        let unparseable = submission(&[("referrer", "https://"), ("origin", "https://shop.example.com")]);
+        assert!(fixture.hits(&ReferrerOriginRule, &unparseable).is_empty());
+    }
+
+    #[test]
+    fn only_form_submissions_are_compared() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        let click = [event("form", EventType::Click, 0, &[("referrer", "https://evil.example.net/"), ("origin", "https://shop.example.com")])];
+        assert!(fixture.hits(&ReferrerOriginRule, &click).is_empty());
+    }
+
+    // --- MODEL ---
+    #[test]
+    fn the_model_probability_is_scaled_by_its_weight() {
+        let fixture = Fixture::new(config(json!({ "model": { "weight": 60 } })));
+        let history = [event("m", EventType::PageLoad, 0, &[])];
+        let hits = fixture.hits_with(&ModelScoreRule, &history, |ctx| ctx.model_probability = Some(0.25));
+        assert_eq!(hits[0].points, 15);
+        assert_eq!(hits[0].reason, "MODEL_SCORE: 0.25");
+        assert!(fixture.hits(&ModelScoreRule, &history).is_empty());
+    }
+
+    // --- FIELD TIMING ---
+    fn form(timings: &str) -> [Arc<UserEvent>; 1] {
+        [event("form", EventType::FormSubmission, 0, &[("fieldTimings", timings)])]
+    }
+
+    #[test]
+    fn an_instantly_filled_form_is_flagged() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        let hits = fixture.hits(&FieldTimingRule, &form(r#"{"name": 100, "email": 140, "address": 190}"#));
+        assert_eq!(codes(&hits), ["FAST_FORM_FILL"]);
+        assert_eq!(hits[0].points, 40);
+        assert_eq!(hits[0].reason, "3 form fields filled within 90ms");
+    }
+
+    #[test]
+    fn a_human_paced_form_is_not_flagged() {
+        let fixture = Fixture::new(config(json!({ "field_timing": { "field_order": ["name", "email", "address"] } })));
+        assert!(fixture.hits(&FieldTimingRule, &form(r#"{"name": 1200, "email": 4800, "address": 9100}"#)).is_empty());
+    }
+
+    #[test]
+    fn fields_filled_out_of_visual_order_are_flagged() {
+        let fixture = Fixture::new(config(json!({ "field_timing": { "field_order": ["name", "email", "address"] } })));
+        let hits = fixture.hits(&FieldTimingRule, &form(r#"{"name": 9100, "email": 4800, "address": 1200}"#));
+        assert_eq!(codes(&hits), ["FIELD_ORDER_ANOMALY"]);
+        assert_eq!(hits[0].points, 25);
+    }
+
+    #[test]
+    fn missing_malformed_or_sparse_timings_skip_the_rule() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        for timings in ["not json", r#"["name", "email", "address"]"#, r#"{"name": 1, "email": 2}"#, r#"{"name": -1, "email": 2, "address": 3}"#] {
+            assert!(fixture.hits(&FieldTimingRule, &form(timings)).is_empty(), "{}", timings);
+        }
+        assert!(fixture.hits(&FieldTimingRule, &[event("form", EventType::FormSubmission, 0, &[])]).is_empty());
+        let click = [event("form", EventType::Click, 0, &[("fieldTimings", r#"{"a": 1, "b": 2, "c": 3}"#)])];
+        assert!(fixture.hits(&FieldTimingRule, &click).is_empty());
+    }
+
+    // --- DISPOSABLE EMAIL ---
+    fn signup(metadata: &[(&str, &str)]) -> [Arc<UserEvent>; 1] {
+        [event("signup", EventType::FormSubmission, 0, metadata)]
+    }
+
+    #[test]
+    fn a_disposable_email_domain_is_flagged() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        let hits = fixture.hits(&DisposableEmailRule, &signup(&[("email", "Someone+x@EU.Mailinator.com")]));
+        assert_eq!(codes(&hits), ["DISPOSABLE_EMAIL"]);
+        assert_eq!(hits[0].points, ScoringConfig::default().disposable_email.points);
+        assert_eq!(hits[0].reason, "Disposable email domain: mailinator.com");
+    }
+
+    #[test]
+    fn a_normal_email_domain_is_not_flagged() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        assert!(fixture.hits(&DisposableEmailRule, &signup(&[("email", "someone@example.com")])).is_empty());
+    }
+
+    #[test]
+    fn a_missing_email_skips_the_rule() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        assert!(fixture.hits(&DisposableEmailRule, &signup(&[])).is_empty());
+        assert!(fixture.hits(&DisposableEmailRule, &signup(&[("name", "someone")])).is_empty());
+        let click = [event("signup", EventType::Click, 0, &[("email", "someone@mailinator.com")])];
+        assert!(fixture.hits(&DisposableEmailRule, &click).is_empty());
+    }
+
+    #[test]
+    fn a_malformed_email_scores_as_invalid() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        let hits = fixture.hits(&DisposableEmailRule, &signup(&[("email", "not an email")]));
+        assert_eq!(codes(&hits), ["INVALID_EMAIL_FORMAT"]);
+    }
+
+    // --- SHARED SESSIONS ---
+    /// One event per entry of `users`, a second apart, in the same session.
+    fn used_by(users: &[Option<&str>]) -> Vec<Arc<UserEvent>> {
+        users
+            .iter()
+            .enumerate()
+            .map(|(n, user_id)| {
+                let mut event = (*event("shared", EventType::PageLoad, n as i64 * 1_000, &[])).clone();
+                event.user_id = user_id.map(String::from);
+                Arc::new(event)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_session_used_by_two_users_is_flagged() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        let hits = fixture.hits(&SharedSessionRule, &used_by(&[Some("alice"), Some("bob")]));
+        assert_eq!(codes(&hits), ["SHARED_SESSION"]);
+        assert_eq!(hits[0].points, 50);
+        assert_eq!(hits[0].reason, "Session used by 2 distinct users");
+    }
+
+    #[test]
+    fn one_user_and_anonymous_events_are_not_flagged() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        assert!(fixture.hits(&SharedSessionRule, &used_by(&[Some("alice"), Some("alice"), Some("alice")])).is_empty());
+        assert!(fixture.hits(&SharedSessionRule, &used_by(&[Some("alice"), None, Some("alice")])).is_empty());
+        // The current event is anonymous: nothing to attribute.
+        assert!(fixture.hits(&SharedSessionRule, &used_by(&[Some("alice"), Some("bob"), None])).is_empty());
+    }
+
+    #[test]
+    fn max_users_raises_the_bar() {
+        let fixture = Fixture::new(config(json!({ "shared_session": { "max_users": 2 } })));
+        assert!(fixture.hits(&SharedSessionRule, &used_by(&[Some("alice"), Some("bob"), Some("alice")])).is_empty());
+        let hits = fixture.hits(&SharedSessionRule, &used_by(&[Some("alice"), Some("bob"), Some("carol")]));
+        assert_eq!(hits[0].reason, "Session used by 3 distinct users");
+    }
+
+    // --- HIGH FREQUENCY ---
+    /// `count` events of `event_type` in one session, `spacing_ms` apart.
+    fn burst(event_type: EventType, count: usize, spacing_ms: i64) -> Vec<Arc<UserEvent>> {
+        (0..count).map(|n| event("burst", event_type.clone(), n as i64 * spacing_ms, &[])).collect()
+    }
+
+    /// The rule's hit on `history`'s last event, with the session's windowed counts
+    /// worked out from the history as the tracker would keep them.
+    fn frequency(config: &ScoringConfig, history: &[Arc<UserEvent>]) -> Option<RuleHit> {
+        let mut fixture = Fixture::new(config.clone());
+        let (last, prior) = history.split_last().unwrap();
+        fixture.rates.session_by_type = crate::rates::scan(prior, last.timestamp, config.high_frequency.window_secs);
+        let mut hits = fixture.hits(&HighFrequencyRule, history);
+        assert!(hits.len() <= 1);
+        hits.pop()
+    }
+
+    #[test]
+    fn frequency_boundaries() {
+        let by_type = json!({ "high_frequency": { "max_events_by_type": { "PageLoad": 20 } } });
+        /// A name, scoring config overrides, the session, and the reason expected if any.
+        type Case = (&'static str, Value, Vec<Arc<UserEvent>>, Option<&'static str>);
+        let cases: &[Case] = &[
+            ("at the limit", json!({}), burst(EventType::Click, 10, 1_000), None),
+            ("one over", json!({}), burst(EventType::Click, 11, 1_000), Some("High frequency of events")),
+            (
+                "the current event left out, at the limit",
+                json!({ "high_frequency": { "count_current_event": false } }),
+                burst(EventType::Click, 11, 1_000),
+                None,
+            ),
+            (
+                "the current event left out, one over",
+                json!({ "high_frequency": { "count_current_event": false } }),
+                burst(EventType::Click, 12, 1_000),
+                Some("High frequency of events"),
+            ),
+            // 11 events 500ms apart span 5s: the first is exactly `window_secs` older.
+            ("the window excludes its far edge", json!({ "high_frequency": { "window_secs": 5 } }), burst(EventType::Click, 11, 500), None),
+            (
+                "the window includes anything nearer",
+                json!({ "high_frequency": { "window_secs": 5 } }),
+                burst(EventType::Click, 11, 499),
+                Some("High frequency of events"),
+            ),
+            ("a tolerated type under its own limit", by_type.clone(), burst(EventType::PageLoad, 20, 100), None),
+            ("a tolerated type over its own limit", by_type.clone(), burst(EventType::PageLoad, 21, 100), Some("High frequency of PageLoad events")),
+            ("other types keep the global limit", by_type, burst(EventType::FormSubmission, 11, 100), Some("High frequency of events")),
+        ];
+        for (name, overrides, history, expected) in cases {
+            let hit = frequency(&config(overrides.clone()), history);
+            assert_eq!(hit.as_ref().map(|hit| hit.reason.as_str()), *expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn a_long_burst_scores_the_rule_once() {
+        let hit = frequency(&ScoringConfig::default(), &burst(EventType::Click, 200, 10)).unwrap();
+        assert_eq!((hit.code.as_ref(), hit.points), ("HIGH_FREQUENCY", 50));
+    }
+
+    // --- ASN REPUTATION ---
+    /// What a resolver knowing `asn` (run by `organization`) reports for the event IP.
+    fn network(asn: u32, organization: Option<&str>, hosting: Option<bool>) -> GeoInfo {
+        GeoInfo {
+            country: None,
+            asn: Some(asn),
+            asn_organization: organization.map(String::from),
+            hosting,
+        }
+    }
+
+    fn asn_hits(fixture: &Fixture, geo: Option<GeoInfo>) -> Vec<RuleHit> {
+        fixture.hits_with(&AsnReputationRule, &[event("asn", EventType::PageLoad, 0, &[])], |ctx| ctx.geo = geo)
+    }
+
+    #[test]
+    fn a_flagged_asn_is_scored() {
+        let fixture = Fixture::new(config(json!({ "asn_reputation": { "bad_asns": [64500] } })));
+        let hits = asn_hits(&fixture, Some(network(64500, Some("BULLETPROOF-AS"), None)));
+        assert_eq!(codes(&hits), ["BAD_ASN"]);
+        assert_eq!(hits[0].points, 60);
+        assert_eq!(hits[0].reason, "IP from flagged network AS64500 (BULLETPROOF-AS)");
+        assert!(asn_hits(&fixture, Some(network(64501, None, None))).is_empty());
+    }
+
+    #[test]
+    fn hosting_and_datacenter_networks_score_when_weighted() {
+        let fixture = Fixture::new(config(json!({
+            "asn_reputation": { "hosting_points": 10, "datacenter_points": 15, "datacenter_asns": [64510] }
+        })));
+        let hits = asn_hits(&fixture, Some(network(64510, None, Some(true))));
+        assert_eq!(codes(&hits), ["HOSTING_NETWORK", "DATACENTER_ASN"]);
+        assert_eq!(hits[1].reason, "IP from datacenter network AS64510");
+        // A residential network scores nothing.
+        assert!(asn_hits(&fixture, Some(network(64520, None, Some(false)))).is_empty());
+        // Without the weights, neither is scored.
+        let unweighted = Fixture::new(ScoringConfig::default());
+        assert!(asn_hits(&unweighted, Some(network(16509, None, Some(true)))).is_empty());
+    }
+
+    #[test]
+    fn missing_asn_data_skips_the_rule() {
+        let fixture = Fixture::new(config(json!({ "asn_reputation": { "bad_asns": [64500] } })));
+        assert!(asn_hits(&fixture, None).is_empty());
+        let unresolved = GeoInfo {
+            country: Some("US".to_string()),
+            asn: None,
+            asn_organization: None,
+            hosting: None,
+        };
+        assert!(asn_hits(&fixture, Some(unresolved)).is_empty());
+    }
+
+    // --- NORMALIZED SCORE ---
+    /// The engine's analysis of one event the model scores `probability`, at 200 points
+    /// for a certain one.
+    fn scored(scoring: Value, probability: f32) -> Analysis {
+        let mut scoring = scoring;
+        scoring["model"] = json!({ "weight": 200 });
+        scoring["session_reputation"] = json!({ "half_life_secs": 0 });
+        let fixture = Fixture::new(config(scoring));
+        let engine = RuleEngine::new(&fixture.config).unwrap();
+        let history = [event("n", EventType::PageLoad, 0, &[])];
+        fixture.with(&history, |ctx| ctx.model_probability = Some(probability), |ctx| engine.evaluate(ctx))
+    }
+
+    #[test]
+    fn the_raw_score_is_kept_and_the_score_is_clamped_to_100() {
+        let analysis = scored(json!({}), 1.0);
+        assert_eq!((analysis.raw_score, analysis.score), (200, 100));
+        assert!(analysis.flagged);
+
+        let analysis = scored(json!({}), 0.2);
+        assert_eq!((analysis.raw_score, analysis.score), (40, 40));
+        assert!(!analysis.flagged);
+    }
+
+    #[test]
+    fn flagging_compares_the_normalized_score() {
+        let logistic = json!({
+            "flag_threshold": 60,
+            "confidence": { "mode": "logistic", "midpoint": 100, "steepness": 0.1 },
+        });
+        // 100 raw points are past the threshold, but only reach the midpoint's 50.
+        let analysis = scored(logistic.clone(), 0.5);
+        assert_eq!((analysis.raw_score, analysis.score), (100, 50));
+        assert!(!analysis.flagged);
+
+        let analysis = scored(logistic, 0.6);
+        assert_eq!(analysis.raw_score, 120);
+        assert!(analysis.score >= 60, "{}", analysis.score);
+        assert!(analysis.flagged);
+    }
+
+    #[test]
+    fn a_raw_score_past_100_flags_at_the_top_threshold() {
+        let analysis = scored(json!({ "flag_threshold": 100 }), 0.7);
+        assert_eq!((analysis.raw_score, analysis.score), (140, 100));
+        assert!(analysis.flagged);
+    }
+
+    #[test]
+    fn decision_bands_compare_the_normalized_score() {
+        let bands = json!({
+            "confidence": { "mode": "logistic", "midpoint": 100, "steepness": 0.1 },
+            "decision_policy": { "bands": [{ "min_score": 60, "decision": "DENY" }] },
+        });
+        assert_eq!(scored(bands.clone(), 0.5).decision, Decision::Allow);
+        assert_eq!(scored(bands, 0.6).decision, Decision::Deny);
+    }
+
+    // --- SESSION DURATION ---
+    /// A page load, then a form submitted `after_ms` later.
+    fn submitted_after(after_ms: i64) -> [Arc<UserEvent>; 2] {
+        [
+            event("d", EventType::PageLoad, 0, &[]),
+            event("d", EventType::FormSubmission, after_ms, &[]),
+        ]
+    }
+
+    #[test]
+    fn a_sub_second_session_is_flagged() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        let hits = fixture.hits(&SessionDurationRule, &submitted_after(400));
+        assert_eq!(codes(&hits), ["SHORT_SESSION"]);
+        assert_eq!(hits[0].points, 35);
+        assert_eq!(hits[0].reason, "Form submitted 400ms into the session");
+    }
+
+    #[test]
+    fn a_normal_duration_session_is_not() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        for after_ms in [1_000, 90_000, 4 * 3_600_000] {
+            assert!(fixture.hits(&SessionDurationRule, &submitted_after(after_ms)).is_empty(), "{}ms", after_ms);
+        }
+    }
+
+    #[test]
+    fn a_submission_after_hours_is_a_long_session() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        let hits = fixture.hits(&SessionDurationRule, &submitted_after(5 * 3_600_000));
+        assert_eq!(codes(&hits), ["LONG_SESSION"]);
+        assert_eq!(hits[0].points, 15);
+        assert_eq!(hits[0].reason, "Form submitted 300 minutes into the session");
+    }
+
+    #[test]
+    fn duration_is_measured_from_the_earliest_stored_event() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        // Stored out of order: the click arrived after the page load it followed.
+        let history = [
+            event("d", EventType::Click, 5_000, &[]),
+            event("d", EventType::PageLoad, 0, &[]),
+            event("d", EventType::FormSubmission, 5_500, &[]),
+        ];
+        assert!(fixture.hits(&SessionDurationRule, &history).is_empty());
+    }
+
+    #[test]
+    fn only_submissions_with_history_are_measured() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        assert!(fixture.hits(&SessionDurationRule, &[event("d", EventType::FormSubmission, 0, &[])]).is_empty());
+        let clicks = [event("d", EventType::PageLoad, 0, &[]), event("d", EventType::Click, 100, &[])];
+        assert!(fixture.hits(&SessionDurationRule, &clicks).is_empty());
+    }
+
+    #[test]
+    fn zero_points_turn_a_check_off() {
+        let fixture = Fixture::new(config(json!({ "session_duration": { "short_points": 0, "min_duration_ms": 2000 } })));
+        assert!(fixture.hits(&SessionDurationRule, &submitted_after(400)).is_empty());
+        let fixture = Fixture::new(config(json!({ "session_duration": { "long_points": 0 } })));
+        assert!(fixture.hits(&SessionDurationRule, &submitted_after(5 * 3_600_000)).is_empty());
+    }
+
+    // --- STATEFUL RULES ---
+    /// The engine's hit codes for a page load followed by `submissions` form submissions
+    /// 400ms apart, with the frequency limit at one event.
+    fn established_after(min_events: usize, submissions: i64) -> Vec<String> {
+        let fixture = Fixture::new(config(json!({
+            "min_events_for_stateful": min_events,
+            "high_frequency": { "max_events": 1 },
+            "session_reputation": { "half_life_secs": 0 },
+        })));
+        let engine = RuleEngine::new(&fixture.config).unwrap();
+        let mut history = vec![event("s", EventType::PageLoad, 0, &[])];
+        history.extend((1..=submissions).map(|n| event("s", EventType::FormSubmission, n * 400, &[])));
+        let analysis = fixture.with(&history, |_| {}, |ctx| engine.evaluate(ctx));
+        analysis.hits.iter().map(|hit| hit.code.to_string()).collect()
+    }
+
+    #[test]
+    fn frequency_waits_for_the_minimum_while_per_event_rules_still_apply() {
+        // Two events are over the limit of one, but short of the three the session needs.
+        let young = established_after(3, 1);
+        assert!(!young.contains(&"HIGH_FREQUENCY".to_string()), "{:?}", young);
+        assert!(young.contains(&"SHORT_SESSION".to_string()), "{:?}", young);
+        let established = established_after(3, 2);
+        assert!(established.contains(&"HIGH_FREQUENCY".to_string()), "{:?}", established);
+        assert!(established.contains(&"SHORT_SESSION".to_string()), "{:?}", established);
+    }
+
+    #[test]
+    fn a_minimum_of_one_consults_stateful_rules_from_the_first_event() {
+        assert!(established_after(1, 1).contains(&"HIGH_FREQUENCY".to_string()));
+    }
+
+    #[test]
+    fn the_history_kept_covers_the_minimum() {
+        let config = config(json!({ "min_events_for_stateful": 40 }));
+        assert!(config.history_needed() >= 40);
+    }
+
+    // --- TYPE TOGGLING ---
+    /// One event per `(type, offset_ms)`.
+    fn sequence(events: &[(EventType, i64)]) -> Vec<Arc<UserEvent>> {
+        events.iter().map(|(event_type, offset_ms)| event("t", event_type.clone(), *offset_ms, &[])).collect()
+    }
+
+    /// Click and FormSubmission in turn, `count` events `spacing_ms` apart.
+    fn alternating(count: usize, spacing_ms: i64) -> Vec<Arc<UserEvent>> {
+        let types = [EventType::Click, EventType::FormSubmission];
+        let events: Vec<_> = (0..count).map(|n| (types[n % 2].clone(), n as i64 * spacing_ms)).collect();
+        sequence(&events)
+    }
+
+    #[test]
+    fn rapid_alternation_is_flagged() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        let hits = fixture.hits(&TypeTogglingRule, &alternating(5, 40));
+        assert_eq!(codes(&hits), ["RAPID_TYPE_TOGGLING"]);
+        assert_eq!(hits[0].points, 30);
+        assert_eq!(
+            hits[0].reason,
+            "Event type changed 4 times in 160ms (Click > FormSubmission > Click > FormSubmission > Click)"
+        );
+    }
+
+    #[test]
+    fn a_natural_sequence_is_not() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        let natural = sequence(&[
+            (EventType::PageLoad, 0),
+            (EventType::Click, 1_800),
+            (EventType::Click, 2_600),
+            (EventType::FormSubmission, 9_000),
+            (EventType::PageLoad, 9_400),
+        ]);
+        assert!(fixture.hits(&TypeTogglingRule, &natural).is_empty());
+        // The same alternation at a human pace.
+        assert!(fixture.hits(&TypeTogglingRule, &alternating(5, 800)).is_empty());
+    }
+
+    #[test]
+    fn one_slow_or_repeated_step_breaks_the_run() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        let slow = sequence(&[
+            (EventType::Click, 0),
+            (EventType::FormSubmission, 40),
+            (EventType::Click, 80),
+            (EventType::FormSubmission, 230),
+            (EventType::Click, 270),
+        ]);
+        // 150ms is the limit itself, so it doesn't count.
+        assert!(fixture.hits(&TypeTogglingRule, &slow).is_empty());
+        let repeated = sequence(&[
+            (EventType::Click, 0),
+            (EventType::FormSubmission, 40),
+            (EventType::FormSubmission, 80),
+            (EventType::Click, 120),
+            (EventType::FormSubmission, 160),
+        ]);
+        assert!(fixture.hits(&TypeTogglingRule, &repeated).is_empty());
+    }
+
+    #[test]
+    fn only_the_last_min_switches_count() {
+        let fixture = Fixture::new(config(json!({ "type_toggling": { "min_switches": 2 } })));
+        let mut history = sequence(&[(EventType::PageLoad, 0), (EventType::PageLoad, 5_000)]);
+        history.extend(sequence(&[(EventType::Click, 5_050), (EventType::FormSubmission, 5_100)]));
+        let hits = fixture.hits(&TypeTogglingRule, &history);
+        assert_eq!(hits[0].reason, "Event type changed 2 times in 100ms (PageLoad > Click > FormSubmission)");
+        // Too short a history for the run.
+        assert!(fixture.hits(&TypeTogglingRule, &alternating(2, 10)).is_empty());
+    }
+
+    // --- LOCALE MISMATCH ---
+    fn with_locale(locale: &str) -> [Arc<UserEvent>; 1] {
+        [event("locale", EventType::PageLoad, 0, &[("locale", locale)])]
+    }
+
+    #[test]
+    fn a_locale_naming_the_ip_country_is_not_scored() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        for locale in ["en-US", "en_US.UTF-8", "en-us"] {
+            assert!(fixture.hits_with(&LocaleMismatchRule, &with_locale(locale), in_country("US")).is_empty(), "{}", locale);
+        }
+        assert!(fixture.hits_with(&LocaleMismatchRule, &with_locale("zh-Hant-TW"), in_country("TW")).is_empty());
+    }
+
+    #[test]
+    fn a_locale_from_another_country_is_scored() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        let hits = fixture.hits_with(&LocaleMismatchRule, &with_locale("en-US"), in_country("VN"));
+        assert_eq!(codes(&hits), ["LOCALE_MISMATCH"]);
+        assert_eq!(hits[0].points, 20);
+        assert_eq!(hits[0].reason, "Locale country US doesn't match IP country VN");
+    }
+
+    #[test]
+    fn allowed_mismatches_are_not_scored() {
+        let fixture = Fixture::new(config(json!({ "locale_mismatch": { "allowed_mismatches": { "us": ["ca", "MX"] } } })));
+        assert!(fixture.hits_with(&LocaleMismatchRule, &with_locale("en-US"), in_country("CA")).is_empty());
+        assert!(fixture.hits_with(&LocaleMismatchRule, &with_locale("es-US"), in_country("MX")).is_empty());
+        // Only in the configured direction.
+        let hits = fixture.hits_with(&LocaleMismatchRule, &with_locale("en-CA"), in_country("US"));
+        assert_eq!(codes(&hits), ["LOCALE_MISMATCH"]);
+    }
+
+    #[test]
+    fn a_locale_or_country_that_is_missing_skips_the_rule() {
+        let fixture = Fixture::new(ScoringConfig::default());
+        assert!(fixture.hits(&LocaleMismatchRule, &with_locale("en-US")).is_empty());
+        let no_locale = [event("locale", EventType::PageLoad, 0, &[])];
+        assert!(fixture.hits_with(&LocaleMismatchRule, &no_locale, in_country("VN")).is_empty());
+        for locale in ["en", "es-419", "en-x-private-US", ""] {
+            assert!(fixture.hits_with(&LocaleMismatchRule, &with_locale(locale), in_country("VN")).is_empty(), "{}", locale);
+        }
+    }
+
+    #[test]
+    fn locale_countries_are_read_from_the_region_subtag() {
+        assert_eq!(locale_country("en-US").as_deref(), Some("US"));
+        assert_eq!(locale_country(" pt_br.UTF-8 ").as_deref(), Some("BR"));
+        assert_eq!(locale_country("de_DE@euro").as_deref(), Some("DE"));
+        assert_eq!(locale_country("zh-Hant-TW").as_deref(), Some("TW"));
+        assert_eq!(locale_country("sr-Latn-RS-u-nu-latn").as_deref(), Some("RS"));
+        assert_eq!(locale_country("es-419"), None);
+        assert_eq!(locale_country("fr"), None);
+    }
+}
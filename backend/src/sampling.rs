@@ -0,0 +1,205 @@
+use chrono::{DateTime, Utc};
+use metrics::counter;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::config::StorageSamplingConfig;
+use crate::UserEvent;
+
+// --- STORAGE SAMPLING ---
+/// What sampling remembers of a session.
+#[derive(Default)]
+struct SessionSampling {
+    latest_score: i32,
+    /// Set once any rule hit the session; from then on every event is stored.
+    retain_all: bool,
+    /// Set once an event of the session went unstored, so its history is partial.
+    partial: bool,
+    last_seen: DateTime<Utc>,
+}
+
+/// Decides which events are stored under `STORAGE_SAMPLE_RATES`. Only events of the
+/// sampled types, from sessions no rule has hit and whose latest score is under
+/// `STORAGE_SAMPLING_MAX_SCORE`, are sampled, and one drawn out is still stored if it
+/// scores as risky itself. The draw is a hash of the event, so a retried or replayed
+/// event gets the same answer.
+pub struct StorageSampler {
+    config: StorageSamplingConfig,
+    sessions: Mutex<HashMap<String, SessionSampling>>,
+}
+
+/// Where in `[0, 1)` the event falls, the same for every instance and every retry.
+fn draw(event: &UserEvent) -> f64 {
+    let digest = Sha256::new()
+        .chain_update(&event.session_id)
+        .chain_update([0])
+        .chain_update(event.event_type.as_str())
+        .chain_update(event.timestamp.timestamp_micros().to_be_bytes())
+        .finalize();
+    let bits = u64::from_be_bytes(digest[..8].try_into().unwrap());
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+impl StorageSampler {
+    pub fn new(config: StorageSamplingConfig) -> Self {
+        Self {
+            config,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether `event` was drawn to go unstored. It still is stored if it turns out
+    /// `risky`.
+    pub fn draws_out(&self, event: &UserEvent) -> bool {
+        let Some(rate) = self.config.rates.get(&event.event_type) else {
+            return false;
+        };
+        let sessions = self.sessions.lock().unwrap();
+        let session = sessions.get(&event.session_id);
+        !session.is_some_and(|session| session.retain_all || session.latest_score >= self.config.max_score) && draw(event) >= *rate
+    }
+
+    /// Whether a score keeps an event, and every later one of its session, stored.
+    pub fn risky(&self, score: i32, hit: bool) -> bool {
+        hit || score >= self.config.max_score
+    }
+
+    /// Takes in a scored event's outcome and whether it went unstored, returning whether
+    /// the session's history is partial.
+    pub fn record(&self, event: &UserEvent, score: i32, hit: bool, unstored: bool, now: DateTime<Utc>) -> bool {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.entry(event.session_id.clone()).or_default();
+        session.latest_score = score;
+        session.retain_all |= hit;
+        session.last_seen = now;
+        if unstored {
+            session.partial = true;
+            counter!("events_sampled_out_total", "event_type" => event.event_type.as_str()).increment(1);
+        }
+        session.partial
+    }
+
+    /// Whether some of the session's events went unstored.
+    pub fn is_partial(&self, session_id: &str) -> bool {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(session_id)
+            .is_some_and(|session| session.partial)
+    }
+
+    /// Forgets sessions not seen since `before`, when their stored history has expired.
+    pub fn sweep(&self, before: DateTime<Utc>) {
+        self.sessions
+            .lock()
+            .unwrap()
+            .retain(|_, session| session.last_seen >= before);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EventType;
+    use chrono::Duration;
+
+    fn start() -> DateTime<Utc> {
+        "2024-05-01T12:00:00Z".parse().unwrap()
+    }
+
+    fn event(session_id: &str, event_type: EventType, offset_ms: i64) -> UserEvent {
+        UserEvent {
+            session_id: session_id.to_string(),
+            user_id: None,
+            event_type,
+            timestamp: start() + Duration::milliseconds(offset_ms),
+            ip_address: "198.51.100.23".to_string(),
+            user_agent: None,
+            metadata: None,
+            asn: None,
+        }
+    }
+
+    fn sampler(rates: &[(EventType, f64)]) -> StorageSampler {
+        StorageSampler::new(StorageSamplingConfig {
+            rates: rates.iter().cloned().collect(),
+            max_score: 10,
+        })
+    }
+
+    #[test]
+    fn the_draw_is_the_same_for_the_same_event() {
+        let click = event("s", EventType::Click, 0);
+        assert_eq!(draw(&click), draw(&click.clone()));
+        assert_ne!(draw(&click), draw(&event("s", EventType::Click, 1)));
+        assert_ne!(draw(&click), draw(&event("t", EventType::Click, 0)));
+        assert_ne!(draw(&click), draw(&event("s", EventType::PageLoad, 0)));
+    }
+
+    #[test]
+    fn about_the_configured_share_is_kept() {
+        let sampler = sampler(&[(EventType::Click, 0.25)]);
+        let kept = (0..4000)
+            .filter(|n| !sampler.draws_out(&event(&format!("s-{}", n % 40), EventType::Click, n * 10)))
+            .count();
+        assert!((900..1100).contains(&kept), "kept {} of 4000", kept);
+    }
+
+    #[test]
+    fn rates_of_zero_and_one_draw_out_everything_and_nothing() {
+        let sampler = sampler(&[(EventType::Click, 0.0), (EventType::PageLoad, 1.0)]);
+        for n in 0..100 {
+            assert!(sampler.draws_out(&event("s", EventType::Click, n)));
+            assert!(!sampler.draws_out(&event("s", EventType::PageLoad, n)));
+            // Types left out are always stored.
+            assert!(!sampler.draws_out(&event("s", EventType::Payment, n)));
+        }
+    }
+
+    #[test]
+    fn a_hit_keeps_the_session_stored_for_good() {
+        let sampler = sampler(&[(EventType::Click, 0.0)]);
+        let click = event("s", EventType::Click, 0);
+        assert!(sampler.risky(0, true));
+        sampler.record(&click, 50, true, false, start());
+        sampler.record(&click, 0, false, false, start());
+        assert!(!sampler.draws_out(&event("s", EventType::Click, 1)));
+        // Other sessions still are sampled.
+        assert!(sampler.draws_out(&event("t", EventType::Click, 1)));
+    }
+
+    #[test]
+    fn a_session_scoring_at_the_threshold_is_stored_while_it_does() {
+        let sampler = sampler(&[(EventType::Click, 0.0)]);
+        let click = event("s", EventType::Click, 0);
+        assert!(sampler.risky(10, false));
+        assert!(!sampler.risky(9, false));
+        sampler.record(&click, 10, false, false, start());
+        assert!(!sampler.draws_out(&click));
+        sampler.record(&click, 9, false, false, start());
+        assert!(sampler.draws_out(&click));
+    }
+
+    #[test]
+    fn a_session_stays_partial_once_an_event_goes_unstored() {
+        let sampler = sampler(&[(EventType::Click, 0.0)]);
+        let click = event("s", EventType::Click, 0);
+        assert!(!sampler.record(&click, 0, false, false, start()));
+        assert!(!sampler.is_partial("s"));
+        assert!(sampler.record(&click, 0, false, true, start()));
+        assert!(sampler.record(&click, 50, true, false, start()));
+        assert!(sampler.is_partial("s"));
+        assert!(!sampler.is_partial("t"));
+    }
+
+    #[test]
+    fn sweeping_forgets_sessions_not_seen_since() {
+        let sampler = sampler(&[(EventType::Click, 0.0)]);
+        sampler.record(&event("old", EventType::Click, 0), 0, false, true, start());
+        sampler.record(&event("new", EventType::Click, 0), 0, false, true, start() + Duration::minutes(5));
+        sampler.sweep(start() + Duration::minutes(1));
+        assert!(!sampler.is_partial("old"));
+        assert!(sampler.is_partial("new"));
+    }
+}
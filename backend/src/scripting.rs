@@ -0,0 +1,214 @@
+use metrics::counter;
+use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::config::{ScoringConfig, ScriptLimitsConfig, ScriptedRuleConfig};
+use crate::ip;
+use crate::rules::{Rule, RuleContext, RuleHit, RuleWeight};
+
+// --- SCRIPTED RULES ---
+/// How many operations run between deadline checks; reading the clock on every
+/// operation would dominate cheap scripts.
+const DEADLINE_CHECK_INTERVAL: u64 = 256;
+
+thread_local! {
+    /// When the script running on this thread must stop. Scripts run synchronously
+    /// inside `evaluate`, so the thread is never shared between two of them.
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+#[derive(Debug)]
+pub struct ScriptError(String);
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A Rhai engine locked down for rule scripts: no modules or printing, capped sizes,
+/// and an operation and wall-clock budget per run.
+fn sandboxed_engine(limits: &ScriptLimitsConfig) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(limits.max_operations.max(1));
+    engine.set_max_call_levels(16);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(16 * 1024);
+    engine.set_max_array_size(1_024);
+    engine.set_max_map_size(1_024);
+    engine.set_max_modules(0);
+    engine.disable_symbol("import");
+    engine.on_print(|_| {});
+    engine.on_debug(|_, _, _| {});
+    engine.on_progress(|operations| {
+        let expired = operations % DEADLINE_CHECK_INTERVAL == 0
+            && DEADLINE.get().is_some_and(|deadline| Instant::now() >= deadline);
+        expired.then(|| Dynamic::from("time budget exceeded"))
+    });
+    engine
+}
+
+/// Compiles every scripted rule against one shared engine. Fails on the first script
+/// that doesn't compile, naming the rule and the line.
+pub fn compile(configs: &[ScriptedRuleConfig], limits: &ScriptLimitsConfig) -> Result<Vec<ScriptedRule>, ScriptError> {
+    let engine = Arc::new(sandboxed_engine(limits));
+    let time_budget = Duration::from_millis(limits.max_duration_ms);
+
+    let mut codes = HashSet::new();
+    configs
+        .iter()
+        .map(|config| {
+            let valid_name = !config.name.is_empty()
+                && config.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid_name {
+                return Err(ScriptError(format!(
+                    "scripted rule name `{}` must be letters, digits, and underscores",
+                    config.name
+                )));
+            }
+            let code = config.name.to_ascii_uppercase();
+            if !codes.insert(code.clone()) {
+                return Err(ScriptError(format!("duplicate scripted rule `{}`", config.name)));
+            }
+
+            let ast = engine.compile(&config.script).map_err(|e| {
+                let line = e.1.line().map_or_else(|| "?".to_string(), |line| line.to_string());
+                ScriptError(format!("scripted rule `{}` failed to compile at line {}: {}", config.name, line, e.0))
+            })?;
+
+            Ok(ScriptedRule {
+                name: config.name.clone(),
+                code,
+                weight: config.weight,
+                ast,
+                engine: engine.clone(),
+                time_budget,
+            })
+        })
+        .collect()
+}
+
+/// A rule from the scoring config. The script sees `event`, `session`, and `geo`
+/// variables and returns `true`/`false` or a number that scales `weight`.
+pub struct ScriptedRule {
+    name: String,
+    code: String,
+    weight: i32,
+    ast: AST,
+    engine: Arc<Engine>,
+    time_budget: Duration,
+}
+
+impl ScriptedRule {
+    fn scope(ctx: &RuleContext<'_>) -> Scope<'static> {
+        let event = ctx.event;
+        let mut event_map = Map::new();
+        event_map.insert("session_id".into(), event.session_id.clone().into());
+        event_map.insert("user_id".into(), event.user_id.clone().map_or(Dynamic::UNIT, Dynamic::from));
+        event_map.insert("event_type".into(), event.event_type.as_str().into());
+        event_map.insert("timestamp".into(), event.timestamp.timestamp_millis().into());
+        event_map.insert("ip_address".into(), ctx.client_ip.to_string().into());
+        event_map.insert("user_agent".into(), event.user_agent().map_or(Dynamic::UNIT, |agent| agent.to_string().into()));
+        let metadata: Map = event
+            .metadata
+            .iter()
+            .flatten()
+            .map(|(key, value)| (key.as_str().into(), value.clone().into()))
+            .collect();
+        event_map.insert("metadata".into(), metadata.into());
+
+        let first_seen = ctx.history.first().map_or(event.timestamp, |first| first.timestamp);
+        let distinct_ips = ctx
+            .history
+            .iter()
+            .map(|event| ip::counter_key(&event.ip_address, ctx.config.ipv6_counter_prefix))
+            .collect::<HashSet<_>>()
+            .len();
+        let mut session = Map::new();
+        session.insert("event_count".into(), (ctx.history.len() as i64).into());
+        session.insert("distinct_ips".into(), (distinct_ips as i64).into());
+        session.insert("duration_secs".into(), (event.timestamp - first_seen).num_seconds().into());
+
+        let mut geo = Map::new();
+        let country = ctx.geo.as_ref().and_then(|geo| geo.country.clone());
+        geo.insert("country".into(), country.map_or(Dynamic::UNIT, Dynamic::from));
+        let asn = ctx.geo.as_ref().and_then(|geo| geo.asn);
+        geo.insert("asn".into(), asn.map_or(Dynamic::UNIT, |asn| i64::from(asn).into()));
+        let hosting = ctx.geo.as_ref().and_then(|geo| geo.hosting);
+        geo.insert("hosting".into(), hosting.map_or(Dynamic::UNIT, Dynamic::from));
+
+        let mut scope = Scope::new();
+        scope.push_constant("event", event_map);
+        scope.push_constant("session", session);
+        scope.push_constant("geo", geo);
+        scope
+    }
+
+    fn run(&self, ctx: &RuleContext<'_>) -> Result<f64, &'static str> {
+        let mut scope = Self::scope(ctx);
+        DEADLINE.set(Some(Instant::now() + self.time_budget));
+        let outcome = self.engine.eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast);
+        DEADLINE.set(None);
+
+        let value = outcome.map_err(|e| match *e {
+            EvalAltResult::ErrorTerminated(..) => "timeout",
+            EvalAltResult::ErrorTooManyOperations(..) => "operations",
+            _ => "runtime",
+        })?;
+        if let Ok(matched) = value.as_bool() {
+            Ok(if matched { 1.0 } else { 0.0 })
+        } else if let Ok(number) = value.as_int() {
+            Ok(number as f64)
+        } else if let Ok(number) = value.as_float() {
+            Ok(number)
+        } else {
+            Err("return_type")
+        }
+    }
+}
+
+impl Rule for ScriptedRule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        "Scripted rule from the scoring config"
+    }
+
+    /// Points per unit the script returns, so `true` scores exactly this.
+    fn weights(&self, _config: &ScoringConfig) -> Vec<RuleWeight> {
+        vec![RuleWeight {
+            code: Cow::Owned(self.code.clone()),
+            points: self.weight,
+        }]
+    }
+
+    fn evaluate(&self, ctx: &RuleContext<'_>, hits: &mut Vec<RuleHit>) {
+        let factor = match self.run(ctx) {
+            Ok(factor) => factor,
+            Err(reason) => {
+                counter!("scripted_rule_errors_total", "rule" => self.name.clone(), "reason" => reason).increment(1);
+                warn!("Scripted rule {} failed ({}) for session {}", self.name, reason, ctx.event.session_id);
+                return;
+            }
+        };
+
+        // Scripts can only add to a score, like native rules.
+        let points = (factor * f64::from(self.weight)).round();
+        if points >= 1.0 {
+            hits.push(RuleHit {
+                code: Cow::Owned(self.code.clone()),
+                points: points.min(f64::from(i32::MAX)) as i32,
+                reason: format!("Custom rule {} matched", self.name),
+                children: Vec::new(),
+            });
+        }
+    }
+}
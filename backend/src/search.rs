@@ -0,0 +1,346 @@
+use axum::{extract::State, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+
+use crate::error::AppQuery;
+use crate::flags::{decode_cursor, encode_cursor};
+use crate::ip;
+use crate::{AppError, AppState, FraudCheckResult, UserEvent};
+
+// --- SESSION SEARCH INDEX ---
+/// Sessions kept in the index; the least recently scored are dropped beyond this.
+const MAX_INDEXED_SESSIONS: usize = 100_000;
+
+/// Result order: last scored, then session id to break ties.
+type SearchKey = (DateTime<Utc>, String);
+
+/// What the index knows about one session, folded from every analyzed event.
+#[derive(Debug)]
+struct IndexedSession {
+    user_ids: HashSet<String>,
+    /// As stored, so pseudonymized when IP privacy is on.
+    ips: HashSet<String>,
+    /// Every reason code the session has hit.
+    codes: HashSet<Cow<'static, str>>,
+    /// Autonomous systems of the session's IPs, where resolved.
+    asns: HashSet<u32>,
+    latest_score: i32,
+    /// Whether any of the session's events was flagged.
+    flagged: bool,
+    first_event: DateTime<Utc>,
+    last_event: DateTime<Utc>,
+    scored_at: DateTime<Utc>,
+}
+
+#[derive(Default)]
+struct Index {
+    sessions: HashMap<String, IndexedSession>,
+    by_scored_at: BTreeSet<SearchKey>,
+    by_ip: HashMap<String, HashSet<String>>,
+    by_user: HashMap<String, HashSet<String>>,
+    by_code: HashMap<Cow<'static, str>, HashSet<String>>,
+    by_asn: HashMap<u32, HashSet<String>>,
+}
+
+fn unlink<K: Eq + Hash>(index: &mut HashMap<K, HashSet<String>>, key: &K, session_id: &str) {
+    if let Some(sessions) = index.get_mut(key) {
+        sessions.remove(session_id);
+        if sessions.is_empty() {
+            index.remove(key);
+        }
+    }
+}
+
+impl Index {
+    fn remove(&mut self, session_id: &str) {
+        let Some(session) = self.sessions.remove(session_id) else {
+            return;
+        };
+        self.by_scored_at.remove(&(session.scored_at, session_id.to_string()));
+        for ip in &session.ips {
+            unlink(&mut self.by_ip, ip, session_id);
+        }
+        for user_id in &session.user_ids {
+            unlink(&mut self.by_user, user_id, session_id);
+        }
+        for code in &session.codes {
+            unlink(&mut self.by_code, code, session_id);
+        }
+        for asn in &session.asns {
+            unlink(&mut self.by_asn, asn, session_id);
+        }
+    }
+}
+
+/// Secondary indexes from IP, user, reason code, and ASN to the sessions analyzed with them,
+/// so analysts can pivot from one bad actor to everything it touched. Sessions expire
+/// from the index on the event store's session TTL.
+#[derive(Default)]
+pub struct SearchIndex {
+    index: Mutex<Index>,
+}
+
+impl SearchIndex {
+    /// Folds the latest event of `history` and its result into the session's entry, and
+    /// returns the session's first and last event times. Only a session new to the index
+    /// has its history scanned; after that each event just widens the span, so it keeps
+    /// the earliest event seen even once the store has trimmed it.
+    pub fn record(&self, history: &[Arc<UserEvent>], result: &FraudCheckResult) -> (DateTime<Utc>, DateTime<Utc>) {
+        let event = history.last().expect("history ends with the recorded event");
+        let session_id = &result.session_id;
+
+        let mut index = self.index.lock().unwrap();
+        let (first_event, last_event) = match index.sessions.get(session_id) {
+            Some(session) => (session.first_event.min(event.timestamp), session.last_event.max(event.timestamp)),
+            None => {
+                let timestamps = history.iter().map(|event| event.timestamp);
+                (timestamps.clone().min().unwrap(), timestamps.max().unwrap())
+            }
+        };
+        let Index {
+            sessions,
+            by_scored_at,
+            by_ip,
+            by_user,
+            by_code,
+            by_asn,
+        } = &mut *index;
+        let session = sessions.entry(session_id.clone()).or_insert_with(|| IndexedSession {
+            user_ids: HashSet::new(),
+            ips: HashSet::new(),
+            codes: HashSet::new(),
+            asns: HashSet::new(),
+            latest_score: 0,
+            flagged: false,
+            first_event,
+            last_event,
+            scored_at: result.check_timestamp,
+        });
+        by_scored_at.remove(&(session.scored_at, session_id.clone()));
+        by_scored_at.insert((result.check_timestamp, session_id.clone()));
+
+        session.latest_score = result.fraud_score;
+        session.flagged |= result.flagged;
+        session.first_event = first_event;
+        session.last_event = last_event;
+        session.scored_at = result.check_timestamp;
+        if session.ips.insert(event.ip_address.clone()) {
+            by_ip.entry(event.ip_address.clone()).or_default().insert(session_id.clone());
+        }
+        if let Some(user_id) = &event.user_id {
+            if session.user_ids.insert(user_id.clone()) {
+                by_user.entry(user_id.clone()).or_default().insert(session_id.clone());
+            }
+        }
+        for hit in &result.breakdown {
+            if session.codes.insert(hit.code.clone()) {
+                by_code.entry(hit.code.clone()).or_default().insert(session_id.clone());
+            }
+        }
+        if let Some(asn) = event.asn {
+            if session.asns.insert(asn) {
+                by_asn.entry(asn).or_default().insert(session_id.clone());
+            }
+        }
+
+        while index.sessions.len() > MAX_INDEXED_SESSIONS {
+            let Some((_, oldest)) = index.by_scored_at.first().cloned() else {
+                break;
+            };
+            index.remove(&oldest);
+        }
+        (first_event, last_event)
+    }
+
+    /// Drops sessions last scored before `cutoff`.
+    pub fn sweep(&self, cutoff: DateTime<Utc>) {
+        let mut index = self.index.lock().unwrap();
+        while let Some((scored_at, session_id)) = index.by_scored_at.first().cloned() {
+            if scored_at >= cutoff {
+                break;
+            }
+            index.remove(&session_id);
+        }
+    }
+
+    /// Drops every session the user appeared in.
+    pub fn forget_user(&self, user_id: &str) {
+        let mut index = self.index.lock().unwrap();
+        let session_ids: Vec<String> = index.by_user.get(user_id).into_iter().flatten().cloned().collect();
+        for session_id in &session_ids {
+            index.remove(session_id);
+        }
+    }
+
+    /// Up to `limit` matching sessions scored before `before`, most recently scored first,
+    /// and the key to continue from when more match.
+    fn search(&self, filter: &SearchFilter, before: Option<&SearchKey>, limit: usize) -> (Vec<SessionMatch>, Option<SearchKey>) {
+        let index = self.index.lock().unwrap();
+
+        // Each given filter narrows the candidates; start from the smallest set.
+        let mut sets: Vec<Option<&HashSet<String>>> = Vec::new();
+        if let Some(ip) = &filter.ip {
+            sets.push(index.by_ip.get(ip));
+        }
+        if let Some(user_id) = &filter.user_id {
+            sets.push(index.by_user.get(user_id));
+        }
+        if let Some(reason) = &filter.reason {
+            sets.push(index.by_code.get(reason.as_str()));
+        }
+        if let Some(asn) = filter.asn {
+            sets.push(index.by_asn.get(&asn));
+        }
+        let Some(sets) = sets.into_iter().collect::<Option<Vec<_>>>() else {
+            return (Vec::new(), None);
+        };
+        let candidates: Vec<&String> = match sets.iter().min_by_key(|set| set.len()) {
+            Some(smallest) => smallest
+                .iter()
+                .filter(|session_id| sets.iter().all(|set| set.contains(*session_id)))
+                .collect(),
+            None => index.sessions.keys().collect(),
+        };
+
+        let mut matches: Vec<(SearchKey, &IndexedSession)> = candidates
+            .into_iter()
+            .filter_map(|session_id| {
+                let session = index.sessions.get(session_id)?;
+                let key = (session.scored_at, session_id.clone());
+                let wanted = (!filter.flagged_only || session.flagged)
+                    && filter.since.is_none_or(|since| session.last_event >= since)
+                    && before.is_none_or(|before| key < *before);
+                wanted.then_some((key, session))
+            })
+            .collect();
+        matches.sort_unstable_by(|(a, _), (b, _)| b.cmp(a));
+
+        let next = (matches.len() > limit).then(|| matches[limit - 1].0.clone());
+        let page = matches
+            .into_iter()
+            .take(limit)
+            .map(|((_, session_id), session)| SessionMatch::new(session_id, session))
+            .collect();
+        (page, next)
+    }
+
+    /// What the index holds for one session.
+    pub fn session(&self, session_id: &str) -> Option<SessionMatch> {
+        let index = self.index.lock().unwrap();
+        let session = index.sessions.get(session_id)?;
+        Some(SessionMatch::new(session_id.to_string(), session))
+    }
+}
+
+// --- SEARCH HANDLER ---
+/// Matches per page unless the request asks for fewer.
+const MAX_SEARCH_RESULTS: usize = 1_000;
+const DEFAULT_SEARCH_RESULTS: usize = 100;
+
+#[derive(Debug)]
+struct SearchFilter {
+    ip: Option<String>,
+    user_id: Option<String>,
+    reason: Option<String>,
+    asn: Option<u32>,
+    since: Option<DateTime<Utc>>,
+    flagged_only: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchParams {
+    ip: Option<String>,
+    user_id: Option<String>,
+    reason: Option<String>,
+    /// Sessions with an event from this autonomous system.
+    asn: Option<u32>,
+    /// Sessions with an event at or after this time.
+    since: Option<DateTime<Utc>>,
+    /// Defaults to `true`.
+    flagged_only: Option<bool>,
+    limit: Option<usize>,
+    /// `nextCursor` from the previous page.
+    cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionMatch {
+    pub session_id: String,
+    pub user_ids: Vec<String>,
+    pub latest_score: i32,
+    pub flagged: bool,
+    pub reason_codes: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub asns: Vec<u32>,
+    pub first_event: DateTime<Utc>,
+    pub last_event: DateTime<Utc>,
+    pub last_scored_at: DateTime<Utc>,
+}
+
+impl SessionMatch {
+    fn new(session_id: String, session: &IndexedSession) -> Self {
+        let mut user_ids: Vec<String> = session.user_ids.iter().cloned().collect();
+        user_ids.sort_unstable();
+        let mut reason_codes: Vec<String> = session.codes.iter().map(|code| code.to_string()).collect();
+        reason_codes.sort_unstable();
+        let mut asns: Vec<u32> = session.asns.iter().copied().collect();
+        asns.sort_unstable();
+        SessionMatch {
+            session_id,
+            user_ids,
+            latest_score: session.latest_score,
+            flagged: session.flagged,
+            reason_codes,
+            asns,
+            first_event: session.first_event,
+            last_event: session.last_event,
+            last_scored_at: session.scored_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResponse {
+    sessions: Vec<SessionMatch>,
+    next_cursor: Option<String>,
+}
+
+/// Sessions matching every given filter, most recently scored first. The `ip` filter
+/// takes a real address and is matched after the IP privacy transform, like stored IPs.
+pub async fn search_handler(
+    State(state): State<Arc<AppState>>,
+    AppQuery(params): AppQuery<SearchParams>,
+) -> Result<Json<SearchResponse>, AppError> {
+    let before = match &params.cursor {
+        Some(cursor) => Some(decode_cursor(cursor).ok_or(AppError::BadRequest("Invalid cursor".to_string()))?),
+        None => None,
+    };
+    let ip = match params.ip {
+        Some(ip) => {
+            let addr = ip::parse(&ip).ok_or_else(|| AppError::Validation(format!("invalid IP address `{}`", ip)))?;
+            Some(state.service.ip_privacy.apply(&addr.to_string()))
+        }
+        None => None,
+    };
+    let filter = SearchFilter {
+        ip,
+        user_id: params.user_id,
+        reason: params.reason,
+        asn: params.asn,
+        since: params.since,
+        flagged_only: params.flagged_only.unwrap_or(true),
+    };
+    let limit = params.limit.unwrap_or(DEFAULT_SEARCH_RESULTS).clamp(1, MAX_SEARCH_RESULTS);
+
+    let (sessions, next) = state.search.search(&filter, before.as_ref(), limit);
+    Ok(Json(SearchResponse {
+        sessions,
+        next_cursor: next.as_ref().map(encode_cursor),
+    }))
+}
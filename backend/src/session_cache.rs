@@ -0,0 +1,179 @@
+use metrics::counter;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::blacklist::Blacklist;
+use crate::geoip::{GeoInfo, GeoIp};
+
+// --- SESSION CACHE ---
+/// Sessions held at once; facts for sessions beyond this are worked out but not kept.
+const MAX_CACHED_SESSIONS: usize = 100_000;
+/// Distinct IPs or user agents kept per session, so one rotating them can't grow its entry.
+const MAX_FACTS_PER_SESSION: usize = 32;
+
+/// A fact and when it was worked out.
+struct Cached<T> {
+    value: T,
+    at: Instant,
+}
+
+#[derive(Default)]
+struct CachedSession {
+    /// Blacklist membership per IP, with the blacklist version it was checked against.
+    blacklisted: HashMap<String, Cached<(bool, u64)>>,
+    geo: HashMap<String, Cached<Option<GeoInfo>>>,
+    /// The automation signature each user agent matched, with the config version it was
+    /// matched under.
+    user_agents: HashMap<String, Cached<(Option<String>, String)>>,
+    touched: Option<Instant>,
+}
+
+/// Facts about a session's IPs and user agents that don't change from one event to the
+/// next, so each is worked out once per session and TTL rather than once per event.
+/// Blacklist answers are also dropped whenever the blacklist changes.
+pub struct SessionCache {
+    sessions: Mutex<HashMap<String, CachedSession>>,
+    ttl: Duration,
+}
+
+/// Looks `key` up in `facts`, or computes it and keeps the answer while the map has room.
+/// `fresh` says whether a cached value still holds.
+fn get_or_insert<T: Clone>(
+    facts: &mut HashMap<String, Cached<T>>,
+    key: &str,
+    ttl: Duration,
+    fact: &'static str,
+    fresh: impl Fn(&T) -> bool,
+    compute: impl FnOnce() -> T,
+) -> T {
+    if let Some(cached) = facts.get(key) {
+        if cached.at.elapsed() < ttl && fresh(&cached.value) {
+            counter!("session_cache_hits_total", "fact" => fact).increment(1);
+            return cached.value.clone();
+        }
+    }
+    counter!("session_cache_misses_total", "fact" => fact).increment(1);
+    let value = compute();
+    if facts.len() < MAX_FACTS_PER_SESSION || facts.contains_key(key) {
+        let cached = Cached {
+            value: value.clone(),
+            at: Instant::now(),
+        };
+        facts.insert(key.to_string(), cached);
+    }
+    value
+}
+
+impl SessionCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    /// Runs `f` on the session's entry, or on a throwaway one when the cache is full.
+    fn with_session<T>(&self, session_id: &str, f: impl FnOnce(&mut CachedSession) -> T) -> T {
+        let mut sessions = self.sessions.lock().unwrap();
+        if !sessions.contains_key(session_id) && sessions.len() >= MAX_CACHED_SESSIONS {
+            return f(&mut CachedSession::default());
+        }
+        let session = sessions.entry(session_id.to_string()).or_default();
+        session.touched = Some(Instant::now());
+        f(session)
+    }
+
+    /// Drops sessions with no new event for a full TTL.
+    pub fn sweep(&self) {
+        let ttl = self.ttl;
+        self.sessions
+            .lock()
+            .unwrap()
+            .retain(|_, session| session.touched.is_some_and(|touched| touched.elapsed() < ttl));
+    }
+
+    pub fn forget(&self, session_id: &str) {
+        self.sessions.lock().unwrap().remove(session_id);
+    }
+}
+
+/// One session's view of the cache, for the lookups and rules scoring its events. Without
+/// a cache (explain and simulation runs, which keep no per-session state, or with
+/// `SESSION_CACHE_TTL_SECS=0`) every fact is worked out afresh.
+pub struct SessionContext<'a> {
+    cache: Option<&'a SessionCache>,
+    session_id: &'a str,
+    blacklist: &'a Blacklist,
+    geoip: Option<&'a GeoIp>,
+}
+
+impl<'a> SessionContext<'a> {
+    pub fn new(
+        cache: Option<&'a SessionCache>,
+        session_id: &'a str,
+        blacklist: &'a Blacklist,
+        geoip: Option<&'a GeoIp>,
+    ) -> Self {
+        Self {
+            cache,
+            session_id,
+            blacklist,
+            geoip,
+        }
+    }
+
+    pub fn blacklisted(&self, ip: &str) -> bool {
+        let Some(cache) = self.cache else {
+            return self.blacklist.contains(ip);
+        };
+        let version = self.blacklist.version();
+        let (blacklisted, _) = cache.with_session(self.session_id, |session| {
+            get_or_insert(
+                &mut session.blacklisted,
+                ip,
+                cache.ttl,
+                "blacklist",
+                |(_, checked)| *checked == version,
+                || (self.blacklist.contains(ip), version),
+            )
+        });
+        blacklisted
+    }
+
+    pub fn geo(&self, ip: &str) -> Option<GeoInfo> {
+        let geoip = self.geoip?;
+        let Some(cache) = self.cache else {
+            return geoip.lookup(ip);
+        };
+        cache.with_session(self.session_id, |session| {
+            get_or_insert(&mut session.geo, ip, cache.ttl, "geo", |_| true, || geoip.lookup(ip))
+        })
+    }
+
+    /// The first of `signatures` that `user_agent` contains, ignoring case. `config_version`
+    /// keys the answer, so a different signature list never reuses it.
+    pub fn automation_signature(&self, user_agent: &str, signatures: &[String], config_version: &str) -> Option<String> {
+        let matched = || {
+            let lowered = user_agent.to_lowercase();
+            signatures
+                .iter()
+                .find(|signature| lowered.contains(&signature.to_lowercase()))
+                .cloned()
+        };
+        let Some(cache) = self.cache else {
+            return matched();
+        };
+        let (signature, _) = cache.with_session(self.session_id, |session| {
+            get_or_insert(
+                &mut session.user_agents,
+                user_agent,
+                cache.ttl,
+                "user_agent",
+                |(_, version)| version == config_version,
+                || (matched(), config_version.to_string()),
+            )
+        });
+        signature
+    }
+}
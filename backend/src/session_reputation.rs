@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Tracker size above which faded sessions are swept on insert.
+const SWEEP_THRESHOLD: usize = 100_000;
+/// Reputations decayed below this are treated as gone.
+const FADED: f64 = 1.0;
+
+// --- SESSION REPUTATION ---
+/// Each session's accumulated event scores, halving every `half_life`. Values are
+/// stored with their last update and decayed when read, so idle sessions cost nothing.
+#[derive(Default)]
+pub struct SessionReputationTracker {
+    sessions: Mutex<HashMap<String, (f64, Instant)>>,
+}
+
+fn decayed(value: f64, since: Instant, now: Instant, half_life: Duration) -> f64 {
+    value * 0.5f64.powf(now.duration_since(since).as_secs_f64() / half_life.as_secs_f64())
+}
+
+impl SessionReputationTracker {
+    /// The session's reputation as of now.
+    pub fn current(&self, session_id: &str, half_life: Duration) -> f64 {
+        match self.sessions.lock().unwrap().get(session_id) {
+            Some((value, updated_at)) => decayed(*value, *updated_at, Instant::now(), half_life),
+            None => 0.0,
+        }
+    }
+
+    /// Adds an event's score to the decayed reputation.
+    pub fn add(&self, session_id: &str, points: f64, half_life: Duration) {
+        let now = Instant::now();
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.len() >= SWEEP_THRESHOLD {
+            sessions.retain(|_, (value, updated_at)| decayed(*value, *updated_at, now, half_life) >= FADED);
+        }
+
+        let entry = sessions.entry(session_id.to_string()).or_insert((0.0, now));
+        *entry = (decayed(entry.0, entry.1, now, half_life) + points, now);
+    }
+
+    /// Drops the session's reputation, returning whether it had one.
+    pub fn forget(&self, session_id: &str) -> bool {
+        self.sessions.lock().unwrap().remove(session_id).is_some()
+    }
+
+    /// Forgets sessions whose reputation has faded.
+    pub fn sweep(&self, half_life: Duration) {
+        let now = Instant::now();
+        self.sessions
+            .lock()
+            .unwrap()
+            .retain(|_, (value, updated_at)| decayed(*value, *updated_at, now, half_life) >= FADED);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HALF_LIFE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn reputation_halves_every_half_life() {
+        let since = Instant::now();
+        assert_eq!(decayed(80.0, since, since, HALF_LIFE), 80.0);
+        assert_eq!(decayed(80.0, since, since + HALF_LIFE, HALF_LIFE), 40.0);
+        assert_eq!(decayed(80.0, since, since + HALF_LIFE * 3, HALF_LIFE), 10.0);
+    }
+
+    #[test]
+    fn scores_accumulate_per_session() {
+        let tracker = SessionReputationTracker::default();
+        for _ in 0..3 {
+            tracker.add("s1", 20.0, HALF_LIFE);
+        }
+        tracker.add("s2", 5.0, HALF_LIFE);
+        assert!((tracker.current("s1", HALF_LIFE) - 60.0).abs() < 0.1);
+        assert!((tracker.current("s2", HALF_LIFE) - 5.0).abs() < 0.1);
+        assert_eq!(tracker.current("unseen", HALF_LIFE), 0.0);
+    }
+
+    #[test]
+    fn sweeping_forgets_faded_sessions_only() {
+        let tracker = SessionReputationTracker::default();
+        tracker.add("faded", 0.5, HALF_LIFE);
+        tracker.add("kept", 20.0, HALF_LIFE);
+        tracker.sweep(HALF_LIFE);
+        let sessions = tracker.sessions.lock().unwrap();
+        assert!(!sessions.contains_key("faded"));
+        assert!(sessions.contains_key("kept"));
+    }
+
+    #[test]
+    fn a_forgotten_session_starts_over() {
+        let tracker = SessionReputationTracker::default();
+        tracker.add("s1", 20.0, HALF_LIFE);
+        assert!(tracker.forget("s1"));
+        assert!(!tracker.forget("s1"));
+        assert_eq!(tracker.current("s1", HALF_LIFE), 0.0);
+    }
+}
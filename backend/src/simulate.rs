@@ -0,0 +1,533 @@
+use axum::{extract::State, http::HeaderMap, response::Response};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use crate::activity::ActivityProfile;
+use crate::config::{Decision, ScoringConfig};
+use crate::entity_reputation::{Entity, EntityScores, StoredReputation};
+use crate::ip;
+use crate::linked_sessions::LinkedSessionTracker;
+use crate::metadata_limits;
+use crate::msgpack::{self, AppBody};
+use crate::payments::{self, PaymentTracker};
+use crate::rates::{RateTracker, Rates};
+use crate::rollout;
+use crate::geoip::GeoInfo;
+use crate::rules::{Analysis, RuleContext, RuleEngine};
+use crate::stats::{self, ScoreBucket};
+use crate::{validate_metadata, AppError, AppState, UserEvent};
+
+// --- SCORING SIMULATION ---
+/// Events one simulation may score.
+pub const MAX_SIMULATION_EVENTS: usize = 10_000;
+/// Simulation datasets are far larger than single events.
+pub const MAX_SIMULATION_BYTES: usize = 8 * 1024 * 1024;
+
+#[derive(Debug, Deserialize)]
+pub struct SimulateRequest {
+    events: Vec<UserEvent>,
+    /// A proposed scoring config, in the `SCORING_CONFIG_PATH` format: fields it leaves
+    /// out take their defaults, not the live values. The live config when omitted.
+    config: Option<Value>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulationReport {
+    events: usize,
+    /// Events the live endpoint would have turned away: metadata over the limits, or
+    /// failing a `reject`-mode metadata schema.
+    rejected: usize,
+    allowlisted: usize,
+    flagged: usize,
+    /// Scores of every event that ran through the rules.
+    score_distribution: Vec<ScoreBucket>,
+    /// Hits per reason code.
+    rule_hits: BTreeMap<String, u64>,
+    /// Events per decision, allowlisted ones included.
+    decisions: BTreeMap<Decision, u64>,
+}
+
+/// What the live service keeps per user and session besides history, rebuilt from the
+/// dataset alone and aged by event timestamps rather than the clock.
+#[derive(Default)]
+struct Replay {
+    flags: HashMap<String, Vec<DateTime<Utc>>>,
+    payments: PaymentTracker,
+    rates: RateTracker,
+    reputations: HashMap<String, (f64, DateTime<Utc>)>,
+    entity_reputations: HashMap<(Entity, String), StoredReputation>,
+    linked_sessions: LinkedSessionTracker,
+    fingerprints: LinkedSessionTracker,
+    activity: HashMap<String, ActivityProfile>,
+}
+
+impl Replay {
+    fn prior_flags(&self, event: &UserEvent, config: &ScoringConfig) -> u32 {
+        let ttl = Duration::seconds(config.repeat_offender.ttl_secs as i64);
+        let Some(flags) = event.user_id.as_ref().and_then(|user_id| self.flags.get(user_id)) else {
+            return 0;
+        };
+        flags.iter().filter(|at| **at > event.timestamp - ttl).count() as u32
+    }
+
+    fn linked_sessions(&self, event: &UserEvent, config: &ScoringConfig) -> Vec<String> {
+        let Some(user_id) = &event.user_id else {
+            return Vec::new();
+        };
+        self.linked_sessions
+            .active_sessions(user_id, &event.session_id, event.timestamp, config.concurrent_sessions.window())
+    }
+
+    fn fingerprint_sessions(&self, event: &UserEvent, config: &ScoringConfig) -> Vec<String> {
+        let Some(fingerprint) = event.fingerprint() else {
+            return Vec::new();
+        };
+        self.fingerprints
+            .active_sessions(fingerprint, &event.session_id, event.timestamp, config.device_fingerprint.window())
+    }
+
+    fn reputation(&self, session_id: &str, at: DateTime<Utc>, config: &ScoringConfig) -> f64 {
+        let Some(half_life) = config.session_reputation.half_life() else {
+            return 0.0;
+        };
+        match self.reputations.get(session_id) {
+            Some((value, updated_at)) => {
+                let elapsed = (at - *updated_at).to_std().unwrap_or_default();
+                value * 0.5f64.powf(elapsed.as_secs_f64() / half_life.as_secs_f64())
+            }
+            None => 0.0,
+        }
+    }
+
+    fn reputation_entities(event: &UserEvent, config: &ScoringConfig) -> Vec<(Entity, String)> {
+        let ip = ip::counter_key(&event.ip_address, config.ipv6_counter_prefix).into_owned();
+        let mut entities = vec![(Entity::Ip, ip)];
+        if let Some(user_id) = &event.user_id {
+            entities.push((Entity::User, user_id.clone()));
+        }
+        entities
+    }
+
+    fn entity_reputation(&self, event: &UserEvent, config: &ScoringConfig) -> EntityScores {
+        let mut scores = EntityScores::default();
+        let Some(half_life) = config.entity_reputation.half_life() else {
+            return scores;
+        };
+        for (entity, id) in Self::reputation_entities(event, config) {
+            let score = self
+                .entity_reputations
+                .get(&(entity, id))
+                .map_or(0.0, |stored| stored.at(event.timestamp, half_life));
+            match entity {
+                Entity::User => scores.user = score,
+                Entity::Ip => scores.ip = score,
+            }
+        }
+        scores
+    }
+
+    fn record_entity_flag(&mut self, event: &UserEvent, config: &ScoringConfig) {
+        let Some(half_life) = config.entity_reputation.half_life() else {
+            return;
+        };
+        for key in Self::reputation_entities(event, config) {
+            let stored = self.entity_reputations.entry(key).or_insert(StoredReputation {
+                score: 0.0,
+                updated_at: event.timestamp,
+            });
+            *stored = StoredReputation {
+                score: stored.at(event.timestamp, half_life) + config.entity_reputation.flag_points,
+                updated_at: event.timestamp,
+            };
+        }
+    }
+
+    fn payment_window_total(&self, event: &UserEvent, config: &ScoringConfig) -> f64 {
+        let (Some(user_id), Some(payment)) = (&event.user_id, payments::parse_payment(event)) else {
+            return 0.0;
+        };
+        let window = Duration::seconds(config.payments.window_secs as i64);
+        self.payments
+            .window_total(user_id, payment.currency.unwrap_or_default(), event.timestamp, window)
+    }
+
+    /// The `high_frequency` rates for the last event of `history`, counting it. `ip` is
+    /// the event's IP as the store would hold it.
+    fn rates(&self, history: &[Arc<UserEvent>], ip: &str, config: &ScoringConfig) -> Rates {
+        let window_secs = config.high_frequency.window_secs;
+        let Some((event, prior)) = history.split_last().filter(|_| window_secs > 0) else {
+            return Rates::default();
+        };
+        let ip = ip::counter_key(ip, config.ipv6_counter_prefix);
+        self.rates.observe(event, prior, &ip, window_secs)
+    }
+
+    fn record_payment(&self, event: &UserEvent, config: &ScoringConfig) {
+        let (Some(user_id), Some(payment)) = (&event.user_id, payments::parse_payment(event)) else {
+            return;
+        };
+        if let Ok(amount) = payment.amount {
+            let window = Duration::seconds(config.payments.window_secs as i64);
+            self.payments
+                .record(user_id, payment.currency.unwrap_or_default(), event.timestamp, amount, window);
+        }
+    }
+}
+
+/// An event as the live endpoint would have it before scoring. Nothing here depends on
+/// the scoring config, so comparisons work it out once for both configs.
+struct PreparedEvent {
+    /// With all its metadata, as the rules see it.
+    event: Arc<UserEvent>,
+    /// What the store would hold, for later events' histories.
+    stored: Arc<UserEvent>,
+    client_ip: String,
+    geo: Option<GeoInfo>,
+    allowlisted: bool,
+}
+
+/// The dataset in timestamp order, each event held to the metadata limits and resolved
+/// against the live allowlist and GeoIP data. `None` for events over the limits.
+fn prepare(state: &AppState, mut events: Vec<UserEvent>) -> Vec<Option<PreparedEvent>> {
+    events.sort_by_key(|event| event.timestamp);
+    let service = &state.service;
+    events
+        .into_iter()
+        .map(|mut event| {
+            metadata_limits::enforce(&mut event, &service.metadata_limits, &service.metadata_retention).ok()?;
+            let client_ip = event.ip_address.clone();
+            event.resolve_asn(state, &client_ip);
+            let stored = service.metadata_retention.stored_copy(&event);
+            let event = Arc::new(event);
+            Some(PreparedEvent {
+                stored: stored.map_or_else(|| event.clone(), Arc::new),
+                geo: state.geo(&client_ip),
+                allowlisted: state.allowlist.matches(&client_ip, event.user_id.as_deref()).is_some(),
+                event,
+                client_ip,
+            })
+        })
+        .collect()
+}
+
+/// What happened to one event of a simulation.
+enum Outcome {
+    /// Turned away: over the metadata limits, or failing a `reject`-mode schema.
+    Rejected,
+    Allowlisted,
+    Scored(Analysis),
+}
+
+/// Scores the prepared events in order as if they arrived at the live endpoint, against
+/// `config` and the live blacklist. Histories, flags, payments, session and entity
+/// reputations, linked sessions, fingerprints, and activity profiles come from the
+/// dataset alone, and nothing is stored, counted, or audited. IP reputation and the
+/// model aren't consulted, so their rules don't fire.
+fn replay(state: &AppState, config: &ScoringConfig, engine: &RuleEngine, events: &[Option<PreparedEvent>]) -> Vec<Outcome> {
+    let service = &state.service;
+    let mut sessions: HashMap<String, Vec<Arc<UserEvent>>> = HashMap::new();
+    let mut replay = Replay::default();
+    let mut outcomes = Vec::with_capacity(events.len());
+
+    for prepared in events {
+        let Some(prepared) = prepared.as_ref().filter(|prepared| validate_metadata(&prepared.event, config).is_ok()) else {
+            outcomes.push(Outcome::Rejected);
+            continue;
+        };
+
+        let event = &prepared.event;
+        let history = sessions.entry(event.session_id.clone()).or_default();
+        history.push(event.clone());
+        if history.len() > service.max_events_per_session {
+            history.remove(0);
+        }
+
+        if !prepared.allowlisted {
+            let linked_sessions = replay.linked_sessions(event, config);
+            let fingerprint_sessions = replay.fingerprint_sessions(event, config);
+            let shadow_rules = rollout::shadowed(&config.rollouts, event);
+            let rates = replay.rates(history, &service.ip_privacy.apply(&prepared.client_ip), config);
+            let analysis = engine.evaluate(&RuleContext {
+                event,
+                history,
+                client_ip: &prepared.client_ip,
+                observed_ip: None,
+                session: state.uncached_session_context(&event.session_id),
+                suspicious_sources: &state.suspicious_sources,
+                disposable_domains: &state.disposable_domains,
+                config,
+                ip_reputation: None,
+                prior_flags: replay.prior_flags(event, config),
+                linked_sessions: &linked_sessions,
+                fingerprint_sessions: &fingerprint_sessions,
+                activity: event.user_id.as_ref().and_then(|user_id| replay.activity.get(user_id)),
+                geo: prepared.geo.clone(),
+                payment_window_total: replay.payment_window_total(event, config),
+                rates: &rates,
+                model_probability: None,
+                session_reputation: replay.reputation(&event.session_id, event.timestamp, config),
+                entity_reputation: replay.entity_reputation(event, config),
+                unavailable: &[],
+                shadow_rules: &shadow_rules,
+            });
+
+            replay.record_payment(event, config);
+            if let Some(user_id) = &event.user_id {
+                let window = config.concurrent_sessions.window();
+                replay.linked_sessions.record(user_id, &event.session_id, event.timestamp, window);
+            }
+            if let Some(fingerprint) = event.fingerprint() {
+                let window = config.device_fingerprint.window();
+                replay.fingerprints.record(fingerprint, &event.session_id, event.timestamp, window);
+            }
+            if let Some(reputation) = analysis.session_reputation {
+                replay
+                    .reputations
+                    .insert(event.session_id.clone(), (reputation, event.timestamp));
+            }
+            if analysis.flagged {
+                if let Some(user_id) = &event.user_id {
+                    replay.flags.entry(user_id.clone()).or_default().push(event.timestamp);
+                }
+                replay.record_entity_flag(event, config);
+            }
+            outcomes.push(Outcome::Scored(analysis));
+        } else {
+            outcomes.push(Outcome::Allowlisted);
+        }
+        if let Some(user_id) = &event.user_id {
+            replay.activity.entry(user_id.clone()).or_default().record(event.timestamp);
+        }
+
+        // Later events see what the store would hold, not the full metadata.
+        *history.last_mut().unwrap() = prepared.stored.clone();
+    }
+    outcomes
+}
+
+/// Adds up a simulation's outcomes.
+fn summarize(outcomes: &[Outcome]) -> SimulationReport {
+    let mut report = SimulationReport {
+        events: outcomes.len(),
+        rejected: 0,
+        allowlisted: 0,
+        flagged: 0,
+        score_distribution: Vec::new(),
+        rule_hits: BTreeMap::new(),
+        decisions: Decision::ALL.into_iter().map(|decision| (decision, 0)).collect(),
+    };
+    let mut scores = Vec::new();
+    for outcome in outcomes {
+        match outcome {
+            Outcome::Rejected => report.rejected += 1,
+            Outcome::Allowlisted => {
+                report.allowlisted += 1;
+                *report.decisions.entry(Decision::Allow).or_default() += 1;
+            }
+            Outcome::Scored(analysis) => {
+                for hit in &analysis.hits {
+                    *report.rule_hits.entry(hit.code.to_string()).or_default() += 1;
+                }
+                scores.push(analysis.score);
+                *report.decisions.entry(analysis.decision).or_default() += 1;
+                report.flagged += usize::from(analysis.flagged);
+            }
+        }
+    }
+    report.score_distribution = stats::distribute(scores);
+    report
+}
+
+/// Scores `events` in timestamp order against `config` and adds up the results; see
+/// `replay` for what the simulation does and doesn't see.
+pub fn simulate(state: &AppState, config: &ScoringConfig, engine: &RuleEngine, events: Vec<UserEvent>) -> SimulationReport {
+    summarize(&replay(state, config, engine, &prepare(state, events)))
+}
+
+/// A proposed config from a request, checked like a config file.
+fn proposed_config(config: Value) -> Result<(Arc<ScoringConfig>, Arc<RuleEngine>), AppError> {
+    let config: ScoringConfig =
+        serde_json::from_value(config).map_err(|e| AppError::Validation(format!("Invalid scoring config: {}", e)))?;
+    config
+        .validate()
+        .map_err(|e| AppError::Validation(format!("Invalid scoring config: {}", e)))?;
+    let engine = RuleEngine::new(&config).map_err(|e| AppError::Validation(format!("Invalid scoring config: {}", e)))?;
+    Ok((Arc::new(config), Arc::new(engine)))
+}
+
+/// The proposed config, or the live one when there is none.
+fn config_or_live(state: &AppState, config: Option<Value>) -> Result<(Arc<ScoringConfig>, Arc<RuleEngine>), AppError> {
+    match config {
+        Some(config) => proposed_config(config),
+        None => {
+            let live = state.scoring_snapshot();
+            Ok((live.config.clone(), live.engine.clone()))
+        }
+    }
+}
+
+fn check_size(events: &[UserEvent]) -> Result<(), AppError> {
+    if events.len() > MAX_SIMULATION_EVENTS {
+        return Err(AppError::Validation(format!(
+            "Simulations take at most {} events, got {}",
+            MAX_SIMULATION_EVENTS,
+            events.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Runs a dataset through a proposed config, for tuning thresholds and weights offline.
+pub async fn simulate_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    AppBody(request): AppBody<SimulateRequest>,
+) -> Result<Response, AppError> {
+    check_size(&request.events)?;
+    let (config, engine) = config_or_live(&state, request.config)?;
+
+    // Thousands of events are a lot of CPU for one request; keep it off the runtime.
+    let report = tokio::task::spawn_blocking(move || simulate(&state, &config, &engine, request.events))
+        .await
+        .map_err(|e| AppError::Internal(format!("Simulation failed: {}", e)))?;
+    msgpack::respond(&headers, report)
+}
+
+// --- CONFIG COMPARISON ---
+#[derive(Debug, Deserialize)]
+pub struct DiffRequest {
+    events: Vec<UserEvent>,
+    /// The config to compare against, in the `SCORING_CONFIG_PATH` format. The live
+    /// config when omitted.
+    base: Option<Value>,
+    candidate: Value,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffReport {
+    events: usize,
+    /// Sessions with an event flagged under one config, the other, or both, sorted.
+    flagged_sessions: FlaggedSessions,
+    /// Hits per reason code under each config, for codes that hit under either.
+    rule_hits: BTreeMap<String, HitDelta>,
+    /// How events scored under both configs moved, candidate minus base.
+    score_deltas: ScoreDeltas,
+    base: SimulationReport,
+    candidate: SimulationReport,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlaggedSessions {
+    base_only: Vec<String>,
+    candidate_only: Vec<String>,
+    both: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct HitDelta {
+    base: u64,
+    candidate: u64,
+    delta: i64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ScoreDeltas {
+    increased: usize,
+    decreased: usize,
+    unchanged: usize,
+    distribution: Vec<ScoreBucket>,
+}
+
+/// Replays one prepared dataset under both configs, each with its own replayed state,
+/// and compares them event by event.
+fn compare(
+    state: &AppState,
+    base: (&ScoringConfig, &RuleEngine),
+    candidate: (&ScoringConfig, &RuleEngine),
+    events: Vec<UserEvent>,
+) -> DiffReport {
+    let prepared = prepare(state, events);
+    let base_outcomes = replay(state, base.0, base.1, &prepared);
+    let candidate_outcomes = replay(state, candidate.0, candidate.1, &prepared);
+
+    let mut flagged: BTreeMap<&str, (bool, bool)> = BTreeMap::new();
+    let mut deltas = Vec::new();
+    for ((prepared, base), candidate) in prepared.iter().zip(&base_outcomes).zip(&candidate_outcomes) {
+        let flagged_under = |outcome: &Outcome| matches!(outcome, Outcome::Scored(analysis) if analysis.flagged);
+        if let Some(prepared) = prepared.as_ref().filter(|_| flagged_under(base) || flagged_under(candidate)) {
+            let sides = flagged.entry(prepared.event.session_id.as_str()).or_default();
+            sides.0 |= flagged_under(base);
+            sides.1 |= flagged_under(candidate);
+        }
+        if let (Outcome::Scored(base), Outcome::Scored(candidate)) = (base, candidate) {
+            deltas.push(candidate.score - base.score);
+        }
+    }
+
+    let mut flagged_sessions = FlaggedSessions::default();
+    for (session_id, sides) in flagged {
+        let list = match sides {
+            (true, true) => &mut flagged_sessions.both,
+            (true, false) => &mut flagged_sessions.base_only,
+            _ => &mut flagged_sessions.candidate_only,
+        };
+        list.push(session_id.to_string());
+    }
+
+    let base = summarize(&base_outcomes);
+    let candidate = summarize(&candidate_outcomes);
+    let mut rule_hits: BTreeMap<String, HitDelta> = BTreeMap::new();
+    for (code, hits) in &base.rule_hits {
+        rule_hits.entry(code.clone()).or_default().base = *hits;
+    }
+    for (code, hits) in &candidate.rule_hits {
+        rule_hits.entry(code.clone()).or_default().candidate = *hits;
+    }
+    for hits in rule_hits.values_mut() {
+        hits.delta = hits.candidate as i64 - hits.base as i64;
+    }
+
+    DiffReport {
+        events: prepared.len(),
+        flagged_sessions,
+        rule_hits,
+        score_deltas: ScoreDeltas {
+            increased: deltas.iter().filter(|delta| **delta > 0).count(),
+            decreased: deltas.iter().filter(|delta| **delta < 0).count(),
+            unchanged: deltas.iter().filter(|delta| **delta == 0).count(),
+            distribution: stats::distribute_deltas(deltas),
+        },
+        base,
+        candidate,
+    }
+}
+
+/// Runs a dataset through two configs and reports what changes between them, for
+/// reviewing a proposed change before deploying it.
+pub async fn diff_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    AppBody(request): AppBody<DiffRequest>,
+) -> Result<Response, AppError> {
+    check_size(&request.events)?;
+    let (base_config, base_engine) = config_or_live(&state, request.base)?;
+    let (candidate_config, candidate_engine) = proposed_config(request.candidate)?;
+
+    let report = tokio::task::spawn_blocking(move || {
+        compare(
+            &state,
+            (&base_config, &base_engine),
+            (&candidate_config, &candidate_engine),
+            request.events,
+        )
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Simulation failed: {}", e)))?;
+    msgpack::respond(&headers, report)
+}
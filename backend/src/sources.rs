@@ -0,0 +1,158 @@
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::blacklist::{self, Blacklist, ImportParams, ImportSummary, SyncStatus};
+use crate::config::SourceListConfig;
+use crate::error::AppQuery;
+use crate::{AppError, AppState};
+
+// --- SUSPICIOUS SOURCES ---
+/// Kinds of IP that are worth extra points but not a block. Each has its own list,
+/// reason code, and weight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceCategory {
+    TorExit,
+    OpenProxy,
+}
+
+impl SourceCategory {
+    pub const ALL: [SourceCategory; 2] = [SourceCategory::TorExit, SourceCategory::OpenProxy];
+
+    /// The name used in URLs and the scoring config.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SourceCategory::TorExit => "tor_exit",
+            SourceCategory::OpenProxy => "open_proxy",
+        }
+    }
+
+    pub fn code(self) -> &'static str {
+        match self {
+            SourceCategory::TorExit => "TOR_EXIT",
+            SourceCategory::OpenProxy => "OPEN_PROXY",
+        }
+    }
+
+    /// Prefix of the category's environment variables.
+    pub fn env_prefix(self) -> &'static str {
+        self.code()
+    }
+
+    pub fn reason(self) -> &'static str {
+        match self {
+            SourceCategory::TorExit => "IP is a known Tor exit node",
+            SourceCategory::OpenProxy => "IP is a known open proxy",
+        }
+    }
+
+    /// The list's name in logs.
+    pub fn label(self) -> &'static str {
+        match self {
+            SourceCategory::TorExit => "Tor exit list",
+            SourceCategory::OpenProxy => "Open proxy list",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|category| category.as_str() == name)
+    }
+}
+
+/// One IP list per category, each loaded, synced, imported, and exported on its own
+/// like the blacklist.
+pub struct SuspiciousSources {
+    lists: Vec<(SourceCategory, Arc<Blacklist>)>,
+}
+
+impl SuspiciousSources {
+    /// Loads each category's file, when it has one. Lists without one start empty.
+    pub fn load(configs: &[(SourceCategory, SourceListConfig)], false_positive_rate: f64) -> Result<Self, String> {
+        let lists = SourceCategory::ALL
+            .into_iter()
+            .map(|category| {
+                let path = configs
+                    .iter()
+                    .find(|(configured, _)| *configured == category)
+                    .and_then(|(_, config)| config.path.as_ref());
+                let list = match path {
+                    Some(path) => Blacklist::load_file(path, false_positive_rate)
+                        .map_err(|e| format!("{}: {}", path.display(), e))?,
+                    None => Blacklist::new(Vec::new(), false_positive_rate),
+                };
+                Ok((category, Arc::new(list)))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self { lists })
+    }
+
+    pub fn list(&self, category: SourceCategory) -> &Arc<Blacklist> {
+        let (_, list) = self.lists.iter().find(|(listed, _)| *listed == category).unwrap();
+        list
+    }
+
+    /// Every category whose list has `ip`.
+    pub fn matches<'a>(&'a self, ip: &'a str) -> impl Iterator<Item = SourceCategory> + 'a {
+        self.lists
+            .iter()
+            .filter(move |(_, list)| list.contains(ip))
+            .map(|(category, _)| *category)
+    }
+}
+
+// --- API ENDPOINT HANDLERS ---
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceListSummary {
+    category: SourceCategory,
+    code: &'static str,
+    entries: usize,
+    sync: Option<SyncStatus>,
+}
+
+fn category(name: &str) -> Result<SourceCategory, AppError> {
+    SourceCategory::from_name(name).ok_or_else(|| {
+        let names: Vec<&str> = SourceCategory::ALL.into_iter().map(SourceCategory::as_str).collect();
+        AppError::NotFound(format!("Unknown source list; expected one of {}", names.join(", ")))
+    })
+}
+
+/// Each list's size and last sync, for the listing and the readiness endpoint.
+pub fn summaries(sources: &SuspiciousSources) -> Vec<SourceListSummary> {
+    sources
+        .lists
+        .iter()
+        .map(|(category, list)| SourceListSummary {
+            category: *category,
+            code: category.code(),
+            entries: list.len(),
+            sync: list.sync_status(),
+        })
+        .collect()
+}
+
+pub async fn list_handler(State(state): State<Arc<AppState>>) -> Json<Vec<SourceListSummary>> {
+    Json(summaries(&state.suspicious_sources))
+}
+
+/// Takes the same body and `replace` flag as the blacklist import.
+pub async fn import_handler(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    AppQuery(params): AppQuery<ImportParams>,
+    body: String,
+) -> Result<Json<ImportSummary>, AppError> {
+    let category = category(&name)?;
+    let list = state.suspicious_sources.list(category);
+    Ok(Json(blacklist::import(list, category.label(), &body, &params)))
+}
+
+pub async fn export_handler(State(state): State<Arc<AppState>>, Path(name): Path<String>) -> Result<Response, AppError> {
+    let category = category(&name)?;
+    Ok(blacklist::export(state.suspicious_sources.list(category)).into_response())
+}
@@ -0,0 +1,709 @@
+use axum::{extract::State, Json};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use crate::admission::Utilization;
+use crate::config::{Decision, StoreFullPolicy};
+use crate::error::AppQuery;
+use crate::ip_set::IpSetMemory;
+use crate::sources::SourceCategory;
+use crate::store::StoreSize;
+use crate::{AppError, AppState, FraudCheckResult};
+
+// --- SCORING STATISTICS ---
+/// Width of one ring-buffer bucket; windows are exact to this granularity.
+const BUCKET_SECS: i64 = 300;
+/// 24 hours of buckets.
+const BUCKET_COUNT: usize = 288;
+/// Buckets making up the last hour.
+const HOUR_BUCKETS: usize = 12;
+/// Scores are bucketed in steps of this, with everything from `SCORE_BUCKET_COUNT - 1`
+/// steps up in the last bucket.
+const SCORE_BUCKET_WIDTH: i32 = 10;
+const SCORE_BUCKET_COUNT: usize = 11;
+/// IPs tracked per bucket. Only scoring IPs count toward it, and later ones are dropped
+/// once it's full, so a spray of one-off addresses can't grow the buffer without bound.
+const MAX_IPS_PER_BUCKET: usize = 1_000;
+/// Entries in each top list.
+const TOP_COUNT: usize = 10;
+
+/// One histogram per minute for the longest window the histogram endpoint serves.
+const HISTOGRAM_MINUTES: usize = 7 * 24 * 60;
+/// Resolution of the stored histograms; requested bucket widths must be multiples of it.
+const HISTOGRAM_BIN_WIDTH: i32 = 5;
+/// Scores at or above this share the last bin.
+const HISTOGRAM_MAX_SCORE: i32 = 250;
+const HISTOGRAM_BINS: usize = (HISTOGRAM_MAX_SCORE / HISTOGRAM_BIN_WIDTH) as usize + 1;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct RuleCounters {
+    hits: u64,
+    /// Hits on results that weren't flagged.
+    unflagged_hits: u64,
+    /// Flagged results where this was the only rule adding points.
+    sole_flags: u64,
+    points: i64,
+}
+
+/// One side of a rule's rollout: enforced, or run in shadow.
+#[derive(Debug, Clone, Copy, Default)]
+struct RolloutCounters {
+    evaluations: u64,
+    hits: u64,
+    /// Hits on flagged results, or in shadow, on results the rule's points would have flagged.
+    flags: u64,
+}
+
+impl RolloutCounters {
+    fn add(&mut self, other: &RolloutCounters) {
+        self.evaluations += other.evaluations;
+        self.hits += other.hits;
+        self.flags += other.flags;
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct IpTotals {
+    score: i64,
+    events: u64,
+}
+
+/// Everything scored during one `BUCKET_SECS` slot.
+#[derive(Debug, Default)]
+struct Bucket {
+    /// `unix time / BUCKET_SECS` of the slot; a stale slot is reset before reuse.
+    slot: i64,
+    events: u64,
+    flagged: u64,
+    /// Events the rules ran on, i.e. not allowlisted.
+    evaluated: u64,
+    rules: HashMap<String, RuleCounters>,
+    /// Per rule under a rollout: the enforced side, then the shadow side.
+    rollouts: HashMap<String, [RolloutCounters; 2]>,
+    scores: [u64; SCORE_BUCKET_COUNT],
+    /// Results per `Decision`, in `Decision::ALL` order.
+    decisions: [u64; Decision::ALL.len()],
+    rule_hits: HashMap<Cow<'static, str>, u64>,
+    /// Reason codes on flagged results only.
+    flag_codes: HashMap<Cow<'static, str>, u64>,
+    ips: HashMap<String, IpTotals>,
+}
+
+/// Scores seen during one minute, in `HISTOGRAM_BIN_WIDTH` bins.
+#[derive(Debug, Clone, Copy)]
+struct MinuteHistogram {
+    /// `unix time / 60` of the minute; a stale slot is reset before reuse.
+    minute: i64,
+    bins: [u32; HISTOGRAM_BINS],
+}
+
+/// Counters for the stats endpoints, kept in fixed-size rings: five-minute buckets for
+/// 24 hours and score histograms per minute for 7 days. Queries merge the buckets, so
+/// they never read the event store, and sessions expiring from it don't change them.
+pub struct ScoringStats {
+    buckets: Mutex<Vec<Bucket>>,
+    histograms: Mutex<Vec<MinuteHistogram>>,
+}
+
+impl Default for ScoringStats {
+    fn default() -> Self {
+        let empty = MinuteHistogram {
+            minute: 0,
+            bins: [0; HISTOGRAM_BINS],
+        };
+        Self {
+            buckets: Mutex::new((0..BUCKET_COUNT).map(|_| Bucket::default()).collect()),
+            histograms: Mutex::new(vec![empty; HISTOGRAM_MINUTES]),
+        }
+    }
+}
+
+fn slot_of(at: DateTime<Utc>) -> i64 {
+    at.timestamp().div_euclid(BUCKET_SECS)
+}
+
+fn minute_of(at: DateTime<Utc>) -> i64 {
+    at.timestamp().div_euclid(60)
+}
+
+fn histogram_bin(score: i32) -> usize {
+    (score.clamp(0, HISTOGRAM_MAX_SCORE) / HISTOGRAM_BIN_WIDTH) as usize
+}
+
+fn score_bucket(score: i32) -> usize {
+    (score.max(0) / SCORE_BUCKET_WIDTH).min(SCORE_BUCKET_COUNT as i32 - 1) as usize
+}
+
+fn score_distribution(scores: [u64; SCORE_BUCKET_COUNT]) -> Vec<ScoreBucket> {
+    scores
+        .into_iter()
+        .enumerate()
+        .map(|(i, count)| {
+            let from = i as i32 * SCORE_BUCKET_WIDTH;
+            ScoreBucket {
+                from: (i > 0).then_some(from),
+                to: (i < SCORE_BUCKET_COUNT - 1).then_some(from + SCORE_BUCKET_WIDTH),
+                count,
+            }
+        })
+        .collect()
+}
+
+/// `scores` in the buckets of the stats summary's `scoreDistribution`.
+pub fn distribute(scores: impl IntoIterator<Item = i32>) -> Vec<ScoreBucket> {
+    let mut counts = [0; SCORE_BUCKET_COUNT];
+    for score in scores {
+        counts[score_bucket(score)] += 1;
+    }
+    score_distribution(counts)
+}
+
+/// Score changes, in buckets of `SCORE_BUCKET_WIDTH` from `-90` to `100` with open
+/// ends beyond, so `[0, 10)` holds the unchanged scores and small increases.
+pub fn distribute_deltas(deltas: impl IntoIterator<Item = i32>) -> Vec<ScoreBucket> {
+    const RANGE: i32 = 100;
+    let count = (2 * RANGE / SCORE_BUCKET_WIDTH + 1) as usize;
+    let mut counts = vec![0; count];
+    for delta in deltas {
+        counts[((delta.max(-RANGE) + RANGE) / SCORE_BUCKET_WIDTH).min(count as i32 - 1) as usize] += 1;
+    }
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(i, count_in)| {
+            let from = i as i32 * SCORE_BUCKET_WIDTH - RANGE;
+            ScoreBucket {
+                from: (i > 0).then_some(from),
+                to: (i < count - 1).then_some(from + SCORE_BUCKET_WIDTH),
+                count: count_in,
+            }
+        })
+        .collect()
+}
+
+impl ScoringStats {
+    /// Counts one analyzed event against its check time. `ip` is the stored form, so it
+    /// is pseudonymized when IP privacy is on.
+    pub fn record(&self, ip: &str, result: &FraudCheckResult) {
+        self.record_histogram(result);
+
+        let slot = slot_of(result.check_timestamp);
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = &mut buckets[slot.rem_euclid(BUCKET_COUNT as i64) as usize];
+        if bucket.slot != slot {
+            *bucket = Bucket {
+                slot,
+                ..Bucket::default()
+            };
+        }
+
+        bucket.events += 1;
+        bucket.scores[score_bucket(result.fraud_score)] += 1;
+        bucket.decisions[result.decision as usize] += 1;
+        for hit in &result.breakdown {
+            *bucket.rule_hits.entry(hit.code.clone()).or_default() += 1;
+        }
+        if result.flagged {
+            bucket.flagged += 1;
+            for hit in &result.breakdown {
+                *bucket.flag_codes.entry(hit.code.clone()).or_default() += 1;
+            }
+        }
+        if !result.breakdown.iter().any(|hit| hit.code == "ALLOWLISTED") {
+            bucket.evaluated += 1;
+        }
+        let sole = result.flagged && result.outcomes.iter().filter(|outcome| outcome.points > 0).count() == 1;
+        for outcome in &result.outcomes {
+            let counters = match bucket.rules.get_mut(&outcome.rule) {
+                Some(counters) => counters,
+                None => bucket.rules.entry(outcome.rule.clone()).or_default(),
+            };
+            counters.hits += 1;
+            counters.points += i64::from(outcome.points);
+            if !result.flagged {
+                counters.unflagged_hits += 1;
+            } else if sole && outcome.points > 0 {
+                counters.sole_flags += 1;
+            }
+        }
+        for outcome in &result.rollouts {
+            let sides = match bucket.rollouts.get_mut(&outcome.rule) {
+                Some(sides) => sides,
+                None => bucket.rollouts.entry(outcome.rule.clone()).or_default(),
+            };
+            let counters = &mut sides[usize::from(!outcome.in_rollout)];
+            counters.evaluations += 1;
+            counters.hits += u64::from(outcome.hit);
+            counters.flags += u64::from(outcome.flagged);
+        }
+        if result.fraud_score > 0 && (bucket.ips.len() < MAX_IPS_PER_BUCKET || bucket.ips.contains_key(ip)) {
+            let totals = bucket.ips.entry(ip.to_string()).or_default();
+            totals.score += i64::from(result.fraud_score);
+            totals.events += 1;
+        }
+    }
+
+    /// Drops the totals kept for `ips` (counter keys) from every bucket, returning how many
+    /// of them had any. The other counters aren't per IP and stay.
+    pub fn forget_ips(&self, ips: &HashSet<String>) -> usize {
+        let mut forgotten = HashSet::new();
+        for bucket in self.buckets.lock().unwrap().iter_mut() {
+            bucket.ips.retain(|ip, _| match ips.contains(ip) {
+                true => {
+                    forgotten.insert(ip.clone());
+                    false
+                }
+                false => true,
+            });
+        }
+        forgotten.len()
+    }
+
+    fn record_histogram(&self, result: &FraudCheckResult) {
+        let minute = minute_of(result.check_timestamp);
+        let mut histograms = self.histograms.lock().unwrap();
+        let histogram = &mut histograms[minute.rem_euclid(HISTOGRAM_MINUTES as i64) as usize];
+        if histogram.minute != minute {
+            *histogram = MinuteHistogram {
+                minute,
+                bins: [0; HISTOGRAM_BINS],
+            };
+        }
+        histogram.bins[histogram_bin(result.fraud_score)] += 1;
+    }
+
+    /// Merges the minute histograms of the `minutes` up to and including `now`'s, regrouped
+    /// into `width`-point buckets. `width` must be a multiple of `HISTOGRAM_BIN_WIDTH`.
+    fn score_histogram(&self, now: DateTime<Utc>, minutes: usize, width: i32) -> Vec<ScoreBucket> {
+        let current = minute_of(now);
+        let mut bins = [0u64; HISTOGRAM_BINS];
+        for histogram in self.histograms.lock().unwrap().iter() {
+            if histogram.minute <= current && histogram.minute > current - minutes as i64 {
+                for (total, count) in bins.iter_mut().zip(histogram.bins) {
+                    *total += u64::from(count);
+                }
+            }
+        }
+
+        // The last bucket is open-ended and also takes the overflow bin.
+        let bucket_count = (HISTOGRAM_MAX_SCORE + width - 1) / width;
+        let mut buckets: Vec<ScoreBucket> = (0..bucket_count)
+            .map(|i| ScoreBucket {
+                from: (i > 0).then_some(i * width),
+                to: (i < bucket_count - 1).then_some((i + 1) * width),
+                count: 0,
+            })
+            .collect();
+        for (bin, count) in bins.into_iter().enumerate() {
+            let bucket = (bin as i32 * HISTOGRAM_BIN_WIDTH / width).min(bucket_count - 1);
+            buckets[bucket as usize].count += count;
+        }
+        buckets
+    }
+
+    /// Merges the buckets covering the `minutes` up to `now` (rounded up to whole
+    /// buckets): the window's totals and one row per rule in `rules`, in that order.
+    fn rule_report<'a>(
+        &self,
+        now: DateTime<Utc>,
+        minutes: i64,
+        rules: impl Iterator<Item = &'a str>,
+    ) -> (WindowStats, Vec<RuleStats>) {
+        let current = slot_of(now);
+        let bucket_count = (minutes * 60 + BUCKET_SECS - 1) / BUCKET_SECS;
+        let buckets = self.buckets.lock().unwrap();
+        let live: Vec<&Bucket> = buckets
+            .iter()
+            .filter(|bucket| bucket.slot <= current && bucket.slot > current - bucket_count)
+            .collect();
+
+        let mut counts = WindowCounts::default();
+        let mut evaluations = 0;
+        for bucket in &live {
+            counts.add(bucket);
+            evaluations += bucket.evaluated;
+        }
+        let rules = rules
+            .map(|rule| {
+                let mut totals = RuleCounters::default();
+                for counters in live.iter().filter_map(|bucket| bucket.rules.get(rule)) {
+                    totals.hits += counters.hits;
+                    totals.unflagged_hits += counters.unflagged_hits;
+                    totals.sole_flags += counters.sole_flags;
+                    totals.points += counters.points;
+                }
+                let ratio = |count: f64, total: u64| if total == 0 { 0.0 } else { count / total as f64 };
+                let mut sides: Option<[RolloutCounters; 2]> = None;
+                for rollout in live.iter().filter_map(|bucket| bucket.rollouts.get(rule)) {
+                    let sides = sides.get_or_insert_with(Default::default);
+                    for (total, counters) in sides.iter_mut().zip(rollout) {
+                        total.add(counters);
+                    }
+                }
+                let side = |counters: RolloutCounters| RolloutSideStats {
+                    evaluations: counters.evaluations,
+                    hits: counters.hits,
+                    hit_rate: ratio(counters.hits as f64, counters.evaluations),
+                    flags: counters.flags,
+                    flag_rate: ratio(counters.flags as f64, counters.evaluations),
+                };
+                RuleStats {
+                    rule: rule.to_string(),
+                    evaluations,
+                    hits: totals.hits,
+                    hit_rate: ratio(totals.hits as f64, evaluations),
+                    unflagged_hits: totals.unflagged_hits,
+                    sole_flags: totals.sole_flags,
+                    avg_points: ratio(totals.points as f64, totals.hits),
+                    rollout: sides.map(|[enforced, shadow]| RolloutStats {
+                        in_rollout: side(enforced),
+                        out_of_rollout: side(shadow),
+                    }),
+                }
+            })
+            .collect();
+
+        (counts.into(), rules)
+    }
+
+    /// Merges the buckets of the 24 hours up to `now`.
+    fn summary(
+        &self,
+        now: DateTime<Utc>,
+        store: StoreSize,
+        capacity: Capacity,
+        ip_lists: BTreeMap<&'static str, IpSetMemory>,
+    ) -> StatsSummary {
+        let current = slot_of(now);
+        let buckets = self.buckets.lock().unwrap();
+        let live = buckets
+            .iter()
+            .filter(|bucket| bucket.slot <= current && bucket.slot > current - BUCKET_COUNT as i64);
+
+        let mut last_hour = WindowCounts::default();
+        let mut last_day = WindowCounts::default();
+        let mut scores = [0; SCORE_BUCKET_COUNT];
+        let mut decisions = [0; Decision::ALL.len()];
+        let mut rule_hits: BTreeMap<String, u64> = BTreeMap::new();
+        let mut flag_codes: HashMap<&str, u64> = HashMap::new();
+        let mut ips: HashMap<&str, IpTotals> = HashMap::new();
+        for bucket in live {
+            if bucket.slot > current - HOUR_BUCKETS as i64 {
+                last_hour.add(bucket);
+            }
+            last_day.add(bucket);
+            for (total, count) in scores.iter_mut().zip(bucket.scores) {
+                *total += count;
+            }
+            for (total, count) in decisions.iter_mut().zip(bucket.decisions) {
+                *total += count;
+            }
+            for (code, hits) in &bucket.rule_hits {
+                *rule_hits.entry(code.to_string()).or_default() += hits;
+            }
+            for (code, count) in &bucket.flag_codes {
+                *flag_codes.entry(code).or_default() += count;
+            }
+            for (ip, bucket_totals) in &bucket.ips {
+                let totals = ips.entry(ip).or_default();
+                totals.score += bucket_totals.score;
+                totals.events += bucket_totals.events;
+            }
+        }
+
+        let mut top_ips: Vec<IpContribution> = ips
+            .into_iter()
+            .map(|(ip, totals)| IpContribution {
+                ip: ip.to_string(),
+                score: totals.score,
+                events: totals.events,
+            })
+            .collect();
+        top_ips.sort_unstable_by(|a, b| b.score.cmp(&a.score).then_with(|| a.ip.cmp(&b.ip)));
+        top_ips.truncate(TOP_COUNT);
+
+        let mut top_reason_codes: Vec<CodeCount> = flag_codes
+            .into_iter()
+            .map(|(code, count)| CodeCount {
+                code: code.to_string(),
+                count,
+            })
+            .collect();
+        top_reason_codes.sort_unstable_by(|a, b| b.count.cmp(&a.count).then_with(|| a.code.cmp(&b.code)));
+        top_reason_codes.truncate(TOP_COUNT);
+
+        let score_distribution = score_distribution(scores);
+
+        StatsSummary {
+            generated_at: now,
+            last_hour: last_hour.into(),
+            last_24h: last_day.into(),
+            rule_hits,
+            decisions: Decision::ALL.into_iter().zip(decisions).collect(),
+            top_ips,
+            top_reason_codes,
+            score_distribution,
+            store,
+            capacity,
+            ip_lists,
+        }
+    }
+}
+
+// --- STATS HANDLER ---
+#[derive(Debug, Default)]
+struct WindowCounts {
+    events: u64,
+    flagged: u64,
+}
+
+impl WindowCounts {
+    fn add(&mut self, bucket: &Bucket) {
+        self.events += bucket.events;
+        self.flagged += bucket.flagged;
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WindowStats {
+    events: u64,
+    flagged: u64,
+    /// Flagged share of events, 0 when there were none.
+    flag_rate: f64,
+}
+
+impl From<WindowCounts> for WindowStats {
+    fn from(counts: WindowCounts) -> Self {
+        let flag_rate = if counts.events == 0 {
+            0.0
+        } else {
+            counts.flagged as f64 / counts.events as f64
+        };
+        Self {
+            events: counts.events,
+            flagged: counts.flagged,
+            flag_rate,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IpContribution {
+    ip: String,
+    /// Sum of the fraud scores of the IP's events.
+    score: i64,
+    events: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeCount {
+    code: String,
+    count: u64,
+}
+
+/// Scores in `[from, to)`; the open ends catch everything below and above.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScoreBucket {
+    from: Option<i32>,
+    to: Option<i32>,
+    count: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsSummary {
+    generated_at: DateTime<Utc>,
+    last_hour: WindowStats,
+    #[serde(rename = "last24h")]
+    last_24h: WindowStats,
+    /// Hits per reason code over the last 24 hours.
+    rule_hits: BTreeMap<String, u64>,
+    /// Results per decision over the last 24 hours.
+    decisions: BTreeMap<Decision, u64>,
+    top_ips: Vec<IpContribution>,
+    /// Reason codes most often present on flagged results.
+    top_reason_codes: Vec<CodeCount>,
+    score_distribution: Vec<ScoreBucket>,
+    /// What the event store holds right now.
+    store: StoreSize,
+    capacity: Capacity,
+    /// What each IP list's permanent entries take in memory, by list.
+    ip_lists: BTreeMap<&'static str, IpSetMemory>,
+}
+
+/// The admission budgets and how much of each is in use.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Capacity {
+    in_flight_analyses: Utilization,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    in_flight_requests: Option<Utilization>,
+    stored_events: Utilization,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    store_full_policy: Option<StoreFullPolicy>,
+}
+
+pub async fn stats_handler(State(state): State<Arc<AppState>>) -> Result<Json<StatsSummary>, AppError> {
+    let store = state.event_store.size().await?;
+    // Redis is bounded by its own maxmemory, not MAX_STORED_EVENTS.
+    let store_capacity = state.service.store_capacity.as_ref().filter(|_| state.service.redis_url.is_none());
+    let capacity = Capacity {
+        in_flight_analyses: state.admission.utilization(),
+        in_flight_requests: state.request_admission.as_ref().map(|requests| requests.utilization()),
+        stored_events: Utilization {
+            current: store.events,
+            limit: store_capacity.map(|capacity| capacity.max_events),
+        },
+        store_full_policy: store_capacity.map(|capacity| capacity.policy),
+    };
+    let mut ip_lists = BTreeMap::from([("blacklist", state.ip_blacklist.memory())]);
+    for category in SourceCategory::ALL {
+        ip_lists.insert(category.as_str(), state.suspicious_sources.list(category).memory());
+    }
+    Ok(Json(state.stats.summary(state.clock.now(), store, capacity, ip_lists)))
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleStats {
+    rule: String,
+    /// Events the rule ran on (every event that wasn't allowlisted).
+    evaluations: u64,
+    hits: u64,
+    hit_rate: f64,
+    /// Hits on events that weren't flagged.
+    unflagged_hits: u64,
+    /// Flagged events where this rule was the only one adding points.
+    sole_flags: u64,
+    /// Mean points per hit.
+    avg_points: f64,
+    /// Set when the rule was under a rollout during the window. The counts above only
+    /// cover events it was enforced for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rollout: Option<RolloutStats>,
+}
+
+/// A rule's hits and flags on each side of its rollout, to compare before widening it.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RolloutStats {
+    in_rollout: RolloutSideStats,
+    /// Run in shadow: its hits didn't score.
+    out_of_rollout: RolloutSideStats,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RolloutSideStats {
+    /// Events the rule ran on, on this side.
+    evaluations: u64,
+    hits: u64,
+    hit_rate: f64,
+    /// Hits on flagged events; out of the rollout, hits whose points would have flagged.
+    flags: u64,
+    flag_rate: f64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleReport {
+    window: String,
+    totals: WindowStats,
+    rules: Vec<RuleStats>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RuleReportParams {
+    /// `<n>m`, `<n>h`, or `<n>d`; defaults to `24h`.
+    window: Option<String>,
+}
+
+/// How every registered rule performed over a recent window, to find rules that are
+/// noisy (many unflagged hits) or carry the flags on their own.
+pub async fn rule_report_handler(
+    State(state): State<Arc<AppState>>,
+    AppQuery(params): AppQuery<RuleReportParams>,
+) -> Result<Json<RuleReport>, AppError> {
+    let window = params.window.unwrap_or_else(|| "24h".to_string());
+    let duration = parse_window(&window)
+        .ok_or_else(|| AppError::BadRequest(format!("Invalid window `{}`, expected e.g. 30m, 1h, or 24h", window)))?;
+    if duration > Duration::seconds(BUCKET_SECS * BUCKET_COUNT as i64) {
+        return Err(AppError::Validation(format!("Window `{}` is longer than 24 hours", window)));
+    }
+
+    let scoring = state.scoring_snapshot();
+    let (totals, rules) = state
+        .stats
+        .rule_report(state.clock.now(), duration.num_minutes(), scoring.engine.rule_names());
+    Ok(Json(RuleReport { window, totals, rules }))
+}
+
+/// Longest window the histogram endpoint accepts.
+const MAX_HISTOGRAM_WINDOW: Duration = Duration::days(7);
+
+#[derive(Debug, Deserialize)]
+pub struct HistogramParams {
+    /// `<n>m`, `<n>h`, or `<n>d`; defaults to `1h`.
+    window: Option<String>,
+    /// Bucket width in score points; defaults to 10.
+    bucket: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScoreHistogram {
+    window: String,
+    bucket: i32,
+    /// Results counted across all buckets.
+    total: u64,
+    buckets: Vec<ScoreBucket>,
+}
+
+fn parse_window(window: &str) -> Option<Duration> {
+    let split = window.len().checked_sub(1)?;
+    let (count, unit) = window.split_at_checked(split)?;
+    let count: i64 = count.parse().ok().filter(|&count| count > 0)?;
+    match unit {
+        "m" => Duration::try_minutes(count),
+        "h" => Duration::try_hours(count),
+        "d" => Duration::try_days(count),
+        _ => None,
+    }
+}
+
+/// Score distribution over a recent window, for tuning `flag_threshold`. Windows are
+/// whole minutes, counting the current one.
+pub async fn score_histogram_handler(
+    State(state): State<Arc<AppState>>,
+    AppQuery(params): AppQuery<HistogramParams>,
+) -> Result<Json<ScoreHistogram>, AppError> {
+    let window = params.window.unwrap_or_else(|| "1h".to_string());
+    let duration = parse_window(&window)
+        .ok_or_else(|| AppError::BadRequest(format!("Invalid window `{}`, expected e.g. 30m, 1h, or 7d", window)))?;
+    if duration > MAX_HISTOGRAM_WINDOW {
+        return Err(AppError::Validation(format!("Window `{}` is longer than 7 days", window)));
+    }
+
+    let width = params.bucket.unwrap_or(10);
+    if width <= 0 || width > HISTOGRAM_MAX_SCORE || width % HISTOGRAM_BIN_WIDTH != 0 {
+        return Err(AppError::Validation(format!(
+            "Bucket width must be a multiple of {} between {} and {}",
+            HISTOGRAM_BIN_WIDTH, HISTOGRAM_BIN_WIDTH, HISTOGRAM_MAX_SCORE
+        )));
+    }
+
+    let buckets = state.stats.score_histogram(state.clock.now(), duration.num_minutes() as usize, width);
+    Ok(Json(ScoreHistogram {
+        window,
+        bucket: width,
+        total: buckets.iter().map(|bucket| bucket.count).sum(),
+        buckets,
+    }))
+}
@@ -0,0 +1,695 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use metrics::{counter, gauge};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+
+use crate::config::{StoreCapacity, StoreFullPolicy};
+use crate::{EventType, UserEvent};
+
+// --- EVENT STORE ---
+#[derive(Debug)]
+pub enum StoreError {
+    /// The backend failed or returned something unreadable.
+    Backend(String),
+    /// The store is at `MAX_STORED_EVENTS` and turns away new sessions.
+    Full,
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Backend(message) => f.write_str(message),
+            StoreError::Full => f.write_str("event store is full"),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+/// How stores read events back. Same shape as `UserEvent`, but `ipAddress` is taken as
+/// stored, since it may be pseudonymized and no longer parse as an address.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredEvent {
+    session_id: String,
+    user_id: Option<String>,
+    event_type: EventType,
+    timestamp: DateTime<Utc>,
+    ip_address: String,
+    #[serde(default)]
+    user_agent: Option<String>,
+    metadata: Option<HashMap<String, String>>,
+    #[serde(default)]
+    asn: Option<u32>,
+}
+
+impl From<StoredEvent> for UserEvent {
+    fn from(stored: StoredEvent) -> Self {
+        UserEvent {
+            session_id: stored.session_id,
+            user_id: stored.user_id,
+            event_type: stored.event_type,
+            timestamp: stored.timestamp,
+            ip_address: stored.ip_address,
+            user_agent: stored.user_agent,
+            metadata: stored.metadata,
+            asn: stored.asn,
+        }
+    }
+}
+
+/// What removing one user's events from a session did.
+#[derive(Debug, Default)]
+pub struct SessionErasure {
+    /// Events with the user's id that were removed.
+    pub deleted: usize,
+    /// Anonymous events left in the session; they can't be attributed to anyone.
+    pub anonymous_retained: usize,
+    /// IPs of the removed events, for erasing what was derived from them elsewhere.
+    pub ips: HashSet<String>,
+}
+
+impl SessionErasure {
+    /// Notes that `event` is being removed.
+    pub fn remove(&mut self, event: &UserEvent) {
+        self.deleted += 1;
+        self.ips.insert(event.ip_address.clone());
+    }
+}
+
+/// How much a store currently holds.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoreSize {
+    pub sessions: usize,
+    pub events: usize,
+}
+
+/// A session's events, oldest first. A store hands out a snapshot of what it holds
+/// rather than a copy, so returning a long history costs a reference count; the events
+/// are copied (as pointers, never deeply) only when a shared snapshot is changed.
+#[derive(Debug, Clone, Default)]
+pub struct SessionHistory {
+    events: Arc<Vec<Arc<UserEvent>>>,
+    /// Events before this were dropped from the front but not yet moved out.
+    start: usize,
+}
+
+impl SessionHistory {
+    /// The events to change in place, copied first if the snapshot is shared.
+    pub fn to_mut(&mut self) -> &mut Vec<Arc<UserEvent>> {
+        self.compact(0);
+        Arc::make_mut(&mut self.events)
+    }
+
+    /// Appends `event`. Dropped events are moved out once they outnumber the kept ones,
+    /// so a session trimmed from the front on every push doesn't shift it each time.
+    pub(crate) fn push_back(&mut self, event: Arc<UserEvent>) {
+        let slack = match Arc::get_mut(&mut self.events) {
+            Some(_) => self.len(),
+            None => 0,
+        };
+        self.compact(slack);
+        Arc::make_mut(&mut self.events).push(event);
+    }
+
+    pub(crate) fn pop_front(&mut self) -> Option<Arc<UserEvent>> {
+        let first = self.first().cloned()?;
+        self.start += 1;
+        Some(first)
+    }
+
+    /// Moves out the dropped events if there are more than `slack` of them.
+    fn compact(&mut self, slack: usize) {
+        if self.start <= slack {
+            return;
+        }
+        match Arc::get_mut(&mut self.events) {
+            Some(events) => drop(events.drain(..self.start)),
+            None => self.events = Arc::new(self.events[self.start..].to_vec()),
+        }
+        self.start = 0;
+    }
+}
+
+impl Deref for SessionHistory {
+    type Target = [Arc<UserEvent>];
+
+    fn deref(&self) -> &Self::Target {
+        &self.events[self.start..]
+    }
+}
+
+impl From<Vec<Arc<UserEvent>>> for SessionHistory {
+    fn from(events: Vec<Arc<UserEvent>>) -> Self {
+        Self {
+            events: Arc::new(events),
+            start: 0,
+        }
+    }
+}
+
+impl FromIterator<Arc<UserEvent>> for SessionHistory {
+    fn from_iter<I: IntoIterator<Item = Arc<UserEvent>>>(events: I) -> Self {
+        Vec::from_iter(events).into()
+    }
+}
+
+impl IntoIterator for SessionHistory {
+    type Item = Arc<UserEvent>;
+    type IntoIter = std::vec::IntoIter<Arc<UserEvent>>;
+
+    fn into_iter(mut self) -> Self::IntoIter {
+        std::mem::take(self.to_mut()).into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a SessionHistory {
+    type Item = &'a Arc<UserEvent>;
+    type IntoIter = std::slice::Iter<'a, Arc<UserEvent>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Per-session event history. Rules only ever see what a store returns, so a shared
+/// backend gives every replica the same view of a session.
+#[async_trait]
+pub trait EventStore: Send + Sync {
+    /// Appends the event, drops the session's oldest events beyond the store's cap, and
+    /// returns the session's history, oldest first, ending with it.
+    async fn push(&self, event: UserEvent) -> Result<SessionHistory, StoreError>;
+
+    /// Appends events without reading anything back, for writers that don't score.
+    #[cfg_attr(not(feature = "redis"), allow(dead_code))]
+    async fn push_batch(&self, events: Vec<UserEvent>) -> Result<(), StoreError> {
+        for event in events {
+            self.push(event).await?;
+        }
+        Ok(())
+    }
+
+    /// Returns the session's stored events, oldest first.
+    async fn session_events(&self, session_id: &str) -> Result<SessionHistory, StoreError>;
+
+    /// Merges `metadata` into the session's newest event with the given `eventId` and
+    /// returns the session's history with the event amended in place, or `None` when the
+    /// session holds no such event.
+    async fn amend(
+        &self,
+        session_id: &str,
+        event_id: &str,
+        metadata: &HashMap<String, String>,
+    ) -> Result<Option<SessionHistory>, StoreError>;
+
+    /// Ids of every stored session, in no particular order.
+    async fn session_ids(&self) -> Result<Vec<String>, StoreError>;
+
+    /// Ids of the stored sessions holding events with the user's id, in no particular
+    /// order, from an index kept as events are pushed, trimmed, and dropped.
+    async fn user_sessions(&self, user_id: &str) -> Result<Vec<String>, StoreError>;
+
+    /// Removes the user's events from one session, dropping the session once it's empty.
+    async fn erase_user_events(&self, session_id: &str, user_id: &str) -> Result<SessionErasure, StoreError>;
+
+    /// Stored session and event counts.
+    async fn size(&self) -> Result<StoreSize, StoreError>;
+}
+
+/// A stored session and its place in the activity order.
+struct MemorySession {
+    events: SessionHistory,
+    touched: u64,
+}
+
+#[derive(Default)]
+struct MemorySessions {
+    sessions: HashMap<String, MemorySession>,
+    /// Sessions by last push, least recently active first.
+    by_activity: BTreeSet<(u64, String)>,
+    next_touch: u64,
+    /// Total across sessions.
+    event_count: usize,
+    /// Sessions by the user ids of their events, with how many events each has of the
+    /// user's.
+    users: HashMap<String, HashMap<String, usize>>,
+}
+
+/// Counts the event toward its user's sessions.
+fn index_user(users: &mut HashMap<String, HashMap<String, usize>>, event: &UserEvent) {
+    if let Some(user_id) = &event.user_id {
+        *users.entry(user_id.clone()).or_default().entry(event.session_id.clone()).or_default() += 1;
+    }
+}
+
+/// Takes a dropped event off its user's sessions, and the session off the user once
+/// none of its events are theirs.
+fn unindex_user(users: &mut HashMap<String, HashMap<String, usize>>, event: &UserEvent) {
+    let Some(user_id) = &event.user_id else {
+        return;
+    };
+    let Some(sessions) = users.get_mut(user_id) else {
+        return;
+    };
+    if let Some(count) = sessions.get_mut(&event.session_id) {
+        *count -= 1;
+        if *count == 0 {
+            sessions.remove(&event.session_id);
+        }
+    }
+    if sessions.is_empty() {
+        users.remove(user_id);
+    }
+}
+
+impl MemorySessions {
+    fn remove(&mut self, session_id: &str) {
+        if let Some(session) = self.sessions.remove(session_id) {
+            self.by_activity.remove(&(session.touched, session_id.to_string()));
+            self.event_count -= session.events.len();
+            for event in &session.events {
+                unindex_user(&mut self.users, event);
+            }
+        }
+    }
+
+    /// Drops the least recently active sessions other than `keep` until an event fits
+    /// under `max_events`. Returns how many were dropped.
+    fn shed(&mut self, max_events: usize, keep: &str) -> usize {
+        let mut shed = 0;
+        while self.event_count >= max_events {
+            let oldest = self.by_activity.iter().find(|(_, session_id)| session_id != keep).cloned();
+            let Some((_, session_id)) = oldest else {
+                break;
+            };
+            self.remove(&session_id);
+            shed += 1;
+        }
+        shed
+    }
+}
+
+/// Process-local store; the default when no shared backend is configured. Each session
+/// keeps its newest `max_events` events, and with a capacity set the store as a whole
+/// never holds more than its `max_events`.
+pub struct MemoryEventStore {
+    sessions: Mutex<MemorySessions>,
+    max_events: usize,
+    capacity: Option<StoreCapacity>,
+}
+
+impl MemoryEventStore {
+    pub fn new(max_events: usize, capacity: Option<StoreCapacity>) -> Self {
+        if let Some(capacity) = &capacity {
+            gauge!("stored_events_limit").set(capacity.max_events as f64);
+        }
+        Self {
+            sessions: Mutex::new(MemorySessions::default()),
+            max_events,
+            capacity,
+        }
+    }
+}
+
+#[async_trait]
+impl EventStore for MemoryEventStore {
+    async fn push(&self, event: UserEvent) -> Result<SessionHistory, StoreError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let known = sessions.sessions.contains_key(&event.session_id);
+        let full = self
+            .capacity
+            .as_ref()
+            .is_some_and(|capacity| sessions.event_count >= capacity.max_events);
+        if let (true, Some(capacity)) = (full, &self.capacity) {
+            match capacity.policy {
+                StoreFullPolicy::ShedOldest => {
+                    let shed = sessions.shed(capacity.max_events, &event.session_id);
+                    counter!("store_sessions_shed_total").increment(shed as u64);
+                }
+                StoreFullPolicy::RejectNew if !known => {
+                    counter!("store_events_rejected_total").increment(1);
+                    return Err(StoreError::Full);
+                }
+                StoreFullPolicy::RejectNew => {}
+            }
+        }
+
+        let MemorySessions {
+            sessions: stored,
+            by_activity,
+            next_touch,
+            event_count,
+            users,
+        } = &mut *sessions;
+        let session = stored.entry(event.session_id.clone()).or_insert_with(|| MemorySession {
+            events: SessionHistory::default(),
+            touched: 0,
+        });
+        by_activity.remove(&(session.touched, event.session_id.clone()));
+        *next_touch += 1;
+        session.touched = *next_touch;
+        by_activity.insert((session.touched, event.session_id.clone()));
+
+        // A full store that couldn't make room elsewhere makes it in the session itself.
+        let room = self
+            .capacity
+            .as_ref()
+            .is_none_or(|capacity| *event_count < capacity.max_events);
+        if session.events.len() >= self.max_events || (!room && !session.events.is_empty()) {
+            if let Some(dropped) = session.events.pop_front() {
+                unindex_user(users, &dropped);
+            }
+        } else {
+            *event_count += 1;
+        }
+        index_user(users, &event);
+        session.events.push_back(Arc::new(event));
+        gauge!("stored_events").set(*event_count as f64);
+        Ok(session.events.clone())
+    }
+
+    async fn session_events(&self, session_id: &str) -> Result<SessionHistory, StoreError> {
+        let sessions = self.sessions.lock().unwrap();
+        Ok(sessions
+            .sessions
+            .get(session_id)
+            .map(|session| session.events.clone())
+            .unwrap_or_default())
+    }
+
+    /// Doesn't count as activity: the session keeps its place in the shedding order.
+    async fn amend(
+        &self,
+        session_id: &str,
+        event_id: &str,
+        metadata: &HashMap<String, String>,
+    ) -> Result<Option<SessionHistory>, StoreError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.sessions.get_mut(session_id) else {
+            return Ok(None);
+        };
+        let Some(index) = session.events.iter().rposition(|event| event.event_id() == Some(event_id)) else {
+            return Ok(None);
+        };
+        let amended = Arc::new(session.events[index].amended(metadata));
+        session.events.to_mut()[index] = amended;
+        Ok(Some(session.events.clone()))
+    }
+
+    async fn session_ids(&self) -> Result<Vec<String>, StoreError> {
+        Ok(self.sessions.lock().unwrap().sessions.keys().cloned().collect())
+    }
+
+    async fn user_sessions(&self, user_id: &str) -> Result<Vec<String>, StoreError> {
+        let sessions = self.sessions.lock().unwrap();
+        Ok(sessions
+            .users
+            .get(user_id)
+            .map(|sessions| sessions.keys().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn erase_user_events(&self, session_id: &str, user_id: &str) -> Result<SessionErasure, StoreError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.sessions.get_mut(session_id) else {
+            return Ok(SessionErasure::default());
+        };
+
+        let mut erasure = SessionErasure::default();
+        session.events.to_mut().retain(|event| match event.user_id.as_deref() == Some(user_id) {
+            true => {
+                erasure.remove(event);
+                false
+            }
+            false => true,
+        });
+        erasure.anonymous_retained = session.events.iter().filter(|event| event.user_id.is_none()).count();
+        let empty = session.events.is_empty();
+        sessions.event_count -= erasure.deleted;
+        if let Some(user_sessions) = sessions.users.get_mut(user_id) {
+            user_sessions.remove(session_id);
+            if user_sessions.is_empty() {
+                sessions.users.remove(user_id);
+            }
+        }
+        if empty {
+            sessions.remove(session_id);
+        }
+        gauge!("stored_events").set(sessions.event_count as f64);
+        Ok(erasure)
+    }
+
+    async fn size(&self) -> Result<StoreSize, StoreError> {
+        let sessions = self.sessions.lock().unwrap();
+        Ok(StoreSize {
+            sessions: sessions.sessions.len(),
+            events: sessions.event_count,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn event(session_id: &str, n: i64) -> UserEvent {
+        UserEvent {
+            session_id: session_id.to_string(),
+            user_id: None,
+            event_type: EventType::Click,
+            timestamp: "2024-05-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap() + Duration::seconds(n),
+            ip_address: "198.51.100.23".to_string(),
+            user_agent: None,
+            metadata: None,
+            asn: None,
+        }
+    }
+
+    fn seconds(history: &[Arc<UserEvent>]) -> Vec<i64> {
+        let start = "2024-05-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        history.iter().map(|event| (event.timestamp - start).num_seconds()).collect()
+    }
+
+    // --- SESSION CAP ---
+    #[tokio::test]
+    async fn a_session_keeps_only_its_newest_events_up_to_the_cap() {
+        let cap = 20;
+        let store = MemoryEventStore::new(cap, None);
+        let mut last = SessionHistory::default();
+        for n in 0..(cap as i64 + 10) {
+            last = store.push(event("s1", n)).await.unwrap();
+            assert!(last.len() <= cap);
+        }
+        let expected: Vec<i64> = (10..cap as i64 + 10).collect();
+        assert_eq!(seconds(&last), expected);
+        assert_eq!(seconds(&store.session_events("s1").await.unwrap()), expected);
+        let size = store.size().await.unwrap();
+        assert_eq!((size.sessions, size.events), (1, cap));
+    }
+
+    #[tokio::test]
+    async fn the_cap_applies_per_session() {
+        let store = MemoryEventStore::new(3, None);
+        for n in 0..5 {
+            store.push(event("s1", n)).await.unwrap();
+        }
+        store.push(event("s2", 0)).await.unwrap();
+        assert_eq!(store.session_events("s1").await.unwrap().len(), 3);
+        assert_eq!(store.session_events("s2").await.unwrap().len(), 1);
+    }
+
+    // --- STORE CAPACITY ---
+    fn capped(max_events: usize, policy: StoreFullPolicy) -> MemoryEventStore {
+        MemoryEventStore::new(100, Some(StoreCapacity { max_events, policy }))
+    }
+
+    #[tokio::test]
+    async fn shedding_drops_the_least_recently_active_sessions() {
+        let store = capped(4, StoreFullPolicy::ShedOldest);
+        for (session_id, n) in [("a", 0), ("a", 1), ("b", 2), ("c", 3), ("a", 4)] {
+            store.push(event(session_id, n)).await.unwrap();
+        }
+        // Full: the next new session sheds `b`, the least recently active.
+        store.push(event("d", 5)).await.unwrap();
+        assert!(store.session_events("b").await.unwrap().is_empty());
+        assert_eq!(store.session_events("a").await.unwrap().len(), 3);
+        let size = store.size().await.unwrap();
+        assert!(size.events <= 4, "{:?}", size);
+    }
+
+    #[tokio::test]
+    async fn rejecting_turns_away_new_sessions_only() {
+        let store = capped(3, StoreFullPolicy::RejectNew);
+        for n in 0..3 {
+            store.push(event("a", n)).await.unwrap();
+        }
+        assert!(matches!(store.push(event("b", 3)).await, Err(StoreError::Full)));
+        // A stored session carries on, dropping its own oldest event to stay in budget.
+        let history = store.push(event("a", 4)).await.unwrap();
+        assert_eq!(seconds(&history), [1, 2, 4]);
+        assert_eq!(store.size().await.unwrap().events, 3);
+    }
+
+    #[tokio::test]
+    async fn the_total_never_exceeds_the_cap() {
+        for policy in [StoreFullPolicy::ShedOldest, StoreFullPolicy::RejectNew] {
+            let store = capped(25, policy);
+            for n in 0..500 {
+                let _ = store.push(event(&format!("s{}", n % 40), n)).await;
+                assert!(store.size().await.unwrap().events <= 25, "{:?}", policy);
+            }
+        }
+    }
+
+    // --- AMENDMENTS ---
+    fn identified(session_id: &str, n: i64, metadata: &[(&str, &str)]) -> UserEvent {
+        UserEvent {
+            metadata: Some(metadata.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()),
+            ..event(session_id, n)
+        }
+    }
+
+    fn amendment(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[tokio::test]
+    async fn amending_merges_into_the_newest_event_with_the_id() {
+        let store = MemoryEventStore::new(10, None);
+        store.push(identified("s1", 0, &[("eventId", "e1"), ("amount", "10")])).await.unwrap();
+        store.push(identified("s1", 1, &[("eventId", "e1"), ("amount", "20"), ("currency", "USD")])).await.unwrap();
+        store.push(event("s1", 2)).await.unwrap();
+
+        let history = store.amend("s1", "e1", &amendment(&[("amount", "30")])).await.unwrap().unwrap();
+        assert_eq!(seconds(&history), [0, 1, 2]);
+        let amount = |event: &UserEvent| event.metadata.as_ref().unwrap()["amount"].clone();
+        assert_eq!(amount(&history[0]), "10");
+        assert_eq!(amount(&history[1]), "30");
+        assert_eq!(history[1].metadata.as_ref().unwrap()["currency"], "USD");
+        assert_eq!(amount(&store.session_events("s1").await.unwrap()[1]), "30");
+    }
+
+    #[tokio::test]
+    async fn amending_an_unknown_event_or_session_changes_nothing() {
+        let store = MemoryEventStore::new(10, None);
+        store.push(identified("s1", 0, &[("eventId", "e1")])).await.unwrap();
+        assert!(store.amend("s1", "e2", &amendment(&[("amount", "1")])).await.unwrap().is_none());
+        assert!(store.amend("s2", "e1", &amendment(&[("amount", "1")])).await.unwrap().is_none());
+        let stored = store.session_events("s1").await.unwrap();
+        assert_eq!(stored[0].metadata.as_ref().unwrap().len(), 1);
+    }
+
+    // --- USER INDEX ---
+    fn by_user(session_id: &str, n: i64, user_id: &str) -> UserEvent {
+        UserEvent {
+            user_id: Some(user_id.to_string()),
+            ..event(session_id, n)
+        }
+    }
+
+    async fn user_sessions(store: &MemoryEventStore, user_id: &str) -> Vec<String> {
+        let mut sessions = store.user_sessions(user_id).await.unwrap();
+        sessions.sort();
+        sessions
+    }
+
+    #[tokio::test]
+    async fn the_user_index_follows_ingestion_and_trimming() {
+        let store = MemoryEventStore::new(2, None);
+        store.push(by_user("s1", 0, "alice")).await.unwrap();
+        store.push(by_user("s2", 1, "alice")).await.unwrap();
+        store.push(by_user("s2", 2, "bob")).await.unwrap();
+        assert_eq!(user_sessions(&store, "alice").await, ["s1", "s2"]);
+        assert_eq!(user_sessions(&store, "bob").await, ["s2"]);
+        assert!(user_sessions(&store, "carol").await.is_empty());
+
+        // s2 is at its cap: the next event trims alice's only one there.
+        store.push(event("s2", 3)).await.unwrap();
+        assert_eq!(user_sessions(&store, "alice").await, ["s1"]);
+        assert_eq!(user_sessions(&store, "bob").await, ["s2"]);
+        store.push(event("s2", 4)).await.unwrap();
+        assert!(user_sessions(&store, "bob").await.is_empty());
+        assert!(!store.sessions.lock().unwrap().users.contains_key("bob"));
+
+        // One of several events trimmed keeps the session listed.
+        store.push(by_user("s1", 5, "alice")).await.unwrap();
+        store.push(event("s1", 6)).await.unwrap();
+        assert_eq!(user_sessions(&store, "alice").await, ["s1"]);
+    }
+
+    #[tokio::test]
+    async fn shed_sessions_leave_the_user_index() {
+        let store = capped(3, StoreFullPolicy::ShedOldest);
+        store.push(by_user("a", 0, "alice")).await.unwrap();
+        store.push(by_user("b", 1, "alice")).await.unwrap();
+        store.push(by_user("c", 2, "bob")).await.unwrap();
+        assert_eq!(user_sessions(&store, "alice").await, ["a", "b"]);
+
+        // Full: `a` is shed for `d`, then `b` for `e`.
+        store.push(by_user("d", 3, "bob")).await.unwrap();
+        assert_eq!(user_sessions(&store, "alice").await, ["b"]);
+        store.push(event("e", 4)).await.unwrap();
+        assert!(user_sessions(&store, "alice").await.is_empty());
+        assert_eq!(user_sessions(&store, "bob").await, ["c", "d"]);
+    }
+
+    #[tokio::test]
+    async fn erasing_a_user_takes_their_sessions_off_the_index_only() {
+        let store = MemoryEventStore::new(10, None);
+        store.push(by_user("shared", 0, "alice")).await.unwrap();
+        store.push(by_user("shared", 1, "bob")).await.unwrap();
+        store.push(by_user("own", 2, "alice")).await.unwrap();
+
+        for session_id in user_sessions(&store, "alice").await {
+            store.erase_user_events(&session_id, "alice").await.unwrap();
+        }
+        assert!(user_sessions(&store, "alice").await.is_empty());
+        assert_eq!(user_sessions(&store, "bob").await, ["shared"]);
+        assert!(store.session_events("own").await.unwrap().is_empty());
+
+        // The index picks the user up again on their next event.
+        store.push(by_user("own", 3, "alice")).await.unwrap();
+        assert_eq!(user_sessions(&store, "alice").await, ["own"]);
+    }
+
+    // --- SHARED HISTORY ---
+    #[tokio::test]
+    async fn a_push_shares_the_stored_events_and_a_held_snapshot_never_changes() {
+        let store = MemoryEventStore::new(3, None);
+        let mut held = store.push(event("s1", 0)).await.unwrap();
+        for n in 1..5 {
+            let history = store.push(event("s1", n)).await.unwrap();
+            assert!(std::ptr::eq(&history[0], &store.session_events("s1").await.unwrap()[0]));
+            if n == 2 {
+                held = history;
+            }
+        }
+        assert_eq!(seconds(&held), [0, 1, 2]);
+        assert_eq!(seconds(&store.session_events("s1").await.unwrap()), [2, 3, 4]);
+
+        // Changing a snapshot copies it, leaving the store's alone.
+        held.to_mut().truncate(1);
+        assert_eq!(seconds(&held), [0]);
+        assert_eq!(seconds(&store.session_events("s1").await.unwrap()), [2, 3, 4]);
+    }
+
+    #[test]
+    fn events_dropped_from_the_front_are_moved_out_once_they_outnumber_the_rest() {
+        let mut history = SessionHistory::default();
+        for n in 0..10 {
+            history.push_back(Arc::new(event("s1", n)));
+            if history.len() > 3 {
+                history.pop_front();
+            }
+            assert!(history.events.len() <= 2 * 3 + 1, "{} held", history.events.len());
+        }
+        assert_eq!(seconds(&history), [7, 8, 9]);
+        assert_eq!(seconds(&history.into_iter().collect::<Vec<_>>()), [7, 8, 9]);
+    }
+}
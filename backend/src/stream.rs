@@ -0,0 +1,126 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, State,
+    },
+    http::HeaderMap,
+    response::Response,
+};
+use futures_util::stream::{FuturesOrdered, StreamExt};
+use futures_util::SinkExt;
+use metrics::{counter, gauge};
+use serde_json::Value;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use crate::config::Verbosity;
+use crate::error::AppQuery;
+use crate::usage::UsageCounts;
+use crate::{AnalyzeParams, AnalyzeResponse, AppError, AppState, UserEvent};
+
+// --- STREAMING INGESTION ---
+/// What every event of one connection shares, worked out once from the upgrade request.
+struct Client {
+    derived_ip: Option<IpAddr>,
+    usage_key: String,
+    verbosity: Verbosity,
+}
+
+/// `GET /api/v1/stream`: a WebSocket taking one JSON event per text message and
+/// answering each with the body `POST /api/v1/events` would have sent, in the order the
+/// events came in. Up to `STREAM_MAX_IN_FLIGHT` events are scored at once; past that
+/// the connection stops reading until a result is sent, so a client sending faster
+/// than it is answered (or than it reads) is slowed down by TCP instead of queued for.
+pub async fn stream_handler(
+    State(state): State<Arc<AppState>>,
+    AppQuery(params): AppQuery<AnalyzeParams>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let client = Client {
+        derived_ip: crate::derive_client_ip(&state, peer, &headers),
+        usage_key: crate::usage_key(&state, &headers).to_string(),
+        verbosity: params.verbosity.unwrap_or(state.service.response_verbosity),
+    };
+    upgrade
+        .max_message_size(state.service.max_body_bytes)
+        .on_upgrade(move |socket| serve(state, Arc::new(client), socket))
+}
+
+/// Reads events and writes results until the client goes away. Results still being
+/// computed then are dropped, but events already being stored are committed in full,
+/// as for an HTTP request that times out.
+async fn serve(state: Arc<AppState>, client: Arc<Client>, mut socket: WebSocket) {
+    gauge!("stream_connections").increment(1.0);
+    let limit = state.service.stream_max_in_flight;
+    let mut pending = FuturesOrdered::new();
+    loop {
+        tokio::select! {
+            Some(reply) = pending.next() => {
+                if socket.send(Message::Text(reply)).await.is_err() {
+                    break;
+                }
+            }
+            message = socket.recv(), if pending.len() < limit => {
+                let decoded = match message {
+                    Some(Ok(Message::Text(text))) => {
+                        let received = UsageCounts {
+                            bytes: text.len() as u64,
+                            ..UsageCounts::default()
+                        };
+                        state.usage.record(&client.usage_key, received, state.clock.now());
+                        decode(&state, &text)
+                    }
+                    Some(Ok(Message::Binary(_))) => Err(AppError::BadRequest("Send events as JSON text messages".to_string())),
+                    // Pings are answered by the socket itself.
+                    Some(Ok(Message::Ping(_) | Message::Pong(_))) => continue,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                };
+                pending.push_back(answer(state.clone(), client.clone(), decoded));
+            }
+        }
+    }
+    if !pending.is_empty() {
+        counter!("stream_results_dropped_total").increment(pending.len() as u64);
+    }
+    // Sends the reply to the client's close frame, which the socket queues but only
+    // writes on a flush. Fails harmlessly when the connection is already gone.
+    let _ = socket.flush().await;
+    gauge!("stream_connections").decrement(1.0);
+}
+
+/// An event message, checked against `EVENT_SCHEMA_PATH` like an HTTP body.
+fn decode(state: &AppState, text: &str) -> Result<UserEvent, AppError> {
+    let invalid = |e: serde_json::Error| match e.classify() {
+        serde_json::error::Category::Data => AppError::Validation(format!("Invalid event: {}", e)),
+        _ => AppError::BadRequest(format!("Failed to parse the message as JSON: {}", e)),
+    };
+    let Some(schema) = &state.event_schema else {
+        return serde_json::from_str(text).map_err(invalid);
+    };
+    let body: Value = serde_json::from_str(text).map_err(invalid)?;
+    schema.check(&body)?;
+    serde_json::from_value(body).map_err(invalid)
+}
+
+/// Scores one event under the same analysis budget and deadline as an HTTP request,
+/// and renders the result, or the error body, as the message to send back.
+async fn answer(state: Arc<AppState>, client: Arc<Client>, event: Result<UserEvent, AppError>) -> String {
+    let scored = async {
+        let event = event?;
+        let _admitted = state.admission.admit()?;
+        let scoring = crate::score_event(&state, event, client.derived_ip, &client.usage_key);
+        let result = match state.service.request_timeout {
+            Some(deadline) => tokio::time::timeout(deadline, scoring).await.map_err(|_| {
+                counter!("requests_timed_out_total").increment(1);
+                AppError::Timeout(format!("Event took longer than {}ms", deadline.as_millis()))
+            })??,
+            None => scoring.await?,
+        };
+        let response = AnalyzeResponse::new(result, client.verbosity);
+        serde_json::to_string(&response).map_err(|e| AppError::Internal(e.to_string()))
+    };
+    // An error body is a kind and a message, so it always serializes.
+    scored.await.unwrap_or_else(|e| serde_json::to_string(&e.report()).unwrap_or_default())
+}
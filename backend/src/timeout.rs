@@ -0,0 +1,27 @@
+use axum::{
+    extract::State,
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use metrics::counter;
+use std::sync::Arc;
+
+use crate::{AppError, AppState};
+
+// --- REQUEST DEADLINE ---
+/// Answers `504` once a request has run for `REQUEST_TIMEOUT_MS` without producing a
+/// response, dropping the handler. Streamed bodies, like exports, only have to start
+/// within the deadline.
+pub async fn enforce_deadline<B>(State(state): State<Arc<AppState>>, request: Request<B>, next: Next<B>) -> Response {
+    let Some(deadline) = state.service.request_timeout else {
+        return next.run(request).await;
+    };
+    match tokio::time::timeout(deadline, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            counter!("requests_timed_out_total").increment(1);
+            AppError::Timeout(format!("Request took longer than {}ms", deadline.as_millis())).into_response()
+        }
+    }
+}
@@ -0,0 +1,425 @@
+use async_trait::async_trait;
+use axum::{
+    body::{Body, Bytes},
+    extract::{FromRequest, State},
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json, RequestExt,
+};
+use chrono::{DateTime, Duration, Utc};
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::warn;
+
+use crate::config::UsageConfig;
+use crate::error::AppQuery;
+use crate::store::StoreError;
+use crate::{AppError, AppState};
+
+/// Traffic without the key header is counted under this key.
+pub const ANONYMOUS_KEY: &str = "anonymous";
+/// Keys past `USAGE_MAX_KEYS`, and header values no key could be, are counted under this.
+pub const OTHER_KEY: &str = "other";
+/// Longer header values are counted as `other` rather than kept.
+const MAX_KEY_LEN: usize = 128;
+/// How often counts are written to the usage store, when there is one.
+pub const FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+const HOUR_SECS: i64 = 3_600;
+
+// --- USAGE ACCOUNTING ---
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageCounts {
+    /// Events scored.
+    pub events: u64,
+    /// Of those, the ones flagged.
+    pub flagged: u64,
+    /// Request body bytes received, scored or not.
+    pub bytes: u64,
+}
+
+impl UsageCounts {
+    fn add(&mut self, other: UsageCounts) {
+        self.events += other.events;
+        self.flagged += other.flagged;
+        self.bytes += other.bytes;
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == UsageCounts::default()
+    }
+}
+
+/// Where hourly counts outlive the process: Redis when `REDIS_URL` is set, so restarts
+/// don't lose what was billed and replicas add up to one total.
+#[async_trait]
+pub trait UsageStore: Send + Sync {
+    /// Adds `counts` to the key's `hour` (unix time / 3600).
+    async fn add(&self, key: &str, hour: i64, counts: UsageCounts) -> Result<(), StoreError>;
+
+    /// The key's non-empty hours in `from..to`, oldest first.
+    async fn series(&self, key: &str, from: i64, to: i64) -> Result<Vec<(i64, UsageCounts)>, StoreError>;
+}
+
+#[derive(Default)]
+struct Counters {
+    events: AtomicU64,
+    flagged: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl Counters {
+    fn load(&self) -> UsageCounts {
+        UsageCounts {
+            events: self.events.load(Ordering::Relaxed),
+            flagged: self.flagged.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    fn add(&self, counts: UsageCounts) {
+        for (counter, n) in [
+            (&self.events, counts.events),
+            (&self.flagged, counts.flagged),
+            (&self.bytes, counts.bytes),
+        ] {
+            if n > 0 {
+                counter.fetch_add(n, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn subtract(&self, counts: UsageCounts) {
+        self.events.fetch_sub(counts.events, Ordering::Relaxed);
+        self.flagged.fetch_sub(counts.flagged, Ordering::Relaxed);
+        self.bytes.fetch_sub(counts.bytes, Ordering::Relaxed);
+    }
+
+    fn reset(&self) {
+        self.events.store(0, Ordering::Relaxed);
+        self.flagged.store(0, Ordering::Relaxed);
+        self.bytes.store(0, Ordering::Relaxed);
+    }
+}
+
+/// One hour of one key's traffic.
+struct Slot {
+    /// `unix time / 3600` of the hour; a stale slot is reset before reuse.
+    hour: AtomicI64,
+    counted: Counters,
+    /// What of `counted` is already in the usage store.
+    flushed: Counters,
+}
+
+impl Slot {
+    /// Counts not yet in the usage store.
+    fn unflushed(&self) -> UsageCounts {
+        let counted = self.counted.load();
+        let flushed = self.flushed.load();
+        UsageCounts {
+            events: counted.events.saturating_sub(flushed.events),
+            flagged: counted.flagged.saturating_sub(flushed.flagged),
+            bytes: counted.bytes.saturating_sub(flushed.bytes),
+        }
+    }
+}
+
+/// A ring of hourly slots covering the retention period. Counting is a few atomic
+/// adds; only the first count of a new hour takes the lock, to reset its slot.
+struct KeyUsage {
+    slots: Box<[Slot]>,
+    rollover: Mutex<()>,
+}
+
+impl KeyUsage {
+    fn new(hours: usize) -> Self {
+        let slots = (0..hours)
+            .map(|_| Slot {
+                hour: AtomicI64::new(i64::MIN),
+                counted: Counters::default(),
+                flushed: Counters::default(),
+            })
+            .collect();
+        Self {
+            slots,
+            rollover: Mutex::new(()),
+        }
+    }
+
+    fn index(&self, hour: i64) -> usize {
+        hour.rem_euclid(self.slots.len() as i64) as usize
+    }
+
+    /// The slot counting `hour`, reset first if it still holds an older hour. `None`
+    /// when it already holds a newer one, i.e. `hour` is past retention.
+    fn slot(&self, hour: i64) -> Option<&Slot> {
+        let slot = &self.slots[self.index(hour)];
+        if slot.hour.load(Ordering::Acquire) != hour {
+            let _rollover = self.rollover.lock().unwrap();
+            let current = slot.hour.load(Ordering::Acquire);
+            if current > hour {
+                return None;
+            }
+            if current < hour {
+                slot.counted.reset();
+                slot.flushed.reset();
+                slot.hour.store(hour, Ordering::Release);
+            }
+        }
+        Some(slot)
+    }
+
+    /// The slot holding `hour`, if it has been counted.
+    fn counted(&self, hour: i64) -> Option<&Slot> {
+        let slot = &self.slots[self.index(hour)];
+        (slot.hour.load(Ordering::Acquire) == hour).then_some(slot)
+    }
+
+    fn idle_since(&self, hour: i64) -> bool {
+        self.slots.iter().all(|slot| slot.hour.load(Ordering::Acquire) < hour)
+    }
+}
+
+/// Per-key hourly counts of events scored, flagged, and body bytes received on the
+/// events endpoints, for billing teams by volume. Kept in this process for the
+/// retention period, and written to the usage store every `FLUSH_INTERVAL` when there
+/// is one.
+pub struct Usage {
+    config: UsageConfig,
+    keys: RwLock<HashMap<String, Arc<KeyUsage>>>,
+    store: Option<Arc<dyn UsageStore>>,
+}
+
+fn hour_of(at: DateTime<Utc>) -> i64 {
+    at.timestamp().div_euclid(HOUR_SECS)
+}
+
+fn hour_start(hour: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(hour * HOUR_SECS, 0).unwrap_or_default()
+}
+
+impl Usage {
+    pub fn new(config: UsageConfig, store: Option<Arc<dyn UsageStore>>) -> Self {
+        Self {
+            config,
+            keys: RwLock::new(HashMap::new()),
+            store,
+        }
+    }
+
+    fn retention_hours(&self) -> usize {
+        self.config.retention_days as usize * 24
+    }
+
+    /// The key a request came in under, from its key header's value.
+    pub fn key<'a>(&self, value: Option<&'a str>) -> &'a str {
+        match value.map(str::trim) {
+            None | Some("") => ANONYMOUS_KEY,
+            Some(key) if key.len() > MAX_KEY_LEN || !key.bytes().all(|b| b.is_ascii_graphic()) => OTHER_KEY,
+            Some(key) => key,
+        }
+    }
+
+    pub fn key_header(&self) -> &axum::http::HeaderName {
+        &self.config.key_header
+    }
+
+    fn counters(&self, key: &str) -> Arc<KeyUsage> {
+        if let Some(usage) = self.keys.read().unwrap().get(key) {
+            return usage.clone();
+        }
+        let mut keys = self.keys.write().unwrap();
+        let key = if keys.len() >= self.config.max_keys && !keys.contains_key(key) {
+            counter!("usage_keys_overflowed_total").increment(1);
+            OTHER_KEY
+        } else {
+            key
+        };
+        keys.entry(key.to_string())
+            .or_insert_with(|| Arc::new(KeyUsage::new(self.retention_hours())))
+            .clone()
+    }
+
+    pub fn record(&self, key: &str, counts: UsageCounts, at: DateTime<Utc>) {
+        if let Some(slot) = self.counters(key).slot(hour_of(at)) {
+            slot.counted.add(counts);
+        }
+    }
+
+    /// The key's hours overlapping `from..to`, from the usage store plus what this
+    /// instance hasn't written to it yet, or from this instance alone without one.
+    pub async fn report(&self, key: &str, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<UsageReport, StoreError> {
+        let (from_hour, to_hour) = (hour_of(from), hour_of(to - Duration::nanoseconds(1)) + 1);
+        let mut hours: HashMap<i64, UsageCounts> = match &self.store {
+            Some(store) => store.series(key, from_hour, to_hour).await?.into_iter().collect(),
+            None => HashMap::new(),
+        };
+        if let Some(usage) = self.keys.read().unwrap().get(key) {
+            for hour in from_hour..to_hour {
+                if let Some(slot) = usage.counted(hour) {
+                    let local = match self.store {
+                        Some(_) => slot.unflushed(),
+                        None => slot.counted.load(),
+                    };
+                    hours.entry(hour).or_default().add(local);
+                }
+            }
+        }
+
+        let mut series: Vec<_> = hours.into_iter().filter(|(_, counts)| !counts.is_empty()).collect();
+        series.sort_by_key(|(hour, _)| *hour);
+        let mut totals = UsageCounts::default();
+        for (_, counts) in &series {
+            totals.add(*counts);
+        }
+        Ok(UsageReport {
+            key: key.to_string(),
+            from,
+            to,
+            hours: series
+                .into_iter()
+                .map(|(hour, counts)| HourlyUsage {
+                    hour: hour_start(hour),
+                    counts,
+                })
+                .collect(),
+            totals,
+        })
+    }
+
+    /// Writes every count not yet in the usage store. A failed write is put back, so
+    /// the next flush tries it again.
+    pub async fn flush(&self) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        let keys: Vec<_> = self
+            .keys
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(key, usage)| (key.clone(), usage.clone()))
+            .collect();
+        for (key, usage) in keys {
+            for slot in usage.slots.iter() {
+                let hour = slot.hour.load(Ordering::Acquire);
+                let delta = slot.unflushed();
+                if hour == i64::MIN || delta.is_empty() {
+                    continue;
+                }
+                // Marked first, so a report running alongside never counts it twice.
+                slot.flushed.add(delta);
+                if let Err(e) = store.add(&key, hour, delta).await {
+                    slot.flushed.subtract(delta);
+                    warn!("Failed to write usage counts: {}", e);
+                    counter!("usage_flush_failures_total").increment(1);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Forgets keys with nothing counted within the retention period.
+    pub fn sweep(&self, now: DateTime<Utc>) {
+        let oldest = hour_of(now) - self.retention_hours() as i64 + 1;
+        self.keys.write().unwrap().retain(|_, usage| !usage.idle_since(oldest));
+    }
+
+    pub fn retention(&self) -> Duration {
+        Duration::days(i64::from(self.config.retention_days))
+    }
+}
+
+/// Writes usage counts to the store every `FLUSH_INTERVAL`.
+pub async fn run_flush(usage: Arc<Usage>) {
+    let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+    loop {
+        interval.tick().await;
+        usage.flush().await;
+    }
+}
+
+/// Layer for the events routes: counts each request body against the request's key.
+/// Events and flags are counted by the handlers, once scored.
+pub async fn meter_bytes(State(state): State<Arc<AppState>>, request: Request<Body>, next: Next<Body>) -> Response {
+    let key = state
+        .usage
+        .key(request.headers().get(state.usage.key_header()).and_then(|value| value.to_str().ok()))
+        .to_string();
+
+    // Buffered under the route's body limit, as the handler itself would.
+    let (parts, body) = match request.with_limited_body() {
+        Ok(request) => {
+            let (parts, body) = request.into_parts();
+            (parts, Bytes::from_request(Request::new(body), &()).await)
+        }
+        Err(request) => {
+            let (parts, body) = request.into_parts();
+            (parts, Bytes::from_request(Request::new(body), &()).await)
+        }
+    };
+    let body = match body {
+        Ok(body) => body,
+        Err(rejection) => return AppError::from(rejection).into_response(),
+    };
+
+    let counts = UsageCounts {
+        bytes: body.len() as u64,
+        ..UsageCounts::default()
+    };
+    state.usage.record(&key, counts, state.clock.now());
+    next.run(Request::from_parts(parts, Body::from(body))).await
+}
+
+// --- USAGE HANDLER ---
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HourlyUsage {
+    /// Start of the hour.
+    hour: DateTime<Utc>,
+    #[serde(flatten)]
+    counts: UsageCounts,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageReport {
+    key: String,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    /// Hours with any traffic, oldest first.
+    hours: Vec<HourlyUsage>,
+    totals: UsageCounts,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsageParams {
+    key: String,
+    /// 24 hours before `to` when omitted.
+    from: Option<DateTime<Utc>>,
+    /// Now when omitted.
+    to: Option<DateTime<Utc>>,
+}
+
+/// Usage of one key per hour, plus totals, for billing.
+pub async fn usage_handler(
+    State(state): State<Arc<AppState>>,
+    AppQuery(params): AppQuery<UsageParams>,
+) -> Result<Json<UsageReport>, AppError> {
+    let to = params.to.unwrap_or_else(|| state.clock.now());
+    let from = params.from.unwrap_or(to - Duration::hours(24));
+    if from >= to {
+        return Err(AppError::Validation("`from` must be before `to`".to_string()));
+    }
+    if to - from > state.usage.retention() {
+        return Err(AppError::Validation(format!(
+            "Usage is kept for {} days; ask for a shorter range",
+            state.usage.retention().num_days()
+        )));
+    }
+    Ok(Json(state.usage.report(&params.key, from, to).await?))
+}
@@ -0,0 +1,166 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tracing::info;
+
+use crate::entity_reputation::Entity;
+use crate::ip;
+use crate::{AppError, AppState};
+
+// --- RIGHT TO ERASURE ---
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErasureReport {
+    user_id: String,
+    events_deleted: usize,
+    sessions_affected: usize,
+    flag_count_cleared: bool,
+    activity_profile_deleted: bool,
+    reputation_reset: bool,
+    payments_deleted: usize,
+    flagged_sessions_deleted: usize,
+    webhook_deliveries_deleted: usize,
+    session_reputations_reset: usize,
+    /// Links between the user's sessions and the device fingerprints their events carried.
+    fingerprint_links_deleted: usize,
+    /// The user's IPs dropped from the per-IP stats totals, with any other traffic from
+    /// them in the same buckets.
+    stats_ips_deleted: usize,
+    /// Anonymous events left in the affected sessions. They carry no user id, so they
+    /// can't be attributed to this user and are not deleted.
+    anonymous_events_retained: usize,
+}
+
+/// Removes everything stored against a user id: their events in every session, their
+/// repeat-offender count, their activity profile, their reputation, their payment
+/// totals, their flagged-session records, logged webhook deliveries about them, the
+/// reputations and device fingerprint links of their sessions, and their IPs' stats.
+/// The user's sessions come from the store's user index, and are processed one at a
+/// time so the store is never locked for the whole sweep.
+pub async fn delete_user_data_handler(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<ErasureReport>, AppError> {
+    let session_ids = state.event_store.user_sessions(&user_id).await?;
+
+    let mut report = ErasureReport {
+        user_id,
+        events_deleted: 0,
+        sessions_affected: 0,
+        flag_count_cleared: false,
+        activity_profile_deleted: false,
+        reputation_reset: false,
+        payments_deleted: 0,
+        flagged_sessions_deleted: 0,
+        webhook_deliveries_deleted: 0,
+        session_reputations_reset: 0,
+        fingerprint_links_deleted: 0,
+        stats_ips_deleted: 0,
+        anonymous_events_retained: 0,
+    };
+    let mut ips = HashSet::new();
+    for session_id in session_ids {
+        let erasure = state.event_store.erase_user_events(&session_id, &report.user_id).await?;
+        if erasure.deleted > 0 {
+            report.events_deleted += erasure.deleted;
+            report.sessions_affected += 1;
+            report.anonymous_events_retained += erasure.anonymous_retained;
+            if let Some(cache) = &state.session_cache {
+                cache.forget(&session_id);
+            }
+            report.session_reputations_reset += usize::from(state.session_reputation.forget(&session_id));
+            report.fingerprint_links_deleted += state.fingerprints.unlink_session(&session_id);
+            ips.extend(erasure.ips);
+        }
+        tokio::task::yield_now().await;
+    }
+
+    report.flag_count_cleared = state.offenders.forget(&report.user_id);
+    report.activity_profile_deleted = state.activity.forget(&report.user_id).await?;
+    report.reputation_reset = state.entity_reputations.reset(Entity::User, &report.user_id).await?;
+    report.payments_deleted = state.payments.forget(&report.user_id);
+    report.flagged_sessions_deleted = state.flags.forget_user(&report.user_id);
+    if let Some(webhooks) = &state.webhooks {
+        report.webhook_deliveries_deleted = webhooks.forget_user(&report.user_id);
+    }
+    state.search.forget_user(&report.user_id);
+    state.linked_sessions.forget(&report.user_id);
+    state.rates.forget_user(&report.user_id);
+    let prefix = state.scoring().ipv6_counter_prefix;
+    let ips = ips.iter().map(|ip| ip::counter_key(ip, prefix).into_owned()).collect();
+    report.stats_ips_deleted = state.stats.forget_ips(&ips);
+    if let Some(cache) = &state.response_cache {
+        cache.clear();
+    }
+
+    info!(
+        "Erased user data: events = {}, sessions = {}, payments = {}",
+        report.events_deleted, report.sessions_affected, report.payments_deleted
+    );
+    Ok(Json(report))
+}
+
+// --- USER SESSIONS ---
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserSessions {
+    user_id: String,
+    /// Most recently scored first; sessions this instance hasn't scored come last.
+    sessions: Vec<UserSession>,
+}
+
+/// One stored session with events from the user, and its latest score from this
+/// instance's search index. The score fields are `null` for sessions it hasn't scored.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserSession {
+    session_id: String,
+    latest_score: Option<i32>,
+    flagged: Option<bool>,
+    reason_codes: Vec<String>,
+    last_event: Option<DateTime<Utc>>,
+    last_scored_at: Option<DateTime<Utc>>,
+}
+
+/// The stored sessions holding events with the user's id, from the store's user index
+/// rather than a walk over every session.
+pub async fn user_sessions_handler(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<String>,
+) -> Result<Json<UserSessions>, AppError> {
+    let mut sessions: Vec<UserSession> = state
+        .event_store
+        .user_sessions(&user_id)
+        .await?
+        .into_iter()
+        .map(|session_id| match state.search.session(&session_id) {
+            Some(scored) => UserSession {
+                session_id,
+                latest_score: Some(scored.latest_score),
+                flagged: Some(scored.flagged),
+                reason_codes: scored.reason_codes,
+                last_event: Some(scored.last_event),
+                last_scored_at: Some(scored.last_scored_at),
+            },
+            None => UserSession {
+                session_id,
+                latest_score: None,
+                flagged: None,
+                reason_codes: Vec::new(),
+                last_event: None,
+                last_scored_at: None,
+            },
+        })
+        .collect();
+    sessions.sort_by(|a, b| {
+        b.last_scored_at
+            .cmp(&a.last_scored_at)
+            .then_with(|| a.session_id.cmp(&b.session_id))
+    });
+    Ok(Json(UserSessions { user_id, sessions }))
+}
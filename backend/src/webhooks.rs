@@ -0,0 +1,295 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::config::{Decision, WebhookConfig};
+use crate::error::AppQuery;
+use crate::{AppError, AppState, FraudCheckResult, UserEvent};
+
+/// Wait before an automatic retry; doubled for each attempt after that.
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+/// Deliveries listed per request unless the request asks for fewer.
+const MAX_LISTED_DELIVERIES: usize = 1_000;
+
+// --- WEBHOOK DELIVERIES ---
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    /// Not yet sent, or waiting to be retried.
+    Pending,
+    Delivered,
+    /// Out of attempts; only a manual retry sends it again.
+    Failed,
+}
+
+/// What the receiver gets for one flagged event. The body is the same on every attempt,
+/// so receivers can deduplicate on `deliveryId` or `sessionId` and `eventTimestamp`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Notification<'a> {
+    delivery_id: u64,
+    session_id: &'a str,
+    user_id: Option<&'a str>,
+    event_timestamp: DateTime<Utc>,
+    checked_at: DateTime<Utc>,
+    fraud_score: i32,
+    raw_score: i32,
+    confidence: u8,
+    decision: Decision,
+    reason_codes: Vec<&'a Cow<'static, str>>,
+}
+
+/// One notification and how sending it has gone.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Delivery {
+    id: u64,
+    session_id: String,
+    user_id: Option<String>,
+    event_timestamp: DateTime<Utc>,
+    status: DeliveryStatus,
+    attempts: u32,
+    /// HTTP status of the last attempt that got a response.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_code: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_error: Option<String>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    #[serde(skip)]
+    body: Arc<str>,
+}
+
+/// Sends a notification to `WEBHOOK_URL` for every flagged event, from background
+/// tasks so a slow receiver never slows scoring, and keeps a log of each delivery for
+/// operators to inspect and retry. The log is held in memory per instance, bounded by
+/// size and age.
+pub struct Webhooks {
+    config: WebhookConfig,
+    http: reqwest::Client,
+    /// Oldest first.
+    log: Mutex<VecDeque<Delivery>>,
+    next_id: AtomicU64,
+}
+
+impl Webhooks {
+    pub fn new(config: WebhookConfig) -> Result<Self, reqwest::Error> {
+        Ok(Self {
+            http: reqwest::Client::builder().timeout(config.timeout).build()?,
+            log: Mutex::new(VecDeque::new()),
+            next_id: AtomicU64::new(1),
+            config,
+        })
+    }
+
+    /// Logs a delivery for the flagged result and sends it in the background, retrying
+    /// up to `WEBHOOK_MAX_ATTEMPTS` times.
+    pub fn notify(self: &Arc<Self>, event: &UserEvent, result: &FraudCheckResult) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let notification = Notification {
+            delivery_id: id,
+            session_id: &result.session_id,
+            user_id: event.user_id.as_deref(),
+            event_timestamp: event.timestamp,
+            checked_at: result.check_timestamp,
+            fraud_score: result.fraud_score,
+            raw_score: result.raw_score,
+            confidence: result.confidence,
+            decision: result.decision,
+            reason_codes: result.breakdown.iter().map(|hit| &hit.code).collect(),
+        };
+        let body = match serde_json::to_string(&notification) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Failed to serialize webhook notification: {}", e);
+                return;
+            }
+        };
+
+        let now = Utc::now();
+        {
+            let mut log = self.log.lock().unwrap();
+            log.push_back(Delivery {
+                id,
+                session_id: result.session_id.clone(),
+                user_id: event.user_id.clone(),
+                event_timestamp: event.timestamp,
+                status: DeliveryStatus::Pending,
+                attempts: 0,
+                response_code: None,
+                last_error: None,
+                created_at: now,
+                updated_at: now,
+                body: body.into(),
+            });
+            self.evict(&mut log, now);
+        }
+
+        let webhooks = self.clone();
+        tokio::spawn(async move {
+            let mut backoff = RETRY_BACKOFF;
+            for attempt in 1..=webhooks.config.max_attempts {
+                let last = attempt == webhooks.config.max_attempts;
+                match webhooks.attempt(id, last).await {
+                    Some(delivery) if delivery.status == DeliveryStatus::Pending => {
+                        tokio::time::sleep(backoff).await;
+                        backoff *= 2;
+                    }
+                    _ => break,
+                }
+            }
+        });
+    }
+
+    /// Sends a failed delivery once more, with the same body, and returns how it went.
+    pub async fn retry(&self, id: u64) -> Result<Delivery, AppError> {
+        {
+            let mut log = self.log.lock().unwrap();
+            let delivery = log
+                .iter_mut()
+                .find(|delivery| delivery.id == id)
+                .ok_or_else(|| AppError::NotFound(format!("No webhook delivery {}", id)))?;
+            if delivery.status != DeliveryStatus::Failed {
+                return Err(AppError::Validation(format!(
+                    "Webhook delivery {} is {}; only failed deliveries can be retried",
+                    id,
+                    status_name(delivery.status)
+                )));
+            }
+            delivery.status = DeliveryStatus::Pending;
+        }
+        self.attempt(id, true)
+            .await
+            .ok_or_else(|| AppError::NotFound(format!("Webhook delivery {} left the log during the retry", id)))
+    }
+
+    /// One attempt at a logged delivery. A failed attempt leaves it pending for another
+    /// try, or marks it failed when `last`. `None` when it is no longer in the log.
+    async fn attempt(&self, id: u64, last: bool) -> Option<Delivery> {
+        let (body, attempt) = {
+            let mut log = self.log.lock().unwrap();
+            let delivery = log.iter_mut().find(|delivery| delivery.id == id)?;
+            delivery.attempts += 1;
+            (delivery.body.clone(), delivery.attempts)
+        };
+
+        let outcome = self
+            .http
+            .post(&self.config.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-Fraud-Delivery-Id", id)
+            .header("X-Fraud-Delivery-Attempt", attempt)
+            .body(body.to_string())
+            .send()
+            .await;
+        let (response_code, error) = match outcome {
+            Ok(response) if response.status().is_success() => (Some(response.status().as_u16()), None),
+            Ok(response) => (Some(response.status().as_u16()), Some(format!("receiver answered {}", response.status()))),
+            Err(e) => (None, Some(e.to_string())),
+        };
+
+        let mut log = self.log.lock().unwrap();
+        let delivery = log.iter_mut().find(|delivery| delivery.id == id)?;
+        delivery.updated_at = Utc::now();
+        delivery.response_code = response_code.or(delivery.response_code);
+        delivery.status = match (&error, last) {
+            (None, _) => DeliveryStatus::Delivered,
+            (Some(_), false) => DeliveryStatus::Pending,
+            (Some(_), true) => DeliveryStatus::Failed,
+        };
+        if let Some(e) = &error {
+            warn!("Webhook delivery {} attempt {} failed: {}", id, attempt, e);
+        }
+        delivery.last_error = error;
+        if delivery.status != DeliveryStatus::Pending {
+            counter!("webhook_deliveries_total", "status" => status_name(delivery.status)).increment(1);
+        }
+        Some(delivery.clone())
+    }
+
+    /// Newest first, optionally only those in `status`.
+    fn list(&self, status: Option<DeliveryStatus>, limit: usize) -> Vec<Delivery> {
+        let mut log = self.log.lock().unwrap();
+        self.evict(&mut log, Utc::now());
+        log.iter()
+            .rev()
+            .filter(|delivery| status.is_none_or(|status| delivery.status == status))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// Drops every delivery about the user, returning how many there were.
+    pub fn forget_user(&self, user_id: &str) -> usize {
+        let mut log = self.log.lock().unwrap();
+        let before = log.len();
+        log.retain(|delivery| delivery.user_id.as_deref() != Some(user_id));
+        before - log.len()
+    }
+
+    fn evict(&self, log: &mut VecDeque<Delivery>, now: DateTime<Utc>) {
+        let max_age = chrono::Duration::from_std(self.config.log_max_age).unwrap_or(chrono::Duration::MAX);
+        while log
+            .front()
+            .is_some_and(|oldest| log.len() > self.config.log_size || oldest.created_at < now - max_age)
+        {
+            log.pop_front();
+        }
+    }
+}
+
+fn status_name(status: DeliveryStatus) -> &'static str {
+    match status {
+        DeliveryStatus::Pending => "pending",
+        DeliveryStatus::Delivered => "delivered",
+        DeliveryStatus::Failed => "failed",
+    }
+}
+
+// --- DELIVERY HANDLERS ---
+#[derive(Debug, Deserialize)]
+pub struct DeliveryParams {
+    status: Option<DeliveryStatus>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeliveryList {
+    deliveries: Vec<Delivery>,
+}
+
+fn webhooks(state: &AppState) -> Result<&Arc<Webhooks>, AppError> {
+    state
+        .webhooks
+        .as_ref()
+        .ok_or_else(|| AppError::NotFound("Webhooks are not configured; set WEBHOOK_URL".to_string()))
+}
+
+/// Lists logged deliveries, newest first, for finding the ones a receiver missed.
+pub async fn list_deliveries_handler(
+    State(state): State<Arc<AppState>>,
+    AppQuery(params): AppQuery<DeliveryParams>,
+) -> Result<Json<DeliveryList>, AppError> {
+    let limit = params.limit.unwrap_or(MAX_LISTED_DELIVERIES).min(MAX_LISTED_DELIVERIES);
+    Ok(Json(DeliveryList {
+        deliveries: webhooks(&state)?.list(params.status, limit),
+    }))
+}
+
+/// Sends a failed delivery again and reports the outcome.
+pub async fn retry_delivery_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<u64>,
+) -> Result<Json<Delivery>, AppError> {
+    Ok(Json(webhooks(&state)?.retry(id).await?))
+}
@@ -0,0 +1,403 @@
+use async_trait::async_trait;
+use metrics::counter;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{info, warn};
+
+use crate::config::WriteBehindConfig;
+use crate::store::{EventStore, SessionErasure, SessionHistory, StoreError, StoreSize};
+use crate::UserEvent;
+
+// --- WRITE-BEHIND EVENT STORE ---
+/// How often cached sessions are checked for having gone idle.
+const CACHE_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+enum WriteOp {
+    Push(UserEvent),
+    /// Answered once every write queued before it has been attempted.
+    Flush(oneshot::Sender<()>),
+}
+
+/// A cached session and when it last saw an event.
+struct CachedSession {
+    events: SessionHistory,
+    touched: Instant,
+}
+
+/// Scores against an in-process cache of recent sessions and writes events to the
+/// backing store from a background task, in batches, so requests never wait on the
+/// store's write latency. A session is loaded from the backing store the first time
+/// this instance sees it and kept in sync from then on, so events other replicas add
+/// to it afterwards aren't seen until it idles out of the cache.
+pub struct WriteBehindStore {
+    inner: Arc<dyn EventStore>,
+    cache: Arc<Mutex<HashMap<String, CachedSession>>>,
+    sender: mpsc::Sender<WriteOp>,
+    max_events: usize,
+    /// Set while the queue is full, so the backlog is logged once rather than per event.
+    backlogged: AtomicBool,
+}
+
+impl WriteBehindStore {
+    /// Starts the writer task. Cached sessions are dropped after `idle` without events.
+    pub fn start(inner: Arc<dyn EventStore>, config: &WriteBehindConfig, max_events: usize, idle: Duration) -> Self {
+        let (sender, receiver) = mpsc::channel(config.queue_size);
+        let cache = Arc::new(Mutex::new(HashMap::new()));
+        tokio::spawn(run_writer(inner.clone(), receiver, config.clone(), cache.clone(), idle));
+
+        Self {
+            inner,
+            cache,
+            sender,
+            max_events,
+            backlogged: AtomicBool::new(false),
+        }
+    }
+
+    fn enqueue(&self, event: UserEvent) {
+        match self.sender.try_send(WriteOp::Push(event)) {
+            Ok(()) => {
+                if self.backlogged.swap(false, Ordering::Relaxed) {
+                    info!("Event write queue drained; persisting events again");
+                }
+            }
+            Err(_) => {
+                counter!("event_writes_dropped_total", "reason" => "queue_full").increment(1);
+                if !self.backlogged.swap(true, Ordering::Relaxed) {
+                    warn!("Event write queue is full; events are scored but not persisted until it drains");
+                }
+            }
+        }
+    }
+
+    /// Waits until every event queued so far has been handed to the backing store.
+    async fn flush(&self) {
+        let (done, flushed) = oneshot::channel();
+        if self.sender.send(WriteOp::Flush(done)).await.is_ok() {
+            let _ = flushed.await;
+        }
+    }
+}
+
+#[async_trait]
+impl EventStore for WriteBehindStore {
+    async fn push(&self, event: UserEvent) -> Result<SessionHistory, StoreError> {
+        let cached = self.cache.lock().unwrap().contains_key(&event.session_id);
+        let loaded = match cached {
+            true => None,
+            false => Some(self.inner.session_events(&event.session_id).await?),
+        };
+
+        let history = {
+            let mut cache = self.cache.lock().unwrap();
+            // Another request may have loaded the session while this one was reading it.
+            let session = cache.entry(event.session_id.clone()).or_insert_with(|| CachedSession {
+                events: loaded.unwrap_or_default(),
+                touched: Instant::now(),
+            });
+            while session.events.len() >= self.max_events {
+                session.events.pop_front();
+            }
+            session.events.push_back(Arc::new(event.clone()));
+            session.touched = Instant::now();
+            session.events.clone()
+        };
+        self.enqueue(event);
+        Ok(history)
+    }
+
+    async fn session_events(&self, session_id: &str) -> Result<SessionHistory, StoreError> {
+        let cached = self
+            .cache
+            .lock()
+            .unwrap()
+            .get(session_id)
+            .map(|session| session.events.clone());
+        match cached {
+            Some(events) => Ok(events),
+            None => self.inner.session_events(session_id).await,
+        }
+    }
+
+    /// Flushes first, so the event is in the backing store to be amended there. An event
+    /// only in the cache (its write was dropped) is amended in the cache alone.
+    async fn amend(
+        &self,
+        session_id: &str,
+        event_id: &str,
+        metadata: &HashMap<String, String>,
+    ) -> Result<Option<SessionHistory>, StoreError> {
+        self.flush().await;
+        let stored = self.inner.amend(session_id, event_id, metadata).await?;
+
+        let mut cache = self.cache.lock().unwrap();
+        let Some(session) = cache.get_mut(session_id) else {
+            return Ok(stored);
+        };
+        let Some(index) = session.events.iter().rposition(|event| event.event_id() == Some(event_id)) else {
+            return Ok(stored);
+        };
+        let amended = Arc::new(session.events[index].amended(metadata));
+        session.events.to_mut()[index] = amended;
+        Ok(Some(session.events.clone()))
+    }
+
+    async fn session_ids(&self) -> Result<Vec<String>, StoreError> {
+        let mut ids = self.inner.session_ids().await?;
+        ids.extend(self.cache.lock().unwrap().keys().cloned());
+        ids.sort_unstable();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    /// The backing store's, once queued events have been written.
+    async fn user_sessions(&self, user_id: &str) -> Result<Vec<String>, StoreError> {
+        self.flush().await;
+        self.inner.user_sessions(user_id).await
+    }
+
+    /// Flushes first, so no queued event of the user's is written after the erasure.
+    async fn erase_user_events(&self, session_id: &str, user_id: &str) -> Result<SessionErasure, StoreError> {
+        self.flush().await;
+        let erasure = self.inner.erase_user_events(session_id, user_id).await?;
+
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(session) = cache.get_mut(session_id) {
+            session.events.to_mut().retain(|event| event.user_id.as_deref() != Some(user_id));
+            if session.events.is_empty() {
+                cache.remove(session_id);
+            }
+        }
+        Ok(erasure)
+    }
+
+    /// The backing store's counts, once queued events have been written.
+    async fn size(&self) -> Result<StoreSize, StoreError> {
+        self.flush().await;
+        self.inner.size().await
+    }
+}
+
+/// Hands queued events to the backing store in batches of up to `batch_size`, or
+/// whatever has queued once `flush_interval` passes, and drops idle cached sessions.
+async fn run_writer(
+    inner: Arc<dyn EventStore>,
+    mut receiver: mpsc::Receiver<WriteOp>,
+    config: WriteBehindConfig,
+    cache: Arc<Mutex<HashMap<String, CachedSession>>>,
+    idle: Duration,
+) {
+    let mut batch = Vec::with_capacity(config.batch_size);
+    let mut interval = tokio::time::interval(config.flush_interval);
+    let mut sweep = tokio::time::interval(CACHE_SWEEP_INTERVAL);
+    loop {
+        tokio::select! {
+            op = receiver.recv() => match op {
+                Some(WriteOp::Push(event)) => {
+                    batch.push(event);
+                    if batch.len() >= config.batch_size {
+                        write_batch(inner.as_ref(), &mut batch).await;
+                    }
+                }
+                Some(WriteOp::Flush(done)) => {
+                    write_batch(inner.as_ref(), &mut batch).await;
+                    let _ = done.send(());
+                }
+                None => {
+                    write_batch(inner.as_ref(), &mut batch).await;
+                    return;
+                }
+            },
+            _ = interval.tick() => write_batch(inner.as_ref(), &mut batch).await,
+            _ = sweep.tick() => cache.lock().unwrap().retain(|_, session| session.touched.elapsed() < idle),
+        }
+    }
+}
+
+async fn write_batch(inner: &dyn EventStore, batch: &mut Vec<UserEvent>) {
+    if batch.is_empty() {
+        return;
+    }
+    let events = std::mem::take(batch);
+    let count = events.len() as u64;
+    match inner.push_batch(events).await {
+        Ok(()) => counter!("events_persisted_total").increment(count),
+        Err(e) => {
+            warn!("Failed to persist {} events: {}", count, e);
+            counter!("event_writes_dropped_total", "reason" => "store_error").increment(count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryEventStore;
+    use crate::EventType;
+    use chrono::{DateTime, Utc};
+    use tokio::sync::Semaphore;
+
+    fn event(session_id: &str, n: i64) -> UserEvent {
+        UserEvent {
+            session_id: session_id.to_string(),
+            user_id: None,
+            event_type: EventType::Click,
+            timestamp: "2024-05-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap() + chrono::Duration::seconds(n),
+            ip_address: "198.51.100.23".to_string(),
+            user_agent: None,
+            metadata: None,
+            asn: None,
+        }
+    }
+
+    /// A memory store recording the batches it's handed, each of which waits for a
+    /// permit from `gate` before it is written.
+    struct Backing {
+        store: MemoryEventStore,
+        batches: Mutex<Vec<usize>>,
+        gate: Semaphore,
+    }
+
+    impl Backing {
+        fn new(open: bool) -> Arc<Self> {
+            Arc::new(Self {
+                store: MemoryEventStore::new(100, None),
+                batches: Mutex::new(Vec::new()),
+                gate: Semaphore::new(if open { Semaphore::MAX_PERMITS } else { 0 }),
+            })
+        }
+
+        async fn persisted(&self, session_id: &str) -> usize {
+            self.store.session_events(session_id).await.unwrap().len()
+        }
+    }
+
+    #[async_trait]
+    impl EventStore for Backing {
+        async fn push(&self, event: UserEvent) -> Result<SessionHistory, StoreError> {
+            self.store.push(event).await
+        }
+        async fn push_batch(&self, events: Vec<UserEvent>) -> Result<(), StoreError> {
+            self.gate.acquire().await.unwrap().forget();
+            self.batches.lock().unwrap().push(events.len());
+            self.store.push_batch(events).await
+        }
+        async fn session_events(&self, session_id: &str) -> Result<SessionHistory, StoreError> {
+            self.store.session_events(session_id).await
+        }
+        async fn amend(
+            &self,
+            session_id: &str,
+            event_id: &str,
+            metadata: &HashMap<String, String>,
+        ) -> Result<Option<SessionHistory>, StoreError> {
+            self.store.amend(session_id, event_id, metadata).await
+        }
+        async fn session_ids(&self) -> Result<Vec<String>, StoreError> {
+            self.store.session_ids().await
+        }
+        async fn user_sessions(&self, user_id: &str) -> Result<Vec<String>, StoreError> {
+            self.store.user_sessions(user_id).await
+        }
+        async fn erase_user_events(&self, session_id: &str, user_id: &str) -> Result<SessionErasure, StoreError> {
+            self.store.erase_user_events(session_id, user_id).await
+        }
+        async fn size(&self) -> Result<StoreSize, StoreError> {
+            self.store.size().await
+        }
+    }
+
+    fn config(queue_size: usize, batch_size: usize, flush_ms: u64) -> WriteBehindConfig {
+        WriteBehindConfig {
+            queue_size,
+            batch_size,
+            flush_interval: Duration::from_millis(flush_ms),
+        }
+    }
+
+    /// Waits, up to a second, for `done`.
+    async fn eventually(mut done: impl AsyncFnMut() -> bool) {
+        for _ in 0..100 {
+            if done().await {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("timed out");
+    }
+
+    #[tokio::test]
+    async fn full_batches_are_written_without_waiting_for_the_interval() {
+        let backing = Backing::new(true);
+        let store = WriteBehindStore::start(backing.clone(), &config(100, 3, 60_000), 100, Duration::from_secs(60));
+        for n in 0..3 {
+            let history = store.push(event("s1", n)).await.unwrap();
+            assert_eq!(history.len(), n as usize + 1);
+        }
+        eventually(async || backing.persisted("s1").await == 3).await;
+        assert_eq!(*backing.batches.lock().unwrap(), [3]);
+    }
+
+    #[tokio::test]
+    async fn a_partial_batch_is_written_once_the_interval_passes() {
+        let backing = Backing::new(true);
+        let store = WriteBehindStore::start(backing.clone(), &config(100, 100, 20), 100, Duration::from_secs(60));
+        store.push(event("s1", 0)).await.unwrap();
+        store.push(event("s1", 1)).await.unwrap();
+        eventually(async || backing.persisted("s1").await == 2).await;
+        assert_eq!(*backing.batches.lock().unwrap(), [2]);
+    }
+
+    #[tokio::test]
+    async fn scoring_reads_the_cache_before_events_are_persisted() {
+        let backing = Backing::new(false);
+        let store = WriteBehindStore::start(backing.clone(), &config(100, 100, 10), 100, Duration::from_secs(60));
+        store.push(event("s1", 0)).await.unwrap();
+        let history = store.push(event("s1", 1)).await.unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(store.session_events("s1").await.unwrap().len(), 2);
+        assert_eq!(backing.persisted("s1").await, 0);
+
+        backing.gate.add_permits(Semaphore::MAX_PERMITS);
+        eventually(async || backing.persisted("s1").await == 2).await;
+    }
+
+    #[tokio::test]
+    async fn a_full_queue_drops_writes_but_still_scores() {
+        let backing = Backing::new(false);
+        // The writer holds one batch at the gate; the queue holds one more event.
+        let store = WriteBehindStore::start(backing.clone(), &config(1, 1, 60_000), 100, Duration::from_secs(60));
+        store.push(event("s1", 0)).await.unwrap();
+        eventually(async || backing.gate.available_permits() == 0 && store.sender.capacity() == 1).await;
+        store.push(event("s1", 1)).await.unwrap();
+        let history = store.push(event("s1", 2)).await.unwrap();
+        assert_eq!(history.len(), 3);
+        assert!(store.backlogged.load(Ordering::Relaxed));
+
+        backing.gate.add_permits(Semaphore::MAX_PERMITS);
+        store.flush().await;
+        assert_eq!(backing.persisted("s1").await, 2);
+    }
+
+    #[tokio::test]
+    async fn amending_flushes_first_so_a_queued_event_is_amended_in_the_backing_store() {
+        let backing = Backing::new(true);
+        let store = WriteBehindStore::start(backing.clone(), &config(100, 100, 60_000), 100, Duration::from_secs(60));
+        let queued = UserEvent {
+            metadata: Some(HashMap::from([("eventId".to_string(), "e1".to_string())])),
+            ..event("s1", 0)
+        };
+        store.push(queued).await.unwrap();
+        assert_eq!(backing.persisted("s1").await, 0);
+
+        let metadata = HashMap::from([("amount".to_string(), "12.00".to_string())]);
+        let history = store.amend("s1", "e1", &metadata).await.unwrap().unwrap();
+        assert_eq!(history[0].metadata.as_ref().unwrap()["amount"], "12.00");
+        let persisted = backing.store.session_events("s1").await.unwrap();
+        assert_eq!(persisted[0].metadata.as_ref().unwrap()["amount"], "12.00");
+        assert_eq!(store.session_events("s1").await.unwrap()[0].metadata.as_ref().unwrap()["amount"], "12.00");
+    }
+}
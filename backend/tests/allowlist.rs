@@ -0,0 +1,82 @@
+//! The allowlist: trusted traffic is stored and answered but never scored.
+mod common;
+
+use axum::http::StatusCode;
+use common::{codes, Harness};
+use serde_json::{json, Value};
+
+fn from(session_id: &str, ip: &str, user_id: Option<&str>) -> Value {
+    let mut event = common::event(session_id, "PageLoad", 0);
+    event["ipAddress"] = json!(ip);
+    if let Some(user_id) = user_id {
+        event["userId"] = json!(user_id);
+    }
+    event
+}
+
+fn assert_allowlisted(result: &Value) {
+    assert_eq!(result["fraudScore"], 0, "{}", result);
+    assert_eq!(result["flagged"], false, "{}", result);
+    assert_eq!(codes(result), ["ALLOWLISTED"], "{}", result);
+}
+
+#[tokio::test]
+async fn an_allowlisted_range_takes_precedence_over_the_blacklist() {
+    let service = Harness::with(&[], json!({ "allowlist": { "ips": ["1.1.1.0/24"] } })).await;
+    let result = service.score(&from("allow-1", "1.1.1.1", None)).await;
+    assert_allowlisted(&result);
+    assert_eq!(result["reasons"], json!(["Allowlisted IP address"]));
+
+    // Still stored, so the session's history stays complete.
+    let export = service
+        .get("/api/v1/export?since=2000-01-01T00:00:00Z&session=allow-1")
+        .await
+        .text();
+    assert_eq!(export.lines().count(), 1);
+
+    // Outside the range, the blacklist applies as usual.
+    let outside = service.score(&from("allow-2", "2.2.2.2", None)).await;
+    assert_eq!(codes(&outside), ["IP_BLACKLISTED"]);
+}
+
+#[tokio::test]
+async fn allowlisted_users_are_suppressed_and_counted() {
+    let service = Harness::with(&[], json!({ "allowlist": { "user_ids": ["qa-bot"] } })).await;
+    let series = r#"events_allowlisted_total{match="user"}"#;
+    let before = common::metric(series);
+    for session_id in ["allow-3", "allow-4"] {
+        let result = service.score(&from(session_id, "1.1.1.1", Some("qa-bot"))).await;
+        assert_allowlisted(&result);
+        assert_eq!(result["reasons"], json!(["Allowlisted user"]));
+    }
+    assert_eq!(common::metric(series) - before, 2.0);
+}
+
+#[tokio::test]
+async fn entries_added_at_runtime_apply_until_removed() {
+    let service = Harness::new().await;
+    let event = from("allow-5", "2.2.2.2", None);
+    let added = service
+        .post("/api/v1/allowlist", &json!({ "ips": ["2.2.2.0/24"], "userIds": ["qa-bot"] }))
+        .await
+        .json();
+    assert_eq!(added, json!({ "ips": 1, "userIds": 1 }));
+    assert_eq!(service.get("/api/v1/allowlist").await.json(), json!({ "ips": ["2.2.2.0/24"], "userIds": ["qa-bot"] }));
+    assert_allowlisted(&service.score(&event).await);
+
+    let removed = service.delete("/api/v1/allowlist", Some(&json!({ "ips": ["2.2.2.0/24"] }))).await.json();
+    assert_eq!(removed, json!({ "ips": 1, "userIds": 0 }));
+    let after = service.score(&from("allow-6", "2.2.2.2", None)).await;
+    assert_eq!(codes(&after), ["IP_BLACKLISTED"]);
+}
+
+#[tokio::test]
+async fn a_bad_entry_rejects_the_whole_change() {
+    let service = Harness::new().await;
+    let response = service
+        .post("/api/v1/allowlist", &json!({ "ips": ["10.0.0.0/8", "qa-runner"] }))
+        .await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(response.json()["message"].as_str().unwrap().contains("qa-runner"));
+    assert_eq!(service.get("/api/v1/allowlist").await.json()["ips"], json!([]));
+}
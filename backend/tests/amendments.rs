@@ -0,0 +1,76 @@
+//! Events naming an earlier event of their session in `metadata.eventId`, merged into the
+//! stored event instead of being scored as new ones.
+mod common;
+
+use common::{points, Harness};
+use serde_json::{json, Value};
+
+fn with_metadata(session_id: &str, event_type: &str, offset_ms: i64, metadata: Value) -> Value {
+    let mut event = common::event(session_id, event_type, offset_ms);
+    event["metadata"] = metadata;
+    event
+}
+
+async fn stored_events(service: &Harness) -> Value {
+    service.get("/api/v1/stats").await.json()["store"]["events"].clone()
+}
+
+#[tokio::test]
+async fn an_amendment_is_merged_and_later_rules_see_the_merged_value() {
+    let service = Harness::new().await;
+    let amended = common::metric("events_amended_total");
+    service.score(&with_metadata("amend-1", "PageLoad", 0, json!({ "eventId": "e1" }))).await;
+
+    // The user agent arrives later, naming the page load.
+    let amendment = with_metadata("amend-1", "PageLoad", 1000, json!({ "eventId": "e1", "userAgent": "Mozilla/5.0 Chrome/120" }));
+    let rescored = service.score(&amendment).await;
+    assert_eq!(rescored["sessionId"], "amend-1");
+    assert_eq!(common::metric("events_amended_total"), amended + 1.0);
+    assert_eq!(stored_events(&service).await, 1);
+
+    // The next event's user agent differs from the amended one.
+    let mut next = common::event("amend-1", "Click", 5000);
+    next["userAgent"] = json!("Mozilla/5.0 Firefox/121");
+    let result = service.score(&next).await;
+    assert_eq!(points(&result, "USER_AGENT_CHANGED"), Some(30), "{}", result);
+    assert_eq!(stored_events(&service).await, 2);
+}
+
+#[tokio::test]
+async fn without_the_amendment_there_is_nothing_to_compare() {
+    let service = Harness::new().await;
+    service.score(&with_metadata("plain-1", "PageLoad", 0, json!({ "eventId": "e1" }))).await;
+    let mut next = common::event("plain-1", "Click", 5000);
+    next["userAgent"] = json!("Mozilla/5.0 Firefox/121");
+    assert_eq!(points(&service.score(&next).await, "USER_AGENT_CHANGED"), None);
+}
+
+#[tokio::test]
+async fn later_keys_win_and_the_rest_are_kept() {
+    let service = Harness::new().await;
+    let first = json!({ "eventId": "pay", "amount": "10.00", "currency": "USD" });
+    service.score(&with_metadata("merge-1", "FormSubmission", 0, first)).await;
+    service.score(&with_metadata("merge-1", "FormSubmission", 1000, json!({ "eventId": "pay", "amount": "20.00" }))).await;
+    service.score(&with_metadata("merge-1", "Click", 2000, json!({ "eventId": "pay", "amount": "99.00" }))).await;
+
+    let since = common::at(-1).to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    let export = service.get(&format!("/api/v1/export?since={}", since)).await.text();
+    let stored: Vec<Value> = export.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(stored.len(), 1, "{}", export);
+    // Only the metadata changes: not the type or the timestamp.
+    assert_eq!(stored[0]["eventType"], "FormSubmission");
+    assert_eq!(stored[0]["timestamp"], "2024-05-01T12:00:00Z");
+    assert_eq!(stored[0]["metadata"], json!({ "eventId": "pay", "amount": "99.00", "currency": "USD" }));
+}
+
+#[tokio::test]
+async fn an_event_id_matching_nothing_is_a_new_event() {
+    let service = Harness::new().await;
+    let amended = common::metric("events_amended_total");
+    service.score(&with_metadata("new-1", "PageLoad", 0, json!({ "eventId": "e1" }))).await;
+    service.score(&with_metadata("new-1", "Click", 1000, json!({ "eventId": "e2" }))).await;
+    // Another session's event id doesn't match either.
+    service.score(&with_metadata("new-2", "Click", 1000, json!({ "eventId": "e1" }))).await;
+    assert_eq!(stored_events(&service).await, 3);
+    assert_eq!(common::metric("events_amended_total"), amended);
+}
@@ -0,0 +1,98 @@
+//! `/api/v1/events` and `/api/v2/events`: one analysis, two result shapes, pinned to
+//! their exact JSON.
+mod common;
+
+use axum::http::StatusCode;
+use common::Harness;
+use serde_json::{json, Value};
+
+fn blacklisted(session_id: &str) -> Value {
+    let mut event = common::event(session_id, "PageLoad", 0);
+    event["ipAddress"] = json!("1.1.1.1");
+    event
+}
+
+#[tokio::test]
+async fn v1_keeps_its_shape_and_is_marked_deprecated() {
+    let service = Harness::new().await;
+    let response = service.analyze(&blacklisted("v1")).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(
+        response.json(),
+        json!({
+            "sessionId": "v1",
+            "fraudScore": 100,
+            "rawScore": 100,
+            "confidence": 100,
+            "flagged": true,
+            "decision": "REVIEW",
+            "reasons": ["Blacklisted IP address"],
+            "breakdown": [{ "code": "IP_BLACKLISTED", "points": 100, "reason": "Blacklisted IP address" }],
+            "sessionReputation": 100,
+            "checkTimestamp": "2024-05-01T12:00:00Z",
+            "degraded": false,
+        })
+    );
+    assert_eq!(response.header("deprecation"), Some("true"));
+    assert_eq!(response.header("link"), Some("</api/v2/events>; rel=\"successor-version\""));
+}
+
+#[tokio::test]
+async fn v2_returns_the_decision_and_structured_reasons() {
+    let service = Harness::new().await;
+    let response = service.post("/api/v2/events", &blacklisted("v2")).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(
+        response.json(),
+        json!({
+            "sessionId": "v2",
+            "decision": "REVIEW",
+            "score": 100,
+            "rawScore": 100,
+            "confidence": 100,
+            "reasons": [{ "code": "IP_BLACKLISTED", "points": 100, "message": "Blacklisted IP address" }],
+            "sessionReputation": 100,
+            "checkedAt": "2024-05-01T12:00:00Z",
+            "degraded": false,
+        })
+    );
+    assert_eq!(response.header("deprecation"), None);
+    assert_eq!(response.header("link"), None);
+}
+
+#[tokio::test]
+async fn a_clean_event_on_v2_has_no_reasons() {
+    let service = Harness::new().await;
+    let response = service.post("/api/v2/events", &common::event("v2-clean", "PageLoad", 0)).await;
+    assert_eq!(
+        response.json(),
+        json!({
+            "sessionId": "v2-clean",
+            "decision": "ALLOW",
+            "score": 0,
+            "rawScore": 0,
+            "confidence": 0,
+            "reasons": [],
+            "sessionReputation": 0,
+            "checkedAt": "2024-05-01T12:00:00Z",
+            "degraded": false,
+        })
+    );
+}
+
+#[tokio::test]
+async fn both_versions_score_the_same_and_reject_the_same() {
+    let v1 = Harness::new().await.score(&blacklisted("same")).await;
+    let v2 = Harness::new().await.post("/api/v2/events", &blacklisted("same")).await.json();
+    assert_eq!(v1["fraudScore"], v2["score"]);
+    assert_eq!(v1["decision"], v2["decision"]);
+    assert_eq!(v1["breakdown"][0]["code"], v2["reasons"][0]["code"]);
+
+    let service = Harness::new().await;
+    let invalid = common::event("stale", "PageLoad", -601_000);
+    let from_v1 = service.post("/api/v1/events", &invalid).await;
+    let from_v2 = service.post("/api/v2/events", &invalid).await;
+    assert_eq!(from_v1.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(from_v2.status, from_v1.status);
+    assert_eq!(from_v2.json(), from_v1.json());
+}
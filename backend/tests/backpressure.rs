@@ -0,0 +1,146 @@
+//! Admission control: analyses past `MAX_IN_FLIGHT_ANALYSES` get `429`, requests past
+//! `MAX_IN_FLIGHT_REQUESTS` get `503`, and the store stays within `MAX_STORED_EVENTS`
+//! however much traffic arrives, turning new sessions away with `503` under `reject_new`.
+mod common;
+
+use axum::body::Body;
+use axum::http::{Method, Request, StatusCode};
+use common::{mocks, Harness};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+async fn capacity(service: &Harness) -> Value {
+    service.get("/api/v1/stats").await.json()["capacity"].clone()
+}
+
+#[tokio::test]
+async fn analyses_past_the_in_flight_cap_are_turned_away() {
+    let service = Harness::with(&[("MAX_IN_FLIGHT_ANALYSES", "1")], serde_json::json!({})).await;
+    let rejected = common::metric("analyses_rejected_total");
+
+    // A request whose body hasn't finished arriving holds its slot.
+    let (mut body, stalled) = Body::channel();
+    let request = Request::post("/api/v1/events").header("content-type", "application/json").body(stalled).unwrap();
+    let held = tokio::spawn(common::send_to(service.router(), request));
+    for _ in 0..100 {
+        if capacity(&service).await["inFlightAnalyses"]["current"] == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(capacity(&service).await["inFlightAnalyses"], serde_json::json!({ "current": 1, "limit": 1 }));
+
+    let response = service.post("/api/v1/events", &common::event("turned-away", "PageLoad", 0)).await;
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.header("retry-after"), Some("1"));
+    assert_eq!(response.json()["error"], "overloaded");
+    assert!(common::metric("analyses_rejected_total") > rejected);
+
+    body.send_data(common::event("held", "PageLoad", 0).to_string().into()).await.unwrap();
+    drop(body);
+    assert_eq!(held.await.unwrap().status, StatusCode::OK);
+    assert_eq!(capacity(&service).await["inFlightAnalyses"]["current"], 0);
+    let response = service.post("/api/v1/events", &common::event("admitted", "PageLoad", 0)).await;
+    assert_eq!(response.status, StatusCode::OK);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn a_burst_stays_within_the_configured_budgets() {
+    let vars = [("MAX_IN_FLIGHT_ANALYSES", "4"), ("MAX_STORED_EVENTS", "50")];
+    let service = Harness::with(&vars, serde_json::json!({})).await;
+    let requests: Vec<_> = (0..400)
+        .map(|n| {
+            let event = common::event(&format!("burst-{}", n % 80), "PageLoad", n);
+            let request = common::json_request(Method::POST, "/api/v1/events", &event);
+            tokio::spawn(common::send_to(service.router(), request))
+        })
+        .collect();
+
+    let mut answered = 0;
+    for request in requests {
+        let response = request.await.unwrap();
+        match response.status {
+            StatusCode::OK => answered += 1,
+            StatusCode::TOO_MANY_REQUESTS => assert_eq!(response.header("retry-after"), Some("1")),
+            other => panic!("unexpected {}: {}", other, response.text()),
+        }
+    }
+    assert!(answered > 0);
+
+    let capacity = capacity(&service).await;
+    assert_eq!(capacity["inFlightAnalyses"]["current"], 0, "{}", capacity);
+    assert_eq!(capacity["storedEvents"]["limit"], 50);
+    assert!(capacity["storedEvents"]["current"].as_u64().unwrap() <= 50, "{}", capacity);
+    assert_eq!(capacity["storeFullPolicy"], "shed_oldest");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn requests_past_the_in_flight_cap_are_shed() {
+    let reputation = mocks::slow_reputation_server(HashMap::new(), Duration::from_millis(500)).await;
+    let vars = [
+        ("MAX_IN_FLIGHT_REQUESTS", "2"),
+        ("IP_REPUTATION_URL", reputation.url.as_str()),
+        ("IP_REPUTATION_TIMEOUT_MS", "2000"),
+    ];
+    let service = Harness::with(&vars, serde_json::json!({})).await;
+    let shed = common::metric("requests_shed_total");
+
+    // Two analyses waiting on the slow lookup hold both slots.
+    let held: Vec<_> = (0..2)
+        .map(|n| {
+            let event = common::event(&format!("slow-{}", n), "PageLoad", 0);
+            tokio::spawn(common::send_to(service.router(), common::json_request(Method::POST, "/api/v1/events", &event)))
+        })
+        .collect();
+    for _ in 0..100 {
+        if reputation.lookups.load(Ordering::Relaxed) == 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    assert_eq!(reputation.lookups.load(Ordering::Relaxed), 2);
+
+    // Any API route is shed, not queued, while the probes still answer.
+    for response in [
+        service.post("/api/v1/events", &common::event("shed", "PageLoad", 0)).await,
+        service.get("/api/v1/stats").await,
+    ] {
+        assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE, "{}", response.text());
+        assert_eq!(response.header("retry-after"), Some("1"));
+        assert_eq!(response.json()["error"], "unavailable");
+    }
+    assert_eq!(common::metric("requests_shed_total"), shed + 2.0);
+    assert_eq!(service.get("/api/v1/ready").await.status, StatusCode::OK);
+    assert_eq!(service.get("/metrics").await.status, StatusCode::OK);
+
+    for request in held {
+        assert_eq!(request.await.unwrap().status, StatusCode::OK);
+    }
+    // The stats request itself holds one.
+    assert_eq!(capacity(&service).await["inFlightRequests"], serde_json::json!({ "current": 1, "limit": 2 }));
+}
+
+#[tokio::test]
+async fn a_full_store_turns_new_sessions_away_under_reject_new() {
+    let vars = [("MAX_STORED_EVENTS", "2"), ("STORE_FULL_POLICY", "reject_new")];
+    let service = Harness::with(&vars, serde_json::json!({})).await;
+    let rejected = common::metric("store_events_rejected_total");
+    for offset_ms in [0, 1_000] {
+        service.score(&common::event("held", "PageLoad", offset_ms)).await;
+    }
+
+    let response = service.analyze(&common::event("overflow", "PageLoad", 2_000)).await;
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE, "{}", response.text());
+    assert_eq!(response.header("retry-after"), Some("1"));
+    assert_eq!(response.json()["error"], "unavailable");
+    assert_eq!(common::metric("store_events_rejected_total"), rejected + 1.0);
+
+    // A session already held is still answered, dropping its own oldest event.
+    service.score(&common::event("held", "Click", 3_000)).await;
+    assert_eq!(common::metric("store_events_rejected_total"), rejected + 1.0);
+    let capacity = capacity(&service).await;
+    assert_eq!(capacity["storedEvents"], serde_json::json!({ "current": 2, "limit": 2 }));
+    assert_eq!(capacity["storeFullPolicy"], "reject_new");
+}
@@ -0,0 +1,68 @@
+//! `POST /api/v1/blacklist`: permanent entries, and temporary bans (`ttlSecs`) that stop
+//! matching once the service clock passes their expiry.
+mod common;
+
+use axum::http::StatusCode;
+use common::{points, Harness};
+use serde_json::{json, Value};
+
+fn from_ip(session_id: &str, ip: &str, offset_ms: i64) -> Value {
+    let mut event = common::event(session_id, "PageLoad", offset_ms);
+    event["ipAddress"] = json!(ip);
+    event
+}
+
+async fn blacklisted(service: &Harness, session_id: &str, ip: &str, offset_ms: i64) -> bool {
+    points(&service.score(&from_ip(session_id, ip, offset_ms)).await, "IP_BLACKLISTED").is_some()
+}
+
+#[tokio::test]
+async fn a_short_ban_matches_until_the_clock_passes_it() {
+    let service = Harness::with(&[], json!({ "entity_reputation": { "half_life_secs": 0 } })).await;
+    let added = service.post("/api/v1/blacklist", &json!({ "ips": ["203.0.113.5"], "ttlSecs": 60 })).await;
+    assert_eq!(added.status, StatusCode::OK, "{}", added.text());
+    assert_eq!(added.json(), json!({ "added": 1, "expiresAt": "2024-05-01T12:01:00Z" }));
+    assert!(blacklisted(&service, "ban-1", "203.0.113.5", 0).await);
+
+    service.clock.advance(chrono::Duration::seconds(59));
+    assert!(blacklisted(&service, "ban-2", "203.0.113.5", 59_000).await);
+    service.clock.advance(chrono::Duration::seconds(1));
+    assert!(!blacklisted(&service, "ban-3", "203.0.113.5", 60_000).await);
+    let export = service.get("/api/v1/blacklist/export").await.text();
+    assert!(!export.contains("203.0.113.5"), "{}", export);
+}
+
+#[tokio::test]
+async fn entries_without_a_ttl_are_permanent() {
+    let service = Harness::with(&[], json!({ "entity_reputation": { "half_life_secs": 0 } })).await;
+    let added = service.post("/api/v1/blacklist", &json!({ "ips": ["203.0.113.6"] })).await;
+    assert_eq!(added.json(), json!({ "added": 1, "expiresAt": null }));
+    let year = chrono::Duration::days(365);
+    service.clock.advance(year);
+    assert!(blacklisted(&service, "forever", "203.0.113.6", year.num_milliseconds()).await);
+}
+
+#[tokio::test]
+async fn invalid_additions_are_rejected_whole() {
+    let service = Harness::new().await;
+    let invalid = service.post("/api/v1/blacklist", &json!({ "ips": ["203.0.113.7", "bogus"] })).await;
+    assert_eq!(invalid.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(invalid.text().contains("Invalid IP entries: bogus"), "{}", invalid.text());
+    let zero = service.post("/api/v1/blacklist", &json!({ "ips": ["203.0.113.7"], "ttlSecs": 0 })).await;
+    assert_eq!(zero.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(!blacklisted(&service, "rejected", "203.0.113.7", 0).await);
+}
+
+#[tokio::test]
+async fn bans_survive_a_restart_with_a_bans_file() {
+    let path = common::scratch_path("bans.txt");
+    let vars = [("BLACKLIST_BANS_PATH", path.to_str().unwrap())];
+    let service = Harness::with(&vars, json!({})).await;
+    // Loading skips bans expired by the system clock, so this one outlasts the test.
+    let ttl_secs: u64 = 100 * 365 * 24 * 3600;
+    service.post("/api/v1/blacklist", &json!({ "ips": ["203.0.113.8"], "ttlSecs": ttl_secs })).await;
+    assert!(std::fs::read_to_string(&path).unwrap().starts_with("203.0.113.8,"));
+
+    let restarted = Harness::with(&vars, json!({})).await;
+    assert!(blacklisted(&restarted, "restarted", "203.0.113.8", 0).await);
+}
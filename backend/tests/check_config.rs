@@ -0,0 +1,44 @@
+//! The startup self-test, run through the binary's `--check-config` mode.
+mod common;
+
+use serde_json::json;
+use std::process::{Command, Output};
+
+/// Runs `--check-config` in an environment holding only `vars`.
+fn check_config(vars: &[(&str, &str)]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rust-fraud-detector"))
+        .arg("--check-config")
+        .env_clear()
+        .envs(vars.iter().copied())
+        .output()
+        .expect("running the binary")
+}
+
+fn logs(output: &Output) -> String {
+    format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr))
+}
+
+#[test]
+fn a_valid_configuration_passes() {
+    let path = common::scratch_path("scoring.json");
+    std::fs::write(&path, json!({ "flag_threshold": 60 }).to_string()).unwrap();
+    let output = check_config(&[("SCORING_CONFIG_PATH", path.to_str().unwrap())]);
+    assert!(output.status.success(), "{}", logs(&output));
+    assert!(logs(&output).contains("Configuration OK"), "{}", logs(&output));
+}
+
+#[test]
+fn a_negative_threshold_fails_with_a_clear_message() {
+    let path = common::scratch_path("scoring.json");
+    std::fs::write(&path, json!({ "flag_threshold": -10 }).to_string()).unwrap();
+    let output = check_config(&[("SCORING_CONFIG_PATH", path.to_str().unwrap())]);
+    assert_eq!(output.status.code(), Some(1), "{}", logs(&output));
+    assert!(logs(&output).contains("flag_threshold must not be negative, got -10"), "{}", logs(&output));
+}
+
+#[test]
+fn a_missing_data_file_fails() {
+    let output = check_config(&[("BLACKLIST_PATH", "/nonexistent/blacklist.txt")]);
+    assert_eq!(output.status.code(), Some(1), "{}", logs(&output));
+    assert!(logs(&output).contains("Failed to load blacklist /nonexistent/blacklist.txt"), "{}", logs(&output));
+}
@@ -0,0 +1,109 @@
+//! Deriving the client IP server-side (`CLIENT_IP_SOURCE`), from the connection or
+//! from a trusted proxy's forwarding headers.
+mod common;
+
+use axum::extract::ConnectInfo;
+use axum::http::{Method, StatusCode};
+use common::{codes, points, Harness};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+
+/// 1.1.1.1, the IP the default blacklist lists.
+const BLACKLISTED: &str = "1.1.1.1";
+
+/// Scores `event` as sent from `peer` with `headers` added.
+async fn score_from(service: &Harness, peer: &str, headers: &[(&'static str, &str)], event: &Value) -> Value {
+    let mut request = common::json_request(Method::POST, "/api/v1/events", event);
+    request.extensions_mut().insert(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+    for (name, value) in headers {
+        request.headers_mut().append(*name, value.parse().unwrap());
+    }
+    let response = service.send(request).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    response.json()
+}
+
+fn behind_proxy(policy: &str) -> Vec<(&'static str, String)> {
+    vec![
+        ("CLIENT_IP_SOURCE", "forwarded".to_string()),
+        ("TRUSTED_PROXIES", "10.0.0.0/8, 2001:db8:ffff::/48".to_string()),
+        ("CLIENT_IP_POLICY", policy.to_string()),
+    ]
+}
+
+async fn service(vars: &[(&'static str, String)]) -> Harness {
+    let vars: Vec<(&str, &str)> = vars.iter().map(|(key, value)| (*key, value.as_str())).collect();
+    Harness::with(&vars, json!({})).await
+}
+
+#[tokio::test]
+async fn the_connection_ip_overrides_the_payload() {
+    let service = service(&[("CLIENT_IP_SOURCE", "connection".to_string())]).await;
+    let result = score_from(&service, "1.1.1.1:40000", &[], &common::event("conn-1", "PageLoad", 0)).await;
+    assert_eq!(points(&result, "IP_BLACKLISTED"), Some(100));
+
+    let result = score_from(&service, "[::ffff:1.1.1.1]:40000", &[], &common::event("conn-2", "PageLoad", 0)).await;
+    assert_eq!(points(&result, "IP_BLACKLISTED"), Some(100));
+}
+
+#[tokio::test]
+async fn the_client_behind_a_trusted_proxy_is_scored() {
+    let service = service(&behind_proxy("override")).await;
+    let xff = [("x-forwarded-for", "203.0.113.9, 1.1.1.1, 10.1.2.3")];
+    let result = score_from(&service, "10.0.0.5:443", &xff, &common::event("xff-1", "PageLoad", 0)).await;
+    assert_eq!(points(&result, "IP_BLACKLISTED"), Some(100));
+
+    let forwarded = [("forwarded", "for=\"[2001:db8:1::7]:4711\""), ("forwarded", "for=1.1.1.1")];
+    let result = score_from(&service, "[2001:db8:ffff::1]:443", &forwarded, &common::event("xff-2", "PageLoad", 0)).await;
+    assert_eq!(points(&result, "IP_BLACKLISTED"), Some(100));
+}
+
+#[tokio::test]
+async fn without_a_derivable_ip_the_payload_is_scored_and_counted() {
+    let service = service(&behind_proxy("override")).await;
+    let before = common::metric("client_ip_unresolved_total");
+    let mut event = common::event("xff-missing", "PageLoad", 0);
+    event["ipAddress"] = json!(BLACKLISTED);
+    let result = score_from(&service, "10.0.0.5:443", &[], &event).await;
+    assert_eq!(points(&result, "IP_BLACKLISTED"), Some(100));
+    assert!(common::metric("client_ip_unresolved_total") > before);
+}
+
+#[tokio::test]
+async fn cross_check_scores_a_payload_ip_that_differs() {
+    let service = service(&behind_proxy("cross_check")).await;
+    let xff = [("x-forwarded-for", "192.0.2.44")];
+
+    let mut agrees = common::event("cross-1", "PageLoad", 0);
+    agrees["ipAddress"] = json!("192.0.2.44");
+    let result = score_from(&service, "10.0.0.5:443", &xff, &agrees).await;
+    assert!(!codes(&result).contains(&"IP_MISMATCH".to_string()), "{}", result);
+
+    // The payload IP is kept, so it is what the blacklist sees.
+    let mut differs = common::event("cross-2", "PageLoad", 0);
+    differs["ipAddress"] = json!(BLACKLISTED);
+    let result = score_from(&service, "10.0.0.5:443", &xff, &differs).await;
+    assert_eq!(points(&result, "IP_MISMATCH"), Some(40));
+    assert_eq!(points(&result, "IP_BLACKLISTED"), Some(100));
+    assert!(!result.to_string().contains("192.0.2.44"), "{}", result);
+}
+
+#[tokio::test]
+async fn a_spoofed_header_from_an_untrusted_peer_is_ignored() {
+    let service = service(&behind_proxy("override")).await;
+    let spoofed = [("x-forwarded-for", "1.1.1.1"), ("forwarded", "for=1.1.1.1")];
+    let mut event = common::event("spoofed-1", "PageLoad", 0);
+    event["ipAddress"] = json!(BLACKLISTED);
+    // The peer itself is the client, and it isn't blacklisted.
+    let result = score_from(&service, "198.51.100.7:40000", &spoofed, &event).await;
+    assert_eq!(points(&result, "IP_BLACKLISTED"), None);
+    assert_eq!(result["fraudScore"], 0);
+}
+
+#[tokio::test]
+async fn entries_spoofed_ahead_of_a_trusted_proxy_are_ignored() {
+    let service = service(&behind_proxy("override")).await;
+    let xff = [("x-forwarded-for", "1.1.1.1, 198.51.100.7")];
+    let result = score_from(&service, "10.0.0.5:443", &xff, &common::event("spoofed-2", "PageLoad", 0)).await;
+    assert_eq!(points(&result, "IP_BLACKLISTED"), None);
+}
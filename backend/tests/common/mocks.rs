@@ -0,0 +1,250 @@
+//! Stand-ins for what the service can be pointed at: a GeoIP database file, the IP
+//! reputation API, and the model server.
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tonic::body::BoxBody;
+use tonic::codec::ProstCodec;
+use tonic::codegen::{http, BoxFuture, Service};
+use tonic::server::{Grpc, NamedService, UnaryService};
+
+// --- GEOIP DATABASES ---
+/// A MaxMind DB that answers `record` for every IPv4 address, written to a scratch file.
+/// `database_type` is what the reader dispatches on, e.g. `GeoLite2-City`.
+pub fn geoip_database(name: &str, database_type: &str, record: &Value) -> PathBuf {
+    let mut file = Vec::new();
+    // One node whose records both point at the first data entry: data pointers are
+    // the node count plus the 16-byte separator past the tree.
+    let node_count = 1u32;
+    let pointer = (node_count + 16).to_be_bytes();
+    file.extend_from_slice(&pointer[1..]);
+    file.extend_from_slice(&pointer[1..]);
+    file.extend_from_slice(&[0; 16]);
+    encode(record, &mut file);
+    file.extend_from_slice(b"\xab\xcd\xefMaxMind.com");
+    encode(
+        &json!({
+            "node_count": node_count,
+            "record_size": {"u16": 24},
+            "ip_version": {"u16": 4},
+            "database_type": database_type,
+            "languages": ["en"],
+            "binary_format_major_version": {"u16": 2},
+            "binary_format_minor_version": {"u16": 0},
+            "build_epoch": {"u64": 1_700_000_000u64},
+            "description": {"en": "test"},
+        }),
+        &mut file,
+    );
+    let path = super::scratch_path(&format!("{}.mmdb", name));
+    std::fs::write(&path, file).unwrap();
+    path
+}
+
+/// A control byte (and extension bytes) for a field of type `kind` and `size`.
+fn control(kind: u8, size: usize, out: &mut Vec<u8>) {
+    let (size_bits, extra) = match size {
+        0..=28 => (size as u8, None),
+        29..=284 => (29, Some((size - 29) as u8)),
+        _ => panic!("field too large for the test writer"),
+    };
+    if kind <= 7 {
+        out.push((kind << 5) | size_bits);
+    } else {
+        out.extend_from_slice(&[size_bits, kind - 7]);
+    }
+    out.extend(extra);
+}
+
+fn unsigned(kind: u8, value: u64, out: &mut Vec<u8>) {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|byte| **byte == 0).count();
+    control(kind, bytes.len() - skip, out);
+    out.extend_from_slice(&bytes[skip..]);
+}
+
+/// Numbers are `uint32`; `{"u16": n}` and `{"u64": n}` pick the width the metadata
+/// section requires for some fields.
+fn encode(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Bool(value) => control(14, usize::from(*value), out),
+        Value::String(value) => {
+            control(2, value.len(), out);
+            out.extend_from_slice(value.as_bytes());
+        }
+        Value::Number(number) => unsigned(6, number.as_u64().expect("unsigned number"), out),
+        Value::Array(values) => {
+            control(11, values.len(), out);
+            values.iter().for_each(|value| encode(value, out));
+        }
+        Value::Object(fields) => match (fields.len(), fields.get("u16"), fields.get("u64")) {
+            (1, Some(value), _) => unsigned(5, value.as_u64().unwrap(), out),
+            (1, _, Some(value)) => unsigned(9, value.as_u64().unwrap(), out),
+            _ => {
+                control(7, fields.len(), out);
+                for (key, value) in fields {
+                    encode(&Value::String(key.clone()), out);
+                    encode(value, out);
+                }
+            }
+        },
+        Value::Null => panic!("MaxMind DB has no null"),
+    }
+}
+
+async fn listen() -> (TcpListener, SocketAddr) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    (listener, addr)
+}
+
+// --- IP REPUTATION ---
+/// An AbuseIPDB-shaped reputation API answering each IP's score from a table (0 for IPs
+/// not in it), counting the lookups it serves.
+pub struct ReputationServer {
+    pub url: String,
+    pub lookups: Arc<AtomicUsize>,
+    /// While set, every lookup gets a `503`.
+    pub down: Arc<AtomicBool>,
+}
+
+#[derive(Clone)]
+struct Reputations {
+    scores: Arc<HashMap<String, u64>>,
+    lookups: Arc<AtomicUsize>,
+    delay: Duration,
+    down: Arc<AtomicBool>,
+}
+
+async fn check(State(reputations): State<Reputations>, Path(ip): Path<String>) -> Result<Json<Value>, StatusCode> {
+    reputations.lookups.fetch_add(1, Ordering::Relaxed);
+    if reputations.down.load(Ordering::Relaxed) {
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+    tokio::time::sleep(reputations.delay).await;
+    let score = reputations.scores.get(&ip).copied().unwrap_or(0);
+    Ok(Json(json!({ "data": { "abuseConfidenceScore": score } })))
+}
+
+pub async fn reputation_server(scores: HashMap<String, u64>) -> ReputationServer {
+    slow_reputation_server(scores, Duration::ZERO).await
+}
+
+/// A reputation API taking `delay` to answer each lookup, for a dependency that hangs.
+pub async fn slow_reputation_server(scores: HashMap<String, u64>, delay: Duration) -> ReputationServer {
+    let lookups = Arc::new(AtomicUsize::new(0));
+    let down = Arc::new(AtomicBool::new(false));
+    let app = Router::new().route("/check/:ip", get(check)).with_state(Reputations {
+        scores: Arc::new(scores),
+        lookups: lookups.clone(),
+        delay,
+        down: down.clone(),
+    });
+    let (listener, addr) = listen().await;
+    tokio::spawn(axum::Server::from_tcp(listener.into_std().unwrap()).unwrap().serve(app.into_make_service()));
+    ReputationServer {
+        url: format!("http://{}/check/{{ip}}", addr),
+        lookups,
+        down,
+    }
+}
+
+// --- MODEL SERVER ---
+/// The messages of `proto/fraud_model.proto`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ScoreRequest {
+    #[prost(string, repeated, tag = "1")]
+    pub feature_names: Vec<String>,
+    #[prost(float, repeated, tag = "2")]
+    pub features: Vec<f32>,
+    #[prost(uint32, tag = "3")]
+    pub feature_version: u32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ScoreResponse {
+    #[prost(float, tag = "1")]
+    pub probability: f32,
+}
+
+/// A `fraud.model.v1.FraudModel` service answering every request with one probability,
+/// counting the requests it gets.
+#[derive(Clone)]
+struct FixedModel {
+    probability: f32,
+    requests: Arc<AtomicUsize>,
+    down: Arc<AtomicBool>,
+}
+
+impl UnaryService<ScoreRequest> for FixedModel {
+    type Response = ScoreResponse;
+    type Future = BoxFuture<tonic::Response<ScoreResponse>, tonic::Status>;
+
+    fn call(&mut self, _request: tonic::Request<ScoreRequest>) -> Self::Future {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if self.down.load(Ordering::Relaxed) {
+            return Box::pin(async { Err(tonic::Status::unavailable("model is down")) });
+        }
+        let probability = self.probability;
+        Box::pin(async move { Ok(tonic::Response::new(ScoreResponse { probability })) })
+    }
+}
+
+impl Service<http::Request<BoxBody>> for FixedModel {
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<BoxBody>) -> Self::Future {
+        let model = self.clone();
+        Box::pin(async move { Ok(Grpc::new(ProstCodec::default()).unary(model, request).await) })
+    }
+}
+
+impl NamedService for FixedModel {
+    const NAME: &'static str = "fraud.model.v1.FraudModel";
+}
+
+pub struct ModelServer {
+    /// For `MODEL_GRPC_ADDR`.
+    pub addr: String,
+    pub requests: Arc<AtomicUsize>,
+    /// While set, every request fails with `UNAVAILABLE`.
+    pub down: Arc<AtomicBool>,
+}
+
+pub async fn model_server(probability: f32) -> ModelServer {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let down = Arc::new(AtomicBool::new(false));
+    let model = FixedModel {
+        probability,
+        requests: requests.clone(),
+        down: down.clone(),
+    };
+    let (listener, addr) = listen().await;
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(model)
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+    );
+    ModelServer {
+        addr: format!("http://{}", addr),
+        requests,
+        down,
+    }
+}
@@ -0,0 +1,244 @@
+//! What the integration tests share: a service built the way `run` builds one, driven
+//! through its router with `oneshot`, on a clock that only moves when a test moves it,
+//! plus stand-ins for the external services and data files it can be pointed at.
+//!
+//! Each test binary uses only part of this, hence the `dead_code` allowance.
+#![allow(dead_code)]
+
+pub mod mocks;
+
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::http::{header, HeaderMap, Method, Request, StatusCode};
+use axum::Router;
+use chrono::{DateTime, Duration, Utc};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use rust_fraud_detector::{router, AppState, ManualClock, ScoringConfig, ServiceConfig};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use tower::ServiceExt;
+
+/// Where every test's clock starts. Event timestamps are given relative to it, so they
+/// stay within the freshness window.
+pub fn start() -> DateTime<Utc> {
+    "2024-05-01T12:00:00Z".parse().unwrap()
+}
+
+/// `offset_ms` after `start`.
+pub fn at(offset_ms: i64) -> DateTime<Utc> {
+    start() + Duration::milliseconds(offset_ms)
+}
+
+/// `ServiceConfig::from_env` reads the process environment, which every test in a
+/// binary shares, so configs are built one at a time.
+static ENV: Mutex<()> = Mutex::new(());
+
+/// The service config `run` would load with `vars` set and nothing else.
+pub fn service_config(vars: &[(&str, &str)]) -> ServiceConfig {
+    let _env = ENV.lock().unwrap_or_else(PoisonError::into_inner);
+    for (key, value) in vars {
+        std::env::set_var(key, value);
+    }
+    let config = ServiceConfig::from_env();
+    for (key, _) in vars {
+        std::env::remove_var(key);
+    }
+    config.unwrap_or_else(|e| panic!("invalid service config {:?}: {}", vars, e))
+}
+
+/// A scoring config from the JSON a `SCORING_CONFIG_PATH` file would hold.
+pub fn scoring_config(config: Value) -> ScoringConfig {
+    let config: ScoringConfig = serde_json::from_value(config).expect("scoring config");
+    config.validate().expect("valid scoring config");
+    config
+}
+
+/// The recorder `counter!` and friends report to, installed once per test binary. Tests
+/// in a binary share it, so they compare before and after rather than absolute values.
+pub fn metrics() -> PrometheusHandle {
+    static METRICS: OnceLock<PrometheusHandle> = OnceLock::new();
+    METRICS
+        .get_or_init(|| PrometheusBuilder::new().install_recorder().expect("metrics recorder"))
+        .clone()
+}
+
+/// The value of `series` (a metric name plus its labels as rendered, such as
+/// `events_sampled_out_total{event_type="Click"}`), or 0 before it is first recorded.
+pub fn metric(series: &str) -> f64 {
+    metrics()
+        .render()
+        .lines()
+        .find_map(|line| line.strip_prefix(series)?.strip_prefix(' ')?.trim().parse().ok())
+        .unwrap_or(0.0)
+}
+
+/// A file under the target directory no other test writes, for configs that name one.
+pub fn scratch_path(name: &str) -> PathBuf {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    dir.join(format!("{}-{}-{}", std::process::id(), n, name))
+}
+
+/// The peer address requests come from unless a test says otherwise.
+pub const PEER: &str = "192.0.2.10:40000";
+
+/// A running service: its state, its router, and the clock it reads.
+pub struct Harness {
+    pub state: Arc<AppState>,
+    pub clock: Arc<ManualClock>,
+    router: Router,
+}
+
+impl Harness {
+    /// The service with every setting at its default.
+    pub async fn new() -> Self {
+        Self::with(&[], json!({})).await
+    }
+
+    /// The service with the environment variables `vars` and the scoring config `scoring`.
+    pub async fn with(vars: &[(&str, &str)], scoring: Value) -> Self {
+        Self::from_configs(service_config(vars), scoring_config(scoring)).await
+    }
+
+    pub async fn from_configs(service: ServiceConfig, scoring: ScoringConfig) -> Self {
+        let clock = Arc::new(ManualClock::new(start()));
+        let state = AppState::new(service, scoring, metrics())
+            .await
+            .expect("app state")
+            .with_clock(clock.clone());
+        let state = Arc::new(state);
+        Self {
+            router: router(state.clone()),
+            state,
+            clock,
+        }
+    }
+
+    /// Sends `request` through the router, from `PEER` unless it names a peer already.
+    pub async fn send(&self, request: Request<Body>) -> TestResponse {
+        send_to(self.router(), request).await
+    }
+
+    /// A handle on the router, for tests sending from tasks of their own; the router
+    /// itself isn't `Sync`, so the harness can't be shared with them.
+    pub fn router(&self) -> Router {
+        self.router.clone()
+    }
+
+    pub async fn get(&self, uri: &str) -> TestResponse {
+        self.send(Request::get(uri).body(Body::empty()).unwrap()).await
+    }
+
+    pub async fn post(&self, uri: &str, body: &Value) -> TestResponse {
+        self.send(json_request(Method::POST, uri, body)).await
+    }
+
+    pub async fn delete(&self, uri: &str, body: Option<&Value>) -> TestResponse {
+        match body {
+            Some(body) => self.send(json_request(Method::DELETE, uri, body)).await,
+            None => self.send(Request::delete(uri).body(Body::empty()).unwrap()).await,
+        }
+    }
+
+    /// Scores `event` on `POST /api/v1/events`, with the clock at the event's timestamp.
+    pub async fn analyze(&self, event: &Value) -> TestResponse {
+        self.set_clock_to(event);
+        self.post("/api/v1/events", event).await
+    }
+
+    /// Scores `event` and returns the result, failing the test on anything but `200`.
+    pub async fn score(&self, event: &Value) -> Value {
+        let response = self.analyze(event).await;
+        assert_eq!(response.status, StatusCode::OK, "scoring {}: {}", event, response.text());
+        response.json()
+    }
+
+    /// Scores `event` on the explain endpoint, against the stored session.
+    pub async fn explain(&self, event: &Value) -> Value {
+        self.set_clock_to(event);
+        let response = self.post("/api/v1/events/explain", &json!({ "event": event })).await;
+        assert_eq!(response.status, StatusCode::OK, "explaining {}: {}", event, response.text());
+        response.json()
+    }
+
+    fn set_clock_to(&self, event: &Value) {
+        if let Some(timestamp) = event["timestamp"].as_str().and_then(|timestamp| timestamp.parse().ok()) {
+            self.clock.set(timestamp);
+        }
+    }
+}
+
+/// `Harness::send`, on a router handed out by `Harness::router`.
+pub async fn send_to(router: Router, mut request: Request<Body>) -> TestResponse {
+    if request.extensions().get::<ConnectInfo<SocketAddr>>().is_none() {
+        request.extensions_mut().insert(ConnectInfo(PEER.parse::<SocketAddr>().unwrap()));
+    }
+    let response = router.oneshot(request).await.expect("router is infallible");
+    let (parts, body) = response.into_parts();
+    TestResponse {
+        status: parts.status,
+        headers: parts.headers,
+        body: hyper::body::to_bytes(body).await.expect("response body").to_vec(),
+    }
+}
+
+pub fn json_request(method: Method, uri: &str, body: &Value) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl TestResponse {
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).unwrap_or_else(|e| panic!("not JSON ({}): {}", e, self.text()))
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+}
+
+/// An event in `session_id` at `offset_ms` after `start`, from a clean IP, with no user
+/// and no metadata. Tests add what their rule reads.
+pub fn event(session_id: &str, event_type: &str, offset_ms: i64) -> Value {
+    json!({
+        "sessionId": session_id,
+        "eventType": event_type,
+        "timestamp": at(offset_ms),
+        "ipAddress": "198.51.100.23",
+    })
+}
+
+/// The reason codes of a result, in order.
+pub fn codes(result: &Value) -> Vec<String> {
+    result["breakdown"]
+        .as_array()
+        .map(|hits| hits.iter().filter_map(|hit| hit["code"].as_str().map(String::from)).collect())
+        .unwrap_or_default()
+}
+
+/// The points a result got for `code`, if it hit.
+pub fn points(result: &Value, code: &str) -> Option<i64> {
+    result["breakdown"]
+        .as_array()?
+        .iter()
+        .find(|hit| hit["code"] == code)
+        .and_then(|hit| hit["points"].as_i64())
+}
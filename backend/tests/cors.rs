@@ -0,0 +1,67 @@
+//! CORS for browser tooling on other origins, per `ALLOWED_ORIGINS`.
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Method, Request};
+use common::Harness;
+use serde_json::json;
+
+const DASHBOARD: &str = "https://dashboard.example.com";
+
+async fn allowing_dashboard() -> Harness {
+    Harness::with(&[("ALLOWED_ORIGINS", "https://dashboard.example.com, https://ops.example.com")], json!({})).await
+}
+
+fn preflight(uri: &str, origin: &str, method: Method) -> Request<Body> {
+    Request::builder()
+        .method(Method::OPTIONS)
+        .uri(uri)
+        .header(header::ORIGIN, origin)
+        .header(header::ACCESS_CONTROL_REQUEST_METHOD, method.as_str())
+        .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "content-type")
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn preflight_for_an_admin_route_allows_a_listed_origin() {
+    let service = allowing_dashboard().await;
+    for (uri, method) in [("/api/v1/blacklist", Method::POST), ("/api/v1/allowlist", Method::DELETE)] {
+        let response = service.send(preflight(uri, DASHBOARD, method.clone())).await;
+        assert!(response.status.is_success(), "{} {}: {}", method, uri, response.status);
+        assert_eq!(response.header("access-control-allow-origin"), Some(DASHBOARD));
+        let methods = response.header("access-control-allow-methods").unwrap();
+        assert!(methods.contains(method.as_str()), "{}", methods);
+        let headers = response.header("access-control-allow-headers").unwrap();
+        assert!(headers.contains("content-type"), "{}", headers);
+    }
+}
+
+#[tokio::test]
+async fn requests_from_a_listed_origin_get_the_header() {
+    let service = allowing_dashboard().await;
+    let request = Request::get("/api/v1/stats")
+        .header(header::ORIGIN, DASHBOARD)
+        .body(Body::empty())
+        .unwrap();
+    let response = service.send(request).await;
+    assert_eq!(response.header("access-control-allow-origin"), Some(DASHBOARD));
+    let exposed = response.header("access-control-expose-headers").unwrap();
+    assert!(exposed.contains("x-request-id"), "{}", exposed);
+}
+
+#[tokio::test]
+async fn other_origins_get_no_allow_origin_header() {
+    let service = allowing_dashboard().await;
+    let response = service
+        .send(preflight("/api/v1/blacklist", "https://evil.example.net", Method::POST))
+        .await;
+    assert_eq!(response.header("access-control-allow-origin"), None);
+}
+
+#[tokio::test]
+async fn without_allowed_origins_cross_origin_requests_get_no_headers() {
+    let service = Harness::new().await;
+    let response = service.send(preflight("/api/v1/blacklist", DASHBOARD, Method::POST)).await;
+    assert_eq!(response.header("access-control-allow-origin"), None);
+}
@@ -0,0 +1,64 @@
+//! The `decision` a result carries, from the scoring config's decision policy, and its
+//! distribution in the stats.
+mod common;
+
+use common::Harness;
+use serde_json::{json, Value};
+
+fn from_ip(session_id: &str, ip: &str) -> Value {
+    let mut event = common::event(session_id, "PageLoad", 0);
+    event["ipAddress"] = json!(ip);
+    event
+}
+
+/// Blacklisted IPs worth `blacklist_points`, a 40-point band for `CHALLENGE` and one at
+/// 80 for `DENY`, and the blacklist always at least `REVIEW`.
+fn policy(blacklist_points: i64) -> Value {
+    json!({
+        "blacklist": { "points": blacklist_points },
+        "entity_reputation": { "half_life_secs": 0 },
+        "decision_policy": {
+            "bands": [{ "min_score": 40, "decision": "CHALLENGE" }, { "min_score": 80, "decision": "DENY" }],
+            "overrides": [{ "code": "IP_BLACKLISTED", "at_least": "REVIEW" }],
+        },
+    })
+}
+
+#[tokio::test]
+async fn results_carry_the_banded_decision_and_stats_count_them() {
+    let service = Harness::with(&[], policy(100)).await;
+    assert_eq!(service.score(&from_ip("clean", "198.51.100.23")).await["decision"], "ALLOW");
+    assert_eq!(service.score(&from_ip("denied-1", "1.1.1.1")).await["decision"], "DENY");
+    assert_eq!(service.score(&from_ip("denied-2", "1.1.1.1")).await["decision"], "DENY");
+
+    let stats = service.get("/api/v1/stats").await.json();
+    assert_eq!(stats["decisions"], json!({ "ALLOW": 1, "CHALLENGE": 0, "REVIEW": 0, "DENY": 2 }));
+}
+
+#[tokio::test]
+async fn a_reason_code_override_lifts_a_low_score() {
+    // 10 points is below every band, but the blacklist hit is at least REVIEW.
+    let service = Harness::with(&[], policy(10)).await;
+    let result = service.score(&from_ip("floored", "1.1.1.1")).await;
+    assert_eq!(result["fraudScore"], 10);
+    assert_eq!(result["flagged"], false);
+    assert_eq!(result["decision"], "REVIEW");
+}
+
+#[tokio::test]
+async fn a_reloaded_policy_applies_to_the_next_event() {
+    let service = Harness::with(&[], policy(100)).await;
+    assert_eq!(service.score(&from_ip("before", "1.1.1.1")).await["decision"], "DENY");
+
+    // Only this test in the binary reads SCORING_CONFIG_PATH.
+    let path = common::scratch_path("decisions.json");
+    std::fs::write(&path, policy(50).to_string()).unwrap();
+    std::env::set_var("SCORING_CONFIG_PATH", &path);
+    let reload = service.post("/api/v1/config/reload", &json!({})).await;
+    std::env::remove_var("SCORING_CONFIG_PATH");
+    assert!(reload.status.is_success(), "{}", reload.text());
+
+    let result = service.score(&from_ip("after", "1.1.1.1")).await;
+    assert_eq!(result["fraudScore"], 50);
+    assert_eq!(result["decision"], "REVIEW");
+}
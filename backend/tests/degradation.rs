@@ -0,0 +1,128 @@
+//! Dependencies that fail mid-traffic: their rules skipped and the result marked
+//! `degraded` under `skip_on_failure`, the event turned away under `fail_request`, and
+//! readiness listing what is failing until it answers again.
+mod common;
+
+use axum::http::StatusCode;
+use common::{mocks, points, Harness};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+
+/// A page load from `ip`; the reputation client hasn't cached it if it's new.
+fn from_ip(session_id: &str, ip: &str) -> Value {
+    let mut event = common::event(session_id, "PageLoad", 0);
+    event["ipAddress"] = json!(ip);
+    event
+}
+
+async fn readiness(service: &Harness) -> Value {
+    let ready = service.get("/api/v1/ready").await;
+    assert_eq!(ready.status, StatusCode::OK);
+    ready.json()
+}
+
+async fn reputation_service(on_failure: &str) -> (mocks::ReputationServer, Harness) {
+    let scores = HashMap::from([("203.0.113.1".to_string(), 100), ("203.0.113.2".to_string(), 100)]);
+    let reputation = mocks::reputation_server(scores).await;
+    let vars = [
+        ("IP_REPUTATION_URL", reputation.url.as_str()),
+        ("IP_REPUTATION_TIMEOUT_MS", "2000"),
+        ("IP_REPUTATION_ON_FAILURE", on_failure),
+    ];
+    let service = Harness::with(&vars, json!({ "entity_reputation": { "half_life_secs": 0 } })).await;
+    (reputation, service)
+}
+
+async fn model_service(on_failure: &str) -> (mocks::ModelServer, Harness) {
+    let model = mocks::model_server(0.75).await;
+    let vars = [
+        ("MODEL_GRPC_ADDR", model.addr.as_str()),
+        ("MODEL_TIMEOUT_MS", "2000"),
+        ("MODEL_ON_FAILURE", on_failure),
+    ];
+    let service = Harness::with(&vars, json!({ "entity_reputation": { "half_life_secs": 0 } })).await;
+    (model, service)
+}
+
+// --- IP REPUTATION ---
+#[tokio::test]
+async fn a_failing_reputation_api_skips_its_rule_until_it_recovers() {
+    let (reputation, service) = reputation_service("skip_on_failure").await;
+    let healthy = service.score(&from_ip("rep-1", "203.0.113.1")).await;
+    assert_eq!(points(&healthy, "IP_REPUTATION"), Some(60));
+    assert_eq!(healthy["degraded"], false);
+    assert_eq!(readiness(&service).await["status"], "ready");
+
+    reputation.down.store(true, Ordering::Relaxed);
+    let series = "dependency_unavailable_total{dependency=\"ip_reputation\",action=\"skipped\"}";
+    let skipped = common::metric(series);
+    let result = service.score(&from_ip("rep-2", "1.1.1.1")).await;
+    assert_eq!(result["degraded"], true);
+    assert_eq!(result["skippedRules"], json!(["ip_reputation"]));
+    // Rules that don't need it still score.
+    assert_eq!(points(&result, "IP_BLACKLISTED"), Some(100));
+    assert_eq!(common::metric(series), skipped + 1.0);
+    let ready = readiness(&service).await;
+    assert_eq!(ready["status"], "degraded");
+    assert_eq!(ready["degraded"][0]["dependency"], "ip_reputation");
+    assert_eq!(ready["degraded"][0]["failsRequests"], false);
+
+    reputation.down.store(false, Ordering::Relaxed);
+    let recovered = service.score(&from_ip("rep-3", "203.0.113.2")).await;
+    assert_eq!(points(&recovered, "IP_REPUTATION"), Some(60));
+    assert_eq!(recovered["degraded"], false);
+    assert!(recovered.get("skippedRules").is_none(), "{}", recovered);
+    let ready = readiness(&service).await;
+    assert_eq!(ready["status"], "ready");
+    assert_eq!(ready["degraded"], json!([]));
+}
+
+#[tokio::test]
+async fn fail_request_turns_events_away_while_the_reputation_api_is_down() {
+    let (reputation, service) = reputation_service("fail_request").await;
+    assert_eq!(service.analyze(&from_ip("strict-1", "203.0.113.1")).await.status, StatusCode::OK);
+
+    reputation.down.store(true, Ordering::Relaxed);
+    let refused = service.analyze(&from_ip("strict-2", "198.51.100.7")).await;
+    assert_eq!(refused.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(refused.json()["error"], "dependency_unavailable", "{}", refused.text());
+    let ready = readiness(&service).await;
+    assert_eq!(ready["degraded"][0]["dependency"], "ip_reputation");
+    assert_eq!(ready["degraded"][0]["failsRequests"], true);
+
+    reputation.down.store(false, Ordering::Relaxed);
+    assert_eq!(service.analyze(&from_ip("strict-3", "203.0.113.2")).await.status, StatusCode::OK);
+    assert_eq!(readiness(&service).await["status"], "ready");
+}
+
+// --- MODEL ---
+#[tokio::test]
+async fn a_failing_model_leaves_the_rules_scoring() {
+    let (model, service) = model_service("skip_on_failure").await;
+    assert_eq!(points(&service.score(&from_ip("model-1", "198.51.100.7")).await, "MODEL_SCORE"), Some(30));
+
+    model.down.store(true, Ordering::Relaxed);
+    let result = service.score(&from_ip("model-2", "1.1.1.1")).await;
+    assert_eq!(points(&result, "MODEL_SCORE"), None);
+    assert_eq!(points(&result, "IP_BLACKLISTED"), Some(100));
+    assert_eq!(result["degraded"], true);
+    assert_eq!(result["skippedRules"], json!(["model"]));
+    assert_eq!(readiness(&service).await["degraded"][0]["dependency"], "model");
+
+    model.down.store(false, Ordering::Relaxed);
+    let recovered = service.score(&from_ip("model-3", "198.51.100.7")).await;
+    assert_eq!(points(&recovered, "MODEL_SCORE"), Some(30));
+    assert_eq!(recovered["degraded"], false);
+    assert_eq!(readiness(&service).await["status"], "ready");
+}
+
+#[tokio::test]
+async fn fail_request_turns_events_away_while_the_model_is_down() {
+    let (model, service) = model_service("fail_request").await;
+    model.down.store(true, Ordering::Relaxed);
+    let refused = service.analyze(&from_ip("model-4", "198.51.100.7")).await;
+    assert_eq!(refused.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(refused.json()["error"], "dependency_unavailable", "{}", refused.text());
+    assert_eq!(readiness(&service).await["degraded"][0]["failsRequests"], true);
+}
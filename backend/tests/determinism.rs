@@ -0,0 +1,79 @@
+//! Reproducible runs: results read time from the injected clock, and `RANDOM_SEED`
+//! fixes the ids generated for requests.
+mod common;
+
+use chrono::Duration;
+use common::Harness;
+use serde_json::json;
+
+/// At most 3 events per session in any 10 seconds, counting every event from the first,
+/// with no rule that remembers the session was flagged.
+fn windowed() -> serde_json::Value {
+    json!({
+        "min_events_for_stateful": 1,
+        "high_frequency": { "max_events": 3, "window_secs": 10 },
+        "fast_interaction": { "points": 0 },
+        "entity_reputation": { "half_life_secs": 0 },
+    })
+}
+
+#[tokio::test]
+async fn results_are_stamped_with_the_injected_clock() {
+    let service = Harness::new().await;
+    service.clock.set(common::at(0));
+    let result = service.post("/api/v1/events", &common::event("stamped", "PageLoad", 0)).await.json();
+    assert_eq!(result["checkTimestamp"], json!(common::at(0)));
+
+    service.clock.advance(Duration::minutes(5));
+    let result = service.post("/api/v1/events", &common::event("stamped", "PageLoad", 1_000)).await.json();
+    assert_eq!(result["checkTimestamp"], json!(common::at(300_000)));
+}
+
+#[tokio::test]
+async fn advancing_the_clock_across_the_frequency_window() {
+    let service = Harness::with(&[], windowed()).await;
+    let codes_at = |offset_ms: i64| {
+        let service = &service;
+        async move { common::codes(&service.score(&common::event("windowed", "Click", offset_ms)).await) }
+    };
+
+    for offset_ms in [0, 2_000, 4_000] {
+        assert!(codes_at(offset_ms).await.is_empty(), "at {}ms", offset_ms);
+    }
+    // A fourth event inside the 10s window is one too many.
+    assert_eq!(codes_at(6_000).await, ["HIGH_FREQUENCY"]);
+    // At 15s, only the event at 6s is still in the window.
+    assert!(codes_at(15_000).await.is_empty());
+}
+
+#[tokio::test]
+async fn a_seed_fixes_the_generated_request_ids() {
+    async fn ids(seed: &str) -> Vec<String> {
+        let service = Harness::with(&[("RANDOM_SEED", seed)], json!({})).await;
+        let mut ids = Vec::new();
+        for n in 0..3 {
+            let response = service.post("/api/v1/events", &common::event("seeded", "PageLoad", n * 1_000)).await;
+            ids.push(response.header("x-request-id").unwrap().to_string());
+        }
+        ids
+    }
+
+    let first = ids("42").await;
+    assert_eq!(first, ids("42").await);
+    assert_ne!(first, ids("43").await);
+    assert!(first[0] != first[1] && first[1] != first[2]);
+}
+
+#[tokio::test]
+async fn replaying_a_session_reproduces_its_results() {
+    async fn replay() -> Vec<serde_json::Value> {
+        let service = Harness::with(&[("RANDOM_SEED", "7")], windowed()).await;
+        let mut results = Vec::new();
+        for offset_ms in [0, 500, 900, 1_200, 30_000] {
+            results.push(service.score(&common::event("replayed", "Click", offset_ms)).await);
+        }
+        results
+    }
+
+    assert_eq!(replay().await, replay().await);
+}
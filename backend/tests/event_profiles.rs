@@ -0,0 +1,65 @@
+//! Per-event-type scoring profiles (`event_profiles`), falling back to the global config.
+mod common;
+
+use common::{points, Harness};
+use serde_json::{json, Value};
+
+fn blacklisted(session_id: &str, event_type: &str) -> Value {
+    let mut event = common::event(session_id, event_type, 0);
+    event["ipAddress"] = json!("1.1.1.1");
+    event
+}
+
+/// A 40-point blacklist under the default threshold of 50, with submissions held to 30.
+/// Entity reputation is off so each event scores on its own.
+fn stricter_submissions(profile: Value) -> Value {
+    json!({
+        "blacklist": { "points": 40 },
+        "entity_reputation": { "half_life_secs": 0 },
+        "event_profiles": { "FormSubmission": profile },
+    })
+}
+
+#[tokio::test]
+async fn a_submission_uses_its_stricter_flag_threshold() {
+    let service = Harness::with(&[], stricter_submissions(json!({ "flag_threshold": 30 }))).await;
+    let submission = service.score(&blacklisted("profile-1", "FormSubmission")).await;
+    assert_eq!(submission["fraudScore"], 40);
+    assert_eq!(submission["flagged"], true);
+
+    let page_load = service.score(&blacklisted("profile-2", "PageLoad")).await;
+    assert_eq!(page_load["fraudScore"], 40);
+    assert_eq!(page_load["flagged"], false);
+}
+
+#[tokio::test]
+async fn profile_weights_replace_or_drop_a_rules_points() {
+    let service = Harness::with(&[], stricter_submissions(json!({ "weights": { "IP_BLACKLISTED": 70 } }))).await;
+    let submission = service.score(&blacklisted("weights-1", "FormSubmission")).await;
+    assert_eq!(points(&submission, "IP_BLACKLISTED"), Some(70));
+    assert_eq!(submission["flagged"], true);
+    assert_eq!(points(&service.score(&blacklisted("weights-2", "Click")).await, "IP_BLACKLISTED"), Some(40));
+
+    let service = Harness::with(&[], stricter_submissions(json!({ "weights": { "IP_BLACKLISTED": 0 } }))).await;
+    let submission = service.score(&blacklisted("weights-3", "FormSubmission")).await;
+    assert_eq!(points(&submission, "IP_BLACKLISTED"), None);
+    assert_eq!(submission["fraudScore"], 0);
+}
+
+#[tokio::test]
+async fn a_profile_threshold_compares_the_normalized_score() {
+    let mut scoring = stricter_submissions(json!({ "flag_threshold": 10 }));
+    scoring["confidence"] = json!({ "mode": "logistic", "midpoint": 60, "steepness": 0.1 });
+    let service = Harness::with(&[], scoring).await;
+    let submission = service.score(&blacklisted("normalized-1", "FormSubmission")).await;
+    assert_eq!((submission["rawScore"].as_i64(), submission["fraudScore"].as_i64()), (Some(40), Some(12)));
+    assert_eq!(submission["flagged"], true);
+    assert_eq!(service.score(&blacklisted("normalized-2", "PageLoad")).await["flagged"], false);
+}
+
+#[tokio::test]
+async fn explain_honours_the_profile() {
+    let service = Harness::with(&[], stricter_submissions(json!({ "flag_threshold": 30 }))).await;
+    let explained = service.explain(&blacklisted("explain-1", "FormSubmission")).await;
+    assert_eq!(explained["flagged"], true, "{}", explained);
+}
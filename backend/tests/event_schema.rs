@@ -0,0 +1,100 @@
+//! Checking raw JSON bodies against `EVENT_SCHEMA_PATH` before they are decoded.
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use common::Harness;
+use serde_json::{json, Value};
+
+/// A service whose schema allows only the event fields, with string metadata values.
+async fn with_schema() -> Harness {
+    let schema = json!({
+        "type": "object",
+        "required": ["sessionId", "eventType", "timestamp", "ipAddress"],
+        "additionalProperties": false,
+        "properties": {
+            "sessionId": { "type": "string" },
+            "userId": { "type": "string" },
+            "eventType": { "type": "string" },
+            "timestamp": { "type": "string" },
+            "ipAddress": { "type": "string" },
+            "userAgent": { "type": "string" },
+            "metadata": { "type": "object", "additionalProperties": { "type": "string" } },
+        },
+    });
+    let path = common::scratch_path("event-schema.json");
+    std::fs::write(&path, schema.to_string()).unwrap();
+    Harness::with(&[("EVENT_SCHEMA_PATH", path.to_str().unwrap())], json!({})).await
+}
+
+fn with_field(session_id: &str, key: &str, value: Value) -> Value {
+    let mut event = common::event(session_id, "PageLoad", 0);
+    event[key] = value;
+    event
+}
+
+#[tokio::test]
+async fn a_conforming_event_is_scored() {
+    let service = with_schema().await;
+    let event = with_field("schema-ok", "metadata", json!({ "page": "/home" }));
+    let result = service.score(&event).await;
+    assert_eq!(result["sessionId"], "schema-ok");
+}
+
+#[tokio::test]
+async fn an_unknown_field_is_rejected_with_its_path() {
+    let service = with_schema().await;
+    // serde would ignore the misspelling and score the event as anonymous.
+    let response = service.analyze(&with_field("schema-typo", "user_id", json!("u-1"))).await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    let body = response.json();
+    assert_eq!(body["error"], "validation");
+    assert_eq!(
+        body["message"],
+        "Event does not match the event schema at `/` (schema path `/additionalProperties`)"
+    );
+}
+
+#[tokio::test]
+async fn a_wrongly_typed_value_is_rejected_without_echoing_it() {
+    let service = with_schema().await;
+    let event = with_field("schema-type", "metadata", json!({ "amount": 4242.5 }));
+    let response = service.analyze(&event).await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    let message = response.json()["message"].as_str().unwrap().to_string();
+    assert!(message.contains("at `/metadata/amount`"), "{}", message);
+    assert!(!message.contains("4242"), "{}", message);
+
+    // Nothing was stored.
+    let since = common::at(-3_600_000).to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    assert_eq!(service.get(&format!("/api/v1/export?since={}", since)).await.text(), "");
+}
+
+#[tokio::test]
+async fn further_violations_are_counted() {
+    let service = with_schema().await;
+    let mut event = with_field("schema-many", "extra", json!(1));
+    event["sessionId"] = json!(7);
+    event.as_object_mut().unwrap().remove("ipAddress");
+    let response = service.analyze(&event).await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    let message = response.json()["message"].as_str().unwrap().to_string();
+    assert!(message.ends_with(", and 2 more violations"), "{}", message);
+}
+
+#[tokio::test]
+async fn malformed_json_is_still_a_bad_request() {
+    let service = with_schema().await;
+    let request = Request::post("/api/v1/events")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from("{\"sessionId\":"))
+        .unwrap();
+    assert_eq!(service.send(request).await.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn without_a_schema_unknown_fields_are_ignored() {
+    let service = Harness::new().await;
+    let result = service.score(&with_field("no-schema", "user_id", json!("u-1"))).await;
+    assert_eq!(result["sessionId"], "no-schema");
+}
@@ -0,0 +1,189 @@
+//! `POST /api/v1/events`: what it accepts and how it answers.
+mod common;
+
+use axum::http::StatusCode;
+use common::Harness;
+use serde_json::json;
+
+// --- IP ADDRESSES ---
+#[tokio::test]
+async fn scores_ipv4_and_ipv6_events() {
+    let service = Harness::new().await;
+    for ip in ["203.0.113.9", "2001:db8::1"] {
+        let mut event = common::event("ip-ok", "PageLoad", 0);
+        event["ipAddress"] = json!(ip);
+        let response = service.analyze(&event).await;
+        assert_eq!(response.status, StatusCode::OK, "{}: {}", ip, response.text());
+    }
+}
+
+#[tokio::test]
+async fn rejects_an_ip_address_that_does_not_parse() {
+    let service = Harness::new().await;
+    let mut event = common::event("ip-bad", "PageLoad", 0);
+    event["ipAddress"] = json!("not-an-ip");
+    let response = service.analyze(&event).await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    let body = response.json();
+    assert_eq!(body["error"], "validation");
+    assert!(body["message"].as_str().unwrap().contains("invalid IP address `not-an-ip`"), "{}", body);
+}
+
+// --- RESPONSE VERBOSITY ---
+fn blacklisted(session_id: &str) -> serde_json::Value {
+    let mut event = common::event(session_id, "PageLoad", 0);
+    event["ipAddress"] = json!("1.1.1.1");
+    event
+}
+
+#[tokio::test]
+async fn minimal_verbosity_drops_reasons_for_clean_events_only() {
+    let service = Harness::new().await;
+    let clean = service
+        .post("/api/v1/events?verbosity=minimal", &common::event("verbose-1", "PageLoad", 0))
+        .await
+        .json();
+    assert_eq!(clean["flagged"], false);
+    assert_eq!(clean["sessionId"], "verbose-1");
+    assert_eq!(clean["fraudScore"], 0);
+    assert!(clean.get("reasons").is_none(), "{}", clean);
+    assert!(clean.get("breakdown").is_none(), "{}", clean);
+
+    let flagged = service
+        .post("/api/v1/events?verbosity=minimal", &blacklisted("verbose-2"))
+        .await
+        .json();
+    assert_eq!(flagged["flagged"], true);
+    assert_eq!(flagged["reasons"], json!(["Blacklisted IP address"]));
+    assert_eq!(flagged["breakdown"][0]["code"], "IP_BLACKLISTED");
+}
+
+#[tokio::test]
+async fn full_verbosity_is_the_default() {
+    let service = Harness::new().await;
+    let clean = service.score(&common::event("verbose-3", "PageLoad", 0)).await;
+    assert!(clean["reasons"].is_array(), "{}", clean);
+    assert!(clean["breakdown"].is_array(), "{}", clean);
+}
+
+#[tokio::test]
+async fn the_query_parameter_overrides_the_configured_verbosity() {
+    let service = Harness::with(&[("RESPONSE_VERBOSITY", "minimal")], json!({})).await;
+    let event = common::event("verbose-4", "PageLoad", 0);
+    assert!(service.score(&event).await.get("reasons").is_none());
+    let full = service.post("/api/v1/events?verbosity=full", &event).await.json();
+    assert!(full["reasons"].is_array(), "{}", full);
+}
+
+// --- CONFIDENCE ---
+#[tokio::test]
+async fn results_carry_the_raw_score_and_the_normalized_one() {
+    let service = Harness::with(
+        &[],
+        json!({ "flag_threshold": 60, "confidence": { "mode": "logistic", "midpoint": 100, "steepness": 0.1 } }),
+    )
+    .await;
+    let clean = service.score(&common::event("conf-1", "PageLoad", 0)).await;
+    assert_eq!(clean["rawScore"], 0);
+    assert_eq!(clean["fraudScore"], 0);
+    assert_eq!(clean["confidence"], 0);
+
+    // 100 points sits at the midpoint: short of the 60 needed to flag.
+    let midpoint = service.score(&blacklisted("conf-2")).await;
+    assert_eq!(midpoint["rawScore"], 100);
+    assert_eq!(midpoint["fraudScore"], 50);
+    assert_eq!(midpoint["confidence"], 50);
+    assert_eq!(midpoint["flagged"], false);
+
+    // Without normalizing, a raw score past 100 is clamped, and flags at any threshold.
+    let service = Harness::with(&[], json!({ "flag_threshold": 100, "blacklist": { "points": 180 } })).await;
+    let clamped = service.score(&blacklisted("conf-3")).await;
+    assert_eq!((clamped["rawScore"].as_i64(), clamped["fraudScore"].as_i64()), (Some(180), Some(100)));
+    assert_eq!(clamped["flagged"], true);
+}
+
+// --- TIMESTAMP FRESHNESS ---
+/// Posts an event `offset_secs` from the service's clock, which stays at `start`.
+async fn at_offset(service: &Harness, session_id: &str, offset_secs: i64) -> common::TestResponse {
+    service.post("/api/v1/events", &common::event(session_id, "PageLoad", offset_secs * 1000)).await
+}
+
+#[tokio::test]
+async fn accepts_events_inside_the_freshness_window() {
+    let service = Harness::new().await;
+    for (session_id, offset_secs) in [("fresh-1", -599), ("fresh-2", 0), ("fresh-3", 599)] {
+        let response = at_offset(&service, session_id, offset_secs).await;
+        assert_eq!(response.status, StatusCode::OK, "{}s: {}", offset_secs, response.text());
+    }
+}
+
+#[tokio::test]
+async fn rejects_events_too_old_or_too_far_in_the_future() {
+    let service = Harness::new().await;
+    let old = at_offset(&service, "stale", -601).await;
+    assert_eq!(old.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(old.json()["error"], "validation");
+    assert_eq!(old.json()["message"], "Event timestamp is 601s in the past, outside the 600s window");
+
+    let new = at_offset(&service, "early", 601).await;
+    assert_eq!(new.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(new.json()["message"], "Event timestamp is 601s in the future, outside the 600s window");
+
+    // Neither was stored.
+    let since = common::at(-3_600_000).to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    let export = service.get(&format!("/api/v1/export?since={}", since)).await;
+    assert_eq!(export.text(), "");
+}
+
+#[tokio::test]
+async fn the_window_is_configurable_and_zero_turns_it_off() {
+    let narrow = Harness::with(&[("EVENT_FRESHNESS_WINDOW_SECS", "30")], json!({})).await;
+    assert_eq!(at_offset(&narrow, "narrow", -31).await.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(at_offset(&narrow, "narrow", -29).await.status, StatusCode::OK);
+
+    let off = Harness::with(&[("EVENT_FRESHNESS_WINDOW_SECS", "0")], json!({})).await;
+    assert_eq!(at_offset(&off, "off", -86_400).await.status, StatusCode::OK);
+}
+
+// --- ERROR RESPONSES ---
+#[tokio::test]
+async fn unparseable_json_is_a_bad_request() {
+    let service = Harness::new().await;
+    let request = axum::http::Request::post("/api/v1/events")
+        .header("content-type", "application/json")
+        .body(axum::body::Body::from("{\"sessionId\":"))
+        .unwrap();
+    let response = service.send(request).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.json()["error"], "bad_request");
+}
+
+#[tokio::test]
+async fn json_missing_a_field_is_a_validation_error() {
+    let service = Harness::new().await;
+    let response = service.post("/api/v1/events", &json!({ "sessionId": "no-type" })).await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response.json()["error"], "validation");
+}
+
+// --- STATEFUL RULES ---
+#[tokio::test]
+async fn the_frequency_rule_waits_for_min_events_for_stateful() {
+    let scoring = json!({
+        "min_events_for_stateful": 3,
+        "high_frequency": { "max_events": 1 },
+        "entity_reputation": { "half_life_secs": 0 },
+    });
+    let service = Harness::with(&[], scoring).await;
+    for offset_ms in [0, 5000] {
+        let result = service.score(&common::event("young", "PageLoad", offset_ms)).await;
+        assert_eq!(common::points(&result, "HIGH_FREQUENCY"), None, "{}", result);
+    }
+    let third = service.score(&common::event("young", "PageLoad", 10_000)).await;
+    assert_eq!(common::points(&third, "HIGH_FREQUENCY"), Some(50), "{}", third);
+
+    // The blacklist is per event, and applies to a session's first.
+    let mut blacklisted = common::event("fresh", "PageLoad", 0);
+    blacklisted["ipAddress"] = json!("1.1.1.1");
+    assert_eq!(common::points(&service.score(&blacklisted).await, "IP_BLACKLISTED"), Some(100));
+}
@@ -0,0 +1,53 @@
+//! `POST /api/v1/events/explain`: the scoring pipeline without its side effects.
+mod common;
+
+use common::{codes, Harness};
+use serde_json::json;
+
+/// A session limited to 3 events, with `window_secs` as given.
+async fn limited(window_secs: u64) -> Harness {
+    Harness::with(
+        &[],
+        json!({ "high_frequency": { "max_events": 3, "window_secs": window_secs } }),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn explaining_twice_does_not_count_toward_the_frequency_limit() {
+    // Without a window the stored session is counted; with one, the rate counters are.
+    for window_secs in [0, 60] {
+        let service = limited(window_secs).await;
+        service.score(&common::event("explain-1", "Click", 0)).await;
+        service.score(&common::event("explain-1", "Click", 2_000)).await;
+
+        let third = common::event("explain-1", "Click", 4_000);
+        let first = service.explain(&third).await;
+        let second = service.explain(&third).await;
+        assert!(!codes(&first).contains(&"HIGH_FREQUENCY".to_string()), "window {}: {}", window_secs, first);
+        assert_eq!(first["breakdown"], second["breakdown"], "window {}", window_secs);
+        assert_eq!(first["fraudScore"], second["fraudScore"], "window {}", window_secs);
+
+        // Had either explain been counted, the session would be over its limit here.
+        let scored = service.score(&third).await;
+        assert!(!codes(&scored).contains(&"HIGH_FREQUENCY".to_string()), "window {}: {}", window_secs, scored);
+        let fourth = service.score(&common::event("explain-1", "Click", 6_000)).await;
+        assert!(codes(&fourth).contains(&"HIGH_FREQUENCY".to_string()), "window {}: {}", window_secs, fourth);
+    }
+}
+
+#[tokio::test]
+async fn explain_scores_against_a_supplied_history_instead_of_the_stored_one() {
+    let service = limited(0).await;
+    let history: Vec<_> = (0..3).map(|n| common::event("explain-2", "Click", n * 2_000)).collect();
+    let event = common::event("explain-2", "Click", 6_000);
+    service.clock.set(common::at(6_000));
+    let response = service
+        .post("/api/v1/events/explain", &json!({ "event": event, "history": history }))
+        .await;
+    assert!(codes(&response.json()).contains(&"HIGH_FREQUENCY".to_string()), "{}", response.text());
+
+    // Nothing from the supplied history was stored.
+    let scored = service.score(&event).await;
+    assert!(codes(&scored).is_empty(), "{}", scored);
+}
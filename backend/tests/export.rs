@@ -0,0 +1,174 @@
+//! `GET /api/v1/export`: stored events as newline-delimited JSON.
+mod common;
+
+use common::Harness;
+use serde_json::Value;
+
+/// Three events in `export-a` and two in `export-b`, a second apart.
+async fn with_two_sessions() -> Harness {
+    let service = Harness::new().await;
+    for (n, session_id) in ["export-a", "export-b", "export-a", "export-b", "export-a"].iter().enumerate() {
+        service.score(&common::event(session_id, "PageLoad", n as i64 * 1_000)).await;
+    }
+    service
+}
+
+/// Each line of the body, parsed.
+fn lines(body: &str) -> Vec<Value> {
+    body.lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("invalid JSON line ({}): {}", e, line)))
+        .collect()
+}
+
+fn since(offset_ms: i64) -> String {
+    common::at(offset_ms).to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+
+#[tokio::test]
+async fn exports_one_json_line_per_stored_event() {
+    let service = with_two_sessions().await;
+    let response = service.get(&format!("/api/v1/export?since={}", since(-1))).await;
+    assert!(response.status.is_success());
+    assert_eq!(response.header("content-type"), Some("application/x-ndjson"));
+    let body = response.text();
+    assert!(body.ends_with('\n'));
+    let events = lines(&body);
+    assert_eq!(events.len(), 5);
+    for event in &events {
+        assert!(event["sessionId"].is_string() && event["eventType"] == "PageLoad", "{}", event);
+    }
+}
+
+#[tokio::test]
+async fn only_events_after_since_are_exported() {
+    let service = with_two_sessions().await;
+    let events = lines(&service.get(&format!("/api/v1/export?since={}", since(2_000))).await.text());
+    assert_eq!(events.len(), 2);
+    let empty = service.get(&format!("/api/v1/export?since={}", since(10_000))).await.text();
+    assert!(empty.is_empty(), "{}", empty);
+}
+
+#[tokio::test]
+async fn the_session_filter_exports_one_session() {
+    let service = with_two_sessions().await;
+    let events = lines(
+        &service
+            .get(&format!("/api/v1/export?since={}&session=export-b", since(-1)))
+            .await
+            .text(),
+    );
+    assert_eq!(events.len(), 2);
+    assert!(events.iter().all(|event| event["sessionId"] == "export-b"));
+}
+
+#[tokio::test]
+async fn since_is_required() {
+    let service = with_two_sessions().await;
+    assert_eq!(service.get("/api/v1/export").await.status, axum::http::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn sessions_keep_their_newest_max_events_per_session() {
+    let service = Harness::with(&[("MAX_EVENTS_PER_SESSION", "25")], serde_json::json!({})).await;
+    for n in 0..35 {
+        service.score(&common::event("export-capped", "PageLoad", n * 1_000)).await;
+    }
+    let events = lines(&service.get(&format!("/api/v1/export?since={}&session=export-capped", since(-1))).await.text());
+    let timestamps: Vec<&str> = events.iter().map(|event| event["timestamp"].as_str().unwrap()).collect();
+    let newest: Vec<String> = (10..35).map(|n| common::event("", "", n * 1_000)["timestamp"].as_str().unwrap().to_string()).collect();
+    assert_eq!(timestamps, newest);
+}
+
+#[tokio::test]
+async fn a_cap_below_what_the_rules_windows_need_is_refused() {
+    let service = common::service_config(&[("MAX_EVENTS_PER_SESSION", "3")]);
+    let error = rust_fraud_detector::AppState::new(service, common::scoring_config(serde_json::json!({})), common::metrics())
+        .await
+        .err()
+        .expect("a cap of 3 is refused");
+    assert!(error.contains("MAX_EVENTS_PER_SESSION is 3"), "{}", error);
+}
+
+// --- PAGING ---
+/// The URL in a `Link: <url>; rel="next"` header.
+fn next_page(response: &common::TestResponse) -> Option<String> {
+    let link = response.header("link")?;
+    let url = link.strip_prefix('<')?.split_once('>')?.0;
+    Some(url.to_string())
+}
+
+/// Every event reachable from `url` by following `Link` headers, and the page count.
+async fn follow(service: &Harness, url: String) -> (Vec<Value>, usize) {
+    let mut events = Vec::new();
+    let mut pages = 0;
+    let mut url = Some(url);
+    while let Some(current) = url {
+        let response = service.get(&current).await;
+        assert!(response.status.is_success(), "{}: {}", current, response.text());
+        events.extend(lines(&response.text()));
+        pages += 1;
+        url = next_page(&response);
+    }
+    (events, pages)
+}
+
+fn keys(events: &[Value]) -> Vec<(String, String)> {
+    events
+        .iter()
+        .map(|event| (event["timestamp"].as_str().unwrap().to_string(), event["sessionId"].as_str().unwrap().to_string()))
+        .collect()
+}
+
+#[tokio::test]
+async fn pages_cover_every_event_once_in_timestamp_order() {
+    let service = with_two_sessions().await;
+    let (events, pages) = follow(&service, format!("/api/v1/export?since={}&limit=2", since(-1))).await;
+    assert_eq!(pages, 3);
+    let keys = keys(&events);
+    let mut expected = keys.clone();
+    expected.sort();
+    expected.dedup();
+    assert_eq!(keys, expected, "pages repeat or reorder events");
+    assert_eq!(keys.len(), 5, "pages skip events");
+}
+
+#[tokio::test]
+async fn a_page_holds_limit_events_and_links_the_next() {
+    let service = with_two_sessions().await;
+    let first = service.get(&format!("/api/v1/export?since={}&limit=2", since(-1))).await;
+    assert_eq!(first.header("content-type"), Some("application/x-ndjson"));
+    assert_eq!(lines(&first.text()).len(), 2);
+    let next = next_page(&first).expect("a next page");
+    assert!(next.starts_with("/api/v1/export?since=") && next.contains("&limit=2&cursor="), "{}", next);
+
+    let last = service.get(&format!("/api/v1/export?since={}&limit=5", since(-1))).await;
+    assert_eq!(lines(&last.text()).len(), 5);
+    assert_eq!(last.header("link"), None);
+}
+
+#[tokio::test]
+async fn events_arriving_between_pages_are_picked_up_later() {
+    let service = with_two_sessions().await;
+    let first = service.get(&format!("/api/v1/export?since={}&limit=2", since(-1))).await;
+    let seen = lines(&first.text());
+    service.score(&common::event("export-c", "PageLoad", 10_000)).await;
+    let (rest, _) = follow(&service, next_page(&first).unwrap()).await;
+    let all: Vec<Value> = seen.into_iter().chain(rest).collect();
+    assert_eq!(keys(&all).len(), 6);
+    assert_eq!(all.last().unwrap()["sessionId"], "export-c");
+}
+
+#[tokio::test]
+async fn paging_keeps_the_session_filter() {
+    let service = with_two_sessions().await;
+    let (events, pages) = follow(&service, format!("/api/v1/export?since={}&limit=1&session=export-a", since(-1))).await;
+    assert_eq!(pages, 3);
+    assert!(events.iter().all(|event| event["sessionId"] == "export-a"), "{:?}", events);
+}
+
+#[tokio::test]
+async fn an_invalid_cursor_is_a_bad_request() {
+    let service = with_two_sessions().await;
+    let response = service.get(&format!("/api/v1/export?since={}&cursor=garbage", since(-1))).await;
+    assert_eq!(response.status, axum::http::StatusCode::BAD_REQUEST);
+}
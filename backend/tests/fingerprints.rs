@@ -0,0 +1,66 @@
+//! Device fingerprints (`metadata["fingerprint"]`) shared by more sessions than allowed.
+mod common;
+
+use common::{points, Harness};
+use serde_json::{json, Value};
+
+const FINGERPRINT: &str = "d3v1c3-h4sh";
+
+fn from_device(session_id: &str, offset_ms: i64, fingerprint: &str) -> Value {
+    let mut event = common::event(session_id, "PageLoad", offset_ms);
+    event["metadata"] = json!({ "fingerprint": fingerprint });
+    event
+}
+
+#[tokio::test]
+async fn sessions_past_the_limit_on_one_device_are_flagged() {
+    let service = Harness::new().await;
+    for n in 1..=3 {
+        let result = service.score(&from_device(&format!("farm-{}", n), n * 1000, FINGERPRINT)).await;
+        assert_eq!(points(&result, "SHARED_FINGERPRINT"), None, "session {}", n);
+        assert_eq!(result["flagged"], false);
+    }
+
+    let fourth = service.score(&from_device("farm-4", 4000, FINGERPRINT)).await;
+    assert_eq!(points(&fourth, "SHARED_FINGERPRINT"), Some(50));
+    assert_eq!(fourth["flagged"], true);
+    let reason = &fourth["breakdown"][0]["reason"];
+    assert_eq!(reason, "Device fingerprint seen in 4 sessions within 60 minutes; other sessions: farm-3, farm-2, farm-1");
+    assert!(!fourth.to_string().contains(FINGERPRINT));
+
+    // An earlier session's next event sees the others too.
+    let again = service.score(&from_device("farm-1", 5000, FINGERPRINT)).await;
+    assert_eq!(points(&again, "SHARED_FINGERPRINT"), Some(50));
+}
+
+#[tokio::test]
+async fn other_devices_and_events_without_one_are_not_counted() {
+    let service = Harness::new().await;
+    for n in 1..=3 {
+        service.score(&from_device(&format!("mixed-{}", n), n * 1000, FINGERPRINT)).await;
+    }
+    let other = service.score(&from_device("mixed-4", 4000, "another-device")).await;
+    assert_eq!(points(&other, "SHARED_FINGERPRINT"), None);
+    let none = service.score(&common::event("mixed-5", "PageLoad", 5000)).await;
+    assert_eq!(points(&none, "SHARED_FINGERPRINT"), None);
+}
+
+#[tokio::test]
+async fn sessions_idle_past_the_window_drop_out() {
+    let scoring = json!({
+        "device_fingerprint": { "window_secs": 60, "max_sessions": 2 },
+        "entity_reputation": { "half_life_secs": 0 },
+    });
+    let service = Harness::with(&[], scoring).await;
+    service.score(&from_device("idle-1", 0, FINGERPRINT)).await;
+    service.score(&from_device("idle-2", 30_000, FINGERPRINT)).await;
+    let third = service.score(&from_device("idle-3", 45_000, FINGERPRINT)).await;
+    assert_eq!(points(&third, "SHARED_FINGERPRINT"), Some(50));
+
+    // A minute after the first session's only event, it no longer counts.
+    let later = service.score(&from_device("idle-4", 61_000, FINGERPRINT)).await;
+    assert_eq!(points(&later, "SHARED_FINGERPRINT"), Some(50));
+    assert!(later["breakdown"][0]["reason"].as_str().unwrap().ends_with("other sessions: idle-3, idle-2"), "{}", later);
+    let much_later = service.score(&from_device("idle-5", 200_000, FINGERPRINT)).await;
+    assert_eq!(points(&much_later, "SHARED_FINGERPRINT"), None);
+}
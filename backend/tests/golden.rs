@@ -0,0 +1,149 @@
+//! Golden-score regression suite. Every file in `tests/scenarios/` is a sequence of
+//! events with the result each one is expected to get; this test scores them, in order,
+//! on a fresh service per file, and fails on any result that differs.
+//!
+//! A change that is meant to move scores (a new weight, a new rule) regenerates them:
+//!
+//! ```text
+//! UPDATE_GOLDENS=1 cargo test --test golden
+//! ```
+//!
+//! which rewrites each file's `expected` values with what the service now returns, so
+//! the change shows up as a reviewable diff of the scenario files. New scenarios can
+//! leave `expected` out and get it the same way.
+mod common;
+
+use common::mocks;
+use common::Harness;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Scenario {
+    description: String,
+    /// Service environment variables, as a deployment would set them.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    env: BTreeMap<String, String>,
+    /// A scoring config, in the `SCORING_CONFIG_PATH` format.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scoring: Option<Value>,
+    /// GeoIP databases answering the same record for every IP.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    geoip: Option<GeoIpFixture>,
+    /// Scores the IP reputation API answers, by IP; unlisted IPs score 0.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ip_reputation: Option<HashMap<String, u64>>,
+    /// The probability the model server answers every event with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    model_probability: Option<f32>,
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct GeoIpFixture {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    city: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    asn: Option<Value>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Step {
+    event: Value,
+    /// The result, minus its `checkTimestamp`; or the status and body of a rejection.
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    expected: Value,
+}
+
+fn scenario_files() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scenarios");
+    let mut files: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("reading {}: {}", dir.display(), e))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+        .collect();
+    files.sort();
+    files
+}
+
+async fn service_for(scenario: &Scenario) -> Harness {
+    let mut env: Vec<(String, String)> = scenario.env.iter().map(|(key, value)| (key.clone(), value.clone())).collect();
+    if let Some(geoip) = &scenario.geoip {
+        if let Some(city) = &geoip.city {
+            let path = mocks::geoip_database("city", "GeoLite2-City", city);
+            env.push(("GEOIP_CITY_DB_PATH".to_string(), path.display().to_string()));
+        }
+        if let Some(asn) = &geoip.asn {
+            let path = mocks::geoip_database("asn", "GeoLite2-ASN", asn);
+            env.push(("GEOIP_ASN_DB_PATH".to_string(), path.display().to_string()));
+        }
+    }
+    if let Some(scores) = &scenario.ip_reputation {
+        let server = mocks::reputation_server(scores.clone()).await;
+        env.push(("IP_REPUTATION_URL".to_string(), server.url));
+        env.push(("IP_REPUTATION_TIMEOUT_MS".to_string(), "2000".to_string()));
+    }
+    if let Some(probability) = scenario.model_probability {
+        let server = mocks::model_server(probability).await;
+        env.push(("MODEL_GRPC_ADDR".to_string(), server.addr));
+        env.push(("MODEL_TIMEOUT_MS".to_string(), "2000".to_string()));
+    }
+    let env: Vec<(&str, &str)> = env.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect();
+    Harness::with(&env, scenario.scoring.clone().unwrap_or_else(|| json!({}))).await
+}
+
+/// What a step is compared on: everything but the time it was scored at.
+async fn observe(service: &Harness, event: &Value) -> Value {
+    let response = service.analyze(event).await;
+    if !response.status.is_success() {
+        return json!({ "status": response.status.as_u16(), "body": response.json() });
+    }
+    let mut result = response.json();
+    result.as_object_mut().unwrap().remove("checkTimestamp");
+    result
+}
+
+#[tokio::test]
+async fn scenarios_match_goldens() {
+    let update = std::env::var("UPDATE_GOLDENS").is_ok_and(|value| value == "1");
+    let mut failures = Vec::new();
+    for path in scenario_files() {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let raw = std::fs::read_to_string(&path).unwrap();
+        let mut scenario: Scenario = serde_json::from_str(&raw).unwrap_or_else(|e| panic!("{}: {}", name, e));
+        let service = service_for(&scenario).await;
+        for (index, step) in scenario.steps.iter_mut().enumerate() {
+            let observed = observe(&service, &step.event).await;
+            if observed == step.expected {
+                continue;
+            }
+            if update {
+                step.expected = observed;
+            } else {
+                failures.push(format!(
+                    "{} step {}:\n  expected {}\n  observed {}",
+                    name,
+                    index + 1,
+                    step.expected,
+                    observed
+                ));
+            }
+        }
+        if update {
+            let updated = serde_json::to_string_pretty(&scenario).unwrap() + "\n";
+            if updated != raw {
+                std::fs::write(&path, updated).unwrap();
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} step(s) differ from their goldens. If the change is intended, regenerate them \
+         with `UPDATE_GOLDENS=1 cargo test --test golden` and review the diff.\n\n{}",
+        failures.len(),
+        failures.join("\n\n")
+    );
+}
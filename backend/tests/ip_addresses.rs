@@ -0,0 +1,64 @@
+//! IP addresses compared in their canonical form, however the payload or the blacklist
+//! spells them.
+mod common;
+
+use common::{points, Harness};
+use serde_json::{json, Value};
+
+fn from_ip(session_id: &str, ip: &str) -> Value {
+    let mut event = common::event(session_id, "PageLoad", 0);
+    event["ipAddress"] = json!(ip);
+    event
+}
+
+#[tokio::test]
+async fn a_mapped_ipv6_payload_hits_the_ipv4_blacklist_entry() {
+    let service = Harness::new().await;
+    for (session_id, ip) in [("mapped-1", "::ffff:1.1.1.1"), ("mapped-2", "::FFFF:1.1.1.1"), ("mapped-3", "::ffff:101:101")] {
+        let result = service.score(&from_ip(session_id, ip)).await;
+        assert_eq!(points(&result, "IP_BLACKLISTED"), Some(100), "{}", ip);
+    }
+}
+
+#[tokio::test]
+async fn ipv6_blacklist_entries_match_every_spelling() {
+    let path = common::scratch_path("blacklist.txt");
+    std::fs::write(&path, "2001:0DB8:0000:0000:0000:0000:0000:0001\n").unwrap();
+    let service = Harness::with(&[("BLACKLIST_PATH", path.to_str().unwrap())], json!({})).await;
+    for (session_id, ip) in [("v6-1", "2001:db8::1"), ("v6-2", "2001:DB8::1"), ("v6-3", "2001:db8:0::0:1"), ("v6-4", "fe80::1%eth0")] {
+        let result = service.score(&from_ip(session_id, ip)).await;
+        let expected = (ip != "fe80::1%eth0").then_some(100);
+        assert_eq!(points(&result, "IP_BLACKLISTED"), expected, "{}", ip);
+    }
+}
+
+/// The top IPs the stats endpoint reports, as `(ip, events)`.
+async fn top_ips(service: &Harness) -> Vec<(String, u64)> {
+    let stats = service.get("/api/v1/stats").await.json();
+    stats["topIps"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| (entry["ip"].as_str().unwrap().to_string(), entry["events"].as_u64().unwrap()))
+        .collect()
+}
+
+#[tokio::test]
+async fn ipv6_counters_group_by_the_configured_prefix() {
+    // Top IPs rank by score, so both addresses are blacklisted to give them one.
+    let path = common::scratch_path("blacklist.txt");
+    std::fs::write(&path, "2001:db8:1:2:aaaa::1\n2001:db8:1:2:bbbb::9\n").unwrap();
+    let blacklist = path.to_str().unwrap();
+
+    let grouped = Harness::with(&[("BLACKLIST_PATH", blacklist)], json!({ "ipv6_counter_prefix": 64 })).await;
+    let ungrouped = Harness::with(&[("BLACKLIST_PATH", blacklist)], json!({})).await;
+    for service in [&grouped, &ungrouped] {
+        service.score(&from_ip("prefix-1", "2001:db8:1:2:aaaa::1")).await;
+        service.score(&from_ip("prefix-2", "2001:DB8:1:2:BBBB::9")).await;
+    }
+    assert_eq!(top_ips(&grouped).await, [("2001:db8:1:2::/64".to_string(), 2)]);
+    assert_eq!(
+        top_ips(&ungrouped).await,
+        [("2001:db8:1:2:aaaa::1".to_string(), 1), ("2001:db8:1:2:bbbb::9".to_string(), 1)]
+    );
+}
@@ -0,0 +1,75 @@
+//! `LOG_SAMPLE_RATE`: the line logged per analysis, kept for every flagged event and one
+//! in N clean ones. The lines are captured by a subscriber set for the test's thread,
+//! which the single-threaded test runtime keeps the handlers on.
+mod common;
+
+use common::Harness;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Captured {
+    /// The analysis lines logged so far.
+    fn analyses(&self) -> Vec<String> {
+        let logs = String::from_utf8(self.0.lock().unwrap().clone()).unwrap();
+        logs.lines().filter(|line| line.contains("Analysis complete")).map(str::to_string).collect()
+    }
+}
+
+fn capture() -> (Captured, tracing::subscriber::DefaultGuard) {
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let subscriber = tracing_subscriber::fmt().with_ansi(false).with_writer(move || writer.clone()).finish();
+    (captured, tracing::subscriber::set_default(subscriber))
+}
+
+fn from_ip(session_id: &str, ip: &str) -> Value {
+    let mut event = common::event(session_id, "PageLoad", 0);
+    event["ipAddress"] = json!(ip);
+    event
+}
+
+#[tokio::test]
+async fn flagged_analyses_always_log_and_clean_ones_one_in_n() {
+    let service = Harness::with(&[("LOG_SAMPLE_RATE", "3")], json!({ "entity_reputation": { "half_life_secs": 0 } })).await;
+    let sampled_out = common::metric("analysis_logs_sampled_out_total");
+    let (captured, _guard) = capture();
+
+    for n in 0..6 {
+        assert_eq!(service.score(&from_ip(&format!("clean-{}", n), "198.51.100.7")).await["flagged"], false);
+    }
+    assert_eq!(captured.analyses().len(), 2, "{:?}", captured.analyses());
+    for n in 0..4 {
+        assert_eq!(service.score(&from_ip(&format!("flagged-{}", n), "1.1.1.1")).await["flagged"], true);
+    }
+
+    let logged = captured.analyses();
+    assert_eq!(logged.len(), 6, "{:?}", logged);
+    assert_eq!(logged.iter().filter(|line| line.contains("Flagged = true")).count(), 4);
+    assert!(logged[0].contains("session [clean-0]") && logged[1].contains("session [clean-3]"), "{:?}", logged);
+    assert_eq!(common::metric("analysis_logs_sampled_out_total"), sampled_out + 4.0);
+}
+
+#[tokio::test]
+async fn every_analysis_logs_by_default() {
+    let service = Harness::new().await;
+    let (captured, _guard) = capture();
+    for n in 0..3 {
+        service.score(&from_ip(&format!("all-{}", n), "198.51.100.7")).await;
+    }
+    assert_eq!(captured.analyses().len(), 3);
+}
@@ -0,0 +1,67 @@
+//! Model scoring over gRPC (`MODEL_GRPC_ADDR`), against a stand-in model server.
+mod common;
+
+use common::{mocks, points, Harness};
+use serde_json::json;
+use std::sync::atomic::Ordering;
+
+async fn scoring_against(addr: &str) -> Harness {
+    Harness::with(&[("MODEL_GRPC_ADDR", addr), ("MODEL_TIMEOUT_MS", "2000")], json!({})).await
+}
+
+#[tokio::test]
+async fn the_models_probability_is_scaled_into_the_breakdown() {
+    let model = mocks::model_server(0.75).await;
+    let service = scoring_against(&model.addr).await;
+    let result = service.score(&common::event("model-1", "PageLoad", 0)).await;
+    // 0.75 of the default 40-point weight.
+    assert_eq!(points(&result, "MODEL_SCORE"), Some(30));
+    assert_eq!(result["fraudScore"], 30);
+    assert_eq!(result["degraded"], false);
+    assert_eq!(model.requests.load(Ordering::Relaxed), 1);
+}
+
+#[tokio::test]
+async fn an_unreachable_model_falls_back_to_rules_only() {
+    // A port nothing listens on any more.
+    let addr = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    };
+    let service = scoring_against(&addr).await;
+    let mut event = common::event("model-2", "PageLoad", 0);
+    event["ipAddress"] = json!("1.1.1.1");
+    let result = service.score(&event).await;
+    assert_eq!(points(&result, "MODEL_SCORE"), None);
+    assert_eq!(points(&result, "IP_BLACKLISTED"), Some(100));
+    assert_eq!(result["degraded"], true);
+    assert_eq!(result["skippedRules"], json!(["model"]));
+}
+
+#[tokio::test]
+async fn an_open_breaker_skips_inference_until_its_cooldown() {
+    let addr = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    };
+    let vars = [
+        ("MODEL_GRPC_ADDR", addr.as_str()),
+        ("MODEL_TIMEOUT_MS", "2000"),
+        ("MODEL_BREAKER_FAILURES", "1"),
+        ("MODEL_BREAKER_COOLDOWN_SECS", "60"),
+    ];
+    let service = Harness::with(&vars, json!({})).await;
+    let skipped = "model_inference_skipped_total{backend=\"grpc\",reason=\"circuit_open\"}";
+    let before = common::metric(skipped);
+
+    // The failed call opens the breaker; the next events don't try.
+    let first = service.score(&common::event("breaker-1", "PageLoad", 0)).await;
+    assert_eq!(first["skippedRules"], json!(["model"]));
+    assert_eq!(common::metric(skipped), before);
+    for n in 2..=3 {
+        let result = service.score(&common::event(&format!("breaker-{}", n), "PageLoad", 0)).await;
+        assert_eq!(result["degraded"], true);
+        assert_eq!(result["skippedRules"], json!(["model"]));
+    }
+    assert_eq!(common::metric(skipped), before + 2.0);
+}
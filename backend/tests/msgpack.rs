@@ -0,0 +1,113 @@
+//! MessagePack bodies and responses, negotiated by `Content-Type` and `Accept`.
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use common::Harness;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+const MSGPACK: &str = "application/msgpack";
+
+fn request(uri: &str, content_type: &str, accept: Option<&str>, body: Vec<u8>) -> Request<Body> {
+    let mut request = Request::post(uri).header(header::CONTENT_TYPE, content_type);
+    if let Some(accept) = accept {
+        request = request.header(header::ACCEPT, accept);
+    }
+    request.body(Body::from(body)).unwrap()
+}
+
+fn blacklisted(session_id: &str) -> Value {
+    let mut event = common::event(session_id, "PageLoad", 0);
+    event["ipAddress"] = json!("1.1.1.1");
+    event
+}
+
+/// An event as a MessagePack client encodes it, with the timestamp in epoch milliseconds.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Event<'a> {
+    session_id: &'a str,
+    event_type: &'a str,
+    timestamp: i64,
+    ip_address: &'a str,
+    metadata: BTreeMap<&'a str, &'a str>,
+}
+
+#[tokio::test]
+async fn a_msgpack_result_decodes_to_the_json_one() {
+    let json_result = Harness::new().await.score(&blacklisted("negotiated")).await;
+
+    let service = Harness::new().await;
+    let body = blacklisted("negotiated").to_string().into_bytes();
+    let response = service.send(request("/api/v1/events", "application/json", Some(MSGPACK), body)).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("content-type"), Some(MSGPACK));
+    let decoded: Value = rmp_serde::from_slice(&response.body).expect("MessagePack result");
+    assert_eq!(decoded, json_result);
+    // Field names and the decision enum survive as strings.
+    assert_eq!(decoded["decision"], "REVIEW");
+    assert_eq!(decoded["checkTimestamp"], "2024-05-01T12:00:00Z");
+}
+
+#[tokio::test]
+async fn a_msgpack_body_is_scored_like_json() {
+    let service = Harness::new().await;
+    let event = Event {
+        session_id: "binary",
+        event_type: "FormSubmission",
+        timestamp: common::start().timestamp_millis(),
+        ip_address: "1.1.1.1",
+        metadata: BTreeMap::from([("form", "signup")]),
+    };
+    let body = rmp_serde::to_vec_named(&event).unwrap();
+    for content_type in [MSGPACK, "application/x-msgpack"] {
+        let response = service.send(request("/api/v1/events", content_type, None, body.clone())).await;
+        assert_eq!(response.status, StatusCode::OK, "{}: {}", content_type, response.text());
+        let result = response.json();
+        assert_eq!(result["sessionId"], "binary");
+        assert_eq!(common::points(&result, "IP_BLACKLISTED"), Some(100));
+    }
+}
+
+#[tokio::test]
+async fn without_an_accept_header_results_stay_json() {
+    let service = Harness::new().await;
+    let body = blacklisted("default").to_string().into_bytes();
+    let response = service.send(request("/api/v2/events", "application/json", None, body)).await;
+    assert_eq!(response.header("content-type"), Some("application/json"));
+    assert_eq!(response.json()["score"], 100);
+
+    let body = blacklisted("v2-binary").to_string().into_bytes();
+    let response = service.send(request("/api/v2/events", "application/json", Some(MSGPACK), body)).await;
+    let decoded: Value = rmp_serde::from_slice(&response.body).unwrap();
+    assert_eq!(decoded["sessionId"], "v2-binary");
+}
+
+#[tokio::test]
+async fn undecodable_bodies_are_rejected_in_json() {
+    let service = Harness::new().await;
+    // A map header promising more entries than there are bytes.
+    let garbage = service.send(request("/api/v1/events", MSGPACK, Some(MSGPACK), vec![0x85])).await;
+    assert_eq!(garbage.status, StatusCode::BAD_REQUEST);
+    assert_eq!(garbage.header("content-type"), Some("application/json"));
+    assert_eq!(garbage.json()["error"], "bad_request");
+
+    // Well-formed MessagePack of the wrong shape.
+    let wrong_shape = rmp_serde::to_vec_named(&json!({ "sessionId": "s" })).unwrap();
+    let response = service.send(request("/api/v1/events", MSGPACK, Some(MSGPACK), wrong_shape)).await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response.json()["error"], "validation");
+}
+
+#[tokio::test]
+async fn simulations_negotiate_msgpack_too() {
+    let service = Harness::new().await;
+    let body = rmp_serde::to_vec_named(&json!({ "events": [blacklisted("sim")] })).unwrap();
+    let response = service.send(request("/api/v1/simulate", MSGPACK, Some(MSGPACK), body)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let report: Value = rmp_serde::from_slice(&response.body).unwrap();
+    assert_eq!(report["events"], 1);
+    assert_eq!(report["flagged"], 1);
+}
@@ -0,0 +1,75 @@
+//! `IP_PRIVACY_MODE`: stored IPs are hashed or truncated, and scoring doesn't change.
+mod common;
+
+use common::{codes, Harness};
+use serde_json::{json, Value};
+
+const MODES: [&[(&str, &str)]; 3] = [
+    &[("IP_PRIVACY_MODE", "off")],
+    &[("IP_PRIVACY_MODE", "hash"), ("IP_PRIVACY_SALT", "pepper")],
+    &[("IP_PRIVACY_MODE", "truncate")],
+];
+
+fn from(session_id: &str, ip: &str, offset_ms: i64) -> Value {
+    let mut event = common::event(session_id, "PageLoad", offset_ms);
+    event["ipAddress"] = json!(ip);
+    event
+}
+
+/// A blacklisted IP, then one IP over the per-IP limit across sessions, next to another
+/// IP that isn't. The IPs are in different /24s, so truncation keeps them apart.
+fn inputs() -> Vec<Value> {
+    let mut events = vec![from("priv-bl", "1.1.1.1", 0)];
+    for n in 0..4 {
+        events.push(from(&format!("priv-fast-{}", n), "203.0.113.9", 1_000 + n * 1_000));
+    }
+    for n in 0..3 {
+        events.push(from(&format!("priv-slow-{}", n), "198.51.100.23", 6_000 + n * 1_000));
+    }
+    events
+}
+
+/// What each event scored: its codes, score, and whether it was flagged.
+async fn outcomes(vars: &[(&str, &str)]) -> Vec<(Vec<String>, Value, Value)> {
+    let service = Harness::with(vars, json!({ "min_events_for_stateful": 1, "high_frequency": { "window_secs": 60, "max_events_per_ip": 3 } })).await;
+    let mut outcomes = Vec::new();
+    for event in inputs() {
+        let result = service.score(&event).await;
+        outcomes.push((codes(&result), result["fraudScore"].clone(), result["flagged"].clone()));
+    }
+    outcomes
+}
+
+#[tokio::test]
+async fn rules_score_the_same_in_every_privacy_mode() {
+    let plain = outcomes(MODES[0]).await;
+    assert_eq!(plain[0].0, ["IP_BLACKLISTED"]);
+    assert!(plain.last().unwrap().0.is_empty());
+    assert_eq!(plain[4].0, ["HIGH_FREQUENCY"], "the fourth event from 203.0.113.9");
+    for vars in &MODES[1..] {
+        assert_eq!(outcomes(vars).await, plain, "{:?}", vars);
+    }
+}
+
+async fn stored_ips(vars: &[(&str, &str)]) -> Vec<String> {
+    let service = Harness::with(vars, json!({})).await;
+    let result = service.score(&from("priv-store", "1.1.1.1", 0)).await;
+    assert!(!result.to_string().contains("1.1.1.1"), "{}", result);
+    service
+        .get("/api/v1/export?since=2000-01-01T00:00:00Z&session=priv-store")
+        .await
+        .text()
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap()["ipAddress"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn stored_events_keep_only_the_transformed_ip() {
+    let hashed = stored_ips(MODES[1]).await;
+    assert_eq!(hashed.len(), 1);
+    assert_eq!(hashed[0].len(), 64);
+    assert!(hashed[0].chars().all(|c| c.is_ascii_hexdigit()), "{}", hashed[0]);
+    assert_eq!(stored_ips(MODES[2]).await, ["1.1.1.0/24"]);
+    assert_eq!(stored_ips(MODES[0]).await, ["1.1.1.1"]);
+}
@@ -0,0 +1,199 @@
+//! `POST /api/v1/events` in protobuf: the same events score the same as in JSON.
+mod common;
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use common::Harness;
+use prost::Message;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// The messages of `proto/fraud_events.proto`, as a client would generate them.
+#[derive(Clone, PartialEq, prost::Message)]
+struct UserEvent {
+    #[prost(string, tag = "1")]
+    session_id: String,
+    #[prost(string, optional, tag = "2")]
+    user_id: Option<String>,
+    #[prost(int32, tag = "3")]
+    event_type: i32,
+    #[prost(string, tag = "4")]
+    timestamp: String,
+    #[prost(string, tag = "5")]
+    ip_address: String,
+    #[prost(string, optional, tag = "6")]
+    user_agent: Option<String>,
+    #[prost(map = "string, string", tag = "7")]
+    metadata: HashMap<String, String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct RuleHit {
+    #[prost(string, tag = "1")]
+    code: String,
+    #[prost(int32, tag = "2")]
+    points: i32,
+    #[prost(string, tag = "3")]
+    reason: String,
+    #[prost(message, repeated, tag = "4")]
+    children: Vec<RuleHit>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct FraudCheckResult {
+    #[prost(string, tag = "1")]
+    session_id: String,
+    #[prost(int32, tag = "2")]
+    fraud_score: i32,
+    #[prost(uint32, tag = "3")]
+    confidence: u32,
+    #[prost(bool, tag = "4")]
+    flagged: bool,
+    #[prost(string, repeated, tag = "5")]
+    reasons: Vec<String>,
+    #[prost(message, repeated, tag = "6")]
+    breakdown: Vec<RuleHit>,
+    #[prost(int32, optional, tag = "7")]
+    session_reputation: Option<i32>,
+    #[prost(string, tag = "8")]
+    check_timestamp: String,
+    #[prost(uint32, optional, tag = "9")]
+    asn: Option<u32>,
+    #[prost(string, tag = "10")]
+    decision: String,
+    #[prost(bool, tag = "11")]
+    degraded: bool,
+    #[prost(string, repeated, tag = "12")]
+    skipped_rules: Vec<String>,
+    #[prost(int32, tag = "13")]
+    raw_score: i32,
+}
+
+fn event_type(name: &str) -> i32 {
+    ["Unspecified", "PageLoad", "Click", "FormSubmission", "Payment"]
+        .iter()
+        .position(|candidate| *candidate == name)
+        .expect("event type") as i32
+}
+
+/// The protobuf encoding of a JSON event.
+fn encode(event: &Value) -> UserEvent {
+    let metadata = event["metadata"]
+        .as_object()
+        .map(|metadata| metadata.iter().map(|(key, value)| (key.clone(), value.as_str().unwrap().to_string())).collect())
+        .unwrap_or_default();
+    UserEvent {
+        session_id: event["sessionId"].as_str().unwrap().to_string(),
+        user_id: event["userId"].as_str().map(String::from),
+        event_type: event_type(event["eventType"].as_str().unwrap()),
+        timestamp: event["timestamp"].as_str().unwrap().to_string(),
+        ip_address: event["ipAddress"].as_str().unwrap().to_string(),
+        user_agent: event["userAgent"].as_str().map(String::from),
+        metadata,
+    }
+}
+
+fn protobuf_request(event: &UserEvent, accept: &str) -> Request<Body> {
+    Request::post("/api/v1/events")
+        .header("content-type", "application/x-protobuf")
+        .header("accept", accept)
+        .body(Body::from(event.encode_to_vec()))
+        .unwrap()
+}
+
+/// A JSON result in the shape of the protobuf one, minus the time it was scored at.
+fn as_proto(result: &Value) -> FraudCheckResult {
+    fn hit(value: &Value) -> RuleHit {
+        RuleHit {
+            code: value["code"].as_str().unwrap().to_string(),
+            points: value["points"].as_i64().unwrap() as i32,
+            reason: value["reason"].as_str().unwrap().to_string(),
+            children: value["children"].as_array().map(|children| children.iter().map(hit).collect()).unwrap_or_default(),
+        }
+    }
+    let strings = |field: &str| -> Vec<String> {
+        result[field].as_array().map(|values| values.iter().map(|value| value.as_str().unwrap().to_string()).collect()).unwrap_or_default()
+    };
+    FraudCheckResult {
+        session_id: result["sessionId"].as_str().unwrap().to_string(),
+        fraud_score: result["fraudScore"].as_i64().unwrap() as i32,
+        confidence: result["confidence"].as_u64().unwrap() as u32,
+        flagged: result["flagged"].as_bool().unwrap(),
+        reasons: strings("reasons"),
+        breakdown: result["breakdown"].as_array().unwrap().iter().map(hit).collect(),
+        session_reputation: result["sessionReputation"].as_i64().map(|reputation| reputation as i32),
+        check_timestamp: String::new(),
+        asn: result["asn"].as_u64().map(|asn| asn as u32),
+        decision: result["decision"].as_str().unwrap().to_string(),
+        degraded: result["degraded"].as_bool().unwrap(),
+        skipped_rules: strings("skippedRules"),
+        raw_score: result["rawScore"].as_i64().unwrap() as i32,
+    }
+}
+
+/// A session that trips several rules: a blacklisted IP, a bot user agent, metadata,
+/// and events too close together.
+fn session() -> Vec<Value> {
+    (0..5)
+        .map(|n| {
+            let mut event = common::event("proto-session", if n % 2 == 0 { "Click" } else { "FormSubmission" }, n * 50);
+            event["userId"] = json!("u-1");
+            event["userAgent"] = json!("curl/8.0");
+            event["metadata"] = json!({ "fieldTimings": "{\"a\": 1, \"b\": 2, \"c\": 3}" });
+            if n == 4 {
+                event["ipAddress"] = json!("1.1.1.1");
+            }
+            event
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn protobuf_and_json_events_get_identical_results() {
+    let json_service = Harness::new().await;
+    let protobuf_service = Harness::new().await;
+    for event in session() {
+        let expected = as_proto(&json_service.score(&event).await);
+
+        protobuf_service.clock.set(event["timestamp"].as_str().unwrap().parse().unwrap());
+        let response = protobuf_service.send(protobuf_request(&encode(&event), "application/x-protobuf")).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+        assert_eq!(response.header("content-type"), Some("application/x-protobuf"));
+        let mut result = FraudCheckResult::decode(response.body.as_slice()).unwrap();
+        assert!(!result.check_timestamp.is_empty());
+        result.check_timestamp.clear();
+        assert_eq!(result, expected, "{}", event);
+    }
+}
+
+#[tokio::test]
+async fn protobuf_events_get_json_results_unless_they_accept_protobuf() {
+    let service = Harness::new().await;
+    let event = common::event("proto-json", "PageLoad", 0);
+    let response = service.send(protobuf_request(&encode(&event), "application/json")).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json()["sessionId"], "proto-json");
+}
+
+#[tokio::test]
+async fn protobuf_events_are_validated_like_json_ones() {
+    let service = Harness::new().await;
+    let mut unspecified = encode(&common::event("proto-bad", "PageLoad", 0));
+    unspecified.event_type = 0;
+    let response = service.send(protobuf_request(&unspecified, "application/x-protobuf")).await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response.json()["message"], "invalid event type `0`");
+
+    let mut bad_ip = encode(&common::event("proto-bad", "PageLoad", 0));
+    bad_ip.ip_address = "not-an-ip".to_string();
+    let response = service.send(protobuf_request(&bad_ip, "application/x-protobuf")).await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let garbage = Request::post("/api/v1/events")
+        .header("content-type", "application/x-protobuf")
+        .body(Body::from(vec![0xff, 0xff, 0xff]))
+        .unwrap();
+    let response = service.send(garbage).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.json()["error"], "bad_request");
+}
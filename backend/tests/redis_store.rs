@@ -0,0 +1,126 @@
+//! The Redis event store, shared by two instances of the service. Needs a server to talk
+//! to, so these are ignored by default: run them with `--features redis -- --ignored` and
+//! `TEST_REDIS_URL` (e.g. `redis://127.0.0.1:6379/15`) set. Without it they fail rather
+//! than pass unchecked. The sessions they write expire with the store's TTL.
+#![cfg(feature = "redis")]
+
+mod common;
+
+use common::{codes, Harness};
+use serde_json::{json, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn redis_url() -> String {
+    std::env::var("TEST_REDIS_URL").expect("TEST_REDIS_URL must be set to run the Redis store tests")
+}
+
+/// A session id no earlier run used, since the server outlives the test.
+fn session_id(name: &str) -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+    format!("{}-{}-{}", name, std::process::id(), nanos)
+}
+
+/// Two instances on one store, each limited to 3 events per `window_secs`.
+async fn replicas(url: &str, window_secs: u64) -> (Harness, Harness) {
+    let vars = [("REDIS_URL", url), ("REDIS_SESSION_TTL_SECS", "300")];
+    let scoring = json!({ "high_frequency": { "max_events": 3, "window_secs": window_secs } });
+    (
+        Harness::with(&vars, scoring.clone()).await,
+        Harness::with(&vars, scoring).await,
+    )
+}
+
+fn click(session_id: &str, offset_ms: i64) -> Value {
+    common::event(session_id, "Click", offset_ms)
+}
+
+#[tokio::test]
+#[ignore = "needs a Redis server at TEST_REDIS_URL"]
+async fn events_pushed_by_one_instance_are_read_by_another() {
+    let url = redis_url();
+    let (a, b) = replicas(&url, 0).await;
+    let session = session_id("redis-push");
+    a.score(&click(&session, 0)).await;
+    a.score(&click(&session, 2_000)).await;
+    b.score(&click(&session, 4_000)).await;
+
+    // Either instance alone has seen too few events to be over the limit.
+    let fourth = b.score(&click(&session, 6_000)).await;
+    assert!(codes(&fourth).contains(&"HIGH_FREQUENCY".to_string()), "{}", fourth);
+
+    let export = a
+        .get(&format!("/api/v1/export?since=2000-01-01T00:00:00Z&session={}", session))
+        .await;
+    let timestamps: Vec<String> = export
+        .text()
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap()["timestamp"].as_str().unwrap().to_string())
+        .collect();
+    let expected: Vec<String> = (0..4)
+        .map(|n| click(&session, n * 2_000)["timestamp"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(timestamps, expected);
+}
+
+#[tokio::test]
+#[ignore = "needs a Redis server at TEST_REDIS_URL"]
+async fn only_events_within_the_window_are_counted() {
+    let url = redis_url();
+    let (a, b) = replicas(&url, 60).await;
+
+    let stale = session_id("redis-stale");
+    for n in 0..3 {
+        a.score(&click(&stale, n * 1_000)).await;
+    }
+    let later = b.score(&click(&stale, 120_000)).await;
+    assert!(!codes(&later).contains(&"HIGH_FREQUENCY".to_string()), "{}", later);
+
+    let recent = session_id("redis-recent");
+    for n in 0..3 {
+        a.score(&click(&recent, n * 1_000)).await;
+    }
+    let next = b.score(&click(&recent, 3_000)).await;
+    assert!(codes(&next).contains(&"HIGH_FREQUENCY".to_string()), "{}", next);
+}
+
+#[tokio::test]
+#[ignore = "needs a Redis server at TEST_REDIS_URL"]
+async fn a_session_alternating_between_instances_is_counted_whole() {
+    let url = redis_url();
+    let (a, b) = replicas(&url, 60).await;
+    let session = session_id("redis-alternating");
+    for (n, instance) in [&a, &b, &a].into_iter().enumerate() {
+        let result = instance.score(&click(&session, n as i64 * 1_000)).await;
+        assert!(!codes(&result).contains(&"HIGH_FREQUENCY".to_string()), "{}", result);
+    }
+
+    // Each instance scored two of the four clicks; only both together are over the limit.
+    let fourth = b.score(&click(&session, 3_000)).await;
+    assert!(codes(&fourth).contains(&"HIGH_FREQUENCY".to_string()), "{}", fourth);
+    let fifth = a.score(&click(&session, 4_000)).await;
+    assert!(codes(&fifth).contains(&"HIGH_FREQUENCY".to_string()), "{}", fifth);
+}
+
+#[tokio::test]
+#[ignore = "needs a Redis server at TEST_REDIS_URL"]
+async fn an_ongoing_session_across_instances_is_counted_as_the_window_slides() {
+    let url = redis_url();
+    let (a, b) = replicas(&url, 10).await;
+    let session = session_id("redis-ongoing");
+    let instances = [&a, &b];
+    let high_frequency = |result: &Value| codes(result).contains(&"HIGH_FREQUENCY".to_string());
+
+    // One click every 4s: never more than three in any 10s, whichever instance scores it.
+    for n in 0..8 {
+        let result = instances[n % 2].score(&click(&session, n as i64 * 4_000)).await;
+        assert!(!high_frequency(&result), "click {}: {}", n + 1, result);
+    }
+    // A burst, still alternating: over the limit from the second click on.
+    for n in 0..4 {
+        let result = instances[n % 2].score(&click(&session, 30_000 + n as i64 * 1_000)).await;
+        assert_eq!(high_frequency(&result), n > 0, "burst click {}: {}", n + 1, result);
+    }
+    // Once the burst has left the window, the pace is fine again.
+    let later = b.score(&click(&session, 45_000)).await;
+    assert!(!high_frequency(&later), "{}", later);
+}
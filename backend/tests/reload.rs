@@ -0,0 +1,105 @@
+//! Scoring config reloads racing analyses: each event is scored, and audited, under one
+//! config or the other, never a mix. `SCORING_CONFIG_PATH` is process-wide, so this
+//! binary is the only one reloading through it.
+mod common;
+
+use axum::http::Method;
+use common::Harness;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+/// Two configs whose threshold and blacklist points only agree with each other: under
+/// `a` a blacklisted IP scores 60 and isn't flagged, under `b` it scores 40 and is.
+fn config(version: &str) -> Value {
+    let (points, threshold) = if version == "a" { (60, 70) } else { (40, 30) };
+    json!({
+        "flag_threshold": threshold,
+        "blacklist": { "points": points },
+        "entity_reputation": { "half_life_secs": 0 },
+        "session_reputation": { "half_life_secs": 0 },
+    })
+}
+
+fn consistent(score: &Value, flagged: &Value) -> Option<&'static str> {
+    match (score.as_i64(), flagged.as_bool()) {
+        (Some(60), Some(false)) => Some("a"),
+        (Some(40), Some(true)) => Some("b"),
+        _ => None,
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_reloads_never_tear_a_requests_config() {
+    let path = common::scratch_path("scoring.json");
+    std::fs::write(&path, config("a").to_string()).unwrap();
+    std::env::set_var("SCORING_CONFIG_PATH", &path);
+    let audit = common::scratch_path("audit.log");
+    let service = Harness::with(&[("AUDIT_LOG_PATH", audit.to_str().unwrap())], json!({})).await;
+
+    // A loaded config's version is a hash of its file; reloading each in turn names them.
+    let mut hashes = HashMap::new();
+    for version in ["b", "a"] {
+        std::fs::write(&path, config(version).to_string()).unwrap();
+        let reload = service.post("/api/v1/config/reload", &json!({})).await.json();
+        hashes.insert(reload["configVersion"].as_str().unwrap().to_string(), version);
+    }
+    assert_eq!(hashes.len(), 2);
+
+    let reloads = tokio::spawn({
+        let router = service.router();
+        let path = path.clone();
+        async move {
+            for n in 0..40 {
+                // Written aside and renamed, so a reload never reads half a file.
+                let staged = path.with_extension("staged");
+                std::fs::write(&staged, config(if n % 2 == 0 { "b" } else { "a" }).to_string()).unwrap();
+                std::fs::rename(&staged, &path).unwrap();
+                let reload = common::send_to(router.clone(), common::json_request(Method::POST, "/api/v1/config/reload", &json!({}))).await;
+                assert!(reload.status.is_success(), "{}", reload.text());
+                tokio::task::yield_now().await;
+            }
+        }
+    });
+    let analyses: Vec<_> = (0..4)
+        .map(|worker| {
+            let router = service.router();
+            tokio::spawn(async move {
+                let mut seen = Vec::new();
+                for n in 0..50 {
+                    let mut event = common::event(&format!("torn-{}-{}", worker, n), "PageLoad", 0);
+                    event["ipAddress"] = json!("1.1.1.1");
+                    let response = common::send_to(router.clone(), common::json_request(Method::POST, "/api/v1/events", &event)).await;
+                    let result = response.json();
+                    let version = consistent(&result["fraudScore"], &result["flagged"]);
+                    assert!(version.is_some(), "torn result: {}", result);
+                    seen.push(version.unwrap());
+                }
+                seen
+            })
+        })
+        .collect();
+    reloads.await.unwrap();
+    let mut versions = HashSet::new();
+    for analysis in analyses {
+        versions.extend(analysis.await.unwrap());
+    }
+    std::env::remove_var("SCORING_CONFIG_PATH");
+    assert_eq!(versions.len(), 2, "both configs served traffic");
+
+    // The audit record names the config each event was scored under.
+    let mut records: Vec<Value> = Vec::new();
+    for _ in 0..200 {
+        let log = std::fs::read_to_string(&audit).unwrap_or_default();
+        records = log.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        if records.len() == 200 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(records.len(), 200);
+    for record in &records {
+        let version = hashes.get(record["configVersion"].as_str().unwrap()).copied();
+        assert_eq!(consistent(&record["fraudScore"], &record["flagged"]), version, "{}", record);
+    }
+}
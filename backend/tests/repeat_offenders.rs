@@ -0,0 +1,45 @@
+//! The `repeat_offender` rule across a user's flagged events.
+mod common;
+
+use common::{points, Harness};
+use serde_json::json;
+
+/// An event from a blacklisted IP, which is flagged on its own.
+fn blacklisted(session_id: &str, user_id: Option<&str>) -> serde_json::Value {
+    let mut event = common::event(session_id, "PageLoad", 0);
+    event["ipAddress"] = json!("1.1.1.1");
+    if let Some(user_id) = user_id {
+        event["userId"] = json!(user_id);
+    }
+    event
+}
+
+/// Entity reputation also carries earlier flags over; it's off so only the bonus moves.
+async fn service() -> Harness {
+    Harness::with(&[], json!({ "entity_reputation": { "half_life_secs": 0 } })).await
+}
+
+#[tokio::test]
+async fn a_users_second_flag_includes_the_escalation_bonus() {
+    let service = service().await;
+    let first = service.score(&blacklisted("ro-1", Some("mallory"))).await;
+    assert_eq!(first["flagged"], true);
+    assert_eq!(points(&first, "REPEAT_OFFENDER"), None);
+
+    let second = service.score(&blacklisted("ro-2", Some("mallory"))).await;
+    assert_eq!(second["flagged"], true);
+    assert_eq!(points(&second, "REPEAT_OFFENDER"), Some(10));
+    assert_eq!(second["rawScore"].as_i64(), Some(first["rawScore"].as_i64().unwrap() + 10));
+
+    let third = service.score(&blacklisted("ro-3", Some("mallory"))).await;
+    assert_eq!(points(&third, "REPEAT_OFFENDER"), Some(20));
+}
+
+#[tokio::test]
+async fn anonymous_events_never_escalate() {
+    let service = service().await;
+    for session_id in ["anon-1", "anon-2", "anon-3"] {
+        let result = service.score(&blacklisted(session_id, None)).await;
+        assert_eq!(points(&result, "REPEAT_OFFENDER"), None, "{}", result);
+    }
+}
@@ -0,0 +1,50 @@
+//! `X-Request-Id`: echoed when the client sends one, generated when it doesn't.
+mod common;
+
+use axum::http::StatusCode;
+use common::{json_request, Harness};
+use uuid::Uuid;
+
+fn with_request_id(id: &str) -> axum::http::Request<axum::body::Body> {
+    let mut request = json_request(axum::http::Method::POST, "/api/v1/events", &common::event("rid-1", "PageLoad", 0));
+    request.headers_mut().insert("x-request-id", id.parse().unwrap());
+    request
+}
+
+#[tokio::test]
+async fn echoes_the_client_request_id() {
+    let service = Harness::new().await;
+    let response = service.send(with_request_id("ticket-4521")).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("x-request-id"), Some("ticket-4521"));
+}
+
+#[tokio::test]
+async fn generates_a_uuid_when_the_client_sends_none() {
+    let service = Harness::new().await;
+    let first = service.get("/api/v1/stats").await;
+    let second = service.get("/api/v1/stats").await;
+    let first = Uuid::parse_str(first.header("x-request-id").unwrap()).unwrap();
+    let second = Uuid::parse_str(second.header("x-request-id").unwrap()).unwrap();
+    assert_eq!(first.get_version_num(), 4);
+    assert_ne!(first, second);
+}
+
+#[tokio::test]
+async fn error_responses_carry_the_id_too() {
+    let service = Harness::new().await;
+    let mut request = with_request_id("ticket-4522");
+    *request.body_mut() = axum::body::Body::from("{not json");
+    let response = service.send(request).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.header("x-request-id"), Some("ticket-4522"));
+}
+
+#[tokio::test]
+async fn an_unusable_id_is_replaced() {
+    let service = Harness::new().await;
+    let long = "x".repeat(200);
+    let response = service.send(with_request_id(&long)).await;
+    let echoed = response.header("x-request-id").unwrap();
+    assert!(Uuid::parse_str(echoed).is_ok(), "{}", echoed);
+}
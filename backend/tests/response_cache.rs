@@ -0,0 +1,74 @@
+//! Explain and simulate responses reused for identical requests (`RESPONSE_CACHE_SIZE`).
+mod common;
+
+use axum::http::StatusCode;
+use common::Harness;
+use serde_json::{json, Value};
+
+fn explain_body(ip: &str) -> Value {
+    let mut event = common::event("cached", "PageLoad", 0);
+    event["ipAddress"] = json!(ip);
+    json!({ "event": event })
+}
+
+#[tokio::test]
+async fn a_repeated_explain_request_is_served_from_the_cache() {
+    let service = Harness::new().await;
+    let hits = common::metric("response_cache_hits_total{endpoint=\"explain\"}");
+
+    let first = service.post("/api/v1/events/explain", &explain_body("1.1.1.1")).await;
+    assert_eq!(first.status, StatusCode::OK);
+    assert_eq!(first.header("x-cache"), Some("miss"));
+    let second = service.post("/api/v1/events/explain", &explain_body("1.1.1.1")).await;
+    assert_eq!(second.header("x-cache"), Some("hit"));
+    assert_eq!(second.header("content-type"), first.header("content-type"));
+    assert_eq!(second.body, first.body);
+    assert_eq!(common::metric("response_cache_hits_total{endpoint=\"explain\"}"), hits + 1.0);
+
+    // Any change to the body is another request.
+    let other = service.post("/api/v1/events/explain", &explain_body("2.2.2.2")).await;
+    assert_eq!(other.header("x-cache"), Some("miss"));
+}
+
+#[tokio::test]
+async fn simulations_are_cached_too() {
+    let service = Harness::new().await;
+    let body = json!({ "events": [common::event("sim", "PageLoad", 0)] });
+    assert_eq!(service.post("/api/v1/simulate", &body).await.header("x-cache"), Some("miss"));
+    assert_eq!(service.post("/api/v1/simulate", &body).await.header("x-cache"), Some("hit"));
+}
+
+#[tokio::test]
+async fn a_blacklist_change_invalidates_cached_answers() {
+    let service = Harness::new().await;
+    let body = explain_body("203.0.113.50");
+    let before = service.post("/api/v1/events/explain", &body).await;
+    assert_eq!(before.json()["fraudScore"], 0);
+
+    let added = service.post("/api/v1/blacklist", &json!({ "ips": ["203.0.113.50"] })).await;
+    assert_eq!(added.status, StatusCode::OK, "{}", added.text());
+    let after = service.post("/api/v1/events/explain", &body).await;
+    assert_eq!(after.header("x-cache"), Some("miss"));
+    assert_eq!(after.json()["fraudScore"], 100);
+}
+
+#[tokio::test]
+async fn errors_are_not_cached() {
+    let service = Harness::new().await;
+    let no_event = json!({ "events": [] });
+    for _ in 0..2 {
+        let response = service.post("/api/v1/events/explain", &no_event).await;
+        assert!(response.status.is_client_error(), "{}", response.status);
+        assert_eq!(response.header("x-cache"), Some("miss"));
+    }
+}
+
+#[tokio::test]
+async fn a_zero_size_turns_the_cache_off() {
+    let service = Harness::with(&[("RESPONSE_CACHE_SIZE", "0")], json!({})).await;
+    for _ in 0..2 {
+        let response = service.post("/api/v1/events/explain", &explain_body("1.1.1.1")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.header("x-cache"), None);
+    }
+}
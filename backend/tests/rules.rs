@@ -0,0 +1,104 @@
+//! `GET /api/v1/rules`: every registered rule with its weights under the loaded config.
+mod common;
+
+use common::Harness;
+use serde_json::{json, Value};
+
+/// The native rules, in evaluation order.
+const BUILT_IN: &[&str] = &[
+    "blacklist",
+    "suspicious_sources",
+    "fast_interaction",
+    "high_frequency",
+    "metadata_keys",
+    "ip_reputation",
+    "asn_reputation",
+    "ip_mismatch",
+    "user_agent",
+    "shared_session",
+    "repeat_offender",
+    "entity_reputation",
+    "concurrent_sessions",
+    "device_fingerprint",
+    "cadence",
+    "click_timing",
+    "type_toggling",
+    "payments",
+    "disposable_email",
+    "referrer_origin",
+    "locale_mismatch",
+    "field_timing",
+    "session_duration",
+    "activity_profile",
+    "model",
+];
+
+async fn rules(service: &Harness) -> Value {
+    let response = service.get("/api/v1/rules").await;
+    assert!(response.status.is_success(), "{}", response.text());
+    response.json()
+}
+
+fn rule<'a>(listing: &'a Value, name: &str) -> &'a Value {
+    listing["rules"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|rule| rule["name"] == name)
+        .unwrap_or_else(|| panic!("no rule `{}` in {}", name, listing))
+}
+
+fn points(rule: &Value, code: &str) -> Option<i64> {
+    rule["weights"].as_array()?.iter().find(|weight| weight["code"] == code)?["points"].as_i64()
+}
+
+#[tokio::test]
+async fn lists_every_built_in_rule_in_order() {
+    let listing = rules(&Harness::new().await).await;
+    let names: Vec<&str> = listing["rules"].as_array().unwrap().iter().map(|rule| rule["name"].as_str().unwrap()).collect();
+    assert_eq!(names, BUILT_IN);
+    assert_eq!(listing["configVersion"], "default");
+    assert_eq!(listing["flagThreshold"], 50);
+    for rule in listing["rules"].as_array().unwrap() {
+        assert!(!rule["description"].as_str().unwrap().is_empty(), "{}", rule);
+        assert!(!rule["weights"].as_array().unwrap().is_empty(), "{}", rule);
+    }
+}
+
+#[tokio::test]
+async fn weights_follow_the_scoring_config() {
+    let defaults = rules(&Harness::new().await).await;
+    assert_eq!(points(rule(&defaults, "blacklist"), "IP_BLACKLISTED"), Some(100));
+    assert_eq!(points(rule(&defaults, "disposable_email"), "DISPOSABLE_EMAIL"), Some(40));
+    assert_eq!(points(rule(&defaults, "model"), "MODEL_SCORE"), Some(40));
+
+    let configured = rules(&Harness::with(&[], json!({ "blacklist": { "points": 70 }, "flag_threshold": 60 })).await).await;
+    assert_eq!(points(rule(&configured, "blacklist"), "IP_BLACKLISTED"), Some(70));
+    assert_eq!(configured["flagThreshold"], 60);
+}
+
+#[tokio::test]
+async fn rules_without_points_or_dependencies_are_disabled() {
+    let listing = rules(&Harness::with(&[], json!({ "blacklist": { "points": 0 } })).await).await;
+    assert_eq!(rule(&listing, "blacklist")["enabled"], false);
+    assert_eq!(rule(&listing, "fast_interaction")["enabled"], true);
+    // No IP_REPUTATION_URL and no model.
+    assert_eq!(rule(&listing, "ip_reputation")["enabled"], false);
+    assert_eq!(rule(&listing, "model")["enabled"], false);
+    assert_eq!(rule(&listing, "high_frequency")["stateful"], true);
+    assert_eq!(rule(&listing, "blacklist")["stateful"], false);
+}
+
+#[tokio::test]
+async fn scripted_and_composite_rules_follow_the_built_in_ones() {
+    let scoring = json!({
+        "scripted_rules": [{ "name": "loan_outside_us", "weight": 45, "script": "event.metadata.formId == \"loan\"" }],
+        "composite_rules": [{ "name": "bot_on_blacklist", "points": 25, "all_of": ["blacklist", "user_agent"] }],
+    });
+    let listing = rules(&Harness::with(&[], scoring).await).await;
+    let names: Vec<&str> = listing["rules"].as_array().unwrap().iter().map(|rule| rule["name"].as_str().unwrap()).collect();
+    assert_eq!(&names[..BUILT_IN.len()], BUILT_IN);
+    assert_eq!(&names[BUILT_IN.len()..], ["loan_outside_us", "bot_on_blacklist"]);
+    assert_eq!(rule(&listing, "bot_on_blacklist")["weights"][0]["points"], 25);
+    assert_eq!(rule(&listing, "loan_outside_us")["enabled"], true);
+}
@@ -0,0 +1,105 @@
+//! `GET /api/v1/search`: finding sessions by IP, user and reason code.
+mod common;
+
+use axum::http::StatusCode;
+use common::Harness;
+use serde_json::{json, Value};
+
+/// Three sessions: alice and bob each flagged from a blacklisted IP, and a clean
+/// session of alice's from another IP, scored in that order.
+async fn with_sessions() -> Harness {
+    let service = Harness::new().await;
+    for (offset_ms, session_id, user_id, ip) in [
+        (0, "search-a", "alice", "1.1.1.1"),
+        (1_000, "search-b", "bob", "1.1.1.1"),
+        (2_000, "search-c", "alice", "198.51.100.23"),
+    ] {
+        let mut event = common::event(session_id, "PageLoad", offset_ms);
+        event["userId"] = json!(user_id);
+        event["ipAddress"] = json!(ip);
+        service.score(&event).await;
+    }
+    service
+}
+
+async fn search(service: &Harness, query: &str) -> Value {
+    let response = service.get(&format!("/api/v1/search?{}", query)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}: {}", query, response.text());
+    response.json()
+}
+
+fn session_ids(page: &Value) -> Vec<&str> {
+    page["sessions"].as_array().unwrap().iter().map(|session| session["sessionId"].as_str().unwrap()).collect()
+}
+
+#[tokio::test]
+async fn finds_sessions_by_ip_most_recent_first() {
+    let service = with_sessions().await;
+    let page = search(&service, "ip=1.1.1.1").await;
+    assert_eq!(session_ids(&page), ["search-b", "search-a"]);
+    assert_eq!(page["nextCursor"], Value::Null);
+    let session = &page["sessions"][1];
+    assert_eq!(session["userIds"], json!(["alice"]));
+    assert_eq!(session["flagged"], true);
+    assert_eq!(session["latestScore"], 100);
+    assert_eq!(session["firstEvent"], json!(common::at(0)));
+}
+
+#[tokio::test]
+async fn finds_sessions_by_user_flagged_only_unless_asked() {
+    let service = with_sessions().await;
+    assert_eq!(session_ids(&search(&service, "user_id=alice").await), ["search-a"]);
+    assert_eq!(session_ids(&search(&service, "user_id=alice&flagged_only=false").await), ["search-c", "search-a"]);
+    assert!(session_ids(&search(&service, "user_id=mallory&flagged_only=false").await).is_empty());
+}
+
+#[tokio::test]
+async fn finds_sessions_by_reason_code() {
+    let service = with_sessions().await;
+    assert_eq!(session_ids(&search(&service, "reason=IP_BLACKLISTED").await), ["search-b", "search-a"]);
+    assert!(session_ids(&search(&service, "reason=HIGH_FREQUENCY").await).is_empty());
+}
+
+#[tokio::test]
+async fn combined_filters_intersect() {
+    let service = with_sessions().await;
+    assert_eq!(session_ids(&search(&service, "ip=1.1.1.1&user_id=alice").await), ["search-a"]);
+    assert_eq!(session_ids(&search(&service, "reason=IP_BLACKLISTED&user_id=bob").await), ["search-b"]);
+    assert!(session_ids(&search(&service, "ip=198.51.100.23&user_id=bob&flagged_only=false").await).is_empty());
+    let since = common::at(500).to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    assert_eq!(session_ids(&search(&service, &format!("ip=1.1.1.1&since={}", since)).await), ["search-b"]);
+}
+
+#[tokio::test]
+async fn pages_through_matches_without_repeats() {
+    let service = with_sessions().await;
+    let mut seen = Vec::new();
+    let mut query = "flagged_only=false&limit=1".to_string();
+    loop {
+        let page = search(&service, &query).await;
+        seen.extend(session_ids(&page).into_iter().map(String::from));
+        match page["nextCursor"].as_str() {
+            Some(cursor) => query = format!("flagged_only=false&limit=1&cursor={}", cursor),
+            None => break,
+        }
+    }
+    assert_eq!(seen, ["search-c", "search-b", "search-a"]);
+}
+
+#[tokio::test]
+async fn rejects_an_invalid_ip_or_cursor() {
+    let service = with_sessions().await;
+    let response = service.get("/api/v1/search?ip=not-an-ip").await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    let response = service.get("/api/v1/search?cursor=garbage").await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn deleting_a_users_data_drops_their_sessions() {
+    let service = with_sessions().await;
+    let response = service.delete("/api/v1/users/alice/data", None).await;
+    assert!(response.status.is_success(), "{}", response.text());
+    assert!(session_ids(&search(&service, "user_id=alice&flagged_only=false").await).is_empty());
+    assert_eq!(session_ids(&search(&service, "ip=1.1.1.1").await), ["search-b"]);
+}
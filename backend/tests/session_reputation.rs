@@ -0,0 +1,55 @@
+//! Session reputation: small scores adding up across a session until it is flagged.
+mod common;
+
+use common::Harness;
+use serde_json::{json, Value};
+
+/// An event from a blacklisted IP, which these tests weigh at 20 points: under the flag
+/// threshold on its own. Spaced 10s apart so no timing rule adds to it.
+fn small_signal(session_id: &str, n: i64) -> Value {
+    let mut event = common::event(session_id, "PageLoad", n * 10_000);
+    event["ipAddress"] = json!("1.1.1.1");
+    event
+}
+
+fn scoring(half_life_secs: u64) -> Value {
+    json!({
+        "blacklist": { "points": 20 },
+        "session_reputation": { "half_life_secs": half_life_secs, "flag_threshold": 50 },
+    })
+}
+
+#[tokio::test]
+async fn small_scores_accumulate_until_the_session_is_flagged() {
+    let service = Harness::with(&[], scoring(900)).await;
+
+    let first = service.score(&small_signal("slow-burn", 0)).await;
+    assert_eq!(first["fraudScore"], 20);
+    assert_eq!(first["flagged"], false);
+    assert_eq!(first["sessionReputation"], 20);
+
+    let second = service.score(&small_signal("slow-burn", 1)).await;
+    assert_eq!(second["flagged"], false);
+    assert_eq!(second["sessionReputation"], 40);
+
+    let third = service.score(&small_signal("slow-burn", 2)).await;
+    assert_eq!(third["fraudScore"], 20, "{}", third);
+    assert_eq!(third["flagged"], true);
+    assert_eq!(third["sessionReputation"], 60);
+    assert_eq!(common::codes(&third), ["IP_BLACKLISTED", "SESSION_REPUTATION"]);
+    assert_eq!(common::points(&third, "SESSION_REPUTATION"), Some(0));
+
+    // Other sessions start from nothing, even on the same IP.
+    let other = service.score(&small_signal("fresh-start", 3)).await;
+    assert_eq!(other["sessionReputation"], other["fraudScore"], "{}", other);
+}
+
+#[tokio::test]
+async fn a_zero_half_life_turns_session_reputation_off() {
+    let service = Harness::with(&[], scoring(0)).await;
+    for n in 0..4 {
+        let result = service.score(&small_signal("no-reputation", n)).await;
+        assert_eq!(result["flagged"], false, "{}", result);
+        assert!(result.get("sessionReputation").is_none(), "{}", result);
+    }
+}
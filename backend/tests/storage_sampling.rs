@@ -0,0 +1,80 @@
+//! Storing only a share of low-risk events (`STORAGE_SAMPLE_RATES`). A rate of 0 draws
+//! every event of its type out and 1 none, so what is stored doesn't depend on the hash.
+mod common;
+
+use axum::http::StatusCode;
+use common::{codes, points, Harness};
+use serde_json::{json, Value};
+
+/// `high_frequency` counting the last minute, so it reads counters rather than the
+/// stored history, from a session's first event: the stored history a sampled session
+/// is established by stays short.
+fn scoring() -> Value {
+    json!({
+        "high_frequency": { "window_secs": 60, "max_events": 5 },
+        "min_events_for_stateful": 1,
+        "entity_reputation": { "half_life_secs": 0 },
+    })
+}
+
+async fn stored_events(service: &Harness, session_id: &str) -> f64 {
+    let features = service.get(&format!("/api/v1/sessions/{}/features", session_id)).await;
+    assert_eq!(features.status, StatusCode::OK, "{}", features.text());
+    features.json()["features"]["event_count"].as_f64().unwrap()
+}
+
+#[tokio::test]
+async fn sampled_out_events_still_count_and_a_hit_keeps_the_rest() {
+    let service = Harness::with(&[("STORAGE_SAMPLE_RATES", "Click=0")], scoring()).await;
+    let series = "events_sampled_out_total{event_type=\"Click\"}";
+    let sampled_out = common::metric(series);
+
+    // Page loads aren't sampled.
+    let first = service.score(&common::event("sampled", "PageLoad", 0)).await;
+    assert!(first.get("historySampled").is_none(), "{}", first);
+    for (n, offset_ms) in [2_100, 4_700, 9_300, 12_800].into_iter().enumerate() {
+        let click = service.score(&common::event("sampled", "Click", offset_ms)).await;
+        assert_eq!(codes(&click), Vec::<String>::new(), "click {}", n + 1);
+        assert_eq!(click["historySampled"], true);
+    }
+    assert_eq!(stored_events(&service, "sampled").await, 1.0);
+    assert_eq!(common::metric(series), sampled_out + 4.0);
+
+    // The unstored clicks were counted: this one is the sixth event in the minute.
+    let fired = service.score(&common::event("sampled", "Click", 17_600)).await;
+    assert_eq!(points(&fired, "HIGH_FREQUENCY"), Some(50), "{}", fired);
+    assert_eq!(fired["historySampled"], true);
+    assert_eq!(stored_events(&service, "sampled").await, 2.0);
+
+    // From the hit on, the session keeps every event, whatever it scores.
+    for offset_ms in [80_000, 83_400] {
+        let click = service.score(&common::event("sampled", "Click", offset_ms)).await;
+        assert_eq!(click["historySampled"], true);
+    }
+    assert_eq!(stored_events(&service, "sampled").await, 4.0);
+    assert_eq!(common::metric(series), sampled_out + 4.0);
+
+    // Explaining against the stored history says it's partial too.
+    let explained = service.explain(&common::event("sampled", "Click", 90_000)).await;
+    assert_eq!(explained["historySampled"], true);
+}
+
+#[tokio::test]
+async fn sessions_with_nothing_drawn_out_are_not_marked() {
+    let service = Harness::with(&[("STORAGE_SAMPLE_RATES", "Click=1")], scoring()).await;
+    service.score(&common::event("kept", "PageLoad", 0)).await;
+    let click = service.score(&common::event("kept", "Click", 2_100)).await;
+    assert!(click.get("historySampled").is_none(), "{}", click);
+    assert_eq!(stored_events(&service, "kept").await, 2.0);
+}
+
+#[tokio::test]
+async fn a_drawn_out_event_that_scores_as_risky_is_stored() {
+    let service = Harness::with(&[("STORAGE_SAMPLE_RATES", "PageLoad=0")], scoring()).await;
+    let mut event = common::event("risky", "PageLoad", 0);
+    event["ipAddress"] = json!("1.1.1.1");
+    let result = service.score(&event).await;
+    assert_eq!(points(&result, "IP_BLACKLISTED"), Some(100));
+    assert!(result.get("historySampled").is_none(), "{}", result);
+    assert_eq!(stored_events(&service, "risky").await, 1.0);
+}
@@ -0,0 +1,119 @@
+//! `GET /api/v1/stream`: events scored over a WebSocket, answered in order, against a
+//! served instance of the router.
+mod common;
+
+use common::{mocks, Harness};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+type Socket = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// Serves `service` on a local port and opens a stream to it.
+async fn connect(service: &Harness) -> Socket {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = service.router().into_make_service_with_connect_info::<SocketAddr>();
+    tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app));
+    let (socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/api/v1/stream", addr)).await.unwrap();
+    socket
+}
+
+async fn send(socket: &mut Socket, event: &Value) {
+    socket.send(Message::Text(event.to_string())).await.unwrap();
+}
+
+async fn receive(socket: &mut Socket) -> Value {
+    let message = tokio::time::timeout(Duration::from_secs(5), socket.next()).await.expect("a reply").unwrap().unwrap();
+    serde_json::from_str(message.to_text().unwrap()).unwrap()
+}
+
+fn from_ip(session_id: &str, ip: &str) -> Value {
+    let mut event = common::event(session_id, "PageLoad", 0);
+    event["ipAddress"] = json!(ip);
+    event
+}
+
+#[tokio::test]
+async fn two_events_get_two_results_in_order() {
+    let service = Harness::new().await;
+    let mut socket = connect(&service).await;
+    send(&mut socket, &from_ip("stream-1", "1.1.1.1")).await;
+    send(&mut socket, &from_ip("stream-2", "198.51.100.23")).await;
+
+    let first = receive(&mut socket).await;
+    assert_eq!(first["sessionId"], "stream-1");
+    assert_eq!(first["flagged"], true);
+    let second = receive(&mut socket).await;
+    assert_eq!(second["sessionId"], "stream-2");
+    assert_eq!(second["flagged"], false);
+    socket.close(None).await.unwrap();
+
+    // Streamed events are stored like posted ones.
+    assert_eq!(service.get("/api/v1/stats").await.json()["store"]["events"], 2);
+}
+
+#[tokio::test]
+async fn bad_messages_are_answered_with_an_error_and_the_stream_goes_on() {
+    let service = Harness::new().await;
+    let mut socket = connect(&service).await;
+    socket.send(Message::Text("{not json".to_string())).await.unwrap();
+    assert_eq!(receive(&mut socket).await["error"], "bad_request");
+    send(&mut socket, &json!({ "sessionId": "missing-fields" })).await;
+    assert_eq!(receive(&mut socket).await["error"], "validation");
+    socket.send(Message::Binary(vec![1, 2, 3])).await.unwrap();
+    let binary = receive(&mut socket).await;
+    assert_eq!(binary["message"], "Send events as JSON text messages");
+
+    send(&mut socket, &from_ip("after-errors", "198.51.100.23")).await;
+    assert_eq!(receive(&mut socket).await["sessionId"], "after-errors");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn a_connection_stops_reading_at_its_in_flight_limit() {
+    let reputation = mocks::slow_reputation_server(HashMap::new(), Duration::from_millis(300)).await;
+    let vars = [
+        ("STREAM_MAX_IN_FLIGHT", "1"),
+        ("IP_REPUTATION_URL", reputation.url.as_str()),
+        ("IP_REPUTATION_TIMEOUT_MS", "2000"),
+    ];
+    let service = Harness::with(&vars, json!({})).await;
+    let mut socket = connect(&service).await;
+    send(&mut socket, &from_ip("held-1", "203.0.113.1")).await;
+    send(&mut socket, &from_ip("held-2", "203.0.113.2")).await;
+
+    // The second event isn't read, so isn't looked up, until the first is answered.
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert_eq!(reputation.lookups.load(Ordering::Relaxed), 1);
+    assert_eq!(receive(&mut socket).await["sessionId"], "held-1");
+    assert_eq!(receive(&mut socket).await["sessionId"], "held-2");
+    assert_eq!(reputation.lookups.load(Ordering::Relaxed), 2);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn results_pending_at_disconnect_are_dropped() {
+    let reputation = mocks::slow_reputation_server(HashMap::new(), Duration::from_millis(300)).await;
+    let vars = [("IP_REPUTATION_URL", reputation.url.as_str()), ("IP_REPUTATION_TIMEOUT_MS", "2000")];
+    let service = Harness::with(&vars, json!({})).await;
+    let dropped = common::metric("stream_results_dropped_total");
+    let mut socket = connect(&service).await;
+    send(&mut socket, &from_ip("gone", "203.0.113.3")).await;
+    while reputation.lookups.load(Ordering::Relaxed) == 0 {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    socket.close(None).await.unwrap();
+    drop(socket);
+
+    for _ in 0..100 {
+        if common::metric("stream_results_dropped_total") > dropped {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(common::metric("stream_results_dropped_total"), dropped + 1.0);
+}
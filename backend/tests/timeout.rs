@@ -0,0 +1,49 @@
+//! `REQUEST_TIMEOUT_MS`: a request stuck on a dependency gets `504` and leaves no trace.
+mod common;
+
+use axum::http::StatusCode;
+use common::{mocks, Harness};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// A service whose IP reputation lookups take `lookup_ms`, allowed `timeout_ms` per request.
+async fn with_slow_reputation(lookup_ms: u64, timeout_ms: &str) -> (Harness, mocks::ReputationServer) {
+    let server = mocks::slow_reputation_server(HashMap::new(), Duration::from_millis(lookup_ms)).await;
+    let vars = [
+        ("IP_REPUTATION_URL", server.url.as_str()),
+        ("IP_REPUTATION_TIMEOUT_MS", "10000"),
+        ("REQUEST_TIMEOUT_MS", timeout_ms),
+    ];
+    (Harness::with(&vars, serde_json::json!({})).await, server)
+}
+
+async fn stored_events(service: &Harness) -> usize {
+    service.get("/api/v1/stats").await.json()["store"]["events"].as_u64().unwrap() as usize
+}
+
+#[tokio::test]
+async fn a_request_outrunning_the_deadline_gets_504_and_stores_nothing() {
+    let (service, server) = with_slow_reputation(2_000, "100").await;
+    let timed_out = common::metric("requests_timed_out_total");
+
+    let response = service.analyze(&common::event("too-slow", "PageLoad", 0)).await;
+    assert_eq!(response.status, StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(response.json()["error"], "timeout");
+    assert_eq!(response.json()["message"], "Request took longer than 100ms");
+    assert_eq!(server.lookups.load(std::sync::atomic::Ordering::Relaxed), 1);
+    assert!(common::metric("requests_timed_out_total") > timed_out);
+
+    assert_eq!(stored_events(&service).await, 0);
+    let since = common::at(-1).to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    assert_eq!(service.get(&format!("/api/v1/export?since={}", since)).await.text(), "");
+    let search = service.get("/api/v1/search?flagged_only=false").await.json();
+    assert_eq!(search["sessions"], serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn a_request_inside_the_deadline_is_scored_and_stored() {
+    let (service, _server) = with_slow_reputation(20, "2000").await;
+    let response = service.analyze(&common::event("fast-enough", "PageLoad", 0)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(stored_events(&service).await, 1);
+}
@@ -0,0 +1,56 @@
+//! Tor exit nodes, scored as the `tor_exit` suspicious-source list (`TOR_EXIT_LIST_PATH`).
+mod common;
+
+use axum::http::{Method, Request};
+use common::{points, Harness};
+use serde_json::{json, Value};
+
+fn from_ip(session_id: &str, ip: &str) -> Value {
+    let mut event = common::event(session_id, "PageLoad", 0);
+    event["ipAddress"] = json!(ip);
+    event
+}
+
+/// The service with a mocked exit list holding two addresses.
+async fn with_exit_list(scoring: Value) -> Harness {
+    let path = common::scratch_path("tor-exits.txt");
+    std::fs::write(&path, "# exit addresses\n185.220.101.1\n2001:db8:7::1\n").unwrap();
+    Harness::with(&[("TOR_EXIT_LIST_PATH", path.to_str().unwrap())], scoring).await
+}
+
+#[tokio::test]
+async fn an_exit_node_ip_gets_the_tor_points() {
+    let service = with_exit_list(json!({})).await;
+    let result = service.score(&from_ip("tor-1", "185.220.101.1")).await;
+    assert_eq!(points(&result, "TOR_EXIT"), Some(30));
+    let hit = result["breakdown"].as_array().unwrap().iter().find(|hit| hit["code"] == "TOR_EXIT").unwrap().clone();
+    assert_eq!(hit["reason"], "IP is a known Tor exit node");
+    assert_eq!(points(&service.score(&from_ip("tor-2", "2001:db8:7::1")).await, "TOR_EXIT"), Some(30));
+
+    let other = service.score(&from_ip("tor-3", "185.220.101.2")).await;
+    assert_eq!(points(&other, "TOR_EXIT"), None);
+}
+
+#[tokio::test]
+async fn the_points_follow_the_scoring_config() {
+    let service = with_exit_list(json!({ "suspicious_sources": { "tor_exit": { "points": 45 } } })).await;
+    assert_eq!(points(&service.score(&from_ip("tor-4", "185.220.101.1")).await, "TOR_EXIT"), Some(45));
+
+    let off = with_exit_list(json!({ "suspicious_sources": { "tor_exit": { "points": 0 } } })).await;
+    assert_eq!(points(&off.score(&from_ip("tor-5", "185.220.101.1")).await, "TOR_EXIT"), None);
+}
+
+#[tokio::test]
+async fn without_a_list_nothing_is_an_exit_until_one_is_imported() {
+    let service = Harness::new().await;
+    assert_eq!(points(&service.score(&from_ip("tor-6", "185.220.101.1")).await, "TOR_EXIT"), None);
+
+    let import = Request::builder()
+        .method(Method::POST)
+        .uri("/api/v1/sources/tor_exit/import")
+        .body("185.220.101.1\n".into())
+        .unwrap();
+    let imported = service.send(import).await;
+    assert!(imported.status.is_success(), "{}", imported.text());
+    assert_eq!(points(&service.score(&from_ip("tor-7", "185.220.101.1")).await, "TOR_EXIT"), Some(30));
+}
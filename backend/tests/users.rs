@@ -0,0 +1,107 @@
+//! `GET /api/v1/users/:user_id/sessions`: a user's stored sessions from the store's user
+//! index, with their latest scores.
+mod common;
+
+use common::Harness;
+use serde_json::{json, Value};
+
+fn by_user(session_id: &str, user_id: &str, ip: &str, offset_ms: i64) -> Value {
+    let mut event = common::event(session_id, "PageLoad", offset_ms);
+    event["userId"] = json!(user_id);
+    event["ipAddress"] = json!(ip);
+    event
+}
+
+async fn sessions(service: &Harness, user_id: &str) -> Value {
+    let response = service.get(&format!("/api/v1/users/{}/sessions", user_id)).await;
+    assert!(response.status.is_success(), "{}", response.text());
+    response.json()
+}
+
+fn ids(listing: &Value) -> Vec<&str> {
+    listing["sessions"].as_array().unwrap().iter().map(|session| session["sessionId"].as_str().unwrap()).collect()
+}
+
+/// The IPs the stats endpoint lists as contributing most to scores.
+async fn top_ips(service: &Harness) -> Vec<String> {
+    let stats = service.get("/api/v1/stats").await.json();
+    stats["topIps"].as_array().unwrap().iter().map(|entry| entry["ip"].as_str().unwrap().to_string()).collect()
+}
+
+#[tokio::test]
+async fn lists_a_users_sessions_with_their_latest_scores() {
+    let service = Harness::with(&[], json!({ "entity_reputation": { "half_life_secs": 0 } })).await;
+    service.score(&by_user("alice-1", "alice", "198.51.100.23", 0)).await;
+    service.score(&by_user("alice-2", "alice", "1.1.1.1", 1000)).await;
+    service.score(&by_user("bob-1", "bob", "198.51.100.23", 2000)).await;
+
+    let listing = sessions(&service, "alice").await;
+    assert_eq!(listing["userId"], "alice");
+    // Most recently scored first.
+    assert_eq!(ids(&listing), ["alice-2", "alice-1"]);
+    let latest = &listing["sessions"][0];
+    assert_eq!(latest["latestScore"], 100);
+    assert_eq!(latest["flagged"], true);
+    assert_eq!(latest["reasonCodes"], json!(["IP_BLACKLISTED"]));
+    assert_eq!(listing["sessions"][1]["flagged"], false);
+    assert_eq!(ids(&sessions(&service, "bob").await), ["bob-1"]);
+    assert!(ids(&sessions(&service, "nobody").await).is_empty());
+}
+
+#[tokio::test]
+async fn a_session_shared_with_another_user_is_listed_for_both() {
+    let service = Harness::new().await;
+    service.score(&by_user("shared", "alice", "198.51.100.23", 0)).await;
+    service.score(&by_user("shared", "bob", "198.51.100.23", 1000)).await;
+    assert_eq!(ids(&sessions(&service, "alice").await), ["shared"]);
+    assert_eq!(ids(&sessions(&service, "bob").await), ["shared"]);
+}
+
+#[tokio::test]
+async fn erasing_a_user_empties_their_listing_and_leaves_others() {
+    let service = Harness::new().await;
+    service.score(&by_user("erase-1", "alice", "198.51.100.23", 0)).await;
+    service.score(&by_user("erase-1", "bob", "198.51.100.23", 1000)).await;
+    service.score(&by_user("erase-2", "alice", "198.51.100.23", 2000)).await;
+
+    let erased = service.delete("/api/v1/users/alice/data", None).await;
+    assert!(erased.status.is_success(), "{}", erased.text());
+    assert!(ids(&sessions(&service, "alice").await).is_empty());
+    assert_eq!(ids(&sessions(&service, "bob").await), ["erase-1"]);
+
+    // New events index the user again.
+    service.score(&by_user("erase-3", "alice", "198.51.100.23", 3000)).await;
+    assert_eq!(ids(&sessions(&service, "alice").await), ["erase-3"]);
+}
+
+#[tokio::test]
+async fn erasing_a_user_drops_their_fingerprint_links_reputation_and_ip_stats() {
+    let scoring = json!({
+        "device_fingerprint": { "max_sessions": 2 },
+        "entity_reputation": { "half_life_secs": 0 },
+    });
+    let service = Harness::with(&[], scoring).await;
+    let on_device = |session_id: &str, user_id: &str, ip: &str, offset_ms: i64| {
+        let mut event = by_user(session_id, user_id, ip, offset_ms);
+        event["metadata"] = json!({ "fingerprint": "erased-device" });
+        event
+    };
+    let scored = service.score(&on_device("gone-1", "carol", "1.1.1.1", 0)).await;
+    assert_eq!(scored["sessionReputation"], 100, "{}", scored);
+    service.score(&on_device("kept-1", "dave", "198.51.100.23", 1000)).await;
+    assert_eq!(top_ips(&service).await, ["1.1.1.1"]);
+
+    let erased = service.delete("/api/v1/users/carol/data", None).await;
+    assert!(erased.status.is_success(), "{}", erased.text());
+    let report = erased.json();
+    assert_eq!(report["fingerprintLinksDeleted"], 1, "{}", report);
+    assert_eq!(report["sessionReputationsReset"], 1);
+    assert_eq!(report["statsIpsDeleted"], 1);
+
+    // The device is left with one other session, so a second is within the limit.
+    let next = service.score(&on_device("kept-2", "dave", "198.51.100.23", 2000)).await;
+    assert_eq!(common::points(&next, "SHARED_FINGERPRINT"), None, "{}", next);
+    let fresh = service.score(&by_user("gone-1", "erin", "198.51.100.23", 3000)).await;
+    assert_eq!(fresh["sessionReputation"], 0, "{}", fresh);
+    assert!(top_ips(&service).await.is_empty());
+}
@@ -0,0 +1,21 @@
+//! `GET /api/v1/version`: the build info embedded by `build.rs`.
+mod common;
+
+use axum::http::StatusCode;
+use common::Harness;
+
+#[tokio::test]
+async fn reports_the_crate_version_and_build_time() {
+    let service = Harness::new().await;
+    let response = service.get("/api/v1/version").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let info = response.json();
+    assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+    assert!(!info["version"].as_str().unwrap().is_empty());
+    let built_at = info["builtAt"].as_str().unwrap();
+    assert!(chrono::DateTime::parse_from_rfc3339(built_at).is_ok(), "{}", built_at);
+    // Null outside a git checkout, otherwise a hash.
+    if let Some(commit) = info["gitCommit"].as_str() {
+        assert!(!commit.is_empty() && commit.chars().all(|c| c.is_ascii_hexdigit()), "{}", commit);
+    }
+}